@@ -0,0 +1,15 @@
+//! Static analysis and transformation of Redcode programs. These operate on
+//! fully evaluated programs, i.e. with all labels and expressions resolved.
+
+mod compression;
+mod dead_code;
+mod flow;
+mod footprint;
+mod steps;
+mod usage;
+
+pub use compression::{compression_report, CompressionReport, Suggestion};
+pub use dead_code::{eliminate_dead_code, DeadCode, Mode};
+pub use footprint::{footprint, Access, Footprint, Location, Part};
+pub use steps::{step_warnings, StepWarning};
+pub use usage::{usage, Usage};
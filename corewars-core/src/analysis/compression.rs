@@ -0,0 +1,460 @@
+//! Suggestions for making a program shorter, e.g. to fit under `MAXLENGTH`.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::load_file::{AddressMode, Field, FieldName, Instruction, Modifier, Opcode, Program};
+
+use super::dead_code::{eliminate_dead_code, Mode};
+use super::flow::{liveness, Resolver};
+
+/// A way in which a program could be made shorter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Suggestion {
+    /// The instruction is never executed or referenced, so it can be removed
+    Remove { index: usize },
+
+    /// The instruction is only used as data, and the fields used could be
+    /// stored in unused fields of another instruction instead
+    Merge {
+        from: usize,
+        into: usize,
+        fields: Vec<FieldName>,
+    },
+
+    /// The instruction's modifier is the default, so it can be omitted
+    ElideModifier { index: usize },
+
+    /// The instruction's field has the default value, so it can be omitted
+    ElideField { index: usize, field: FieldName },
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Remove { index } => write!(
+                formatter,
+                "instruction {} is never executed or referenced, and can be removed",
+                index
+            ),
+            Self::Merge { from, into, fields } => {
+                let fields: Vec<String> = fields.iter().map(|f| format!("{:?}", f)).collect();
+                write!(
+                    formatter,
+                    "instruction {} is only used as data, and its {} field(s) could be \
+                     stored in the unused field(s) of instruction {}",
+                    from,
+                    fields.join(" and "),
+                    into
+                )
+            }
+            Self::ElideModifier { index } => write!(
+                formatter,
+                "the modifier of instruction {} is the default, and can be omitted",
+                index
+            ),
+            Self::ElideField { index, field } => write!(
+                formatter,
+                "the {:?}-field of instruction {} is the default, and can be omitted",
+                field, index
+            ),
+        }
+    }
+}
+
+/// A summary of how a program could be made shorter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressionReport {
+    /// The number of instructions in the program
+    pub length: usize,
+
+    /// The number of instructions if all removals and merges were applied
+    pub minimal_length: usize,
+
+    /// All suggestions, ordered by kind and then by instruction
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl fmt::Display for CompressionReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "length {}, minimal length {}",
+            self.length, self.minimal_length
+        )?;
+
+        for suggestion in &self.suggestions {
+            write!(formatter, "\n{}", suggestion)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Analyze a program for instructions which could be removed or merged, and
+/// defaults which could be omitted from its source.
+pub fn compression_report(program: &Program) -> CompressionReport {
+    let length = program.instructions.len();
+
+    let removed = eliminate_dead_code(program, Mode::Conservative).removed;
+    let mut suggestions: Vec<Suggestion> = removed
+        .iter()
+        .map(|&index| Suggestion::Remove { index })
+        .collect();
+
+    let merges = merges(program, &removed);
+    let minimal_length = length - removed.len() - merges.len();
+    suggestions.extend(merges);
+
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        if !removed.contains(&index) {
+            suggestions.extend(elisions(index, instruction));
+        }
+    }
+
+    CompressionReport {
+        length,
+        minimal_length,
+        suggestions,
+    }
+}
+
+/// Which fields of each instruction are used, either by executing the
+/// instruction or by other instructions referring to it.
+struct Usage {
+    fields: Vec<[bool; 2]>,
+
+    /// Whether the whole instruction is used as data, e.g. by `MOV.I`
+    whole: Vec<bool>,
+}
+
+impl Usage {
+    fn is_used(&self, index: usize, field: FieldName) -> bool {
+        self.whole[index] || self.fields[index][field as usize]
+    }
+
+    fn set_used(&mut self, address: i64, field: FieldName) {
+        if let Some(fields) = usize::try_from(address)
+            .ok()
+            .and_then(|address| self.fields.get_mut(address))
+        {
+            fields[field as usize] = true;
+        }
+    }
+}
+
+/// Find instructions which are only used as data and could be merged
+/// into another instruction. Returns no suggestions if some operand can't
+/// be resolved, since any instruction might then be used.
+fn merges(program: &Program, removed: &[usize]) -> Vec<Suggestion> {
+    use FieldName::{A, B};
+
+    let resolver = Resolver::proving_constant(&program.instructions);
+    let reachable = match liveness(&resolver, program.origin.unwrap_or(0), true) {
+        Some(liveness) => liveness.reachable,
+        None => return Vec::new(),
+    };
+
+    let len = program.instructions.len();
+    let mut usage = Usage {
+        fields: vec![[false; 2]; len],
+        whole: vec![false; len],
+    };
+
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        if !reachable[index] {
+            continue;
+        }
+
+        let field_usage = instruction.opcode.field_usage();
+
+        for &field_name in &[A, B] {
+            let field = instruction.field(field_name);
+            let ignored = match instruction.opcode {
+                Opcode::Dat | Opcode::Nop => true,
+                Opcode::Jmp | Opcode::Spl => field_name == B,
+                _ => false,
+            };
+
+            let uses_own_value = match field.address_mode {
+                AddressMode::Immediate => false,
+                AddressMode::Direct => !ignored,
+                _ => true,
+            };
+            if uses_own_value {
+                usage.fields[index][field_name as usize] = true;
+            }
+
+            let operand = resolver.operand(index, field_name);
+            if let Some((pointer, pointer_field)) = operand.pointer {
+                usage.set_used(pointer, pointer_field);
+            }
+
+            let uses_target = field_usage.is_some_and(|u| match field_name {
+                A => u.reads_a,
+                B => u.reads_b || u.writes_b,
+            });
+
+            if let (true, Some(target)) = (uses_target, operand.target) {
+                for pair in instruction.modifier.field_pairs() {
+                    usage.set_used(target, if field_name == A { pair.a } else { pair.b });
+                }
+
+                let whole = instruction.modifier == Modifier::I
+                    && field_usage.is_some_and(|u| u.whole_instruction);
+                if let (true, Ok(target)) = (whole, usize::try_from(target)) {
+                    if target < len {
+                        usage.whole[target] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut merged = vec![false; len];
+    let mut suggestions = Vec::new();
+    let is_present = |index: usize, merged: &[bool]| !removed.contains(&index) && !merged[index];
+
+    for from in 0..len {
+        if reachable[from] || usage.whole[from] || !is_present(from, &merged) {
+            continue;
+        }
+
+        let fields: Vec<FieldName> = [A, B]
+            .iter()
+            .copied()
+            .filter(|&field| usage.is_used(from, field))
+            .collect();
+
+        let into = (0..len).find(|&into| {
+            into != from
+                && is_present(into, &merged)
+                && fields.iter().all(|&field| !usage.is_used(into, field))
+        });
+
+        if let Some(into) = into {
+            for &field in &fields {
+                usage.fields[into][field as usize] = true;
+            }
+            merged[from] = true;
+            suggestions.push(Suggestion::Merge { from, into, fields });
+        }
+    }
+
+    suggestions
+}
+
+/// Defaults the parser would fill in if omitted from the source
+fn elisions(index: usize, instruction: &Instruction) -> Vec<Suggestion> {
+    let mut suggestions = Vec::new();
+
+    let default_modifier = Modifier::default_88_to_94(
+        instruction.opcode,
+        instruction.a_field.address_mode,
+        instruction.b_field.address_mode,
+    );
+    if instruction.modifier == default_modifier {
+        suggestions.push(Suggestion::ElideModifier { index });
+    }
+
+    match instruction.opcode {
+        Opcode::Dat if instruction.a_field == Field::immediate(0) => {
+            suggestions.push(Suggestion::ElideField {
+                index,
+                field: FieldName::A,
+            })
+        }
+        Opcode::Jmp | Opcode::Spl | Opcode::Nop if instruction.b_field == Field::direct(0) => {
+            suggestions.push(Suggestion::ElideField {
+                index,
+                field: FieldName::B,
+            })
+        }
+        _ => {}
+    }
+
+    suggestions
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::load_file::Value;
+
+    fn instruction(
+        opcode: Opcode,
+        modifier: Modifier,
+        a_field: Field,
+        b_field: Field,
+    ) -> Instruction {
+        Instruction {
+            opcode,
+            modifier,
+            a_field,
+            b_field,
+        }
+    }
+
+    #[test]
+    fn merges_data_fields() {
+        // The step and the pointer each only use one field, so they could
+        // share a single instruction
+        let program = Program {
+            instructions: vec![
+                instruction(
+                    Opcode::Add,
+                    Modifier::AB,
+                    Field::direct(2),
+                    Field::direct(3),
+                ),
+                instruction(
+                    Opcode::Jmp,
+                    Modifier::B,
+                    Field::direct(-1),
+                    Field::immediate(0),
+                ),
+                instruction(
+                    Opcode::Dat,
+                    Modifier::F,
+                    Field::immediate(4),
+                    Field::immediate(4),
+                ),
+                instruction(
+                    Opcode::Dat,
+                    Modifier::F,
+                    Field::immediate(0),
+                    Field::immediate(0),
+                ),
+            ]
+            .into(),
+            origin: None,
+        };
+
+        let report = compression_report(&program);
+
+        assert_eq!(report.length, 4);
+        assert_eq!(report.minimal_length, 3);
+        assert_eq!(
+            report.suggestions,
+            vec![
+                Suggestion::Merge {
+                    from: 2,
+                    into: 3,
+                    fields: vec![FieldName::A],
+                },
+                Suggestion::ElideModifier { index: 1 },
+                Suggestion::ElideModifier { index: 2 },
+                Suggestion::ElideModifier { index: 3 },
+                Suggestion::ElideField {
+                    index: 3,
+                    field: FieldName::A,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn whole_instruction_not_merged() {
+        let program = Program {
+            instructions: vec![
+                instruction(
+                    Opcode::Mov,
+                    Modifier::I,
+                    Field::direct(2),
+                    Field::direct(10),
+                ),
+                instruction(
+                    Opcode::Jmp,
+                    Modifier::B,
+                    Field::direct(-1),
+                    Field::immediate(0),
+                ),
+                instruction(
+                    Opcode::Dat,
+                    Modifier::F,
+                    Field::immediate(0),
+                    Field::immediate(0),
+                ),
+            ]
+            .into(),
+            origin: None,
+        };
+
+        let report = compression_report(&program);
+        assert_eq!(report.minimal_length, 3);
+    }
+
+    #[test]
+    fn unresolved_operands_prevent_merges() {
+        let program = Program {
+            instructions: vec![
+                instruction(
+                    Opcode::Mov,
+                    Modifier::AB,
+                    Field::direct(2),
+                    Field {
+                        address_mode: AddressMode::PostIncIndirectB,
+                        value: Value::Literal(3),
+                    },
+                ),
+                instruction(
+                    Opcode::Jmp,
+                    Modifier::B,
+                    Field::direct(-1),
+                    Field::immediate(0),
+                ),
+                instruction(
+                    Opcode::Dat,
+                    Modifier::F,
+                    Field::immediate(4),
+                    Field::immediate(4),
+                ),
+                instruction(
+                    Opcode::Dat,
+                    Modifier::F,
+                    Field::immediate(0),
+                    Field::immediate(0),
+                ),
+            ]
+            .into(),
+            origin: None,
+        };
+
+        let report = compression_report(&program);
+        assert_eq!(report.minimal_length, 4);
+        assert!(!report
+            .suggestions
+            .iter()
+            .any(|suggestion| matches!(suggestion, Suggestion::Merge { .. })));
+    }
+
+    #[test]
+    fn display() {
+        let program = Program {
+            instructions: vec![
+                instruction(Opcode::Jmp, Modifier::B, Field::direct(0), Field::direct(0)),
+                instruction(
+                    Opcode::Dat,
+                    Modifier::AB,
+                    Field::immediate(1),
+                    Field::immediate(0),
+                ),
+            ]
+            .into(),
+            origin: None,
+        };
+
+        assert_eq!(
+            compression_report(&program).to_string(),
+            [
+                "length 2, minimal length 1",
+                "instruction 1 is never executed or referenced, and can be removed",
+                "the modifier of instruction 0 is the default, and can be omitted",
+                "the B-field of instruction 0 is the default, and can be omitted",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Removal of instructions which can never be executed or referenced.
+
+use std::collections::BTreeMap;
+
+use crate::load_file::{AddressMode, FieldName, InstructionsBuilder, Program, Value};
+
+use super::flow::{liveness, Liveness, Resolver};
+
+/// How much to trust the initial values of pointers when eliminating dead code.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Only resolve indirect operands through pointers which are proven to
+    /// never be modified, and leave the program unchanged whenever an operand
+    /// or jump cannot be resolved. This never changes the program's behavior.
+    #[default]
+    Conservative,
+
+    /// Assume pointers always keep their initial values. This removes more
+    /// code, but may break programs which copy or modify themselves using
+    /// pointers, e.g. a paper's copy loop.
+    Aggressive,
+}
+
+/// The result of [`eliminate_dead_code`](eliminate_dead_code).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadCode {
+    /// The program with dead code removed, and offsets adjusted to match
+    pub program: Program,
+
+    /// Indices of the instructions that were removed from the original program
+    pub removed: Vec<usize>,
+}
+
+/// Remove instructions which are unreachable from the program's origin, and
+/// are not referenced by any operand of a reachable instruction. Any operands
+/// spanning the removed instructions are adjusted so they still refer to the
+/// same instructions.
+pub fn eliminate_dead_code(program: &Program, mode: Mode) -> DeadCode {
+    let unchanged = DeadCode {
+        program: program.clone(),
+        removed: Vec::new(),
+    };
+
+    let instructions = &program.instructions;
+    let resolver = match mode {
+        Mode::Conservative => Resolver::proving_constant(instructions),
+        Mode::Aggressive => Resolver::assuming_constant(instructions),
+    };
+
+    let Liveness { reachable, live } = match liveness(
+        &resolver,
+        program.origin.unwrap_or(0),
+        mode == Mode::Conservative,
+    ) {
+        Some(liveness) => liveness,
+        None => return unchanged,
+    };
+
+    let removed: Vec<usize> = (0..instructions.len()).filter(|&i| !live[i]).collect();
+    if removed.is_empty() {
+        return unchanged;
+    }
+
+    // The new address for an old address. The end of the program moves with
+    // it, but addresses further outside are other parts of the core, which
+    // don't move when the program shrinks
+    let relocate = |address: i64| -> i64 {
+        if address < 0 || address > instructions.len() as i64 {
+            return address;
+        }
+        let removed_before = removed
+            .iter()
+            .take_while(|&&index| (index as i64) < address)
+            .count();
+        address - removed_before as i64
+    };
+
+    let relative = |from: i64, to: i64| -> i32 { (relocate(to) - relocate(from)) as i32 };
+
+    // Relocate the fields of pointers used by indirect operands, keyed by
+    // the pointer address and field
+    let mut pointer_fields = BTreeMap::new();
+
+    for index in (0..instructions.len()).filter(|&i| reachable[i]) {
+        for &field_name in &[FieldName::A, FieldName::B] {
+            let operand = resolver.operand(index, field_name);
+
+            if let (Some((pointer, pointer_field)), Some(target)) =
+                (operand.pointer, operand.target)
+            {
+                let pointer_value = resolver
+                    .get(pointer)
+                    .expect("resolved pointers are in the program")
+                    .field(pointer_field)
+                    .unwrap_value();
+
+                // Keep any difference between the pointer value and the
+                // target, e.g. for pre-decrement modes
+                let adjust = (target - pointer - i64::from(pointer_value)) as i32;
+                let relocated = relative(pointer, target) - adjust;
+
+                if mode == Mode::Conservative
+                    && relocated != pointer_value
+                    && is_used_as_data(&resolver, &reachable, pointer)
+                {
+                    // The pointer value may be used for more than its target
+                    return unchanged;
+                }
+
+                pointer_fields.insert((pointer as usize, pointer_field), relocated);
+            }
+        }
+    }
+
+    let mut optimized = Program {
+        instructions: InstructionsBuilder::with_capacity(instructions.len() - removed.len()),
+        origin: program
+            .origin
+            .map(|origin| relocate(i64::from(origin)) as u32),
+    };
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        if !live[index] {
+            continue;
+        }
+
+        let mut instruction = instruction.clone();
+
+        for &field_name in &[FieldName::A, FieldName::B] {
+            let field = instruction.field_mut(field_name);
+
+            if let Some(&value) = pointer_fields.get(&(index, field_name)) {
+                field.value = Value::Literal(value);
+            } else if field.address_mode != AddressMode::Immediate {
+                let address = index as i64 + i64::from(field.unwrap_value());
+                field.value = Value::Literal(relative(index as i64, address));
+            }
+        }
+
+        optimized.instructions.push(instruction);
+    }
+
+    DeadCode {
+        program: optimized,
+        removed,
+    }
+}
+
+/// Whether any reachable instruction refers to the instruction at `address`
+/// other than as a pointer, i.e. its value might be read or written directly.
+fn is_used_as_data(resolver: &Resolver, reachable: &[bool], address: i64) -> bool {
+    (0..resolver.len()).filter(|&i| reachable[i]).any(|index| {
+        [FieldName::A, FieldName::B]
+            .iter()
+            .any(|&field_name| resolver.operand(index, field_name).target == Some(address))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::load_file::{Field, Instruction, Opcode};
+
+    fn program(instructions: Vec<Instruction>) -> Program {
+        Program {
+            instructions: instructions.into(),
+            origin: None,
+        }
+    }
+
+    fn field(address_mode: AddressMode, value: i32) -> Field {
+        Field {
+            address_mode,
+            value: Value::Literal(value),
+        }
+    }
+
+    #[test]
+    fn removes_unreachable_code() {
+        let input = program(vec![
+            Instruction::new(Opcode::Jmp, Field::direct(2), Field::direct(0)),
+            Instruction::new(Opcode::Nop, Field::direct(0), Field::direct(0)),
+            Instruction::new(Opcode::Mov, Field::direct(2), Field::direct(3)),
+            Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
+            Instruction::new(Opcode::Dat, Field::immediate(5), Field::immediate(5)),
+        ]);
+
+        let result = eliminate_dead_code(&input, Mode::Conservative);
+
+        assert_eq!(result.removed, vec![1]);
+        assert_eq!(
+            result.program,
+            program(vec![
+                Instruction::new(Opcode::Jmp, Field::direct(1), Field::direct(0)),
+                Instruction::new(Opcode::Mov, Field::direct(2), Field::direct(3)),
+                Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
+                Instruction::new(Opcode::Dat, Field::immediate(5), Field::immediate(5)),
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_referenced_data() {
+        let input = program(vec![
+            Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(3)),
+            Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
+            Instruction::new(Opcode::Dat, Field::immediate(1), Field::immediate(1)),
+            Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(0)),
+        ]);
+
+        let result = eliminate_dead_code(&input, Mode::Conservative);
+
+        assert_eq!(result.removed, vec![2]);
+        assert_eq!(
+            result.program.instructions[0],
+            Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(2)),
+        );
+    }
+
+    #[test]
+    fn relocates_origin() {
+        let mut input = program(vec![
+            Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(0)),
+            Instruction::new(Opcode::Mov, Field::direct(0), Field::direct(1)),
+        ]);
+        input.origin = Some(1);
+
+        let result = eliminate_dead_code(&input, Mode::Conservative);
+
+        assert_eq!(result.removed, vec![0]);
+        assert_eq!(result.program.origin, Some(0));
+    }
+
+    #[test]
+    fn keeps_targets_outside_program() {
+        let input = program(vec![
+            Instruction::new(Opcode::Mov, Field::direct(0), Field::direct(100)),
+            Instruction::new(Opcode::Jmp, Field::direct(2), Field::direct(0)),
+            Instruction::new(Opcode::Nop, Field::direct(0), Field::direct(0)),
+            Instruction::new(Opcode::Jmp, Field::direct(-2), Field::direct(-10)),
+        ]);
+
+        let result = eliminate_dead_code(&input, Mode::Conservative);
+
+        assert_eq!(result.removed, vec![2]);
+        assert_eq!(
+            result.program,
+            program(vec![
+                Instruction::new(Opcode::Mov, Field::direct(0), Field::direct(100)),
+                Instruction::new(Opcode::Jmp, Field::direct(1), Field::direct(0)),
+                Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(-9)),
+            ])
+        );
+    }
+
+    #[test]
+    fn conservative_keeps_unprovable_pointers() {
+        // A copy loop through pointers which are modified as it runs
+        let input = program(vec![
+            Instruction::new(
+                Opcode::Mov,
+                field(AddressMode::PostIncIndirectA, 2),
+                field(AddressMode::PostIncIndirectB, 2),
+            ),
+            Instruction::new(Opcode::Jmn, Field::direct(-1), Field::direct(1)),
+            Instruction::new(Opcode::Dat, Field::immediate(2), Field::immediate(100)),
+            Instruction::new(Opcode::Nop, Field::direct(0), Field::direct(0)),
+            Instruction::new(Opcode::Dat, Field::immediate(1), Field::immediate(1)),
+        ]);
+
+        let conservative = eliminate_dead_code(&input, Mode::Conservative);
+        assert_eq!(conservative.removed, Vec::<usize>::new());
+        assert_eq!(conservative.program, input);
+
+        let aggressive = eliminate_dead_code(&input, Mode::Aggressive);
+        assert_eq!(aggressive.removed, vec![3]);
+        assert_eq!(
+            aggressive.program.instructions[2],
+            Instruction::new(Opcode::Dat, Field::immediate(1), Field::immediate(100)),
+        );
+    }
+
+    #[test]
+    fn conservative_resolves_constant_pointers() {
+        let input = program(vec![
+            Instruction::new(
+                Opcode::Jmp,
+                field(AddressMode::IndirectB, 2),
+                Field::direct(0),
+            ),
+            Instruction::new(Opcode::Nop, Field::direct(0), Field::direct(0)),
+            Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(2)),
+            Instruction::new(Opcode::Nop, Field::direct(0), Field::direct(0)),
+            Instruction::new(Opcode::Jmp, Field::direct(0), Field::direct(0)),
+        ]);
+
+        let result = eliminate_dead_code(&input, Mode::Conservative);
+
+        assert_eq!(result.removed, vec![1, 3]);
+        assert_eq!(
+            result.program,
+            program(vec![
+                Instruction::new(
+                    Opcode::Jmp,
+                    field(AddressMode::IndirectB, 1),
+                    Field::direct(0)
+                ),
+                Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(1)),
+                Instruction::new(Opcode::Jmp, Field::direct(0), Field::direct(0)),
+            ])
+        );
+    }
+}
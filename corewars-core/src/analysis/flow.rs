@@ -0,0 +1,227 @@
+//! Helpers for statically resolving operands and control flow of a program.
+//! Addresses are program-relative instruction indices, and may be outside
+//! the bounds of the program.
+
+use crate::load_file::{AddressMode, FieldName, Instruction, Opcode};
+
+use super::footprint::{footprint, Location};
+
+/// A statically resolved operand of an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Operand {
+    /// The address of the pointer used by an indirect address mode, and
+    /// which of its fields is used
+    pub pointer: Option<(i64, FieldName)>,
+
+    /// The address the operand refers to, if it could be determined
+    pub target: Option<i64>,
+}
+
+/// Statically resolves operands using the initial contents of the program.
+pub struct Resolver<'a> {
+    instructions: &'a [Instruction],
+
+    /// Which instructions may be assumed to keep their initial values
+    constant: Vec<bool>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Create a resolver which assumes pointers in the program always keep
+    /// their initial values.
+    pub fn assuming_constant(instructions: &'a [Instruction]) -> Self {
+        Self {
+            instructions,
+            constant: vec![true; instructions.len()],
+        }
+    }
+
+    /// Create a resolver which only resolves indirect operands through
+    /// pointers that are proven to never be modified by the program.
+    pub fn proving_constant(instructions: &'a [Instruction]) -> Self {
+        let mut resolver = Self::assuming_constant(instructions);
+
+        // Start by assuming everything is constant, and remove any instructions
+        // that could be written until nothing else changes. At that point,
+        // no write can modify an instruction still considered constant.
+        loop {
+            let mut changed = false;
+
+            for index in 0..instructions.len() {
+                for written in resolver.writes(index) {
+                    match written {
+                        Some(address) => {
+                            if let Some(constant) = resolver.constant_mut(address) {
+                                changed |= *constant;
+                                *constant = false;
+                            }
+                        }
+                        None => {
+                            // Could write anywhere, so nothing is provably constant
+                            resolver.constant = vec![false; instructions.len()];
+                            return resolver;
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                return resolver;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Get the instruction at a given address, if it is in the program.
+    pub fn get(&self, address: i64) -> Option<&'a Instruction> {
+        if address < 0 {
+            None
+        } else {
+            self.instructions.get(address as usize)
+        }
+    }
+
+    fn constant_mut(&mut self, address: i64) -> Option<&mut bool> {
+        if address < 0 {
+            None
+        } else {
+            self.constant.get_mut(address as usize)
+        }
+    }
+
+    fn is_constant(&self, address: i64) -> bool {
+        address >= 0 && self.constant.get(address as usize) == Some(&true)
+    }
+
+    /// Resolve one operand of the instruction at `index`.
+    pub fn operand(&self, index: usize, field_name: FieldName) -> Operand {
+        use AddressMode::*;
+
+        let field = self.instructions[index].field(field_name);
+        let address = index as i64 + i64::from(field.unwrap_value());
+
+        let (pointer_field, adjust) = match field.address_mode {
+            Immediate => {
+                return Operand {
+                    pointer: None,
+                    target: Some(index as i64),
+                }
+            }
+            Direct => {
+                return Operand {
+                    pointer: None,
+                    target: Some(address),
+                }
+            }
+            IndirectA | PostIncIndirectA => (FieldName::A, 0),
+            IndirectB | PostIncIndirectB => (FieldName::B, 0),
+            PreDecIndirectA => (FieldName::A, -1),
+            PreDecIndirectB => (FieldName::B, -1),
+        };
+
+        let target = match self.get(address) {
+            Some(pointer) if self.is_constant(address) => {
+                Some(address + i64::from(pointer.field(pointer_field).unwrap_value()) + adjust)
+            }
+            _ => None,
+        };
+
+        Operand {
+            pointer: Some((address, pointer_field)),
+            target,
+        }
+    }
+
+    /// Addresses which may be written by executing the instruction at `index`.
+    /// `None` means the address could not be determined.
+    pub fn writes(&self, index: usize) -> Vec<Option<i64>> {
+        let footprint = footprint(&self.instructions[index]);
+        let mut writes: Vec<Option<i64>> = footprint
+            .writes
+            .iter()
+            .map(|access| match access.location {
+                Location::Relative(offset) => Some(index as i64 + offset),
+                // Only the B operand's target is written through a pointer
+                Location::Indirect { .. } => self.operand(index, FieldName::B).target,
+            })
+            .collect();
+
+        // Each written field of an instruction is a separate access
+        writes.dedup();
+        writes
+    }
+
+    /// Addresses execution may continue at after executing the instruction at
+    /// `index`. `None` means a jump target could not be determined.
+    pub fn successors(&self, index: usize) -> Vec<Option<i64>> {
+        use Opcode::*;
+
+        let next = Some(index as i64 + 1);
+        let jump = || self.operand(index, FieldName::A).target;
+
+        match self.instructions[index].opcode {
+            Dat => vec![],
+            Jmp => vec![jump()],
+            Spl | Jmz | Jmn | Djn => vec![next, jump()],
+            Seq | Sne | Cmp | Slt => vec![next, Some(index as i64 + 2)],
+            Mov | Add | Sub | Mul | Div | Mod | Nop | Ldp | Stp => vec![next],
+        }
+    }
+}
+
+pub struct Liveness {
+    /// Instructions which may be executed
+    pub reachable: Vec<bool>,
+
+    /// Instructions which may be executed or are referenced by an operand
+    pub live: Vec<bool>,
+}
+
+/// Determine which instructions are reachable from `origin` or referenced by
+/// a reachable instruction. Returns `None` if this could not be determined,
+/// i.e. there is a jump to an unknown address or if `strict` and there is any
+/// unresolved operand.
+pub fn liveness(resolver: &Resolver, origin: u32, strict: bool) -> Option<Liveness> {
+    let len = resolver.len();
+    let in_program = |address: i64| address >= 0 && (address as usize) < len;
+
+    let mut reachable = vec![false; len];
+    let mut to_visit = vec![i64::from(origin)];
+
+    while let Some(address) = to_visit.pop() {
+        if !in_program(address) || reachable[address as usize] {
+            continue;
+        }
+        reachable[address as usize] = true;
+
+        for successor in resolver.successors(address as usize) {
+            // Jumping somewhere unknown could reach any instruction
+            to_visit.push(successor?);
+        }
+    }
+
+    let mut live = reachable.clone();
+
+    for index in (0..len).filter(|&i| reachable[i]) {
+        for &field_name in &[FieldName::A, FieldName::B] {
+            let operand = resolver.operand(index, field_name);
+
+            if let Some((pointer, _)) = operand.pointer {
+                if in_program(pointer) {
+                    live[pointer as usize] = true;
+                }
+            }
+
+            match operand.target {
+                Some(target) if in_program(target) => live[target as usize] = true,
+                Some(_) => {}
+                None if strict => return None,
+                None => {}
+            }
+        }
+    }
+
+    Some(Liveness { reachable, live })
+}
@@ -0,0 +1,312 @@
+//! Which parts of the core an instruction reads and writes when executed,
+//! under ICWS'94 semantics. This is the one table of data access shared by
+//! the control flow analysis, the linter and `explain`.
+//!
+//! Addresses are relative to the executing instruction. The target of an
+//! indirect operand depends on the value of its pointer when it executes, so
+//! it is only given as the pointer it goes through.
+
+use std::fmt;
+
+use crate::load_file::{AddressMode, FieldName, Instruction, Modifier, Opcode};
+
+/// Where an instruction accesses the core.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Location {
+    /// The instruction this many after the executing one
+    Relative(i64),
+
+    /// The target of an indirect operand, found by adding the `field` of the
+    /// instruction at `pointer` to `pointer`, after any pre-decrement
+    Indirect { pointer: i64, field: FieldName },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Relative(offset) => write!(formatter, "{:+}", offset),
+            Self::Indirect { pointer, field } => {
+                write!(formatter, "{:+} through its {:?}-field", pointer, field)
+            }
+        }
+    }
+}
+
+/// The part of an instruction which is accessed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Part {
+    /// The value of a field
+    Field(FieldName),
+
+    /// The opcode, modifier and address modes, which are only accessed along
+    /// with both fields, as a whole instruction
+    Instruction,
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Field(field) => write!(formatter, "{:?}-field", field),
+            Self::Instruction => write!(formatter, "opcode and modifier"),
+        }
+    }
+}
+
+/// A read or write of part of the instruction at a location.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Access {
+    pub location: Location,
+    pub part: Part,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} of {}", self.part, self.location)
+    }
+}
+
+/// Everything an instruction reads and writes, besides fetching itself, in
+/// the order it happens: evaluating the A operand, then the B operand, then
+/// the operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Footprint {
+    /// Where the A operand refers to
+    pub a_target: Location,
+
+    /// Where the B operand refers to
+    pub b_target: Location,
+
+    pub reads: Vec<Access>,
+    pub writes: Vec<Access>,
+}
+
+impl Footprint {
+    /// Where the `operand` refers to.
+    pub fn target(&self, operand: FieldName) -> Location {
+        match operand {
+            FieldName::A => self.a_target,
+            FieldName::B => self.b_target,
+        }
+    }
+
+    /// The parts of the target of `operand` which are read.
+    pub fn target_reads(&self, operand: FieldName) -> Vec<Part> {
+        Self::parts_at(&self.reads, self.target(operand))
+    }
+
+    /// The parts of the target of `operand` which are written.
+    pub fn target_writes(&self, operand: FieldName) -> Vec<Part> {
+        Self::parts_at(&self.writes, self.target(operand))
+    }
+
+    fn parts_at(accesses: &[Access], location: Location) -> Vec<Part> {
+        accesses
+            .iter()
+            .filter(|access| access.location == location)
+            .map(|access| access.part)
+            .collect()
+    }
+}
+
+/// Where the operand `field_name` of `instruction` refers to, and the pointer
+/// field it reads and writes on the way, if it is indirect.
+fn operand(instruction: &Instruction, field_name: FieldName) -> (Location, Option<Access>, bool) {
+    use AddressMode::*;
+
+    let field = instruction.field(field_name);
+    let offset = i64::from(field.unwrap_value());
+
+    let (pointer_field, modifies) = match field.address_mode {
+        Immediate => return (Location::Relative(0), None, false),
+        Direct => return (Location::Relative(offset), None, false),
+        IndirectA => (FieldName::A, false),
+        IndirectB => (FieldName::B, false),
+        PreDecIndirectA | PostIncIndirectA => (FieldName::A, true),
+        PreDecIndirectB | PostIncIndirectB => (FieldName::B, true),
+    };
+
+    let pointer = Access {
+        location: Location::Relative(offset),
+        part: Part::Field(pointer_field),
+    };
+    let target = Location::Indirect {
+        pointer: offset,
+        field: pointer_field,
+    };
+    (target, Some(pointer), modifies)
+}
+
+fn fields(
+    location: Location,
+    fields: impl Iterator<Item = FieldName>,
+) -> impl Iterator<Item = Access> {
+    fields.map(move |field| Access {
+        location,
+        part: Part::Field(field),
+    })
+}
+
+/// Which fields of which instructions `instruction` reads and writes when
+/// executed, ignoring the values its indirect operands' pointers will have.
+pub fn footprint(instruction: &Instruction) -> Footprint {
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+
+    let mut targets = [Location::Relative(0); 2];
+    for (target, &field_name) in targets.iter_mut().zip(&[FieldName::A, FieldName::B]) {
+        let (location, pointer, modifies) = operand(instruction, field_name);
+        *target = location;
+        if let Some(pointer) = pointer {
+            reads.push(pointer);
+            if modifies {
+                writes.push(pointer);
+            }
+        }
+    }
+    let [a_target, b_target] = targets;
+
+    let pairs = instruction.modifier.field_pairs();
+    let a_fields = || fields(a_target, pairs.iter().map(|pair| pair.a));
+    let b_fields = || fields(b_target, pairs.iter().map(|pair| pair.b));
+
+    match instruction.opcode.field_usage() {
+        Some(usage) => {
+            let whole = instruction.modifier == Modifier::I && usage.whole_instruction;
+            let whole_instruction = |location| {
+                Some(Access {
+                    location,
+                    part: Part::Instruction,
+                })
+                .filter(|_| whole)
+            };
+
+            if usage.reads_a {
+                reads.extend(a_fields());
+                reads.extend(whole_instruction(a_target));
+            }
+            if usage.reads_b {
+                reads.extend(b_fields());
+                reads.extend(whole_instruction(b_target));
+            }
+            if usage.writes_b {
+                writes.extend(b_fields());
+                writes.extend(whole_instruction(b_target));
+            }
+        }
+        // LDP loads the P-space cell given by the A operand into the B operand,
+        // and STP stores the A operand in the P-space cell given by the B operand
+        None if instruction.opcode == Opcode::Ldp => {
+            reads.extend(a_fields());
+            writes.extend(b_fields());
+        }
+        None if instruction.opcode == Opcode::Stp => {
+            reads.extend(a_fields());
+            reads.extend(b_fields());
+        }
+        None => {}
+    }
+
+    Footprint {
+        a_target,
+        b_target,
+        reads,
+        writes,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::load_file::Field;
+
+    use FieldName::{A, B};
+
+    fn field(address_mode: AddressMode, value: i32) -> Field {
+        Field {
+            address_mode,
+            ..Field::direct(value)
+        }
+    }
+
+    #[test]
+    fn mov_i_direct() {
+        let footprint = footprint(&Instruction::new(
+            Opcode::Mov,
+            Field::direct(0),
+            Field::direct(1),
+        ));
+
+        assert_eq!(footprint.a_target, Location::Relative(0));
+        assert_eq!(
+            footprint.target_reads(A),
+            vec![Part::Field(A), Part::Field(B), Part::Instruction]
+        );
+        assert_eq!(footprint.target_reads(B), vec![]);
+        assert_eq!(
+            footprint.target_writes(B),
+            vec![Part::Field(A), Part::Field(B), Part::Instruction]
+        );
+    }
+
+    #[test]
+    fn indirect_operands() {
+        let mut add = Instruction::new(
+            Opcode::Add,
+            field(AddressMode::Immediate, 4),
+            field(AddressMode::PostIncIndirectA, 3),
+        );
+        add.modifier = Modifier::AB;
+        let footprint = footprint(&add);
+
+        let pointer = Access {
+            location: Location::Relative(3),
+            part: Part::Field(A),
+        };
+        let target = Location::Indirect {
+            pointer: 3,
+            field: A,
+        };
+        assert_eq!(
+            footprint.reads,
+            vec![
+                pointer,
+                Access {
+                    location: Location::Relative(0),
+                    part: Part::Field(A),
+                },
+                Access {
+                    location: target,
+                    part: Part::Field(B),
+                },
+            ]
+        );
+        assert_eq!(footprint.writes[0], pointer);
+        assert_eq!(footprint.target_writes(B), vec![Part::Field(B)]);
+        assert_eq!(
+            footprint.writes[1].to_string(),
+            "B-field of +3 through its A-field"
+        );
+    }
+
+    #[test]
+    fn jumps_only_evaluate_operands() {
+        let footprint = footprint(&Instruction::new(
+            Opcode::Jmz,
+            Field::direct(-2),
+            field(AddressMode::PreDecIndirectB, 1),
+        ));
+
+        assert_eq!(footprint.a_target, Location::Relative(-2));
+        assert_eq!(footprint.target_reads(A), vec![]);
+        assert_eq!(footprint.target_reads(B), vec![Part::Field(B)]);
+        assert_eq!(
+            footprint.writes,
+            vec![Access {
+                location: Location::Relative(1),
+                part: Part::Field(B),
+            }]
+        );
+    }
+}
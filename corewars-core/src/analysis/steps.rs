@@ -0,0 +1,176 @@
+//! Checks for bombing and scanning steps which share factors with the core
+//! size, so repeatedly adding them only ever reaches part of the core.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::load_file::{FieldName, Opcode, Program};
+
+use super::flow::Resolver;
+
+/// A constant step added to (or subtracted from) a pointer, which only
+/// reaches every `modulus`th location of the core.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepWarning {
+    /// The index of the `ADD` or `SUB` instruction using the step
+    pub index: usize,
+
+    /// The step, normalized to be within the core
+    pub step: u32,
+
+    /// The greatest common divisor of the step and the core size. A pattern
+    /// with this step is commonly called "mod-`modulus`"
+    pub modulus: u32,
+
+    /// The number of distinct locations the step reaches
+    pub locations: u32,
+}
+
+impl StepWarning {
+    /// The stable diagnostic code of this warning, which the parser's
+    /// `explain_code` documents along with its own diagnostics.
+    pub const CODE: &'static str = "W0007";
+}
+
+impl fmt::Display for StepWarning {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "instruction {} uses step {}, which is mod-{} and only reaches {} locations, \
+             so it can miss warriors shorter than {} instructions",
+            self.index, self.step, self.modulus, self.locations, self.modulus
+        )
+    }
+}
+
+/// Find constant steps used by `ADD` and `SUB` instructions which could miss
+/// an opponent at least `min_opponent_length` instructions long entirely in
+/// a core of `core_size`. Steps are propagated through data, e.g.
+/// `ADD step, ptr` where `step` is a `DAT` which the program never writes to.
+pub fn step_warnings(
+    program: &Program,
+    core_size: u32,
+    min_opponent_length: u32,
+) -> Vec<StepWarning> {
+    // Bombs usually go through pointers that change as the program runs, so
+    // proving nothing is written through them is hopeless. Instead, assume
+    // pointers keep their initial values and exclude anything written
+    let resolver = Resolver::assuming_constant(&program.instructions);
+    let written: HashSet<i64> = (0..program.instructions.len())
+        .flat_map(|index| resolver.writes(index))
+        .flatten()
+        .collect();
+
+    let mut warnings = Vec::new();
+
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        if !matches!(instruction.opcode, Opcode::Add | Opcode::Sub) {
+            continue;
+        }
+
+        let source = match resolver.operand(index, FieldName::A).target {
+            Some(target) if !written.contains(&target) => resolver.get(target),
+            _ => None,
+        };
+
+        let source = match source {
+            Some(source) => source,
+            None => continue,
+        };
+
+        let mut steps: Vec<u32> = instruction
+            .modifier
+            .field_pairs()
+            .iter()
+            .map(|pair| {
+                let value = i64::from(source.field(pair.a).unwrap_value());
+                value.rem_euclid(i64::from(core_size)) as u32
+            })
+            .collect();
+        steps.dedup();
+
+        for step in steps {
+            let modulus = gcd(step, core_size);
+
+            if modulus > min_opponent_length {
+                warnings.push(StepWarning {
+                    index,
+                    step,
+                    modulus,
+                    locations: core_size / modulus,
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+    a
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::load_file::{Field, Instruction, Modifier};
+
+    fn program(instructions: Vec<Instruction>) -> Program {
+        Program {
+            instructions: instructions.into(),
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn warns_for_large_modulus() {
+        let mut add = Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(3));
+        add.modifier = Modifier::AB;
+        let mut input = program(vec![
+            add,
+            Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
+        ]);
+
+        assert_eq!(step_warnings(&input, 8000, 4), Vec::new());
+
+        input.instructions[0].a_field = Field::immediate(3000);
+        assert_eq!(
+            step_warnings(&input, 8000, 4),
+            vec![StepWarning {
+                index: 0,
+                step: 3000,
+                modulus: 1000,
+                locations: 8,
+            }]
+        );
+    }
+
+    #[test]
+    fn propagates_constant_steps() {
+        let mut sub = Instruction::new(Opcode::Sub, Field::direct(2), Field::direct(3));
+        sub.modifier = Modifier::F;
+        let input = program(vec![
+            sub,
+            Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
+            Instruction::new(Opcode::Dat, Field::immediate(-10), Field::immediate(3)),
+        ]);
+
+        let warnings = step_warnings(&input, 8000, 4);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].step, 7990);
+        assert_eq!(warnings[0].modulus, 10);
+        assert_eq!(
+            warnings[0].to_string(),
+            "instruction 0 uses step 7990, which is mod-10 and only reaches 800 locations, \
+             so it can miss warriors shorter than 10 instructions"
+        );
+    }
+}
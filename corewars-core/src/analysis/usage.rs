@@ -0,0 +1,96 @@
+//! Counts of the opcodes, modifiers and address modes a program uses, e.g. to
+//! check it is eligible for a hill which only allows some of them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::load_file::{AddressMode, Modifier, Opcode, Program};
+
+/// How many times each opcode, modifier and address mode appears in a
+/// program. Anything which doesn't appear is left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub opcodes: BTreeMap<Opcode, usize>,
+    pub modifiers: BTreeMap<Modifier, usize>,
+
+    /// Counted once for each field, so an instruction can use two
+    pub address_modes: BTreeMap<AddressMode, usize>,
+}
+
+impl Usage {
+    /// The number of fields using `mode`.
+    pub fn address_mode(&self, mode: AddressMode) -> usize {
+        self.address_modes.get(&mode).copied().unwrap_or_default()
+    }
+
+    /// The number of instructions using `modifier`.
+    pub fn modifier(&self, modifier: Modifier) -> usize {
+        self.modifiers.get(&modifier).copied().unwrap_or_default()
+    }
+}
+
+/// Each count on a line, e.g. `address mode > 2`, so they are easy to check
+/// from a script.
+impl fmt::Display for Usage {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines = Vec::new();
+        for (opcode, count) in &self.opcodes {
+            lines.push(format!("opcode {} {}", opcode, count));
+        }
+        for (modifier, count) in &self.modifiers {
+            lines.push(format!("modifier {} {}", modifier, count));
+        }
+        for (mode, count) in &self.address_modes {
+            lines.push(format!("address mode {} {}", mode, count));
+        }
+
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+/// Count the opcodes, modifiers and address modes used by `program`.
+pub fn usage(program: &Program) -> Usage {
+    let mut usage = Usage::default();
+
+    for instruction in &program.instructions {
+        *usage.opcodes.entry(instruction.opcode).or_default() += 1;
+        *usage.modifiers.entry(instruction.modifier).or_default() += 1;
+        for field in [&instruction.a_field, &instruction.b_field] {
+            *usage.address_modes.entry(field.address_mode).or_default() += 1;
+        }
+    }
+
+    usage
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::load_file::{Field, Instruction};
+
+    #[test]
+    fn counts_usage() {
+        let mut mov = Instruction::new(Opcode::Mov, Field::direct(2), Field::direct(2));
+        mov.modifier = Modifier::I;
+        mov.b_field.address_mode = AddressMode::IndirectB;
+        let program = Program {
+            instructions: vec![
+                Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(3)),
+                mov,
+                Instruction::new(Opcode::Jmp, Field::direct(-2), Field::immediate(0)),
+            ]
+            .into(),
+            origin: None,
+        };
+
+        let usage = usage(&program);
+        assert_eq!(usage.opcodes.len(), 3);
+        assert_eq!(usage.modifier(Modifier::I), 1);
+        assert_eq!(usage.modifier(Modifier::X), 0);
+        assert_eq!(usage.address_mode(AddressMode::Direct), 3);
+        assert_eq!(usage.address_mode(AddressMode::PostIncIndirectB), 0);
+        assert!(usage.to_string().ends_with("address mode @ 1"));
+    }
+}
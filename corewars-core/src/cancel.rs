@@ -0,0 +1,50 @@
+//! Cooperative cancellation of long operations, e.g. expanding a huge `FOR`
+//! loop or running a battle for millions of cycles, so an embedder such as a
+//! GUI or a server can give up on one promptly without killing its thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared between whoever wants an operation stopped and the
+/// operation itself, which checks it between units of work. Clones share the
+/// same flag, so a clone can be handed to the operation and the original
+/// cancelled from another thread.
+///
+/// ```
+/// use corewars_core::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let worker = token.clone();
+/// assert!(!worker.is_cancelled());
+///
+/// token.cancel();
+/// assert!(worker.is_cancelled());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// A token which hasn't been cancelled yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding a clone of this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Tokens are equal if they are clones of each other, since cancelling one
+/// cancels the other.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
@@ -3,7 +3,12 @@
 mod util;
 
 // Public modules
+pub mod analysis;
+pub mod cancel;
 pub mod load_file;
+pub mod perf;
+pub mod text;
 
 // Re-exports
+pub use cancel::CancellationToken;
 pub use load_file::Warrior;
@@ -2,16 +2,24 @@ use std::fmt;
 
 use lazy_static::lazy_static;
 use maplit::hashmap;
+use sha2::{Digest, Sha256};
 
+use crate::text::TextLayout;
+
+mod instructions;
 mod metadata;
 mod offset;
 mod program;
 mod types;
 
-pub use metadata::Metadata;
+pub use instructions::{Instructions, InstructionsBuilder, LengthError};
+pub use metadata::{Metadata, Provenance};
 pub use offset::Offset;
-pub use program::{Instructions, LabelMap, Program};
-pub use types::{AddressMode, Modifier, Opcode, PseudoOpcode, Value};
+pub use program::{LabelMap, Program};
+pub use types::{
+    AddressMode, FieldName, FieldPair, FieldUsage, Modifier, ModifierPolicy, Opcode, PseudoOpcode,
+    Standard, Value,
+};
 
 lazy_static! {
     // TODO: handle command-line constant redefinition and things like
@@ -27,7 +35,7 @@ lazy_static! {
 }
 
 /// The main public struct used to represent a Redcode warrior
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
 pub struct Warrior {
     pub program: Program,
     pub metadata: Metadata,
@@ -36,6 +44,10 @@ pub struct Warrior {
 impl fmt::Display for Warrior {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         write!(formatter, "{}", self.metadata)?;
+        if let Some(pin) = self.metadata.pin {
+            // Width to match other instruction types
+            writeln!(formatter, "{:<8}{}", PseudoOpcode::Pin, pin)?;
+        }
         write!(formatter, "{}", self.program)
     }
 }
@@ -50,13 +62,101 @@ impl Warrior {
     pub fn is_empty(&self) -> bool {
         self.program.instructions.is_empty()
     }
+
+    /// A name for this warrior, the `index`th of several, which none of the
+    /// others before it have according to `taken`: its own name, or
+    /// `Warrior3` if it has none, numbered like `Imp (2)` if that is taken.
+    pub fn distinct_name<F: Fn(&str) -> bool>(&self, index: usize, taken: F) -> String {
+        let base = self
+            .metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Warrior{}", index));
+
+        let mut name = base.clone();
+        for number in 2.. {
+            if !taken(&name) {
+                break;
+            }
+            name = format!("{} ({})", base, number);
+        }
+        name
+    }
+
+    /// A copy of this warrior with a different name.
+    pub fn renamed(&self, name: String) -> Self {
+        Self {
+            program: self.program.clone(),
+            metadata: Metadata {
+                name: Some(name),
+                ..self.metadata.clone()
+            },
+        }
+    }
+
+    /// This warrior in load file format, with lines ended as in `layout`.
+    pub fn to_text(&self, layout: &TextLayout) -> String {
+        layout.apply(&self.to_string())
+    }
+
+    /// A hex-encoded SHA-256 hash identifying this warrior's program. Warriors
+    /// have the same digest exactly when they have the same instructions and
+    /// origin, regardless of formatting, comments, or metadata.
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+
+        for instruction in self.program.instructions.iter() {
+            hasher.update([
+                position(Opcode::iter_values(), instruction.opcode),
+                position(Modifier::iter_values(), instruction.modifier),
+            ]);
+
+            for field in &[&instruction.a_field, &instruction.b_field] {
+                hasher.update([position(AddressMode::iter_values(), field.address_mode)]);
+                match &field.value {
+                    Value::Literal(value) => {
+                        hasher.update([0]);
+                        hasher.update(value.to_le_bytes());
+                    }
+                    Value::Label(label) => {
+                        // Length-prefixed, so labels can't run into the next field
+                        hasher.update([1]);
+                        hasher.update((label.len() as u32).to_le_bytes());
+                        hasher.update(label.as_bytes());
+                    }
+                }
+            }
+        }
+
+        hasher.update(self.program.origin.unwrap_or(0).to_le_bytes());
+
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// The [distinct name](Warrior::distinct_name) of each of `warriors`, so
+/// that warriors with the same name can be told apart.
+pub fn distinct_names(warriors: &[Warrior]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(warriors.len());
+    for (i, warrior) in warriors.iter().enumerate() {
+        let name = warrior.distinct_name(i, |name| names.iter().any(|taken| taken == name));
+        names.push(name);
+    }
+    names
+}
+
+/// The index of `value` in `values`, e.g. from `Opcode::iter_values()`.
+fn position<T: PartialEq + 'static>(mut values: impl Iterator<Item = &'static T>, value: T) -> u8 {
+    values
+        .position(|other| *other == value)
+        .expect("value should be one of all values") as u8
 }
 
 // It might be useful to have a "target" vs  a "value" function,
 // based on the address mode I guess...
 //
 // See docs/icws94.txt:891
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Field {
     pub address_mode: AddressMode,
     pub value: Value,
@@ -103,7 +203,7 @@ impl Field {
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Instruction {
     pub opcode: Opcode,
     pub modifier: Modifier,
@@ -123,6 +223,22 @@ impl Instruction {
             b_field,
         }
     }
+
+    /// Get one of the fields of this instruction by name
+    pub fn field(&self, name: FieldName) -> &Field {
+        match name {
+            FieldName::A => &self.a_field,
+            FieldName::B => &self.b_field,
+        }
+    }
+
+    /// Get one of the fields of this instruction by name, mutably
+    pub fn field_mut(&mut self, name: FieldName) -> &mut Field {
+        match name {
+            FieldName::A => &mut self.a_field,
+            FieldName::B => &mut self.b_field,
+        }
+    }
 }
 
 impl fmt::Display for Instruction {
@@ -160,4 +276,33 @@ mod test {
 
         assert_eq!(Instruction::default(), expected_instruction)
     }
+
+    #[test]
+    fn digest_identifies_program() {
+        let imp = |name: &str, origin| Warrior {
+            program: Program {
+                instructions: vec![Instruction::new(
+                    Opcode::Mov,
+                    Field::direct(0),
+                    Field::direct(1),
+                )]
+                .into(),
+                origin,
+            },
+            metadata: Metadata {
+                name: Some(name.into()),
+                ..Metadata::default()
+            },
+        };
+
+        let digest = imp("Imp", None).digest();
+        assert_eq!(digest.len(), 64);
+        assert_eq!(digest, imp("Renamed", None).digest());
+        assert_eq!(digest, imp("Imp", Some(0)).digest());
+        assert_ne!(digest, imp("Imp", Some(1)).digest());
+
+        let mut bomb = imp("Imp", None);
+        bomb.program.instructions[0].b_field = Field::immediate(1);
+        assert_ne!(digest, bomb.digest());
+    }
 }
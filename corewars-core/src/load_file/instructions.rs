@@ -0,0 +1,435 @@
+//! The instructions of a program, in the order they are loaded into a core.
+
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+
+use super::{AddressMode, Instruction, Offset, Program, Value};
+
+/// Why instructions can't be used as a warrior.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LengthError {
+    /// There were no instructions
+    Empty,
+
+    /// There were more instructions than the maximum length, e.g. MAXLENGTH
+    TooLong { length: usize, max: usize },
+
+    /// The origin was not one of the instructions
+    InvalidOrigin { origin: u32, length: usize },
+}
+
+impl fmt::Display for LengthError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(formatter, "warrior has no instructions"),
+            Self::TooLong { length, max } => write!(
+                formatter,
+                "warrior has {} instructions, more than the maximum length {}",
+                length, max
+            ),
+            Self::InvalidOrigin { origin, length } => write!(
+                formatter,
+                "warrior origin {} is outside its {} instructions",
+                origin, length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LengthError {}
+
+/// Instructions which are known to fit in a warrior: there is at least one,
+/// and no more than the maximum length they were checked against. They can
+/// only be made by checking, e.g. with [`validated`](Self::validated) or
+/// [`InstructionsBuilder::build`], and can't be changed afterwards, only read
+/// as a slice.
+///
+/// ```
+/// use corewars_core::load_file::{Instruction, Instructions, LengthError};
+///
+/// let instructions = Instructions::validated(vec![Instruction::default(); 3], 100).unwrap();
+/// assert_eq!(instructions.len(), 3);
+/// assert_eq!(instructions.wrapping_slice(2, 2).len(), 2);
+///
+/// assert_eq!(Instructions::validated(Vec::new(), 100), Err(LengthError::Empty));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Instructions(Vec<Instruction>);
+
+impl Instructions {
+    /// Instructions for a warrior at most `max_length` long, e.g. MAXLENGTH.
+    pub fn validated(
+        instructions: Vec<Instruction>,
+        max_length: usize,
+    ) -> Result<Self, LengthError> {
+        if instructions.is_empty() {
+            return Err(LengthError::Empty);
+        }
+        if instructions.len() > max_length {
+            return Err(LengthError::TooLong {
+                length: instructions.len(),
+                max: max_length,
+            });
+        }
+
+        Ok(Self(instructions))
+    }
+
+    /// A program starting from the instruction at `origin`.
+    pub fn with_origin(self, origin: u32) -> Result<Program, LengthError> {
+        if origin as usize >= self.len() {
+            return Err(LengthError::InvalidOrigin {
+                origin,
+                length: self.len(),
+            });
+        }
+
+        Ok(Program {
+            instructions: self.0.into(),
+            origin: Some(origin),
+        })
+    }
+
+    pub fn into_vec(self) -> Vec<Instruction> {
+        self.0
+    }
+
+    /// `len` instructions starting from the one at `start`, wrapping around
+    /// to the first after the last, like a region of a core.
+    pub fn wrapping_slice(&self, start: usize, len: usize) -> InstructionsBuilder {
+        self.iter()
+            .cycle()
+            .skip(start % self.len())
+            .take(len)
+            .cloned()
+            .collect()
+    }
+
+    /// A copy starting from the instruction at `offset`, see
+    /// [`InstructionsBuilder::relocated`]. This keeps the same number of
+    /// instructions, so they still fit.
+    pub fn relocated(&self, offset: u32, core_size: u32) -> Self {
+        Self(relocated(&self.0, offset, core_size))
+    }
+}
+
+impl Deref for Instructions {
+    type Target = [Instruction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Instructions> for Vec<Instruction> {
+    fn from(instructions: Instructions) -> Self {
+        instructions.0
+    }
+}
+
+impl IntoIterator for Instructions {
+    type Item = Instruction;
+    type IntoIter = std::vec::IntoIter<Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Instructions {
+    type Item = &'a Instruction;
+    type IntoIter = std::slice::Iter<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// One instruction per line, in load file format.
+impl fmt::Display for Instructions {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write_lines(&self.0, formatter)
+    }
+}
+
+/// A sequence of instructions which may not fit in a warrior yet, e.g. while
+/// a program is still being assembled. They can be collected from anything
+/// and changed freely, and derefs to a slice of them, until they are
+/// [built](Self::build) into [`Instructions`].
+///
+/// ```
+/// use corewars_core::load_file::{Instruction, InstructionsBuilder, LengthError};
+///
+/// let mut builder = InstructionsBuilder::new();
+/// assert_eq!(builder.clone().build(100), Err(LengthError::Empty));
+///
+/// builder.push(Instruction::default());
+/// assert_eq!(builder.build(100).unwrap().len(), 1);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct InstructionsBuilder(Vec<Instruction>);
+
+impl InstructionsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(Vec::with_capacity(capacity))
+    }
+
+    /// Check that the instructions fit in a warrior at most `max_length`
+    /// long, like [`Instructions::validated`].
+    pub fn build(self, max_length: usize) -> Result<Instructions, LengthError> {
+        Instructions::validated(self.0, max_length)
+    }
+
+    pub fn push(&mut self, instruction: Instruction) {
+        self.0.push(instruction);
+    }
+
+    /// Make sure there are at least `len` instructions, adding default ones
+    /// to the end if needed.
+    pub fn extend_to(&mut self, len: usize) {
+        if self.0.len() < len {
+            self.0.resize_with(len, Default::default);
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<Instruction> {
+        self.0
+    }
+
+    /// A copy starting from the instruction at `offset`, wrapping around to
+    /// the ones before it, for a core of `core_size`, e.g. to write out part
+    /// of a core as a standalone warrior. Fields which refer to another
+    /// instruction of the block are adjusted to still refer to it, so the
+    /// copy behaves the same wherever it's loaded. Other fields, e.g.
+    /// bombing targets and immediate values, are only written as signed
+    /// offsets like [`Program::normalized`](Program::normalized).
+    pub fn relocated(&self, offset: u32, core_size: u32) -> Self {
+        Self(relocated(&self.0, offset, core_size))
+    }
+}
+
+fn relocated(instructions: &[Instruction], offset: u32, core_size: u32) -> Vec<Instruction> {
+    let len = instructions.len() as i64;
+    if len == 0 {
+        return Vec::new();
+    }
+    let offset = i64::from(offset) % len;
+    let size = i64::from(core_size);
+
+    (0..len)
+        .map(|new_index| {
+            let old_index = (new_index + offset) % len;
+            let mut instruction = instructions[old_index as usize].clone();
+
+            for field in [&mut instruction.a_field, &mut instruction.b_field] {
+                if let Value::Literal(value) = field.value {
+                    let target = (old_index + i64::from(value)).rem_euclid(size);
+                    let value = if field.address_mode != AddressMode::Immediate && target < len {
+                        (target - offset).rem_euclid(len) - new_index
+                    } else {
+                        i64::from(value)
+                    };
+                    field.value = Offset::new(value.rem_euclid(size) as i32, core_size)
+                        .signed_value()
+                        .into();
+                }
+            }
+
+            instruction
+        })
+        .collect()
+}
+
+fn write_lines(instructions: &[Instruction], formatter: &mut fmt::Formatter) -> fmt::Result {
+    for (i, instruction) in instructions.iter().enumerate() {
+        if i > 0 {
+            writeln!(formatter)?;
+        }
+        write!(formatter, "{}", instruction)?;
+    }
+    Ok(())
+}
+
+impl Deref for InstructionsBuilder {
+    type Target = [Instruction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for InstructionsBuilder {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<Instruction>> for InstructionsBuilder {
+    fn from(instructions: Vec<Instruction>) -> Self {
+        Self(instructions)
+    }
+}
+
+impl From<Instructions> for InstructionsBuilder {
+    fn from(instructions: Instructions) -> Self {
+        Self(instructions.0)
+    }
+}
+
+impl From<InstructionsBuilder> for Vec<Instruction> {
+    fn from(instructions: InstructionsBuilder) -> Self {
+        instructions.0
+    }
+}
+
+impl FromIterator<Instruction> for InstructionsBuilder {
+    fn from_iter<I: IntoIterator<Item = Instruction>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Instruction> for InstructionsBuilder {
+    fn extend<I: IntoIterator<Item = Instruction>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+impl IntoIterator for InstructionsBuilder {
+    type Item = Instruction;
+    type IntoIter = std::vec::IntoIter<Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a InstructionsBuilder {
+    type Item = &'a Instruction;
+    type IntoIter = std::slice::Iter<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut InstructionsBuilder {
+    type Item = &'a mut Instruction;
+    type IntoIter = std::slice::IterMut<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+/// One instruction per line, in load file format.
+impl fmt::Display for InstructionsBuilder {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write_lines(&self.0, formatter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::load_file::{Field, Opcode};
+
+    fn dwarf() -> Instructions {
+        let mut mov = Instruction::new(Opcode::Mov, Field::direct(2), Field::direct(2));
+        mov.b_field.address_mode = AddressMode::IndirectB;
+        let instructions = vec![
+            Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(3)),
+            mov,
+            Instruction::new(Opcode::Jmp, Field::direct(-2), Field::direct(0)),
+            Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(0)),
+        ];
+        Instructions::validated(instructions, 100).unwrap()
+    }
+
+    #[test]
+    fn validates_length() {
+        assert_eq!(
+            Instructions::validated(dwarf().into_vec(), 3),
+            Err(LengthError::TooLong { length: 4, max: 3 })
+        );
+        assert_eq!(
+            dwarf().with_origin(4),
+            Err(LengthError::InvalidOrigin {
+                origin: 4,
+                length: 4
+            })
+        );
+        assert_eq!(dwarf().with_origin(2).unwrap().origin, Some(2));
+    }
+
+    #[test]
+    fn builds_instructions() {
+        let mut builder: InstructionsBuilder = dwarf().into();
+        builder.extend_to(6);
+        builder[5] = dwarf()[0].clone();
+
+        assert_eq!(
+            builder.clone().build(5),
+            Err(LengthError::TooLong { length: 6, max: 5 })
+        );
+        let built = builder.build(6).unwrap();
+        assert_eq!(built[4], Instruction::default());
+        assert_eq!(built[5], dwarf()[0]);
+        assert_eq!(InstructionsBuilder::new().build(6), Err(LengthError::Empty));
+    }
+
+    #[test]
+    fn slices_with_wrapping() {
+        let instructions = dwarf();
+        let slice = instructions.wrapping_slice(3, 3);
+        assert_eq!(
+            slice.iter().map(|i| i.opcode).collect::<Vec<_>>(),
+            vec![Opcode::Dat, Opcode::Add, Opcode::Mov]
+        );
+        assert_eq!(
+            instructions.wrapping_slice(1, 2).to_string(),
+            "MOV.I   $2,     @2\nJMP.B   $-2,    $0"
+        );
+    }
+
+    #[test]
+    fn relocates_instructions() {
+        let dwarf = Program {
+            instructions: dwarf().into(),
+            origin: None,
+        };
+
+        let relocated = dwarf.relocated(2, 8000);
+        assert_eq!(
+            relocated.instructions.to_string(),
+            "JMP.B   $2,     $0\n\
+             DAT.F   #0,     #0\n\
+             ADD.AB  #4,     $-1\n\
+             MOV.I   $-2,    @-2"
+        );
+        assert_eq!(relocated.origin, Some(2));
+        assert_eq!(
+            relocated.relocated(2, 8000),
+            Program {
+                origin: Some(0),
+                ..dwarf.clone()
+            }
+        );
+
+        // References outside the block keep their target
+        let bomb: InstructionsBuilder = vec![Instruction::new(
+            Opcode::Mov,
+            Field::direct(0),
+            Field::direct(7990),
+        )]
+        .into();
+        assert_eq!(bomb.relocated(0, 8000)[0].b_field, Field::direct(-10));
+    }
+}
@@ -4,7 +4,7 @@
 use std::fmt;
 
 /// Metadata about a Redcode program that is stored in the comments.
-#[derive(Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Metadata {
     /// The Redcode standard for this warrior (e.g. "94").
     // TODO #38 handle directives like `redcode-94` etc.
@@ -22,18 +22,28 @@ pub struct Metadata {
     /// The version of this warrior.
     pub version: Option<String>,
 
-    /// A description of the warrior's strategy
-    // TODO #38 handle multiline strategies
+    /// A description of the warrior's strategy. Each line of a multiline
+    /// strategy is written as a separate `;strategy` comment.
+    // TODO #38 handle parsing multiline strategies
     pub strategy: Option<String>,
 
     /// An assertion for this warrior to ensure compilation.
     pub assertion: Option<String>,
+
+    /// A detached signature by the author over the warrior's
+    /// [digest](crate::Warrior::digest), hex-encoded.
+    pub signature: Option<String>,
+
+    /// The P-space identifier given by `PIN`. Warriors with the same PIN
+    /// share their P-space, otherwise each warrior has its own. This is set
+    /// by a `PIN` line rather than a comment.
+    pub pin: Option<u32>,
 }
 
 impl Metadata {
     /// Parse warrior metadata out of a line. Any comments will be removed and
-    /// the resulting string returned, with whitespace trimmed.
-    pub fn parse_line(&mut self, line: &str) -> String {
+    /// the rest of the line returned, with whitespace trimmed.
+    pub fn parse_line<'a>(&mut self, line: &'a str) -> &'a str {
         let split_line: Vec<&str> = line.splitn(2, ';').map(|p| p.trim()).collect();
 
         if split_line.len() > 1 {
@@ -53,11 +63,81 @@ impl Metadata {
                 "version" => self.version = value,
                 "strategy" => self.strategy = value,
                 "assert" => self.assertion = value,
+                "signature" => self.signature = value,
                 _ => (),
             }
         }
 
-        split_line[0].trim().to_string()
+        split_line[0].trim()
+    }
+
+    /// Fill in metadata for a warrior produced by a program rather than
+    /// written by hand, so it can be traced back to how it was generated.
+    /// `name` and `author` are only set if they are missing, while the
+    /// provenance is appended to any existing strategy.
+    pub fn annotate_generated(&mut self, provenance: &Provenance) {
+        if self.name.is_none() {
+            self.name = Some(provenance.default_name());
+        }
+
+        if self.author.is_none() {
+            self.author = Some(provenance.generator.clone());
+        }
+
+        let mut strategy: Vec<String> = self.strategy.iter().cloned().collect();
+        strategy.extend(provenance.strategy_lines());
+        self.strategy = Some(strategy.join("\n"));
+    }
+}
+
+/// A description of how a warrior was generated, e.g. by an evolver or
+/// parameter tuner.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Provenance {
+    /// The name of the program which generated the warrior.
+    pub generator: String,
+
+    /// The generation number, for iterative generators.
+    pub generation: Option<u64>,
+
+    /// Hashes identifying the warriors this one was derived from.
+    pub parents: Vec<String>,
+
+    /// The parameters used to generate the warrior, in order.
+    pub parameters: Vec<(String, String)>,
+}
+
+impl Provenance {
+    pub fn new(generator: impl Into<String>) -> Self {
+        Self {
+            generator: generator.into(),
+            ..Self::default()
+        }
+    }
+
+    fn default_name(&self) -> String {
+        match self.generation {
+            Some(generation) => format!("{} generation {}", self.generator, generation),
+            None => self.generator.clone(),
+        }
+    }
+
+    fn strategy_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("generated by {}", self.generator)];
+
+        if let Some(generation) = self.generation {
+            lines.push(format!("generation {}", generation));
+        }
+
+        if !self.parents.is_empty() {
+            lines.push(format!("parents {}", self.parents.join(" ")));
+        }
+
+        for (key, value) in &self.parameters {
+            lines.push(format!("parameter {}={}", key, value));
+        }
+
+        lines
     }
 }
 
@@ -71,12 +151,19 @@ impl fmt::Display for Metadata {
             (&self.date, "date"),
             (&self.strategy, "strategy"),
             (&self.assertion, "assert"),
+            (&self.signature, "signature"),
         ] {
             if let Some(value) = field.as_deref() {
                 if value.is_empty() {
                     writeln!(formatter, ";{}", name)?;
-                } else {
-                    writeln!(formatter, ";{} {}", name, value)?;
+                }
+
+                for line in value.lines() {
+                    if line.is_empty() {
+                        writeln!(formatter, ";{}", name)?;
+                    } else {
+                        writeln!(formatter, ";{} {}", name, line)?;
+                    }
                 }
             }
         }
@@ -85,3 +172,57 @@ impl fmt::Display for Metadata {
 }
 
 // TODO as part of #38 test parse_line
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn annotate_generated() {
+        let mut metadata = Metadata {
+            redcode: Some("94".into()),
+            strategy: Some("paper".into()),
+            ..Metadata::default()
+        };
+
+        metadata.annotate_generated(&Provenance {
+            generator: "evolver".into(),
+            generation: Some(12),
+            parents: vec!["abc123".into(), "def456".into()],
+            parameters: vec![("step".into(), "3044".into())],
+        });
+
+        assert_eq!(
+            metadata.to_string(),
+            [
+                ";redcode 94",
+                ";name evolver generation 12",
+                ";author evolver",
+                ";strategy paper",
+                ";strategy generated by evolver",
+                ";strategy generation 12",
+                ";strategy parents abc123 def456",
+                ";strategy parameter step=3044",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn annotate_keeps_existing_fields() {
+        let mut metadata = Metadata {
+            name: Some("Imp".into()),
+            author: Some("A. K. Dewdney".into()),
+            ..Metadata::default()
+        };
+
+        metadata.annotate_generated(&Provenance::new("tuner"));
+
+        assert_eq!(metadata.name.as_deref(), Some("Imp"));
+        assert_eq!(metadata.author.as_deref(), Some("A. K. Dewdney"));
+        assert_eq!(metadata.strategy.as_deref(), Some("generated by tuner"));
+    }
+}
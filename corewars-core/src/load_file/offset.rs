@@ -1,7 +1,7 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Rem, RemAssign, Sub, SubAssign};
 
 /// A non-negative offset from the beginning of a core.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub struct Offset {
     value: u32,
     core_size: u32,
@@ -34,6 +34,17 @@ impl Offset {
         self.value
     }
 
+    /// Get the value of the offset as the nearest signed distance from the
+    /// start of the core, which is how load files usually write fields. This
+    /// is greater than `-core_size / 2` and at most `core_size / 2`.
+    pub fn signed_value(&self) -> i32 {
+        if self.value > self.core_size / 2 {
+            self.value as i32 - self.core_size as i32
+        } else {
+            self.value as i32
+        }
+    }
+
     /// Set the value of the offset. The value will be adjusted to be within
     /// bounds of the core size.
     pub fn set_value(&mut self, value: i32) {
@@ -146,6 +157,15 @@ mod tests {
         assert_eq!(offset.value(), 8);
     }
 
+    #[test]
+    fn signed_offset_value() {
+        assert_eq!(Offset::new(4000, 8000).signed_value(), 4000);
+        assert_eq!(Offset::new(4001, 8000).signed_value(), -3999);
+        assert_eq!(Offset::new(-4000, 8000).signed_value(), 4000);
+        assert_eq!(Offset::new(-1, 8000).signed_value(), -1);
+        assert_eq!(Offset::new(6, 7).signed_value(), -1);
+    }
+
     #[test]
     fn add_offset() {
         let mut offset = Offset::new(0, 12);
@@ -3,17 +3,16 @@
 
 use std::{collections::HashMap, fmt};
 
-use super::{Instruction, PseudoOpcode};
+use super::{Instruction, InstructionsBuilder, Offset, PseudoOpcode, Value};
 
-pub type Instructions = Vec<Instruction>;
 pub type LabelMap = HashMap<String, u32>;
 
 /// A parsed Redcode program, which can be loaded into a core for execution
-#[derive(Default, PartialEq)]
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
 pub struct Program {
     /// The list of instructions in the program. These are one-to-one copied into
-    /// the core when loaded for execution
-    pub instructions: Instructions,
+    /// the core when loaded for execution, which checks that they fit
+    pub instructions: InstructionsBuilder,
 
     /// The program's entry point as an instruction index
     pub origin: Option<u32>,
@@ -25,11 +24,45 @@ impl Program {
     }
 
     pub fn set(&mut self, index: usize, value: Instruction) {
-        if index >= self.instructions.len() {
-            self.instructions.resize_with(index + 1, Default::default);
+        self.instructions.extend_to(index + 1);
+        self.instructions[index] = value;
+    }
+
+    /// A copy of this program with every field value and the origin wrapped
+    /// to the given core size. Fields are written as signed offsets, e.g.
+    /// `-1` rather than `7999`, since that's how they are usually read.
+    pub fn normalized(&self, core_size: u32) -> Self {
+        let mut normalized = self.clone();
+
+        for instruction in normalized.instructions.iter_mut() {
+            for field in [&mut instruction.a_field, &mut instruction.b_field] {
+                if let Value::Literal(value) = field.value {
+                    field.value = Offset::new(value, core_size).signed_value().into();
+                }
+            }
         }
 
-        self.instructions[index] = value;
+        normalized.origin = self
+            .origin
+            .map(|origin| Offset::new(origin as i32, core_size).value());
+        normalized
+    }
+
+    /// A copy of this program starting from the instruction at `offset`, see
+    /// [`InstructionsBuilder::relocated`](InstructionsBuilder::relocated),
+    /// with the origin moved to the same instruction as before.
+    pub fn relocated(&self, offset: u32, core_size: u32) -> Self {
+        let len = self.instructions.len() as u32;
+        let origin = match (self.origin, len) {
+            (_, 0) => self.origin,
+            (None, _) if offset.is_multiple_of(len) => None,
+            (origin, _) => Some((origin.unwrap_or_default() % len + len - offset % len) % len),
+        };
+
+        Self {
+            instructions: self.instructions.relocated(offset, core_size),
+            origin,
+        }
     }
 }
 
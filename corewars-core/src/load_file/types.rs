@@ -1,10 +1,44 @@
 use std::fmt;
+use std::str::FromStr;
 
 enum_string! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    /// A revision of the Redcode language.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum Standard {
+        Icws86 => "86",
+        Icws88 => "88",
+        Icws94 => "94",
+    }
+}
+
+impl Standard {
+    /// The standards which can be parsed and run by this crate. ICWS'88
+    /// programs are converted to ICWS'94 by inferring instruction modifiers.
+    pub fn supported() -> &'static [Self] {
+        &[Self::Icws88, Self::Icws94]
+    }
+
+    /// Whether programs written for this standard can be parsed and run.
+    pub fn is_supported(self) -> bool {
+        Self::supported().contains(&self)
+    }
+
+    /// A short human-readable description of the standard.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Icws86 => "ICWS'86 standard",
+            Self::Icws88 => "ICWS'88 standard",
+            Self::Icws94 => "ICWS'94 draft standard, with pMARS extensions",
+        }
+    }
+}
+
+enum_string! {
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum Opcode {
         Add => "ADD",
         Cmp => "CMP",
+        #[default]
         Dat => "DAT",
         Div => "DIV",
         Djn => "DJN",
@@ -25,43 +59,172 @@ enum_string! {
     }
 }
 
-impl Default for Opcode {
-    fn default() -> Self {
-        Self::Dat
-    }
-}
-
 enum_string! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum PseudoOpcode {
         Org => "ORG",
         End => "END",
         Equ => "EQU",
         For => "FOR",
+        Pin => "PIN",
     }
 }
 
 enum_string! {
     #[allow(clippy::upper_case_acronyms)]
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum Modifier {
         A   => "A",
         B   => "B",
         AB  => "AB",
         BA  => "BA",
+        #[default]
         F   => "F",
         X   => "X",
         I   => "I",
     }
 }
 
-impl Default for Modifier {
-    fn default() -> Self {
-        Self::F
+impl Opcode {
+    /// The earliest standard which included this opcode.
+    pub fn since(self) -> Standard {
+        use Opcode::*;
+
+        match self {
+            Dat | Mov | Add | Sub | Jmp | Jmz | Jmn | Djn | Cmp | Spl => Standard::Icws86,
+            Slt => Standard::Icws88,
+            Mul | Div | Mod | Seq | Sne | Nop | Ldp | Stp => Standard::Icws94,
+        }
+    }
+
+    /// A short human-readable description of what the opcode does.
+    pub fn description(self) -> &'static str {
+        use Opcode::*;
+
+        match self {
+            Add => "add A to B, storing the result in B",
+            Cmp => "skip the next instruction if A equals B (same as SEQ)",
+            Dat => "remove the executing process from the process queue",
+            Div => "divide B by A, removing the process if A is zero",
+            Djn => "decrement B, then jump to A if B is not zero",
+            Jmn => "jump to A if B is not zero",
+            Jmp => "jump to A",
+            Jmz => "jump to A if B is zero",
+            Ldp => "load P-space cell A into B",
+            Mod => "divide B by A and store the remainder in B, removing the process if A is zero",
+            Mov => "copy A to B",
+            Mul => "multiply A by B, storing the result in B",
+            Nop => "do nothing",
+            Seq => "skip the next instruction if A equals B",
+            Slt => "skip the next instruction if A is less than B",
+            Sne => "skip the next instruction if A does not equal B",
+            Spl => "add a new process at A to the process queue",
+            Stp => "store A into P-space cell B",
+            Sub => "subtract A from B, storing the result in B",
+        }
+    }
+
+    /// How this opcode uses the fields of its operands when executed, or
+    /// `None` if it does not operate on fields at all. See
+    /// [`Modifier::field_pairs`](Modifier::field_pairs) for which fields are used.
+    pub fn field_usage(self) -> Option<FieldUsage> {
+        use Opcode::*;
+
+        let usage = |reads_a, reads_b, writes_b, whole_instruction| {
+            Some(FieldUsage {
+                reads_a,
+                reads_b,
+                writes_b,
+                whole_instruction,
+            })
+        };
+
+        match self {
+            Dat | Nop | Jmp | Spl => None,
+            Mov => usage(true, false, true, true),
+            Add | Mul | Sub | Div | Mod => usage(true, true, true, false),
+            Cmp | Seq | Sne => usage(true, true, false, true),
+            Slt => usage(true, true, false, false),
+            Djn => usage(false, true, true, false),
+            Jmn | Jmz => usage(false, true, false, false),
+            // TODO P-space is not implemented yet, so these are not described
+            Ldp | Stp => None,
+        }
+    }
+}
+
+/// How an opcode uses the fields of its operands which are selected by its modifier.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FieldUsage {
+    /// Whether the selected fields of the A operand are read
+    pub reads_a: bool,
+    /// Whether the selected fields of the B operand are read
+    pub reads_b: bool,
+    /// Whether the selected fields of the B operand are written
+    pub writes_b: bool,
+    /// Whether the `I` modifier applies to the whole instruction, rather than
+    /// behaving the same as `F`
+    pub whole_instruction: bool,
+}
+
+impl PseudoOpcode {
+    /// The earliest standard which included this pseudo-opcode.
+    pub fn since(self) -> Standard {
+        match self {
+            Self::End => Standard::Icws86,
+            Self::Equ => Standard::Icws88,
+            Self::Org | Self::For | Self::Pin => Standard::Icws94,
+        }
+    }
+
+    /// A short human-readable description of what the pseudo-opcode does.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Org => "set the address of the first instruction to execute",
+            Self::End => "end the program, optionally setting the starting address",
+            Self::Equ => "define a label as a constant or text replacement",
+            Self::For => "repeat a block of lines until the matching ROF",
+            Self::Pin => "share P-space with other warriors which have the same PIN",
+        }
     }
 }
 
 impl Modifier {
+    /// The earliest standard which included this modifier. Modifiers were
+    /// introduced in ICWS'94, and are inferred for earlier standards.
+    pub fn since(self) -> Standard {
+        Standard::Icws94
+    }
+
+    /// A short human-readable description of how the modifier selects the
+    /// fields used by an instruction.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::A => "A-field of A to A-field of B",
+            Self::B => "B-field of A to B-field of B",
+            Self::AB => "A-field of A to B-field of B",
+            Self::BA => "B-field of A to A-field of B",
+            Self::F => "both fields of A to the same fields of B",
+            Self::X => "both fields of A to the opposite fields of B",
+            Self::I => "whole instruction A to whole instruction B",
+        }
+    }
+
+    /// The pairs of fields used by an instruction with this modifier, in the
+    /// order they are operated on. See docs/icws94.txt:1113 for details.
+    pub fn field_pairs(self) -> &'static [FieldPair] {
+        use FieldName::{A, B};
+
+        match self {
+            Self::A => &[FieldPair { a: A, b: A }],
+            Self::B => &[FieldPair { a: B, b: B }],
+            Self::AB => &[FieldPair { a: A, b: B }],
+            Self::BA => &[FieldPair { a: B, b: A }],
+            Self::F | Self::I => &[FieldPair { a: A, b: A }, FieldPair { a: B, b: B }],
+            Self::X => &[FieldPair { a: B, b: A }, FieldPair { a: A, b: B }],
+        }
+    }
+
     pub fn default_88_to_94(opcode: Opcode, a_mode: AddressMode, b_mode: AddressMode) -> Self {
         /// Implemented based on the ICWS '94 document,
         /// section A.2.1.2: ICWS'88 to ICWS'94 Conversion
@@ -78,7 +241,7 @@ impl Modifier {
                 } else {
                     match opcode {
                         Mov | Cmp | Seq | Sne => Modifier::I,
-                        Slt => Modifier::B,
+                        Slt | Ldp | Stp => Modifier::B,
                         Add | Sub | Mul | Div | Mod => Modifier::F,
                         _ => unreachable!(),
                     }
@@ -88,10 +251,78 @@ impl Modifier {
     }
 }
 
+/// How the parser chooses the modifier of an instruction written without
+/// one, from its opcode and address modes. MARS dialects mostly agree, but
+/// differ in a few edge cases, e.g. `CMP` with an immediate B-operand.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum ModifierPolicy {
+    /// The ICWS'94 draft's table, see
+    /// [`Modifier::default_88_to_94`](Modifier::default_88_to_94)
+    #[default]
+    Icws94,
+
+    /// ICWS'88, which didn't allow an immediate B-operand for `CMP` and
+    /// `SLT`, so their modifier only depends on the A-operand: `.AB` if it
+    /// is immediate, and otherwise `.I` for `CMP` and `.B` for `SLT`. Other
+    /// opcodes are the same as [`Icws94`](Self::Icws94)
+    Icws88,
+
+    /// A function of the opcode and the A and B address modes
+    Custom(fn(Opcode, AddressMode, AddressMode) -> Modifier),
+}
+
+impl ModifierPolicy {
+    /// The modifier of an instruction with `opcode` and these address modes.
+    pub fn modifier(self, opcode: Opcode, a_mode: AddressMode, b_mode: AddressMode) -> Modifier {
+        match self {
+            Self::Icws94 => Modifier::default_88_to_94(opcode, a_mode, b_mode),
+            Self::Icws88 => match opcode {
+                Opcode::Cmp | Opcode::Slt if a_mode == AddressMode::Immediate => Modifier::AB,
+                Opcode::Cmp => Modifier::I,
+                Opcode::Slt => Modifier::B,
+                _ => Modifier::default_88_to_94(opcode, a_mode, b_mode),
+            },
+            Self::Custom(modifier) => modifier(opcode, a_mode, b_mode),
+        }
+    }
+}
+
+impl FromStr for ModifierPolicy {
+    type Err = String;
+
+    /// Parse the standard whose table to use, "94" or "88".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "94" => Ok(Self::Icws94),
+            "88" => Ok(Self::Icws88),
+            _ => Err(format!(
+                "unknown modifier policy {:?}, expected \"94\" or \"88\"",
+                s
+            )),
+        }
+    }
+}
+
+/// One of the two fields of an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FieldName {
+    A,
+    B,
+}
+
+/// A field of an instruction's A operand which is used with a field of its
+/// B operand. Results of an operation are always stored in the B operand's field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FieldPair {
+    pub a: FieldName,
+    pub b: FieldName,
+}
+
 enum_string! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum AddressMode {
         Immediate           => "#",
+        #[default]
         Direct              => "$",
         IndirectA           => "*",
         IndirectB           => "@",
@@ -102,13 +333,36 @@ enum_string! {
     }
 }
 
-impl Default for AddressMode {
-    fn default() -> Self {
-        Self::Direct
+impl AddressMode {
+    /// The earliest standard which included this address mode.
+    pub fn since(self) -> Standard {
+        use AddressMode::*;
+
+        match self {
+            Immediate | Direct | IndirectB | PreDecIndirectB => Standard::Icws86,
+            IndirectA | PreDecIndirectA | PostIncIndirectA | PostIncIndirectB => Standard::Icws94,
+        }
+    }
+
+    /// A short human-readable description of how the address mode resolves
+    /// an operand.
+    pub fn description(self) -> &'static str {
+        use AddressMode::*;
+
+        match self {
+            Immediate => "the operand is the value itself",
+            Direct => "the operand is the instruction at the offset",
+            IndirectA => "use the A-field of the instruction at the offset as a pointer",
+            IndirectB => "use the B-field of the instruction at the offset as a pointer",
+            PreDecIndirectA => "decrement the A-field pointer, then use it",
+            PreDecIndirectB => "decrement the B-field pointer, then use it",
+            PostIncIndirectA => "use the A-field pointer, then increment it",
+            PostIncIndirectB => "use the B-field pointer, then increment it",
+        }
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Value {
     Label(String),
     Literal(i32),
@@ -197,6 +451,19 @@ mod test {
             }
         }
 
+        for (&opcode, &a_mode, &b_mode) in iproduct!(
+            [Ldp, Stp].iter(),
+            AddressMode::iter_values(),
+            AddressMode::iter_values()
+        ) {
+            if a_mode != AddressMode::Immediate {
+                assert_eq!(
+                    Modifier::default_88_to_94(opcode, a_mode, b_mode),
+                    Modifier::B
+                )
+            }
+        }
+
         let opcodes = [Jmp, Jmz, Jmn, Djn, Spl, Nop];
 
         for (&opcode, &a_mode, &b_mode) in iproduct!(
@@ -211,9 +478,34 @@ mod test {
         }
     }
 
+    #[test]
+    fn modifier_policies() {
+        use AddressMode::{Direct, Immediate};
+
+        assert_eq!(
+            ModifierPolicy::Icws94.modifier(Cmp, Direct, Immediate),
+            Modifier::B
+        );
+        assert_eq!(
+            ModifierPolicy::Icws88.modifier(Cmp, Direct, Immediate),
+            Modifier::I
+        );
+        assert_eq!(
+            ModifierPolicy::Icws88.modifier(Slt, Immediate, Immediate),
+            Modifier::AB
+        );
+        assert_eq!(
+            ModifierPolicy::Icws88.modifier(Mov, Direct, Immediate),
+            Modifier::B
+        );
+
+        let always_f = ModifierPolicy::Custom(|_, _, _| Modifier::F);
+        assert_eq!(always_f.modifier(Jmp, Direct, Direct), Modifier::F);
+    }
+
     #[test]
     fn modifier_ab_default() {
-        let opcodes = [Mov, Cmp, Seq, Sne, Add, Sub, Mul, Div, Mod, Slt];
+        let opcodes = [Mov, Cmp, Seq, Sne, Add, Sub, Mul, Div, Mod, Slt, Ldp, Stp];
 
         for (&opcode, &b_mode) in iproduct!(opcodes.iter(), AddressMode::iter_values()) {
             assert_eq!(
@@ -259,6 +551,41 @@ mod test {
         }
     }
 
+    #[test]
+    fn since() {
+        assert_eq!(Opcode::Mov.since(), Standard::Icws86);
+        assert_eq!(Opcode::Slt.since(), Standard::Icws88);
+        assert_eq!(Opcode::Seq.since(), Standard::Icws94);
+        assert_eq!(AddressMode::IndirectB.since(), Standard::Icws86);
+        assert_eq!(AddressMode::PostIncIndirectB.since(), Standard::Icws94);
+        assert_eq!(PseudoOpcode::For.since(), Standard::Icws94);
+
+        for modifier in Modifier::all() {
+            assert_eq!(modifier.since(), Standard::Icws94);
+        }
+    }
+
+    #[test]
+    fn supported_standards() {
+        assert_eq!(Standard::supported(), &[Standard::Icws88, Standard::Icws94]);
+        assert!(Standard::Icws94.is_supported());
+        assert!(!Standard::Icws86.is_supported());
+        assert_eq!("94".parse(), Ok(Standard::Icws94));
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(Opcode::all().len(), 19);
+        assert_eq!(AddressMode::all().len(), 8);
+
+        for opcode in Opcode::all() {
+            assert!(!opcode.description().is_empty());
+        }
+        for mode in AddressMode::all() {
+            assert!(!mode.description().is_empty());
+        }
+    }
+
     #[test]
     fn value_to_string() {
         assert_eq!(
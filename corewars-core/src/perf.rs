@@ -0,0 +1,108 @@
+//! Timing and memory statistics for each phase of parsing and simulation, to
+//! help find slow inputs and performance regressions.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The cost of a single phase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhaseStats {
+    pub name: &'static str,
+    pub duration: Duration,
+
+    /// The approximate memory used by the phase's output, in bytes
+    pub bytes: usize,
+}
+
+/// Statistics for a sequence of phases, in the order they ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PerfStats {
+    pub phases: Vec<PhaseStats>,
+}
+
+impl PerfStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `phase`, recording how long it took. `bytes` estimates the memory
+    /// used by its output.
+    pub fn time<T, F, B>(&mut self, name: &'static str, phase: F, bytes: B) -> T
+    where
+        F: FnOnce() -> T,
+        B: FnOnce(&T) -> usize,
+    {
+        let start = Instant::now();
+        let output = phase();
+        let duration = start.elapsed();
+
+        self.phases.push(PhaseStats {
+            name,
+            duration,
+            bytes: bytes(&output),
+        });
+        output
+    }
+
+    /// Add the phases recorded in `other` after those already recorded.
+    pub fn extend(&mut self, other: Self) {
+        self.phases.extend(other.phases);
+    }
+
+    /// The total time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.duration).sum()
+    }
+}
+
+impl fmt::Display for PerfStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{:<16}{:>12}{:>12}",
+            "phase", "time (us)", "bytes"
+        )?;
+
+        for phase in &self.phases {
+            write!(
+                formatter,
+                "\n{:<16}{:>12}{:>12}",
+                phase.name,
+                phase.duration.as_micros(),
+                phase.bytes
+            )?;
+        }
+
+        write!(
+            formatter,
+            "\n{:<16}{:>12}",
+            "total",
+            self.total().as_micros()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn records_phases() {
+        let mut stats = PerfStats::new();
+
+        let text = stats.time("read", || "mov 0, 1".to_string(), String::len);
+        let lines = stats.time("split", || vec![text], Vec::len);
+        assert_eq!(lines, vec!["mov 0, 1".to_string()]);
+
+        let names: Vec<_> = stats.phases.iter().map(|phase| phase.name).collect();
+        assert_eq!(names, vec!["read", "split"]);
+        assert_eq!(stats.phases[0].bytes, 8);
+        assert_eq!(stats.phases[1].bytes, 1);
+
+        let report = stats.to_string();
+        assert!(report.starts_with("phase              time (us)       bytes\nread "));
+        assert!(report.contains("\ntotal "));
+    }
+}
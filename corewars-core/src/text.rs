@@ -0,0 +1,164 @@
+//! Measuring text as it appears in a terminal, so that columns line up and
+//! markers point at the right character even when the text contains tabs,
+//! multi-byte characters or wide characters. Also the line endings to write
+//! text with, since tools which output is compared against differ.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The distance between tab stops, in columns.
+pub const TAB_WIDTH: usize = 8;
+
+/// The number of columns `text` takes up when displayed from the start of a
+/// line.
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).fold(0, advance)
+}
+
+/// The number of user-perceived characters in `text`, e.g. for reporting a
+/// column number.
+pub fn characters(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Replace each tab in `text` with spaces up to the next tab stop, so that it
+/// displays the same wherever it is printed.
+pub fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+
+    for grapheme in text.graphemes(true) {
+        let next = advance(column, grapheme);
+        if grapheme == "\t" {
+            expanded.extend(std::iter::repeat_n(' ', next - column));
+        } else {
+            expanded.push_str(grapheme);
+        }
+        column = next;
+    }
+
+    expanded
+}
+
+/// Pad `text` with spaces on the right to at least `columns` wide.
+pub fn pad(text: &str, columns: usize) -> String {
+    let padding = columns.saturating_sub(width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+enum_string! {
+    /// What ends each line of written text.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub enum LineEnding {
+        /// `\n`, as on Unix
+        #[default]
+        Lf => "lf",
+        /// `\r\n`, as on Windows
+        CrLf => "crlf",
+    }
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+/// How lines of written text, e.g. a load file, are separated and ended.
+///
+/// ```
+/// use corewars_core::text::{LineEnding, TextLayout};
+///
+/// let layout = TextLayout {
+///     line_ending: LineEnding::CrLf,
+///     trailing_newline: false,
+/// };
+/// assert_eq!(layout.apply("mov 0, 1\njmp -1\n"), "mov 0, 1\r\njmp -1");
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextLayout {
+    pub line_ending: LineEnding,
+
+    /// Whether the last line is ended too
+    pub trailing_newline: bool,
+}
+
+/// Unix line endings, with the last line ended.
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+        }
+    }
+}
+
+impl TextLayout {
+    /// `text` with each line ended by this layout's line ending, whichever
+    /// one it was written with. Blank lines at the end are removed.
+    pub fn apply(&self, text: &str) -> String {
+        let mut lines = text.lines().collect::<Vec<_>>();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+
+        let mut applied = lines.join(self.line_ending.as_str());
+        if self.trailing_newline && !lines.is_empty() {
+            applied.push_str(self.line_ending.as_str());
+        }
+        applied
+    }
+}
+
+/// The column after displaying `grapheme` at `column`.
+fn advance(column: usize, grapheme: &str) -> usize {
+    if grapheme == "\t" {
+        (column / TAB_WIDTH + 1) * TAB_WIDTH
+    } else {
+        column + grapheme.width()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn measures_width() {
+        assert_eq!(width("mov 0, 1"), 8);
+        assert_eq!(width("\tmov"), 11);
+        assert_eq!(width("ab\tc"), 9);
+        // A combining accent, and a wide character
+        assert_eq!(width("Jose\u{301}"), 4);
+        assert_eq!(width("戦争"), 4);
+        assert_eq!(characters("Jose\u{301}"), 4);
+    }
+
+    #[test]
+    fn expands_tabs() {
+        assert_eq!(expand_tabs("a\tb"), "a       b");
+        assert_eq!(expand_tabs("é\tb"), "é       b");
+        assert_eq!(pad("é", 3), "é  ");
+    }
+
+    #[test]
+    fn applies_layout() {
+        let text = "a\r\nb\n\n";
+        assert_eq!(TextLayout::default().apply(text), "a\nb\n");
+        assert_eq!(
+            TextLayout {
+                line_ending: LineEnding::CrLf,
+                trailing_newline: true,
+            }
+            .apply(text),
+            "a\r\nb\r\n"
+        );
+        assert_eq!(TextLayout::default().apply(""), "");
+        assert_eq!("crlf".parse(), Ok(LineEnding::CrLf));
+    }
+}
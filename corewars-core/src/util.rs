@@ -23,7 +23,9 @@
 ///
 /// This will generate a `pub enum Foo` with variants `Bar` and `Baz`, which
 /// implements `std::str::FromStr` and `std::fmt::Display` for the string
-/// values specified.
+/// values specified. All variants can be listed with `Foo::all()`, or
+/// iterated with `Foo::iter_values()`. Attributes like `#[default]` may also be applied to
+/// individual variants.
 ///
 // This really should have #[cfg_attr(doctest, macro_export)]
 // But cfg(doctest) does not work as expected: https://github.com/rust-lang/rust/issues/67295
@@ -32,12 +34,12 @@ macro_rules! enum_string {
     (
         $(#[$attr:meta])*
         $vis:vis enum $name:ident {
-            $($variant:ident => $value:expr),* $(,)?
+            $($(#[$variant_attr:meta])* $variant:ident => $value:expr),* $(,)?
         }
     ) => {
         $(#[$attr])*
         $vis enum $name {
-            $($variant,)*
+            $($(#[$variant_attr])* $variant,)*
         }
 
         impl ::std::fmt::Display for $name {
@@ -63,10 +65,15 @@ macro_rules! enum_string {
         }
 
         impl $name {
+            /// All variants of this enum, in declaration order.
+            #[allow(dead_code)]
+            pub fn all() -> &'static [Self] {
+                &[$($name::$variant,)*]
+            }
+
             #[allow(dead_code)]
             pub fn iter_values() -> ::std::slice::Iter<'static, Self> {
-                const VALUES: &[$name] = &[$($name::$variant,)*];
-                VALUES.iter()
+                Self::all().iter()
             }
         }
     };
@@ -89,6 +96,15 @@ mod test {
                 NoTrailing => "still works"
             }
         }
+
+        enum_string! {
+            #[derive(Debug, Default, PartialEq, Eq)]
+            pub enum WithDefault {
+                First => "first",
+                #[default]
+                Second => "second",
+            }
+        }
     }
 
     enum_string! {
@@ -106,6 +122,11 @@ mod test {
         let _ = submod::Comma::NoTrailing;
     }
 
+    #[test]
+    fn variant_attributes() {
+        assert_eq!(submod::WithDefault::default(), submod::WithDefault::Second);
+    }
+
     #[test]
     fn to_string() {
         assert_eq!(Foo::Bar.to_string(), "Bar");
@@ -125,6 +146,11 @@ mod test {
         );
     }
 
+    #[test]
+    fn all() {
+        assert_eq!(Foo::all(), &[Foo::Bar, Foo::Baz, Foo::SomethingElse]);
+    }
+
     #[test]
     fn iter_values() {
         let values_from_iter: Vec<Foo> = Foo::iter_values().cloned().collect();
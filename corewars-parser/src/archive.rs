@@ -0,0 +1,70 @@
+//! Archives of several warriors in a single source file, e.g. to publish the
+//! warriors of a hill. Each warrior is written as its metadata comments and
+//! code, ending with `END`. The parser ignores everything after an `END`, so
+//! an archive is split at each one to parse its warriors in turn.
+
+use corewars_core::load_file::Warrior;
+
+use crate::phase;
+
+/// Write `warriors` as a single archive, each followed by an `END` line and
+/// separated by a blank line.
+pub fn archive(warriors: &[Warrior]) -> String {
+    let sources: Vec<String> = warriors
+        .iter()
+        .map(|warrior| format!("{}\nEND\n", warrior))
+        .collect();
+    sources.join("\n")
+}
+
+/// Split an archive into the source of each warrior, which ends with its
+/// `END` line, except perhaps the last. Any whitespace after the last `END`
+/// belongs to the last warrior, so joining the sources gives back the input
+/// exactly.
+pub fn split_archive(input: &str) -> Vec<&str> {
+    let mut sources = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+
+    for line in input.split_inclusive('\n') {
+        end += line.len();
+        if phase::ends_program(line) {
+            sources.push(&input[start..end]);
+            start = end;
+        }
+    }
+
+    let rest = &input[start..];
+    match sources.last_mut() {
+        Some(last) if rest.trim().is_empty() => *last = &input[start - last.len()..],
+        _ if !rest.is_empty() => sources.push(rest),
+        _ => (),
+    }
+    sources
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn splits_at_end() {
+        let input = ";name Imp\nmov 0, 1\nend\n\n;name Dwarf\nadd #4, 3 ; not the end\nEND start ; with an origin\n\n";
+        assert_eq!(
+            split_archive(input),
+            vec![
+                ";name Imp\nmov 0, 1\nend\n",
+                "\n;name Dwarf\nadd #4, 3 ; not the end\nEND start ; with an origin\n\n",
+            ]
+        );
+
+        assert_eq!(
+            split_archive("mov 0, 1\nEND\njmp 0"),
+            vec!["mov 0, 1\nEND\n", "jmp 0"]
+        );
+        assert_eq!(split_archive("; just a comment"), vec!["; just a comment"]);
+        assert!(split_archive("").is_empty());
+    }
+}
@@ -0,0 +1,408 @@
+//! Stable codes for every error and warning of the parser, e.g. `E0001` for a
+//! missing label, with a longer description of each to look up by code. The
+//! warnings of the lints in [`corewars_core::analysis`] are numbered along
+//! with the parser's, e.g. [`StepWarning::CODE`].
+//!
+//! Codes are never reused or renumbered, so they can be searched for and
+//! referred to in bug reports even once the message of a diagnostic changes.
+//! Errors are numbered `E0001` and up, and warnings `W0001` and up, in the
+//! order they were added.
+
+use std::fmt;
+
+#[cfg(doc)]
+use corewars_core::analysis::StepWarning;
+
+use super::diagnostics::Severity;
+use super::error::{Error, Warning};
+
+/// The documentation of a diagnostic code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodeInfo {
+    pub code: &'static str,
+    pub severity: Severity,
+
+    /// A short description, like the message of the diagnostic
+    pub summary: &'static str,
+
+    /// What causes the diagnostic, and how to fix it
+    pub description: &'static str,
+
+    /// Redcode which causes the diagnostic, if it can be caused by a single
+    /// file parsed with the default options
+    pub example: Option<&'static str>,
+}
+
+/// Each code on a line, then its description, and the example if any.
+impl fmt::Display for CodeInfo {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} ({}): {}\n\n{}",
+            self.code, self.severity, self.summary, self.description
+        )?;
+
+        if let Some(example) = self.example {
+            write!(formatter, "\n\nFor example:\n")?;
+            for line in example.lines() {
+                write!(formatter, "\n    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every diagnostic code, in order.
+pub const CODES: &[CodeInfo] = &[
+    CodeInfo {
+        code: "E0001",
+        severity: Severity::Error,
+        summary: "no such label",
+        description: "An operand refers to a label which isn't declared anywhere in the \
+            warrior. Check the spelling of the label, including its case, and that the line \
+            declaring it wasn't commented out.",
+        example: Some("jmp start"),
+    },
+    CodeInfo {
+        code: "E0002",
+        severity: Severity::Error,
+        summary: "invalid origin specified",
+        description: "The origin given with ORG or END evaluates to a negative number, so it \
+            can't be the index of an instruction. Use an offset from the start of the \
+            warrior, or a label.",
+        example: Some("org -1\nmov 0, 1"),
+    },
+    CodeInfo {
+        code: "E0003",
+        severity: Severity::Error,
+        summary: "invalid syntax",
+        description: "A line isn't valid Redcode. The message lists what the parser expected \
+            to find where the marker points, e.g. an operand after a comma.",
+        example: Some("mov 0,"),
+    },
+    CodeInfo {
+        code: "E0004",
+        severity: Severity::Error,
+        summary: "expected additional arguments",
+        description: "An instruction was given fewer operands than its opcode needs. Only \
+            DAT, JMP, SPL and NOP may be written with a single operand.",
+        example: Some("mov 0"),
+    },
+    CodeInfo {
+        code: "E0005",
+        severity: Severity::Error,
+        summary: "recursive substitution",
+        description: "An EQU definition refers back to itself, directly or through other \
+            definitions, so it can never be fully expanded. The message lists every label \
+            involved, with the line it was defined on.",
+        example: Some("a equ b\nb equ a\ndat a, 0"),
+    },
+    CodeInfo {
+        code: "E0006",
+        severity: Severity::Error,
+        summary: "FOR loop is nested too deep",
+        description: "A FOR loop is nested inside more loops than the expansion limits allow. \
+            Flatten the loops, or raise the limit if the input is trusted.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0007",
+        severity: Severity::Error,
+        summary: "FOR loop repeats too many times",
+        description: "A FOR loop would repeat its body more times than the expansion limits \
+            allow, counting the repetitions of any loops around it. By default, loops may \
+            repeat at most as many times as there are instructions in the core, since \
+            anything longer wouldn't fit in it.",
+        example: Some("for 10000\ndat 0, 0\nrof"),
+    },
+    CodeInfo {
+        code: "E0008",
+        severity: Severity::Error,
+        summary: "parsing was cancelled",
+        description: "The program embedding the parser stopped it before it finished, e.g. \
+            because it was taking too long. Parsing the same input again without cancelling \
+            it may succeed.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0009",
+        severity: Severity::Error,
+        summary: "division by zero",
+        description: "An expression divides by zero, or takes the remainder of dividing by \
+            zero, which has no value. This often comes from an EQU constant which is 0.",
+        example: Some("dat 1 / 0, 0"),
+    },
+    CodeInfo {
+        code: "E0010",
+        severity: Severity::Error,
+        summary: "number is too large",
+        description: "A number in an expression doesn't fit in 32 bits. Values are wrapped \
+            into the core anyway, so use the equivalent value within the core size.",
+        example: Some("dat 99999999999, 0"),
+    },
+    CodeInfo {
+        code: "E0011",
+        severity: Severity::Error,
+        summary: "unresolved import",
+        description: "A module being linked lists a label in an IMPORT line which no other \
+            module lists in its EXPORT line.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0012",
+        severity: Severity::Error,
+        summary: "undeclared export",
+        description: "A module being linked lists a label in an EXPORT line, but never \
+            declares it.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0013",
+        severity: Severity::Error,
+        summary: "duplicate export",
+        description: "More than one module being linked exports the same label, so it's \
+            ambiguous which one an import refers to. Rename one of them.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0014",
+        severity: Severity::Error,
+        summary: "label used without importing it",
+        description: "A module being linked uses a label exported by another module, without \
+            listing it in an IMPORT line. Add it to one, so dependencies between modules are \
+            explicit.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0015",
+        severity: Severity::Error,
+        summary: "cannot include file",
+        description: "A file named by an INCLUDE line couldn't be read. Paths are relative to \
+            the directory of the file including them.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0016",
+        severity: Severity::Error,
+        summary: "include cycle",
+        description: "A file includes itself, directly or through other included files. The \
+            message lists each file involved, ending with the repeated one.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0017",
+        severity: Severity::Error,
+        summary: "EQU continuation without a labeled EQU",
+        description: "An EQU without a label continues a multi-line EQU, so it must directly \
+            follow a labeled EQU or another continuation.",
+        example: Some("equ 1\ndat 0, 0"),
+    },
+    CodeInfo {
+        code: "E0018",
+        severity: Severity::Error,
+        summary: "malformed instruction",
+        description: "An instruction was missing a part the grammar should have required. \
+            This is a bug in the parser; please report it with the input which caused it.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0019",
+        severity: Severity::Error,
+        summary: "unresolved variables",
+        description: "The input uses ${NAME} variables which aren't set. Set them, e.g. in \
+            the environment, or remove them from the input.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0020",
+        severity: Severity::Error,
+        summary: "error in directive",
+        description: "The handler of a custom directive rejected its operands. The message \
+            comes from the handler, which is provided by the program embedding the parser.",
+        example: None,
+    },
+    CodeInfo {
+        code: "E0021",
+        severity: Severity::Error,
+        summary: "unterminated variable",
+        description: "A ${NAME} variable is missing its closing }, so it can't be \
+            substituted. Add the } right after the name.",
+        example: None,
+    },
+    CodeInfo {
+        code: "W0001",
+        severity: Severity::Warning,
+        summary: "origin redefined",
+        description: "The origin was set more than once, with ORG or END. Only the first is \
+            used, so remove the others.",
+        example: Some("org 0\norg 1\nmov 0, 1"),
+    },
+    CodeInfo {
+        code: "W0002",
+        severity: Severity::Warning,
+        summary: "ORG without an argument",
+        description: "ORG was written without the origin to use, so it is ignored. Give it \
+            the label or offset of the first instruction to execute.",
+        example: None,
+    },
+    CodeInfo {
+        code: "W0003",
+        severity: Severity::Warning,
+        summary: "PIN redefined",
+        description: "The P-space identifier was set more than once with PIN. Only the first \
+            is used, so remove the others.",
+        example: Some("pin 1\npin 2\nmov 0, 1"),
+    },
+    CodeInfo {
+        code: "W0004",
+        severity: Severity::Warning,
+        summary: "PIN without an argument",
+        description: "PIN was written without an identifier, so it is ignored. Warriors with \
+            the same identifier share their P-space.",
+        example: None,
+    },
+    CodeInfo {
+        code: "W0005",
+        severity: Severity::Warning,
+        summary: "empty substitution",
+        description: "An EQU has nothing after it, so its label is replaced with nothing \
+            wherever it's used, which is rarely intended.",
+        example: Some("empty equ\nmov 0, 1"),
+    },
+    CodeInfo {
+        code: "W0006",
+        severity: Severity::Warning,
+        summary: "label without an instruction",
+        description: "A label is declared after the last instruction, so there is no \
+            instruction for it to refer to, and it isn't defined.",
+        example: Some("mov 0, 1\nlast"),
+    },
+    CodeInfo {
+        code: "W0007",
+        severity: Severity::Warning,
+        summary: "step only reaches part of the core",
+        description: "Found by `lint`: an ADD or SUB adds a constant step which shares a \
+            large factor with the core size, so its pointer only ever reaches that fraction \
+            of the core, and an opponent shorter than the factor can sit entirely between \
+            the bombs. Pick a step which is coprime to the core size, or shares only a \
+            small factor with it.",
+        example: Some("add #4000, 1\njmp -1"),
+    },
+];
+
+/// The documentation of `code`, ignoring case, e.g. `e0001`.
+///
+/// ```
+/// let info = corewars_parser::explain_code("e0009").unwrap();
+/// assert_eq!(info.summary, "division by zero");
+/// assert!(corewars_parser::explain_code("E9999").is_none());
+/// ```
+pub fn explain_code(code: &str) -> Option<&'static CodeInfo> {
+    CODES
+        .iter()
+        .find(|info| info.code.eq_ignore_ascii_case(code.trim()))
+}
+
+impl Error {
+    /// The stable code of this error, documented by [`explain_code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::LabelNotFound { .. } => "E0001",
+            Self::InvalidOrigin(_) => "E0002",
+            Self::InvalidSyntax { .. } => "E0003",
+            Self::InvalidArguments { .. } => "E0004",
+            Self::RecursiveSubstitution { .. } => "E0005",
+            Self::ForNestingTooDeep { .. } => "E0006",
+            Self::ForExpansionTooLarge { .. } => "E0007",
+            Self::Cancelled => "E0008",
+            Self::DivideByZero { .. } => "E0009",
+            Self::NumberTooLarge { .. } => "E0010",
+            Self::UnresolvedImport { .. } => "E0011",
+            Self::UndeclaredExport { .. } => "E0012",
+            Self::DuplicateExport { .. } => "E0013",
+            Self::NotImported { .. } => "E0014",
+            Self::IncludeFailed { .. } => "E0015",
+            Self::IncludeCycle { .. } => "E0016",
+            Self::EquWithoutLabel { .. } => "E0017",
+            Self::MalformedInstruction { .. } => "E0018",
+            Self::UnresolvedVariables { .. } => "E0019",
+            Self::DirectiveFailed { .. } => "E0020",
+            Self::UnterminatedVariable { .. } => "E0021",
+        }
+    }
+}
+
+impl Warning {
+    /// The stable code of this warning, documented by [`explain_code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::OriginRedefinition { .. } => "W0001",
+            Self::MissingOrigin { .. } => "W0002",
+            Self::PinRedefinition { .. } => "W0003",
+            Self::MissingPin { .. } => "W0004",
+            Self::EmptySubstitution { .. } => "W0005",
+            Self::EmptyOffset { .. } => "W0006",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use pretty_assertions::assert_eq;
+
+    use corewars_core::analysis::{self, StepWarning};
+
+    use super::*;
+    use crate::Result;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes: HashSet<&str> = CODES.iter().map(|info| info.code).collect();
+        assert_eq!(codes.len(), CODES.len());
+
+        for info in CODES {
+            let prefix = match info.severity {
+                Severity::Error => 'E',
+                Severity::Warning => 'W',
+            };
+            assert!(info.code.starts_with(prefix), "{}", info.code);
+        }
+    }
+
+    #[test]
+    fn examples_cause_their_code() {
+        for info in CODES {
+            let example = match info.example {
+                Some(example) => example,
+                None => continue,
+            };
+
+            let codes: Vec<&str> = match crate::parse(example) {
+                Result::Ok(warrior, warnings) => warnings
+                    .iter()
+                    .map(Warning::code)
+                    .chain(
+                        analysis::step_warnings(&warrior.program, 8000, 4)
+                            .iter()
+                            .map(|_| StepWarning::CODE),
+                    )
+                    .collect(),
+                Result::Err(error, _) => vec![error.code()],
+            };
+            assert_eq!(codes, vec![info.code], "example of {}", info.code);
+        }
+    }
+
+    #[test]
+    fn displays_explanation() {
+        let info = explain_code("W0001").unwrap();
+        assert!(info
+            .to_string()
+            .starts_with("W0001 (warning): origin redefined\n\nThe origin was set"));
+        assert!(info
+            .to_string()
+            .ends_with("For example:\n\n    org 0\n    org 1\n    mov 0, 1"));
+    }
+}
@@ -0,0 +1,113 @@
+//! A uniform view of the errors and warnings produced by the parser, for
+//! tools such as editors which display them alongside the input.
+
+use std::fmt;
+
+use super::error::{Error, Span, Warning};
+use super::result::Result;
+
+/// How serious a [`Diagnostic`](Diagnostic) is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Parsing succeeded, but the input is probably not what was intended
+    Warning,
+
+    /// Parsing failed
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// An error or warning, with its location in the input to the parser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    /// The stable code of the error or warning, e.g. `E0001`, which can be
+    /// looked up with [`explain_code`](crate::explain_code)
+    pub code: &'static str,
+
+    pub message: String,
+
+    /// Where in the input the problem is, if known. Lines and byte offsets
+    /// refer to the original input, before any preprocessing.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}[{}]: {}",
+            self.severity, self.code, self.message
+        )?;
+
+        if let Some(span) = self.span {
+            write!(
+                formatter,
+                " (line {}, column {})",
+                span.line,
+                span.start + 1
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(error: &Error) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: error.code(),
+            message: error.to_string(),
+            span: error.span().copied(),
+        }
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: warning.code(),
+            message: warning.to_string(),
+            span: warning.span().copied(),
+        }
+    }
+}
+
+impl<T> Result<T> {
+    /// Every warning in this result followed by the error, if any, as
+    /// diagnostics.
+    ///
+    /// ```
+    /// use corewars_parser::{Diagnostic, Severity};
+    ///
+    /// let result = corewars_parser::parse("org 0\norg 1\nmov 0, what");
+    /// let diagnostics: Vec<Diagnostic> = result.diagnostics();
+    ///
+    /// assert_eq!(diagnostics[0].severity, Severity::Warning);
+    /// assert_eq!(diagnostics[0].span.unwrap().line, 2);
+    /// assert_eq!(diagnostics[1].to_string(), "error[E0001]: no such label \"what\" (line 3, column 8)");
+    /// ```
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let (warnings, error) = match self {
+            Self::Ok(_, warnings) => (warnings, None),
+            Self::Err(error, warnings) => (warnings, Some(error)),
+        };
+
+        warnings
+            .iter()
+            .map(Diagnostic::from)
+            .chain(error.map(Diagnostic::from))
+            .collect()
+    }
+}
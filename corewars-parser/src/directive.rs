@@ -0,0 +1,208 @@
+//! Custom pseudo-opcodes ("directives") which extend the Redcode language.
+//! Each directive is expanded into regular Redcode by a user-provided handler,
+//! before any other preprocessing happens.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use corewars_core::load_file::{Opcode, PseudoOpcode};
+
+use super::error::Error;
+use super::phase::CommentsRemoved;
+
+/// A handler for a custom pseudo-opcode. It is called with the text of the
+/// operands following the pseudo-opcode, and returns the lines of Redcode
+/// it should be replaced with, or an error message.
+pub type Handler = dyn Fn(&str) -> Result<Vec<String>, String>;
+
+/// A set of custom pseudo-opcodes and their handlers.
+#[derive(Default)]
+pub struct Directives {
+    /// Handlers, keyed by the uppercase name of their pseudo-opcode
+    handlers: HashMap<String, Box<Handler>>,
+}
+
+impl Directives {
+    /// Add a handler for the pseudo-opcode `name`, which is case-insensitive.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is a standard opcode or pseudo-opcode.
+    pub fn insert<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(&str) -> Result<Vec<String>, String> + 'static,
+    {
+        let name = name.to_uppercase();
+
+        if Opcode::from_str(&name).is_ok() || PseudoOpcode::from_str(&name).is_ok() {
+            panic!("cannot redefine standard opcode {}", name);
+        }
+
+        self.handlers.insert(name, Box::new(handler));
+    }
+
+    /// Replace any custom pseudo-opcodes in `state` with the output of their handlers.
+    pub(crate) fn expand(&self, state: &mut CommentsRemoved, buffer: &str) -> Result<(), Error> {
+        if self.handlers.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = Vec::with_capacity(state.lines.len());
+        let mut source_lines = Vec::with_capacity(state.source_lines.len());
+
+        for (line, &source_line) in state.lines.iter().zip(state.source_lines.iter()) {
+            match self.expand_line(line) {
+                Some((name, Err(message))) => {
+                    return Err(Error::DirectiveFailed {
+                        name,
+                        message,
+                        span: None,
+                    }
+                    .locate(source_line, line, buffer))
+                }
+                Some((_, Ok(expanded))) => {
+                    source_lines.extend(expanded.iter().map(|_| source_line));
+                    lines.extend(expanded.into_iter().map(Cow::Owned));
+                }
+                None => {
+                    lines.push(line.clone());
+                    source_lines.push(source_line);
+                }
+            }
+        }
+
+        state.lines = lines;
+        state.source_lines = source_lines;
+        Ok(())
+    }
+
+    /// Expand a single line, if it uses a custom pseudo-opcode. The pseudo-opcode
+    /// may be preceded by a label, which is applied to the first expanded line.
+    fn expand_line(&self, line: &str) -> Option<(String, Result<Vec<String>, String>)> {
+        let mut words = line.split_whitespace();
+        let first = words.next()?;
+
+        let (label, name) = match self.handler(first) {
+            Some(_) => (None, first),
+            None => (Some(first), words.next()?),
+        };
+
+        let (name, handler) = self
+            .handler(name)
+            .map(|handler| (name.to_uppercase(), handler))?;
+
+        // Everything after the pseudo-opcode is passed to the handler
+        let mut rest = line.trim_start();
+        if let Some(label) = label {
+            rest = rest[label.len()..].trim_start();
+        }
+        let operands = rest[name.len()..].trim();
+
+        let result = handler(operands).map(|mut expanded| {
+            if let Some(label) = label {
+                match expanded.first_mut() {
+                    Some(first) => *first = format!("{} {}", label, first),
+                    None => expanded.push(label.to_string()),
+                }
+            }
+            expanded
+        });
+
+        Some((name, result))
+    }
+
+    fn handler(&self, name: &str) -> Option<&Handler> {
+        self.handlers
+            .get(&name.to_uppercase())
+            .map(|handler| handler.as_ref())
+    }
+}
+
+impl fmt::Debug for Directives {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.handlers.keys().collect();
+        names.sort();
+        formatter.debug_set().entries(names).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn data_directive(operands: &str) -> Result<Vec<String>, String> {
+        if operands.is_empty() {
+            return Err("expected at least one value".into());
+        }
+
+        Ok(operands
+            .split(',')
+            .map(|value| format!("dat #0, #{}", value.trim()))
+            .collect())
+    }
+
+    fn expand<'a>(lines: &[&'a str]) -> Result<CommentsRemoved<'a>, Error> {
+        let mut directives = Directives::default();
+        directives.insert("data", data_directive);
+
+        let mut state = CommentsRemoved {
+            lines: lines.iter().map(|&s| s.into()).collect(),
+            source_lines: (1..=lines.len()).collect(),
+            ..CommentsRemoved::default()
+        };
+
+        directives.expand(&mut state, &lines.join("\n"))?;
+        Ok(state)
+    }
+
+    #[test]
+    fn expands_directive() {
+        let state = expand(&["mov 0, 1", "DATA 1, 2, 3", "jmp -1"]).unwrap();
+
+        assert_eq!(
+            state.lines,
+            vec![
+                "mov 0, 1",
+                "dat #0, #1",
+                "dat #0, #2",
+                "dat #0, #3",
+                "jmp -1"
+            ]
+        );
+        assert_eq!(state.source_lines, vec![1, 2, 2, 2, 3]);
+    }
+
+    #[test]
+    fn expands_labeled_directive() {
+        let state = expand(&["table data 7", "data_label Data 8"]).unwrap();
+
+        assert_eq!(
+            state.lines,
+            vec!["table dat #0, #7", "data_label dat #0, #8"]
+        );
+    }
+
+    #[test]
+    fn handler_error() {
+        let err = expand(&["mov 0, 1", "  data"]).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::DirectiveFailed {
+                name: "DATA".into(),
+                message: "expected at least one value".into(),
+                span: Some(crate::error::Span::new(2, 2, 6)),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot redefine standard opcode MOV")]
+    fn cannot_redefine_opcode() {
+        Directives::default().insert("mov", data_directive);
+    }
+}
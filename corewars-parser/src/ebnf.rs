@@ -0,0 +1,284 @@
+//! A rendering of the [pest grammar](crate::GRAMMAR) as W3C-style EBNF, for
+//! tools which don't understand pest, e.g. to generate syntax highlighting.
+//!
+//! It is translated from the grammar itself, so it always matches what the
+//! parser accepts. A few pest features have no EBNF equivalent, and are
+//! written as follows:
+//!
+//! - keywords matched case-insensitively, like `^"DAT"`, become character
+//!   classes, like `[Dd] [Aa] [Tt]`
+//! - `!X` matches only where `X` doesn't, and `&X` only where it does,
+//!   without consuming any input
+//! - spaces and tabs may appear between the items of a rule, except in
+//!   rules marked `/* no whitespace */`
+//! - `/* start of input */` and `/* end of input */` mark rules which must
+//!   match a whole line
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::grammar::GRAMMAR;
+
+/// The EBNF equivalents of the pest builtin rules used by the grammar.
+const BUILTINS: &[(&str, &str)] = &[
+    ("ASCII_DIGIT", "[0-9]"),
+    ("ASCII_ALPHA", "[a-zA-Z]"),
+    ("ASCII_ALPHANUMERIC", "[a-zA-Z0-9]"),
+    ("ANY", "[#x0-#x10FFFF]"),
+    ("SOI", "/* start of input */"),
+    ("EOI", "/* end of input */"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Comment(String),
+    BlankLine,
+    Name(String),
+    Literal { text: String, insensitive: bool },
+    Symbol(char),
+}
+
+/// The grammar of a line of Redcode, as EBNF.
+///
+/// ```
+/// let ebnf = corewars_parser::ebnf();
+/// assert!(ebnf.contains("\nField ::= AddressMode? Expression\n"));
+/// ```
+pub fn ebnf() -> String {
+    let mut tokens = tokenize(GRAMMAR).into_iter().peekable();
+    let mut lines = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Comment(comment) => lines.push(format!("/* {} */", comment)),
+            Token::BlankLine => lines.push(String::new()),
+            Token::Name(name) => lines.push(rule(&name, &mut tokens)),
+            token => panic!("unexpected {:?} outside a rule of the grammar", token),
+        }
+    }
+
+    // Comments between rules leave runs of blank lines behind
+    lines.dedup_by(|a, b| a.is_empty() && b.is_empty());
+    let text = lines.join("\n");
+    format!("{}\n", text.trim())
+}
+
+/// Translate the rule called `name`, whose body is the next tokens.
+fn rule<I: Iterator<Item = Token>>(name: &str, tokens: &mut Peekable<I>) -> String {
+    assert_eq!(
+        tokens.next(),
+        Some(Token::Symbol('=')),
+        "rule {} has no =",
+        name
+    );
+
+    let mut atomic = false;
+    loop {
+        match tokens.next() {
+            Some(Token::Symbol('{')) => break,
+            Some(Token::Symbol('@')) | Some(Token::Symbol('$')) => atomic = true,
+            Some(Token::Symbol('_')) | Some(Token::Symbol('!')) => {}
+            token => panic!("unexpected {:?} before the body of rule {}", token, name),
+        }
+    }
+
+    let mut items: Vec<String> = Vec::new();
+    let mut depth = 0;
+    loop {
+        let token = tokens
+            .next()
+            .unwrap_or_else(|| panic!("rule {} is never closed", name));
+
+        let item = match token {
+            Token::Symbol('}') if depth == 0 => break,
+            Token::Symbol('~') | Token::Comment(_) | Token::BlankLine => continue,
+            Token::Symbol(c @ '(') => {
+                depth += 1;
+                c.to_string()
+            }
+            Token::Symbol(c @ ')') => {
+                depth -= 1;
+                c.to_string()
+            }
+            Token::Symbol(c @ '|') => format!(" {} ", c),
+            Token::Symbol(c) => c.to_string(),
+            Token::Name(name) => BUILTINS
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map_or(name.clone(), |(_, ebnf)| ebnf.to_string()),
+            Token::Literal { text, insensitive } => literal(&text, insensitive),
+        };
+        items.push(item);
+    }
+
+    let mut body = String::new();
+    for (i, item) in items.iter().enumerate() {
+        let joined = i == 0
+            || body.ends_with(' ')
+            || body.ends_with('(')
+            || body.ends_with('!')
+            || body.ends_with('&')
+            || item.starts_with(' ')
+            || matches!(item.as_str(), ")" | "?" | "*" | "+");
+        if !joined {
+            body.push(' ');
+        }
+        body.push_str(item);
+    }
+
+    if atomic {
+        body.push_str(" /* no whitespace */");
+    }
+    format!("{} ::= {}", name, body)
+}
+
+/// Write a string literal, with each letter of a case-insensitive one as a
+/// class of its upper and lower case, and control characters as their code.
+fn literal(text: &str, insensitive: bool) -> String {
+    fn flush(run: &mut String, items: &mut Vec<String>) {
+        if !run.is_empty() {
+            let quote = if run.contains('"') { '\'' } else { '"' };
+            items.push(format!("{}{}{}", quote, run, quote));
+            run.clear();
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut run = String::new();
+    for c in text.chars() {
+        if insensitive && c.is_ascii_alphabetic() {
+            flush(&mut run, &mut items);
+            items.push(format!(
+                "[{}{}]",
+                c.to_ascii_uppercase(),
+                c.to_ascii_lowercase()
+            ));
+        } else if c.is_control() {
+            flush(&mut run, &mut items);
+            items.push(format!("#x{:X}", u32::from(c)));
+        } else {
+            run.push(c);
+        }
+    }
+    flush(&mut run, &mut items);
+
+    items.join(" ")
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut newlines = 0;
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            newlines += 1;
+            if newlines == 2 {
+                tokens.push(Token::BlankLine);
+            }
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        newlines = 0;
+
+        let token = match c {
+            '/' if chars.peek() == Some(&'/') => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                newlines = 1;
+                Token::Comment(comment.trim_start_matches('/').trim().to_string())
+            }
+            '"' => Token::Literal {
+                text: string(&mut chars),
+                insensitive: false,
+            },
+            '^' if chars.peek() == Some(&'"') => {
+                chars.next();
+                Token::Literal {
+                    text: string(&mut chars),
+                    insensitive: true,
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' && is_name_next(&chars) => {
+                let mut name = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                Token::Name(name)
+            }
+            c => Token::Symbol(c),
+        };
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Whether a `_` starts a name, rather than marking a rule as silent.
+fn is_name_next(chars: &Peekable<Chars>) -> bool {
+    chars
+        .clone()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The rest of a string literal whose opening quote has been read.
+fn string(chars: &mut Peekable<Chars>) -> String {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('t') => text.push('\t'),
+                Some('n') => text.push('\n'),
+                Some(c) => text.push(c),
+                None => break,
+            },
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn translates_rules() {
+        let ebnf = ebnf();
+        let line = |name: &str| {
+            ebnf.lines()
+                .find(|line| line.starts_with(&format!("{} ::= ", name)))
+                .unwrap_or_else(|| panic!("no rule {}", name))
+                .to_string()
+        };
+
+        assert_eq!(
+            line("Modifier"),
+            "Modifier ::= [Aa] [Bb] | [Bb] [Aa] | [Aa] | [Bb] | [Ff] | [Xx] | [Ii]"
+        );
+        assert_eq!(
+            line("Operation"),
+            "Operation ::= Opcode (\".\" Modifier)? ![a-zA-Z0-9] /* no whitespace */"
+        );
+        assert_eq!(
+            line("InstructionLine"),
+            "InstructionLine ::= /* start of input */ Instruction /* end of input */"
+        );
+        assert_eq!(line("WHITESPACE"), "WHITESPACE ::= \" \" | #x9");
+        assert_eq!(
+            line("Label"),
+            "Label ::= Alpha Alphanumeral* /* no whitespace */"
+        );
+        assert!(ebnf.starts_with("/* This grammar describes a single line of Redcode,"));
+        assert!(!ebnf.contains("\n\n\n"));
+    }
+}
@@ -1,12 +1,56 @@
 //! Error types for the corewars library
 
 use std::num::TryFromIntError;
+use std::path::PathBuf;
 
 use thiserror::Error as ThisError;
 
 use corewars_core::load_file::Opcode;
 
-// TODO: use pest spans for error reporting? Or at least line number
+use super::grammar::SyntaxError;
+
+/// A location in the parser input, used to point at the cause of an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    /// The line of the input containing the span, starting from 1.
+    pub line: usize,
+
+    /// The byte offset within the line where the span starts.
+    pub start: usize,
+
+    /// The byte offset within the line where the span ends (exclusive).
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span within the given line of input.
+    pub fn new(line: usize, start: usize, end: usize) -> Self {
+        Self { line, start, end }
+    }
+}
+
+impl Span {
+    /// A span covering the code of `line` in `buffer`, without any comment
+    /// or surrounding whitespace.
+    pub(crate) fn of_line(line: usize, buffer: &str) -> Self {
+        let source = buffer
+            .lines()
+            .nth(line.saturating_sub(1))
+            .unwrap_or_default();
+        let code = source.split(';').next().unwrap_or_default();
+        let code_start = code.len() - code.trim_start().len();
+
+        Self::new(line, code_start, code.trim_end().len())
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span) -> Self {
+        // The line number is unknown at this point, it gets filled in by
+        // the phase that knows where the text came from
+        Self::new(0, span.start(), span.end())
+    }
+}
 
 /// An error that occurred while parsing a warrior.
 #[derive(ThisError, Debug, PartialEq, Eq)]
@@ -14,19 +58,279 @@ use corewars_core::load_file::Opcode;
 pub enum Error {
     /// The warrior contained a reference to a label that doesn't exist.
     #[error("no such label {label:?}")]
-    LabelNotFound { label: String, line: Option<usize> },
+    LabelNotFound { label: String, span: Option<Span> },
 
     /// An invalid warrior origin (not a positive integer) was specified.
     #[error("invalid origin specified")]
     InvalidOrigin(#[from] TryFromIntError),
 
     /// The input string was ill-formed Redcode syntax.
-    #[error("invalid syntax")]
-    InvalidSyntax(#[from] super::grammar::SyntaxError),
+    #[error("invalid syntax: {}", syntax_error_message(.error))]
+    InvalidSyntax {
+        error: Box<SyntaxError>,
+        span: Option<Span>,
+    },
 
     /// The given opcode was not given enough arguments.
     #[error("expected additional arguments for {opcode} opcode")]
-    InvalidArguments { opcode: Opcode },
+    InvalidArguments { opcode: Opcode, span: Option<Span> },
+
+    /// An EQU substitution refers back to itself, directly or through other
+    /// substitutions, so it can never be fully expanded. `cycle` lists each
+    /// label involved along with the line it was defined on.
+    #[error("recursive substitution: {}", recursion_message(.cycle))]
+    RecursiveSubstitution {
+        cycle: Vec<(String, usize)>,
+        span: Option<Span>,
+    },
+
+    /// A FOR loop was nested inside more loops than allowed by the
+    /// [`ExpansionLimits`](crate::ExpansionLimits).
+    #[error("FOR loop is nested {depth} levels deep, more than the maximum of {max}")]
+    ForNestingTooDeep {
+        depth: usize,
+        max: usize,
+        span: Option<Span>,
+    },
+
+    /// A FOR loop would repeat its body more times than allowed by the
+    /// [`ExpansionLimits`](crate::ExpansionLimits), counting the repetitions
+    /// of any loops enclosing it.
+    #[error("FOR loop repeats {repetitions} times in total, more than the maximum of {max}")]
+    ForExpansionTooLarge {
+        repetitions: u64,
+        max: u64,
+        span: Option<Span>,
+    },
+
+    /// Parsing was stopped by the
+    /// [cancellation token](crate::ExpansionLimits::cancellation) of the
+    /// expansion limits.
+    #[error("parsing was cancelled")]
+    Cancelled,
+
+    /// An expression divided by zero, or took the remainder of dividing by
+    /// zero.
+    #[error("division by zero")]
+    DivideByZero { span: Option<Span> },
+
+    /// A number in an expression doesn't fit in 32 bits.
+    #[error("number {number} is too large")]
+    NumberTooLarge { number: String, span: Option<Span> },
+
+    /// A module being [linked](crate::Parser::link) imports a label which no
+    /// module exports.
+    #[error("{module} imports {label:?}, which no other file exports")]
+    UnresolvedImport { label: String, module: String },
+
+    /// A module being linked exports a label it doesn't declare.
+    #[error("{module} exports {label:?}, which it does not declare")]
+    UndeclaredExport { label: String, module: String },
+
+    /// More than one module being linked exports the same label.
+    #[error("{label:?} is exported by both {} and {}", .modules.0, .modules.1)]
+    DuplicateExport {
+        label: String,
+        modules: (String, String),
+    },
+
+    /// A module being linked uses a label exported by another module,
+    /// without importing it.
+    #[error("{module} uses {label:?} from {exporter} without importing it")]
+    NotImported {
+        label: String,
+        module: String,
+        exporter: String,
+    },
+
+    /// An included file could not be read.
+    #[error("cannot include {}: {message}", .path.display())]
+    IncludeFailed { path: PathBuf, message: String },
+
+    /// A file includes itself, directly or through other included files.
+    /// `cycle` lists each file involved, ending with the repeated one.
+    #[error("include cycle: {}", include_message(.cycle))]
+    IncludeCycle { cycle: Vec<PathBuf> },
+
+    /// A continuation of a multi-line `EQU` was not preceded by a labeled
+    /// `EQU` to continue.
+    #[error("EQU continuation without a labeled EQU before it")]
+    EquWithoutLabel { span: Option<Span> },
+
+    /// An instruction was missing a part the grammar should have required.
+    #[error("malformed instruction")]
+    MalformedInstruction { span: Option<Span> },
+
+    /// The input used `${NAME}` [variables](crate::Parser::variables) which
+    /// are not set. `names` lists each of them, in the order they're used.
+    #[error("unresolved variables: {}", .names.join(", "))]
+    UnresolvedVariables {
+        names: Vec<String>,
+        span: Option<Span>,
+    },
+
+    /// A `${` starting a [variable](crate::Parser::variables) is followed by
+    /// its name, but not by the `}` which should close it.
+    #[error("unterminated variable ${{{name}: expected }}")]
+    UnterminatedVariable { name: String, span: Option<Span> },
+
+    /// A custom directive's handler rejected its input.
+    #[error("error in {name} directive: {message}")]
+    DirectiveFailed {
+        name: String,
+        message: String,
+        span: Option<Span>,
+    },
+}
+
+impl Error {
+    /// The location in the input that caused this error, if known.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Self::LabelNotFound { span, .. }
+            | Self::InvalidSyntax { span, .. }
+            | Self::InvalidArguments { span, .. }
+            | Self::RecursiveSubstitution { span, .. }
+            | Self::ForNestingTooDeep { span, .. }
+            | Self::ForExpansionTooLarge { span, .. }
+            | Self::DivideByZero { span }
+            | Self::NumberTooLarge { span, .. }
+            | Self::DirectiveFailed { span, .. }
+            | Self::EquWithoutLabel { span }
+            | Self::MalformedInstruction { span }
+            | Self::UnresolvedVariables { span, .. }
+            | Self::UnterminatedVariable { span, .. } => span.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn span_mut(&mut self) -> Option<&mut Option<Span>> {
+        match self {
+            Self::LabelNotFound { span, .. }
+            | Self::InvalidSyntax { span, .. }
+            | Self::InvalidArguments { span, .. }
+            | Self::RecursiveSubstitution { span, .. }
+            | Self::ForNestingTooDeep { span, .. }
+            | Self::ForExpansionTooLarge { span, .. }
+            | Self::DivideByZero { span }
+            | Self::NumberTooLarge { span, .. }
+            | Self::DirectiveFailed { span, .. }
+            | Self::EquWithoutLabel { span }
+            | Self::MalformedInstruction { span }
+            | Self::UnresolvedVariables { span, .. }
+            | Self::UnterminatedVariable { span, .. } => Some(span),
+            _ => None,
+        }
+    }
+
+    /// Move this error's span to another line, e.g. to report it against
+    /// the [included](crate::Source) file it came from.
+    pub fn relocated(mut self, line: usize) -> Self {
+        if let Some(Some(span)) = self.span_mut() {
+            span.line = line;
+        }
+        self
+    }
+
+    /// Shift this error's span (if it has not been located yet) to the right,
+    /// for errors which occurred in a substring of a line.
+    pub(crate) fn shifted(mut self, by: usize) -> Self {
+        if let Some(Some(span)) = self
+            .span_mut()
+            .filter(|span| span.is_some_and(|span| span.line == 0))
+        {
+            span.start += by;
+            span.end += by;
+        }
+        self
+    }
+
+    /// Resolve this error's span into a location in the original input.
+    /// The span of the error (if any) is relative to `text`, which is a
+    /// possibly-expanded version of line number `line` of `buffer`.
+    pub(crate) fn locate(mut self, line: usize, text: &str, buffer: &str) -> Self {
+        let span = match self.span_mut() {
+            Some(span) => span,
+            None => return self,
+        };
+
+        let source = buffer
+            .lines()
+            .nth(line.saturating_sub(1))
+            .unwrap_or_default();
+
+        // Only the code portion of the line is relevant, not any comments
+        let code = source.split(';').next().unwrap_or_default();
+        let whole_line = Span::of_line(line, buffer);
+
+        *span = Some(match span.take() {
+            Some(relative) if relative.line == 0 => {
+                let snippet = text.get(relative.start..relative.end).unwrap_or_default();
+
+                if let Some(text_start) = code.find(text) {
+                    // The line was not modified by any previous phases
+                    Span::new(line, text_start + relative.start, text_start + relative.end)
+                } else if let Some(snippet_start) =
+                    code.find(snippet).filter(|_| !snippet.is_empty())
+                {
+                    Span::new(line, snippet_start, snippet_start + snippet.len())
+                } else {
+                    whole_line
+                }
+            }
+            Some(absolute) => absolute,
+            None => whole_line,
+        });
+
+        self
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(error: SyntaxError) -> Self {
+        use pest::error::InputLocation;
+
+        let span = match error.location {
+            InputLocation::Pos(pos) => Span::new(0, pos, pos + 1),
+            InputLocation::Span((start, end)) => Span::new(0, start, end),
+        };
+
+        Self::InvalidSyntax {
+            // Boxed since pest errors are quite large compared to other variants
+            error: Box::new(error),
+            span: Some(span),
+        }
+    }
+}
+
+fn syntax_error_message(error: &SyntaxError) -> String {
+    use pest::error::ErrorVariant;
+
+    match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+            let expected: Vec<String> =
+                positives.iter().map(|rule| format!("{:?}", rule)).collect();
+            format!("expected {}", expected.join(" or "))
+        }
+        ErrorVariant::ParsingError { .. } => "unexpected input".into(),
+        ErrorVariant::CustomError { message } => message.to_lowercase(),
+    }
+}
+
+fn recursion_message(cycle: &[(String, usize)]) -> String {
+    cycle
+        .iter()
+        .map(|(label, line)| format!("{} (line {})", label, line))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+fn include_message(cycle: &[PathBuf]) -> String {
+    cycle
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
 }
 
 /// A warning that occurred while parsing a warrior.
@@ -35,13 +339,65 @@ pub enum Error {
 pub enum Warning {
     /// Attempt to define the warrior origin more than once.
     #[error("origin already defined as {old:?}, new definition {new:?} will be ignored")]
-    OriginRedefinition { old: String, new: String },
+    OriginRedefinition {
+        old: String,
+        new: String,
+        span: Option<Span>,
+    },
+
+    /// `ORG` without an argument.
+    #[error("ORG must be given an argument, it will be ignored")]
+    MissingOrigin { span: Option<Span> },
+
+    /// Attempt to define the P-space identifier more than once.
+    #[error("PIN already defined as {old:?}, new definition {new:?} will be ignored")]
+    PinRedefinition {
+        old: String,
+        new: String,
+        span: Option<Span>,
+    },
+
+    /// `PIN` without an argument.
+    #[error("PIN must be given an argument, it will be ignored")]
+    MissingPin { span: Option<Span> },
 
     /// Empty EQU substitution.
-    #[error("right-hand side of substitution for label {0:?} is empty")]
-    EmptySubstitution(String),
+    #[error("right-hand side of substitution for label {label:?} is empty")]
+    EmptySubstitution { label: String, span: Option<Span> },
 
     /// Offset label declaration with no instruction.
-    #[error("no instruction offset for label {0:?}, it will not b")]
-    EmptyOffset(String),
+    #[error("no instruction offset for label {label:?}, it will not be defined")]
+    EmptyOffset { label: String, span: Option<Span> },
+}
+
+impl Warning {
+    /// The location in the input that caused this warning, if known.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Self::OriginRedefinition { span, .. }
+            | Self::MissingOrigin { span }
+            | Self::PinRedefinition { span, .. }
+            | Self::MissingPin { span }
+            | Self::EmptySubstitution { span, .. }
+            | Self::EmptyOffset { span, .. } => span.as_ref(),
+        }
+    }
+
+    /// Move this warning's span down by `lines`, for warnings from a part of
+    /// a larger input.
+    pub(crate) fn offset(mut self, lines: usize) -> Self {
+        match &mut self {
+            Self::OriginRedefinition { span, .. }
+            | Self::MissingOrigin { span }
+            | Self::PinRedefinition { span, .. }
+            | Self::MissingPin { span }
+            | Self::EmptySubstitution { span, .. }
+            | Self::EmptyOffset { span, .. } => {
+                if let Some(span) = span {
+                    span.line += lines;
+                }
+            }
+        }
+        self
+    }
 }
@@ -23,9 +23,12 @@ mod derived {
 
 pub use derived::{Grammar, Rule};
 
+/// The pest grammar of a line of Redcode, as used by the parser.
+pub const GRAMMAR: &str = include_str!("grammar/redcode.pest");
+
 /// Parse an input line and flatten it to only include the terminal token pairs,
 /// i.e. pairs without any inner token pairs.
-pub fn tokenize(line: &str) -> Vec<Pair> {
+pub fn tokenize(line: &str) -> Vec<Pair<'_>> {
     parse_line(line)
         .map(|pairs| {
             pairs
@@ -38,12 +41,51 @@ pub fn tokenize(line: &str) -> Vec<Pair> {
 }
 
 /// Parse a single line of input according to the grammar.
-pub fn parse_line(line: &str) -> Result<Pairs, Error> {
+pub fn parse_line(line: &str) -> Result<Pairs<'_>, Error> {
     Ok(Grammar::parse(Rule::Line, line)?)
 }
 
+/// Parse a line which must consist of exactly one instruction, with no labels
+/// or any other trailing input.
+pub fn parse_instruction(line: &str) -> Result<Pair<'_>, Error> {
+    let mut pairs = Grammar::parse(Rule::InstructionLine, line)?;
+
+    Ok(pairs
+        .find(|pair| pair.as_rule() == Rule::Instruction)
+        .expect("InstructionLine must contain an Instruction"))
+}
+
+/// Parse a line of a load file, which must be a single instruction with
+/// explicit modifiers and address modes, and numbers for fields.
+pub fn parse_load_instruction(line: &str) -> Result<Pair<'_>, Error> {
+    let mut pairs = Grammar::parse(Rule::LoadFileLine, line)?;
+
+    Ok(pairs
+        .find(|pair| pair.as_rule() == Rule::LoadInstruction)
+        .expect("LoadFileLine must contain a LoadInstruction"))
+}
+
+/// Parse the origin of a load file, which must be a single number.
+pub fn parse_load_origin(line: &str) -> Result<Pair<'_>, Error> {
+    let mut pairs = Grammar::parse(Rule::LoadOriginLine, line)?;
+
+    Ok(pairs
+        .find(|pair| pair.as_rule() == Rule::SignedNumber)
+        .expect("LoadOriginLine must contain a SignedNumber"))
+}
+
+/// Parse a line which must consist of exactly one expression, with no
+/// trailing input.
+pub fn parse_expression_line(line: &str) -> Result<Pair<'_>, Error> {
+    let mut pairs = Grammar::parse(Rule::ExpressionLine, line)?;
+
+    Ok(pairs
+        .find(|pair| pair.as_rule() == Rule::Expression)
+        .expect("ExpressionLine must contain an Expression"))
+}
+
 /// Parse a single expression as a string.
-pub fn parse_expression(line: &str) -> Result<Pair, Error> {
+pub fn parse_expression(line: &str) -> Result<Pair<'_>, Error> {
     let mut pairs = Grammar::parse(Rule::Expression, line)?;
 
     pairs
@@ -59,10 +101,10 @@ pub fn parse_expression(line: &str) -> Result<Pair, Error> {
         })
 }
 
-#[cfg(any(test, doctest))] // cfg(doctest) so we run the helper's doctest
+#[cfg(test)]
 mod test {
     // pest::parses_to seems to have a panic that doesn't conform to rust 2021
-    #![allow(non_fmt_panic)]
+    #![allow(non_fmt_panics)]
 
     use pest::{consumes_to, parses_to};
     use test_case::test_case;
@@ -72,7 +114,7 @@ mod test {
 
     /// A macro to assert on the way a certain input string parses
     /// Two forms are allowed. One has no identifier:
-    /// ```
+    /// ```ignore
     /// match_parse!(Field {
     ///     "123" | "4567" => [
     ///         // This should look like the `tokens` field of `parses_to!`
@@ -81,7 +123,7 @@ mod test {
     /// ```
     ///
     /// The other allows you to bind the input string so you can use it in your
-    /// ```
+    /// ```ignore
     /// match_parse!(input, Field {
     ///     "123" | "4567" => [
     ///         // You can do something with e.g. `input.len()` here, which
@@ -0,0 +1,370 @@
+//! Including other source files with an `INCLUDE "common.red"` line (or
+//! `;include "common.red"`, which other assemblers ignore as a comment).
+//! Paths are relative to the directory of the file which includes them.
+//!
+//! Included files are spliced into a single text, which is parsed as usual.
+//! A [`SourceMap`](SourceMap) records which file each line of that text came
+//! from, so errors can be reported against the file that caused them.
+//!
+//! An `END` in an included file only ends that file, and its metadata
+//! comments such as `;name` are dropped, so they can't take the place of
+//! those of the file including it.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use corewars_core::load_file::Metadata;
+
+use super::error::Error;
+use super::phase::ends_program;
+
+/// A source file with any files it includes spliced in.
+///
+/// ```
+/// use std::path::Path;
+///
+/// let source = corewars_parser::Source::resolve("mov 0, 1\njmp -1", Path::new("imp.red")).unwrap();
+/// let location = source.source_map.locate(2).unwrap();
+/// assert_eq!((location.path, location.line), (Path::new("imp.red"), 2));
+/// ```
+#[derive(Debug)]
+pub struct Source {
+    /// The text to parse, with included files spliced in. Include lines are
+    /// left in as comments, so errors on other lines keep their positions.
+    pub text: String,
+
+    /// Where each line of `text` came from
+    pub source_map: SourceMap,
+}
+
+/// The file and line that each line of a [`Source`](Source) came from.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// Each file that was read, and its contents
+    files: Vec<(PathBuf, String)>,
+
+    /// The index in `files` and line number of each line
+    lines: Vec<(usize, usize)>,
+}
+
+/// Where a line of a [`Source`](Source) came from.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Location<'a> {
+    /// The file containing the line
+    pub path: &'a Path,
+
+    /// The line number within that file, starting from 1
+    pub line: usize,
+
+    /// The whole text of the file
+    pub text: &'a str,
+}
+
+impl Source {
+    /// Read the file at `path`, and any files it includes.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).map_err(|err| Error::IncludeFailed {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        Self::resolve(&text, path)
+    }
+
+    /// Splice any files included by `text`, which was read from `path`.
+    pub fn resolve(text: &str, path: &Path) -> Result<Self, Error> {
+        Self::resolve_with(text, path, |path| fs::read_to_string(path))
+    }
+
+    fn resolve_with<R>(text: &str, path: &Path, mut read: R) -> Result<Self, Error>
+    where
+        R: FnMut(&Path) -> io::Result<String>,
+    {
+        let mut source = Self {
+            text: String::with_capacity(text.len()),
+            source_map: SourceMap::default(),
+        };
+        let mut stack = vec![normalize(path)];
+        source.splice(text, path, &mut stack, &mut read)?;
+        Ok(source)
+    }
+
+    fn splice<R>(
+        &mut self,
+        text: &str,
+        path: &Path,
+        stack: &mut Vec<PathBuf>,
+        read: &mut R,
+    ) -> Result<(), Error>
+    where
+        R: FnMut(&Path) -> io::Result<String>,
+    {
+        let file = self.source_map.files.len();
+        self.source_map
+            .files
+            .push((path.to_path_buf(), text.to_string()));
+        let nested = stack.len() > 1;
+
+        for (i, line) in text.lines().enumerate() {
+            self.source_map.lines.push((file, i + 1));
+
+            if nested {
+                if ends_program(line) {
+                    // Left in as a comment, so the includer carries on after it
+                    self.text.push(';');
+                    self.text.push_str(line);
+                    self.text.push('\n');
+                    break;
+                }
+
+                let mut metadata = Metadata::default();
+                let code = metadata.parse_line(line);
+                if metadata != Metadata::default() {
+                    self.text.push_str(code);
+                    self.text.push('\n');
+                    continue;
+                }
+            }
+
+            let target = match include_target(line) {
+                Some(target) => target,
+                None => {
+                    self.text.push_str(line);
+                    self.text.push('\n');
+                    continue;
+                }
+            };
+
+            if !line.trim_start().starts_with(';') {
+                self.text.push(';');
+            }
+            self.text.push_str(line);
+            self.text.push('\n');
+
+            let included = path.parent().unwrap_or_else(|| Path::new("")).join(target);
+            let normalized = normalize(&included);
+
+            if let Some(start) = stack.iter().position(|path| *path == normalized) {
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(normalized);
+                return Err(Error::IncludeCycle { cycle });
+            }
+
+            let contents = read(&included).map_err(|err| Error::IncludeFailed {
+                path: included.clone(),
+                message: err.to_string(),
+            })?;
+
+            stack.push(normalized);
+            self.splice(&contents, &included, stack, read)?;
+            stack.pop();
+        }
+
+        Ok(())
+    }
+}
+
+impl SourceMap {
+    /// Find where `line` (starting from 1) of the spliced text came from.
+    pub fn locate(&self, line: usize) -> Option<Location<'_>> {
+        let &(file, line) = self.lines.get(line.checked_sub(1)?)?;
+        let (path, text) = &self.files[file];
+
+        Some(Location { path, line, text })
+    }
+
+    /// Every file which was read, starting with the one which included the
+    /// others.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+}
+
+/// The path given by an include line, if `line` is one.
+fn include_target(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let line = line.strip_prefix(';').unwrap_or(line).trim_start();
+
+    let keyword_end = line.find(char::is_whitespace)?;
+    if !line[..keyword_end].eq_ignore_ascii_case("include") {
+        return None;
+    }
+
+    let quoted = line[keyword_end..].trim_start().strip_prefix('"')?;
+    let end = quoted.find('"')?;
+    Some(&quoted[..end]).filter(|target| !target.is_empty())
+}
+
+/// Remove `.` and `..` components from `path` without touching the
+/// filesystem, so the same file is recognized when reached different ways.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn resolve(files: &[(&str, &str)]) -> Result<Source, Error> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), text.to_string()))
+            .collect();
+
+        Source::resolve_with(
+            &files[Path::new("main.red")],
+            Path::new("main.red"),
+            |path| {
+                files
+                    .get(&normalize(path))
+                    .cloned()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+            },
+        )
+    }
+
+    #[test]
+    fn splices_includes() {
+        let source = resolve(&[
+            (
+                "main.red",
+                "start jmp bomb\n;include \"lib/bomb.red\"\nend start",
+            ),
+            (
+                "lib/bomb.red",
+                "INCLUDE \"../common.red\"\nbomb dat 0, step",
+            ),
+            ("common.red", "step equ 4"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            source.text,
+            "start jmp bomb\n;include \"lib/bomb.red\"\n;INCLUDE \"../common.red\"\nstep equ 4\nbomb dat 0, step\nend start\n"
+        );
+
+        let location = source.source_map.locate(5).unwrap();
+        assert_eq!(location.path, Path::new("lib/bomb.red"));
+        assert_eq!(location.line, 2);
+        assert_eq!(source.source_map.locate(6).unwrap().line, 3);
+        assert_eq!(source.source_map.locate(7), None);
+
+        let warrior = crate::parse(&source.text).unwrap();
+        assert_eq!(
+            warrior.program.instructions[1].to_string(),
+            "DAT.F   $0,     $4"
+        );
+    }
+
+    #[test]
+    fn included_end_only_ends_include() {
+        let source = resolve(&[
+            ("main.red", "include \"lib.red\"\nmov 0, 1\nend"),
+            ("lib.red", "dat 0, 0\nEND\nnot redcode"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            source.text,
+            ";include \"lib.red\"\ndat 0, 0\n;END\nmov 0, 1\nend\n"
+        );
+        assert_eq!(
+            source.source_map.locate(4).unwrap().path,
+            Path::new("main.red")
+        );
+
+        let warrior = crate::parse(&source.text).unwrap();
+        assert_eq!(warrior.program.instructions.len(), 2);
+    }
+
+    #[test]
+    fn drops_included_metadata() {
+        let source = resolve(&[
+            (
+                "main.red",
+                ";name Main\n;author Me\ninclude \"lib.red\"\nmov 0, 1",
+            ),
+            (
+                "lib.red",
+                ";name Library\n;author Someone\ndat 0, 0 ; keep\nstep equ 2 ;name Step",
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            source.text,
+            ";name Main\n;author Me\n;include \"lib.red\"\n\n\ndat 0, 0 ; keep\nstep equ 2\nmov 0, 1\n"
+        );
+
+        let warrior = crate::parse(&source.text).unwrap();
+        assert_eq!(warrior.metadata.name.as_deref(), Some("Main"));
+        assert_eq!(warrior.metadata.author.as_deref(), Some("Me"));
+        assert_eq!(warrior.program.instructions.len(), 2);
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let error = resolve(&[
+            ("main.red", "include \"a.red\""),
+            ("a.red", "include \"./b.red\""),
+            ("b.red", "include \"a.red\""),
+        ])
+        .unwrap_err();
+
+        assert_eq!(
+            error,
+            Error::IncludeCycle {
+                cycle: vec!["a.red".into(), "b.red".into(), "a.red".into()]
+            }
+        );
+        assert_eq!(error.to_string(), "include cycle: a.red -> b.red -> a.red");
+
+        // Including the same file twice is fine, as long as it isn't nested
+        let source = resolve(&[
+            ("main.red", "include \"a.red\"\ninclude \"a.red\""),
+            ("a.red", "dat 0, 0"),
+        ])
+        .unwrap();
+        assert_eq!(source.source_map.files().count(), 3);
+    }
+
+    #[test]
+    fn missing_include() {
+        let error = resolve(&[("main.red", "include \"missing.red\"")]).unwrap_err();
+        assert_eq!(
+            error,
+            Error::IncludeFailed {
+                path: "missing.red".into(),
+                message: "not found".into(),
+            }
+        );
+
+        // Includes need a quoted path, otherwise they're left alone
+        assert_eq!(include_target("include common.red"), None);
+        assert_eq!(
+            include_target("; Include \"x.red\" ; comment"),
+            Some("x.red")
+        );
+    }
+}
@@ -1,39 +1,473 @@
+// test_case generates unit expressions which trip this lint
+#![cfg_attr(test, allow(clippy::unused_unit))]
+
 //! This module is used for parsing a Redcode program.
 //! It operates in multiple phases, which are found in the [phase](phase/index.html)
 //! module. Each phase passes its result to the next phase.
 
-pub use error::{Error, Warning};
+pub use archive::{archive, split_archive};
+pub use codes::{explain_code, CodeInfo, CODES};
+pub use diagnostics::{Diagnostic, Severity};
+pub use directive::{Directives, Handler};
+pub use ebnf::ebnf;
+pub use error::{Error, Span, Warning};
+pub use grammar::GRAMMAR;
+pub use include::{Location, Source, SourceMap};
+pub use link::{combined_buffer, Module};
+pub use listing::{Listing, ListingLine};
+pub use phase::{Accessor, ExpansionLimits};
+pub use rename::{rename, Error as RenameError};
 pub use result::Result;
+pub use variables::Lookup;
 
+mod archive;
+mod codes;
+mod diagnostics;
+mod directive;
+mod ebnf;
 mod error;
 mod grammar;
+mod include;
+mod link;
+mod listing;
 mod phase;
+mod rename;
 mod result;
+mod variables;
 
-use std::convert::TryFrom;
-
-use corewars_core::load_file::Warrior;
+use corewars_core::load_file::{ModifierPolicy, Warrior};
+use corewars_core::perf::PerfStats;
+use corewars_core::CancellationToken;
 
-use phase::{CommentsRemoved, Evaluated, Expanded, Output, Phase, Raw};
+use phase::{CommentsRemoved, Expanded, Output, Phase, Raw};
+use variables::Variables;
 
 /// Parse a given input string into a [`Result`](Result). If successful the
 /// `Result` will contain a `Warrior`, otherwise it will contain an error. In
 /// either case, one or more [`Warning`](error::Warning)s may be generated with
 /// the `Warrior`.
 pub fn parse(input: &str) -> Result<Warrior> {
-    parse_impl(input).into()
+    Parser::new().parse(input)
+}
+
+/// Parse every warrior in an [`archive`](archive), like [`parse`](parse).
+/// See [`Parser::parse_archive`](Parser::parse_archive).
+pub fn parse_archive(input: &str) -> Result<Vec<Warrior>> {
+    Parser::new().parse_archive(input)
+}
+
+/// Parse an ICWS '94 load file, such as one written by another assembler.
+/// Every instruction must have its modifier and address modes written out,
+/// and its fields must be numbers, so labels and expressions are errors.
+///
+/// ```
+/// let warrior = corewars_parser::parse_load_file(";name Imp\nORG 0\nMOV.I $0, $1").unwrap();
+/// assert_eq!(warrior.metadata.name.as_deref(), Some("Imp"));
+/// assert_eq!(warrior.program.origin, Some(0));
+/// assert_eq!(warrior.program.instructions, corewars_parser::parse("mov 0, 1").unwrap().program.instructions);
+///
+/// // Modifiers can't be left out
+/// let diagnostics = corewars_parser::parse_load_file("mov $0, $1").diagnostics();
+/// assert_eq!(
+///     diagnostics[0].to_string(),
+///     "error[E0003]: invalid syntax: expected LoadOperation (line 1, column 1)"
+/// );
+/// ```
+pub fn parse_load_file(input: &str) -> Result<Warrior> {
+    let mut cleaned = Phase::<CommentsRemoved>::from(Phase::<Raw>::from(input));
+    let warnings = cleaned.take_warnings();
+    let result = cleaned
+        .load()
+        .map(|evaluated| Phase::<Output>::from(evaluated).state.warrior);
+
+    Result::with_warnings(result, warnings)
+}
+
+/// Run only the preprocessing phases on a given input string. See
+/// [`Parser::preprocess`](Parser::preprocess).
+pub fn preprocess(input: &str) -> Result<String> {
+    Parser::new().preprocess(input)
+}
+
+/// Assemble a given input string into a [`Listing`](Listing) of each line
+/// and the instructions it produced. See [`Parser::listing`](Parser::listing).
+pub fn listing(input: &str) -> Result<Listing> {
+    Parser::new().listing(input)
+}
+
+/// Evaluate a standalone expression, such as a debugger condition like
+/// `core[100].opcode == DAT && cycle > 5000`. The value of each name it uses
+/// is looked up with `lookup`, and names it returns `None` for are an error.
+///
+/// ```
+/// let value = corewars_parser::evaluate("cycle * 2 + 1", |accessor| {
+///     Some(if accessor.name == "cycle" { 20 } else { 0 })
+/// });
+/// assert_eq!(value, Ok(41));
+/// ```
+pub fn evaluate<F>(expression: &str, lookup: F) -> std::result::Result<i32, Error>
+where
+    F: FnMut(&Accessor) -> Option<i32>,
+{
+    phase::evaluate_standalone(expression, lookup)
+}
+
+/// A configurable parser, which can be extended with custom pseudo-opcodes.
+///
+/// ```
+/// let parser = corewars_parser::Parser::new().directive("data", |operands| {
+///     Ok(operands
+///         .split(',')
+///         .map(|value| format!("dat #0, #{}", value.trim()))
+///         .collect())
+/// });
+///
+/// let warrior = parser.parse("data 1, 2, 3").unwrap();
+/// assert_eq!(warrior.program.instructions.len(), 3);
+/// ```
+#[derive(Debug, Default)]
+pub struct Parser {
+    directives: Directives,
+    limits: ExpansionLimits,
+    variables: Variables,
+    modifiers: ModifierPolicy,
 }
 
-fn parse_impl(input: &str) -> std::result::Result<Warrior, Error> {
-    let raw = Phase::<Raw>::from(input);
+impl Parser {
+    /// Create a parser for standard Redcode.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a custom pseudo-opcode `name`, which will be replaced by the lines
+    /// returned from `handler` before any other preprocessing. The handler is
+    /// given the text following the pseudo-opcode. An optional label before
+    /// the pseudo-opcode is applied to the first line returned.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is a standard opcode or pseudo-opcode.
+    pub fn directive<F>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(&str) -> std::result::Result<Vec<String>, String> + 'static,
+    {
+        self.directives.insert(name, handler);
+        self
+    }
+
+    /// Replace each `${NAME}` in the input with the value `lookup` returns
+    /// for `NAME`, right after comments are removed. It is an error to use
+    /// a variable `lookup` returns `None` for.
+    ///
+    /// ```
+    /// let parser = corewars_parser::Parser::new()
+    ///     .variables(|name| if name == "STEP" { Some("4".into()) } else { None });
+    ///
+    /// let warrior = parser.parse("add #${STEP}, 3").unwrap();
+    /// assert_eq!(warrior.program.instructions[0].to_string(), "ADD.AB  #4,     $3");
+    /// assert!(parser.parse("add #${STEPS}, 3").diagnostics()[0]
+    ///     .message
+    ///     .contains("STEPS"));
+    /// ```
+    pub fn variables<F>(mut self, lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + 'static,
+    {
+        self.variables = Variables::new(lookup);
+        self
+    }
+
+    /// Replace each `${NAME}` in the input with the environment variable
+    /// `NAME`, like [`variables`](Self::variables).
+    pub fn environment(self) -> Self {
+        self.variables(|name| std::env::var(name).ok())
+    }
+
+    /// Set the limits on how far `FOR` loops may expand, e.g. to accept
+    /// larger programs or to be stricter with untrusted input.
+    ///
+    /// ```
+    /// use corewars_parser::{Error, ExpansionLimits, Parser, Result};
+    ///
+    /// let parser = Parser::new().limits(ExpansionLimits {
+    ///     max_for_depth: 1,
+    ///     ..ExpansionLimits::default()
+    /// });
+    ///
+    /// let result = parser.parse("for 2\nfor 2\nnop 0, 0\nrof\nrof");
+    /// assert!(matches!(result, Result::Err(Error::ForNestingTooDeep { .. }, _)));
+    /// ```
+    pub fn limits(mut self, limits: ExpansionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Stop parsing with [`Error::Cancelled`] once `token` is cancelled, e.g.
+    /// from another thread when a user gives up on a slow program. This
+    /// replaces the token of the [`limits`](Self::limits).
+    ///
+    /// ```
+    /// use corewars_core::CancellationToken;
+    /// use corewars_parser::{Error, Parser, Result};
+    ///
+    /// let token = CancellationToken::new();
+    /// let parser = Parser::new().cancellation(token.clone());
+    ///
+    /// token.cancel();
+    /// let result = parser.parse("for 1000\nnop 0, 0\nrof");
+    /// assert!(matches!(result, Result::Err(Error::Cancelled, _)));
+    /// ```
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.limits.cancellation = token;
+        self
+    }
+
+    /// Choose the modifier of instructions written without one with
+    /// `modifiers`, e.g. to match another MARS.
+    ///
+    /// ```
+    /// use corewars_parser::Parser;
+    /// use corewars_core::load_file::ModifierPolicy;
+    ///
+    /// let parser = Parser::new().modifiers(ModifierPolicy::Icws88);
+    /// let warrior = parser.parse("cmp 1, #2").unwrap();
+    /// assert_eq!(warrior.program.instructions[0].to_string(), "CMP.I   $1,     #2");
+    /// ```
+    pub fn modifiers(mut self, modifiers: ModifierPolicy) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    /// Run only the preprocessing phases on a given input string, producing
+    /// standard Redcode which other assemblers can read. Labels, EQU, FOR
+    /// and expressions are all resolved, but omitted modifiers and address
+    /// modes are not filled in.
+    pub fn preprocess(&self, input: &str) -> Result<String> {
+        let mut warnings = Vec::new();
+        let result = self.preprocess_impl(input, &mut warnings);
+        Result::with_warnings(result, warnings)
+    }
+
+    fn preprocess_impl(
+        &self,
+        input: &str,
+        warnings: &mut Vec<Warning>,
+    ) -> std::result::Result<String, Error> {
+        self.expand(input, &mut PerfStats::new(), warnings)?
+            .preprocessed()
+    }
+
+    /// Assemble a given input string into load file format, keeping the
+    /// comment at the end of each line of the input as a trailing comment on
+    /// the instruction it produced. Without any such comments, this is the
+    /// same as printing the parsed [`Warrior`](Warrior).
+    ///
+    /// ```
+    /// let input = "start mov 0, 1 ; the imp\n      jmp start";
+    /// let output = corewars_parser::Parser::new().annotated(input).unwrap();
+    /// assert_eq!(output, "MOV.I   $0,     $1  ; the imp\nJMP.B   $-1,    $0");
+    /// ```
+    pub fn annotated(&self, input: &str) -> Result<String> {
+        let mut warnings = Vec::new();
+        let result = self.annotated_impl(input, &mut warnings);
+        Result::with_warnings(result, warnings)
+    }
+
+    fn annotated_impl(
+        &self,
+        input: &str,
+        warnings: &mut Vec<Warning>,
+    ) -> std::result::Result<String, Error> {
+        self.expand(input, &mut PerfStats::new(), warnings)?
+            .annotated(self.modifiers)
+    }
+
+    /// Assemble a given input string into a listing of each line alongside
+    /// the offsets and resolved operands of the instructions it produced.
+    pub fn listing(&self, input: &str) -> Result<Listing> {
+        let mut warnings = Vec::new();
+        let result = self.listing_impl(input, &mut warnings);
+        Result::with_warnings(result, warnings)
+    }
+
+    fn listing_impl(
+        &self,
+        input: &str,
+        warnings: &mut Vec<Warning>,
+    ) -> std::result::Result<Listing, Error> {
+        self.expand(input, &mut PerfStats::new(), warnings)?
+            .listing(self.modifiers)
+    }
+
+    /// Parse a given input string, like [`parse`](parse).
+    pub fn parse(&self, input: &str) -> Result<Warrior> {
+        self.parse_with_stats(input).0
+    }
+
+    /// Parse every warrior in an [`archive`](archive), split with
+    /// [`split_archive`](split_archive). Line numbers in errors and warnings
+    /// count from the start of the archive. Parsing stops at the first
+    /// warrior with an error.
+    ///
+    /// ```
+    /// let imp = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+    /// let dwarf = corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat 0, 0").unwrap();
+    ///
+    /// let archive = corewars_parser::archive(&[imp, dwarf]);
+    /// let warriors = corewars_parser::parse_archive(&archive).unwrap();
+    /// assert_eq!(warriors[1].metadata.name.as_deref(), Some("Dwarf"));
+    ///
+    /// let diagnostics = corewars_parser::parse_archive(&format!("{}\nmov 0", archive)).diagnostics();
+    /// assert!(diagnostics[0].to_string().ends_with("(line 12, column 1)"));
+    /// ```
+    pub fn parse_archive(&self, input: &str) -> Result<Vec<Warrior>> {
+        let mut warriors = Vec::new();
+        let mut warnings = Vec::new();
+        let mut line_offset = 0;
+
+        for source in split_archive(input) {
+            match self.parse(source) {
+                Result::Ok(warrior, source_warnings) => {
+                    warnings.extend(source_warnings.into_iter().map(|w| w.offset(line_offset)));
+                    warriors.push(warrior);
+                }
+                Result::Err(error, source_warnings) => {
+                    warnings.extend(source_warnings.into_iter().map(|w| w.offset(line_offset)));
+                    let error = match error.span().map(|span| span.line) {
+                        Some(line) if line > 0 => error.relocated(line + line_offset),
+                        _ => error,
+                    };
+                    return Result::Err(error, warnings);
+                }
+            }
+            line_offset += source.lines().count();
+        }
+
+        Result::Ok(warriors, warnings)
+    }
+
+    /// Assemble a warrior from several modules, each given as its name (e.g.
+    /// a file name) and source. Each module's labels and `EQU` definitions
+    /// are private to it, unless it lists them in an `EXPORT` line. Other
+    /// modules must then list the labels they use in an `IMPORT` line.
+    ///
+    /// Modules are placed one after another in the order they're given, with
+    /// `EQU` definitions moved ahead of them. The warrior's metadata and
+    /// origin come from the first module. Line numbers in errors count from
+    /// the start of the first module, as if the modules were one file.
+    ///
+    /// ```
+    /// let main = "
+    /// ;name Linked dwarf
+    /// IMPORT bomb, step
+    /// start   add #step, bomb
+    ///         mov bomb, @bomb
+    ///         jmp start
+    /// ";
+    /// let bomber = "
+    /// EXPORT bomb, step
+    /// step    equ 4
+    /// start   ; private, so it doesn't clash with main's start
+    /// bomb    dat #0, #0
+    /// ";
+    ///
+    /// let parser = corewars_parser::Parser::new();
+    /// let warrior = parser.link(&[("main.red", main), ("bomber.red", bomber)]).unwrap();
+    /// assert_eq!(warrior.metadata.name.as_deref(), Some("Linked dwarf"));
+    /// assert_eq!(warrior.program.instructions[0].to_string(), "ADD.AB  #4,     $3");
+    /// ```
+    pub fn link(&self, modules: &[Module]) -> Result<Warrior> {
+        let mut warnings = Vec::new();
+        let result = self.link_impl(modules, &mut warnings);
+        Result::with_warnings(result, warnings)
+    }
+
+    fn link_impl(
+        &self,
+        modules: &[Module],
+        warnings: &mut Vec<Warning>,
+    ) -> std::result::Result<Warrior, Error> {
+        let buffer = combined_buffer(modules);
+        let mut linked = Phase::<CommentsRemoved>::link(modules, &buffer)?;
+        warnings.extend(linked.take_warnings());
+        linked.substitute_variables(&self.variables)?;
+        linked.expand_directives(&self.directives)?;
+
+        let mut expanded = linked.expand(&self.limits)?;
+        warnings.extend(expanded.take_warnings());
+        let evaluated = expanded.evaluate(self.modifiers)?;
+        Ok(Phase::<Output>::from(evaluated).state.warrior)
+    }
+
+    /// Parse a given input string, also returning how long each phase of the
+    /// parser took and roughly how much memory it used.
+    ///
+    /// ```
+    /// let (result, stats) = corewars_parser::Parser::new().parse_with_stats("mov 0, 1");
+    /// assert_eq!(result.unwrap().len(), 1);
+    /// assert_eq!(stats.phases.last().unwrap().name, "output");
+    /// ```
+    pub fn parse_with_stats(&self, input: &str) -> (Result<Warrior>, PerfStats) {
+        let mut stats = PerfStats::new();
+        let mut warnings = Vec::new();
+        let result = self.parse_impl(input, &mut stats, &mut warnings);
+        (Result::with_warnings(result, warnings), stats)
+    }
+
+    fn parse_impl(
+        &self,
+        input: &str,
+        stats: &mut PerfStats,
+        warnings: &mut Vec<Warning>,
+    ) -> std::result::Result<Warrior, Error> {
+        let expanded = self.expand(input, stats, warnings)?;
+
+        let evaluated = stats.time(
+            "evaluate",
+            || expanded.evaluate(self.modifiers),
+            |result| result.as_ref().map_or(0, Phase::bytes),
+        )?;
+
+        let output = stats.time("output", || Phase::<Output>::from(evaluated), Phase::bytes);
+
+        Ok(output.state.warrior)
+    }
 
-    let cleaned = Phase::<CommentsRemoved>::from(raw);
+    /// Run the phases up to expansion, adding their warnings to `warnings`.
+    fn expand<'a>(
+        &self,
+        input: &'a str,
+        stats: &mut PerfStats,
+        warnings: &mut Vec<Warning>,
+    ) -> std::result::Result<Phase<'a, Expanded<'a>>, Error> {
+        let raw = stats.time("read", || Phase::<Raw>::from(input), Phase::bytes);
 
-    let expanded = Phase::<Expanded>::from(cleaned);
+        let mut cleaned = stats.time(
+            "comments",
+            || Phase::<CommentsRemoved>::from(raw),
+            Phase::bytes,
+        );
+        warnings.extend(cleaned.take_warnings());
+        cleaned.substitute_variables(&self.variables)?;
 
-    let evaluated = Phase::<Evaluated>::try_from(expanded)?;
+        let cleaned = stats.time(
+            "directives",
+            || {
+                let mut cleaned = cleaned;
+                cleaned
+                    .expand_directives(&self.directives)
+                    .map(|()| cleaned)
+            },
+            |result| result.as_ref().map_or(0, Phase::bytes),
+        )?;
 
-    let output = Phase::<Output>::from(evaluated);
+        let mut expanded = stats.time(
+            "expand",
+            || cleaned.expand(&self.limits),
+            |result| result.as_ref().map_or(0, Phase::bytes),
+        )?;
+        warnings.extend(expanded.take_warnings());
 
-    Ok(output.state.warrior)
+        Ok(expanded)
+    }
 }
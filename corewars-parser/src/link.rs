@@ -0,0 +1,398 @@
+//! Linking a warrior from several source files ("modules"). Each module's
+//! labels and `EQU` definitions are private to it, unless it lists them in an
+//! `EXPORT` line. Other modules must then list the labels they use in an
+//! `IMPORT` line:
+//!
+//! ```redcode
+//! ; bomber.red
+//! EXPORT bomb, step
+//! step    EQU 4
+//! bomb    dat #0, #0
+//!
+//! ; main.red
+//! IMPORT bomb, step
+//! start   add #step, bomb
+//! ```
+//!
+//! Modules are placed one after another in the order they're given, so the
+//! first module's code is loaded first. `EQU` definitions are moved ahead of
+//! all code, so a module can use constants from modules after it. The
+//! metadata, origin and `PIN` of the warrior come from the first module.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use corewars_core::load_file::{Opcode, PseudoOpcode};
+
+use super::error::{Error, Warning};
+use super::grammar::{self, Rule};
+use super::phase::{CommentsRemoved, Line, Phase, Raw};
+
+/// The name and source text of a module to link.
+pub type Module<'a> = (&'a str, &'a str);
+
+/// The modules joined into a single input, which line numbers in errors from
+/// [`Parser::link`](crate::Parser::link) refer to.
+pub fn combined_buffer(modules: &[Module]) -> String {
+    let mut buffer = String::new();
+    for (_, source) in modules {
+        buffer.push_str(source);
+        if !source.ends_with('\n') {
+            buffer.push('\n');
+        }
+    }
+    buffer
+}
+
+/// A module after its comments were removed, along with its `EXPORT` and
+/// `IMPORT` lines.
+struct Parsed<'a> {
+    name: &'a str,
+    line_count: usize,
+    state: CommentsRemoved<'a>,
+    exports: Vec<String>,
+    imports: Vec<String>,
+    declared: HashSet<String>,
+    warnings: Vec<Warning>,
+}
+
+/// Combine the modules into a single program, renaming each module's
+/// private labels so they can't clash with those of other modules. Also
+/// returns any warnings from removing the comments of each module.
+pub(crate) fn link<'a>(
+    modules: &[Module<'a>],
+) -> Result<(CommentsRemoved<'a>, Vec<Warning>), Error> {
+    let mut parsed: Vec<Parsed> = modules.iter().map(parse_module).collect();
+
+    // Which module exports each label
+    let mut exporters: HashMap<&str, &str> = HashMap::new();
+    for module in &parsed {
+        for label in &module.exports {
+            if !module.declared.contains(label) {
+                return Err(Error::UndeclaredExport {
+                    label: label.clone(),
+                    module: module.name.to_string(),
+                });
+            }
+            if let Some(first) = exporters.insert(label, module.name) {
+                return Err(Error::DuplicateExport {
+                    label: label.clone(),
+                    modules: (first.to_string(), module.name.to_string()),
+                });
+            }
+        }
+    }
+
+    for module in &parsed {
+        for label in &module.imports {
+            if !exporters.contains_key(label.as_str()) {
+                return Err(Error::UnresolvedImport {
+                    label: label.clone(),
+                    module: module.name.to_string(),
+                });
+            }
+        }
+
+        // Using another module's export without importing it is probably a
+        // mistake, so it's an error rather than silently resolving
+        for line in &module.state.lines {
+            for (_, identifier) in identifiers(line) {
+                if module.declared.contains(identifier)
+                    || module.imports.iter().any(|l| l == identifier)
+                {
+                    continue;
+                }
+                if let Some(exporter) = exporters.get(identifier) {
+                    return Err(Error::NotImported {
+                        label: identifier.to_string(),
+                        module: module.name.to_string(),
+                        exporter: exporter.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    let exported: HashSet<String> = exporters.keys().map(|label| label.to_string()).collect();
+
+    let mut linked = CommentsRemoved::default();
+    let mut warnings = Vec::new();
+    let mut definitions = Vec::new();
+    let mut definition_lines = Vec::new();
+    let mut line_offset = 0;
+
+    for (index, module) in parsed.iter_mut().enumerate() {
+        let private: HashSet<&String> = module
+            .declared
+            .iter()
+            .filter(|label| !exported.contains(*label))
+            .collect();
+        let rename = |text: Line<'a>| mangle(text, index, &private);
+
+        if index == 0 {
+            linked.metadata = std::mem::take(&mut module.state.metadata);
+            linked.origin = module.state.origin.take().map(rename);
+            linked.origin_line = module.state.origin_line;
+            linked.pin = module.state.pin.take();
+            linked.pin_line = module.state.pin_line;
+        }
+
+        let lines = module.state.lines.drain(..);
+        for (line, source_line) in lines.zip(&module.state.source_lines) {
+            let (lines, source_lines) = if is_definition(&line) {
+                (&mut definitions, &mut definition_lines)
+            } else {
+                (&mut linked.lines, &mut linked.source_lines)
+            };
+            lines.push(rename(line));
+            source_lines.push(source_line + line_offset);
+        }
+
+        warnings.extend(
+            module
+                .warnings
+                .drain(..)
+                .map(|warning| warning.offset(line_offset)),
+        );
+        line_offset += module.line_count;
+    }
+
+    definitions.append(&mut linked.lines);
+    definition_lines.append(&mut linked.source_lines);
+    linked.lines = definitions;
+    linked.source_lines = definition_lines;
+
+    Ok((linked, warnings))
+}
+
+fn parse_module<'a>(&(name, source): &Module<'a>) -> Parsed<'a> {
+    let mut phase = Phase::<CommentsRemoved>::from(Phase::<Raw>::from(source));
+    let warnings = phase.take_warnings();
+    let mut state = phase.state;
+    let mut exports = Vec::new();
+    let mut imports = Vec::new();
+    let mut lines = Vec::with_capacity(state.lines.len());
+    let mut source_lines = Vec::with_capacity(state.source_lines.len());
+
+    for (line, source_line) in state.lines.drain(..).zip(state.source_lines.drain(..)) {
+        match parse_visibility(&line) {
+            Some((true, labels)) => exports.extend(labels),
+            Some((false, labels)) => imports.extend(labels),
+            None => {
+                lines.push(line);
+                source_lines.push(source_line);
+            }
+        }
+    }
+
+    state.lines = lines;
+    state.source_lines = source_lines;
+
+    let declared = state
+        .lines
+        .iter()
+        .flat_map(|line| declared_labels(line))
+        .collect();
+
+    Parsed {
+        name,
+        line_count: source.lines().count(),
+        state,
+        exports,
+        imports,
+        declared,
+        warnings,
+    }
+}
+
+/// Parse an `EXPORT` or `IMPORT` line, returning whether it exports, and the
+/// labels it lists. A line is only treated as one if every item after the
+/// keyword is a label, so a label named e.g. `export` can still be used.
+fn parse_visibility(line: &str) -> Option<(bool, Vec<String>)> {
+    let trimmed = line.trim_start();
+    let keyword_end = trimmed
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(trimmed.len());
+
+    let is_export = match trimmed[..keyword_end].to_uppercase().as_str() {
+        "EXPORT" => true,
+        "IMPORT" => false,
+        _ => return None,
+    };
+
+    let labels: Vec<String> = trimmed[keyword_end..]
+        .split(',')
+        .map(|label| label.trim().to_string())
+        .collect();
+
+    if labels.iter().all(|label| is_identifier(label)) {
+        Some((is_export, labels))
+    } else {
+        None
+    }
+}
+
+/// The labels declared at the start of `line`, including `EQU` definitions
+/// and `FOR` index labels.
+pub(crate) fn declared_labels(line: &str) -> Vec<String> {
+    let tokens = grammar::tokenize(line);
+
+    if tokens.is_empty() {
+        // Lines which don't parse yet, e.g. using `&` concatenation, can
+        // still declare a label before their opcode
+        return line
+            .split_whitespace()
+            .next()
+            .filter(|word| is_identifier(word) && !is_opcode(word))
+            .map(|word| vec![word.to_string()])
+            .unwrap_or_default();
+    }
+
+    tokens
+        .iter()
+        .take_while(|token| token.as_rule() == Rule::Label)
+        .map(|token| token.as_str().to_string())
+        .collect()
+}
+
+/// Whether `line` is (part of) an `EQU` definition.
+fn is_definition(line: &str) -> bool {
+    grammar::tokenize(line)
+        .iter()
+        .any(|token| token.as_rule() == Rule::Substitution)
+}
+
+pub(crate) fn is_opcode(word: &str) -> bool {
+    let word = word.to_uppercase();
+    let name = word.split('.').next().unwrap_or_default();
+    name.parse::<Opcode>().is_ok() || name.parse::<PseudoOpcode>().is_ok()
+}
+
+pub(crate) fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Every identifier in `line`, with its byte offset.
+pub(crate) fn identifiers(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut start = None;
+    let mut found = Vec::new();
+
+    for (i, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        let continues = c.is_ascii_alphanumeric() || c == '_';
+        match start {
+            Some(begin) if !continues => {
+                found.push((begin, &line[begin..i]));
+                start = None;
+            }
+            None if c.is_ascii_alphabetic() || c == '_' => {
+                // Letters directly after a number aren't an identifier
+                let after_digit = line[..i]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_ascii_digit());
+                if !after_digit {
+                    start = Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    found.into_iter()
+}
+
+/// Rename the `private` labels in `line`, which came from the module with
+/// the given index. Lines without any are returned as they are.
+fn mangle<'a>(line: Line<'a>, module: usize, private: &HashSet<&String>) -> Line<'a> {
+    let mut renamed = String::new();
+    let mut end = 0;
+
+    for (start, identifier) in identifiers(&line) {
+        if private.iter().any(|label| label.as_str() == identifier) {
+            renamed.push_str(&line[end..start]);
+            renamed.push_str(&format!("__{}_{}", module, identifier));
+            end = start + identifier.len();
+        }
+    }
+
+    if end == 0 {
+        return line;
+    }
+    renamed.push_str(&line[end..]);
+    Cow::Owned(renamed)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn finds_identifiers() {
+        let found: Vec<&str> = identifiers("loop_1 add.ab #4, bomb+CORESIZE-1")
+            .map(|(_, id)| id)
+            .collect();
+        assert_eq!(found, vec!["loop_1", "add", "ab", "bomb", "CORESIZE"]);
+
+        assert_eq!(
+            parse_visibility("export bomb, step"),
+            Some((true, vec!["bomb".to_string(), "step".to_string()]))
+        );
+        assert_eq!(parse_visibility("export mov 0, 1"), None);
+        assert_eq!(declared_labels("a b mov 0, 1"), vec!["a", "b"]);
+        assert!(is_definition("step equ 4"));
+        assert!(!is_definition("step mov 0, 1"));
+    }
+
+    #[test]
+    fn checks_visibility() {
+        let error = |modules: &[Module]| link(modules).unwrap_err();
+
+        assert_eq!(
+            error(&[("a", "IMPORT bomb\njmp bomb"), ("b", "dat 0, 0")]),
+            Error::UnresolvedImport {
+                label: "bomb".into(),
+                module: "a".into(),
+            }
+        );
+        assert_eq!(
+            error(&[("a", "EXPORT bomb\njmp 0")]),
+            Error::UndeclaredExport {
+                label: "bomb".into(),
+                module: "a".into(),
+            }
+        );
+        assert_eq!(
+            error(&[
+                ("a", "EXPORT bomb\nbomb dat 0, 0"),
+                ("b", "EXPORT bomb\nbomb dat 1, 1")
+            ]),
+            Error::DuplicateExport {
+                label: "bomb".into(),
+                modules: ("a".into(), "b".into()),
+            }
+        );
+        assert_eq!(
+            error(&[("a", "jmp bomb"), ("b", "EXPORT bomb\nbomb dat 0, 0")]),
+            Error::NotImported {
+                label: "bomb".into(),
+                module: "a".into(),
+                exporter: "b".into(),
+            }
+        );
+
+        let (linked, _) = link(&[("a", "loop jmp loop"), ("b", "loop jmp loop")]).unwrap();
+        assert_eq!(
+            linked.lines,
+            vec!["__0_loop jmp __0_loop", "__1_loop jmp __1_loop"]
+        );
+    }
+}
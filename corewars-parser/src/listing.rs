@@ -0,0 +1,157 @@
+//! Assembler listings, showing each line of the input alongside the
+//! instructions it assembled to, for checking label arithmetic and macro
+//! expansion.
+
+use std::fmt;
+
+use corewars_core::load_file::Instruction;
+use corewars_core::text;
+
+/// A single line of the input, and the instructions assembled from it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingLine {
+    /// The line number in the input, starting from 1
+    pub line: usize,
+
+    /// The text of the line, as written but with tabs expanded to spaces
+    pub source: String,
+
+    /// The offset of each instruction from the start of the program, along
+    /// with the instruction after labels and expressions were resolved
+    pub instructions: Vec<(u32, Instruction)>,
+}
+
+/// A listing of every line of the input.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    /// Build a listing of `buffer`, where each instruction in `instructions`
+    /// came from the line in the matching entry of `source_lines`.
+    pub(crate) fn new(
+        buffer: &str,
+        instructions: Vec<Instruction>,
+        source_lines: &[usize],
+    ) -> Self {
+        let mut lines: Vec<ListingLine> = buffer
+            .lines()
+            .enumerate()
+            .map(|(i, source)| ListingLine {
+                line: i + 1,
+                // Tabs are expanded so the source lines up however it's printed
+                source: text::expand_tabs(source),
+                instructions: Vec::new(),
+            })
+            .collect();
+
+        for (offset, (instruction, &line)) in instructions.into_iter().zip(source_lines).enumerate()
+        {
+            if let Some(listing_line) = lines.get_mut(line.wrapping_sub(1)) {
+                listing_line.instructions.push((offset as u32, instruction));
+            }
+        }
+
+        Self { lines }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{:>5}  {:<8}{:<24}source",
+            "line", "offset", "instruction"
+        )?;
+
+        for line in &self.lines {
+            let mut instructions = line.instructions.iter();
+            let first = instructions.next();
+
+            let row = match first {
+                Some((offset, instruction)) => format!(
+                    "{:>5}  {:0>5}   {:<24}{}",
+                    line.line,
+                    offset,
+                    instruction.to_string(),
+                    line.source
+                ),
+                None => format!("{:>5}  {:<8}{:<24}{}", line.line, "", "", line.source),
+            };
+            write!(formatter, "\n{}", row.trim_end())?;
+
+            // Lines which expanded to several instructions, e.g. FOR loops,
+            // list the rest without repeating the source
+            for (offset, instruction) in instructions {
+                write!(formatter, "\n{:>5}  {:0>5}   {}", "", offset, instruction)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn lists_source_lines() {
+        let input = "\
+;name Twice
+start   add #4, bomb
+        for 2
+        mov 0, start
+        rof
+bomb    dat #0, #0
+";
+        let listing = crate::listing(input).unwrap();
+
+        let offsets: Vec<(usize, Vec<u32>)> = listing
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.line,
+                    line.instructions
+                        .iter()
+                        .map(|(offset, _)| *offset)
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                (1, vec![]),
+                (2, vec![0]),
+                (3, vec![]),
+                (4, vec![1, 2]),
+                (5, vec![]),
+                (6, vec![3]),
+            ]
+        );
+
+        let text = listing.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], " line  offset  instruction             source");
+        assert_eq!(
+            lines[1],
+            "    1                                  ;name Twice"
+        );
+        assert_eq!(
+            lines[2],
+            "    2  00000   ADD.AB  #4,     $3      start   add #4, bomb"
+        );
+        assert_eq!(
+            lines[4],
+            "    4  00001   MOV.I   $0,     $-1             mov 0, start"
+        );
+        assert_eq!(lines[5], "       00002   MOV.I   $0,     $-2");
+
+        let listing = crate::listing(";author José\nstart\tjmp\tstart").unwrap();
+        assert_eq!(listing.lines[0].source, ";author José");
+        assert_eq!(listing.lines[1].source, "start   jmp     start");
+    }
+}
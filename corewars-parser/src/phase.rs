@@ -1,33 +1,86 @@
 //! This module defines the parser state machine. Each phase of the parser
 //! is a submodule within this module.
 
+use std::borrow::Cow;
 use std::convert::TryFrom;
 
 mod comment;
 mod evaluation;
 mod expansion;
 
+pub use comment::ends_program;
+pub use evaluation::{evaluate_standalone, Accessor};
+pub use expansion::ExpansionLimits;
+
 use corewars_core::load_file;
 
-use super::error::Error;
+use super::directive::Directives;
+use super::error::{Error, Warning};
+use super::link::{self, Module};
+use super::listing::Listing;
+use super::variables::Variables;
 
 /// The data type that is passed through the parser phases. This is a simple state
 /// machine, which transitions to the next state by passing through a parser phase.
 #[derive(Debug)]
-pub struct Phase<PhaseState> {
+pub struct Phase<'a, PhaseState> {
     /// The original input to the parser, which can be used for spans / string views
-    buffer: String,
+    buffer: &'a str,
+    /// Warnings from this and any previous phases
+    warnings: Vec<Warning>,
     /// State specific to the current phase of the state machine
     pub state: PhaseState,
 }
 
+impl<PhaseState> Phase<'_, PhaseState> {
+    /// Take the warnings produced by the phases so far.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+impl<PhaseState: StateSize> Phase<'_, PhaseState> {
+    /// The approximate memory held by this phase, in bytes.
+    pub fn bytes(&self) -> usize {
+        self.buffer.len() + self.state.bytes()
+    }
+}
+
+/// Estimating the memory used by the state of a phase, for
+/// [`PerfStats`](corewars_core::perf::PerfStats).
+pub trait StateSize {
+    fn bytes(&self) -> usize;
+}
+
+/// A line of text in one of the phases, which borrows from the input until a
+/// phase changes it, e.g. by substituting a label.
+pub type Line<'a> = Cow<'a, str>;
+
+/// The memory held by `lines`, not counting any still borrowed from the input.
+fn text_bytes(lines: &[Line]) -> usize {
+    lines
+        .iter()
+        .map(|line| match line {
+            Cow::Borrowed(_) => 0,
+            Cow::Owned(line) => line.len(),
+        })
+        .sum()
+}
+
 /// The initial state of parsing, before any preprocessing has occurred.
 pub struct Raw;
 
-impl From<&str> for Phase<Raw> {
-    fn from(buf: &str) -> Self {
+impl StateSize for Raw {
+    fn bytes(&self) -> usize {
+        0
+    }
+}
+
+impl<'a> From<&'a str> for Phase<'a, Raw> {
+    fn from(buf: &'a str) -> Self {
         Phase {
-            buffer: buf.to_string(),
+            buffer: buf,
+            warnings: Vec::new(),
             state: Raw,
         }
     }
@@ -36,49 +89,357 @@ impl From<&str> for Phase<Raw> {
 /// The Phase after comments have been removed and metadata parsed from comments.
 /// This phase also parses ORG and END, and removes any text after END
 #[derive(Debug, Default, PartialEq)]
-pub struct CommentsRemoved {
-    pub lines: Vec<String>,
+pub struct CommentsRemoved<'a> {
+    pub lines: Vec<Line<'a>>,
+    /// The line number in the original input of each entry in `lines`
+    pub source_lines: Vec<usize>,
     pub metadata: load_file::Metadata,
-    pub origin: Option<String>,
+    pub origin: Option<Line<'a>>,
+    /// The line number in the original input of the `ORG` or `END` which
+    /// set `origin`
+    pub origin_line: Option<usize>,
+    /// The expression given to `PIN`, identifying the warrior's P-space
+    pub pin: Option<Line<'a>>,
+    /// The line number in the original input of the `PIN`
+    pub pin_line: Option<usize>,
 }
 
-impl From<Phase<Raw>> for Phase<CommentsRemoved> {
-    fn from(prev: Phase<Raw>) -> Self {
-        let state = comment::extract_from_string(&prev.buffer);
+impl StateSize for CommentsRemoved<'_> {
+    fn bytes(&self) -> usize {
+        text_bytes(&self.lines)
+    }
+}
+
+impl<'a> From<Phase<'a, Raw>> for Phase<'a, CommentsRemoved<'a>> {
+    fn from(mut prev: Phase<'a, Raw>) -> Self {
+        let state = comment::extract_from_string(prev.buffer, &mut prev.warnings);
         Self {
             buffer: prev.buffer,
+            warnings: prev.warnings,
             state,
         }
     }
 }
 
+impl<'a> Phase<'a, CommentsRemoved<'a>> {
+    /// Link several modules into a single program, with the comments of each
+    /// removed. `buffer` is the [combined](link::combined_buffer) input of
+    /// the modules.
+    pub fn link(modules: &[Module<'a>], buffer: &'a str) -> Result<Self, Error> {
+        let (state, warnings) = link::link(modules)?;
+        Ok(Self {
+            buffer,
+            warnings,
+            state,
+        })
+    }
+
+    /// Replace any `${NAME}` variables with their values.
+    pub fn substitute_variables(&mut self, variables: &Variables) -> Result<(), Error> {
+        variables.substitute(&mut self.state, self.buffer)
+    }
+
+    /// Replace any custom directives with the lines produced by their handlers.
+    pub fn expand_directives(&mut self, directives: &Directives) -> Result<(), Error> {
+        directives.expand(&mut self.state, self.buffer)
+    }
+
+    /// Read the lines of this phase as a load file, e.g. one written by
+    /// another assembler. This skips expansion, since load files have no
+    /// labels, macros or expressions.
+    pub fn load(self) -> Result<Phase<'a, Evaluated>, Error> {
+        let instructions = evaluation::evaluate_load_file(
+            &self.state.lines,
+            &self.state.source_lines,
+            self.buffer,
+        )?;
+
+        let origin = match &self.state.origin {
+            Some(origin) => Some(evaluation::evaluate_load_origin(origin).map_err(|err| {
+                match self.state.origin_line {
+                    Some(line) => err.locate(line, origin, self.buffer),
+                    None => err,
+                }
+            })?),
+            None => None,
+        };
+
+        let pin =
+            match &self.state.pin {
+                Some(pin) => Some(evaluation::evaluate_load_origin(pin).map_err(|err| {
+                    match self.state.pin_line {
+                        Some(line) => err.locate(line, pin, self.buffer),
+                        None => err,
+                    }
+                })?),
+                None => None,
+            };
+
+        let mut metadata = self.state.metadata;
+        metadata.pin = pin;
+
+        Ok(Phase {
+            buffer: self.buffer,
+            warnings: self.warnings,
+            state: Evaluated {
+                metadata,
+                program: load_file::Program {
+                    instructions,
+                    origin,
+                },
+            },
+        })
+    }
+
+    /// Expand the lines of this phase, with the given limits on how far
+    /// `FOR` loops may expand.
+    pub fn expand(mut self, limits: &ExpansionLimits) -> Result<Phase<'a, Expanded<'a>>, Error> {
+        let origin_line = self.state.origin_line;
+        let origin = self.state.origin.clone().unwrap_or_default();
+        let buffer = self.buffer;
+
+        // Errors in the lines are already located, so any others came from
+        // the origin
+        let lines = expansion::expand(
+            self.state.lines,
+            self.state.source_lines,
+            self.state.origin,
+            self.buffer,
+            limits,
+        )
+        .map_err(|err| match origin_line {
+            Some(line) => err.locate(line, &origin, buffer),
+            None => err,
+        })?;
+        self.warnings.extend(lines.warnings);
+
+        Ok(Phase {
+            buffer: self.buffer,
+            warnings: self.warnings,
+            state: Expanded {
+                lines: lines.text,
+                source_lines: lines.source_lines,
+                origin: lines.origin,
+                origin_line,
+                pin: self.state.pin,
+                pin_line: self.state.pin_line,
+                metadata: self.state.metadata,
+            },
+        })
+    }
+}
+
 /// The phase in which labels are collected and expanded. Resulting struct
 /// contains metadata from previous phase and the expanded lines
 #[derive(Debug, Default)]
-pub struct Expanded {
+pub struct Expanded<'a> {
     /// The expanded lines of text to be parsed later
-    lines: Vec<String>,
+    lines: Vec<Line<'a>>,
+
+    /// The line number in the original input each expanded line came from
+    source_lines: Vec<usize>,
 
     /// Metadata gathered in previous phase
     metadata: load_file::Metadata,
 
     /// The entrypoint to the program, gathered in previous phase. This is still
     /// a string because it may be an expression to be evaluated later
-    origin: Option<String>,
+    origin: Option<Line<'a>>,
+
+    /// The line number in the original input the origin was defined on
+    origin_line: Option<usize>,
+
+    /// The P-space identifier of the program, still to be evaluated
+    pin: Option<Line<'a>>,
+
+    /// The line number in the original input the `PIN` was defined on
+    pin_line: Option<usize>,
 }
 
-impl From<Phase<CommentsRemoved>> for Phase<Expanded> {
-    fn from(prev: Phase<CommentsRemoved>) -> Self {
-        let lines = expansion::expand(prev.state.lines, prev.state.origin);
+impl StateSize for Expanded<'_> {
+    fn bytes(&self) -> usize {
+        text_bytes(&self.lines)
+    }
+}
 
-        Self {
-            buffer: prev.buffer,
-            state: Expanded {
-                lines: lines.text,
-                origin: lines.origin,
-                metadata: prev.state.metadata,
+impl<'a> TryFrom<Phase<'a, CommentsRemoved<'a>>> for Phase<'a, Expanded<'a>> {
+    type Error = Error;
+
+    fn try_from(prev: Phase<'a, CommentsRemoved<'a>>) -> Result<Self, Error> {
+        prev.expand(&ExpansionLimits::default())
+    }
+}
+
+impl<'a> Phase<'a, Expanded<'a>> {
+    /// Evaluate the expanded lines into a program, choosing omitted
+    /// modifiers with `modifiers`. This is where the text of the lines is
+    /// finally turned into owned instructions.
+    pub fn evaluate(
+        self,
+        modifiers: load_file::ModifierPolicy,
+    ) -> Result<Phase<'a, Evaluated>, Error> {
+        let origin = self.evaluate_origin();
+        let pin = self.evaluate_pin();
+        let instructions = evaluation::evaluate(
+            &self.state.lines,
+            &self.state.source_lines,
+            self.buffer,
+            modifiers,
+        )?;
+        let origin = origin?;
+        let pin = pin?;
+
+        // TODO evaluate assertions
+
+        let mut metadata = self.state.metadata;
+        metadata.pin = pin;
+
+        Ok(Phase {
+            buffer: self.buffer,
+            warnings: self.warnings,
+            state: Evaluated {
+                metadata,
+                program: load_file::Program {
+                    instructions,
+                    origin,
+                },
             },
+        })
+    }
+
+    /// Render the expanded program as standard Redcode, with all expressions
+    /// evaluated but opcodes, modifiers and address modes kept as written.
+    pub fn preprocessed(&self) -> Result<String, Error> {
+        let lines = evaluation::evaluate_expressions(
+            &self.state.lines,
+            &self.state.source_lines,
+            self.buffer,
+        )?;
+
+        let origin = self.evaluate_origin()?;
+        let pin = self.evaluate_pin()?;
+
+        let mut output = self.state.metadata.to_string();
+
+        if let Some(origin) = origin {
+            output.push_str(&format!("{:<8}{}\n", load_file::PseudoOpcode::Org, origin));
+        }
+        if let Some(pin) = pin {
+            output.push_str(&format!("{:<8}{}\n", load_file::PseudoOpcode::Pin, pin));
+        }
+
+        for line in lines {
+            output.push_str(&line);
+            output.push('\n');
+        }
+
+        output.push_str(&load_file::PseudoOpcode::End.to_string());
+        Ok(output)
+    }
+
+    /// Render the program in load file format, like
+    /// [`Warrior`](load_file::Warrior)'s `Display`, but with the comment at
+    /// the end of each line of the input after the first instruction it
+    /// produced. The comments are aligned in a column after the longest
+    /// instruction.
+    pub fn annotated(&self, modifiers: load_file::ModifierPolicy) -> Result<String, Error> {
+        let instructions = evaluation::evaluate(
+            &self.state.lines,
+            &self.state.source_lines,
+            self.buffer,
+            modifiers,
+        )?;
+        let origin = self.evaluate_origin()?;
+        let pin = self.evaluate_pin()?;
+
+        let input: Vec<&str> = self.buffer.lines().collect();
+        let comment_on = |line: Option<usize>| {
+            line.and_then(|line| input.get(line.checked_sub(1)?))
+                .and_then(|line| comment::trailing_comment(line))
+        };
+
+        let mut lines: Vec<(String, Option<&str>)> = Vec::new();
+
+        if let Some(origin) = origin {
+            lines.push((
+                format!("{:<8}{}", load_file::PseudoOpcode::Org, origin),
+                comment_on(self.state.origin_line),
+            ));
+        }
+        if let Some(pin) = pin {
+            lines.push((
+                format!("{:<8}{}", load_file::PseudoOpcode::Pin, pin),
+                comment_on(self.state.pin_line),
+            ));
         }
+
+        let mut previous_line = None;
+        for (instruction, &line) in instructions.iter().zip(&self.state.source_lines) {
+            // Lines which expanded to several instructions only comment the first
+            let comment = Some(line)
+                .filter(|&line| previous_line != Some(line))
+                .and_then(|line| comment_on(Some(line)));
+            previous_line = Some(line);
+            lines.push((instruction.to_string(), comment));
+        }
+
+        let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        let rendered: Vec<String> = lines
+            .into_iter()
+            .map(|(line, comment)| match comment {
+                Some(comment) => format!("{:<width$}  ; {}", line, comment, width = width),
+                None => line,
+            })
+            .collect();
+
+        Ok(format!("{}{}", self.state.metadata, rendered.join("\n")))
+    }
+
+    /// Evaluate the origin of the program, if it has one.
+    fn evaluate_origin(&self) -> Result<Option<u32>, Error> {
+        let origin = match &self.state.origin {
+            Some(origin) => origin,
+            None => return Ok(None),
+        };
+
+        evaluation::evaluate_expression(origin)
+            .map(Some)
+            .map_err(|err| match self.state.origin_line {
+                Some(line) => err.locate(line, origin, self.buffer),
+                None => err,
+            })
+    }
+
+    /// Evaluate the P-space identifier of the program, if it has one.
+    fn evaluate_pin(&self) -> Result<Option<u32>, Error> {
+        let pin = match &self.state.pin {
+            Some(pin) => pin,
+            None => return Ok(None),
+        };
+
+        evaluation::evaluate_expression(pin)
+            .map(Some)
+            .map_err(|err| match self.state.pin_line {
+                Some(line) => err.locate(line, pin, self.buffer),
+                None => err,
+            })
+    }
+
+    /// List each line of the input alongside the instructions it was
+    /// expanded and evaluated into.
+    pub fn listing(&self, modifiers: load_file::ModifierPolicy) -> Result<Listing, Error> {
+        let instructions = evaluation::evaluate(
+            &self.state.lines,
+            &self.state.source_lines,
+            self.buffer,
+            modifiers,
+        )?;
+
+        Ok(Listing::new(
+            self.buffer,
+            instructions.into_vec(),
+            &self.state.source_lines,
+        ))
     }
 }
 
@@ -93,29 +454,17 @@ pub struct Evaluated {
     program: load_file::Program,
 }
 
-impl TryFrom<Phase<Expanded>> for Phase<Evaluated> {
-    type Error = Error;
-
-    fn try_from(prev: Phase<Expanded>) -> Result<Self, Error> {
-        let instructions = evaluation::evaluate(prev.state.lines)?;
-        let origin = prev
-            .state
-            .origin
-            .map(evaluation::evaluate_expression)
-            .transpose()?;
+impl StateSize for Evaluated {
+    fn bytes(&self) -> usize {
+        self.program.instructions.len() * std::mem::size_of::<load_file::Instruction>()
+    }
+}
 
-        // TODO evaluate assertions
+impl<'a> TryFrom<Phase<'a, Expanded<'a>>> for Phase<'a, Evaluated> {
+    type Error = Error;
 
-        Ok(Self {
-            buffer: prev.buffer,
-            state: Evaluated {
-                metadata: prev.state.metadata,
-                program: load_file::Program {
-                    instructions,
-                    origin,
-                },
-            },
-        })
+    fn try_from(prev: Phase<'a, Expanded<'a>>) -> Result<Self, Error> {
+        prev.evaluate(load_file::ModifierPolicy::default())
     }
 }
 
@@ -125,10 +474,17 @@ pub struct Output {
     pub warrior: load_file::Warrior,
 }
 
-impl From<Phase<Evaluated>> for Phase<Output> {
-    fn from(prev: Phase<Evaluated>) -> Self {
+impl StateSize for Output {
+    fn bytes(&self) -> usize {
+        self.warrior.program.instructions.len() * std::mem::size_of::<load_file::Instruction>()
+    }
+}
+
+impl<'a> From<Phase<'a, Evaluated>> for Phase<'a, Output> {
+    fn from(prev: Phase<'a, Evaluated>) -> Self {
         Self {
             buffer: prev.buffer,
+            warnings: prev.warnings,
             state: Output {
                 warrior: load_file::Warrior {
                     metadata: prev.state.metadata,
@@ -1,74 +1,138 @@
 //! In this phase, all comments are removed from the input phase.
 //! Any comments like `;redcode` and `;author` will be parsed and stored in
-//! load_file::Metadata. This phase also finds the origin and end of the program.
+//! load_file::Metadata. This phase also finds the origin and end of the program,
+//! and the `PIN` which identifies its P-space.
 
-use super::CommentsRemoved;
+use std::borrow::Cow;
+
+use super::{CommentsRemoved, Line};
 
 use corewars_core::load_file::Metadata;
 
+use crate::error::{Span, Warning};
 use crate::grammar;
 
-enum OriginInLine {
-    NewOrigin(String),
-    EndWithNewOrigin(String),
+enum OriginInLine<'a> {
+    NewOrigin(&'a str),
+    EndWithNewOrigin(&'a str),
     End,
+    Pin(&'a str),
     NotFound,
 }
 
+/// A pseudo-opcode found without the argument it needs.
+enum MissingArgument {
+    Origin,
+    Pin,
+}
+
 /// Parse a raw String input and return the output sans comments, with metadata.
-pub fn extract_from_string(input: &str) -> CommentsRemoved {
+/// The lines of the output borrow from `input`. Any problems found along the
+/// way are added to `warnings`.
+pub fn extract_from_string<'a>(input: &'a str, warnings: &mut Vec<Warning>) -> CommentsRemoved<'a> {
     let mut metadata = Metadata::default();
-    let mut origin: Option<String> = None;
+    let mut origin: Option<Line> = None;
+    let mut origin_line = None;
 
-    // Returns whether a new origin was set
-    let mut set_origin = |new_origin: String| {
+    let mut set_origin = |new_origin: &'a str, line: usize, warnings: &mut Vec<Warning>| {
         if let Some(old_origin) = origin.as_ref() {
-            // TODO (#25) proper warnings instead of just eprintln
-            eprintln!(
-                "Warning: ORG already defined as {:?}, new definition {:?} will be ignored",
-                new_origin, old_origin
-            );
+            warnings.push(Warning::OriginRedefinition {
+                old: old_origin.to_string(),
+                new: new_origin.to_string(),
+                span: Some(Span::of_line(line, input)),
+            });
         } else {
-            origin = Some(new_origin);
+            origin = Some(Cow::Borrowed(new_origin));
+            origin_line = Some(line);
         }
     };
 
-    let mut lines: Vec<String> = Vec::new();
+    let mut pin: Option<Line> = None;
+    let mut pin_line = None;
+
+    let mut lines: Vec<Line> = Vec::new();
+    let mut source_lines: Vec<usize> = Vec::new();
 
-    for line in input.lines() {
+    for (i, line) in input.lines().enumerate() {
         let trimmed_line = metadata.parse_line(line);
         if trimmed_line.is_empty() {
             continue;
         }
 
-        if let Ok(found_origin) = find_origin_in_line(&trimmed_line) {
-            match found_origin {
-                OriginInLine::NewOrigin(new_origin) => {
-                    set_origin(new_origin);
-                }
-                OriginInLine::EndWithNewOrigin(new_origin) => {
-                    set_origin(new_origin);
-                    break;
+        match find_origin_in_line(trimmed_line) {
+            Ok(OriginInLine::NewOrigin(new_origin)) => {
+                set_origin(new_origin, i + 1, warnings);
+            }
+            Ok(OriginInLine::EndWithNewOrigin(new_origin)) => {
+                set_origin(new_origin, i + 1, warnings);
+                break;
+            }
+            Ok(OriginInLine::End) => break,
+            Ok(OriginInLine::Pin(new_pin)) => match pin.as_ref() {
+                Some(old_pin) => warnings.push(Warning::PinRedefinition {
+                    old: old_pin.to_string(),
+                    new: new_pin.to_string(),
+                    span: Some(Span::of_line(i + 1, input)),
+                }),
+                None => {
+                    pin = Some(Cow::Borrowed(new_pin));
+                    pin_line = Some(i + 1);
                 }
-                OriginInLine::End => break,
-                OriginInLine::NotFound => lines.push(trimmed_line),
+            },
+            Ok(OriginInLine::NotFound) => {
+                lines.push(Cow::Borrowed(trimmed_line));
+                source_lines.push(i + 1);
             }
-        } else {
-            // TODO (#25) return error
+            Err(MissingArgument::Origin) => warnings.push(Warning::MissingOrigin {
+                span: Some(Span::of_line(i + 1, input)),
+            }),
+            Err(MissingArgument::Pin) => warnings.push(Warning::MissingPin {
+                span: Some(Span::of_line(i + 1, input)),
+            }),
         }
     }
 
     CommentsRemoved {
         lines,
+        source_lines,
         metadata,
         origin,
+        origin_line,
+        pin,
+        pin_line,
+    }
+}
+
+/// The comment at the end of a line of code, without its leading `;`. Lines
+/// which are only a comment, such as `;name`, have no trailing comment.
+pub fn trailing_comment(line: &str) -> Option<&str> {
+    let (code, comment) = line.split_at(line.find(';')?);
+    if code.trim().is_empty() {
+        return None;
     }
+
+    Some(comment[1..].trim()).filter(|comment| !comment.is_empty())
+}
+
+/// Whether `line` is an `END`, after which the rest of the input is ignored.
+pub fn ends_program(line: &str) -> bool {
+    let code = line.split(';').next().unwrap_or_default().trim();
+    !code.is_empty()
+        && matches!(
+            find_origin_in_line(code),
+            Ok(OriginInLine::End | OriginInLine::EndWithNewOrigin(_))
+        )
 }
 
-/// Find and return the origin defined in the given line.
-fn find_origin_in_line(line: &str) -> Result<OriginInLine, ()> {
+/// Find and return the origin (or `PIN`) defined in the given line.
+fn find_origin_in_line(line: &str) -> Result<OriginInLine<'_>, MissingArgument> {
     use OriginInLine::*;
 
+    // Without an origin, `END` would be read as a label
+    if line.trim().eq_ignore_ascii_case("END") {
+        return Ok(End);
+    }
+
     let tokenized = grammar::tokenize(line);
 
     if tokenized.is_empty() {
@@ -85,16 +149,21 @@ fn find_origin_in_line(line: &str) -> Result<OriginInLine, ()> {
             match tokenized[0].as_str().to_uppercase().as_str() {
                 "ORG" => {
                     if let Some(remainder) = remainder {
-                        Ok(NewOrigin(remainder.to_owned()))
+                        Ok(NewOrigin(remainder))
+                    } else {
+                        Err(MissingArgument::Origin)
+                    }
+                }
+                "PIN" => {
+                    if let Some(remainder) = remainder {
+                        Ok(Pin(remainder))
                     } else {
-                        // TODO (#25) proper error handling, probably in the return type
-                        eprintln!("Error: ORG must be given an argument!");
-                        Err(())
+                        Err(MissingArgument::Pin)
                     }
                 }
                 "END" => {
                     if let Some(remainder) = remainder {
-                        Ok(EndWithNewOrigin(remainder.to_owned()))
+                        Ok(EndWithNewOrigin(remainder))
                     } else {
                         Ok(End)
                     }
@@ -115,7 +184,7 @@ mod test {
 
     struct Param {
         input: &'static str,
-        expected: CommentsRemoved,
+        expected: CommentsRemoved<'static>,
     }
 
     #[test_case(
@@ -128,10 +197,11 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![
-                    "foo who".to_string(),
-                    "bar di bar".to_string(),
-                    "baz.".to_string(),
+                    "foo who".into(),
+                    "bar di bar".into(),
+                    "baz.".into(),
                 ],
+                source_lines: vec![2, 3, 4],
                 ..Default::default()
             }
         };
@@ -146,9 +216,10 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![
-                    "foo who".to_string(),
-                    "baz.".to_string(),
+                    "foo who".into(),
+                    "baz.".into(),
                 ],
+                source_lines: vec![1, 3],
                 ..Default::default()
             }
         };
@@ -164,7 +235,8 @@ mod test {
                 MOV 1, 1"
             ),
             expected: CommentsRemoved {
-                lines: vec!["MOV 1, 1".to_string()],
+                lines: vec!["MOV 1, 1".into()],
+                source_lines: vec![5],
                 metadata: Metadata {
                     redcode: Some("".to_string()),
                     name: Some("my-amazing-warrior".to_string()),
@@ -187,9 +259,11 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![
-                    "MOV 0, 1".to_string()
+                    "MOV 0, 1".into()
                 ],
-                origin: Some("5".to_string()),
+                source_lines: vec![3],
+                origin: Some("5".into()),
+                origin_line: Some(2),
                 ..Default::default()
             },
         };
@@ -206,9 +280,11 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![
-                    "lbl1 MOV 0, 1".to_string()
+                    "lbl1 MOV 0, 1".into()
                 ],
-                origin: Some("lbl1".to_string()),
+                source_lines: vec![3],
+                origin: Some("lbl1".into()),
+                origin_line: Some(2),
                 ..Default::default()
             },
         };
@@ -225,9 +301,11 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![
-                    "lbl1 MOV 0, 1".to_string()
+                    "lbl1 MOV 0, 1".into()
                 ],
-                origin: Some("lbl1 + 1".to_string()),
+                source_lines: vec![3],
+                origin: Some("lbl1 + 1".into()),
+                origin_line: Some(2),
                 ..Default::default()
             },
         };
@@ -243,7 +321,8 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![],
-                origin: Some("5".to_string()),
+                origin: Some("5".into()),
+                origin_line: Some(2),
                 ..Default::default()
             }
 
@@ -260,7 +339,8 @@ mod test {
             ),
             expected: CommentsRemoved {
                 lines: vec![],
-                origin: Some("5".to_string()),
+                origin: Some("5".into()),
+                origin_line: Some(2),
                 ..Default::default()
             }
         };
@@ -276,8 +356,10 @@ mod test {
                 "
             ),
             expected: CommentsRemoved {
-                lines: vec!["MOV 1, 1".to_string()],
-                origin: Some("2".to_string()),
+                lines: vec!["MOV 1, 1".into()],
+                source_lines: vec![2],
+                origin: Some("2".into()),
+                origin_line: Some(4),
                 ..Default::default()
             }
         };
@@ -294,13 +376,50 @@ mod test {
                 "
             ),
             expected: CommentsRemoved {
-                lines: vec!["MOV 1, 1".to_string()],
-                origin: Some("2".to_string()),
+                lines: vec!["MOV 1, 1".into()],
+                source_lines: vec![2],
+                origin: Some("2".into()),
+                origin_line: Some(3),
                 ..Default::default()
             }
         };
         "parse multiple END"
     )]
+    #[test_case(
+        Param {
+            input: dedent!(
+                "
+                MOV 1, 1
+                end
+                MOV 2, 2
+                "
+            ),
+            expected: CommentsRemoved {
+                lines: vec!["MOV 1, 1".into()],
+                source_lines: vec![2],
+                ..Default::default()
+            }
+        };
+        "parse END without origin"
+    )]
+    #[test_case(
+        Param {
+            input: dedent!(
+                "
+                PIN 7 * 2
+                MOV 0, 1
+                "
+            ),
+            expected: CommentsRemoved {
+                lines: vec!["MOV 0, 1".into()],
+                source_lines: vec![3],
+                pin: Some("7 * 2".into()),
+                pin_line: Some(2),
+                ..Default::default()
+            }
+        };
+        "parse PIN"
+    )]
     #[test_case(
         Param {
             input: dedent!(
@@ -313,7 +432,7 @@ mod test {
         "empty result"
     )]
     fn parse(param: Param) {
-        let result = extract_from_string(param.input);
+        let result = extract_from_string(param.input, &mut Vec::new());
 
         assert_eq!(result, param.expected);
     }
@@ -326,15 +445,89 @@ mod test {
                 "
             ),
             expected: CommentsRemoved {
-                lines: vec!["MOV 0, 1".to_string()],
+                lines: vec!["MOV 0, 1".into()],
+                source_lines: vec![3],
                 ..Default::default()
             }
         };
         "inconclusive(should error): parse ORG without arg"
     )]
     fn parse_error(param: Param) {
-        let result = extract_from_string(param.input);
+        let result = extract_from_string(param.input, &mut Vec::new());
 
         assert_eq!(result, param.expected);
     }
+
+    #[test]
+    fn warns_about_origin_redefinition() {
+        let mut warnings = Vec::new();
+        extract_from_string("ORG 5\n  org 2 ; ignored\n", &mut warnings);
+
+        assert_eq!(
+            warnings,
+            vec![Warning::OriginRedefinition {
+                old: "5".into(),
+                new: "2".into(),
+                span: Some(Span::new(2, 2, 7)),
+            }]
+        );
+    }
+
+    #[test]
+    fn warns_about_pin_redefinition() {
+        let mut warnings = Vec::new();
+        let result = extract_from_string("pin 1\npin 2\nmov 0, 1\n", &mut warnings);
+
+        assert_eq!(result.pin.as_deref(), Some("1"));
+        assert_eq!(
+            warnings,
+            vec![Warning::PinRedefinition {
+                old: "1".into(),
+                new: "2".into(),
+                span: Some(Span::new(2, 0, 5)),
+            }]
+        );
+    }
+
+    #[test]
+    fn round_trips_pin() {
+        let warrior = crate::parse(";name Shared\npin 6 * 7\nldp.ab #0, 1\n")
+            .expect("Failed to parse warrior");
+        assert_eq!(warrior.metadata.pin, Some(42));
+
+        let dumped = warrior.to_string();
+        assert!(dumped.contains("PIN     42\n"), "{}", dumped);
+
+        let loaded = crate::parse_load_file(&dumped).expect("Failed to load warrior");
+        assert_eq!(loaded, warrior);
+    }
+
+    #[test]
+    fn keeps_trailing_comments() {
+        assert_eq!(trailing_comment("mov 0, 1 ;  the imp "), Some("the imp"));
+        assert_eq!(trailing_comment(";name Imp"), None);
+        assert_eq!(trailing_comment("mov 0, 1 ;"), None);
+
+        let input = "\
+;name Twice
+        org start   ; begin here
+step    equ 4       ; ignored, as EQU produces no instruction
+start   add #step, 3
+        for 2       ; also ignored
+        mov 0, start ; copies
+        rof
+        dat 0, 0";
+        let output = crate::Parser::new().annotated(input).unwrap();
+
+        assert_eq!(
+            output,
+            "\
+;name Twice
+ORG     0            ; begin here
+ADD.AB  #4,     $3
+MOV.I   $0,     $-1  ; copies
+MOV.I   $0,     $-2
+DAT.F   $0,     $0"
+        );
+    }
 }
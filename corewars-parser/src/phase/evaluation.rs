@@ -2,6 +2,8 @@
 
 mod expression;
 
+pub use expression::Accessor;
+
 use std::convert::TryFrom;
 use std::str::FromStr;
 
@@ -9,72 +11,144 @@ use corewars_core::load_file;
 
 use super::super::error::Error;
 use super::super::grammar;
+use super::Line;
+
+/// Convert the text input lines into in-memory data structures. `source_lines`
+/// is the line number of each line in `buffer`, the original input text.
+/// Omitted modifiers are chosen by `modifiers`.
+pub fn evaluate(
+    lines: &[Line],
+    source_lines: &[usize],
+    buffer: &str,
+    modifiers: load_file::ModifierPolicy,
+) -> Result<load_file::InstructionsBuilder, Error> {
+    let mut instructions = load_file::InstructionsBuilder::with_capacity(lines.len());
+
+    for (line, &source_line) in lines.iter().zip(source_lines) {
+        let locate = |err: Error| err.locate(source_line, line, buffer);
+
+        let parse_result = grammar::parse_instruction(line).map_err(locate)?;
+        instructions.push(parse_instruction(parse_result.into_inner(), modifiers).map_err(locate)?);
+    }
 
-/// Convert the text input lines into in-memory data structures
-pub fn evaluate(lines: Vec<String>) -> Result<load_file::Instructions, Error> {
-    let mut instructions = Vec::with_capacity(lines.len());
-
-    for line in lines.into_iter() {
-        if let Some(parse_result) = grammar::parse_line(&line)?.next() {
-            match &parse_result.as_rule() {
-                grammar::Rule::Instruction => {
-                    instructions.push(parse_instruction(parse_result.into_inner())?);
-                }
-                rule => eprintln!("Unexpected rule {:?}", rule),
-            }
-        }
+    Ok(instructions)
+}
+
+/// Convert the lines of a load file into in-memory data structures. Unlike
+/// [`evaluate`](evaluate), every instruction must have its modifier and
+/// address modes written out, with only numbers for its fields.
+pub fn evaluate_load_file(
+    lines: &[Line],
+    source_lines: &[usize],
+    buffer: &str,
+) -> Result<load_file::InstructionsBuilder, Error> {
+    let mut instructions = load_file::InstructionsBuilder::with_capacity(lines.len());
+
+    for (line, &source_line) in lines.iter().zip(source_lines) {
+        let locate = |err: Error| err.locate(source_line, line, buffer);
+
+        let parse_result = grammar::parse_load_instruction(line).map_err(locate)?;
+        instructions.push(parse_load_instruction(parse_result.into_inner()).map_err(locate)?);
     }
 
     Ok(instructions)
 }
 
+/// Parse the origin of a load file, which must be a number.
+pub fn evaluate_load_origin(origin: &str) -> Result<u32, Error> {
+    let number = parse_signed_number(&grammar::parse_load_origin(origin)?)?;
+
+    Ok(u32::try_from(number)?)
+}
+
+/// Evaluate the expressions in the text input lines, without otherwise
+/// changing the instructions. Opcodes, modifiers and address modes are kept
+/// as written, so omitted modifiers and address modes stay omitted.
+pub fn evaluate_expressions(
+    lines: &[Line],
+    source_lines: &[usize],
+    buffer: &str,
+) -> Result<Vec<String>, Error> {
+    let mut evaluated = Vec::with_capacity(lines.len());
+
+    for (line, &source_line) in lines.iter().zip(source_lines) {
+        let parse_result = grammar::parse_instruction(line)
+            .map_err(|err| err.locate(source_line, line, buffer))?;
+
+        let mut instruction_pairs = parse_result.into_inner();
+        let operation = instruction_pairs
+            .next()
+            .ok_or_else(|| malformed().locate(source_line, line, buffer))?
+            .as_str()
+            .to_uppercase();
+
+        let fields = instruction_pairs
+            .filter(|pair| pair.as_rule() == grammar::Rule::Field)
+            .map(evaluate_field)
+            .collect::<Result<Vec<String>, Error>>()
+            .map_err(|err| err.locate(source_line, line, buffer))?;
+
+        evaluated.push(format!("{:<8}{}", operation, fields.join(", ")));
+    }
+
+    Ok(evaluated)
+}
+
 /// Parse and evaluate a single expression string to find the entry point to
 /// a warrior.
-pub fn evaluate_expression(expr: String) -> Result<u32, Error> {
-    let expr_pair = grammar::parse_expression(&expr)?;
+pub fn evaluate_expression(expr: &str) -> Result<u32, Error> {
+    let expr_pair = grammar::parse_expression(expr)?;
 
-    let origin = expression::evaluate(expr_pair);
+    let origin = expression::evaluate(expr_pair)?;
 
     Ok(u32::try_from(origin)?)
 }
 
+/// Parse and evaluate a standalone expression, looking up the names it uses
+/// with `lookup`.
+pub fn evaluate_standalone<F>(expr: &str, mut lookup: F) -> Result<i32, Error>
+where
+    F: FnMut(&Accessor) -> Option<i32>,
+{
+    let expr_pair = grammar::parse_expression_line(expr)?;
+
+    expression::evaluate_with(expr_pair, &mut lookup)
+}
+
+/// The error for a line which parsed, but not into the pairs the grammar
+/// should have produced.
+fn malformed() -> Error {
+    Error::MalformedInstruction { span: None }
+}
+
 fn parse_instruction(
     mut instruction_pairs: grammar::Pairs,
+    modifiers: load_file::ModifierPolicy,
 ) -> Result<load_file::Instruction, Error> {
-    let mut operation_pairs = instruction_pairs
-        .next()
-        .expect("Operation must be first pair after Label in Instruction")
-        .into_inner();
+    let operation_pair = instruction_pairs.next().ok_or_else(malformed)?;
 
-    let opcode = parse_opcode(
-        &operation_pairs
-            .next()
-            .expect("Opcode must be first pair in Operation"),
-    );
+    let operation_span = operation_pair.as_span();
+    let mut operation_pairs = operation_pair.into_inner();
+
+    let opcode = parse_opcode(&operation_pairs.next().ok_or_else(malformed)?)?;
 
     let maybe_modifier = operation_pairs
         .peek()
         .filter(|pair| pair.as_rule() == grammar::Rule::Modifier)
-        .map(|pair| parse_modifier(&pair));
+        .map(|pair| parse_modifier(&pair))
+        .transpose()?;
 
-    let a_field = parse_field(
-        instruction_pairs
-            .next()
-            .expect("Field must appear after Opcode"),
-    );
+    let a_field = parse_field(instruction_pairs.next().ok_or_else(malformed)?)?;
 
     let b_field = instruction_pairs
         .next()
         .filter(|pair| pair.as_rule() == grammar::Rule::Field)
-        .map(parse_field);
+        .map(parse_field)
+        .transpose()?;
 
     if let Some(b_field) = b_field {
         let modifier = maybe_modifier.unwrap_or_else(|| {
-            load_file::Modifier::default_88_to_94(
-                opcode,
-                a_field.address_mode,
-                b_field.address_mode,
-            )
+            modifiers.modifier(opcode, a_field.address_mode, b_field.address_mode)
         });
 
         Ok(load_file::Instruction {
@@ -91,62 +165,160 @@ fn parse_instruction(
         use load_file::Opcode::*;
 
         match opcode {
-            Dat => Ok(load_file::Instruction {
-                opcode,
-                modifier: maybe_modifier.unwrap_or(load_file::Modifier::F),
-                a_field: load_file::Field::immediate(0),
-                b_field: a_field,
-            }),
-            Jmp | Spl | Nop => Ok(load_file::Instruction {
-                opcode,
-                modifier: maybe_modifier.unwrap_or(load_file::Modifier::B),
-                a_field,
-                b_field: load_file::Field::direct(0),
+            Dat => {
+                let b_field = a_field;
+                let a_field = load_file::Field::immediate(0);
+                let modifier = maybe_modifier.unwrap_or_else(|| {
+                    modifiers.modifier(opcode, a_field.address_mode, b_field.address_mode)
+                });
+                Ok(load_file::Instruction {
+                    opcode,
+                    modifier,
+                    a_field,
+                    b_field,
+                })
+            }
+            Jmp | Spl | Nop => {
+                let b_field = load_file::Field::direct(0);
+                let modifier = maybe_modifier.unwrap_or_else(|| {
+                    modifiers.modifier(opcode, a_field.address_mode, b_field.address_mode)
+                });
+                Ok(load_file::Instruction {
+                    opcode,
+                    modifier,
+                    a_field,
+                    b_field,
+                })
+            }
+            other => Err(Error::InvalidArguments {
+                opcode: other,
+                span: Some(operation_span.into()),
             }),
-            other => Err(Error::InvalidArguments { opcode: other }),
         }
     }
 }
 
-fn parse_modifier(modifier_pair: &grammar::Pair) -> load_file::Modifier {
-    load_file::Modifier::from_str(modifier_pair.as_str().to_uppercase().as_ref()).unwrap()
+fn parse_load_instruction(
+    mut instruction_pairs: grammar::Pairs,
+) -> Result<load_file::Instruction, Error> {
+    let mut operation_pairs = instruction_pairs.next().ok_or_else(malformed)?.into_inner();
+
+    let opcode = parse_opcode(&operation_pairs.next().ok_or_else(malformed)?)?;
+    let modifier = parse_modifier(&operation_pairs.next().ok_or_else(malformed)?)?;
+
+    let mut fields = instruction_pairs
+        .filter(|pair| pair.as_rule() == grammar::Rule::LoadField)
+        .map(parse_load_field);
+    let a_field = fields.next().ok_or_else(malformed)??;
+    let b_field = fields.next().ok_or_else(malformed)??;
+
+    Ok(load_file::Instruction {
+        opcode,
+        modifier,
+        a_field,
+        b_field,
+    })
 }
 
-fn parse_opcode(opcode_pair: &grammar::Pair) -> load_file::Opcode {
-    load_file::Opcode::from_str(opcode_pair.as_str().to_uppercase().as_ref()).unwrap()
+fn parse_load_field(field_pair: grammar::Pair) -> Result<load_file::Field, Error> {
+    let mut field_pairs = field_pair.into_inner();
+    let mode_pair = field_pairs.next().ok_or_else(malformed)?;
+
+    let address_mode = load_file::AddressMode::from_str(mode_pair.as_str()).map_err(|_| {
+        Error::MalformedInstruction {
+            span: Some(mode_pair.as_span().into()),
+        }
+    })?;
+    let value = parse_signed_number(&field_pairs.next().ok_or_else(malformed)?)?;
+
+    Ok(load_file::Field {
+        address_mode,
+        value: load_file::Value::Literal(value),
+    })
+}
+
+fn parse_signed_number(number_pair: &grammar::Pair) -> Result<i32, Error> {
+    let number = number_pair.as_str();
+
+    // `i32` doesn't accept a leading `+`
+    number
+        .strip_prefix('+')
+        .unwrap_or(number)
+        .parse()
+        .map_err(|_| Error::NumberTooLarge {
+            number: number.to_string(),
+            span: Some(number_pair.as_span().into()),
+        })
 }
 
-fn parse_field(field_pair: grammar::Pair) -> load_file::Field {
+fn parse_modifier(modifier_pair: &grammar::Pair) -> Result<load_file::Modifier, Error> {
+    load_file::Modifier::from_str(modifier_pair.as_str().to_uppercase().as_ref()).map_err(|_| {
+        Error::MalformedInstruction {
+            span: Some(modifier_pair.as_span().into()),
+        }
+    })
+}
+
+fn parse_opcode(opcode_pair: &grammar::Pair) -> Result<load_file::Opcode, Error> {
+    load_file::Opcode::from_str(opcode_pair.as_str().to_uppercase().as_ref()).map_err(|_| {
+        Error::MalformedInstruction {
+            span: Some(opcode_pair.as_span().into()),
+        }
+    })
+}
+
+fn parse_field(field_pair: grammar::Pair) -> Result<load_file::Field, Error> {
+    let field_span = field_pair.as_span();
     let mut field_pairs = field_pair.into_inner();
 
-    let address_mode = field_pairs
+    let address_mode = match field_pairs
         .peek()
         .filter(|pair| pair.as_rule() == grammar::Rule::AddressMode)
-        .map_or(load_file::AddressMode::default(), |pair| {
-            load_file::AddressMode::from_str(pair.as_str()).expect("Invalid AddressMode")
-        });
+    {
+        Some(pair) => load_file::AddressMode::from_str(pair.as_str()).map_err(|_| {
+            Error::MalformedInstruction {
+                span: Some(pair.as_span().into()),
+            }
+        })?,
+        None => load_file::AddressMode::default(),
+    };
 
-    let offset = expression::evaluate(
-        field_pairs
-            .find(|pair| pair.as_rule() == grammar::Rule::Expression)
-            .unwrap_or_else(|| panic!("No expression found in Field: {:?}", field_pairs)),
-    );
+    let expression = field_pairs
+        .find(|pair| pair.as_rule() == grammar::Rule::Expression)
+        .ok_or(Error::MalformedInstruction {
+            span: Some(field_span.into()),
+        })?;
 
-    load_file::Field {
+    Ok(load_file::Field {
         address_mode,
-        value: load_file::Value::Literal(offset),
+        value: load_file::Value::Literal(expression::evaluate(expression)?),
+    })
+}
+
+fn evaluate_field(field_pair: grammar::Pair) -> Result<String, Error> {
+    let mut address_mode = "";
+    let mut offset = 0;
+
+    for pair in field_pair.into_inner() {
+        match pair.as_rule() {
+            grammar::Rule::AddressMode => address_mode = pair.as_str(),
+            grammar::Rule::Expression => offset = expression::evaluate(pair)?,
+            _ => {}
+        }
     }
+
+    Ok(format!("{}{}", address_mode, offset))
 }
 
 #[cfg(test)]
 mod test {
 
     use super::*;
-    use load_file::{Field, Instruction, Opcode};
+    use load_file::{Field, Instruction, ModifierPolicy, Opcode};
 
     #[test]
     fn parse_simple_file() {
-        let simple_input = [
+        let simple_input: Vec<Line> = [
             "mov 1, 3",
             "mov 100, #12",
             "dat #0, #0",
@@ -155,7 +327,7 @@ mod test {
             "jmp -1",
         ]
         .iter()
-        .map(|s| s.to_string())
+        .map(|&s| s.into())
         .collect();
 
         let expected_core = vec![
@@ -167,21 +339,118 @@ mod test {
             Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
         ];
 
-        let parsed = evaluate(simple_input)
-            .unwrap_or_else(|err| panic!("Failed to parse simple file: {}", err));
+        let parsed = evaluate(
+            &simple_input,
+            &[1, 2, 3, 4, 5, 6],
+            "",
+            ModifierPolicy::default(),
+        )
+        .unwrap_or_else(|err| panic!("Failed to parse simple file: {}", err));
+
+        assert_eq!(parsed.into_vec(), expected_core);
+    }
 
-        assert_eq!(parsed, expected_core);
+    #[test]
+    fn evaluates_expressions_only() {
+        let input: Vec<Line> = ["mov.i 1 + 2, }3 * 4", "dat 4 / 2", "spl #(-1)"]
+            .iter()
+            .map(|&s| s.into())
+            .collect();
+
+        let evaluated = evaluate_expressions(&input, &[1, 2, 3], "")
+            .unwrap_or_else(|err| panic!("Failed to evaluate expressions: {}", err));
+
+        assert_eq!(
+            evaluated,
+            vec!["MOV.I   3, }12", "DAT     2", "SPL     #-1"]
+        );
     }
 
     #[test]
     fn evaluates_origin() {
-        let evaluated =
-            evaluate_expression("2 * (4 + 3)".into()).expect("Should parse successfully");
+        let evaluated = evaluate_expression("2 * (4 + 3)").expect("Should parse successfully");
         assert_eq!(evaluated, 14);
     }
 
     #[test]
     fn fails_for_negative_origin() {
-        evaluate_expression("-10".into()).expect_err("-10 should be an invalid origin");
+        evaluate_expression("-10").expect_err("-10 should be an invalid origin");
+    }
+
+    #[test]
+    fn parses_load_file() {
+        let lines: Vec<Line> = ["SPL.B  #+4, <-2", "DAT.F $0,$0"]
+            .iter()
+            .map(|&s| s.into())
+            .collect();
+
+        let parsed = evaluate_load_file(&lines, &[1, 2], "").unwrap();
+        assert_eq!(
+            parsed.into_vec(),
+            vec![
+                Instruction {
+                    opcode: Opcode::Spl,
+                    modifier: load_file::Modifier::B,
+                    a_field: Field::immediate(4),
+                    b_field: Field {
+                        address_mode: load_file::AddressMode::PreDecIndirectB,
+                        value: load_file::Value::Literal(-2),
+                    },
+                },
+                Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(0)),
+            ]
+        );
+
+        assert_eq!(evaluate_load_origin("+3"), Ok(3));
+        assert!(evaluate_load_origin("start").is_err());
+    }
+
+    #[test]
+    fn rejects_assembly_in_load_file() {
+        let buffer = "MOV.I $0, $1\nMOV.I $0, 1\nJMP.B $-1\nMOV.I $x, $1\nMOV.I $1+1, $0\nMOV.I $9999999999, $0";
+        let span_of = |line: usize| {
+            let text = buffer.lines().nth(line - 1).unwrap();
+            evaluate_load_file(&[text.into()], &[line], buffer)
+                .unwrap_err()
+                .span()
+                .map(|span| (span.line, span.start))
+        };
+
+        // Missing address mode, missing field, label, expression, overflow
+        assert_eq!(span_of(2), Some((2, 10)));
+        assert_eq!(span_of(3), Some((3, 9)));
+        assert_eq!(span_of(4), Some((4, 7)));
+        assert_eq!(span_of(5), Some((5, 8)));
+        assert_eq!(span_of(6), Some((6, 7)));
+    }
+
+    #[test]
+    fn evaluates_full_expressions() {
+        let warrior = crate::parse(
+            "step equ 3\nstart mov step*2+1, (last-start)/2\nlast dat CORESIZE-1, 7 % 3",
+        )
+        .unwrap();
+
+        let instructions: Vec<String> = warrior
+            .program
+            .instructions
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            instructions,
+            vec!["MOV.I   $7,     $0", "DAT.F   $7999,  $1"]
+        );
+    }
+
+    #[test]
+    fn locates_expression_errors() {
+        let line_of = |input| match crate::parse(input) {
+            crate::Result::Err(err, _) => err.span().map(|span| span.line),
+            crate::Result::Ok(..) => panic!("{:?} should fail to parse", input),
+        };
+
+        assert_eq!(line_of("mov 0, 1\nmov 1/0, 0"), Some(2));
+        assert_eq!(line_of("mov 0, 1\nend 5 % 0"), Some(2));
     }
 }
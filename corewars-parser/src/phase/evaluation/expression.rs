@@ -1,14 +1,38 @@
 //! Helper functions for evaluating an expression syntax tree.
 //!
-//! Most functions here panic instead of returning Result because at this point
-//! any errors should have been caught earlier during initial parsing.
+//! By the time a warrior's expressions are evaluated, their labels have all
+//! been substituted, so [`evaluate`] treats any remaining name as a missing
+//! label. Standalone expressions, such as debugger conditions, are evaluated
+//! with [`evaluate_with`] instead, which looks up names at runtime.
+//!
+//! Arithmetic wraps around on overflow rather than failing, since values
+//! are reduced modulo the core size anyway.
 
+use crate::error::Error;
 use crate::grammar::*;
 
-/// Evaluate an Expression. Panics if the expression tree is invalid, which
-/// should only happen due to programmer error (either the grammar or this code
-/// is incorrect).
-pub fn evaluate(pair: Pair) -> i32 {
+/// A name used in a standalone expression, optionally indexed and followed by
+/// field names, e.g. `cycle` or `core[100].opcode`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accessor {
+    pub name: String,
+    pub index: Option<i32>,
+    pub fields: Vec<String>,
+}
+
+/// Looks up the value of an accessor, returning `None` if it doesn't exist.
+pub type Lookup<'a> = dyn FnMut(&Accessor) -> Option<i32> + 'a;
+
+/// Evaluate an Expression whose labels have already been substituted. Panics
+/// if the expression tree is invalid, which should only happen due to
+/// programmer error (either the grammar or this code is incorrect).
+pub fn evaluate(pair: Pair) -> Result<i32, Error> {
+    evaluate_with(pair, &mut |_| None)
+}
+
+/// Evaluate an Expression, looking up the value of any labels or accessors
+/// it uses with `lookup`.
+pub fn evaluate_with(pair: Pair, lookup: &mut Lookup) -> Result<i32, Error> {
     let mut result = None;
     let mut boolean_op: fn(i32, i32) -> i32 =
         |_, _| unreachable!("BooleanOp called before first operand");
@@ -16,7 +40,7 @@ pub fn evaluate(pair: Pair) -> i32 {
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::Value => {
-                let operand = evaluate_value(inner_pair);
+                let operand = evaluate_value(inner_pair, lookup)?;
                 result = result.map(|x| boolean_op(x, operand)).or(Some(operand));
             }
             Rule::BooleanOp => {
@@ -30,10 +54,10 @@ pub fn evaluate(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Expression"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Expression")))
 }
 
-fn evaluate_value(pair: Pair) -> i32 {
+fn evaluate_value(pair: Pair, lookup: &mut Lookup) -> Result<i32, Error> {
     let mut result = None;
     let mut compare_op: fn(i32, i32) -> i32 =
         |_, _| unreachable!("CompareOp called before first operand");
@@ -41,7 +65,7 @@ fn evaluate_value(pair: Pair) -> i32 {
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::Sum => {
-                let operand = evaluate_sum(inner_pair);
+                let operand = evaluate_sum(inner_pair, lookup)?;
                 result = result.map(|x| compare_op(x, operand)).or(Some(operand));
             }
             Rule::CompareOp => {
@@ -60,23 +84,23 @@ fn evaluate_value(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Value"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Value")))
 }
 
-fn evaluate_sum(pair: Pair) -> i32 {
+fn evaluate_sum(pair: Pair, lookup: &mut Lookup) -> Result<i32, Error> {
     let mut result = None;
     let mut add_op: fn(i32, i32) -> i32 = |_, _| unreachable!("AddOp called before first operand");
 
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::Product => {
-                let operand = evaluate_product(inner_pair);
+                let operand = evaluate_product(inner_pair, lookup)?;
                 result = result.map(|x| add_op(x, operand)).or(Some(operand));
             }
             Rule::AddOp => {
                 add_op = match inner_pair.as_str() {
-                    "+" => |a, b| a + b,
-                    "-" => |a, b| a - b,
+                    "+" => i32::wrapping_add,
+                    "-" => i32::wrapping_sub,
                     op => unreachable!("Invalid AddOp {:?}", op),
                 };
             }
@@ -84,25 +108,31 @@ fn evaluate_sum(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Sum"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Sum")))
 }
 
-fn evaluate_product(pair: Pair) -> i32 {
+fn evaluate_product(pair: Pair, lookup: &mut Lookup) -> Result<i32, Error> {
     let mut result = None;
-    let mut mul_op: fn(i32, i32) -> i32 =
+    let mut mul_op: fn(i32, i32) -> Option<i32> =
         |_, _| unreachable!("MultiplyOp called before first operand");
 
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::UnaryExpr => {
-                let operand = evaluate_unary(inner_pair);
-                result = result.map(|x| mul_op(x, operand)).or(Some(operand));
+                let span = inner_pair.as_span();
+                let operand = evaluate_unary(inner_pair, lookup)?;
+                result = match result {
+                    None => Some(operand),
+                    Some(x) => Some(mul_op(x, operand).ok_or(Error::DivideByZero {
+                        span: Some(span.into()),
+                    })?),
+                };
             }
             Rule::MultiplyOp => {
                 mul_op = match inner_pair.as_str() {
-                    "*" => |a, b| a * b,
-                    "/" => |a, b| a / b,
-                    "%" => |a, b| a % b,
+                    "*" => |a, b| Some(a.wrapping_mul(b)),
+                    "/" => |a, b| (b != 0).then(|| a.wrapping_div(b)),
+                    "%" => |a, b| (b != 0).then(|| a.wrapping_rem(b)),
                     op => unreachable!("Invalid MultiplyOp {:?}", op),
                 };
             }
@@ -114,19 +144,20 @@ fn evaluate_product(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Product"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Product")))
 }
 
-fn evaluate_unary(pair: Pair) -> i32 {
+fn evaluate_unary(pair: Pair, lookup: &mut Lookup) -> Result<i32, Error> {
     let mut result = None;
     let mut unary_ops: Vec<fn(i32) -> i32> = Vec::new();
 
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
-            Rule::Number => result = Some(evaluate_number(inner_pair)),
-            Rule::Expression => result = Some(evaluate(inner_pair)),
+            Rule::Number => result = Some(evaluate_number(inner_pair)?),
+            Rule::Expression => result = Some(evaluate_with(inner_pair, lookup)?),
+            Rule::Label | Rule::Accessor => result = Some(evaluate_accessor(inner_pair, lookup)?),
             Rule::UnaryOp => match inner_pair.as_str() {
-                "-" => unary_ops.push(|x| -x),
+                "-" => unary_ops.push(i32::wrapping_neg),
                 "+" => (), // Identity function
                 "!" => unary_ops.push(|x| (x == 0) as i32),
                 other => unreachable!("Invalid unary operator {:?}", other),
@@ -143,14 +174,48 @@ fn evaluate_unary(pair: Pair) -> i32 {
         result = result.map(op);
     }
 
-    result.unwrap_or_else(|| panic!("UnaryExpr did not contain a value"))
+    Ok(result.unwrap_or_else(|| panic!("UnaryExpr did not contain a value")))
 }
 
-fn evaluate_number(pair: Pair) -> i32 {
+fn evaluate_accessor(pair: Pair, lookup: &mut Lookup) -> Result<i32, Error> {
+    let text = pair.as_str();
+    let span = pair.as_span();
+
+    let mut accessor = Accessor {
+        name: String::new(),
+        index: None,
+        fields: Vec::new(),
+    };
+
+    if pair.as_rule() == Rule::Label {
+        accessor.name = text.to_string();
+    } else {
+        for inner_pair in pair.into_inner() {
+            match inner_pair.as_rule() {
+                Rule::Label if accessor.name.is_empty() => {
+                    accessor.name = inner_pair.as_str().to_string()
+                }
+                Rule::Label => accessor.fields.push(inner_pair.as_str().to_string()),
+                Rule::Expression => accessor.index = Some(evaluate_with(inner_pair, lookup)?),
+                other => unreachable!("unexpected {:?} in Accessor: {:?}", other, text),
+            }
+        }
+    }
+
+    lookup(&accessor).ok_or_else(|| Error::LabelNotFound {
+        label: text.to_string(),
+        span: Some(span.into()),
+    })
+}
+
+fn evaluate_number(pair: Pair) -> Result<i32, Error> {
     assert!(pair.as_rule() == Rule::Number);
     pair.as_str()
         .parse::<i32>()
-        .unwrap_or_else(|_| panic!("Invalid Number: {:?}", pair.as_str()))
+        .map_err(|_| Error::NumberTooLarge {
+            number: pair.as_str().to_string(),
+            span: Some(pair.as_span().into()),
+        })
 }
 
 #[cfg(test)]
@@ -186,9 +251,79 @@ mod test {
     // Boolean
     #[test_case("0 && 1" => 0; "boolean and")]
     #[test_case("0 || 1" => 1; "boolean or")]
+    // Overflow
+    #[test_case("2147483647 + 1" => i32::MIN; "wrapping sum")]
+    #[test_case("-2147483647 - 1 - 1" => i32::MAX; "wrapping difference")]
     fn evaluates_expressions(input: &str) -> i32 {
         let pair = parse_expression(input).expect("Failed to parse as Expression");
 
-        evaluate(pair)
+        evaluate(pair).expect("Failed to evaluate Expression")
+    }
+
+    #[test_case("7 / (3 - 3)"; "quotient")]
+    #[test_case("7 % 0"; "remainder")]
+    fn divide_by_zero(input: &str) {
+        let pair = parse_expression(input).unwrap();
+
+        assert!(matches!(evaluate(pair), Err(Error::DivideByZero { .. })));
+    }
+
+    #[test]
+    fn number_too_large() {
+        let pair = parse_expression("1 + 99999999999").unwrap();
+
+        assert_eq!(
+            evaluate(pair),
+            Err(Error::NumberTooLarge {
+                number: "99999999999".into(),
+                span: Some(crate::error::Span::new(0, 4, 15)),
+            })
+        );
+    }
+
+    #[test]
+    fn evaluates_accessors() {
+        use pretty_assertions::assert_eq;
+
+        let mut seen = Vec::new();
+        let mut lookup = |accessor: &Accessor| {
+            seen.push(accessor.clone());
+            match accessor.name.as_str() {
+                "cycle" => Some(5001),
+                "core" => Some(accessor.index? * 10),
+                "DAT" => Some(3),
+                _ => None,
+            }
+        };
+
+        let pair = parse_expression("core[1 + 2].opcode == 30 && cycle > 5000").unwrap();
+        assert_eq!(evaluate_with(pair, &mut lookup), Ok(1));
+        assert_eq!(
+            seen,
+            vec![
+                Accessor {
+                    name: "core".into(),
+                    index: Some(3),
+                    fields: vec!["opcode".into()],
+                },
+                Accessor {
+                    name: "cycle".into(),
+                    index: None,
+                    fields: vec![],
+                },
+            ]
+        );
+
+        let pair = parse_expression("cycle / (DAT - 3)").unwrap();
+        assert!(matches!(
+            evaluate_with(pair, &mut |_| Some(3)),
+            Err(Error::DivideByZero { .. })
+        ));
+
+        let pair = parse_expression("warrior.name").unwrap();
+        assert!(matches!(
+            evaluate_with(pair, &mut |_| None),
+            Err(Error::LabelNotFound { label, .. }) if label == "warrior.name"
+        ));
     }
 }
@@ -1,59 +1,147 @@
 //! This phase finds and expands substitutions, namely:
 //! - EQU definitions
-//! - FOR blocks (not yet implemented)
+//! - FOR blocks, including the index label of each loop
 //! - Standard labels which alias an address
 //!
 //! Labels used in the right-hand side of an expression substituted in-place.
 
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 
 use pest::Span;
 
+use crate::error::{Error, Span as ErrorSpan, Warning};
 use crate::grammar;
 
-use super::evaluation;
+use super::{evaluation, Line};
 
 use corewars_core::load_file::DEFAULT_CONSTANTS;
-
-/// The result of expansion and substitution
-#[derive(Debug, Default, PartialEq)]
-pub struct Lines {
-    pub text: Vec<String>,
-    pub origin: Option<String>,
+use corewars_core::CancellationToken;
+
+/// Limits on how much `FOR` loops may expand the input, to protect against
+/// pathological programs which would take too long or too much memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpansionLimits {
+    /// The maximum number of `FOR` loops which can be nested inside each other
+    pub max_for_depth: usize,
+
+    /// The maximum number of times the body of a `FOR` loop can be repeated,
+    /// including the repetitions of any loops enclosing it
+    pub max_repetitions: u64,
+
+    /// Checked before expanding each line, to stop expanding with
+    /// [`Error::Cancelled`] once it is cancelled, e.g. for a program taking
+    /// too long within the other limits
+    pub cancellation: CancellationToken,
 }
 
-/// Collect and subsitute all labels found in the input lines.
-pub fn expand(mut text: Vec<String>, mut origin: Option<String>) -> Lines {
-    let labels = collect_and_expand(&mut text);
+impl Default for ExpansionLimits {
+    fn default() -> Self {
+        Self {
+            max_for_depth: 16,
+            // Repeating anything more than would fit in the core is pointless
+            max_repetitions: u64::from(DEFAULT_CONSTANTS["CORESIZE"]),
+            cancellation: CancellationToken::new(),
+        }
+    }
+}
 
-    substitute_offsets(&mut text, &labels);
+/// The result of expansion and substitution. Lines which didn't need to be
+/// changed still borrow from the input.
+#[derive(Debug, Default, PartialEq)]
+pub struct Lines<'a> {
+    pub text: Vec<Line<'a>>,
+    /// The line number in the original input of each entry in `text`
+    pub source_lines: Vec<usize>,
+    pub origin: Option<Line<'a>>,
+    pub warnings: Vec<Warning>,
+}
 
-    if let Some(mut origin_str) = origin.as_mut() {
-        substitute_offsets_in_line(&mut origin_str, &labels, 0);
+/// Collect and subsitute all labels found in the input lines. `source_lines`
+/// is the line number of each line in `buffer`, the original input text.
+pub fn expand<'a>(
+    mut text: Vec<Line<'a>>,
+    mut source_lines: Vec<usize>,
+    mut origin: Option<Line<'a>>,
+    buffer: &str,
+    limits: &ExpansionLimits,
+) -> Result<Lines<'a>, Error> {
+    let mut warnings = Vec::new();
+    let labels = collect_and_expand(&mut text, &mut source_lines, buffer, limits, &mut warnings)?;
+
+    substitute_offsets(&mut text, &source_lines, &labels, buffer)?;
+
+    if let Some(origin_str) = origin.as_mut() {
+        substitute_offsets_in_line(origin_str, &labels, 0)?;
     }
 
-    Lines { text, origin }
+    Ok(Lines {
+        text,
+        source_lines,
+        origin,
+        warnings,
+    })
 }
 
 /// Collect and strip out offset-based label declarations, meanwhile expanding
-/// `EQU` labels.
-fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
+/// `EQU` labels. Any problems which aren't errors are added to `warnings`.
+fn collect_and_expand(
+    lines: &mut Vec<Line>,
+    sources: &mut Vec<usize>,
+    buffer: &str,
+    limits: &ExpansionLimits,
+    warnings: &mut Vec<Warning>,
+) -> Result<Labels, Error> {
     use grammar::Rule;
 
-    let mut collector = Collector::new();
+    let mut collector = Collector::new(limits.clone());
 
     let mut i: usize = 0;
     let mut offset: u32 = 0;
 
     while i < lines.len() {
+        if limits.cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        // Only copies the line if an earlier phase changed it
         let line = lines[i].clone();
         let tokenized_line = grammar::tokenize(&line);
 
         if tokenized_line.is_empty() {
+            // Nothing to expand, any syntax errors will be caught during evaluation
+            i += 1;
             continue;
         }
 
         let first_token = &tokenized_line[0];
+        let source_line = sources[i];
+        let locate = |err: Error| err.locate(source_line, &line, buffer);
+
+        if collector.deferred_depth > 0 {
+            // Leave the body of a deferred loop as-is, until the loop around
+            // it is unrolled
+            if is_for_statement(&tokenized_line) {
+                if tokenized_line
+                    .iter()
+                    .any(|token| token.as_rule() == Rule::Rof)
+                {
+                    collector.deferred_depth -= 1;
+                } else {
+                    collector.defer_for().map_err(locate)?;
+                }
+            }
+            i += 1;
+            continue;
+        }
+
+        if collector.in_for() && line.contains('&') && !is_for_statement(&tokenized_line) {
+            // Lines using `&` concatenation only make sense once the loop is
+            // unrolled, so leave them as-is until the loop is repeated
+            offset += 1;
+            i += 1;
+            continue;
+        }
 
         // Returns true if anything was expanded, false otherwise
         let mut expand_next_token = |collector: &Collector, is_for_expr: bool| {
@@ -67,24 +155,35 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                                 let relative_offset = (abs_offset as i32) - (offset as i32);
                                 expand_lines(
                                     lines,
+                                    sources,
                                     i,
                                     token.as_span(),
                                     &[relative_offset.to_string()],
                                 );
                             }
                             LabelValue::RelativeOffset(rel_offset) => {
-                                expand_lines(lines, i, token.as_span(), &[rel_offset.to_string()]);
+                                expand_lines(
+                                    lines,
+                                    sources,
+                                    i,
+                                    token.as_span(),
+                                    &[rel_offset.to_string()],
+                                );
                             }
                             LabelValue::Substitution(subst) => {
-                                expand_lines(lines, i, token.as_span(), &subst);
+                                collector.check_recursion(token.as_str(), token.as_span())?;
+                                expand_lines(lines, sources, i, token.as_span(), &subst);
                             }
                         }
 
-                        return true;
+                        return Ok(true);
                     }
 
-                    if is_for_expr {
-                        panic!("No label value found for expr {:?}", token.as_str())
+                    if is_for_expr && !collector.is_index(token.as_str()) {
+                        return Err(Error::LabelNotFound {
+                            label: token.as_str().to_owned(),
+                            span: Some(token.as_span().into()),
+                        });
                     } else {
                         // this is probably a forward usage of a label not
                         // yet declared, which _could_ be an error
@@ -92,34 +191,49 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                 }
             }
 
-            false
+            Ok(false)
         };
 
         match first_token.as_rule() {
             Rule::For => {
                 collector.resolve_pending_labels(offset);
 
-                if expand_next_token(&collector, true) {
+                if expand_next_token(&collector, true).map_err(locate)? {
                     continue;
                 }
 
-                let line_remainder = &line[first_token.as_span().end()..];
-                collector.push_for(None, i, offset, line_remainder);
+                let remainder_start = first_token.as_span().end();
+                collector
+                    .push_for(None, i, offset, &line[remainder_start..])
+                    .map_err(|err| locate(err.shifted(remainder_start)))?;
                 // Continue processing lines as normal, since we still need to collect
                 // labels and potentially nested for loops
             }
             Rule::Rof => {
                 let for_stmt = collector.pop_for();
-                // For now, we don't handle the label properly, just copy+paste
-                // the inner lines N times without any substitutions
+                // Copy+paste the inner lines N times, substituting the index
+                // label with the loop counter, which starts from 1
                 let range_to_repeat = (for_stmt.start_line + 1)..i;
                 let insert_line_count = for_stmt.iter_count as usize * range_to_repeat.len();
 
-                // We need to subtract the offset, since we end up replacing
+                // We need to reset the offset, since we end up replacing
                 // those lines. They will be processed normally after substitution
-                offset -= range_to_repeat.len() as u32;
+                offset = for_stmt.start_offset;
+
+                let body = &lines[range_to_repeat.clone()];
+                let index_label = &for_stmt.index_label;
+                let new_contents = (1..=for_stmt.iter_count)
+                    .flat_map(|iteration| {
+                        body.iter().map(move |line| match index_label {
+                            Some(label) if line.contains(label.as_str()) => {
+                                Cow::Owned(substitute_index(line, label, iteration))
+                            }
+                            _ => line.clone(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
 
-                let new_contents = lines[range_to_repeat]
+                let new_sources = sources[range_to_repeat]
                     .iter()
                     .cloned()
                     .cycle()
@@ -127,7 +241,8 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                     .collect::<Vec<_>>();
 
                 let range_to_replace = for_stmt.start_line..=i;
-                lines.splice(range_to_replace, new_contents);
+                lines.splice(range_to_replace.clone(), new_contents);
+                sources.splice(range_to_replace, new_sources);
 
                 i = for_stmt.start_line;
 
@@ -137,22 +252,30 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                 if let Some(next_token) = tokenized_line.get(1) {
                     match next_token.as_rule() {
                         Rule::Substitution => {
-                            collector.process_equ(first_token.as_str(), next_token.as_str());
+                            collector.process_equ(
+                                first_token.as_str(),
+                                next_token.as_str(),
+                                source_line,
+                                buffer,
+                            );
                             lines.remove(i);
+                            sources.remove(i);
                             continue;
                         }
                         Rule::For => {
                             collector.resolve_pending_labels(offset);
 
-                            if !expand_next_token(&collector, true) {
-                                let line_remainder = &line[next_token.as_span().end()..];
+                            if !expand_next_token(&collector, true).map_err(locate)? {
+                                let remainder_start = next_token.as_span().end();
 
-                                collector.push_for(
-                                    first_token.as_str().to_string(),
-                                    i,
-                                    offset,
-                                    line_remainder,
-                                );
+                                collector
+                                    .push_for(
+                                        first_token.as_str().to_string(),
+                                        i,
+                                        offset,
+                                        &line[remainder_start..],
+                                    )
+                                    .map_err(|err| locate(err.shifted(remainder_start)))?;
 
                                 i += 1;
                             }
@@ -167,13 +290,19 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                 if let Some(LabelValue::Substitution(substitution)) =
                     collector.get_label_value(first_token.as_str(), offset)
                 {
-                    expand_lines(lines, i, first_token.as_span(), &substitution);
+                    collector
+                        .check_recursion(first_token.as_str(), first_token.as_span())
+                        .map_err(locate)?;
+                    expand_lines(lines, sources, i, first_token.as_span(), &substitution);
                     continue;
                 }
 
-                collector.add_pending_label(first_token.as_str());
+                collector.add_pending_label(
+                    first_token.as_str(),
+                    ErrorSpan::of_line(source_line, buffer),
+                );
 
-                if expand_next_token(&collector, false) {
+                if expand_next_token(&collector, false).map_err(locate)? {
                     continue;
                 }
 
@@ -182,21 +311,25 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                     offset += 1;
 
                     let next_token = tokenized_line[1].as_span();
-                    lines[i] = line[next_token.start()..].to_owned();
+                    lines[i] = line_from(&line, next_token.start());
                 } else {
                     lines.remove(i);
+                    sources.remove(i);
                     continue;
                 }
             }
             Rule::Substitution => {
-                collector.process_equ_continuation(first_token.as_str());
+                collector
+                    .process_equ_continuation(first_token.as_str())
+                    .map_err(locate)?;
                 lines.remove(i);
+                sources.remove(i);
                 continue;
             }
             other_rule => {
                 collector.resolve_pending_labels(offset);
 
-                if expand_next_token(&collector, false) {
+                if expand_next_token(&collector, false).map_err(locate)? {
                     continue;
                 }
 
@@ -213,10 +346,19 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
         i += 1;
     }
 
-    collector.finish()
+    collector.warn_empty_offsets();
+    warnings.append(&mut collector.warnings);
+
+    Ok(collector.finish())
 }
 
-fn expand_lines(lines: &mut Vec<String>, index: usize, span: Span, substitution: &[String]) {
+fn expand_lines(
+    lines: &mut Vec<Line>,
+    sources: &mut Vec<usize>,
+    index: usize,
+    span: Span,
+    substitution: &[String],
+) {
     let line = &lines[index];
 
     let before = &line[..span.start()];
@@ -228,64 +370,113 @@ fn expand_lines(lines: &mut Vec<String>, index: usize, span: Span, substitution:
     new_lines[0] = before.to_owned() + &new_lines[0];
     new_lines.last_mut().unwrap().push_str(after);
 
-    lines.splice(index..=index, new_lines);
+    // All of the substituted lines originate from the line being expanded
+    let source = sources[index];
+    sources.splice(index..=index, vec![source; new_lines.len()]);
+    lines.splice(index..=index, new_lines.into_iter().map(Cow::Owned));
+}
+
+/// The rest of `line` from byte `start`, still borrowing from the input if
+/// `line` does.
+fn line_from<'a>(line: &Line<'a>, start: usize) -> Line<'a> {
+    match line {
+        Cow::Borrowed(line) => Cow::Borrowed(&line[start..]),
+        Cow::Owned(line) => Cow::Owned(line[start..].to_owned()),
+    }
 }
 
-fn substitute_offsets(lines: &mut Vec<String>, labels: &Labels) {
+fn substitute_offsets(
+    lines: &mut [Line],
+    sources: &[usize],
+    labels: &Labels,
+    buffer: &str,
+) -> Result<(), Error> {
     let mut i = 0;
-    for line in lines.iter_mut() {
-        let cloned = line.clone();
-        let tokenized_line = grammar::tokenize(&cloned);
+    for (line, &source) in lines.iter_mut().zip(sources) {
+        let tokenized_line = grammar::tokenize(line);
+        if tokenized_line.is_empty() {
+            // Lines which don't parse have no tokens, and nor do empty lines
+            // substituted for an EQU, which are never instructions
+            return Err(match grammar::parse_line(line) {
+                Err(err) => err.locate(source, line, buffer),
+                Ok(_) => Error::MalformedInstruction {
+                    span: Some(ErrorSpan::of_line(source, buffer)),
+                },
+            });
+        }
 
-        if tokenized_line[0].as_rule() == grammar::Rule::Label {
-            if let Some(next_token) = tokenized_line.get(1) {
-                line.replace_range(..next_token.as_span().start(), "");
-            } else {
-                line.clear();
+        // Only the position of the first instruction token is needed, so
+        // the line can be changed without copying it first
+        let is_origin = tokenized_line[0].as_rule() == grammar::Rule::Opcode
+            && tokenized_line[0].as_str().eq_ignore_ascii_case("ORG");
+        let label_end = match tokenized_line[0].as_rule() {
+            grammar::Rule::Label => {
+                Some(tokenized_line.get(1).map(|token| token.as_span().start()))
+            }
+            _ => None,
+        };
+        drop(tokenized_line);
+
+        match label_end {
+            Some(Some(end)) => *line = line_from(line, end),
+            Some(None) => {
+                *line = Cow::Borrowed("");
                 // Skip incrementing offset since the line was just a label
                 continue;
             }
+            None => {}
         }
 
-        substitute_offsets_in_line(line, labels, i);
+        substitute_offsets_in_line(line, labels, i)
+            .map_err(|err| err.locate(source, line, buffer))?;
 
-        if tokenized_line[0].as_rule() != grammar::Rule::Opcode
-            || tokenized_line[0].as_str().to_uppercase() != "ORG"
-        {
+        if !is_origin {
             i += 1;
         }
     }
-}
 
-fn substitute_offsets_in_line(line: &mut String, labels: &Labels, from_offset: u32) {
-    let tokenized_line = grammar::tokenize(&line);
-
-    for token in tokenized_line.iter() {
-        if token.as_rule() == grammar::Rule::Label {
-            let label_value = labels.get(token.as_str());
+    Ok(())
+}
 
-            let relative_offset = match label_value {
-                Some(&LabelValue::AbsoluteOffset(offset)) => (offset as i32) - (from_offset as i32),
-                Some(&LabelValue::RelativeOffset(offset)) => offset,
-                _ => {
-                    // TODO #25 actual error
-                    panic!("No label {:?} found", token.as_str());
-                }
-            };
+/// Replace every label in `line` with its offset relative to `from_offset`,
+/// only copying it if it has any. Spans in errors refer to `line` as it was
+/// before any substitution.
+fn substitute_offsets_in_line(
+    line: &mut Line,
+    labels: &Labels,
+    from_offset: u32,
+) -> Result<(), Error> {
+    let mut substituted = String::with_capacity(line.len());
+    let mut end = 0;
+
+    for token in grammar::tokenize(line) {
+        if token.as_rule() != grammar::Rule::Label {
+            continue;
+        }
 
-            let span = token.as_span();
+        let relative_offset = match labels.get(token.as_str()) {
+            Some(&LabelValue::AbsoluteOffset(offset)) => (offset as i32) - (from_offset as i32),
+            Some(&LabelValue::RelativeOffset(offset)) => offset,
+            _ => {
+                return Err(Error::LabelNotFound {
+                    label: token.as_str().to_owned(),
+                    span: Some(ErrorSpan::from(token.as_span())),
+                });
+            }
+        };
 
-            let range = span.start()..span.end();
-            let replace_with = relative_offset.to_string();
-            line.replace_range(range, &replace_with);
+        let span = token.as_span();
+        substituted.push_str(&line[end..span.start()]);
+        substituted.push_str(&relative_offset.to_string());
+        end = span.end();
+    }
 
-            // Recursively re-parse line and continue substitution.
-            // This is less efficient, but means we don't need to deal
-            // with the fact that the whole line was invalidate after
-            // `replace_range`
-            return substitute_offsets_in_line(line, labels, from_offset);
-        }
+    if end > 0 {
+        substituted.push_str(&line[end..]);
+        *line = Cow::Owned(substituted);
     }
+
+    Ok(())
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -318,58 +509,74 @@ struct ForStatement {
 struct Collector {
     labels: Labels,
     current_equ: Option<(String, Vec<String>)>,
-    pending_labels: HashSet<String>,
+    /// The source line of each EQU definition, for reporting recursion
+    equ_lines: HashMap<String, usize>,
+    /// Labels waiting for the next instruction, and where each was declared
+    pending_labels: HashMap<String, ErrorSpan>,
     for_stack: Vec<ForStatement>,
-    for_offsets: HashMap<String, u32>,
+    /// How many loops deep the current line is within a loop whose count
+    /// depends on the index of an enclosing loop
+    deferred_depth: usize,
+    limits: ExpansionLimits,
+    warnings: Vec<Warning>,
 }
 
 impl Collector {
-    fn new() -> Self {
+    fn new(limits: ExpansionLimits) -> Self {
         Self {
+            limits,
             labels: default_labels(),
             current_equ: None,
-            pending_labels: HashSet::new(),
+            equ_lines: HashMap::new(),
+            pending_labels: HashMap::new(),
             for_stack: Vec::new(),
-            for_offsets: HashMap::new(),
+            deferred_depth: 0,
+            warnings: Vec::new(),
         }
     }
 
-    fn process_equ(&mut self, label: &str, substitution: &str) {
+    fn process_equ(&mut self, label: &str, substitution: &str, line: usize, buffer: &str) {
         if substitution.is_empty() {
-            // TODO #25 warning empty RHS of EQU (see docs/pmars-redcode-94.txt:170)
+            // See docs/pmars-redcode-94.txt:170
+            self.warnings.push(Warning::EmptySubstitution {
+                label: label.to_owned(),
+                span: Some(ErrorSpan::of_line(line, buffer)),
+            });
         }
 
         if self.current_equ.is_some() {
             self.resolve_pending_equ();
         }
 
+        self.equ_lines.insert(label.to_owned(), line);
         self.current_equ = Some((label.to_owned(), vec![substitution.to_owned()]));
     }
 
-    fn process_equ_continuation(&mut self, substitution: &str) {
-        if let Some((_, ref mut values)) = self.current_equ {
-            values.push(substitution.to_string());
-        } else {
-            // TODO #25 real error
-            eprintln!("Error: first occurrence of EQU without label")
+    fn process_equ_continuation(&mut self, substitution: &str) -> Result<(), Error> {
+        match self.current_equ {
+            Some((_, ref mut values)) => {
+                values.push(substitution.to_string());
+                Ok(())
+            }
+            None => Err(Error::EquWithoutLabel { span: None }),
         }
     }
 
-    fn add_pending_label(&mut self, label: &str) {
-        self.pending_labels.insert(label.to_owned());
+    fn add_pending_label(&mut self, label: &str, span: ErrorSpan) {
+        self.pending_labels.insert(label.to_owned(), span);
     }
 
     fn resolve_pending_labels(&mut self, offset: u32) {
         let mut result = HashMap::new();
 
         let pending_labels = std::mem::take(&mut self.pending_labels);
-        for pending_label in pending_labels.into_iter() {
+        for pending_label in pending_labels.into_keys() {
             result.insert(pending_label, LabelValue::AbsoluteOffset(offset));
         }
 
         self.resolve_pending_equ();
 
-        self.labels.extend(result.into_iter())
+        self.labels.extend(result)
     }
 
     fn resolve_pending_equ(&mut self) {
@@ -388,9 +595,29 @@ impl Collector {
         line: usize,
         offset: u32,
         expression: &str,
-    ) {
-        // TODO handle errors instead of unwrap
-        let expr_value = evaluation::evaluate_expression(expression.to_string()).unwrap();
+    ) -> Result<(), Error> {
+        if referenced_labels(expression).any(|label| self.is_index(label)) {
+            // The count isn't known until the enclosing loop is unrolled, so
+            // this loop is expanded afterwards, like pMARS does
+            return self.defer_for();
+        }
+
+        let expr_value = evaluation::evaluate_expression(expression)?;
+        self.check_for_depth(self.for_stack.len() + 1)?;
+
+        let repetitions = self
+            .for_stack
+            .iter()
+            .fold(u64::from(expr_value), |total, for_stmt| {
+                total.saturating_mul(u64::from(for_stmt.iter_count))
+            });
+        if repetitions > self.limits.max_repetitions {
+            return Err(Error::ForExpansionTooLarge {
+                repetitions,
+                max: self.limits.max_repetitions,
+                span: None,
+            });
+        }
 
         self.for_stack.push(ForStatement {
             index_label: label.into(),
@@ -398,15 +625,40 @@ impl Collector {
             start_line: line,
             start_offset: offset,
         });
+
+        Ok(())
     }
 
-    fn pop_for(&mut self) -> ForStatement {
-        let for_stmt = self.for_stack.pop().unwrap();
-        if let Some(label) = &for_stmt.index_label {
-            self.for_offsets
-                .insert(label.clone(), for_stmt.start_offset);
+    /// Whether `label` is the index label of a loop being expanded.
+    fn is_index(&self, label: &str) -> bool {
+        self.for_stack
+            .iter()
+            .any(|for_stmt| for_stmt.index_label.as_deref() == Some(label))
+    }
+
+    /// Skip a loop until the loop around it has been unrolled.
+    fn defer_for(&mut self) -> Result<(), Error> {
+        self.deferred_depth += 1;
+        self.check_for_depth(self.for_stack.len() + self.deferred_depth)
+    }
+
+    fn check_for_depth(&self, depth: usize) -> Result<(), Error> {
+        if depth > self.limits.max_for_depth {
+            return Err(Error::ForNestingTooDeep {
+                depth,
+                max: self.limits.max_for_depth,
+                span: None,
+            });
         }
-        for_stmt
+        Ok(())
+    }
+
+    fn in_for(&self) -> bool {
+        !self.for_stack.is_empty()
+    }
+
+    fn pop_for(&mut self) -> ForStatement {
+        self.for_stack.pop().unwrap()
     }
 
     fn get_label_value(&self, label: &str, current_offset: u32) -> Option<LabelValue> {
@@ -422,26 +674,89 @@ impl Collector {
                 value
             } else {
                 // Special-case for current line number
-                if label == "CURLINE" {
-                    // Similar to the impl of `default_labels`, use a relative offset
-                    // to avoid translating back to absolute
-                    dbg!(Some(LabelValue::RelativeOffset(current_offset as i32)))
-                } else {
-                    self.for_offsets.get(label).map(|start_offset| {
-                        LabelValue::RelativeOffset((current_offset as i32) - (*start_offset as i32))
-                    })
-                }
+                // Similar to the impl of `default_labels`, use a relative offset
+                // to avoid translating back to absolute
+                Some(LabelValue::RelativeOffset(current_offset as i32))
+                    .filter(|_| label == "CURLINE")
             }
         } else {
             None
         }
     }
 
-    fn finish(mut self) -> Labels {
-        if !self.pending_labels.is_empty() {
-            // TODO #25 warning for empty definition for each pending label
+    /// Check that expanding the substitution for `label` terminates, i.e. it
+    /// does not refer back to itself either directly or through other
+    /// substitutions. `span` is the usage of `label` being expanded.
+    fn check_recursion(&self, label: &str, span: Span) -> Result<(), Error> {
+        let mut chain = vec![label.to_owned()];
+        let mut finite = HashSet::new();
+
+        if !self.find_cycle(&mut chain, &mut finite) {
+            return Ok(());
+        }
+
+        // The chain may lead into a cycle which doesn't include `label` itself
+        let repeated = chain.last().unwrap();
+        let start = chain.iter().position(|lbl| lbl == repeated).unwrap();
+
+        let cycle = chain[start..]
+            .iter()
+            .map(|lbl| (lbl.clone(), self.equ_lines.get(lbl).copied().unwrap_or(0)))
+            .collect();
+
+        Err(Error::RecursiveSubstitution {
+            cycle,
+            span: Some(span.into()),
+        })
+    }
+
+    /// Depth-first search for a cycle of substitutions reachable from the last
+    /// label in `chain`. If found, the chain ends with the repeated label.
+    /// `finite` caches labels already known to expand without any cycles.
+    fn find_cycle(&self, chain: &mut Vec<String>, finite: &mut HashSet<String>) -> bool {
+        let label = chain.last().unwrap().clone();
+
+        let substitution = match self.labels.get(&label) {
+            Some(LabelValue::Substitution(substitution)) => substitution,
+            _ => return false,
+        };
+
+        if finite.contains(&label) {
+            return false;
+        }
+
+        for reference in substitution.iter().flat_map(|text| referenced_labels(text)) {
+            let seen = chain.iter().any(|lbl| lbl == reference);
+            chain.push(reference.to_owned());
+
+            if seen || self.find_cycle(chain, finite) {
+                return true;
+            }
+
+            chain.pop();
         }
 
+        finite.insert(label);
+        false
+    }
+
+    /// Warn about labels which were declared after the last instruction, so
+    /// they have no offset to refer to.
+    fn warn_empty_offsets(&mut self) {
+        let mut pending: Vec<(&String, &ErrorSpan)> = self.pending_labels.iter().collect();
+        pending.sort_by_key(|(_, span)| span.line);
+
+        self.warnings.extend(
+            pending
+                .into_iter()
+                .map(|(label, span)| Warning::EmptyOffset {
+                    label: label.clone(),
+                    span: Some(*span),
+                }),
+        );
+    }
+
+    fn finish(mut self) -> Labels {
         self.labels.extend(
             self.current_equ
                 .take()
@@ -452,6 +767,89 @@ impl Collector {
     }
 }
 
+/// Whether a line starts or ends a `FOR` loop, possibly with a label.
+fn is_for_statement(tokenized_line: &[grammar::Pair]) -> bool {
+    use grammar::Rule;
+
+    tokenized_line
+        .iter()
+        .take(2)
+        .any(|token| matches!(token.as_rule(), Rule::For | Rule::Rof))
+}
+
+/// Replace the index `label` of a `FOR` loop in `line` with the loop counter
+/// `iteration`, both where it is used on its own and where it is
+/// [concatenated](concatenate) to another label.
+fn substitute_index(line: &str, label: &str, iteration: u32) -> String {
+    let line = concatenate(line, label, iteration);
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line.as_str();
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        let len = rest[start..]
+            .find(|c| !is_word(c))
+            .unwrap_or(rest.len() - start);
+        // Letters after a digit are part of a number or a pasted label, and
+        // after a `.` they're a modifier, like the `i` in `mov.i`
+        let in_word = rest[..start].ends_with(|c| is_word(c) || c == '.');
+        let word = &rest[start..start + len];
+
+        result.push_str(&rest[..start]);
+        if word == label && !in_word {
+            result.push_str(&iteration.to_string());
+        } else {
+            result.push_str(word);
+        }
+        rest = &rest[start + len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Replace each `&label` in `line` with the loop counter `iteration`, padded
+/// to two digits like pMARS does. For example, `x&N` becomes `x01` in the
+/// first iteration of a `N FOR` loop.
+fn concatenate(line: &str, label: &str, iteration: u32) -> String {
+    let pattern = format!("&{}", label);
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find(&pattern) {
+        let end = start + pattern.len();
+        // Digits may follow if an inner loop's counter was already pasted,
+        // e.g. `x&I&J` becomes `x&I01` before the outer loop is unrolled
+        let ends_word = !rest[end..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+        // `&&` is the boolean "and" operator, not a concatenation
+        let is_operator = rest[..start].ends_with('&');
+
+        result.push_str(&rest[..start]);
+        if ends_word && !is_operator {
+            result.push_str(&format!("{:02}", iteration));
+        } else {
+            result.push_str(&pattern);
+        }
+        rest = &rest[end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// All identifiers in `text` which could refer to a label. This doesn't
+/// require `text` to be valid on its own, since a substitution can be any
+/// fragment of a line.
+fn referenced_labels(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| {
+            word.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        })
+}
+
 #[cfg(test)]
 mod test {
     use maplit::hashmap;
@@ -462,9 +860,9 @@ mod test {
 
     #[test]
     fn collects_equ() {
-        let mut collector = Collector::new();
+        let mut collector = Collector::new(ExpansionLimits::default());
 
-        collector.process_equ("foo", "1");
+        collector.process_equ("foo", "1", 1, "");
         let labels = collector.finish();
 
         assert_eq!(
@@ -475,10 +873,10 @@ mod test {
 
     #[test]
     fn collects_multi_line_equ() {
-        let mut collector = Collector::new();
+        let mut collector = Collector::new(ExpansionLimits::default());
 
-        collector.process_equ("foo", "mov 1, 1");
-        collector.process_equ_continuation("jne 0, -1");
+        collector.process_equ("foo", "mov 1, 1", 1, "");
+        collector.process_equ_continuation("jne 0, -1").unwrap();
         let labels = collector.finish();
 
         assert_eq!(
@@ -492,15 +890,15 @@ mod test {
 
     #[test]
     fn collects_label_offset() {
-        let mut collector = Collector::new();
+        let mut collector = Collector::new(ExpansionLimits::default());
 
-        collector.add_pending_label("foo");
-        collector.add_pending_label("bar");
+        collector.add_pending_label("foo", ErrorSpan::default());
+        collector.add_pending_label("bar", ErrorSpan::default());
         collector.resolve_pending_labels(1);
 
-        collector.add_pending_label("zip");
-        collector.add_pending_label("zap");
-        collector.add_pending_label("gone");
+        collector.add_pending_label("zip", ErrorSpan::default());
+        collector.add_pending_label("zap", ErrorSpan::default());
+        collector.add_pending_label("gone", ErrorSpan::default());
         let labels = collector.finish();
 
         assert_eq!(Some(&AbsoluteOffset(1)), labels.get("foo"),);
@@ -564,11 +962,21 @@ mod test {
             .map(|s| s.to_string())
             .collect::<Vec<String>>();
 
-        let mut lines = vec![line.to_string()];
+        let mut lines = vec![Line::from(line)];
+        let mut sources = vec![1];
 
-        expand_lines(&mut lines, 0, span, &substitution);
+        expand_lines(&mut lines, &mut sources, 0, span, &substitution);
 
         assert_eq!(lines, expected);
+        assert_eq!(sources, vec![1; expected.len()]);
+    }
+
+    #[test_case("x&N", "N", 1, "x01"; "suffix")]
+    #[test_case("x&N y&N", "N", 12, "x12 y12"; "repeated")]
+    #[test_case("x&NN &M", "N", 1, "x&NN &M"; "other labels")]
+    #[test_case("a&&N", "N", 1, "a&&N"; "boolean and")]
+    fn concatenates_labels(line: &str, label: &str, iteration: u32, expected: &str) {
+        assert_eq!(concatenate(line, label, iteration), expected);
     }
 
     #[test_case(
@@ -634,8 +1042,16 @@ mod test {
         "label with expansion"
     )]
     fn collects_and_expands_labels(lines: &[&str], expected: Labels) {
-        let mut lines = lines.iter().map(|s| s.to_string()).collect();
-        let result = collect_and_expand(&mut lines);
+        let mut lines: Vec<Line> = lines.iter().map(|&s| s.into()).collect();
+        let mut sources = (1..=lines.len()).collect();
+        let result = collect_and_expand(
+            &mut lines,
+            &mut sources,
+            "",
+            &ExpansionLimits::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
 
         for (k, v) in expected.iter() {
             assert_eq!(Some(v), result.get(k));
@@ -724,13 +1140,73 @@ mod test {
             "rof",
         ],
         &[
-            "mov 0, 0", // base
-            "mov -1, 1",
-            "mov -2, 2",
-            "mov -3, 3",
+            "mov 0, 1", // base
+            "mov -1, 2",
+            "mov -2, 3",
+            "mov -3, 4",
         ];
         "repeat index"
     )]
+    #[test_case(
+        &[
+            "i for 2",
+            "j for 2",
+            "x&i&j mov.i i, j*10",
+            "rof",
+            "rof",
+            "jmp x0201",
+        ],
+        &[
+            "mov.i 1, 1*10",
+            "mov.i 1, 2*10",
+            "mov.i 2, 1*10",
+            "mov.i 2, 2*10",
+            "jmp -2",
+        ];
+        "nested index"
+    )]
+    #[test_case(
+        &[
+            "i for 2",
+            "j for i",
+            "dat i, j",
+            "rof",
+            "rof",
+        ],
+        &[
+            "dat 1, 1",
+            "dat 2, 1",
+            "dat 2, 2",
+        ];
+        "count uses outer index"
+    )]
+    #[test_case(
+        &[
+            "prime01 equ 2",
+            "prime02 equ 3",
+            "N for 2",
+            "dat prime&N, N_",
+            "rof",
+        ],
+        &[
+            "dat 2, N_",
+            "dat 3, N_",
+        ];
+        "concatenate index"
+    )]
+    #[test_case(
+        &[
+            "bombs equ N for 2",
+            "equ dat N, 0",
+            "equ rof",
+            "bombs",
+        ],
+        &[
+            "dat 1, 0",
+            "dat 2, 0",
+        ];
+        "multiline equ for"
+    )]
     #[test_case(
         &[
             "foo equ mov 0, 1",
@@ -776,8 +1252,16 @@ mod test {
         "expand expr labels"
     )]
     fn collects_and_expands_forrof(lines: &[&str], expected: &[&str]) {
-        let mut lines = lines.iter().map(|s| s.to_string()).collect();
-        let _ = collect_and_expand(&mut lines);
+        let mut lines: Vec<Line> = lines.iter().map(|&s| s.into()).collect();
+        let mut sources = (1..=lines.len()).collect();
+        let _ = collect_and_expand(
+            &mut lines,
+            &mut sources,
+            "",
+            &ExpansionLimits::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
 
         let expected_lines: Vec<String> = expected.iter().map(|s| s.to_string()).collect();
 
@@ -877,17 +1361,49 @@ mod test {
         ];
         "expand default labels"
     )]
+    #[test_case(
+        &[
+            "N for 2",
+            "lbl&N mov 0, 1",
+            "rof",
+            "jmp lbl01",
+            "jmp lbl02",
+        ],
+        &[
+            "mov 0, 1",
+            "mov 0, 1",
+            "jmp -2",
+            "jmp -2",
+        ];
+        "concatenated label"
+    )]
+    #[test_case(
+        &[
+            "I for 2",
+            "J for 2",
+            "x&I&J dat 0, 0",
+            "rof",
+            "rof",
+            "dat x0102, x0201",
+        ],
+        &[
+            "dat 0, 0",
+            "dat 0, 0",
+            "dat 0, 0",
+            "dat 0, 0",
+            "dat -3, -2",
+        ];
+        "nested concatenated label"
+    )]
     fn expands_substitutions(lines: &[&str], expected: &[&str]) {
-        let lines = lines.iter().map(|s| s.to_string()).collect();
+        let lines: Vec<Line> = lines.iter().map(|&s| s.into()).collect();
+        let sources = (1..=lines.len()).collect();
         let expected: Vec<String> = expected.iter().map(|s| s.to_string()).collect();
 
-        assert_eq!(
-            Lines {
-                text: expected,
-                origin: None,
-            },
-            expand(lines, None),
-        );
+        let result = expand(lines, sources, None, "", &ExpansionLimits::default()).unwrap();
+
+        assert_eq!(result.text, expected);
+        assert_eq!(result.origin, None);
     }
 
     #[test_case(
@@ -956,15 +1472,229 @@ mod test {
         origin: Option<String>,
         expected_origin: Option<String>,
     ) {
-        let lines = lines.iter().map(|s| s.to_string()).collect();
+        let lines: Vec<Line> = lines.iter().map(|&s| s.into()).collect();
+        let sources = (1..=lines.len()).collect();
         let expected: Vec<String> = expected_lines.iter().map(|s| s.to_string()).collect();
 
+        let origin = origin.map(Line::from);
+        let result = expand(lines, sources, origin, "", &ExpansionLimits::default()).unwrap();
+
+        assert_eq!(result.text, expected);
+        assert_eq!(result.origin.as_deref(), expected_origin.as_deref());
+    }
+
+    #[test]
+    fn borrows_unchanged_lines() {
+        let buffer = "step equ 4\nstart add #step, 1\nnop 0, 0\nfor 2\ndat 0, 0\nrof";
+        let lines = buffer.lines().map(Line::from).collect();
+
+        let result = expand(
+            lines,
+            (1..=6).collect(),
+            None,
+            buffer,
+            &ExpansionLimits::default(),
+        )
+        .unwrap();
+
         assert_eq!(
-            expand(lines, origin),
-            Lines {
-                text: expected,
-                origin: expected_origin,
+            result.text,
+            vec!["add #4, 1", "nop 0, 0", "dat 0, 0", "dat 0, 0"]
+        );
+        let borrowed: Vec<bool> = result
+            .text
+            .iter()
+            .map(|line| matches!(line, Cow::Borrowed(_)))
+            .collect();
+        assert_eq!(borrowed, vec![false, true, true, true]);
+    }
+
+    #[test]
+    fn tracks_source_lines() {
+        let lines = [
+            "do_thing equ mov 1, 2",
+            "equ mov 3, 4",
+            "for 2",
+            "nop 0, 0",
+            "rof",
+            "do_thing",
+        ];
+        let lines = lines.iter().map(|&s| s.into()).collect();
+
+        let result = expand(
+            lines,
+            vec![1, 2, 4, 5, 6, 8],
+            None,
+            "",
+            &ExpansionLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.source_lines, vec![5, 5, 8, 8]);
+    }
+
+    #[test]
+    fn warns_about_empty_definitions() {
+        let buffer = "empty equ\nmov 0, 1\n  last ; nothing after";
+        let lines = vec!["empty equ".into(), "mov 0, 1".into(), "last".into()];
+
+        let result = expand(
+            lines,
+            vec![1, 2, 3],
+            None,
+            buffer,
+            &ExpansionLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.warnings,
+            vec![
+                Warning::EmptySubstitution {
+                    label: "empty".into(),
+                    span: Some(ErrorSpan::new(1, 0, 9)),
+                },
+                Warning::EmptyOffset {
+                    label: "last".into(),
+                    span: Some(ErrorSpan::new(3, 2, 6)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn equ_without_label_error() {
+        let lines = vec!["equ 1".into()];
+
+        let err = expand(lines, vec![1], None, "equ 1", &ExpansionLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::EquWithoutLabel {
+                span: Some(ErrorSpan::new(1, 0, 5)),
+            }
+        );
+    }
+
+    #[test_case("@@@", ErrorSpan::new(1, 0, 3); "invalid syntax")]
+    #[test_case("x equ 1\nx , 0", ErrorSpan::new(2, 0, 5); "substituted into invalid syntax")]
+    #[test_case("x equ 1\n equ 2\ndat x, 0", ErrorSpan::new(3, 0, 8); "continuation as operand")]
+    #[test_case("y equ dat 0, 0\n equ\ny", ErrorSpan::new(3, 0, 1); "empty continuation")]
+    fn unparseable_line_error(buffer: &str, span: ErrorSpan) {
+        let lines: Vec<Line> = buffer.lines().map(|s| s.trim().into()).collect();
+        let sources = (1..=lines.len()).collect();
+
+        let err = expand(lines, sources, None, buffer, &ExpansionLimits::default()).unwrap_err();
+
+        assert_eq!(err.span(), Some(&span));
+    }
+
+    #[test]
+    fn missing_label_error() {
+        let buffer = "mov 0, 1\n  nop 0, missing ; comment";
+        let lines = vec!["mov 0, 1".into(), "nop 0, missing".into()];
+
+        let err = expand(lines, vec![1, 2], None, buffer, &ExpansionLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::LabelNotFound {
+                label: "missing".into(),
+                span: Some(ErrorSpan::new(2, 9, 16)),
+            }
+        );
+
+        // The span is in the line as written, even after an earlier label
+        // in it was replaced by a shorter offset
+        let buffer = "start nop start, missing";
+        let lines = vec!["start nop start, missing".into()];
+        let err = expand(lines, vec![1], None, buffer, &ExpansionLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::LabelNotFound {
+                label: "missing".into(),
+                span: Some(ErrorSpan::new(1, 17, 24)),
+            }
+        );
+    }
+
+    #[test_case(
+        "foo equ foo + 1\ndat foo, 0",
+        &[("foo", 1), ("foo", 1)],
+        ErrorSpan::new(2, 4, 7);
+        "direct"
+    )]
+    #[test_case(
+        "a equ b\nb equ 1 + a\nmov a, 1",
+        &[("a", 1), ("b", 2), ("a", 1)],
+        ErrorSpan::new(3, 4, 5);
+        "mutual"
+    )]
+    #[test_case(
+        "a equ b\nb equ c\nc equ b\n  a",
+        &[("b", 2), ("c", 3), ("b", 2)],
+        ErrorSpan::new(4, 2, 3);
+        "indirect statement"
+    )]
+    fn recursive_equ_error(buffer: &str, cycle: &[(&str, usize)], span: ErrorSpan) {
+        let lines: Vec<Line> = buffer.lines().map(|s| s.trim().into()).collect();
+        let sources = (1..=lines.len()).collect();
+
+        let err = expand(lines, sources, None, buffer, &ExpansionLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::RecursiveSubstitution {
+                cycle: cycle
+                    .iter()
+                    .map(|&(label, line)| (label.to_string(), line))
+                    .collect(),
+                span: Some(span),
             }
         );
     }
+
+    #[test_case(
+        "for 2\nfor 2\nfor 2\nnop 0, 0\nrof\nrof\nrof",
+        Error::ForNestingTooDeep {
+            depth: 3,
+            max: 2,
+            span: Some(ErrorSpan::new(3, 0, 5)),
+        };
+        "nesting"
+    )]
+    #[test_case(
+        "for 4\nN for 3\nnop 0, 0\nrof\nrof",
+        Error::ForExpansionTooLarge {
+            repetitions: 12,
+            max: 10,
+            span: Some(ErrorSpan::new(2, 0, 7)),
+        };
+        "repetitions"
+    )]
+    fn for_limit_error(buffer: &str, expected: Error) {
+        let lines: Vec<Line> = buffer.lines().map(Line::from).collect();
+        let sources = (1..=lines.len()).collect();
+        let limits = ExpansionLimits {
+            max_for_depth: 2,
+            max_repetitions: 10,
+            ..ExpansionLimits::default()
+        };
+
+        let err = expand(lines, sources, None, buffer, &limits).unwrap_err();
+
+        assert_eq!(err, expected);
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let limits = ExpansionLimits::default();
+        limits.cancellation.cancel();
+
+        let lines = vec!["nop 0, 0".into()];
+        let err = expand(lines, vec![1], None, "nop 0, 0", &limits).unwrap_err();
+
+        assert_eq!(err, Error::Cancelled);
+    }
 }
@@ -0,0 +1,169 @@
+//! Renaming labels and `EQU` definitions throughout a source file, e.g. for
+//! an editor's "rename symbol". Every renamed identifier is replaced where it
+//! is declared and wherever it is used, and the rest of the source is kept
+//! exactly as it was, including comments and whitespace:
+//!
+//! ```
+//! let source = "step  equ 4\nstart add #step, start ; step forward\n";
+//! let renamed = corewars_parser::rename(source, &[("step", "stride")]).unwrap();
+//! assert_eq!(renamed, "stride  equ 4\nstart add #stride, start ; step forward\n");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error as ThisError;
+
+use corewars_core::load_file::DEFAULT_CONSTANTS;
+
+use super::link::{declared_labels, identifiers, is_identifier, is_opcode};
+
+/// Why a set of renames could not be applied.
+#[derive(ThisError, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The label to rename is not declared in the source.
+    #[error("{0:?} is not declared as a label or EQU")]
+    Undeclared(String),
+
+    /// The new name is not a valid label, e.g. it is an opcode.
+    #[error("{0:?} cannot be used as a label")]
+    InvalidName(String),
+
+    /// The new name is already used in the source, or by another rename.
+    #[error("cannot rename {label:?} to {name:?}, which is already used")]
+    Collision { label: String, name: String },
+}
+
+/// Rename each label in `renames` to its new name throughout `source`. The
+/// renames happen all at once, so two labels can swap names.
+pub fn rename(source: &str, renames: &[(&str, &str)]) -> Result<String, Error> {
+    let declared: HashSet<String> = source
+        .lines()
+        .flat_map(|line| declared_labels(code(line)))
+        .collect();
+    let used: HashSet<&str> = source
+        .lines()
+        .flat_map(|line| references(code(line)))
+        .map(|(_, identifier)| identifier)
+        .collect();
+
+    let mut names = HashMap::new();
+    for &(label, name) in renames {
+        if !declared.contains(label) {
+            return Err(Error::Undeclared(label.to_string()));
+        }
+        if !is_identifier(name)
+            || is_opcode(name)
+            || name.eq_ignore_ascii_case("ROF")
+            || DEFAULT_CONSTANTS.contains_key(name)
+        {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+
+        let renamed_away = renames.iter().any(|&(other, _)| other == name);
+        let shared = renames.iter().filter(|&&(_, other)| other == name).count() > 1;
+        if shared || (used.contains(name) && !renamed_away) {
+            return Err(Error::Collision {
+                label: label.to_string(),
+                name: name.to_string(),
+            });
+        }
+        names.insert(label, name);
+    }
+
+    let mut renamed = String::with_capacity(source.len());
+    for line in source.split_inclusive('\n') {
+        let mut end = 0;
+        for (start, identifier) in references(code(line)) {
+            if let Some(name) = names.get(identifier) {
+                renamed.push_str(&line[end..start]);
+                renamed.push_str(name);
+                end = start + identifier.len();
+            }
+        }
+        renamed.push_str(&line[end..]);
+    }
+
+    Ok(renamed)
+}
+
+/// The part of `line` before any comment.
+fn code(line: &str) -> &str {
+    line.split(';').next().unwrap_or_default()
+}
+
+/// The identifiers in `line` which could be labels, i.e. not modifiers such
+/// as the `ab` of `mov.ab`.
+fn references(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    identifiers(line).filter(move |&(start, _)| !line[..start].ends_with('.'))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const DWARF: &str = ";redcode-94
+;name Dwarf
+step    equ 4
+ab      dat #0, #0    ; the bomb, also known as ab
+start   add.ab #step, ab
+        mov.ab ab, @ab
+        jmp start
+        end start
+";
+
+    #[test]
+    fn renames_labels() {
+        let renamed = rename(DWARF, &[("ab", "bomb"), ("start", "loop")]).unwrap();
+
+        assert_eq!(
+            renamed,
+            ";redcode-94
+;name Dwarf
+step    equ 4
+bomb      dat #0, #0    ; the bomb, also known as ab
+loop   add.ab #step, bomb
+        mov.ab bomb, @bomb
+        jmp loop
+        end loop
+"
+        );
+        crate::parse(&renamed).expect("Failed to parse renamed warrior");
+    }
+
+    #[test]
+    fn swaps_labels() {
+        let renamed = rename("a dat 0, b\nb dat 0, a", &[("a", "b"), ("b", "a")]).unwrap();
+        assert_eq!(renamed, "b dat 0, a\na dat 0, b");
+    }
+
+    #[test]
+    fn rejects_conflicts() {
+        assert_eq!(
+            rename(DWARF, &[("bomb", "shell")]),
+            Err(Error::Undeclared("bomb".into()))
+        );
+        assert_eq!(
+            rename(DWARF, &[("ab", "step")]),
+            Err(Error::Collision {
+                label: "ab".into(),
+                name: "step".into(),
+            })
+        );
+        assert_eq!(
+            rename(DWARF, &[("ab", "bomb"), ("step", "bomb")]),
+            Err(Error::Collision {
+                label: "ab".into(),
+                name: "bomb".into(),
+            })
+        );
+        for name in ["mov", "CORESIZE", "2nd", "rof"] {
+            assert_eq!(
+                rename(DWARF, &[("ab", name)]),
+                Err(Error::InvalidName(name.into()))
+            );
+        }
+    }
+}
@@ -29,6 +29,21 @@ impl<T> Result<T> {
         Self::Err(err, Vec::new())
     }
 
+    /// Combine a result with the warnings produced along the way.
+    pub(crate) fn with_warnings(result: StdResult<T, Error>, warnings: Vec<Warning>) -> Self {
+        match result {
+            Ok(value) => Self::Ok(value, warnings),
+            Err(err) => Self::Err(err, warnings),
+        }
+    }
+
+    /// The warnings carried by either variant.
+    pub fn warnings(&self) -> &[Warning] {
+        match self {
+            Self::Ok(_, warnings) | Self::Err(_, warnings) => warnings,
+        }
+    }
+
     /// Unwrap the parse result, panicking if it was not an `Ok`.
     pub fn unwrap(self) -> T {
         match self {
@@ -0,0 +1,257 @@
+//! Substituting `${NAME}` with the value of a variable, e.g. from the
+//! environment, to parameterize warriors from scripts. This is opt-in, and
+//! happens right after comments are removed.
+
+use std::fmt;
+
+use super::error::{Error, Span};
+use super::phase::CommentsRemoved;
+
+/// Looks up the value of a variable by name, returning `None` if it is not set.
+pub type Lookup = dyn Fn(&str) -> Option<String>;
+
+/// The variables that may be substituted into the input, if enabled.
+#[derive(Default)]
+pub struct Variables {
+    lookup: Option<Box<Lookup>>,
+}
+
+impl Variables {
+    /// Substitute variables with the values from `lookup`.
+    pub fn new<F>(lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + 'static,
+    {
+        Self {
+            lookup: Some(Box::new(lookup)),
+        }
+    }
+
+    /// Replace every `${NAME}` in the lines and origin of `state`. Any
+    /// variables which aren't set are listed in the error, which points at
+    /// the first of them. A `${NAME` without its closing `}` is an error too.
+    pub(crate) fn substitute(
+        &self,
+        state: &mut CommentsRemoved,
+        buffer: &str,
+    ) -> Result<(), Error> {
+        let lookup = match &self.lookup {
+            Some(lookup) => lookup,
+            None => return Ok(()),
+        };
+
+        let mut unresolved: Vec<String> = Vec::new();
+        let mut first_unresolved = None;
+
+        for (line, &source_line) in state.lines.iter_mut().zip(&state.source_lines) {
+            // Lines without variables can keep borrowing from the input
+            if !line.contains("${") {
+                continue;
+            }
+            let (substituted, missing) =
+                substitute_line(line, lookup.as_ref()).map_err(|(name, span)| {
+                    Error::UnterminatedVariable {
+                        name,
+                        span: Some(span),
+                    }
+                    .locate(source_line, line, buffer)
+                })?;
+
+            if let Some((_, span)) = missing.first().filter(|_| first_unresolved.is_none()) {
+                first_unresolved = Some((*span, source_line, line.to_string()));
+            }
+            for (name, _) in missing {
+                if !unresolved.contains(&name) {
+                    unresolved.push(name);
+                }
+            }
+
+            *line = substituted.into();
+        }
+
+        if let Some(origin) = state.origin.as_mut() {
+            let (substituted, missing) = substitute_line(origin, lookup.as_ref())
+                .map_err(|(name, _)| Error::UnterminatedVariable { name, span: None })?;
+            for (name, _) in missing {
+                if !unresolved.contains(&name) {
+                    unresolved.push(name);
+                }
+            }
+            *origin = substituted.into();
+        }
+
+        match first_unresolved {
+            Some((span, source_line, line)) => Err(Error::UnresolvedVariables {
+                names: unresolved,
+                span: Some(span),
+            }
+            .locate(source_line, &line, buffer)),
+            None if !unresolved.is_empty() => Err(Error::UnresolvedVariables {
+                names: unresolved,
+                span: None,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for Variables {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Variables")
+            .field("enabled", &self.lookup.is_some())
+            .finish()
+    }
+}
+
+/// The name of a variable and its span in a line.
+type Located = (String, Span);
+
+/// Substitute the variables in a single line, returning the new line and
+/// the unresolved variables, with their spans relative to `line`. If a
+/// variable isn't closed, its name and span are returned instead.
+fn substitute_line(line: &str, lookup: &Lookup) -> Result<(String, Vec<Located>), Located> {
+    let mut substituted = String::with_capacity(line.len());
+    let mut missing = Vec::new();
+    let mut rest = line;
+    let mut offset = 0;
+
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        let len = after
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(after.len());
+        let name = Some(&after[..len]).filter(|name| is_name(name));
+
+        if name.is_some() && !after[len..].starts_with('}') {
+            let span = Span::new(0, offset + start, offset + start + len + 2);
+            return Err((after[..len].to_string(), span));
+        }
+
+        let name = match name {
+            Some(name) => name,
+            None => {
+                // Not a variable, so leave it for the grammar to reject
+                substituted.push_str(&rest[..start + 2]);
+                offset += start + 2;
+                rest = after;
+                continue;
+            }
+        };
+
+        let end = start + name.len() + 3;
+        substituted.push_str(&rest[..start]);
+
+        match lookup(name) {
+            Some(value) => substituted.push_str(&value),
+            None => {
+                missing.push((name.to_string(), Span::new(0, offset + start, offset + end)));
+                substituted.push_str(&rest[start..end]);
+            }
+        }
+
+        offset += end;
+        rest = &rest[end..];
+    }
+
+    substituted.push_str(rest);
+    Ok((substituted, missing))
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "STEP" => Some("4".into()),
+            "BOMB_AT" => Some("bomb".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let (line, missing) = substitute_line("add #${STEP}, ${BOMB_AT}", &lookup).unwrap();
+        assert_eq!(line, "add #4, bomb");
+        assert!(missing.is_empty());
+
+        // Anything else which looks a bit like a variable is left alone
+        let (line, missing) = substitute_line("mov ${, ${1} $0", &lookup).unwrap();
+        assert_eq!(line, "mov ${, ${1} $0");
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn lists_unresolved_variables() {
+        let buffer = "mov ${STEP}, 1\n  jmp ${A}, ${B}\ndat ${A}, 0\nend ${C}";
+        let mut state = CommentsRemoved {
+            lines: vec![
+                "mov ${STEP}, 1".into(),
+                "jmp ${A}, ${B}".into(),
+                "dat ${A}, 0".into(),
+            ],
+            source_lines: vec![1, 2, 3],
+            origin: Some("${C}".into()),
+            ..CommentsRemoved::default()
+        };
+
+        let error = Variables::new(lookup)
+            .substitute(&mut state, buffer)
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            Error::UnresolvedVariables {
+                names: vec!["A".into(), "B".into(), "C".into()],
+                span: Some(Span::new(2, 6, 10)),
+            }
+        );
+        assert_eq!(error.to_string(), "unresolved variables: A, B, C");
+        assert_eq!(state.lines[0], "mov 4, 1");
+    }
+
+    #[test]
+    fn rejects_unterminated_variables() {
+        assert_eq!(
+            substitute_line("add #${STEP}, ${BOMB_AT", &lookup),
+            Err(("BOMB_AT".into(), Span::new(0, 14, 23)))
+        );
+        assert_eq!(
+            substitute_line("add #${STEP, 1", &lookup),
+            Err(("STEP".into(), Span::new(0, 5, 11)))
+        );
+
+        let buffer = "mov 0, 1\n  add #${STEP, 1";
+        let mut state = CommentsRemoved {
+            lines: vec!["mov 0, 1".into(), "add #${STEP, 1".into()],
+            source_lines: vec![1, 2],
+            ..CommentsRemoved::default()
+        };
+        let error = Variables::new(lookup)
+            .substitute(&mut state, buffer)
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            Error::UnterminatedVariable {
+                name: "STEP".into(),
+                span: Some(Span::new(2, 7, 13)),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "unterminated variable ${STEP: expected }"
+        );
+    }
+}
@@ -10,6 +10,8 @@ use corewars_parser::Result as ParseResult;
 #[test_resources("testdata/input/simple/*.redcode")]
 #[test_resources("testdata/input/wilkie/*.redcode")]
 #[test_resources("testdata/input/wilmoo/*.redcode")]
+#[test_resources("testdata/input/unimplemented/wilkie/pswing.redcode")]
+#[test_resources("testdata/input/unimplemented/wilkie/time.redcode")]
 fn read_dir(input_file: &str) {
     // Workaround for the fact that `test_resources` paths are based on workspace Cargo.toml
     // https://github.com/frehberg/test-generator/issues/6
@@ -42,3 +44,87 @@ fn read_dir(input_file: &str) {
 
     assert_eq!(expected_lines, actual_lines);
 }
+
+#[test_resources("testdata/input/simple/*.redcode")]
+#[test_resources("testdata/input/wilkie/*.redcode")]
+#[test_resources("testdata/input/wilmoo/*.redcode")]
+#[test_resources("testdata/input/unimplemented/wilkie/pswing.redcode")]
+#[test_resources("testdata/input/unimplemented/wilkie/time.redcode")]
+fn preprocess_roundtrip(input_file: &str) {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    std::env::set_current_dir(current_dir).unwrap();
+
+    let input = fs::read_to_string(input_file)
+        .unwrap_or_else(|err| panic!("Unable to read file {:?}: {:?}", input_file, err));
+
+    let preprocessed = match corewars_parser::preprocess(&input) {
+        ParseResult::Ok(output, _) => output,
+        ParseResult::Err(e, _) => panic!("Preprocess error:\n{}", e),
+    };
+
+    // Assembling the preprocessed output should give the same warrior
+    let parse = |text: &str| match corewars_parser::parse(text) {
+        ParseResult::Ok(core, _) => core,
+        ParseResult::Err(e, _) => panic!("Parse error:\n{}\n{}", e, text),
+    };
+
+    assert_eq!(parse(&input).to_string(), parse(&preprocessed).to_string());
+}
+
+#[test_resources("testdata/input/simple/*.redcode")]
+#[test_resources("testdata/input/wilkie/*.redcode")]
+#[test_resources("testdata/input/wilmoo/*.redcode")]
+fn load_file_roundtrip(input_file: &str) {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    std::env::set_current_dir(current_dir).unwrap();
+
+    let input = fs::read_to_string(input_file)
+        .unwrap_or_else(|err| panic!("Unable to read file {:?}: {:?}", input_file, err));
+
+    let dumped = match corewars_parser::parse(&input) {
+        ParseResult::Ok(core, _) => core.to_string(),
+        ParseResult::Err(e, _) => panic!("Parse error:\n{}", e),
+    };
+
+    // Our own output should load back as the same warrior
+    let loaded = match corewars_parser::parse_load_file(&dumped) {
+        ParseResult::Ok(core, _) => core,
+        ParseResult::Err(e, _) => panic!("Load file error:\n{}\n{}", e, dumped),
+    };
+
+    assert_eq!(dumped, loaded.to_string());
+}
+
+#[test]
+fn archive_roundtrip() {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+
+    let mut warriors = Vec::new();
+    for dir in &["simple", "wilkie", "wilmoo"] {
+        let mut paths: Vec<PathBuf> = fs::read_dir(current_dir.join("testdata/input").join(dir))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let input = fs::read_to_string(&path).unwrap();
+            match corewars_parser::parse(&input) {
+                ParseResult::Ok(warrior, _) => warriors.push(warrior),
+                ParseResult::Err(e, _) => panic!("Parse error in {:?}:\n{}", path, e),
+            }
+        }
+    }
+
+    let archive = corewars_parser::archive(&warriors);
+    assert_eq!(corewars_parser::split_archive(&archive).concat(), archive);
+
+    let parsed = match corewars_parser::parse_archive(&archive) {
+        ParseResult::Ok(parsed, _) => parsed,
+        ParseResult::Err(e, _) => panic!("Parse error:\n{}", e),
+    };
+
+    let dump =
+        |warriors: &[_]| -> Vec<String> { warriors.iter().map(ToString::to_string).collect() };
+    assert_eq!(dump(&warriors), dump(&parsed));
+}
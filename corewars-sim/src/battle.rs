@@ -0,0 +1,788 @@
+//! Battles between multiple warriors sharing a single core.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+use thiserror::Error as ThisError;
+
+use corewars_core::load_file::DEFAULT_CONSTANTS;
+use corewars_core::{CancellationToken, Warrior};
+
+use crate::core::{Backend, Core, Error, FieldRange, Scheduler, WarriorHandle};
+use crate::event::Event;
+use crate::faults::Faults;
+use crate::snapshot::CoreSnapshot;
+use crate::victory::{LastStanding, VictoryCondition};
+
+/// How to decide the outcome of a battle when more than one warrior survives
+/// until the maximum number of cycles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// All surviving warriors tie, as in ICWS and most hills
+    #[default]
+    Tie,
+
+    /// The survivor with the most processes wins
+    Processes,
+
+    /// The survivor which owns the most instructions in the core wins
+    Territory,
+}
+
+/// Settings for a battle. Defaults match the ICWS '94 standard hill.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BattleConfig {
+    pub core_size: u32,
+
+    /// The number of cycles after which the battle ends with the survivors
+    pub max_cycles: usize,
+
+    /// The most processes each warrior may have, after which its `SPL`
+    /// instructions no longer start new ones
+    pub max_processes: usize,
+
+    pub tie_break: TieBreak,
+
+    /// The most warriors which may battle in one core
+    pub max_warriors: usize,
+
+    /// The most instructions a warrior may have
+    pub max_length: u32,
+
+    /// The minimum distance between the first instructions of any two
+    /// warriors, which must be at least `max_length` so warriors never overlap
+    pub min_distance: u32,
+
+    /// How the core stores its instructions
+    pub backend: Backend,
+
+    /// What to do with field values too big for the core
+    pub field_range: FieldRange,
+}
+
+impl Default for BattleConfig {
+    fn default() -> Self {
+        Self {
+            core_size: DEFAULT_CONSTANTS["CORESIZE"],
+            max_cycles: DEFAULT_CONSTANTS["MAXCYCLES"] as usize,
+            max_processes: DEFAULT_CONSTANTS["MAXPROCESSES"] as usize,
+            tie_break: TieBreak::default(),
+            // The same limit as pMARS
+            max_warriors: 36,
+            max_length: DEFAULT_CONSTANTS["MAXLENGTH"],
+            min_distance: DEFAULT_CONSTANTS["MINDISTANCE"],
+            backend: Backend::default(),
+            field_range: FieldRange::default(),
+        }
+    }
+}
+
+impl BattleConfig {
+    /// Check that `warriors` warriors can be placed in the core, each
+    /// separated from the others by at least `min_distance`.
+    pub fn validate(&self, warriors: usize) -> Result<(), ConfigError> {
+        if warriors > self.max_warriors {
+            return Err(ConfigError::TooManyWarriors {
+                warriors,
+                max: self.max_warriors,
+            });
+        }
+
+        if self.min_distance < self.max_length {
+            return Err(ConfigError::DistanceTooShort {
+                min_distance: self.min_distance,
+                max_length: self.max_length,
+            });
+        }
+
+        if warriors as u64 * u64::from(self.min_distance) > u64::from(self.core_size) {
+            return Err(ConfigError::DoesNotFit {
+                warriors,
+                min_distance: self.min_distance,
+                core_size: self.core_size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// An error setting up a battle with a [`BattleConfig`](BattleConfig).
+#[derive(ThisError, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    #[error("cannot battle {warriors} warriors, the maximum is {max}")]
+    TooManyWarriors { warriors: usize, max: usize },
+
+    #[error(
+        "minimum distance {min_distance} is less than the maximum length {max_length}, \
+         so warriors could overlap"
+    )]
+    DistanceTooShort { min_distance: u32, max_length: u32 },
+
+    #[error(
+        "{warriors} warriors at least {min_distance} apart do not fit in a core of size \
+         {core_size}"
+    )]
+    DoesNotFit {
+        warriors: usize,
+        min_distance: u32,
+        core_size: u32,
+    },
+
+    #[error("warrior {name} has {length} instructions, the maximum is {max}")]
+    WarriorTooLong { name: String, length: u32, max: u32 },
+
+    #[error(transparent)]
+    Load(#[from] Error),
+}
+
+/// The result of a battle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// A single warrior won, either by outliving all others or by a tie-break
+    Win(String),
+
+    /// The battle ended with these warriors tied. This is empty if every
+    /// warrior died, which is only possible if there was a single warrior.
+    Tie(Vec<String>),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Win(name) => write!(formatter, "{} wins", name),
+            Self::Tie(names) if names.is_empty() => write!(formatter, "no warriors survived"),
+            Self::Tie(names) => write!(formatter, "tie between {}", names.join(", ")),
+        }
+    }
+}
+
+/// An observer passed to [`Battle::run_observed`] panicked. The battle
+/// carries on without it, since the core is only changed between calls to
+/// the observer, so the outcome is the same as if it hadn't been observed.
+#[derive(ThisError, Clone, Debug, PartialEq, Eq)]
+#[error("observer panicked after cycle {cycle}: {message}")]
+pub struct ObserverPanic {
+    /// The number of cycles executed when the observer panicked
+    pub cycle: usize,
+
+    /// The message the observer panicked with, if it was a string
+    pub message: String,
+}
+
+/// A battle between warriors loaded into the same core.
+#[derive(Debug)]
+pub struct Battle {
+    config: BattleConfig,
+    core: Core,
+    victory: Box<dyn VictoryCondition>,
+    faults: Option<Faults>,
+    observer_panic: Option<ObserverPanic>,
+}
+
+/// Redcode simulators are traditionally called a MARS (Memory Array Redcode
+/// Simulator). A [`Battle`] is one: it owns the [`Core`], each warrior's
+/// processes, and decides the outcome.
+pub type Mars = Battle;
+
+impl Battle {
+    /// Create a battle with an empty core. Unlike a bare [`Core`], it doesn't
+    /// [trace](Core::set_trace) each instruction unless asked to.
+    pub fn new(config: BattleConfig) -> Result<Self, Error> {
+        let mut core = Core::with_backend(config.core_size, config.backend)?;
+        core.set_trace(false);
+        core.set_max_length(config.max_length);
+        core.set_min_distance(config.min_distance);
+        core.set_field_range(config.field_range);
+        core.set_max_processes(config.max_processes);
+
+        Ok(Self {
+            core,
+            config,
+            victory: Box::new(LastStanding),
+            faults: None,
+            observer_panic: None,
+        })
+    }
+
+    pub fn config(&self) -> &BattleConfig {
+        &self.config
+    }
+
+    /// The core the battle takes place in.
+    pub fn core(&self) -> &Core {
+        &self.core
+    }
+
+    /// The core in its final state, once the battle is over, so that
+    /// analyses of it can run concurrently without copying it.
+    pub fn into_snapshot(self) -> CoreSnapshot {
+        CoreSnapshot::from(self.core)
+    }
+
+    /// The core the battle takes place in, e.g. to change its settings
+    /// before running.
+    pub fn core_mut(&mut self) -> &mut Core {
+        &mut self.core
+    }
+
+    /// Replace the scheduler used by the core, see
+    /// [`Core::set_scheduler`](Core::set_scheduler).
+    pub fn set_scheduler<S: Scheduler + 'static>(&mut self, scheduler: S) {
+        self.core.set_scheduler(scheduler);
+    }
+
+    /// Replace the rule which decides when the battle is over before the
+    /// maximum number of cycles. By default, the
+    /// [last warrior standing](LastStanding) wins.
+    pub fn set_victory_condition<V: VictoryCondition + 'static>(&mut self, condition: V) {
+        self.victory = Box::new(condition);
+    }
+
+    /// Inject `faults` into the core as the battle runs, replacing any
+    /// injected before.
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.faults = Some(faults);
+    }
+
+    /// The faults being injected, and those injected so far.
+    pub fn faults(&self) -> Option<&Faults> {
+        self.faults.as_ref()
+    }
+
+    /// Load a warrior into the core at `position`. It must be no longer than
+    /// `max_length`, and at least `min_distance` from the warriors before it.
+    pub fn load(&mut self, warrior: &Warrior, position: u32) -> Result<WarriorHandle, Error> {
+        self.core.load_warrior_at(warrior, position)
+    }
+
+    /// Load all of `warriors`, spread evenly through the core so every pair is
+    /// at least `min_distance` apart, after checking the configuration allows
+    /// them. Returns the position each warrior was loaded at.
+    pub fn load_all(&mut self, warriors: &[Warrior]) -> Result<Vec<u32>, ConfigError> {
+        self.config.validate(warriors.len())?;
+
+        for (i, warrior) in warriors.iter().enumerate() {
+            if warrior.len() > self.config.max_length {
+                return Err(ConfigError::WarriorTooLong {
+                    name: warrior
+                        .metadata
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("Warrior{}", i)),
+                    length: warrior.len(),
+                    max: self.config.max_length,
+                });
+            }
+        }
+
+        let count = warriors.len().max(1) as u64;
+        let mut positions = Vec::new();
+        for (i, warrior) in warriors.iter().enumerate() {
+            let position = (i as u64 * u64::from(self.config.core_size) / count) as u32;
+            self.load(warrior, position)?;
+            positions.push(position);
+        }
+
+        Ok(positions)
+    }
+
+    /// Run the battle until the victory condition decides it, or the maximum
+    /// number of cycles is reached.
+    pub fn run(&mut self) -> Outcome {
+        self.run_observed(|_| {})
+    }
+
+    /// Like [`run`](Battle::run), but call `observer` with the state of the
+    /// core after each step, e.g. to record a replay.
+    ///
+    /// If the observer panics, the panic is caught and the battle is marked
+    /// as [errored](Battle::observer_panic): no observer is called again,
+    /// but the battle still runs to the same outcome as without one, so a
+    /// buggy viewer can't change the result.
+    pub fn run_observed<F: FnMut(&Core)>(&mut self, observer: F) -> Outcome {
+        self.run_while(|| true, observer)
+            .expect("battle should only stop at the end")
+    }
+
+    /// Like [`run_observed`](Battle::run_observed), but call `handler` with
+    /// each [event](Event) of the battle as it happens, ending with
+    /// [`Event::RoundEnded`]. Panics are caught the same way.
+    pub fn run_with_events<F: FnMut(&Event)>(&mut self, mut handler: F) -> Outcome {
+        self.core.enable_events();
+        let outcome = self.run_observed(|core| core.last_events().iter().for_each(&mut handler));
+
+        let ended = Event::RoundEnded {
+            cycle: self.core.cycles(),
+            outcome: outcome.clone(),
+        };
+        self.observe(|_| handler(&ended));
+        outcome
+    }
+
+    /// Execute the instruction of the warrior whose turn it is, unless the
+    /// battle is already over. Returns the outcome once the battle is over.
+    pub fn step(&mut self) -> Option<Outcome> {
+        if !self.is_over() {
+            self.execute();
+        }
+
+        if self.is_over() {
+            Some(self.outcome())
+        } else {
+            None
+        }
+    }
+
+    /// Execute up to `cycles` more cycles, stopping early if the battle ends.
+    /// Returns the outcome if the battle is over.
+    pub fn run_for(&mut self, cycles: usize) -> Option<Outcome> {
+        let end = self.core.cycles().saturating_add(cycles);
+        while self.core.cycles() < end {
+            if let Some(outcome) = self.step() {
+                return Some(outcome);
+            }
+        }
+
+        if self.is_over() {
+            Some(self.outcome())
+        } else {
+            None
+        }
+    }
+
+    /// Whether the victory condition has decided the battle, e.g. at most one
+    /// warrior is left alive, or the maximum number of cycles has been reached.
+    pub fn is_over(&self) -> bool {
+        self.core.cycles() >= self.config.max_cycles || self.victory.outcome(&self.core).is_some()
+    }
+
+    /// Like [`run`](Battle::run), but give up if the battle is still going at
+    /// `deadline`. The battle can be resumed by calling this again.
+    pub fn run_until(&mut self, deadline: Instant) -> Option<Outcome> {
+        self.run_while(|| Instant::now() < deadline, |_| {})
+    }
+
+    /// Like [`run`](Battle::run), but give up once `token` is cancelled,
+    /// e.g. from another thread. The token is checked every so often rather
+    /// than every step, so this stops soon after, and the battle can be
+    /// resumed by calling any of the run methods.
+    pub fn run_cancellable(&mut self, token: &CancellationToken) -> Option<Outcome> {
+        self.run_while(|| !token.is_cancelled(), |_| {})
+    }
+
+    /// Run the battle until a warrior first reads or writes an instruction
+    /// owned by another, e.g. to start stepping through it from there. Returns
+    /// `None` if it stopped at the contact, which is then available from
+    /// [`Core::first_contact`](Core::first_contact), or the outcome if the
+    /// battle ended first. The battle can be resumed with any of the other
+    /// run methods.
+    pub fn run_to_contact(&mut self) -> Option<Outcome> {
+        self.core.enable_contact_detection();
+
+        while self.core.first_contact().is_none() {
+            if let Some(outcome) = self.step() {
+                return Some(outcome);
+            }
+        }
+
+        None
+    }
+
+    /// Run the battle while `keep_going` returns true, which is checked every
+    /// [`CHECK_INTERVAL`] steps, and `observer` after every step. Returns
+    /// `None` if it stopped the battle early.
+    fn run_while<F, O>(&mut self, mut keep_going: F, mut observer: O) -> Option<Outcome>
+    where
+        F: FnMut() -> bool,
+        O: FnMut(&Core),
+    {
+        const CHECK_INTERVAL: usize = 1024;
+
+        while !self.is_over() {
+            if self.core.steps_taken().is_multiple_of(CHECK_INTERVAL) && !keep_going() {
+                return None;
+            }
+
+            self.execute();
+            self.observe(&mut observer);
+        }
+
+        Some(self.outcome())
+    }
+
+    /// Call `observer` with the core, unless an observer already panicked.
+    fn observe<O: FnOnce(&Core)>(&mut self, observer: O) {
+        if self.observer_panic.is_none() {
+            let core = &self.core;
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| observer(core))) {
+                self.observer_panic = Some(ObserverPanic {
+                    cycle: self.core.cycles(),
+                    message: panic_message(payload.as_ref()),
+                });
+            }
+        }
+    }
+
+    /// Why observers of this battle are no longer called, if one panicked,
+    /// e.g. to discard a replay which stopped partway through.
+    pub fn observer_panic(&self) -> Option<&ObserverPanic> {
+        self.observer_panic.as_ref()
+    }
+
+    /// Inject any faults due, and execute the next instruction.
+    fn execute(&mut self) {
+        if let Some(faults) = self.faults.as_mut() {
+            faults.inject(&mut self.core);
+        }
+
+        // A process dying is reflected in the outcome, not an error
+        let _ = self.core.step();
+    }
+
+    /// The outcome of the battle given the current state of the core: the
+    /// one decided by the victory condition, if any, or otherwise the
+    /// survivors after the tie-break.
+    pub fn outcome(&self) -> Outcome {
+        if let Some(outcome) = self.victory.outcome(&self.core) {
+            return outcome;
+        }
+
+        let survivors: Vec<_> = self
+            .core
+            .warrior_stats()
+            .into_iter()
+            .filter(|stats| stats.processes > 0)
+            .collect();
+
+        if survivors.len() == 1 && self.core.warriors().len() > 1 {
+            return Outcome::Win(survivors[0].name.clone());
+        }
+
+        let score = |stats: &crate::WarriorStats| match self.config.tie_break {
+            TieBreak::Tie => 0,
+            TieBreak::Processes => stats.processes,
+            TieBreak::Territory => stats.owned_cells,
+        };
+
+        let best = survivors.iter().map(score).max().unwrap_or(0);
+        let mut leaders: Vec<String> = survivors
+            .iter()
+            .filter(|stats| score(stats) == best)
+            .map(|stats| stats.name.clone())
+            .collect();
+
+        if leaders.len() == 1 && self.config.tie_break != TieBreak::Tie {
+            Outcome::Win(leaders.remove(0))
+        } else {
+            Outcome::Tie(leaders)
+        }
+    }
+}
+
+/// The message of a panic, if it was given one.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn battle(tie_break: TieBreak, programs: &[&str]) -> Battle {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 800,
+            max_cycles: 200,
+            tie_break,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            battle
+                .load(&warrior, i as u32 * 400)
+                .expect("Failed to load warrior");
+        }
+
+        battle
+    }
+
+    #[test]
+    fn last_warrior_standing_wins() {
+        use pretty_assertions::assert_eq;
+
+        let mut battle = battle(
+            TieBreak::Tie,
+            &[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"],
+        );
+        assert_eq!(battle.run(), Outcome::Win("Imp".into()));
+    }
+
+    #[test]
+    fn identifies_warriors_by_load_order() {
+        use pretty_assertions::assert_eq;
+
+        let mut duel = battle(
+            TieBreak::Tie,
+            &[";name Same\nmov 0, 1", ";name Same\ndat 0, 0"],
+        );
+        assert!(!duel.core().is_tracing());
+        assert_eq!(duel.core().warriors(), ["Same", "Same"]);
+        assert_eq!(duel.run(), Outcome::Win("Same".into()));
+        assert_eq!(duel.core().steps_taken(), 2);
+
+        let mut imps = battle(
+            TieBreak::Tie,
+            &[";name Same\nmov 0, 1", ";name Same\nmov 0, 1"],
+        );
+        assert_eq!(imps.run(), Outcome::Tie(vec!["Same".into(), "Same".into()]));
+        assert_eq!(imps.core().owned_cells(), [201, 201]);
+    }
+
+    #[test]
+    fn backends_agree() {
+        let warriors = [
+            ";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0",
+            ";name Imp\nmov 0, 1",
+        ];
+
+        let cores: Vec<Core> = Backend::ALL
+            .iter()
+            .map(|&backend| {
+                let mut battle = Battle::new(BattleConfig {
+                    core_size: 800,
+                    max_cycles: 2000,
+                    backend,
+                    ..BattleConfig::default()
+                })
+                .unwrap();
+                let warriors: Vec<Warrior> = warriors
+                    .iter()
+                    .map(|program| corewars_parser::parse(program).unwrap())
+                    .collect();
+                battle.load_all(&warriors).unwrap();
+                battle.run();
+
+                assert_eq!(battle.core().backend(), backend);
+                battle.core().clone()
+            })
+            .collect();
+
+        assert_eq!(cores[0].steps_taken(), cores[1].steps_taken());
+        assert_eq!(cores[0].to_string(), cores[1].to_string());
+    }
+
+    #[test]
+    fn steps_until_over() {
+        let mut duel = battle(
+            TieBreak::Tie,
+            &[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"],
+        );
+
+        assert!(!duel.is_over());
+        assert_eq!(duel.step(), None);
+        assert_eq!(duel.run_for(10), Some(Outcome::Win("Imp".into())));
+        assert_eq!(duel.core().steps_taken(), 2);
+
+        // Nothing more is executed once the battle is over
+        assert_eq!(duel.step(), Some(Outcome::Win("Imp".into())));
+        assert_eq!(duel.core().steps_taken(), 2);
+
+        let mut imps = battle(
+            TieBreak::Tie,
+            &[";name Imp\nmov 0, 1", ";name Imp2\nmov 0, 1"],
+        );
+        assert_eq!(imps.run_for(150), None);
+        assert_eq!(
+            imps.run_for(150),
+            Some(Outcome::Tie(vec!["Imp".into(), "Imp2".into()]))
+        );
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let mut duel = battle(
+            TieBreak::Tie,
+            &[";name Imp\nmov 0, 1", ";name Imp2\nmov 0, 1"],
+        );
+
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(duel.run_cancellable(&token), None);
+        assert_eq!(duel.core().steps_taken(), 0);
+
+        let outcome = duel.run_cancellable(&CancellationToken::new());
+        assert_eq!(
+            outcome,
+            Some(Outcome::Tie(vec!["Imp".into(), "Imp2".into()]))
+        );
+    }
+
+    #[test]
+    fn survives_panicking_observer() {
+        use pretty_assertions::assert_eq;
+
+        let programs = [
+            ";name Imp\nmov 0, 1",
+            ";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2",
+        ];
+        let mut expected = battle(TieBreak::Tie, &programs);
+        let expected = (expected.run(), expected.core().clone());
+
+        let mut observed = battle(TieBreak::Tie, &programs);
+        let mut cycles = 0;
+        let outcome = observed.run_observed(|core| {
+            cycles += 1;
+            if core.steps_taken() == 3 {
+                panic!("viewer bug");
+            }
+        });
+
+        assert_eq!(cycles, 3);
+        assert_eq!(
+            observed.observer_panic(),
+            Some(&ObserverPanic {
+                // The third step is the first of the second cycle
+                cycle: 1,
+                message: "viewer bug".into(),
+            })
+        );
+        assert_eq!((outcome, observed.core().clone()), expected);
+    }
+
+    #[test]
+    fn observes_every_cycle() {
+        let mut battle = battle(
+            TieBreak::Tie,
+            &[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"],
+        );
+
+        let mut cycles = Vec::new();
+        let outcome = battle.run_observed(|core| cycles.push(core.steps_taken()));
+        assert_eq!(outcome, Outcome::Win("Imp".into()));
+        assert_eq!(cycles, vec![1, 2]);
+    }
+
+    #[test_case(TieBreak::Tie, Outcome::Tie(vec!["Imp".into(), "Splitter".into()]); "tie")]
+    #[test_case(TieBreak::Processes, Outcome::Win("Splitter".into()); "processes")]
+    #[test_case(TieBreak::Territory, Outcome::Win("Imp".into()); "territory")]
+    fn tie_break(tie_break: TieBreak, expected: Outcome) {
+        use pretty_assertions::assert_eq;
+
+        let mut battle = battle(
+            tie_break,
+            &[";name Imp\nmov 0, 1", ";name Splitter\nspl 0\njmp -1"],
+        );
+        assert_eq!(battle.run(), expected);
+    }
+
+    #[test]
+    fn limits_processes() {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 800,
+            max_cycles: 200,
+            max_processes: 8,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        let splitter = corewars_parser::parse(";name Splitter\nspl 0\njmp -1").unwrap();
+        battle.load(&splitter, 0).unwrap();
+
+        battle.run();
+        assert_eq!(battle.core().max_processes(), 8);
+        assert_eq!(battle.core().process_queue().thread_count(0), 8);
+    }
+
+    #[test]
+    fn loads_many_warriors() {
+        use pretty_assertions::assert_eq;
+
+        let warriors: Vec<Warrior> = (0..5)
+            .map(|i| corewars_parser::parse(&format!(";name Imp{}\nmov 0, 1", i)).unwrap())
+            .collect();
+
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 1000,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        assert_eq!(
+            battle.load_all(&warriors).unwrap(),
+            vec![0, 200, 400, 600, 800]
+        );
+        assert_eq!(battle.core().warriors().len(), 5);
+    }
+
+    #[test_case(BattleConfig { max_warriors: 4, ..BattleConfig::default() }, 5,
+        ConfigError::TooManyWarriors { warriors: 5, max: 4 }; "too many")]
+    #[test_case(BattleConfig { min_distance: 50, ..BattleConfig::default() }, 2,
+        ConfigError::DistanceTooShort { min_distance: 50, max_length: 100 }; "overlapping")]
+    #[test_case(BattleConfig { core_size: 450, ..BattleConfig::default() }, 5,
+        ConfigError::DoesNotFit { warriors: 5, min_distance: 100, core_size: 450 }; "too small")]
+    fn rejects_impossible_configs(config: BattleConfig, warriors: usize, expected: ConfigError) {
+        use pretty_assertions::assert_eq;
+
+        assert_eq!(config.validate(warriors), Err(expected));
+    }
+
+    #[test]
+    fn pauses_on_contact() {
+        let mut battle = battle(
+            TieBreak::Tie,
+            &[
+                ";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #393",
+                ";name Imp\nmov 0, 1",
+            ],
+        );
+
+        // The dwarf's first bomb lands on the imp
+        assert_eq!(battle.run_to_contact(), None);
+        let contact = battle.core().first_contact().unwrap().clone();
+        assert_eq!(
+            contact.to_string(),
+            "first contact at cycle 1: Dwarf wrote 00400, owned by Imp"
+        );
+        assert_eq!(battle.core().steps_taken(), 3);
+
+        // Running again carries on from the contact
+        assert!(battle.run_to_contact().is_none());
+        assert_eq!(battle.core().first_contact(), Some(&contact));
+        assert!(matches!(battle.run(), Outcome::Win(_) | Outcome::Tie(_)));
+    }
+
+    #[test]
+    fn deadline() {
+        use pretty_assertions::assert_eq;
+
+        let mut battle = battle(
+            TieBreak::Tie,
+            &[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"],
+        );
+        assert_eq!(battle.run_until(Instant::now()), None);
+        assert_eq!(battle.core().steps_taken(), 0);
+
+        let later = Instant::now() + std::time::Duration::from_secs(60);
+        assert_eq!(battle.run_until(later), Some(Outcome::Win("Imp".into())));
+    }
+
+    #[test]
+    fn single_warrior() {
+        use pretty_assertions::assert_eq;
+
+        let mut battle = battle(TieBreak::Processes, &[";name Dies\ndat 0, 0"]);
+        assert_eq!(battle.run(), Outcome::Tie(Vec::new()));
+        assert_eq!(battle.outcome().to_string(), "no warriors survived");
+    }
+}
@@ -0,0 +1,267 @@
+//! A microbenchmark of the execution cost of each opcode and modifier.
+//!
+//! Every measurement starts from a freshly built core filled with the
+//! instruction being timed, with one process per instruction. Each step then
+//! executes exactly that instruction, so no warmup is needed to reach a
+//! steady state. The fastest of several rounds is reported, which filters
+//! out most noise from the rest of the system.
+//!
+//! Core [backends](Backend) are compared by running an imp, which writes a
+//! new instruction every cycle, and timing both the steps and cloning the
+//! core partway through, as taking a snapshot would.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use corewars_core::load_file::{Field, Instruction, Metadata, Modifier, Opcode, Program};
+use corewars_core::Warrior;
+
+use crate::core::{Backend, Core};
+
+/// The measured cost of executing one instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpcodeTiming {
+    pub opcode: Opcode,
+    pub modifier: Modifier,
+
+    /// The average time to execute the instruction once, in nanoseconds
+    pub nanos: f64,
+}
+
+impl OpcodeTiming {
+    /// Time `instruction`, like [`time_instruction`](time_instruction).
+    pub fn measure(instruction: &Instruction, core_size: u32, rounds: usize) -> Self {
+        Self {
+            opcode: instruction.opcode,
+            modifier: instruction.modifier,
+            nanos: time_instruction(instruction, core_size, rounds),
+        }
+    }
+}
+
+impl fmt::Display for OpcodeTiming {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let operation = format!("{}.{}", self.opcode, self.modifier);
+        write!(formatter, "{:<8}{:>10.1} ns", operation, self.nanos)
+    }
+}
+
+/// The measured cost of running and cloning a core with one backend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackendTiming {
+    pub backend: Backend,
+
+    /// The average time to execute one cycle, in nanoseconds
+    pub step_nanos: f64,
+
+    /// The time to clone the core, in nanoseconds
+    pub clone_nanos: f64,
+
+    /// The [memory used](Core::memory_bytes) by the core after running
+    pub bytes: usize,
+}
+
+impl BackendTiming {
+    /// Time `backend` in a core of `core_size` instructions, using the
+    /// fastest of `rounds` rounds.
+    ///
+    /// # Panics
+    ///
+    /// If `core_size` or `rounds` is 0.
+    pub fn measure(backend: Backend, core_size: u32, rounds: usize) -> Self {
+        assert!(core_size > 0, "core size must be positive");
+        assert!(rounds > 0, "must time at least one round");
+
+        let imp = Warrior {
+            program: Program {
+                instructions: vec![Instruction::new(
+                    Opcode::Mov,
+                    Field::direct(0),
+                    Field::direct(1),
+                )]
+                .into(),
+                origin: None,
+            },
+            metadata: Metadata {
+                name: Some("Imp".into()),
+                ..Metadata::default()
+            },
+        };
+
+        let mut fastest: Option<Self> = None;
+        for _ in 0..rounds {
+            let mut core =
+                Core::with_backend(core_size, backend).expect("benchmark core size must be valid");
+            core.set_trace(false);
+            core.load_warrior(&imp)
+                .expect("benchmark warrior must fit in the core");
+
+            // Only run through a tenth of the core, so most of it is untouched
+            let cycles = (core_size / 10).max(1);
+            let start = Instant::now();
+            for _ in 0..cycles {
+                core.step().expect("an imp never dies");
+            }
+            let step_nanos = start.elapsed().as_nanos() as f64 / f64::from(cycles);
+
+            let start = Instant::now();
+            let snapshot = core.clone();
+            let clone_nanos = start.elapsed().as_nanos() as f64;
+            drop(snapshot);
+
+            let timing = Self {
+                backend,
+                step_nanos,
+                clone_nanos,
+                bytes: core.memory_bytes(),
+            };
+            fastest = match fastest {
+                Some(best) if best.step_nanos + best.clone_nanos <= step_nanos + clone_nanos => {
+                    Some(best)
+                }
+                _ => Some(timing),
+            };
+        }
+
+        fastest.unwrap()
+    }
+}
+
+impl fmt::Display for BackendTiming {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{:<8}{:>10.1} ns/step{:>12.0} ns/clone{:>12} bytes",
+            self.backend.to_string(),
+            self.step_nanos,
+            self.clone_nanos,
+            self.bytes
+        )
+    }
+}
+
+/// Time every [backend](Backend::ALL), like
+/// [`BackendTiming::measure`](BackendTiming::measure).
+pub fn time_backends(core_size: u32, rounds: usize) -> Vec<BackendTiming> {
+    Backend::ALL
+        .iter()
+        .map(|&backend| BackendTiming::measure(backend, core_size, rounds))
+        .collect()
+}
+
+/// Time every [benchmarked instruction](benchmarked_instructions). Each is
+/// executed `core_size` times per round, and the fastest of `rounds` rounds
+/// is used.
+pub fn time_opcodes(core_size: u32, rounds: usize) -> Vec<OpcodeTiming> {
+    benchmarked_instructions()
+        .iter()
+        .map(|instruction| OpcodeTiming::measure(instruction, core_size, rounds))
+        .collect()
+}
+
+/// Every combination of opcode and modifier, using direct operands, in the
+/// order they are timed by [`time_opcodes`](time_opcodes). The P-space
+/// opcodes are skipped, since the simulator can't execute them yet.
+pub fn benchmarked_instructions() -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+
+    for &opcode in Opcode::iter_values() {
+        if opcode == Opcode::Ldp || opcode == Opcode::Stp {
+            continue;
+        }
+
+        for &modifier in Modifier::iter_values() {
+            instructions.push(Instruction {
+                opcode,
+                modifier,
+                a_field: Field::direct(1),
+                b_field: Field::direct(1),
+            });
+        }
+    }
+
+    instructions
+}
+
+/// Time a single instruction, returning the average nanoseconds per execution
+/// in the fastest of `rounds` rounds.
+///
+/// # Panics
+///
+/// If `core_size` or `rounds` is 0.
+pub fn time_instruction(instruction: &Instruction, core_size: u32, rounds: usize) -> f64 {
+    assert!(core_size > 0, "core size must be positive");
+    assert!(rounds > 0, "must time at least one round");
+
+    let fastest = (0..rounds)
+        .map(|_| time_round(instruction, core_size))
+        .min()
+        .unwrap();
+
+    fastest.as_nanos() as f64 / f64::from(core_size)
+}
+
+fn time_round(instruction: &Instruction, core_size: u32) -> Duration {
+    let mut core = Core::new(core_size).expect("benchmark core size must be valid");
+    core.set_trace(false);
+
+    let warrior = Warrior {
+        program: Program {
+            instructions: vec![instruction.clone()].into(),
+            origin: None,
+        },
+        metadata: Metadata {
+            name: Some("Bench".into()),
+            ..Metadata::default()
+        },
+    };
+
+    // One process per instruction, so even instructions which terminate
+    // their process (like DAT) can be executed once per instruction
+    for position in 0..core_size {
+        core.load_warrior_at(&warrior, position)
+            .expect("benchmark warrior must fit in the core");
+    }
+
+    let start = Instant::now();
+    for _ in 0..core_size {
+        // Terminating the last process is expected for DAT
+        let _ = core.step();
+    }
+    start.elapsed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_all_opcodes() {
+        let timings = time_opcodes(16, 1);
+
+        assert_eq!(
+            timings.len(),
+            (Opcode::iter_values().len() - 2) * Modifier::iter_values().len()
+        );
+        assert!(timings
+            .iter()
+            .all(|timing| timing.nanos.is_finite() && timing.nanos >= 0.0));
+
+        let mov = timings
+            .iter()
+            .find(|timing| timing.opcode == Opcode::Mov && timing.modifier == Modifier::I)
+            .unwrap();
+        assert!(mov.to_string().starts_with("MOV.I   "));
+        assert!(mov.to_string().ends_with(" ns"));
+    }
+
+    #[test]
+    fn times_backends() {
+        let timings = time_backends(8000, 1);
+        assert_eq!(timings.len(), Backend::ALL.len());
+
+        // Most of the copy-on-write core is still the shared empty page
+        assert!(timings[1].bytes < timings[0].bytes);
+        assert!(timings[1].to_string().starts_with("cow     "));
+    }
+}
@@ -0,0 +1,148 @@
+//! Detecting when a warrior has cleared the core: every instruction outside
+//! the warrior is a `DAT`, so anything left of its opponents dies as soon as
+//! it executes. This is a milestone for stones and scissors, which usually
+//! win soon after, unless an opponent has processes which outlive it.
+
+use std::fmt;
+
+use corewars_core::load_file::Opcode;
+
+use crate::core::Core;
+
+/// The first time a warrior cleared the core, see
+/// [`Core::enable_clear_detection`](crate::Core::enable_clear_detection).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoreClear {
+    /// The cycle in which the core was cleared, counting from 0
+    pub cycle: usize,
+
+    /// The warrior (as an index into [`Core::warriors`]) which owns every
+    /// instruction other than a `DAT`
+    pub id: usize,
+
+    /// The name of that warrior
+    pub warrior: String,
+}
+
+impl fmt::Display for CoreClear {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "core cleared at cycle {} by {}",
+            self.cycle, self.warrior
+        )
+    }
+}
+
+/// The number of instructions other than `DAT` owned by each warrior, kept up
+/// to date from the instructions written each cycle rather than by counting
+/// the whole core again.
+#[derive(Clone, Debug)]
+pub(crate) struct LiveCells {
+    /// The owner of each instruction other than a `DAT`, as 0 if it has
+    /// none, and otherwise 1 more than the ID of the warrior
+    owners: Box<[Option<usize>]>,
+
+    /// The number of instructions in `owners` with each owner
+    counts: Vec<usize>,
+    total: usize,
+}
+
+impl LiveCells {
+    /// Count the instructions of every warrior in `core`.
+    pub fn new(core: &Core) -> Self {
+        let mut cells = Self {
+            owners: vec![None; core.size() as usize].into_boxed_slice(),
+            counts: vec![0; core.warriors().len() + 1],
+            total: 0,
+        };
+        for address in 0..core.size() as usize {
+            cells.update(core, address);
+        }
+        cells
+    }
+
+    /// Count the instruction at `address` again, after it was written.
+    pub fn update(&mut self, core: &Core, address: usize) {
+        if let Some(owner) = self.owners[address].take() {
+            self.counts[owner] -= 1;
+            self.total -= 1;
+        }
+
+        if core.get(address as i32).opcode != Opcode::Dat {
+            let owner = core.owner_id(address as i32).map_or(0, |id| id + 1);
+            if owner >= self.counts.len() {
+                self.counts.resize(owner + 1, 0);
+            }
+            self.owners[address] = Some(owner);
+            self.counts[owner] += 1;
+            self.total += 1;
+        }
+    }
+
+    /// Whether every instruction other than a `DAT` is owned by the `warrior`th
+    /// warrior loaded into the core.
+    pub fn cleared_by(&self, warrior: usize) -> bool {
+        self.counts.get(warrior + 1).copied().unwrap_or_default() == self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn build_core(programs: &[&str]) -> Core {
+        let mut core = Core::new(100).unwrap();
+        core.set_trace(false);
+        core.enable_clear_detection();
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            core.load_warrior_at(&warrior, i as u32 * 50)
+                .expect("Failed to load warrior");
+        }
+
+        core
+    }
+
+    #[test]
+    fn detects_clear() {
+        // Bombs the two instructions of its opponent, then sits still
+        let mut core = build_core(&[
+            ";name Bomber\nmov 3, 50\nmov 2, 50\njmp 0\ndat 0, 0",
+            ";name Target\nnop 0, 0\njmp -1",
+        ]);
+
+        core.step().unwrap();
+        // The target dies, but one of its instructions is still there
+        core.step().ok();
+        assert_eq!(core.core_clears(), &[]);
+
+        core.step().unwrap();
+        core.step().unwrap();
+        assert_eq!(
+            core.core_clears(),
+            &[CoreClear {
+                cycle: 2,
+                id: 0,
+                warrior: "Bomber".into(),
+            }]
+        );
+        assert_eq!(
+            core.core_clears()[0].to_string(),
+            "core cleared at cycle 2 by Bomber"
+        );
+        assert_eq!(core.warrior_stats()[0].core_cleared, Some(2));
+        assert_eq!(core.warrior_stats()[1].core_cleared, None);
+    }
+
+    #[test]
+    fn ignores_unopposed_warriors() {
+        let mut core = build_core(&[";name Imp\nmov 0, 1"]);
+
+        core.step().unwrap();
+        assert_eq!(core.core_clears(), &[]);
+    }
+}
@@ -0,0 +1,104 @@
+//! Detecting the first time a warrior reads or writes an instruction owned by
+//! another warrior, which is usually when a battle starts to get interesting.
+
+use std::fmt;
+
+/// Whether a warrior read or wrote the other warrior's instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ContactKind {
+    Read,
+    Write,
+}
+
+/// The first contact between two warriors in a core, see
+/// [`Core::enable_contact_detection`](crate::Core::enable_contact_detection).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contact {
+    /// The cycle in which the contact happened, counting from 0
+    pub cycle: usize,
+
+    /// The warrior whose process read or wrote the instruction
+    pub warrior: String,
+
+    /// The warrior which owned the instruction
+    pub owner: String,
+
+    pub address: u32,
+    pub kind: ContactKind,
+}
+
+impl fmt::Display for Contact {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let verb = match self.kind {
+            ContactKind::Read => "read",
+            ContactKind::Write => "wrote",
+        };
+
+        write!(
+            formatter,
+            "first contact at cycle {}: {} {} {:0>5}, owned by {}",
+            self.cycle, self.warrior, verb, self.address, self.owner
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::core::Core;
+
+    fn build_core(programs: &[&str]) -> Core {
+        let mut core = Core::new(100).unwrap();
+        core.enable_contact_detection();
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            core.load_warrior_at(&warrior, i as u32 * 50)
+                .expect("Failed to load warrior");
+        }
+
+        core
+    }
+
+    #[test]
+    fn detects_writes() {
+        let mut core = build_core(&[
+            ";name Bomber\nnop 0, 0\nmov 1, 49\ndat 0, 0",
+            ";name Imp\nmov 0, 1",
+        ]);
+
+        core.step().unwrap();
+        assert_eq!(core.first_contact(), None);
+
+        core.step().unwrap();
+        core.step().unwrap();
+        assert_eq!(
+            core.first_contact(),
+            Some(&Contact {
+                cycle: 1,
+                warrior: "Bomber".into(),
+                owner: "Imp".into(),
+                address: 50,
+                kind: ContactKind::Write,
+            })
+        );
+        assert_eq!(
+            core.first_contact().unwrap().to_string(),
+            "first contact at cycle 1: Bomber wrote 00050, owned by Imp"
+        );
+    }
+
+    #[test]
+    fn detects_reads() {
+        let mut core = build_core(&[";name Reader\nmov @1, 0\ndat 0, 49", ";name Imp\nmov 0, 1"]);
+
+        core.step().unwrap();
+        assert_eq!(
+            core.first_contact()
+                .map(|contact| (contact.kind, contact.address)),
+            Some((ContactKind::Read, 50))
+        );
+    }
+}
@@ -2,16 +2,39 @@
 //! This is where all simulation of a Core Wars battle takes place.
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 use thiserror::Error as ThisError;
 
-use corewars_core::load_file::{self, Instruction, Offset};
+use corewars_core::load_file::{self, Instruction, Offset, DEFAULT_CONSTANTS};
 use corewars_core::Warrior;
 
+use crate::clear::{CoreClear, LiveCells};
+use crate::contact::{Contact, ContactKind};
+use crate::coverage::Coverage;
+use crate::event::Event;
+use crate::history::{Executed, History};
+use crate::snapshot::CoreSnapshot;
+
 mod address;
+mod dump;
+mod effects;
+mod memory;
 mod modifier;
 mod opcode;
+mod ownership;
+mod placement;
 mod process;
+mod range;
+mod scheduler;
+
+pub use dump::DumpFilter;
+pub use effects::Effects;
+pub use memory::Backend;
+pub use placement::WarriorHandle;
+pub use process::{Error as ProcessError, ProcessEntry, Queue};
+pub use range::{FieldRange, FoldedField};
+pub use scheduler::{RoundRobin, Scheduler, SchedulerClone};
 
 const DEFAULT_MAXCYCLES: usize = 10_000;
 
@@ -29,33 +52,261 @@ pub enum Error {
 
     #[error(transparent)]
     WarriorAlreadyLoaded(#[from] process::Error),
+
+    /// The warrior was longer than the max length set for the core
+    #[error("warrior has {length} instructions, more than the maximum length {max}")]
+    ExceedsMaxLength { length: u32, max: u32 },
+
+    /// The warrior's origin was not one of its instructions
+    #[error("warrior origin {origin} is outside its {length} instructions")]
+    InvalidOrigin { origin: u32, length: u32 },
+
+    /// The warrior would be loaded too close to another warrior
+    #[error(
+        "warrior at {position} would be closer than the minimum distance {min_distance} to \
+         {other} at {other_position}"
+    )]
+    TooClose {
+        position: u32,
+        other: String,
+        other_position: u32,
+        min_distance: u32,
+    },
+
+    /// There was nowhere left in the core to place a warrior
+    #[error("no room for another warrior at least {min_distance} from the others")]
+    NoRoom { min_distance: u32 },
+
+    /// A field value was too big for the core, see [`FieldRange`]
+    #[error(
+        "the {field:?}-field value {value} of instruction {index} is outside the core of \
+         {core_size} instructions"
+    )]
+    FieldOutOfRange {
+        index: u32,
+        field: load_file::FieldName,
+        value: i32,
+        core_size: u32,
+    },
+
+    /// The warrior uses an opcode the simulator cannot execute yet
+    #[error("instruction {index} uses {opcode}, which is not supported yet")]
+    UnsupportedOpcode {
+        index: u32,
+        opcode: load_file::Opcode,
+    },
 }
 
 /// The full memory core at a given point in time
+#[derive(Clone)]
 pub struct Core {
-    instructions: Box<[Instruction]>,
+    instructions: memory::Memory,
     process_queue: process::Queue,
     steps_taken: usize,
+    cycles: usize,
+    ownership: ownership::Ownership,
+    coverage: Option<Coverage>,
+    history: Option<History>,
+    scheduler: Box<dyn Scheduler>,
+    trace: bool,
+    last_executed: Option<Offset>,
+
+    /// Regions of the core whose processes are tagged, as the start, length
+    /// and tag of each region
+    tag_regions: Vec<(Offset, u32, String)>,
+
+    /// Rules for loading warriors, and the warriors loaded so far
+    max_length: Option<u32>,
+    field_range: FieldRange,
+    folded_fields: Vec<FoldedField>,
+    min_distance: u32,
+    loaded: Vec<WarriorHandle>,
+
+    detect_contact: bool,
+    first_contact: Option<Contact>,
+
+    /// With clear detection enabled, the instructions of each warrior, if
+    /// counted since the last warrior was loaded
+    detect_clears: bool,
+    live_cells: Option<LiveCells>,
+    core_clears: Vec<CoreClear>,
+
+    /// With events enabled, the events of the most recent step
+    record_events: bool,
+    last_events: Vec<Event>,
+}
+
+/// Cores are equal if they are in the same state for simulation: the same
+/// instructions (including the size of the core), processes and number of
+/// steps and cycles taken. How instructions are stored and scheduled, and statistics
+/// such as ownership and coverage, are not compared.
+impl PartialEq for Core {
+    fn eq(&self, other: &Self) -> bool {
+        self.steps_taken == other.steps_taken
+            && self.cycles == other.cycles
+            && self.instructions == other.instructions
+            && self.process_queue == other.process_queue
+    }
+}
+
+impl Eq for Core {}
+
+impl Hash for Core {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.steps_taken.hash(state);
+        self.cycles.hash(state);
+        self.instructions.hash(state);
+        self.process_queue.hash(state);
+    }
 }
 
 impl Core {
     /// Create a new Core with the given number of possible instructions.
     pub fn new(core_size: u32) -> Result<Self, Error> {
+        Self::with_backend(core_size, Backend::default())
+    }
+
+    /// Create a new Core which stores its instructions with `backend`.
+    pub fn with_backend(core_size: u32, backend: Backend) -> Result<Self, Error> {
         if core_size == u32::MAX {
             return Err(Error::InvalidCoreSize(core_size));
         }
 
+        let mut process_queue = process::Queue::new();
+        process_queue.set_limit(DEFAULT_CONSTANTS["MAXPROCESSES"] as usize);
+
         Ok(Self {
-            instructions: vec![Instruction::default(); core_size as usize].into_boxed_slice(),
-            process_queue: process::Queue::new(),
+            instructions: memory::Memory::new(backend, core_size as usize),
+            process_queue,
             steps_taken: 0,
+            cycles: 0,
+            ownership: ownership::Ownership::new(core_size),
+            coverage: None,
+            history: None,
+            scheduler: Box::new(RoundRobin),
+            trace: true,
+            last_executed: None,
+            tag_regions: Vec::new(),
+            max_length: None,
+            field_range: FieldRange::default(),
+            folded_fields: Vec::new(),
+            min_distance: 0,
+            loaded: Vec::new(),
+            detect_contact: false,
+            first_contact: None,
+            detect_clears: false,
+            live_cells: None,
+            core_clears: Vec::new(),
+            record_events: false,
+            last_events: Vec::new(),
         })
     }
 
+    /// How the core stores its instructions.
+    pub fn backend(&self) -> Backend {
+        self.instructions.backend()
+    }
+
+    /// The number of instructions executed so far, by all warriors.
     pub fn steps_taken(&self) -> usize {
         self.steps_taken
     }
 
+    /// The number of cycles completed so far. Each cycle, every warrior still
+    /// running executes one instruction, as in ICWS '94.
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// The most processes each warrior may have at once (MAXPROCESSES).
+    pub fn max_processes(&self) -> usize {
+        self.process_queue.limit()
+    }
+
+    /// Limit the number of processes each warrior may have at once. Once a
+    /// warrior has `max_processes`, `SPL` only continues with the next
+    /// instruction, without starting a new process.
+    pub fn set_max_processes(&mut self, max_processes: usize) {
+        self.process_queue.set_limit(max_processes);
+    }
+
+    /// Get the queue of processes waiting to be executed.
+    pub fn process_queue(&self) -> &process::Queue {
+        &self.process_queue
+    }
+
+    /// Remove the `index`th process of the [queue](Core::process_queue), as
+    /// if it had died, e.g. to inject a fault.
+    pub fn kill_process(&mut self, index: usize) -> Result<ProcessEntry, process::Error> {
+        self.process_queue.remove(index)
+    }
+
+    /// Names of the warriors loaded into the core, in the order they were loaded.
+    pub fn warriors(&self) -> &[String] {
+        self.ownership.warriors()
+    }
+
+    /// The name of the warrior which loaded or last wrote the instruction at
+    /// a given index, if any.
+    pub fn owner(&self, index: i32) -> Option<&str> {
+        self.ownership.owner(self.offset(index).value() as usize)
+    }
+
+    /// The warrior (as an index into [`warriors`](Core::warriors)) which
+    /// loaded or last wrote the instruction at a given index, if any.
+    pub fn owner_id(&self, index: i32) -> Option<usize> {
+        self.ownership.owner_id(self.offset(index).value() as usize)
+    }
+
+    /// The number of instructions owned by each warrior, in the same order
+    /// as [`warriors`](Core::warriors).
+    pub fn owned_cells(&self) -> &[usize] {
+        self.ownership.counts()
+    }
+
+    /// The number of times each instruction in the core has been modified by
+    /// an executing process, indexed by address.
+    pub fn write_counts(&self) -> &[u64] {
+        self.ownership.writes()
+    }
+
+    /// The number of times each instruction in the core has been executed,
+    /// indexed by address.
+    pub fn execution_counts(&self) -> &[u64] {
+        self.ownership.executions()
+    }
+
+    /// The approximate memory used by the core's instructions, ownership
+    /// tracking and process queue, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        use std::mem::size_of;
+
+        let cells = self.instructions.len();
+        let processes: usize = self
+            .process_queue
+            .iter()
+            .map(|entry| size_of::<process::ProcessEntry>() + entry.name.len())
+            .sum();
+
+        self.instructions.bytes()
+            + cells * (size_of::<Option<usize>>() + 2 * size_of::<u64>())
+            + processes
+    }
+
+    /// The warrior (as an index into [`warriors`](Core::warriors)) and address
+    /// of the instruction executed by the most recent step, if any.
+    pub fn last_executed(&self) -> Option<(usize, u32)> {
+        let warrior = self.ownership.last_warrior()?;
+        let offset = self.last_executed?;
+        Some((warrior, offset.value()))
+    }
+
+    /// The addresses of instructions modified by the most recent step, in the
+    /// order they were first written.
+    pub fn last_writes(&self) -> &[usize] {
+        self.ownership.last_writes()
+    }
+
     #[cfg(test)]
     fn program_counter(&self) -> Offset {
         self.process_queue
@@ -68,6 +319,122 @@ impl Core {
         Offset::new(value.into(), self.size())
     }
 
+    /// Replace the scheduler which chooses the process to execute each step.
+    /// By default, processes are executed [`RoundRobin`](RoundRobin).
+    pub fn set_scheduler<S: Scheduler + 'static>(&mut self, scheduler: S) {
+        self.scheduler = Box::new(scheduler);
+    }
+
+    /// Whether to print each instruction to stderr as it executes. This is
+    /// enabled by default, but is far slower than the execution itself.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Whether each instruction is printed to stderr as it executes, see
+    /// [`set_trace`](Core::set_trace).
+    pub fn is_tracing(&self) -> bool {
+        self.trace
+    }
+
+    /// Tag processes which start in the `len` instructions from `start`, to
+    /// tell the components of a warrior apart in [`tag_stats`](Core::tag_stats).
+    /// A warrior's first process is tagged by the region containing its
+    /// origin, so regions should be tagged before loading it. Processes
+    /// created by `SPL` are tagged by the region they split to, or otherwise
+    /// inherit the tag of the process which split.
+    pub fn tag_region<S: Into<String>>(&mut self, start: i32, len: u32, tag: S) {
+        let start = self.offset(start);
+        self.tag_regions.push((start, len, tag.into()));
+    }
+
+    /// The tag of the last region tagged containing `offset`, if any.
+    fn region_tag(&self, offset: Offset) -> Option<&String> {
+        self.tag_regions
+            .iter()
+            .rev()
+            .find(|(start, len, _)| (offset - *start).value() < *len)
+            .map(|(_, _, tag)| tag)
+    }
+
+    /// Start watching for the first time a process reads or writes an
+    /// instruction owned by another warrior, see
+    /// [`first_contact`](Core::first_contact). This makes each step slower,
+    /// since every address an instruction reads must be checked.
+    pub fn enable_contact_detection(&mut self) {
+        self.detect_contact = true;
+    }
+
+    /// The first contact between warriors since
+    /// [`enable_contact_detection`](Core::enable_contact_detection) was
+    /// called, if any.
+    pub fn first_contact(&self) -> Option<&Contact> {
+        self.first_contact.as_ref()
+    }
+
+    /// Start watching for each warrior to clear the core, see
+    /// [`core_clears`](Core::core_clears). This makes each step slightly
+    /// slower, since every instruction written must be counted.
+    pub fn enable_clear_detection(&mut self) {
+        self.detect_clears = true;
+    }
+
+    /// The first time each warrior cleared the core since
+    /// [`enable_clear_detection`](Core::enable_clear_detection) was called,
+    /// in the order they did. A warrior clears the core when it's still
+    /// running and every instruction other than a `DAT` is its own, whether
+    /// it loaded it or wrote it, e.g. after bombing every instruction of its
+    /// opponents. Only instructions written by processes are counted, not
+    /// those injected as faults.
+    pub fn core_clears(&self) -> &[CoreClear] {
+        &self.core_clears
+    }
+
+    /// An immutable copy of the core in its current state, which can be
+    /// shared between threads, e.g. to analyze a battle once it's over.
+    pub fn snapshot(&self) -> CoreSnapshot {
+        CoreSnapshot::from(self.clone())
+    }
+
+    /// Start recording the [events](Event) of each step, see
+    /// [`last_events`](Core::last_events).
+    pub fn enable_events(&mut self) {
+        self.record_events = true;
+    }
+
+    /// The events of the most recent step, in the order they happened, if
+    /// [`enable_events`](Core::enable_events) was called before it. Rounds
+    /// end in a [`Battle`](crate::Battle), so this never includes
+    /// [`Event::RoundEnded`].
+    pub fn last_events(&self) -> &[Event] {
+        &self.last_events
+    }
+
+    /// Start recording which instructions are executed, see
+    /// [`coverage`](Core::coverage).
+    pub fn enable_coverage(&mut self) {
+        self.coverage.get_or_insert_with(Coverage::new);
+    }
+
+    /// The instructions executed since [`enable_coverage`](Core::enable_coverage)
+    /// was called, or `None` if it never was.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Start keeping the last `len` instructions executed, see
+    /// [`history`](Core::history). Any history kept before is forgotten.
+    pub fn enable_history(&mut self, len: usize) {
+        self.history = Some(History::new(len));
+    }
+
+    /// The last instructions executed since
+    /// [`enable_history`](Core::enable_history) was called, or `None` if it
+    /// never was.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
     /// Get the number of instructions in the core (available to programs via the `CORESIZE` label)
     pub fn size(&self) -> u32 {
         self.instructions.len() as _
@@ -75,12 +442,12 @@ impl Core {
 
     /// Get an instruction from a given index in the core
     pub fn get(&self, index: i32) -> &Instruction {
-        &self.get_offset(self.offset(index))
+        self.get_offset(self.offset(index))
     }
 
     /// Get an instruction from a given offset in the core
     fn get_offset(&self, offset: Offset) -> &Instruction {
-        &self.instructions[offset.value() as usize]
+        self.instructions.get(offset.value() as usize)
     }
 
     /// Get a mutable instruction from a given index in the core
@@ -90,7 +457,8 @@ impl Core {
 
     /// Get a mutable from a given offset in the core
     fn get_offset_mut(&mut self, offset: Offset) -> &mut Instruction {
-        &mut self.instructions[offset.value() as usize]
+        self.ownership.written(offset.value() as usize);
+        self.instructions.get_mut(offset.value() as usize)
     }
 
     /// Write an instruction at a given index into the core
@@ -100,39 +468,102 @@ impl Core {
     }
 
     /// Write an instruction at a given offset into the core
-    #[cfg(test)]
     fn set_offset(&mut self, index: Offset, value: Instruction) {
-        self.instructions[index.value() as usize] = value;
+        *self.instructions.get_mut(index.value() as usize) = value;
     }
 
     /// Load a [`Warrior`](Warrior) into the core starting at the front (first instruction of the core).
     /// Returns an error if the Warrior was too long to fit in the core, or had unresolved labels
-    pub fn load_warrior(&mut self, warrior: &Warrior) -> Result<(), Error> {
+    pub fn load_warrior(&mut self, warrior: &Warrior) -> Result<WarriorHandle, Error> {
+        self.load_warrior_at(warrior, 0)
+    }
+
+    /// Check that every opcode `warrior` uses can be executed. P-space is not
+    /// implemented yet, so `LDP` and `STP` cannot.
+    pub fn check_supported(warrior: &Warrior) -> Result<(), Error> {
+        let unsupported = warrior
+            .program
+            .instructions
+            .iter()
+            .enumerate()
+            .find(|(_, i)| matches!(i.opcode, load_file::Opcode::Ldp | load_file::Opcode::Stp));
+
+        match unsupported {
+            Some((index, instruction)) => Err(Error::UnsupportedOpcode {
+                index: index as u32,
+                opcode: instruction.opcode,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Load a [`Warrior`](Warrior) into the core starting at `position`. Warriors
+    /// without a name are named by the order they were loaded in, e.g. `Warrior1`.
+    /// Field values are wrapped to the size of the core. Returns an error if
+    /// the warrior breaks the rules set with [`set_max_length`](Core::set_max_length),
+    /// [`set_min_distance`](Core::set_min_distance) or
+    /// [`set_field_range`](Core::set_field_range), or its origin is outside
+    /// of it.
+    pub fn load_warrior_at(
+        &mut self,
+        warrior: &Warrior,
+        position: u32,
+    ) -> Result<WarriorHandle, Error> {
         if warrior.len() > self.size() {
             return Err(Error::WarriorTooLong);
         }
+        if let Some(max) = self.max_length.filter(|&max| warrior.len() > max) {
+            return Err(Error::ExceedsMaxLength {
+                length: warrior.len(),
+                max,
+            });
+        }
+        if let Some(origin) = warrior.program.origin.filter(|&o| o >= warrior.len()) {
+            if !warrior.is_empty() {
+                return Err(Error::InvalidOrigin {
+                    origin,
+                    length: warrior.len(),
+                });
+            }
+        }
+        Self::check_supported(warrior)?;
+        let position = self.offset(position as i32).value();
+        self.check_placement(position)?;
 
         // TODO check that all instructions are fully resolved? Or require a type
         // safe way of loading a resolved warrior perhaps
 
-        for (i, instruction) in warrior.program.instructions.iter().enumerate() {
-            self.instructions[i] = self.normalize(instruction.clone());
-        }
-
-        // TODO: Maybe some kinda increasing counter for warrior names
         let warrior_name = warrior
             .metadata
             .name
             .clone()
-            .unwrap_or_else(|| String::from("Warrior0"));
+            .unwrap_or_else(|| format!("Warrior{}", self.warriors().len()));
+        self.check_field_range(warrior, &warrior_name)?;
+        let warrior_id = self.ownership.add_warrior(&warrior_name);
 
-        self.process_queue.push(
-            warrior_name,
-            self.offset(warrior.program.origin.unwrap_or(0) as i32),
-            None,
-        );
+        let start = self.offset(position as i32);
+        for (i, instruction) in warrior.program.instructions.iter().enumerate() {
+            let offset = start + i as i32;
+            self.set_offset(offset, self.normalize(instruction.clone()));
+            self.ownership
+                .set_owner(offset.value() as usize, warrior_id);
+        }
 
-        Ok(())
+        let origin = start + warrior.program.origin.unwrap_or(0) as i32;
+        let tag = self.region_tag(origin).cloned();
+        self.process_queue
+            .push_tagged(warrior_id, warrior_name.clone(), origin, None, tag);
+
+        let handle = WarriorHandle {
+            id: warrior_id,
+            name: warrior_name,
+            position,
+            origin: origin.value(),
+            len: warrior.len(),
+        };
+        self.loaded.push(handle.clone());
+        self.live_cells = None;
+        Ok(handle)
     }
 
     fn normalize(&self, mut instruction: Instruction) -> Instruction {
@@ -154,26 +585,110 @@ impl Core {
         instruction
     }
 
-    /// Run a single cycle of simulation. This will continue to execute even
+    /// Execute the next process of the warrior whose turn it is, then pass
+    /// the turn on to the next warrior. This will continue to execute even
     /// after MAXCYCLES has been reached
     pub fn step(&mut self) -> Result<(), process::Error> {
-        let current_process = self.process_queue.pop()?;
-
-        eprintln!(
-            "Step{:>6} (t{:>2}): {:0>5} {}",
-            self.steps_taken,
-            current_process.thread,
-            current_process.offset.value(),
-            self.get_offset(current_process.offset),
+        self.last_events.clear();
+        if self.process_queue.is_empty() {
+            return Err(process::Error::NoRemainingProcesses);
+        }
+
+        let index = self.scheduler.select(&self.process_queue);
+        assert!(
+            index < self.process_queue.len(),
+            "scheduler selected process {} from a queue of {}",
+            index,
+            self.process_queue.len()
         );
+        let current_process = self.process_queue.remove(index)?;
+        let current_process_warrior = current_process.warrior;
+
+        if self.trace {
+            eprintln!(
+                "Step{:>6} (t{:>2}): {:0>5} {}",
+                self.steps_taken,
+                current_process.thread,
+                current_process.offset.value(),
+                self.get_offset(current_process.offset),
+            );
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.record(Executed {
+                cycle: self.cycles,
+                warrior: current_process.name.clone(),
+                address: current_process.offset.value(),
+                instruction: self
+                    .instructions
+                    .get(current_process.offset.value() as usize)
+                    .clone(),
+            });
+        }
+        if self.record_events {
+            self.last_events.push(Event::InstructionExecuted {
+                cycle: self.cycles,
+                warrior: current_process.name.clone(),
+                address: current_process.offset.value(),
+                instruction: self.get_offset(current_process.offset).clone(),
+            });
+        }
         self.steps_taken += 1;
+        self.last_executed = Some(current_process.offset);
+
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(
+                self.instructions
+                    .get(current_process.offset.value() as usize),
+            );
+        }
+
+        let watch_contact = self.detect_contact && self.first_contact.is_none();
+        let reads = if watch_contact {
+            self.reads(current_process.offset)
+        } else {
+            Vec::new()
+        };
 
+        self.ownership.begin(
+            current_process.warrior,
+            current_process.offset.value() as usize,
+        );
         let result = opcode::execute(self, current_process.offset);
 
-        match result {
+        if watch_contact {
+            self.record_contact(&current_process.name, &reads);
+        }
+        self.ownership.end();
+
+        let cycle = self.cycles;
+        if self.record_events {
+            for &address in self.ownership.last_writes() {
+                self.last_events.push(Event::CellWritten {
+                    cycle,
+                    warrior: current_process.name.clone(),
+                    address: address as u32,
+                    instruction: self.instructions.get(address).clone(),
+                });
+            }
+        }
+
+        let stepped = match result {
             Err(err) => match err {
                 process::Error::DivideByZero | process::Error::ExecuteDat(_) => {
-                    if self.process_queue.thread_count(&current_process.name) < 1 {
+                    if self.record_events {
+                        self.last_events.push(Event::ProcessDied {
+                            cycle,
+                            warrior: current_process.name.clone(),
+                            address: current_process.offset.value(),
+                        });
+                    }
+                    if self.process_queue.thread_count(current_process.warrior) < 1 {
+                        if self.record_events {
+                            self.last_events.push(Event::WarriorEliminated {
+                                cycle,
+                                warrior: current_process.name,
+                            });
+                        }
                         Err(err)
                     } else {
                         // This is fine, the task terminated but the process is still alive
@@ -186,10 +701,12 @@ impl Core {
                 // In the special case of a split, enqueue PC+1 (with same thread id)
                 // before also enqueueing the other offset (new thread id)
                 let new_thread_id = if result.should_split {
-                    self.process_queue.push(
+                    self.process_queue.push_tagged(
+                        current_process.warrior,
                         current_process.name.clone(),
                         current_process.offset + 1,
                         Some(current_process.thread),
+                        current_process.tag.clone(),
                     );
                     None
                 } else {
@@ -200,32 +717,132 @@ impl Core {
                 let offset = result
                     .program_counter_offset
                     .unwrap_or_else(|| self.offset(1));
+                let next = current_process.offset + offset;
 
-                self.process_queue.push(
+                // A new process may start a different component of the warrior
+                let tag = if result.should_split {
+                    self.region_tag(next).cloned().or(current_process.tag)
+                } else {
+                    current_process.tag
+                };
+                let name = current_process.name.clone();
+                let queued = self.process_queue.push_tagged(
+                    current_process.warrior,
                     current_process.name,
-                    current_process.offset + offset,
+                    next,
                     new_thread_id,
+                    tag,
                 );
+                // A split past MAXPROCESSES only continues with the next instruction
+                if result.should_split && queued && self.record_events {
+                    self.last_events.push(Event::ProcessSpawned {
+                        cycle,
+                        warrior: name,
+                        address: next.value(),
+                    });
+                }
 
                 Ok(())
             }
+        };
+
+        if self.process_queue.end_turn(current_process_warrior) {
+            self.cycles += 1;
+        }
+
+        // After the process is queued again, so it counts as still running
+        if self.detect_clears {
+            self.record_clears();
+        }
+        stepped
+    }
+
+    /// Record the first contact with another warrior by the process which just
+    /// executed, given the addresses it read before executing.
+    fn record_contact(&mut self, warrior: &str, reads: &[u32]) {
+        let executing = self.ownership.last_warrior();
+        let read = reads.iter().find_map(|&address| {
+            let owner = self.ownership.owner_id(address as usize)?;
+            Some((address, owner, ContactKind::Read)).filter(|_| Some(owner) != executing)
+        });
+        let written = self
+            .ownership
+            .last_overwritten()
+            .first()
+            .map(|&(address, owner)| (address as u32, owner, ContactKind::Write));
+
+        if let Some((address, owner, kind)) = read.or(written) {
+            self.first_contact = Some(Contact {
+                cycle: self.cycles,
+                warrior: warrior.to_string(),
+                owner: self.warriors()[owner].clone(),
+                address,
+                kind,
+            });
+        }
+    }
+
+    /// Count the instructions written by the process which just executed, and
+    /// record any warrior which has now cleared the core.
+    fn record_clears(&mut self) {
+        let live_cells = match self.live_cells.take() {
+            Some(mut live_cells) => {
+                for &address in self.ownership.last_writes() {
+                    live_cells.update(self, address);
+                }
+                live_cells
+            }
+            None => LiveCells::new(self),
+        };
+
+        if self.warriors().len() > 1 {
+            let clears: Vec<CoreClear> = (self.warriors().iter().enumerate())
+                .filter(|&(id, _)| {
+                    live_cells.cleared_by(id)
+                        && self.process_queue.thread_count(id) > 0
+                        && !self.core_clears.iter().any(|clear| clear.id == id)
+                })
+                .map(|(id, name)| CoreClear {
+                    cycle: self.cycles,
+                    id,
+                    warrior: name.clone(),
+                })
+                .collect();
+            self.core_clears.extend(clears);
         }
+
+        self.live_cells = Some(live_cells);
     }
 
-    /// Run a core to completion. Return value determines whether the core resulted
-    /// in a tie (Ok) or something cause the warrior to stop executing (ExecutionError)
+    /// Run a core for `max_cycles` cycles, or until a warrior dies. Return
+    /// value determines whether the core resulted in a tie (Ok) or something
+    /// caused the warrior to stop executing (ExecutionError)
     pub fn run<T: Into<Option<usize>>>(&mut self, max_cycles: T) -> Result<(), process::Error> {
+        self.run_observed(max_cycles, |_| {})
+    }
+
+    /// Run a core to completion like [`run`](Core::run), calling `observer`
+    /// with the state of the core before the first step and after each step.
+    pub fn run_observed<T, F>(
+        &mut self,
+        max_cycles: T,
+        mut observer: F,
+    ) -> Result<(), process::Error>
+    where
+        T: Into<Option<usize>>,
+        F: FnMut(&Core),
+    {
         let max_cycles = max_cycles.into().unwrap_or(DEFAULT_MAXCYCLES);
+        observer(self);
 
         loop {
-            if self.steps_taken >= max_cycles {
+            if self.cycles >= max_cycles {
                 break;
             }
 
             let result = self.step();
-            if result.is_err() {
-                return result;
-            }
+            observer(self);
+            result?;
         }
 
         Ok(())
@@ -332,6 +949,27 @@ mod tests {
         assert_eq!(core.size(), 128);
     }
 
+    #[test]
+    fn compares_core_state() {
+        use std::collections::HashSet;
+
+        let dense = build_core("mov 0, 1");
+        let mut cow = Core::with_backend(8000, Backend::CopyOnWrite).unwrap();
+        cow.load_warrior(&corewars_parser::parse("mov 0, 1").unwrap())
+            .unwrap();
+
+        // The backend doesn't matter, but the size of the core does
+        assert!(dense == cow);
+        assert!(Core::new(128).unwrap() != Core::new(129).unwrap());
+
+        let mut stepped = dense.clone();
+        stepped.step().unwrap();
+        assert!(stepped != dense);
+
+        let states: HashSet<Core> = vec![dense, cow, stepped].into_iter().collect();
+        assert_eq!(states.len(), 2);
+    }
+
     #[test]
     fn load_program() {
         let mut core = Core::new(128).unwrap();
@@ -350,7 +988,7 @@ mod tests {
         assert_eq!(core.size(), expected_core_size as u32);
 
         assert_eq!(
-            &core.instructions[..4],
+            &core.instructions.to_vec()[..4],
             &[
                 Instruction::new(Opcode::Mov, Field::direct(1), Field::immediate(1)),
                 Instruction::new(
@@ -376,7 +1014,8 @@ mod tests {
                 instructions: vec![
                     Instruction::new(Opcode::Dat, Field::direct(1), Field::direct(1),);
                     255
-                ],
+                ]
+                .into(),
                 origin: None,
             },
             ..Default::default()
@@ -388,6 +1027,17 @@ mod tests {
         assert_eq!(core.size(), 128);
     }
 
+    #[test]
+    fn load_program_unsupported_opcode() {
+        let mut core = Core::new(128).unwrap();
+        let warrior = corewars_parser::parse("nop 0, 0\nldp.ab #0, 1").unwrap();
+
+        assert_eq!(
+            core.load_warrior(&warrior).unwrap_err().to_string(),
+            "instruction 1 uses LDP, which is not supported yet"
+        );
+    }
+
     #[test]
     fn wrap_program_counter_on_overflow() {
         let mut core = build_core("mov $0, $1");
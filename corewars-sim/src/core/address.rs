@@ -22,7 +22,9 @@ pub fn resolve_b_pointer(core: &Core, program_counter: Offset) -> Offset {
     resolve_pointer(core, program_counter, &b_field)
 }
 
-fn resolve_pointer(core: &Core, program_counter: Offset, field: &Field) -> Offset {
+/// Get the *relative* offset of the instruction pointed to by `field`, an
+/// operand of the instruction at `program_counter`.
+pub fn resolve_pointer(core: &Core, program_counter: Offset, field: &Field) -> Offset {
     use AddressMode::*;
 
     let address_mode = field.address_mode;
@@ -47,17 +49,21 @@ pub enum EvalTime {
     Post,
 }
 
+#[cfg(test)]
 pub fn apply_a_pointer(core: &mut Core, program_counter: Offset, eval_time: EvalTime) {
     let a_field = core.get_offset(program_counter).a_field.clone();
     apply_pointer(core, program_counter, &a_field, eval_time);
 }
 
+#[cfg(test)]
 pub fn apply_b_pointer(core: &mut Core, program_counter: Offset, eval_time: EvalTime) {
     let b_field = core.get_offset(program_counter).b_field.clone();
     apply_pointer(core, program_counter, &b_field, eval_time);
 }
 
-fn apply_pointer(core: &mut Core, program_counter: Offset, field: &Field, eval_time: EvalTime) {
+/// Apply any decrement or increment of `field`, an operand of the instruction
+/// at `program_counter`, which happens at `eval_time`.
+pub fn apply_pointer(core: &mut Core, program_counter: Offset, field: &Field, eval_time: EvalTime) {
     use AddressMode::*;
 
     let address_mode = field.address_mode;
@@ -67,13 +73,24 @@ fn apply_pointer(core: &mut Core, program_counter: Offset, field: &Field, eval_t
     let a_value = core.offset(pointed_to.a_field.unwrap_value());
     let b_value = core.offset(pointed_to.b_field.unwrap_value());
 
-    let pointed_to = core.get_offset_mut(pointer_location);
-
+    // Only borrow mutably when modifying, since that counts as a write
     match (eval_time, address_mode) {
-        (EvalTime::Pre, PreDecIndirectA) => pointed_to.a_field.set_value(a_value - 1),
-        (EvalTime::Pre, PreDecIndirectB) => pointed_to.b_field.set_value(b_value - 1),
-        (EvalTime::Post, PostIncIndirectA) => pointed_to.a_field.set_value(a_value + 1),
-        (EvalTime::Post, PostIncIndirectB) => pointed_to.b_field.set_value(b_value + 1),
+        (EvalTime::Pre, PreDecIndirectA) => core
+            .get_offset_mut(pointer_location)
+            .a_field
+            .set_value(a_value - 1),
+        (EvalTime::Pre, PreDecIndirectB) => core
+            .get_offset_mut(pointer_location)
+            .b_field
+            .set_value(b_value - 1),
+        (EvalTime::Post, PostIncIndirectA) => core
+            .get_offset_mut(pointer_location)
+            .a_field
+            .set_value(a_value + 1),
+        (EvalTime::Post, PostIncIndirectB) => core
+            .get_offset_mut(pointer_location)
+            .b_field
+            .set_value(b_value + 1),
         _ => {}
     }
 }
@@ -0,0 +1,135 @@
+//! Listing selected instructions of a core, e.g. to see only what a warrior
+//! wrote during a battle instead of thousands of empty instructions.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use corewars_core::load_file::Instruction;
+
+use super::Core;
+
+/// A prebuilt filter for [`Core::dump_with`](Core::dump_with).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DumpFilter {
+    /// Instructions other than the default `DAT.F $0, $0`
+    NonDefault,
+
+    /// Instructions which were executed at least once
+    Executed,
+
+    /// Instructions owned by the named warrior, i.e. which it loaded or
+    /// last wrote
+    OwnedBy(String),
+}
+
+impl DumpFilter {
+    /// Whether the `instruction` at `address` in `core` passes this filter.
+    pub fn matches(&self, core: &Core, address: u32, instruction: &Instruction) -> bool {
+        match self {
+            DumpFilter::NonDefault => *instruction != Instruction::default(),
+            DumpFilter::Executed => core.execution_counts()[address as usize] > 0,
+            DumpFilter::OwnedBy(name) => core.owner(address as i32) == Some(name.as_str()),
+        }
+    }
+}
+
+impl FromStr for DumpFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "non-default" => Ok(DumpFilter::NonDefault),
+            "executed" => Ok(DumpFilter::Executed),
+            _ => match s.strip_prefix("owner=") {
+                Some(name) if !name.is_empty() => Ok(DumpFilter::OwnedBy(name.to_string())),
+                _ => Err(format!(
+                    "unknown dump filter {:?}, expected \"non-default\", \"executed\" or \"owner=NAME\"",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl Core {
+    /// List every instruction for which `filter` returns true, given its
+    /// address and the instruction. Each line is the address followed by the
+    /// instruction.
+    pub fn dump_filtered<F: FnMut(u32, &Instruction) -> bool>(&self, mut filter: F) -> String {
+        let mut dump = String::new();
+
+        for (address, instruction) in self.instructions.iter().enumerate() {
+            if filter(address as u32, instruction) {
+                let _ = writeln!(dump, "{:0>6} {}", address, instruction);
+            }
+        }
+
+        dump
+    }
+
+    /// List every instruction which passes all of `filters`.
+    pub fn dump_with(&self, filters: &[DumpFilter]) -> String {
+        self.dump_filtered(|address, instruction| {
+            filters
+                .iter()
+                .all(|filter| filter.matches(self, address, instruction))
+        })
+    }
+
+    /// List every instruction other than the default.
+    pub fn dump(&self) -> String {
+        self.dump_with(&[DumpFilter::NonDefault])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn filters_dump() {
+        let mut core = Core::new(100).unwrap();
+        core.set_trace(false);
+        let dwarf = corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+            .unwrap();
+        core.load_warrior(&dwarf).unwrap();
+        core.run(3).unwrap();
+
+        assert_eq!(
+            core.dump(),
+            "\
+000000 ADD.AB  #4,     $3
+000001 MOV.I   $2,     @2
+000002 JMP.B   $98,    $0
+000003 DAT.F   #0,     #4
+000007 DAT.F   #0,     #4
+"
+        );
+
+        assert_eq!(
+            core.dump_with(&[DumpFilter::Executed]),
+            "\
+000000 ADD.AB  #4,     $3
+000001 MOV.I   $2,     @2
+000002 JMP.B   $98,    $0
+"
+        );
+
+        // The bomb is owned by Dwarf, since it wrote it
+        assert_eq!(
+            core.dump_with(&[DumpFilter::NonDefault, DumpFilter::OwnedBy("Dwarf".into())])
+                .lines()
+                .count(),
+            5
+        );
+        assert_eq!(
+            core.dump_filtered(|address, _| address >= 98),
+            "000098 DAT.F   $0,     $0\n000099 DAT.F   $0,     $0\n"
+        );
+
+        assert_eq!("owner=Imp".parse(), Ok(DumpFilter::OwnedBy("Imp".into())));
+        assert!("owner=".parse::<DumpFilter>().is_err());
+    }
+}
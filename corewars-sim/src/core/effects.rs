@@ -0,0 +1,223 @@
+//! Previewing the effects of executing a single instruction, without
+//! modifying the core it would execute in.
+
+use corewars_core::load_file::{AddressMode, Field, Instruction, Offset};
+
+use super::address;
+use super::opcode;
+use super::process;
+use super::Core;
+
+/// The result of executing a single instruction, as reported by
+/// [`Core::effects`](Core::effects).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Effects {
+    /// Addresses of every instruction read during execution, in ascending order
+    pub reads: Vec<u32>,
+
+    /// Addresses of every instruction modified during execution, in ascending
+    /// order, with their contents after execution
+    pub writes: Vec<(u32, Instruction)>,
+
+    /// Addresses the process would continue executing from. This is empty if
+    /// the process was terminated, and has two entries after a split.
+    pub next: Vec<u32>,
+
+    /// The error which terminated the process, if any
+    pub error: Option<process::Error>,
+}
+
+impl Core {
+    /// Report what would happen if `instruction` were executed at `address`,
+    /// given the current contents of the core. The core itself is not modified.
+    pub fn effects(&self, address: i32, instruction: &Instruction) -> Effects {
+        let mut preview = Core {
+            instructions: self.instructions.clone(),
+            process_queue: process::Queue::new(),
+            steps_taken: self.steps_taken,
+            cycles: self.cycles,
+            ownership: self.ownership.clone(),
+            coverage: None,
+            history: None,
+            scheduler: Box::new(super::RoundRobin),
+            trace: false,
+            last_executed: None,
+            tag_regions: Vec::new(),
+            max_length: None,
+            field_range: self.field_range,
+            folded_fields: Vec::new(),
+            min_distance: 0,
+            loaded: Vec::new(),
+            detect_contact: false,
+            first_contact: None,
+            detect_clears: false,
+            live_cells: None,
+            core_clears: Vec::new(),
+            record_events: false,
+            last_events: Vec::new(),
+        };
+
+        let program_counter = preview.offset(address);
+        preview.set_offset(program_counter, preview.normalize(instruction.clone()));
+
+        let before = preview.instructions.clone();
+        let reads = preview.reads(program_counter);
+        let result = opcode::execute(&mut preview, program_counter);
+
+        let writes = preview
+            .instructions
+            .iter()
+            .zip(before.iter())
+            .enumerate()
+            .filter(|(_, (after, before))| after != before)
+            .map(|(i, (after, _))| (i as u32, after.clone()))
+            .collect();
+
+        let (next, error) = match result {
+            Ok(executed) => {
+                let mut next = Vec::new();
+                if executed.should_split {
+                    next.push((program_counter + 1_i32).value());
+                }
+                let offset = executed
+                    .program_counter_offset
+                    .unwrap_or_else(|| preview.offset(1));
+                next.push((program_counter + offset).value());
+                (next, None)
+            }
+            Err(err) => (Vec::new(), Some(err)),
+        };
+
+        Effects {
+            reads,
+            writes,
+            next,
+            error,
+        }
+    }
+
+    /// All the addresses read while executing the instruction at `program_counter`
+    pub(super) fn reads(&self, program_counter: Offset) -> Vec<u32> {
+        let instruction = self.get_offset(program_counter);
+        let usage = instruction.opcode.field_usage();
+
+        let mut reads = vec![program_counter];
+
+        let mut read_operand = |field: &Field, target: Offset, is_read: bool| {
+            if !matches!(
+                field.address_mode,
+                AddressMode::Immediate | AddressMode::Direct
+            ) {
+                reads.push(program_counter + field.unwrap_value());
+            }
+            if is_read {
+                reads.push(target);
+            }
+        };
+
+        read_operand(
+            &instruction.a_field,
+            address::resolve_a_pointer(self, program_counter),
+            usage.is_some_and(|usage| usage.reads_a),
+        );
+        read_operand(
+            &instruction.b_field,
+            address::resolve_b_pointer(self, program_counter),
+            usage.is_some_and(|usage| usage.reads_b),
+        );
+
+        let mut reads: Vec<u32> = reads.into_iter().map(|offset| offset.value()).collect();
+        reads.sort_unstable();
+        reads.dedup();
+        reads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use corewars_core::load_file::{Modifier, Opcode};
+
+    use super::super::tests::build_core;
+    use super::*;
+
+    #[test]
+    fn preview_does_not_modify_core() {
+        let core = build_core(
+            "
+            nop 0, 0
+            dat #1, #2
+            dat #3, #4
+            ",
+        );
+
+        let instruction = Instruction {
+            opcode: Opcode::Mov,
+            modifier: Modifier::AB,
+            a_field: Field::direct(1),
+            b_field: Field::direct(2),
+        };
+
+        let effects = core.effects(0, &instruction);
+
+        assert_eq!(effects.reads, vec![0, 1]);
+        assert_eq!(
+            effects.writes,
+            vec![(
+                2,
+                Instruction {
+                    opcode: Opcode::Dat,
+                    modifier: Modifier::F,
+                    a_field: Field::immediate(3),
+                    b_field: Field::immediate(1),
+                }
+            )]
+        );
+        assert_eq!(effects.next, vec![1]);
+        assert_eq!(effects.error, None);
+
+        assert_eq!(core.get(0).opcode, Opcode::Nop);
+        assert_eq!(core.get(2).b_field, Field::immediate(4));
+    }
+
+    #[test]
+    fn indirect_reads_pointer() {
+        let core = build_core(
+            "
+            nop 0, 0
+            dat #0, #2
+            ",
+        );
+
+        let instruction = Instruction::new(Opcode::Jmz, Field::direct(5), {
+            let mut field = Field::direct(1);
+            field.address_mode = AddressMode::PostIncIndirectB;
+            field
+        });
+
+        let effects = core.effects(0, &instruction);
+
+        assert_eq!(effects.reads, vec![0, 1, 3]);
+        assert_eq!(
+            effects.writes,
+            vec![(
+                1,
+                Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(3))
+            )]
+        );
+        assert_eq!(effects.next, vec![5]);
+    }
+
+    #[test]
+    fn split_and_terminate() {
+        let core = Core::new(100).unwrap();
+
+        let split = Instruction::new(Opcode::Spl, Field::direct(-1), Field::direct(0));
+        assert_eq!(core.effects(10, &split).next, vec![11, 9]);
+
+        let effects = core.effects(10, &Instruction::default());
+        assert_eq!(effects.next, vec![]);
+        assert!(matches!(effects.error, Some(process::Error::ExecuteDat(_))));
+    }
+}
@@ -0,0 +1,224 @@
+//! Storage for the instructions of a core.
+//!
+//! Most of a large core is usually the default `DAT.F $0, $0`, and most cycles
+//! only write one or two instructions, so copying the whole core for every
+//! snapshot or replay diff wastes a lot of time and memory. The
+//! [copy-on-write](Backend::CopyOnWrite) backend splits the core into pages
+//! which are shared between clones of a core until one of them writes to it.
+//! Every page starts out shared with every other untouched page.
+//!
+//! Reads are slightly slower than the [dense](Backend::Dense) backend, since
+//! they go through the page table, so which backend is faster depends on how
+//! often the core is cloned. `corewars bench --backends` compares the two.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use corewars_core::load_file::Instruction;
+
+/// The number of instructions in each page of a copy-on-write core.
+pub(super) const PAGE_SIZE: usize = 256;
+
+/// How a core stores its instructions.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// A single contiguous block of instructions, copied whenever the core is
+    /// cloned
+    #[default]
+    Dense,
+
+    /// Pages of 256 instructions, each only copied when it is
+    /// written while shared with another core
+    CopyOnWrite,
+}
+
+impl Backend {
+    /// Every backend, in the order they are benchmarked.
+    pub const ALL: [Backend; 2] = [Backend::Dense, Backend::CopyOnWrite];
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            Backend::Dense => "dense",
+            Backend::CopyOnWrite => "cow",
+        })
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dense" => Ok(Backend::Dense),
+            "cow" | "copy-on-write" => Ok(Backend::CopyOnWrite),
+            _ => Err(format!(
+                "unknown core backend {:?}, expected \"dense\" or \"cow\"",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) enum Memory {
+    Dense(Box<[Instruction]>),
+    CopyOnWrite {
+        pages: Box<[Arc<Vec<Instruction>>]>,
+        len: usize,
+    },
+}
+
+impl Memory {
+    pub fn new(backend: Backend, len: usize) -> Self {
+        match backend {
+            Backend::Dense => Memory::Dense(vec![Instruction::default(); len].into_boxed_slice()),
+            Backend::CopyOnWrite => {
+                // Only the last page may be shorter than the rest
+                let empty = Arc::new(vec![Instruction::default(); PAGE_SIZE]);
+                let full_pages = len / PAGE_SIZE;
+
+                let mut pages = vec![empty; full_pages];
+                if !len.is_multiple_of(PAGE_SIZE) {
+                    pages.push(Arc::new(vec![Instruction::default(); len % PAGE_SIZE]));
+                }
+
+                Memory::CopyOnWrite {
+                    pages: pages.into_boxed_slice(),
+                    len,
+                }
+            }
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            Memory::Dense(_) => Backend::Dense,
+            Memory::CopyOnWrite { .. } => Backend::CopyOnWrite,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Memory::Dense(instructions) => instructions.len(),
+            Memory::CopyOnWrite { len, .. } => *len,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> &Instruction {
+        match self {
+            Memory::Dense(instructions) => &instructions[index],
+            Memory::CopyOnWrite { pages, .. } => &pages[index / PAGE_SIZE][index % PAGE_SIZE],
+        }
+    }
+
+    /// Get an instruction to modify, first copying its page if it's shared.
+    pub fn get_mut(&mut self, index: usize) -> &mut Instruction {
+        match self {
+            Memory::Dense(instructions) => &mut instructions[index],
+            Memory::CopyOnWrite { pages, .. } => {
+                &mut Arc::make_mut(&mut pages[index / PAGE_SIZE])[index % PAGE_SIZE]
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Instruction> + '_> {
+        match self {
+            Memory::Dense(instructions) => Box::new(instructions.iter()),
+            Memory::CopyOnWrite { pages, .. } => {
+                Box::new(pages.iter().flat_map(|page| page.iter()))
+            }
+        }
+    }
+
+    /// The bytes used to store the instructions. Pages shared within the
+    /// core are only counted once, but pages shared with other cores are
+    /// counted by each of them.
+    pub fn bytes(&self) -> usize {
+        match self {
+            Memory::Dense(instructions) => instructions.len() * size_of::<Instruction>(),
+            Memory::CopyOnWrite { pages, .. } => {
+                let mut seen = HashSet::new();
+                let unique: usize = pages
+                    .iter()
+                    .filter(|page| seen.insert(Arc::as_ptr(page)))
+                    .map(|page| page.len())
+                    .sum();
+
+                pages.len() * size_of::<Arc<Vec<Instruction>>>() + unique * size_of::<Instruction>()
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn to_vec(&self) -> Vec<Instruction> {
+        self.iter().cloned().collect()
+    }
+}
+
+/// Memories are equal if they hold the same instructions, however they are
+/// stored. Unlike comparing with `zip`, memories of different lengths are
+/// never equal.
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Memory {}
+
+impl Hash for Memory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hashed the same way as a slice, regardless of the backend
+        self.len().hash(state);
+        for instruction in self.iter() {
+            instruction.hash(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use corewars_core::load_file::{Field, Opcode};
+
+    use super::*;
+
+    #[test]
+    fn copies_shared_pages_on_write() {
+        let mut memory = Memory::new(Backend::CopyOnWrite, 1000);
+        assert_eq!(memory.len(), 1000);
+        assert_eq!(memory.iter().count(), 1000);
+
+        let empty = memory.bytes();
+        let imp = Instruction::new(Opcode::Mov, Field::direct(0), Field::direct(1));
+        *memory.get_mut(600) = imp.clone();
+
+        let snapshot = memory.clone();
+        *memory.get_mut(999) = imp.clone();
+
+        assert_eq!(memory.get(600), &imp);
+        assert_eq!(memory.get(999), &imp);
+        assert_eq!(snapshot.get(600), &imp);
+        assert_eq!(snapshot.get(999), &Instruction::default());
+
+        // Only copying the shared empty page added to the pages this core
+        // stores itself
+        assert_eq!(memory.bytes(), empty + PAGE_SIZE * size_of::<Instruction>());
+        assert_eq!(memory.to_vec().len(), 1000);
+    }
+
+    #[test]
+    fn parses_backends() {
+        for backend in Backend::ALL.iter() {
+            assert_eq!(backend.to_string().parse(), Ok(*backend));
+        }
+        assert!("sparse".parse::<Backend>().is_err());
+    }
+}
@@ -6,10 +6,11 @@ use super::address;
 use super::Core;
 
 /// A helper struct to execute an instruction using the proper modifiers.
-/// This struct maintains the "registers" used for evaluating instructions
+/// This struct maintains the "registers" used for evaluating instructions:
+/// the current instruction, and the instructions its operands point to.
 pub(super) struct Executor<'a> {
     core: &'a mut Core,
-    program_counter: Offset,
+    instruction: Instruction,
     a_value: Instruction,
     b_value: Instruction,
     a_ptr: Offset,
@@ -19,25 +20,30 @@ pub(super) struct Executor<'a> {
 impl<'a> Executor<'a> {
     /// Build a new executor for the given program offset of the given [`Core`].
     pub fn new(core: &'a mut Core, program_counter: Offset) -> Self {
-        let a_ptr = address::resolve_a_pointer(core, program_counter);
+        // The instruction is copied before evaluating its operands, so the B
+        // operand is the one fetched even if evaluating the A operand modifies
+        // the instruction in the core
+        let instruction = core.get_offset(program_counter).clone();
 
         // NOTE: the order of evaluation is significant here: we create the "register"
         // by cloning the A operand before evaluating the B pointer, and all further
         // operations must use the buffered A operand, in case the B pointer evaluation
         // modifies memory
-        address::apply_a_pointer(core, program_counter, address::EvalTime::Pre);
+        let a_field = &instruction.a_field;
+        let a_ptr = address::resolve_pointer(core, program_counter, a_field);
+        address::apply_pointer(core, program_counter, a_field, address::EvalTime::Pre);
         let a_value = core.get_offset(a_ptr).clone();
-        address::apply_a_pointer(core, program_counter, address::EvalTime::Post);
+        address::apply_pointer(core, program_counter, a_field, address::EvalTime::Post);
 
-        let b_ptr = address::resolve_b_pointer(core, program_counter);
-
-        address::apply_b_pointer(core, program_counter, address::EvalTime::Pre);
+        let b_field = &instruction.b_field;
+        let b_ptr = address::resolve_pointer(core, program_counter, b_field);
+        address::apply_pointer(core, program_counter, b_field, address::EvalTime::Pre);
         let b_value = core.get_offset(b_ptr).clone();
-        address::apply_b_pointer(core, program_counter, address::EvalTime::Post);
+        address::apply_pointer(core, program_counter, b_field, address::EvalTime::Post);
 
         Self {
             core,
-            program_counter,
+            instruction,
             a_value,
             b_value,
             a_ptr,
@@ -50,6 +56,33 @@ impl<'a> Executor<'a> {
         self.a_ptr
     }
 
+    /// The pairs of fields of the A and B values the modifier selects, as
+    /// offsets.
+    pub fn values(&self) -> Vec<(Offset, Offset)> {
+        let core_size = self.core.size();
+        self.instruction
+            .modifier
+            .field_pairs()
+            .iter()
+            .map(|pair| {
+                (
+                    self.a_value.field(pair.a).as_offset(core_size),
+                    self.b_value.field(pair.b).as_offset(core_size),
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the A and B values are the same: the fields selected by the
+    /// modifier, or with `.I`, the whole instructions.
+    pub fn values_equal(&self) -> bool {
+        if self.instruction.modifier == Modifier::I {
+            self.a_value == self.b_value
+        } else {
+            self.values().iter().all(|(a, b)| a == b)
+        }
+    }
+
     /// Execute a given operation (`FieldOp`) on a given instruction. This is a convenience
     /// shortcut for [`run_on_instructions`](Self::run_on_instructions) without an `InstructionOp`.
     pub fn run_on_fields<FieldOp>(self, field_op: FieldOp)
@@ -61,7 +94,9 @@ impl<'a> Executor<'a> {
 
     /// Execute a given operation (`FieldOp`) on a given instruction.
     /// `field_op` and `instruction_op` are closures taking an `a` and `b`
-    /// argument and returning the new value to set in the `b` instruction, if any.
+    /// argument and returning the new value to set in the `b` instruction, if
+    /// any. With the `.I` modifier, `instruction_op` is given the whole A and
+    /// B values, and the instruction it returns replaces the B target.
     pub fn run_on_instructions<FieldOp, InstructionOp, OptionalInstructionOp>(
         self,
         mut field_op: FieldOp,
@@ -71,64 +106,42 @@ impl<'a> Executor<'a> {
         InstructionOp: FnMut(Instruction, Instruction) -> Option<Instruction>,
         OptionalInstructionOp: Into<Option<InstructionOp>>,
     {
-        let instruction = self.core.get_offset(self.program_counter).clone();
-
-        let a_value_a_offset = self.core.offset(self.a_value.a_field.unwrap_value());
-        let a_value_b_offset = self.core.offset(self.a_value.b_field.unwrap_value());
-
-        let b_value_a_offset = self.core.offset(self.b_value.a_field.unwrap_value());
-        let b_value_b_offset = self.core.offset(self.b_value.b_field.unwrap_value());
-
+        let modifier = self.instruction.modifier;
+        let values = self.values();
         let b_target = self.core.get_offset_mut(self.b_ptr);
 
-        match instruction.modifier {
-            Modifier::A => {
-                if let Some(res) = field_op(a_value_a_offset, b_value_a_offset) {
-                    b_target.a_field.set_value(res);
-                }
-            }
-            Modifier::B => {
-                if let Some(res) = field_op(a_value_b_offset, b_value_b_offset) {
-                    b_target.b_field.set_value(res);
-                }
-            }
-            Modifier::AB => {
-                if let Some(res) = field_op(a_value_a_offset, b_value_b_offset) {
-                    b_target.b_field.set_value(res);
-                }
+        for (field, (a, b)) in modifier.field_pairs().iter().zip(values) {
+            if let Some(res) = field_op(a, b) {
+                b_target.field_mut(field.b).set_value(res);
             }
-            Modifier::BA => {
-                if let Some(res) = field_op(a_value_b_offset, b_value_a_offset) {
-                    b_target.a_field.set_value(res);
-                }
-            }
-            Modifier::F | Modifier::I => {
-                if let Some(a_res) = field_op(a_value_a_offset, b_value_a_offset) {
-                    b_target.a_field.set_value(a_res);
-                }
-                if let Some(b_res) = field_op(a_value_b_offset, b_value_b_offset) {
-                    b_target.b_field.set_value(b_res);
-                }
+        }
 
-                if instruction.modifier == Modifier::I {
-                    if let Some(mut instruction_op) = instruction_op.into() {
-                        if let Some(res) = instruction_op(self.a_value, b_target.clone()) {
-                            b_target.opcode = res.opcode;
-                            b_target.modifier = res.modifier;
-                        }
-                    }
-                }
-            }
-            Modifier::X => {
-                if let Some(a_res) = field_op(a_value_b_offset, b_value_a_offset) {
-                    b_target.a_field.set_value(a_res);
-                }
-                if let Some(b_res) = field_op(a_value_a_offset, b_value_b_offset) {
-                    b_target.b_field.set_value(b_res);
+        if modifier == Modifier::I {
+            if let Some(mut instruction_op) = instruction_op.into() {
+                if let Some(res) = instruction_op(self.a_value, self.b_value) {
+                    *b_target = res;
                 }
             }
         }
     }
+
+    /// Decrement the fields of the B target selected by the modifier, as
+    /// `DJN` does, and return whether any of the B value's fields were not
+    /// zero once decremented.
+    pub fn decrement_b_target(self) -> bool {
+        let modifier = self.instruction.modifier;
+        let values = self.values();
+        let core_size = self.core.size();
+        let b_target = self.core.get_offset_mut(self.b_ptr);
+
+        let mut not_zero = false;
+        for (field, (_, b)) in modifier.field_pairs().iter().zip(values) {
+            let target = b_target.field(field.b).as_offset(core_size);
+            b_target.field_mut(field.b).set_value(target - 1_i32);
+            not_zero |= b.value() != 1;
+        }
+        not_zero
+    }
 }
 
 #[cfg(test)]
@@ -218,8 +231,8 @@ mod tests {
             &Instruction {
                 opcode: Opcode::Nop,
                 modifier: Modifier::AB,
-                a_field: Field::direct(35),
-                b_field: Field::direct(46),
+                a_field: Field::direct(0),
+                b_field: Field::direct(0),
             }
         );
     }
@@ -14,10 +14,12 @@ pub struct Executed {
     pub should_split: bool,
 }
 
-/// TODO: docstring
+/// Execute the instruction at `program_counter`, returning how the process
+/// should continue. This follows the ICWS '94 draft's EMI94 reference
+/// implementation, and must be kept in sync with
+/// [`Opcode::field_usage`](Opcode::field_usage).
 pub fn execute(core: &mut Core, program_counter: Offset) -> Result<Executed, process::Error> {
-    let instruction = core.get_offset(program_counter).clone();
-    let opcode = instruction.opcode;
+    let opcode = core.get_offset(program_counter).opcode;
 
     // These are basically just useful constants that some opcodes need to use
     let zero = core.offset(0);
@@ -29,6 +31,7 @@ pub fn execute(core: &mut Core, program_counter: Offset) -> Result<Executed, pro
 
     // For jumping opcodes, this is the relative offset they will use to make the jump
     let jump_offset = executor.a_ptr() - program_counter;
+    let values = executor.values();
 
     // See docs/icws94.txt:1113 for detailed description of each opcode
     match opcode {
@@ -40,107 +43,66 @@ pub fn execute(core: &mut Core, program_counter: Offset) -> Result<Executed, pro
         Opcode::Nop => {}
 
         // Infallible arithmetic
-        Opcode::Add => executor.run_on_fields(|a, b| Some(a + b)),
-        Opcode::Mul => executor.run_on_fields(|a, b| Some(a * b)),
+        Opcode::Add => executor.run_on_fields(|a, b| Some(b + a)),
+        Opcode::Mul => executor.run_on_fields(|a, b| Some(b * a)),
         Opcode::Sub => executor.run_on_fields(|a, b| Some(b - a)),
 
-        // Fallible arithmetic
-        Opcode::Div => {
-            let mut div_result = Ok(());
+        // Fallible arithmetic: the B value is divided by the A value. Pairs
+        // which don't divide by zero are still written, but the process is
+        // removed if any of them do.
+        Opcode::Div | Opcode::Mod => {
+            let mut divided_by_zero = false;
             executor.run_on_fields(|a, b| {
-                if b.value() == 0 {
-                    div_result = Err(process::Error::DivideByZero);
+                if a.value() == 0 {
+                    divided_by_zero = true;
                     None
+                } else if opcode == Opcode::Div {
+                    Some(b / a)
                 } else {
-                    Some(a / b)
+                    Some(b % a)
                 }
             });
-            div_result?;
-        }
-        Opcode::Mod => {
-            let mut rem_result = Ok(());
-            executor.run_on_fields(|a, b| {
-                if b.value() == 0 {
-                    rem_result = Err(process::Error::DivideByZero);
-                    None
-                } else {
-                    Some(a % b)
-                }
-            });
-            rem_result?;
+            if divided_by_zero {
+                return Err(process::Error::DivideByZero);
+            }
         }
 
         // Skipping control flow opcodes
         Opcode::Cmp | Opcode::Seq => {
-            program_counter_offset.set(skip_one.into());
-            executor.run_on_instructions(
-                |a, b| {
-                    if a != b {
-                        program_counter_offset.set(None)
-                    }
-                    None
-                },
-                // For e.g. F and I, all fields must match
-                |a, b| {
-                    if a != b {
-                        program_counter_offset.set(None);
-                    }
-                    None
-                },
-            )
-        }
-        Opcode::Slt => {
-            program_counter_offset.set(skip_one.into());
-            executor.run_on_fields(|a, b| {
-                if a.value() >= b.value() {
-                    program_counter_offset.set(None);
-                }
-                None
-            })
+            if executor.values_equal() {
+                program_counter_offset.set(Some(skip_one));
+            }
         }
         Opcode::Sne => {
-            let next_instruction = Some(skip_one);
-            executor.run_on_instructions(
-                |a, b| {
-                    if a != b {
-                        program_counter_offset.set(next_instruction);
-                    }
-                    None
-                },
-                |a, b| {
-                    if a != b {
-                        program_counter_offset.set(next_instruction);
-                    }
-                    None
-                },
-            )
+            if !executor.values_equal() {
+                program_counter_offset.set(Some(skip_one));
+            }
+        }
+        Opcode::Slt => {
+            if values.iter().all(|(a, b)| a.value() < b.value()) {
+                program_counter_offset.set(Some(skip_one));
+            }
         }
 
-        // Jumping control flow opcodes
-        // These subtract the current program counter since this offset will be added to it later
-        Opcode::Djn => executor.run_on_fields(|_a, b| {
-            let decremented = b - 1_i32;
-            if decremented != zero {
+        // Jumping control flow opcodes. With `.F`, `.X` and `.I`, JMZ jumps
+        // if both fields are zero, and JMN and DJN if either is not.
+        Opcode::Djn => {
+            if executor.decrement_b_target() {
                 program_counter_offset.set(jump_offset.into());
             }
-            Some(decremented)
-        }),
-        Opcode::Jmn => executor.run_on_fields(|_a, b| {
-            if b != zero {
+        }
+        Opcode::Jmn => {
+            if values.iter().any(|&(_, b)| b != zero) {
                 program_counter_offset.set(jump_offset.into());
             }
-            None
-        }),
+        }
         Opcode::Jmp | Opcode::Spl => {
             program_counter_offset.set(jump_offset.into());
         }
         Opcode::Jmz => {
-            executor.run_on_fields(|_a, b| {
-                if b == zero {
-                    program_counter_offset.set(jump_offset.into());
-                }
-                None
-            });
+            if values.iter().all(|&(_, b)| b == zero) {
+                program_counter_offset.set(jump_offset.into());
+            }
         }
 
         // P-space opcodes
@@ -164,6 +126,58 @@ mod tests {
 
     use test_case::test_case;
 
+    mod field_usage {
+        use super::*;
+
+        use pretty_assertions::assert_eq;
+
+        /// Verify that the fields written during execution match the table
+        #[test]
+        fn matches_execution() {
+            use corewars_core::load_file::{FieldName, Modifier};
+
+            for &opcode in Opcode::iter_values() {
+                if matches!(opcode, Opcode::Ldp | Opcode::Stp) {
+                    continue;
+                }
+
+                for &modifier in Modifier::iter_values() {
+                    let mut core = build_core(&format!(
+                        "
+                        {}.{} $1, $2
+                        dat   #3, #5
+                        dat   #7, #11
+                        ",
+                        opcode, modifier
+                    ));
+
+                    let before = core.get(2).clone();
+                    let pc = core.offset(0);
+                    let _ = execute(&mut core, pc);
+                    let after = core.get(2);
+
+                    let writes_b = opcode.field_usage().is_some_and(|usage| usage.writes_b);
+                    let written: Vec<_> =
+                        modifier.field_pairs().iter().map(|pair| pair.b).collect();
+
+                    let a_changed = before.a_field != after.a_field;
+                    let b_changed = before.b_field != after.b_field;
+
+                    assert_eq!(
+                        (a_changed, b_changed),
+                        (
+                            writes_b && written.contains(&FieldName::A),
+                            writes_b && written.contains(&FieldName::B),
+                        ),
+                        "{}.{}",
+                        opcode,
+                        modifier
+                    );
+                }
+            }
+        }
+    }
+
     mod process {
 
         use super::*;
@@ -187,7 +201,7 @@ mod tests {
 
             assert_eq!(err, Error::ExecuteDat(pc));
             assert_eq!(
-                &core.instructions[1..=2],
+                &core.instructions.to_vec()[1..=2],
                 &[
                     Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(1)),
                     Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(1)),
@@ -209,7 +223,7 @@ mod tests {
             assert!(result.program_counter_offset.is_none());
 
             assert_eq!(
-                &core.instructions[..4],
+                &core.instructions.to_vec()[..4],
                 &vec![
                     instruction.clone(),
                     instruction,
@@ -219,6 +233,29 @@ mod tests {
             );
         }
 
+        #[test]
+        fn execute_mov_i_copies_modes() {
+            let mut core = build_core("mov.i 1, 2\ndat }3, >4");
+            let pc = core.offset(0);
+            execute(&mut core, pc).unwrap();
+
+            assert_eq!(core.get(2), core.get(1));
+            assert_eq!(core.get(2).to_string(), "DAT.F   }3,     >4");
+        }
+
+        #[test]
+        fn execute_mov_uses_fetched_instruction() {
+            // Evaluating the A operand decrements the B-field of the MOV, but
+            // the B operand is still the one fetched
+            let mut core = build_core("mov.i <0, 1");
+            let pc = core.offset(0);
+            execute(&mut core, pc).unwrap();
+
+            assert_eq!(core.get(0).b_field.unwrap_value(), 0);
+            assert_eq!(core.get(1).opcode, Opcode::Mov);
+            assert_eq!(core.get(2), &Instruction::default());
+        }
+
         #[test]
         fn execute_nop() {
             let mut core = build_core("nop #0, #0");
@@ -299,33 +336,51 @@ mod tests {
             let mut core = build_core(
                 "
                 div $1, $2
+                dat #2, #3
                 dat #8, #7
-                dat #4, #2
                 ",
             );
             let pc = core.offset(0);
             let result = execute(&mut core, pc).unwrap();
             assert!(result.program_counter_offset.is_none());
 
+            // The B value is divided by the A value
             assert_eq!(
                 *core.get(2),
-                Instruction::new(Opcode::Dat, Field::immediate(2), Field::immediate(3)),
+                Instruction::new(Opcode::Dat, Field::immediate(4), Field::immediate(2)),
+            )
+        }
+
+        #[test_case("div.ab #2, 1", 5 ; "a_number")]
+        // An immediate A operand points at the DIV itself, so .B divides by
+        // its own B-number
+        #[test_case("div.b #2, 1", 10 ; "b_number")]
+        fn execute_div_immediate(instruction: &str, quotient: i32) {
+            use pretty_assertions::assert_eq;
+
+            let mut core = build_core(&format!("{}\ndat #0, #10", instruction));
+            let pc = core.offset(0);
+            execute(&mut core, pc).unwrap();
+
+            assert_eq!(
+                *core.get(1),
+                Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(quotient)),
             )
         }
 
         #[test_case(
             Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(2)),
-            Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(3))
+            Instruction::new(Opcode::Dat, Field::immediate(4), Field::immediate(3))
             ; "a_zero"
         )]
         #[test_case(
             Instruction::new(Opcode::Dat, Field::direct(2), Field::direct(0)),
-            Instruction::new(Opcode::Dat, Field::direct(2), Field::direct(0))
+            Instruction::new(Opcode::Dat, Field::immediate(2), Field::immediate(6))
             ; "b_zero"
         )]
         #[test_case(
             Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(0)),
-            Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(0))
+            Instruction::new(Opcode::Dat, Field::immediate(4), Field::immediate(6))
             ; "both_zero"
         )]
         fn execute_div_by_zero(divisor: Instruction, result: Instruction) {
@@ -334,11 +389,12 @@ mod tests {
             let mut core = build_core(
                 "
                 div.f   $1, $2
+                dat     #1, #1
                 dat     #4, #6
                 ",
             );
 
-            core.set(2, divisor);
+            core.set(1, divisor);
             let pc = core.offset(0);
             let err = execute(&mut core, pc).unwrap_err();
 
@@ -351,8 +407,8 @@ mod tests {
             let mut core = build_core(
                 "
                 mod $1, $2
+                dat #3, #4
                 dat #8, #7
-                dat #4, #2
                 ",
             );
             let pc = core.offset(0);
@@ -361,23 +417,23 @@ mod tests {
 
             assert_eq!(
                 *core.get(2),
-                Instruction::new(Opcode::Dat, Field::immediate(0), Field::immediate(1)),
+                Instruction::new(Opcode::Dat, Field::immediate(2), Field::immediate(3)),
             )
         }
 
         #[test_case(
             Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(4)),
-            Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(2))
+            Instruction::new(Opcode::Dat, Field::immediate(4), Field::immediate(2))
             ; "a_zero"
         )]
         #[test_case(
             Instruction::new(Opcode::Dat, Field::direct(3), Field::direct(0)),
-            Instruction::new(Opcode::Dat, Field::direct(1), Field::direct(0))
+            Instruction::new(Opcode::Dat, Field::immediate(1), Field::immediate(6))
             ; "b_zero"
         )]
         #[test_case(
             Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(0)),
-            Instruction::new(Opcode::Dat, Field::direct(0), Field::direct(0))
+            Instruction::new(Opcode::Dat, Field::immediate(4), Field::immediate(6))
             ; "both_zero"
         )]
         fn execute_mod_by_zero(divisor: Instruction, result: Instruction) {
@@ -386,11 +442,12 @@ mod tests {
             let mut core = build_core(
                 "
                 mod.f   $1, $2
+                dat     #1, #1
                 dat     #4, #6
                 ",
             );
 
-            core.set(2, divisor);
+            core.set(1, divisor);
             let pc = core.offset(0);
             let err = execute(&mut core, pc).unwrap_err();
 
@@ -510,6 +567,9 @@ mod tests {
 
     mod jumping {
         use super::*;
+
+        use super::test_case;
+
         use pretty_assertions::assert_eq;
 
         #[test]
@@ -527,7 +587,7 @@ mod tests {
 
             assert_eq!(result.program_counter_offset, None);
             assert_eq!(
-                &core.instructions[1..4],
+                &core.instructions.to_vec()[1..4],
                 &vec![
                     Instruction {
                         opcode: Opcode::Djn,
@@ -556,7 +616,7 @@ mod tests {
 
             assert_eq!(result.program_counter_offset, Some(core.offset(2)));
             assert_eq!(
-                &core.instructions[1..4],
+                &core.instructions.to_vec()[1..4],
                 &vec![
                     Instruction {
                         opcode: Opcode::Djn,
@@ -615,7 +675,7 @@ mod tests {
 
             assert_eq!(result.program_counter_offset, Some(core.offset(3)));
             assert_eq!(
-                &core.instructions[1..5],
+                &core.instructions.to_vec()[1..5],
                 &vec![
                     Instruction::new(Opcode::Jmp, Field::direct(3), Field::immediate(0)),
                     Default::default(),
@@ -639,7 +699,7 @@ mod tests {
             assert_eq!(result.program_counter_offset, Some(core.offset(3)));
             assert!(result.should_split);
             assert_eq!(
-                &core.instructions[1..5],
+                &core.instructions.to_vec()[1..5],
                 &vec![
                     Instruction::new(Opcode::Spl, Field::direct(3), Field::immediate(0)),
                     Default::default(),
@@ -682,5 +742,26 @@ mod tests {
 
             assert_eq!(result.program_counter_offset, Some(core.offset(2)));
         }
+
+        #[test_case("jmz.f", "dat #0, #0", true ; "jmz_both_zero")]
+        #[test_case("jmz.f", "dat #0, #1", false ; "jmz_one_zero")]
+        #[test_case("jmz.x", "dat #1, #0", false ; "jmz_x_one_zero")]
+        #[test_case("jmz.i", "dat #0, #0", true ; "jmz_i_both_zero")]
+        #[test_case("jmn.f", "dat #0, #1", true ; "jmn_one_nonzero")]
+        #[test_case("jmn.f", "dat #0, #0", false ; "jmn_both_zero")]
+        #[test_case("jmn.x", "dat #1, #0", true ; "jmn_x_one_nonzero")]
+        #[test_case("djn.f", "dat #1, #2", true ; "djn_one_nonzero")]
+        #[test_case("djn.f", "dat #1, #1", false ; "djn_both_zero")]
+        #[test_case("djn.i", "dat #2, #1", true ; "djn_i_one_nonzero")]
+        fn execute_jump_both_fields(opcode: &str, target: &str, jumps: bool) {
+            use pretty_assertions::assert_eq;
+
+            let mut core = build_core(&format!("{} $2, $1\n{}", opcode, target));
+            let pc = core.offset(0);
+            let result = execute(&mut core, pc).unwrap();
+
+            let expected = if jumps { Some(core.offset(2)) } else { None };
+            assert_eq!(result.program_counter_offset, expected);
+        }
     }
 }
@@ -0,0 +1,126 @@
+//! Tracking which warrior last wrote each instruction in the core.
+
+/// The owner of each instruction in a core, i.e. the warrior which loaded or
+/// last modified it. Warriors are identified by the order they were loaded in.
+#[derive(Clone, Debug, Default)]
+pub struct Ownership {
+    /// Names of all warriors loaded into the core
+    warriors: Vec<String>,
+
+    /// The owner of each instruction, if any
+    owners: Box<[Option<usize>]>,
+
+    /// The number of instructions owned by each warrior
+    counts: Vec<usize>,
+
+    /// The number of times each instruction has been written by a process
+    writes: Box<[u64]>,
+
+    /// The number of times each instruction has been executed
+    executions: Box<[u64]>,
+
+    /// The warrior whose process is currently executing, which owns any
+    /// instructions written until it finishes
+    executing: Option<usize>,
+
+    /// The warrior whose process executed most recently, and the
+    /// instructions it wrote (each only once)
+    last_warrior: Option<usize>,
+    last_writes: Vec<usize>,
+
+    /// Instructions written since the current process began which were
+    /// owned by another warrior, with that warrior
+    last_overwritten: Vec<(usize, usize)>,
+}
+
+impl Ownership {
+    pub fn new(core_size: u32) -> Self {
+        Self {
+            owners: vec![None; core_size as usize].into_boxed_slice(),
+            writes: vec![0; core_size as usize].into_boxed_slice(),
+            executions: vec![0; core_size as usize].into_boxed_slice(),
+            ..Self::default()
+        }
+    }
+
+    pub fn warriors(&self) -> &[String] {
+        &self.warriors
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    pub fn writes(&self) -> &[u64] {
+        &self.writes
+    }
+
+    pub fn executions(&self) -> &[u64] {
+        &self.executions
+    }
+
+    pub fn last_warrior(&self) -> Option<usize> {
+        self.last_warrior
+    }
+
+    pub fn last_writes(&self) -> &[usize] {
+        &self.last_writes
+    }
+
+    pub fn owner(&self, index: usize) -> Option<&str> {
+        self.owners[index].map(|warrior| self.warriors[warrior].as_str())
+    }
+
+    pub fn owner_id(&self, index: usize) -> Option<usize> {
+        self.owners[index]
+    }
+
+    pub fn last_overwritten(&self) -> &[(usize, usize)] {
+        &self.last_overwritten
+    }
+
+    /// Add a warrior, returning its ID. Every warrior loaded gets a new ID,
+    /// even if another already has the same name.
+    pub fn add_warrior(&mut self, name: &str) -> usize {
+        self.warriors.push(name.to_string());
+        self.counts.push(0);
+        self.warriors.len() - 1
+    }
+
+    pub fn set_owner(&mut self, index: usize, warrior: usize) {
+        if let Some(previous) = self.owners[index].replace(warrior) {
+            self.counts[previous] -= 1;
+        }
+        self.counts[warrior] += 1;
+    }
+
+    /// Start executing a process of the `warrior`th warrior, at `index`.
+    pub fn begin(&mut self, warrior: usize, index: usize) {
+        self.executions[index] += 1;
+        self.executing = Some(warrior);
+        self.last_warrior = self.executing;
+        self.last_writes.clear();
+        self.last_overwritten.clear();
+    }
+
+    /// Finish executing the current process.
+    pub fn end(&mut self) {
+        self.executing = None;
+    }
+
+    /// Record that the instruction at `index` was written by the executing
+    /// warrior, if any.
+    pub fn written(&mut self, index: usize) {
+        if let Some(warrior) = self.executing {
+            if let Some(previous) = self.owners[index].filter(|&owner| owner != warrior) {
+                self.last_overwritten.push((index, previous));
+            }
+            self.set_owner(index, warrior);
+            self.writes[index] += 1;
+
+            if !self.last_writes.contains(&index) {
+                self.last_writes.push(index);
+            }
+        }
+    }
+}
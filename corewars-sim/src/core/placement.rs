@@ -0,0 +1,197 @@
+//! Where warriors are placed in a core: the rules checked when loading each
+//! one, and choosing random positions which follow them.
+
+use rand::Rng;
+
+use corewars_core::Warrior;
+
+use super::{Core, Error};
+
+/// A warrior which was loaded into a core, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WarriorHandle {
+    /// The index of the warrior in [`Core::warriors`](Core::warriors)
+    pub id: usize,
+
+    pub name: String,
+
+    /// The address of the warrior's first instruction
+    pub position: u32,
+
+    /// The address its first process starts executing from
+    pub origin: u32,
+
+    /// The number of instructions loaded
+    pub len: u32,
+}
+
+impl Core {
+    /// Reject warriors longer than `max_length` when loading them, e.g. to
+    /// enforce MAXLENGTH. By default, any warrior which fits in the core can
+    /// be loaded.
+    pub fn set_max_length(&mut self, max_length: u32) {
+        self.max_length = Some(max_length);
+    }
+
+    /// Reject warriors whose first instruction would be closer than
+    /// `min_distance` to that of a warrior already loaded, e.g. to enforce
+    /// MINDISTANCE. By default, warriors can be loaded anywhere.
+    pub fn set_min_distance(&mut self, min_distance: u32) {
+        self.min_distance = min_distance;
+    }
+
+    /// The warriors loaded into the core, in the order they were loaded.
+    pub fn loaded(&self) -> &[WarriorHandle] {
+        &self.loaded
+    }
+
+    /// Load each of `warriors` at a random position, like pMARS does. The
+    /// first is loaded at the start of the core, and each of the others
+    /// somewhere at least the minimum distance from every warrior before it,
+    /// without overlapping them.
+    ///
+    /// ```
+    /// use rand::SeedableRng;
+    ///
+    /// let imp = || corewars_parser::parse("mov 0, 1").unwrap();
+    /// let mut core = corewars_sim::Core::new(8000).unwrap();
+    /// core.set_min_distance(100);
+    ///
+    /// let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    /// let handles = core.load_randomly(&[imp(), imp()], &mut rng).unwrap();
+    /// assert_eq!(handles[0].position, 0);
+    /// assert!((100..=7900).contains(&handles[1].position));
+    /// ```
+    pub fn load_randomly<R: Rng>(
+        &mut self,
+        warriors: &[Warrior],
+        rng: &mut R,
+    ) -> Result<Vec<WarriorHandle>, Error> {
+        let mut handles = Vec::with_capacity(warriors.len());
+
+        for warrior in warriors {
+            let position = if self.loaded.is_empty() {
+                0
+            } else {
+                let candidates: Vec<u32> = (0..self.size())
+                    .filter(|&position| self.has_room(position, warrior.len()))
+                    .collect();
+                if candidates.is_empty() {
+                    return Err(Error::NoRoom {
+                        min_distance: self.min_distance,
+                    });
+                }
+                candidates[rng.gen_range(0, candidates.len())]
+            };
+
+            handles.push(self.load_warrior_at(warrior, position)?);
+        }
+
+        Ok(handles)
+    }
+
+    /// Check a warrior could be loaded at `position`, i.e. it isn't closer
+    /// than the minimum distance to another warrior.
+    pub(super) fn check_placement(&self, position: u32) -> Result<(), Error> {
+        for other in &self.loaded {
+            let distance = self.distance(position, other.position);
+            if distance < self.min_distance {
+                return Err(Error::TooClose {
+                    position,
+                    other: other.name.clone(),
+                    other_position: other.position,
+                    min_distance: self.min_distance,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether a warrior of length `len` could be loaded at `position`
+    /// without overlapping another warrior.
+    fn has_room(&self, position: u32, len: u32) -> bool {
+        self.check_placement(position).is_ok()
+            && self.loaded.iter().all(|other| {
+                let after = |from: u32, to: u32| (to + self.size() - from) % self.size();
+                after(position, other.position) >= len
+                    && after(other.position, position) >= other.len
+            })
+    }
+
+    /// The shortest distance between two addresses, in either direction
+    /// around the core.
+    fn distance(&self, first: u32, second: u32) -> u32 {
+        let forward = (second + self.size() - first) % self.size();
+        forward.min(self.size() - forward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn parse(program: &str) -> Warrior {
+        corewars_parser::parse(program).expect("Failed to parse warrior")
+    }
+
+    #[test]
+    fn checks_placement() {
+        let mut core = Core::new(8000).unwrap();
+        core.set_max_length(2);
+        core.set_min_distance(100);
+
+        let handle = core
+            .load_warrior_at(&parse(";name Imp\norg 1\ndat 0, 0\nmov 0, 1"), 7950)
+            .unwrap();
+        assert_eq!(
+            handle,
+            WarriorHandle {
+                id: 0,
+                name: "Imp".into(),
+                position: 7950,
+                origin: 7951,
+                len: 2,
+            }
+        );
+
+        assert_eq!(
+            core.load_warrior_at(&parse("mov 0, 1"), 40),
+            Err(Error::TooClose {
+                position: 40,
+                other: "Imp".into(),
+                other_position: 7950,
+                min_distance: 100,
+            })
+        );
+        assert_eq!(
+            core.load_warrior_at(&parse("dat 0, 0\ndat 0, 0\njmp 0"), 4000),
+            Err(Error::ExceedsMaxLength { length: 3, max: 2 })
+        );
+        assert_eq!(core.loaded().len(), 1);
+    }
+
+    #[test]
+    fn places_randomly() {
+        let mut core = Core::new(12).unwrap();
+        core.set_min_distance(5);
+
+        // Only one position leaves room for the second warrior
+        let warrior = || parse("dat 0, 0\ndat 0, 0\ndat 0, 0\ndat 0, 0\ndat 0, 0\ndat 0, 0");
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+
+        let handles = core
+            .load_randomly(&[warrior(), warrior()], &mut rng)
+            .unwrap();
+        assert_eq!(handles[0].position, 0);
+        assert_eq!(handles[1].position, 6);
+
+        assert_eq!(
+            core.load_randomly(&[warrior()], &mut rng),
+            Err(Error::NoRoom { min_distance: 5 })
+        );
+    }
+}
@@ -1,100 +1,273 @@
-/// Container for managing the process queue of warriors. A given core has
-/// a single queue, but the queue itself may have numerous "threads" of execution
-/// and determines what process is scheduled when.
+//! The process queues of the warriors in a core. As in ICWS '94, each warrior
+//! has its own FIFO queue of processes ("tasks"), and the warriors take turns
+//! to execute the process at the front of their queue, so a warrior with more
+//! processes doesn't get more turns.
 use std::collections::{BTreeMap, VecDeque};
 
 use thiserror::Error as ThisError;
 
 use super::Offset;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct ProcessEntry {
+    /// The warrior this process belongs to, as an index into
+    /// [`Core::warriors`](super::Core::warriors)
+    pub warrior: usize,
+
     pub name: String,
     pub thread: usize,
     pub offset: Offset,
+
+    /// The component of the warrior this process belongs to, if it was
+    /// tagged, e.g. "stone" or "imp"
+    pub tag: Option<String>,
+}
+
+/// The processes of a single warrior.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+struct WarriorQueue {
+    processes: VecDeque<ProcessEntry>,
+
+    /// An increasing counter to give unique thread ids
+    next_thread_id: usize,
+
+    /// The number of processes in the queue for each tag. Tags are kept once
+    /// they have no processes left, so dead components are known.
+    tags: BTreeMap<String, usize>,
 }
 
-/// A representation of the process queue. This is effectively a simple FIFO queue.
-// TODO enforce size limits based on MAXPROCESSES
-#[derive(Debug)]
+/// The process queues of every warrior, and whose turn it is to execute.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Queue {
-    /// The actual offsets enqueued to be executed
-    queue: VecDeque<ProcessEntry>,
+    /// The queue of each warrior, indexed by warrior
+    warriors: Vec<WarriorQueue>,
 
-    /// A map of process names to the number of tasks each has in the queue.
-    /// This is updated whenever instructions are added to/removed from the queue,
-    /// and can be used to determine whether a process is alive or not.
-    processes: BTreeMap<String, usize>,
+    /// The warrior whose turn it is to execute next, unless it has no
+    /// processes left
+    turn: usize,
 
-    /// An increasing counter per process to give unique thread ids
-    next_thread_id: BTreeMap<String, usize>,
+    /// The most processes a single warrior may have (MAXPROCESSES)
+    limit: usize,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Queue {
-    /// Create an empty queue
+    /// Create an empty queue, without a limit on the number of processes
     pub fn new() -> Self {
         Self {
-            queue: VecDeque::new(),
-            processes: BTreeMap::new(),
-            next_thread_id: BTreeMap::new(),
+            warriors: Vec::new(),
+            turn: 0,
+            limit: usize::MAX,
         }
     }
 
-    /// Get the next offset for execution, removing it from the queue.
+    /// The most processes each warrior may have at once.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Limit the number of processes each warrior may have at once. Once a
+    /// warrior has this many, no more are queued for it, e.g. by `SPL`.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Get the next process for execution, removing it from the queue.
     pub fn pop(&mut self) -> Result<ProcessEntry, Error> {
-        if let Some(entry) = self.queue.pop_front() {
-            let decremented = self.processes[&entry.name].saturating_sub(1);
-            self.processes
-                .entry(entry.name.clone())
-                .and_modify(|count| *count = decremented);
+        self.remove(0)
+    }
 
-            Ok(entry)
+    /// Remove the entry at `index` (in the order of [`iter`](Queue::iter))
+    /// from the queue, e.g. as chosen by a [`Scheduler`](super::Scheduler).
+    pub fn remove(&mut self, index: usize) -> Result<ProcessEntry, Error> {
+        let (warrior, position) = if index == 0 {
+            (self.next_warrior().ok_or(Error::NoRemainingProcesses)?, 0)
         } else {
-            Err(Error::NoRemainingProcesses)
+            self.positions()
+                .nth(index)
+                .ok_or(Error::NoRemainingProcesses)?
+        };
+
+        let queue = &mut self.warriors[warrior];
+        let entry = queue
+            .processes
+            .remove(position)
+            .ok_or(Error::NoRemainingProcesses)?;
+        if let Some(count) = entry.tag.as_ref().and_then(|tag| queue.tags.get_mut(tag)) {
+            *count = count.saturating_sub(1);
         }
+        Ok(entry)
     }
 
-    /// Get the next offset for execution without modifying the queue.
+    /// Get the next process for execution without modifying the queue.
     // TODO: this should probably just return Option<&ProcessEntry>
     pub fn peek(&self) -> Result<&ProcessEntry, Error> {
-        if let Some(entry) = self.queue.get(0) {
-            Ok(entry)
-        } else {
-            Err(Error::NoRemainingProcesses)
-        }
+        self.next_warrior()
+            .and_then(|warrior| self.warriors[warrior].processes.front())
+            .ok_or(Error::NoRemainingProcesses)
     }
 
-    /// Add an entry to the process queue. If specified, it will use the given thread ID,
-    /// otherwise a new thread ID will be created based on the current number of
-    /// threads active for this process name.
-    pub fn push(&mut self, process_name: String, offset: Offset, thread: Option<usize>) {
-        let thread_id = if let Some(id) = thread {
-            id
-        } else {
-            let entry = self.next_thread_id.entry(process_name.clone()).or_insert(0);
-            let id = *entry;
-            *entry += 1;
-            id
-        };
+    /// Add a process to the back of `warrior`'s queue, unless it already has
+    /// as many as the [limit](Queue::set_limit). If specified, it will use
+    /// the given thread ID, otherwise a new thread ID will be created for the
+    /// warrior. Returns whether the process was queued.
+    pub fn push(
+        &mut self,
+        warrior: usize,
+        name: String,
+        offset: Offset,
+        thread: Option<usize>,
+    ) -> bool {
+        self.push_tagged(warrior, name, offset, thread, None)
+    }
+
+    /// Add a process like [`push`](Queue::push), as part of the component of
+    /// the warrior named by `tag`.
+    pub fn push_tagged(
+        &mut self,
+        warrior: usize,
+        name: String,
+        offset: Offset,
+        thread: Option<usize>,
+        tag: Option<String>,
+    ) -> bool {
+        if self.warriors.len() <= warrior {
+            self.warriors
+                .resize_with(warrior + 1, WarriorQueue::default);
+        }
+        let queue = &mut self.warriors[warrior];
+        if queue.processes.len() >= self.limit {
+            return false;
+        }
+
+        let thread = thread.unwrap_or_else(|| {
+            queue.next_thread_id += 1;
+            queue.next_thread_id - 1
+        });
+        if let Some(tag) = &tag {
+            *queue.tags.entry(tag.clone()).or_insert(0) += 1;
+        }
 
-        self.queue.push_back(ProcessEntry {
-            name: process_name.clone(),
-            thread: thread_id,
+        queue.processes.push_back(ProcessEntry {
+            warrior,
+            name,
+            thread,
             offset,
+            tag,
         });
+        true
+    }
+
+    /// Pass the turn on from `warrior`, which just executed, to the next
+    /// warrior (in load order) with processes left. Returns whether this
+    /// completed a cycle, i.e. every warrior still running has had its turn.
+    pub fn end_turn(&mut self, warrior: usize) -> bool {
+        let count = self.warriors.len();
+        let next = (1..=count)
+            .map(|i| (warrior + i) % count)
+            .find(|&next| !self.warriors[next].processes.is_empty());
+
+        match next {
+            Some(next) => {
+                self.turn = next;
+                next <= warrior
+            }
+            None => true,
+        }
+    }
+
+    /// The warrior whose process executes next, if any are left.
+    pub fn next_warrior(&self) -> Option<usize> {
+        self.turn_order().next()
+    }
+
+    /// Warriors with processes, starting with the one whose turn it is.
+    fn turn_order(&self) -> impl Iterator<Item = usize> + '_ {
+        let count = self.warriors.len();
+        (0..count)
+            .map(move |i| (self.turn + i) % count)
+            .filter(move |&warrior| !self.warriors[warrior].processes.is_empty())
+    }
+
+    /// The warrior each entry belongs to and its position in their queue, in
+    /// the order of [`iter`](Queue::iter).
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let order: Vec<usize> = self.turn_order().collect();
+        let rounds = order
+            .iter()
+            .map(|&warrior| self.warriors[warrior].processes.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut positions = Vec::with_capacity(self.len());
+        for position in 0..rounds {
+            for &warrior in &order {
+                if position < self.warriors[warrior].processes.len() {
+                    positions.push((warrior, position));
+                }
+            }
+        }
+        positions.into_iter()
+    }
+
+    /// Iterate over the entries in the queue, in the order they will execute
+    /// if no processes are added or removed: the front of each warrior's
+    /// queue in turn, starting with the warrior whose turn it is.
+    pub fn iter(&self) -> impl Iterator<Item = &ProcessEntry> {
+        self.positions()
+            .map(move |(warrior, position)| &self.warriors[warrior].processes[position])
+    }
+
+    /// The total number of entries in the queue, across all warriors.
+    pub fn len(&self) -> usize {
+        self.warriors
+            .iter()
+            .map(|queue| queue.processes.len())
+            .sum()
+    }
+
+    /// Whether there are no entries left in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.warriors.iter().all(|queue| queue.processes.is_empty())
+    }
 
-        *self.processes.entry(process_name).or_insert(0) += 1;
+    /// The number of processes `warrior` has in the queue.
+    pub fn thread_count(&self, warrior: usize) -> usize {
+        self.warriors
+            .get(warrior)
+            .map_or(0, |queue| queue.processes.len())
     }
 
-    /// Check the status of a process in the queue. Panics if the process was
-    /// never added to the queue.
-    pub fn thread_count(&self, name: &str) -> usize {
-        self.processes[name]
+    /// The processes of `warrior`, in the order it will execute them.
+    pub fn warrior_processes(&self, warrior: usize) -> impl Iterator<Item = &ProcessEntry> {
+        self.warriors
+            .get(warrior)
+            .into_iter()
+            .flat_map(|queue| queue.processes.iter())
+    }
+
+    /// Every tag which has been in the queue, with the warrior and the number
+    /// of processes it has left, sorted by warrior then tag.
+    pub fn tag_counts(&self) -> impl Iterator<Item = (usize, &str, usize)> {
+        self.warriors
+            .iter()
+            .enumerate()
+            .flat_map(|(warrior, queue)| {
+                queue
+                    .tags
+                    .iter()
+                    .map(move |(tag, &count)| (warrior, tag.as_str(), count))
+            })
     }
 }
 
 /// An process-related error occurred
-#[derive(ThisError, Debug, Eq, PartialEq)]
+#[derive(ThisError, Clone, Debug, Eq, PartialEq)]
 #[non_exhaustive]
 pub enum Error {
     /// All processes terminated
@@ -116,8 +289,20 @@ pub enum Error {
 
 #[cfg(test)]
 mod tests {
+    use pretty_assertions::assert_eq;
+
     use super::*;
 
+    fn entry(warrior: usize, thread: usize, offset: Offset) -> ProcessEntry {
+        ProcessEntry {
+            warrior,
+            name: format!("p{}", warrior + 1),
+            thread,
+            offset,
+            tag: None,
+        }
+    }
+
     #[test]
     fn queue_multiple_processes() {
         let mut queue = Queue::new();
@@ -127,55 +312,26 @@ mod tests {
 
         let starting_offset = Offset::new(10, 8000);
 
-        queue.push("p1".into(), starting_offset, None);
-        assert_eq!(
-            queue.peek().unwrap(),
-            &ProcessEntry {
-                name: "p1".into(),
-                thread: 0,
-                offset: starting_offset
-            }
-        );
-        assert!(queue.thread_count("p1") > 0);
+        queue.push(0, "p1".into(), starting_offset, None);
+        assert_eq!(queue.peek().unwrap(), &entry(0, 0, starting_offset));
+        assert!(queue.thread_count(0) > 0);
 
-        queue.push("p2".into(), starting_offset + 5, None);
-        assert!(queue.thread_count("p2") > 0);
+        queue.push(1, "p2".into(), starting_offset + 5, None);
+        assert!(queue.thread_count(1) > 0);
 
-        assert_eq!(
-            queue.pop().unwrap(),
-            ProcessEntry {
-                name: "p1".into(),
-                thread: 0,
-                offset: starting_offset
-            }
-        );
-        assert_eq!(
-            queue.peek().unwrap(),
-            &ProcessEntry {
-                name: "p2".into(),
-                thread: 0,
-                offset: starting_offset + 5
-            }
-        );
-        assert!(!queue.thread_count("p1") > 0);
-        assert!(queue.thread_count("p2") > 0);
+        assert_eq!(queue.pop().unwrap(), entry(0, 0, starting_offset));
+        assert!(!queue.end_turn(0));
+        assert_eq!(queue.peek().unwrap(), &entry(1, 0, starting_offset + 5));
+        assert_eq!(queue.thread_count(0), 0);
+        assert!(queue.thread_count(1) > 0);
 
-        assert_eq!(
-            queue.pop().unwrap(),
-            ProcessEntry {
-                name: "p2".into(),
-                thread: 0,
-                offset: starting_offset + 5
-            }
-        );
-        assert!(!queue.thread_count("p1") > 0);
-        assert!(!queue.thread_count("p2") > 0);
+        assert_eq!(queue.pop().unwrap(), entry(1, 0, starting_offset + 5));
+        assert!(queue.end_turn(1));
+        assert_eq!(queue.thread_count(0), 0);
+        assert_eq!(queue.thread_count(1), 0);
 
         assert_eq!(queue.peek().unwrap_err(), Error::NoRemainingProcesses);
         assert_eq!(queue.pop().unwrap_err(), Error::NoRemainingProcesses);
-
-        assert!(!queue.thread_count("p1") > 0);
-        assert!(!queue.thread_count("p2") > 0);
     }
 
     #[test]
@@ -183,40 +339,92 @@ mod tests {
         let mut queue = Queue::new();
         let starting_offset = Offset::new(10, 8000);
 
-        queue.push("p1".into(), starting_offset, None);
-        assert_eq!(
-            queue.peek().unwrap(),
-            &ProcessEntry {
-                name: "p1".into(),
-                thread: 0,
-                offset: starting_offset
-            }
-        );
-        assert!(queue.thread_count("p1") > 0);
+        queue.push(0, "p1".into(), starting_offset, None);
+        assert_eq!(queue.peek().unwrap(), &entry(0, 0, starting_offset));
 
         // should increment the thread id to 1
-        queue.push("p1".into(), starting_offset, None);
+        queue.push(0, "p1".into(), starting_offset, None);
         queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), &entry(0, 1, starting_offset));
+
+        queue.push(0, "p1".into(), starting_offset, Some(1));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), &entry(0, 1, starting_offset));
+        assert_eq!(queue.thread_count(0), 1);
+
+        // With a single warrior, every turn completes a cycle
+        assert!(queue.end_turn(0));
+    }
+
+    #[test]
+    fn warriors_take_turns() {
+        let mut queue = Queue::new();
+        let offset = Offset::new(0, 8000);
+
+        for thread in 0..3 {
+            queue.push(0, "p1".into(), offset + thread, None);
+        }
+        queue.push(1, "p2".into(), offset + 100, None);
+
+        // The order alternates between warriors, however many processes each has
+        let order: Vec<(usize, usize)> = queue
+            .iter()
+            .map(|entry| (entry.warrior, entry.thread))
+            .collect();
+        assert_eq!(order, vec![(0, 0), (1, 0), (0, 1), (0, 2)]);
+
+        let mut executed = Vec::new();
+        let mut cycles = 0;
+        for _ in 0..6 {
+            let entry = queue.pop().unwrap();
+            executed.push((entry.warrior, entry.thread));
+            queue.push(
+                entry.warrior,
+                entry.name,
+                entry.offset + 1,
+                Some(entry.thread),
+            );
+            cycles += queue.end_turn(entry.warrior) as usize;
+        }
         assert_eq!(
-            queue.peek().unwrap(),
-            &ProcessEntry {
-                name: "p1".into(),
-                thread: 1,
-                offset: starting_offset
-            }
+            executed,
+            vec![(0, 0), (1, 0), (0, 1), (1, 0), (0, 2), (1, 0)]
         );
-        assert!(queue.thread_count("p1") > 0);
+        assert_eq!(cycles, 3);
+
+        // Removing by index follows the same order
+        assert_eq!(queue.remove(1).unwrap().warrior, 1);
+        assert_eq!(queue.thread_count(1), 0);
+    }
+
+    #[test]
+    fn limits_processes() {
+        let mut queue = Queue::new();
+        queue.set_limit(2);
+        let offset = Offset::new(0, 8000);
+
+        assert!(queue.push(0, "p1".into(), offset, None));
+        assert!(queue.push(0, "p1".into(), offset, None));
+        assert!(!queue.push(0, "p1".into(), offset, None));
+        assert!(queue.push(1, "p2".into(), offset, None));
+        assert_eq!(queue.thread_count(0), 2);
+    }
+
+    #[test]
+    fn queue_tagged_processes() {
+        let mut queue = Queue::new();
+        let starting_offset = Offset::new(10, 8000);
+
+        queue.push_tagged(0, "p1".into(), starting_offset, None, Some("stone".into()));
+        queue.push_tagged(0, "p1".into(), starting_offset, None, Some("imp".into()));
+        queue.push_tagged(0, "p1".into(), starting_offset, None, Some("imp".into()));
+        queue.push(1, "p2".into(), starting_offset, None);
 
-        queue.push("p1".into(), starting_offset, Some(1));
         queue.pop().unwrap();
         assert_eq!(
-            queue.peek().unwrap(),
-            &ProcessEntry {
-                name: "p1".into(),
-                thread: 1,
-                offset: starting_offset
-            }
+            queue.tag_counts().collect::<Vec<_>>(),
+            vec![(0, "imp", 2), (0, "stone", 0)]
         );
-        assert!(queue.thread_count("p1") > 0);
+        assert_eq!(queue.thread_count(0), 2);
     }
 }
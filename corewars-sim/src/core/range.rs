@@ -0,0 +1,145 @@
+//! Checks that the field values of a warrior fit in the core it is loaded
+//! into. Values outside it are sometimes meant to wrap, e.g. the results of
+//! expressions, but may also have been meant for a bigger core, so they are
+//! never wrapped silently.
+
+use std::fmt;
+
+use corewars_core::load_file::FieldName;
+use corewars_core::Warrior;
+
+use super::{Core, Error};
+
+/// What to do with a field value too big for the core, i.e. at least the
+/// core size either side of 0, when loading a warrior.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FieldRange {
+    /// Wrap the value into the core like any other, like pMARS does, and
+    /// record it in [`Core::folded_fields`](Core::folded_fields) to be
+    /// warned about
+    #[default]
+    Permissive,
+
+    /// Refuse to load the warrior
+    Strict,
+}
+
+/// A field value which was too big for the core, and was wrapped into it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FoldedField {
+    pub warrior: String,
+
+    /// The index of the instruction in the warrior
+    pub index: u32,
+
+    pub field: FieldName,
+
+    /// The value as written
+    pub value: i32,
+
+    /// The value it was wrapped to
+    pub folded: u32,
+}
+
+impl fmt::Display for FoldedField {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}: the {:?}-field value {} of instruction {} is outside the core, wrapped to {}",
+            self.warrior, self.field, self.value, self.index, self.folded
+        )
+    }
+}
+
+impl Core {
+    /// Choose what to do with field values too big for the core when loading
+    /// warriors. By default, they are wrapped into the core.
+    pub fn set_field_range(&mut self, range: FieldRange) {
+        self.field_range = range;
+    }
+
+    /// The field values wrapped into the core while loading warriors with
+    /// [`FieldRange::Permissive`], in the order they were loaded.
+    pub fn folded_fields(&self) -> &[FoldedField] {
+        &self.folded_fields
+    }
+
+    /// Check the fields of `warrior`, called `name`, against the range.
+    pub(super) fn check_field_range(&mut self, warrior: &Warrior, name: &str) -> Result<(), Error> {
+        let size = self.size();
+        let mut folded = Vec::new();
+
+        for (index, instruction) in warrior.program.instructions.iter().enumerate() {
+            for (field, value) in [
+                (FieldName::A, &instruction.a_field),
+                (FieldName::B, &instruction.b_field),
+            ] {
+                let value = value.unwrap_value();
+                if value.unsigned_abs() < size {
+                    continue;
+                }
+
+                let index = index as u32;
+                match self.field_range {
+                    FieldRange::Strict => {
+                        return Err(Error::FieldOutOfRange {
+                            index,
+                            field,
+                            value,
+                            core_size: size,
+                        })
+                    }
+                    FieldRange::Permissive => folded.push(FoldedField {
+                        warrior: name.to_string(),
+                        index,
+                        field,
+                        value,
+                        folded: self.offset(value).value(),
+                    }),
+                }
+            }
+        }
+
+        self.folded_fields.extend(folded);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn checks_field_range() {
+        let warrior = corewars_parser::parse(";name Big\nmov -99, 99\njmp 100, -100").unwrap();
+
+        let mut core = Core::new(100).unwrap();
+        core.set_field_range(FieldRange::Strict);
+        assert_eq!(
+            core.load_warrior(&warrior).unwrap_err(),
+            Error::FieldOutOfRange {
+                index: 1,
+                field: FieldName::A,
+                value: 100,
+                core_size: 100,
+            }
+        );
+        assert!(core.loaded().is_empty());
+
+        core.set_field_range(FieldRange::Permissive);
+        core.load_warrior(&warrior).unwrap();
+        let folded: Vec<(i32, u32)> = core
+            .folded_fields()
+            .iter()
+            .map(|folded| (folded.value, folded.folded))
+            .collect();
+        assert_eq!(folded, vec![(100, 0), (-100, 0)]);
+        assert_eq!(core.get(1).a_field.unwrap_value(), 0);
+        assert_eq!(
+            core.folded_fields()[1].to_string(),
+            "Big: the B-field value -100 of instruction 1 is outside the core, wrapped to 0"
+        );
+    }
+}
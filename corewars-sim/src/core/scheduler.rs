@@ -0,0 +1,123 @@
+//! Choosing which process in the queue executes next. The standard
+//! [`RoundRobin`](RoundRobin) scheduler is used unless another is given to
+//! [`Core::set_scheduler`](super::Core::set_scheduler).
+
+use std::fmt;
+
+use super::process::Queue;
+
+/// A scheduling discipline for the processes in a core, e.g. to give some
+/// warriors more turns than others. Schedulers are shared between threads
+/// along with their core, e.g. by a [`CoreSnapshot`](crate::CoreSnapshot).
+///
+/// ```
+/// use corewars_sim::{Core, Queue, Scheduler};
+///
+/// /// Always execute the most recently queued process
+/// #[derive(Clone, Debug)]
+/// struct Newest;
+///
+/// impl Scheduler for Newest {
+///     fn select(&mut self, queue: &Queue) -> usize {
+///         queue.len() - 1
+///     }
+/// }
+///
+/// let mut core = Core::new(100).unwrap();
+/// core.set_scheduler(Newest);
+/// ```
+pub trait Scheduler: SchedulerClone + fmt::Debug + Send + Sync {
+    /// Choose the index in `queue` (in the order of [`Queue::iter`]) of the
+    /// process to execute next, which will be removed from the queue. `queue` is never empty, and the returned
+    /// index must be less than its length.
+    fn select(&mut self, queue: &Queue) -> usize;
+}
+
+/// A helper for cloning boxed schedulers, implemented for any `Scheduler`
+/// which implements `Clone`.
+pub trait SchedulerClone {
+    fn clone_box(&self) -> Box<dyn Scheduler>;
+}
+
+impl<T: Scheduler + Clone + 'static> SchedulerClone for T {
+    fn clone_box(&self) -> Box<dyn Scheduler> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Scheduler> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// The standard scheduler, as in ICWS '94: the warriors take turns, each
+/// executing the process at the front of its own queue. Each executed process
+/// is queued again at the back of its warrior's queue, so all of a warrior's
+/// processes take turns too.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoundRobin;
+
+impl Scheduler for RoundRobin {
+    fn select(&mut self, _queue: &Queue) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::Core;
+
+    /// Always prefer processes of the named warrior
+    #[derive(Clone, Debug)]
+    struct Priority(&'static str);
+
+    impl Scheduler for Priority {
+        fn select(&mut self, queue: &Queue) -> usize {
+            queue
+                .iter()
+                .position(|entry| entry.name == self.0)
+                .unwrap_or(0)
+        }
+    }
+
+    fn run(scheduler: Option<Priority>) -> Vec<usize> {
+        let mut core = Core::new(100).unwrap();
+        if let Some(scheduler) = scheduler {
+            core.set_scheduler(scheduler);
+        }
+
+        for (i, program) in [";name Imp\nmov 0, 1", ";name Wait\njmp 0"]
+            .iter()
+            .enumerate()
+        {
+            let warrior = corewars_parser::parse(program).unwrap();
+            core.load_warrior_at(&warrior, i as u32 * 50).unwrap();
+        }
+
+        for _ in 0..4 {
+            core.step().unwrap();
+        }
+
+        core.owned_cells().to_vec()
+    }
+
+    #[test]
+    fn round_robin_alternates() {
+        assert_eq!(run(None), vec![3, 1]);
+    }
+
+    #[test]
+    fn custom_scheduler() {
+        assert_eq!(run(Some(Priority("Imp"))), vec![5, 1]);
+
+        // The scheduler is kept when cloning the core
+        let mut core = Core::new(10).unwrap();
+        core.set_scheduler(Priority("Imp"));
+        let cloned = core.clone();
+        assert_eq!(format!("{:?}", cloned.scheduler), "Priority(\"Imp\")");
+    }
+}
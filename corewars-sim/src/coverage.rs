@@ -0,0 +1,210 @@
+//! Tracking which combinations of opcode, modifier and address modes have been
+//! executed, to find instructions a test suite or battle never exercised.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use corewars_core::load_file::{AddressMode, Instruction, Modifier, Opcode};
+
+/// The features of an instruction which affect how it is executed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Combination {
+    pub opcode: Opcode,
+    pub modifier: Modifier,
+    pub a_mode: AddressMode,
+    pub b_mode: AddressMode,
+}
+
+impl Combination {
+    /// Every combination which could possibly be executed.
+    pub fn all() -> impl Iterator<Item = Self> {
+        Opcode::iter_values().flat_map(|&opcode| {
+            Modifier::iter_values().flat_map(move |&modifier| {
+                AddressMode::iter_values().flat_map(move |&a_mode| {
+                    AddressMode::iter_values().map(move |&b_mode| Self {
+                        opcode,
+                        modifier,
+                        a_mode,
+                        b_mode,
+                    })
+                })
+            })
+        })
+    }
+}
+
+impl From<&Instruction> for Combination {
+    fn from(instruction: &Instruction) -> Self {
+        Self {
+            opcode: instruction.opcode,
+            modifier: instruction.modifier,
+            a_mode: instruction.a_field.address_mode,
+            b_mode: instruction.b_field.address_mode,
+        }
+    }
+}
+
+impl fmt::Display for Combination {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}.{} {}, {}",
+            self.opcode, self.modifier, self.a_mode, self.b_mode
+        )
+    }
+}
+
+/// The number of times each [`Combination`](Combination) was executed.
+/// Coverage can be collected from a core with
+/// [`Core::enable_coverage`](crate::Core::enable_coverage), and combined
+/// across many cores with [`merge`](Coverage::merge).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    executed: HashMap<Combination, usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `instruction` was executed.
+    pub fn record(&mut self, instruction: &Instruction) {
+        *self.executed.entry(instruction.into()).or_insert(0) += 1;
+    }
+
+    /// Add all executions recorded by `other`.
+    pub fn merge(&mut self, other: &Self) {
+        for (&combination, &count) in &other.executed {
+            *self.executed.entry(combination).or_insert(0) += count;
+        }
+    }
+
+    /// The number of times `combination` was executed.
+    pub fn count(&self, combination: &Combination) -> usize {
+        self.executed.get(combination).copied().unwrap_or(0)
+    }
+
+    /// The number of distinct combinations executed.
+    pub fn covered(&self) -> usize {
+        self.executed.len()
+    }
+
+    /// All combinations which were never executed.
+    pub fn gaps(&self) -> Vec<Combination> {
+        Combination::all()
+            .filter(|combination| !self.executed.contains_key(combination))
+            .collect()
+    }
+}
+
+impl fmt::Display for Coverage {
+    /// Summarize the coverage of each opcode and modifier, with the address
+    /// modes not covered for any which were executed at least once.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let total = Combination::all().count();
+        write!(
+            formatter,
+            "{} of {} instruction combinations executed",
+            self.covered(),
+            total
+        )?;
+
+        let mut never_executed = Vec::new();
+
+        for &opcode in Opcode::iter_values() {
+            if !self.executed.keys().any(|c| c.opcode == opcode) {
+                never_executed.push(opcode.to_string());
+                continue;
+            }
+
+            for &modifier in Modifier::iter_values() {
+                let (covered, gaps): (Vec<Combination>, Vec<Combination>) = Combination::all()
+                    .filter(|c| c.opcode == opcode && c.modifier == modifier)
+                    .partition(|c| self.executed.contains_key(c));
+
+                if covered.is_empty() {
+                    write!(formatter, "\n{}.{}: never executed", opcode, modifier)?;
+                } else if !gaps.is_empty() {
+                    let modes: Vec<String> = gaps
+                        .iter()
+                        .map(|c| format!("{}{}", c.a_mode, c.b_mode))
+                        .collect();
+                    write!(
+                        formatter,
+                        "\n{}.{}: {} of {} address modes, missing {}",
+                        opcode,
+                        modifier,
+                        covered.len(),
+                        covered.len() + gaps.len(),
+                        modes.join(" ")
+                    )?;
+                }
+            }
+        }
+
+        if !never_executed.is_empty() {
+            write!(formatter, "\nnever executed: {}", never_executed.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::Core;
+
+    #[test]
+    fn records_executed_instructions() {
+        let mut core = Core::new(100).unwrap();
+        let warrior = corewars_parser::parse("mov.i $0, $1").unwrap();
+        core.load_warrior(&warrior).unwrap();
+        core.enable_coverage();
+
+        for _ in 0..3 {
+            core.step().unwrap();
+        }
+
+        let coverage = core.coverage().unwrap();
+        let imp = Combination {
+            opcode: Opcode::Mov,
+            modifier: Modifier::I,
+            a_mode: AddressMode::Direct,
+            b_mode: AddressMode::Direct,
+        };
+
+        assert_eq!(coverage.count(&imp), 3);
+        assert_eq!(coverage.covered(), 1);
+        assert_eq!(coverage.gaps().len(), Combination::all().count() - 1);
+        assert_eq!(imp.to_string(), "MOV.I $, $");
+    }
+
+    #[test]
+    fn merges_coverage() {
+        let mut first = Coverage::new();
+        first.record(&Instruction::default());
+
+        let mut second = Coverage::new();
+        second.record(&Instruction::default());
+        second.record(
+            &corewars_parser::parse("jmp 0")
+                .unwrap()
+                .program
+                .instructions[0],
+        );
+
+        first.merge(&second);
+
+        assert_eq!(first.count(&(&Instruction::default()).into()), 2);
+        assert_eq!(first.covered(), 2);
+
+        let report = first.to_string();
+        assert!(report.starts_with("2 of 8512 instruction combinations executed\n"));
+        assert!(report.contains("\nDAT.A: never executed"));
+        assert!(report.contains("\nJMP.B: 1 of 64 address modes, missing ## #$ "));
+    }
+}
@@ -0,0 +1,221 @@
+//! The events of a battle, in the one shape shared by everything which
+//! follows a battle as it runs: observers, replays, the websocket stream and
+//! the debugger.
+//!
+//! Events are only ever added to, never changed, and a new kind of event
+//! increments [`Event::VERSION`], so consumers of serialized events can tell
+//! which kinds to expect.
+
+use std::fmt;
+
+use corewars_core::load_file::Instruction;
+
+use crate::battle::Outcome;
+
+/// Something which happened during a battle, see
+/// [`Core::enable_events`](crate::Core::enable_events) and
+/// [`Battle::run_with_events`](crate::Battle::run_with_events).
+///
+/// The `cycle` of each event is the cycle it happened in, counting from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A process of `warrior` executed `instruction`, as it was before it
+    /// executed
+    InstructionExecuted {
+        cycle: usize,
+        warrior: String,
+        address: u32,
+        instruction: Instruction,
+    },
+
+    /// The instruction executed by `warrior` wrote `instruction` to `address`
+    CellWritten {
+        cycle: usize,
+        warrior: String,
+        address: u32,
+        instruction: Instruction,
+    },
+
+    /// `warrior` split, starting a new process at `address`
+    ProcessSpawned {
+        cycle: usize,
+        warrior: String,
+        address: u32,
+    },
+
+    /// A process of `warrior` died executing the instruction at `address`
+    ProcessDied {
+        cycle: usize,
+        warrior: String,
+        address: u32,
+    },
+
+    /// The last process of `warrior` died
+    WarriorEliminated { cycle: usize, warrior: String },
+
+    /// The round ended with `outcome`. Unlike other events, `cycle` is the
+    /// number of cycles executed, i.e. the cycle after the last one.
+    RoundEnded { cycle: usize, outcome: Outcome },
+}
+
+impl Event {
+    /// The version of the set of events, incremented when a kind is added.
+    pub const VERSION: u32 = 1;
+
+    pub fn cycle(&self) -> usize {
+        match self {
+            Self::InstructionExecuted { cycle, .. }
+            | Self::CellWritten { cycle, .. }
+            | Self::ProcessSpawned { cycle, .. }
+            | Self::ProcessDied { cycle, .. }
+            | Self::WarriorEliminated { cycle, .. }
+            | Self::RoundEnded { cycle, .. } => *cycle,
+        }
+    }
+
+    /// The warrior the event happened to, unless it happened to the round.
+    pub fn warrior(&self) -> Option<&str> {
+        match self {
+            Self::InstructionExecuted { warrior, .. }
+            | Self::CellWritten { warrior, .. }
+            | Self::ProcessSpawned { warrior, .. }
+            | Self::ProcessDied { warrior, .. }
+            | Self::WarriorEliminated { warrior, .. } => Some(warrior),
+            Self::RoundEnded { .. } => None,
+        }
+    }
+
+    /// A stable name for the kind of event, for serialized events, e.g.
+    /// `instruction_executed`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InstructionExecuted { .. } => "instruction_executed",
+            Self::CellWritten { .. } => "cell_written",
+            Self::ProcessSpawned { .. } => "process_spawned",
+            Self::ProcessDied { .. } => "process_died",
+            Self::WarriorEliminated { .. } => "warrior_eliminated",
+            Self::RoundEnded { .. } => "round_ended",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "cycle {}: ", self.cycle())?;
+        match self {
+            Self::InstructionExecuted {
+                warrior,
+                address,
+                instruction,
+                ..
+            } => write!(
+                formatter,
+                "{} executed {:0>5} {}",
+                warrior, address, instruction
+            ),
+            Self::CellWritten {
+                warrior,
+                address,
+                instruction,
+                ..
+            } => write!(
+                formatter,
+                "{} wrote {:0>5} {}",
+                warrior, address, instruction
+            ),
+            Self::ProcessSpawned {
+                warrior, address, ..
+            } => write!(
+                formatter,
+                "{} started a process at {:0>5}",
+                warrior, address
+            ),
+            Self::ProcessDied {
+                warrior, address, ..
+            } => write!(
+                formatter,
+                "a process of {} died at {:0>5}",
+                warrior, address
+            ),
+            Self::WarriorEliminated { warrior, .. } => {
+                write!(formatter, "{} was eliminated", warrior)
+            }
+            Self::RoundEnded { outcome, .. } => write!(formatter, "round ended, {}", outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{Battle, BattleConfig};
+
+    fn events(programs: &[&str]) -> Vec<Event> {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 100,
+            max_cycles: 10,
+            min_distance: 0,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            battle
+                .core_mut()
+                .load_warrior_at(&warrior, i as u32 * 50)
+                .expect("Failed to load warrior");
+        }
+
+        let mut events = Vec::new();
+        battle.run_with_events(|event| events.push(event.clone()));
+        events
+    }
+
+    #[test]
+    fn records_events() {
+        let events = events(&[
+            ";name Splitter\nspl 2\nmov 0, 1\ndat 0, 0",
+            ";name Dead\ndat 0, 0",
+        ]);
+        let lines: Vec<String> = events.iter().map(ToString::to_string).collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "cycle 0: Splitter executed 00000 SPL.B   $2,     $0",
+                "cycle 0: Splitter started a process at 00002",
+                "cycle 0: Dead executed 00050 DAT.F   $0,     $0",
+                "cycle 0: a process of Dead died at 00050",
+                "cycle 0: Dead was eliminated",
+                "cycle 1: round ended, Splitter wins",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_writes() {
+        let events = events(&[";name Imp\nmov 0, 1"]);
+
+        assert_eq!(events.len(), 21);
+        let executed = match &events[0] {
+            Event::InstructionExecuted { instruction, .. } => instruction.clone(),
+            event => panic!("unexpected event {}", event),
+        };
+        assert_eq!(
+            events[1],
+            Event::CellWritten {
+                cycle: 0,
+                warrior: "Imp".into(),
+                address: 1,
+                instruction: executed,
+            }
+        );
+        assert_eq!(events[1].warrior(), Some("Imp"));
+        assert_eq!(events[1].kind(), "cell_written");
+        assert_eq!(events[20].warrior(), None);
+        assert_eq!(events[20].cycle(), 10);
+    }
+}
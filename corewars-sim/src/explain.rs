@@ -0,0 +1,189 @@
+//! Descriptions of what an instruction does when executed. These are built
+//! from the same tables used by the simulator, through the
+//! [`footprint`](corewars_core::analysis::footprint) of the instruction, so
+//! they always match its behavior.
+
+use std::fmt;
+
+use corewars_core::analysis::{footprint, Part};
+use corewars_core::load_file::{Field, FieldName, FieldPair, Instruction, Modifier, Opcode};
+
+/// A structured description of an opcode/modifier combination.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    pub opcode: Opcode,
+    pub modifier: Modifier,
+
+    /// The fields of the A operand which are paired with fields of the B
+    /// operand, in the order they are operated on. Empty if the opcode does
+    /// not use its operands' fields.
+    pub pairs: Vec<FieldPair>,
+
+    /// Fields of the A operand which are read
+    pub a_reads: Vec<FieldName>,
+
+    /// Fields of the B operand which are read
+    pub b_reads: Vec<FieldName>,
+
+    /// Fields of the B operand which are written
+    pub b_writes: Vec<FieldName>,
+
+    /// Whether the opcode and modifier are used as well as the fields, i.e.
+    /// the whole instruction is read and/or written.
+    pub whole_instruction: bool,
+}
+
+/// Describe what an instruction with the given opcode and modifier does.
+pub fn explain(opcode: Opcode, modifier: Modifier) -> Explanation {
+    let usage = opcode.field_usage();
+    let pairs = match usage {
+        Some(_) => modifier.field_pairs().to_vec(),
+        None => Vec::new(),
+    };
+
+    // Direct operands to different addresses, so nothing else is accessed
+    // and the accesses to each operand can be told apart
+    let mut instruction = Instruction::new(opcode, Field::direct(1), Field::direct(2));
+    instruction.modifier = modifier;
+    let footprint = footprint(&instruction);
+
+    let fields = |parts: Vec<Part>| -> Vec<FieldName> {
+        parts
+            .into_iter()
+            .filter_map(|part| match part {
+                Part::Field(field) => Some(field),
+                Part::Instruction => None,
+            })
+            .collect()
+    };
+
+    Explanation {
+        opcode,
+        modifier,
+        whole_instruction: footprint
+            .reads
+            .iter()
+            .chain(&footprint.writes)
+            .any(|access| access.part == Part::Instruction),
+        pairs,
+        a_reads: fields(footprint.target_reads(FieldName::A)),
+        b_reads: fields(footprint.target_reads(FieldName::B)),
+        b_writes: fields(footprint.target_writes(FieldName::B)),
+    }
+}
+
+impl Explanation {
+    fn describe_fields(&self, fields: &[FieldName], operand: &str) -> Vec<String> {
+        let mut names: Vec<String> = fields
+            .iter()
+            .map(|field| format!("{:?}-field of {}", field, operand))
+            .collect();
+
+        if self.whole_instruction && !fields.is_empty() {
+            names.push(format!("opcode and modifier of {}", operand));
+        }
+
+        names
+    }
+}
+
+fn join_or_nothing(names: Vec<String>) -> String {
+    if names.is_empty() {
+        String::from("nothing")
+    } else {
+        names.join(", ")
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            formatter,
+            "{}.{}: {}",
+            self.opcode,
+            self.modifier,
+            self.opcode.description()
+        )?;
+
+        if self.pairs.is_empty() {
+            return write!(formatter, "  operand fields are not used");
+        }
+
+        let mut reads = self.describe_fields(&self.a_reads, "A");
+        reads.extend(self.describe_fields(&self.b_reads, "B"));
+
+        writeln!(formatter, "  reads:  {}", join_or_nothing(reads))?;
+        write!(
+            formatter,
+            "  writes: {}",
+            join_or_nothing(self.describe_fields(&self.b_writes, "B"))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    use FieldName::{A, B};
+
+    #[test]
+    fn mov_ab() {
+        let explanation = explain(Opcode::Mov, Modifier::AB);
+
+        assert_eq!(explanation.pairs, vec![FieldPair { a: A, b: B }]);
+        assert_eq!(explanation.a_reads, vec![A]);
+        assert_eq!(explanation.b_reads, vec![]);
+        assert_eq!(explanation.b_writes, vec![B]);
+        assert!(!explanation.whole_instruction);
+
+        assert_eq!(
+            explanation.to_string(),
+            [
+                "MOV.AB: copy A to B",
+                "  reads:  A-field of A",
+                "  writes: B-field of B",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn seq_i() {
+        let explanation = explain(Opcode::Seq, Modifier::I);
+
+        assert!(explanation.whole_instruction);
+        assert_eq!(explanation.b_writes, vec![]);
+        assert_eq!(
+            explanation.to_string(),
+            [
+                "SEQ.I: skip the next instruction if A equals B",
+                "  reads:  A-field of A, B-field of A, opcode and modifier of A, \
+                 A-field of B, B-field of B, opcode and modifier of B",
+                "  writes: nothing",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn jmp_ignores_fields() {
+        let explanation = explain(Opcode::Jmp, Modifier::B);
+
+        assert!(explanation.pairs.is_empty());
+        assert_eq!(
+            explanation.to_string(),
+            "JMP.B: jump to A\n  operand fields are not used"
+        );
+    }
+
+    #[test]
+    fn add_x() {
+        let explanation = explain(Opcode::Add, Modifier::X);
+
+        assert_eq!(explanation.a_reads, vec![B, A]);
+        assert_eq!(explanation.b_writes, vec![A, B]);
+    }
+}
@@ -0,0 +1,399 @@
+//! Faults injected into a battle from outside, like a cosmic ray flipping a
+//! bit, to study how well warriors stand up to interference. A [`Faults`]
+//! plan is given to a battle with [`set_faults`](crate::Battle::set_faults),
+//! and each of its faults is injected just before the cycle it is scheduled
+//! for. Faults which need to pick something, like which process to kill, do
+//! so with a seeded random number generator, so a plan always injects the
+//! same faults into the same battle.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
+
+use corewars_core::load_file::{AddressMode, Field, Instruction, Modifier, Opcode, Value};
+
+use crate::core::Core;
+
+/// Something done to a core from outside the battle. Addresses are absolute.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Flip a random bit of the instruction at `address`, changing either
+    /// its opcode, its modifier, or one of its address modes or values to
+    /// another
+    FlipCell { address: u32 },
+
+    /// Kill a random process, of the warrior with this name or of any
+    /// warrior
+    KillProcess { warrior: Option<String> },
+
+    /// Replace the A or B value of the instruction at `address` with a
+    /// random other value
+    CorruptOperand { address: u32, operand: Operand },
+}
+
+/// One of the two fields of an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    A,
+    B,
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    /// Parse a fault like "flip=ADDRESS", "kill", "kill=NAME",
+    /// "corrupt-a=ADDRESS" or "corrupt-b=ADDRESS".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let kind = parts.next().unwrap_or_default();
+        let argument = parts.next();
+        let address = || argument.and_then(|address| address.parse().ok());
+
+        let fault = match (kind, argument) {
+            ("kill", None) => Some(Self::KillProcess { warrior: None }),
+            ("kill", Some(name)) if !name.is_empty() => Some(Self::KillProcess {
+                warrior: Some(name.to_string()),
+            }),
+            ("flip", _) => address().map(|address| Self::FlipCell { address }),
+            ("corrupt-a", _) => address().map(|address| Self::CorruptOperand {
+                address,
+                operand: Operand::A,
+            }),
+            ("corrupt-b", _) => address().map(|address| Self::CorruptOperand {
+                address,
+                operand: Operand::B,
+            }),
+            _ => None,
+        };
+
+        fault.ok_or_else(|| {
+            format!(
+                "unknown fault {:?}, expected \"flip=ADDRESS\", \"kill\", \"kill=NAME\", \
+                 \"corrupt-a=ADDRESS\" or \"corrupt-b=ADDRESS\"",
+                s
+            )
+        })
+    }
+}
+
+/// What injecting a fault did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Effect {
+    /// The instruction at `address` was changed
+    Changed {
+        address: u32,
+        before: Instruction,
+        after: Instruction,
+    },
+
+    /// A process of `warrior`, about to execute the instruction at
+    /// `address`, was killed
+    Killed { warrior: String, address: u32 },
+
+    /// There was no process to kill
+    Nothing,
+}
+
+/// A fault which was injected, and what it did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Injection {
+    /// The number of cycles executed before the fault was injected
+    pub cycle: usize,
+    pub fault: Fault,
+    pub effect: Effect,
+}
+
+impl fmt::Display for Injection {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "cycle {}: ", self.cycle)?;
+        match &self.effect {
+            Effect::Changed {
+                address,
+                before,
+                after,
+            } => write!(
+                formatter,
+                "changed {:0>6} from {} to {}",
+                address, before, after
+            ),
+            Effect::Killed { warrior, address } => write!(
+                formatter,
+                "killed a process of {} at {:0>6}",
+                warrior, address
+            ),
+            Effect::Nothing => write!(formatter, "no process to kill"),
+        }
+    }
+}
+
+/// The faults to inject into a battle, and when.
+///
+/// ```
+/// use corewars_sim::{Battle, BattleConfig, Fault, Faults};
+///
+/// let faults = Faults::new(0)
+///     .at(100, Fault::FlipCell { address: 1 })
+///     .at(200, Fault::KillProcess { warrior: None });
+///
+/// let mut battle = Battle::new(BattleConfig::default()).unwrap();
+/// battle.set_faults(faults);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Faults {
+    scheduled: Vec<(usize, Fault)>,
+    rng: StdRng,
+    injected: Vec<Injection>,
+}
+
+impl Faults {
+    /// An empty plan, making its random choices from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            scheduled: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+            injected: Vec::new(),
+        }
+    }
+
+    /// Inject `fault` once `cycle` cycles have been executed, or before the
+    /// first cycle if `cycle` is 0. Faults scheduled for the same cycle are
+    /// injected in the order they were added.
+    pub fn at(mut self, cycle: usize, fault: Fault) -> Self {
+        let index = self.scheduled.partition_point(|&(at, _)| at <= cycle);
+        self.scheduled.insert(index, (cycle, fault));
+        self
+    }
+
+    /// Whether no faults are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.scheduled.is_empty()
+    }
+
+    /// The faults injected so far, in order.
+    pub fn injected(&self) -> &[Injection] {
+        &self.injected
+    }
+
+    /// Inject every fault which is due given the cycles `core` has executed,
+    /// and hasn't been injected yet.
+    pub fn inject(&mut self, core: &mut Core) {
+        let cycle = core.cycles();
+        let due: Vec<Fault> = self.scheduled[self.injected.len()..]
+            .iter()
+            .take_while(|&&(at, _)| at <= cycle)
+            .map(|(_, fault)| fault.clone())
+            .collect();
+
+        for fault in due {
+            let effect = self.apply(&fault, core);
+            self.injected.push(Injection {
+                cycle,
+                fault,
+                effect,
+            });
+        }
+    }
+
+    fn apply(&mut self, fault: &Fault, core: &mut Core) -> Effect {
+        match fault {
+            Fault::FlipCell { address } => {
+                let size = core.size();
+                self.change(core, *address, |rng, instruction| {
+                    flip(rng, instruction, size)
+                })
+            }
+            Fault::CorruptOperand { address, operand } => {
+                let size = core.size();
+                self.change(core, *address, |rng, instruction| {
+                    let field = match operand {
+                        Operand::A => &mut instruction.a_field,
+                        Operand::B => &mut instruction.b_field,
+                    };
+                    let shift = rng.gen_range(1, size.max(2));
+                    field.value = Value::Literal(
+                        ((i64::from(field.unwrap_value()) + i64::from(shift))
+                            .rem_euclid(i64::from(size))) as i32,
+                    );
+                })
+            }
+            Fault::KillProcess { warrior } => {
+                let candidates = core
+                    .process_queue()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| warrior.as_ref().is_none_or(|name| &entry.name == name))
+                    .map(|(index, _)| index);
+
+                match candidates.choose(&mut self.rng) {
+                    Some(index) => {
+                        let entry = core
+                            .kill_process(index)
+                            .expect("index should be in the queue");
+                        Effect::Killed {
+                            warrior: entry.name,
+                            address: entry.offset.value(),
+                        }
+                    }
+                    None => Effect::Nothing,
+                }
+            }
+        }
+    }
+
+    /// Change the instruction at `address` with `change`.
+    fn change<F>(&mut self, core: &mut Core, address: u32, change: F) -> Effect
+    where
+        F: FnOnce(&mut StdRng, &mut Instruction),
+    {
+        let address = address % core.size();
+        let instruction = core.get_mut(address as i32);
+        let before = instruction.clone();
+        change(&mut self.rng, instruction);
+
+        Effect::Changed {
+            address,
+            before,
+            after: instruction.clone(),
+        }
+    }
+}
+
+/// Flip one bit of `instruction`, in a core of `size` instructions: an
+/// opcode, modifier or address mode changes to another at random, and a
+/// value has one of its bits flipped.
+fn flip(rng: &mut StdRng, instruction: &mut Instruction, size: u32) {
+    fn other<T: Copy + PartialEq>(rng: &mut StdRng, all: &[T], current: T) -> T {
+        *all.iter()
+            .filter(|&&value| value != current)
+            .choose(rng)
+            .unwrap_or(&current)
+    }
+
+    fn flip_value(rng: &mut StdRng, field: &mut Field, size: u32) {
+        let bits = (u32::BITS - (size - 1).leading_zeros()).max(1);
+        let flipped = field.unwrap_value() ^ (1 << rng.gen_range(0, bits));
+        field.value = Value::Literal(flipped.rem_euclid(size as i32));
+    }
+
+    let fields = [Operand::A, Operand::B];
+    match rng.gen_range(0, 4) {
+        0 => instruction.opcode = other(rng, Opcode::all(), instruction.opcode),
+        1 => instruction.modifier = other(rng, Modifier::all(), instruction.modifier),
+        part => {
+            let field = match fields.choose(rng) {
+                Some(Operand::A) => &mut instruction.a_field,
+                _ => &mut instruction.b_field,
+            };
+            if part == 2 {
+                field.address_mode = other(rng, AddressMode::all(), field.address_mode);
+            } else {
+                flip_value(rng, field, size);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::battle::{Battle, BattleConfig, Outcome};
+
+    fn battle(faults: Faults) -> Battle {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 800,
+            max_cycles: 200,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        battle.set_faults(faults);
+
+        let splitter = corewars_parser::parse(";name Splitter\nspl 0\njmp -1").unwrap();
+        let imp = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+        battle.load(&splitter, 0).unwrap();
+        battle.load(&imp, 400).unwrap();
+        battle
+    }
+
+    #[test]
+    fn parses_faults() {
+        assert_eq!(
+            "flip=12".parse::<Fault>(),
+            Ok(Fault::FlipCell { address: 12 })
+        );
+        assert_eq!(
+            "kill=Imp".parse::<Fault>(),
+            Ok(Fault::KillProcess {
+                warrior: Some("Imp".into())
+            })
+        );
+        assert_eq!(
+            "corrupt-b=3".parse::<Fault>(),
+            Ok(Fault::CorruptOperand {
+                address: 3,
+                operand: Operand::B
+            })
+        );
+        assert!("flip".parse::<Fault>().is_err());
+        assert!("melt=3".parse::<Fault>().is_err());
+    }
+
+    #[test]
+    fn kills_processes() {
+        // The imp only has one process, so killing it loses the battle
+        let mut killed = battle(Faults::new(0).at(
+            10,
+            Fault::KillProcess {
+                warrior: Some("Imp".into()),
+            },
+        ));
+        assert_eq!(killed.run(), Outcome::Win("Splitter".into()));
+        assert_eq!(killed.core().cycles(), 11);
+        assert_eq!(
+            killed.core().steps_taken(),
+            21,
+            "the step after the fault should still be executed"
+        );
+
+        let faults = killed.faults().unwrap();
+        assert_eq!(faults.injected().len(), 1);
+        assert!(matches!(
+            &faults.injected()[0].effect,
+            Effect::Killed { warrior, .. } if warrior == "Imp"
+        ));
+    }
+
+    #[test]
+    fn changes_cells() {
+        let faults = Faults::new(1).at(0, Fault::FlipCell { address: 400 }).at(
+            0,
+            Fault::CorruptOperand {
+                address: 801,
+                operand: Operand::A,
+            },
+        );
+        let mut flipped = battle(faults.clone());
+        flipped.step();
+
+        let injected = flipped.faults().unwrap().injected();
+        assert_eq!(injected.len(), 2);
+        for injection in injected {
+            match &injection.effect {
+                Effect::Changed { before, after, .. } => assert_ne!(before, after),
+                effect => panic!("unexpected effect {:?}", effect),
+            }
+        }
+        assert!(injected[1]
+            .to_string()
+            .starts_with("cycle 0: changed 000001 from "));
+
+        // The same plan makes the same changes
+        let mut again = battle(faults);
+        again.step();
+        assert_eq!(again.faults().unwrap().injected(), injected);
+    }
+}
@@ -0,0 +1,375 @@
+//! Differential testing of the simulator: battles between randomly generated
+//! warriors are run in lockstep with two configurations of the simulator,
+//! which must agree on the state of the core after every step and on the
+//! outcome. This catches changes, such as optimizations, which alter how
+//! warriors behave.
+//!
+//! Random warriors are mostly nonsense, but they exercise every opcode,
+//! modifier and address mode, and bomb each other often enough to cover
+//! processes dying and splitting.
+
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use corewars_core::load_file::{
+    AddressMode, Field, Instruction, Metadata, Modifier, Opcode, Program, Value,
+};
+use corewars_core::Warrior;
+
+use crate::battle::{Battle, BattleConfig, Outcome};
+use crate::core::Core;
+#[cfg(any(test, feature = "reference"))]
+use crate::reference::Reference;
+
+/// A random warrior of between 1 and `max_length` instructions, for a core of
+/// `core_size` instructions. Most field values point within `max_length` of
+/// the instruction, while the rest point anywhere in the core, and the last
+/// instruction jumps back to the first, so warriors keep running until they
+/// are bombed. The P-space opcodes are never generated, since the simulator
+/// can't execute them yet.
+///
+/// # Panics
+///
+/// If `max_length` or `core_size` is 0.
+pub fn random_warrior<R: Rng>(rng: &mut R, name: &str, max_length: u32, core_size: u32) -> Warrior {
+    assert!(
+        max_length > 0,
+        "warriors must have at least one instruction"
+    );
+    assert!(core_size > 0, "core size must be positive");
+
+    // DAT is left out, so the only way to die is from being bombed or
+    // running off the end of the warrior into empty core
+    let opcodes: Vec<Opcode> = Opcode::all()
+        .iter()
+        .copied()
+        .filter(|&opcode| !matches!(opcode, Opcode::Dat | Opcode::Ldp | Opcode::Stp))
+        .collect();
+
+    let nearby = i64::from(max_length);
+    let field = |rng: &mut R| {
+        let value = if rng.gen_bool(0.8) {
+            rng.gen_range(-nearby, nearby + 1)
+        } else {
+            rng.gen_range(0, i64::from(core_size))
+        };
+
+        Field {
+            address_mode: *AddressMode::all().choose(rng).unwrap(),
+            value: Value::Literal(value.rem_euclid(i64::from(core_size)) as i32),
+        }
+    };
+
+    let len = rng.gen_range(1, max_length + 1);
+    let mut instructions: Vec<Instruction> = (1..len)
+        .map(|_| Instruction {
+            opcode: *opcodes.choose(rng).unwrap(),
+            modifier: *Modifier::all().choose(rng).unwrap(),
+            a_field: field(rng),
+            b_field: field(rng),
+        })
+        .collect();
+
+    let back_to_start = (core_size - (len - 1) % core_size) % core_size;
+    instructions.push(Instruction::new(
+        Opcode::Jmp,
+        Field::direct(back_to_start as i32),
+        field(rng),
+    ));
+
+    Warrior {
+        program: Program {
+            instructions: instructions.into(),
+            origin: Some(rng.gen_range(0, len)),
+        },
+        metadata: Metadata {
+            name: Some(name.into()),
+            ..Metadata::default()
+        },
+    }
+}
+
+/// A battle which two configurations of the simulator disagreed on.
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The round of [`Differential::run`](Differential::run) the battle was
+    /// generated in, counting from 0
+    pub round: usize,
+
+    /// The warriors in the battle, which can be dumped to reproduce it
+    pub warriors: Vec<Warrior>,
+
+    /// The number of cycles executed when the configurations first disagreed
+    pub cycle: usize,
+
+    /// The outcome of the battle in each configuration, if it was over
+    pub outcomes: (Option<Outcome>, Option<Outcome>),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |outcome: &Option<Outcome>| match outcome {
+            Some(outcome) => outcome.to_string(),
+            None => "still running".to_string(),
+        };
+
+        writeln!(
+            formatter,
+            "round {} diverged after {} cycles ({} vs. {}), between:",
+            self.round,
+            self.cycle,
+            describe(&self.outcomes.0),
+            describe(&self.outcomes.1),
+        )?;
+
+        for warrior in &self.warriors {
+            write!(formatter, "\n{}", warrior)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs random battles with two configurations of the simulator, which
+/// should behave the same, e.g. different [backends](crate::Backend), or
+/// with one configuration and the reference executor. The configurations
+/// should agree on the size of the core, the number of cycles and how
+/// warriors are placed, or every battle will be a mismatch.
+///
+/// ```
+/// use rand::SeedableRng;
+/// use corewars_sim::{Backend, BattleConfig, Differential};
+///
+/// let config = |backend| BattleConfig {
+///     core_size: 800,
+///     max_cycles: 1000,
+///     backend,
+///     ..BattleConfig::default()
+/// };
+///
+/// let differential = Differential::new(config(Backend::Dense), config(Backend::CopyOnWrite));
+/// let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+/// differential.run(10, &mut rng).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Differential {
+    expected: Expected,
+    actual: BattleConfig,
+    warriors: usize,
+}
+
+/// What the battles of a [`Differential`](Differential) are expected to do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expected {
+    Config(BattleConfig),
+    #[cfg(any(test, feature = "reference"))]
+    Reference,
+}
+
+impl Differential {
+    /// Compare the `expected` configuration with the `actual` one, in duels.
+    pub fn new(expected: BattleConfig, actual: BattleConfig) -> Self {
+        Self {
+            expected: Expected::Config(expected),
+            actual,
+            warriors: 2,
+        }
+    }
+
+    /// Compare the `actual` configuration with the
+    /// [reference executor](Reference), in duels. The reference always ties
+    /// between survivors, so `actual` should use
+    /// [`TieBreak::Tie`](crate::TieBreak::Tie).
+    #[cfg(any(test, feature = "reference"))]
+    pub fn against_reference(actual: BattleConfig) -> Self {
+        Self {
+            expected: Expected::Reference,
+            actual,
+            warriors: 2,
+        }
+    }
+
+    /// Battle this many warriors in each round, instead of 2.
+    pub fn warriors(mut self, warriors: usize) -> Self {
+        self.warriors = warriors;
+        self
+    }
+
+    /// Run `rounds` battles, stopping at the first on which the
+    /// configurations disagree.
+    ///
+    /// # Panics
+    ///
+    /// If the warriors can't be loaded with the `actual` configuration, see
+    /// [`BattleConfig::validate`](BattleConfig::validate).
+    pub fn run<R: Rng>(&self, rounds: usize, rng: &mut R) -> Result<(), Mismatch> {
+        (0..rounds).try_for_each(|round| self.run_round(round, rng))
+    }
+
+    fn run_round<R: Rng>(&self, round: usize, rng: &mut R) -> Result<(), Mismatch> {
+        let warriors: Vec<Warrior> = (0..self.warriors)
+            .map(|i| {
+                let name = format!("Warrior{}", i);
+                random_warrior(rng, &name, self.actual.max_length, self.actual.core_size)
+            })
+            .collect();
+
+        let battle = |config: &BattleConfig| {
+            let mut battle = Battle::new(config.clone()).expect("core size should be valid");
+            battle
+                .load_all(&warriors)
+                .expect("random warriors should fit in the core");
+            battle
+        };
+
+        let mut actual = battle(&self.actual);
+        let mut expected = match &self.expected {
+            Expected::Config(config) => Engine::Battle(Box::new(battle(config))),
+            #[cfg(any(test, feature = "reference"))]
+            Expected::Reference => {
+                let mut reference = Reference::new(
+                    self.actual.core_size,
+                    self.actual.max_cycles,
+                    self.actual.max_processes,
+                );
+                for (warrior, handle) in warriors.iter().zip(actual.core().loaded()) {
+                    reference
+                        .load(warrior, handle.position)
+                        .expect("random warriors don't use P-space");
+                }
+                Engine::Reference(reference)
+            }
+        };
+
+        loop {
+            let outcomes = (expected.step(), actual.step());
+
+            if outcomes.0 != outcomes.1 || !expected.matches(actual.core()) {
+                return Err(Mismatch {
+                    round,
+                    cycle: actual.core().cycles(),
+                    warriors,
+                    outcomes,
+                });
+            }
+
+            if outcomes.0.is_some() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The simulator running the expected side of a battle.
+enum Engine {
+    Battle(Box<Battle>),
+    #[cfg(any(test, feature = "reference"))]
+    Reference(Reference),
+}
+
+impl Engine {
+    fn step(&mut self) -> Option<Outcome> {
+        match self {
+            Self::Battle(battle) => battle.step(),
+            #[cfg(any(test, feature = "reference"))]
+            Self::Reference(reference) => reference.step(),
+        }
+    }
+
+    /// Whether `core` is in the same state as this engine.
+    fn matches(&self, core: &Core) -> bool {
+        match self {
+            Self::Battle(battle) => battle.core() == core,
+            #[cfg(any(test, feature = "reference"))]
+            Self::Reference(reference) => reference.matches(core),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::core::Backend;
+
+    fn config(backend: Backend) -> BattleConfig {
+        BattleConfig {
+            core_size: 800,
+            max_cycles: 2000,
+            max_length: 20,
+            min_distance: 20,
+            backend,
+            ..BattleConfig::default()
+        }
+    }
+
+    #[test]
+    fn generates_warriors() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let warrior = random_warrior(&mut rng, "Random", 5, 800);
+            assert!((1..=5).contains(&warrior.len()));
+            assert!(warrior.program.origin.unwrap() < warrior.len());
+
+            let last = warrior.program.instructions.last().unwrap();
+            assert_eq!(last.opcode, Opcode::Jmp);
+            assert_eq!(
+                (last.a_field.unwrap_value() + warrior.len() as i32 - 1) % 800,
+                0
+            );
+
+            for instruction in &warrior.program.instructions {
+                assert!(!matches!(instruction.opcode, Opcode::Ldp | Opcode::Stp));
+                assert!((0..800).contains(&instruction.a_field.unwrap_value()));
+            }
+        }
+    }
+
+    #[test]
+    fn backends_agree() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+
+        Differential::new(config(Backend::Dense), config(Backend::CopyOnWrite))
+            .warriors(3)
+            .run(50, &mut rng)
+            .unwrap_or_else(|mismatch| panic!("{}", mismatch));
+    }
+
+    #[test]
+    fn matches_reference() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+
+        for &backend in Backend::ALL.iter() {
+            Differential::against_reference(config(backend))
+                .warriors(3)
+                .run(100, &mut rng)
+                .unwrap_or_else(|mismatch| panic!("{}", mismatch));
+        }
+    }
+
+    #[test]
+    fn reports_mismatch() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+
+        let shorter = BattleConfig {
+            max_cycles: 10,
+            ..config(Backend::Dense)
+        };
+        let mismatch = Differential::new(config(Backend::Dense), shorter)
+            .run(50, &mut rng)
+            .unwrap_err();
+
+        assert_eq!(mismatch.cycle, 10);
+        assert_eq!(mismatch.outcomes.0, None);
+        assert_eq!(mismatch.warriors.len(), 2);
+        assert!(mismatch.to_string().starts_with(&format!(
+            "round {} diverged after 10 cycles",
+            mismatch.round
+        )));
+    }
+}
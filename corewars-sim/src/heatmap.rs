@@ -0,0 +1,189 @@
+//! Pictures of where in the core warriors were active, drawn as text so they
+//! can be printed by anything, even a terminal without colors. The core is
+//! wrapped onto rows, with one character per instruction, and denser
+//! characters where the instruction was written or executed more often.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::core::Core;
+
+/// The characters of an [ASCII](HeatmapStyle::Ascii) heatmap, from no
+/// activity to the most.
+const ASCII_LEVELS: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// The background colors of an [ANSI](HeatmapStyle::Ansi) heatmap, from no
+/// activity to the most, as indices into the 256-color palette: black, then
+/// from dark red through to yellow and white.
+const ANSI_LEVELS: &[u8] = &[16, 52, 88, 124, 160, 196, 202, 208, 214, 220, 226, 231];
+
+/// Which activity a [`Heatmap`] shows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Activity {
+    /// How often each instruction was written, see
+    /// [`Core::write_counts`](Core::write_counts)
+    Writes,
+
+    /// How often each instruction was executed, see
+    /// [`Core::execution_counts`](Core::execution_counts)
+    Executions,
+}
+
+impl FromStr for Activity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "writes" => Ok(Self::Writes),
+            "executions" => Ok(Self::Executions),
+            _ => Err(format!(
+                "unknown activity {:?}, expected \"writes\" or \"executions\"",
+                s
+            )),
+        }
+    }
+}
+
+/// How a [`Heatmap`] is drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HeatmapStyle {
+    /// Characters from ` ` to `@`, for any terminal or file
+    #[default]
+    Ascii,
+
+    /// Blocks colored with ANSI escape codes, for terminals with 256 colors
+    Ansi,
+}
+
+impl FromStr for HeatmapStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Self::Ascii),
+            "ansi" => Ok(Self::Ansi),
+            _ => Err(format!(
+                "unknown heatmap style {:?}, expected \"ascii\" or \"ansi\"",
+                s
+            )),
+        }
+    }
+}
+
+/// A count of activity for each instruction in a core.
+///
+/// ```
+/// use corewars_sim::{Activity, Core, Heatmap, HeatmapStyle};
+///
+/// let mut core = Core::new(8).unwrap();
+/// core.load_warrior(&corewars_parser::parse("mov 0, 1").unwrap()).unwrap();
+/// core.run(4).unwrap();
+///
+/// let heatmap = Heatmap::of(&core, Activity::Executions);
+/// assert_eq!(heatmap.render(4, HeatmapStyle::Ascii), "000000 @@@@\n000004     \n");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heatmap {
+    counts: Vec<u64>,
+}
+
+impl Heatmap {
+    /// A heatmap of `counts`, indexed by address.
+    pub fn new(counts: Vec<u64>) -> Self {
+        Self { counts }
+    }
+
+    /// A heatmap of the `activity` in `core` so far.
+    pub fn of(core: &Core, activity: Activity) -> Self {
+        let counts = match activity {
+            Activity::Writes => core.write_counts(),
+            Activity::Executions => core.execution_counts(),
+        };
+        Self::new(counts.to_vec())
+    }
+
+    /// Draw the heatmap with `width` instructions per row, each row starting
+    /// with the address of its first instruction. Counts are shaded on a
+    /// logarithmic scale up to the highest, so a few hot spots don't hide
+    /// everything else.
+    ///
+    /// # Panics
+    ///
+    /// If `width` is 0.
+    pub fn render(&self, width: usize, style: HeatmapStyle) -> String {
+        assert!(width > 0, "heatmaps must be at least one instruction wide");
+
+        let levels = match style {
+            HeatmapStyle::Ascii => ASCII_LEVELS.len(),
+            HeatmapStyle::Ansi => ANSI_LEVELS.len(),
+        };
+        let max = self.counts.iter().copied().max().unwrap_or_default();
+
+        let mut text = String::new();
+        for (row, counts) in self.counts.chunks(width).enumerate() {
+            let _ = write!(text, "{:0>6} ", row * width);
+
+            for &count in counts {
+                let level = level(count, max, levels);
+                match style {
+                    HeatmapStyle::Ascii => text.push(ASCII_LEVELS[level]),
+                    HeatmapStyle::Ansi => {
+                        let _ = write!(text, "\x1b[48;5;{}m ", ANSI_LEVELS[level]);
+                    }
+                }
+            }
+
+            if style == HeatmapStyle::Ansi {
+                text.push_str("\x1b[0m");
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// The shade of `count` out of `levels`, given the highest count is `max`.
+/// Only a count of 0 gets the lowest shade, and only the highest the top.
+fn level(count: u64, max: u64, levels: usize) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    if max <= 1 {
+        return levels - 1;
+    }
+
+    let fraction = (count as f64).ln() / (max as f64).ln();
+    let level = 1 + (fraction * (levels - 2) as f64).floor() as usize;
+    level.min(levels - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn shades_logarithmically() {
+        assert_eq!(level(0, 1000, 10), 0);
+        assert_eq!(level(1, 1000, 10), 1);
+        assert_eq!(level(31, 1000, 10), 4);
+        assert_eq!(level(999, 1000, 10), 8);
+        assert_eq!(level(1000, 1000, 10), 9);
+        assert_eq!(level(1, 1, 10), 9);
+    }
+
+    #[test]
+    fn wraps_rows() {
+        let heatmap = Heatmap::new(vec![0, 1, 10, 100, 0]);
+        assert_eq!(
+            heatmap.render(2, HeatmapStyle::Ascii),
+            "000000  .\n000002 +@\n000004  \n"
+        );
+        assert_eq!(
+            heatmap.render(5, HeatmapStyle::Ansi),
+            "000000 \x1b[48;5;16m \x1b[48;5;52m \x1b[48;5;202m \x1b[48;5;231m \
+             \x1b[48;5;16m \x1b[0m\n"
+        );
+    }
+}
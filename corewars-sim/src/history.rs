@@ -0,0 +1,152 @@
+//! A record of the last instructions executed in a core, for finding out
+//! what a warrior was doing when it died without tracing the whole battle.
+//! Only the most recent entries are kept, so it can be left enabled for long
+//! battles.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use corewars_core::load_file::Instruction;
+
+/// An instruction which was executed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Executed {
+    /// The number of cycles executed before this one
+    pub cycle: usize,
+
+    /// The warrior whose process executed the instruction
+    pub warrior: String,
+
+    pub address: u32,
+
+    /// The instruction as it was just before being executed
+    pub instruction: Instruction,
+}
+
+impl fmt::Display for Executed {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "cycle {:>6}: {} {:0>6} {}",
+            self.cycle, self.warrior, self.address, self.instruction
+        )
+    }
+}
+
+/// The last instructions executed in a core, from the oldest to the newest.
+/// A core keeps one once [`Core::enable_history`](crate::Core::enable_history)
+/// is called.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<Executed>,
+}
+
+impl History {
+    /// An empty history keeping up to `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Add an entry, forgetting the oldest if the history is full.
+    pub fn record(&mut self, executed: Executed) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(executed);
+    }
+
+    /// The most entries kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries, from the oldest to the newest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Executed> {
+        self.entries.iter()
+    }
+
+    /// The instructions `warrior` executed, from the newest to the oldest,
+    /// like a backtrace: after a warrior dies, the first is the instruction
+    /// which killed its last process.
+    pub fn backtrace<'a>(&'a self, warrior: &'a str) -> impl Iterator<Item = &'a Executed> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(move |executed| executed.warrior == warrior)
+    }
+}
+
+/// Each entry on a line, from the newest to the oldest.
+impl fmt::Display for History {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (i, executed) in self.entries.iter().rev().enumerate() {
+            if i > 0 {
+                writeln!(formatter)?;
+            }
+            write!(formatter, "{}", executed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::core::Core;
+
+    #[test]
+    fn keeps_latest_entries() {
+        let mut history = History::new(2);
+        for cycle in 0..3 {
+            history.record(Executed {
+                cycle,
+                warrior: if cycle == 1 { "Imp" } else { "Dwarf" }.into(),
+                address: cycle as u32,
+                instruction: Instruction::default(),
+            });
+        }
+
+        assert_eq!(history.len(), 2);
+        let cycles: Vec<usize> = history.iter().map(|executed| executed.cycle).collect();
+        assert_eq!(cycles, vec![1, 2]);
+        let dwarf: Vec<usize> = history.backtrace("Dwarf").map(|e| e.cycle).collect();
+        assert_eq!(dwarf, vec![2]);
+        assert!(history
+            .to_string()
+            .starts_with("cycle      2: Dwarf 000002 DAT"));
+    }
+
+    #[test]
+    fn records_after_death() {
+        let mut core = Core::new(100).unwrap();
+        core.set_trace(false);
+        core.enable_history(3);
+        let warrior = corewars_parser::parse(";name Doomed\njmp 1\njmp 1\ndat 0, 0").unwrap();
+        core.load_warrior(&warrior).unwrap();
+
+        assert!(core.run(10).is_err());
+        let history = core.history().unwrap();
+        let last = history.backtrace("Doomed").next().unwrap();
+        assert_eq!(last.cycle, 2);
+        assert_eq!(last.address, 2);
+        assert_eq!(last.instruction, *core.get(2));
+        assert_eq!(history.len(), 3);
+    }
+}
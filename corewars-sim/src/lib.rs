@@ -1,5 +1,58 @@
+// test_case generates unit expressions which trip this lint
+#![cfg_attr(test, allow(clippy::unused_unit))]
+
 // Public modules
+mod battle;
+mod bench;
+mod clear;
+mod contact;
 mod core;
+mod coverage;
+mod event;
+mod explain;
+mod faults;
+mod fuzz;
+mod heatmap;
+mod history;
+mod metrics;
+mod positions;
+#[cfg(any(test, feature = "reference"))]
+mod reference;
+mod snapshot;
+mod snippet;
+mod stats;
+mod timeline;
+mod victory;
 
 // Re-exports
-pub use crate::core::Core;
+pub use crate::battle::{
+    Battle, BattleConfig, ConfigError, Mars, ObserverPanic, Outcome, TieBreak,
+};
+pub use crate::bench::{
+    benchmarked_instructions, time_backends, time_instruction, time_opcodes, BackendTiming,
+    OpcodeTiming,
+};
+pub use crate::clear::CoreClear;
+pub use crate::contact::{Contact, ContactKind};
+pub use crate::core::{
+    Backend, Core, DumpFilter, Effects, Error as CoreError, FieldRange, FoldedField, ProcessEntry,
+    ProcessError, Queue, RoundRobin, Scheduler, SchedulerClone, WarriorHandle,
+};
+pub use crate::coverage::{Combination, Coverage};
+pub use crate::event::Event;
+pub use crate::explain::{explain, Explanation};
+pub use crate::faults::{Effect, Fault, Faults, Injection, Operand};
+pub use crate::fuzz::{random_warrior, Differential, Mismatch};
+pub use crate::heatmap::{Activity, Heatmap, HeatmapStyle};
+pub use crate::history::{Executed, History};
+pub use crate::metrics::{CoreMetrics, MetricsTimeline};
+pub use crate::positions::{PositionSchedule, ScheduleError};
+#[cfg(any(test, feature = "reference"))]
+pub use crate::reference::Reference;
+pub use crate::snapshot::{AnalysisPass, CoreSnapshot};
+pub use crate::snippet::{run_snippet, SNIPPET_CORE_SIZE};
+pub use crate::stats::{ImpRing, TagStats, WarriorStats};
+pub use crate::timeline::{OwnershipTimeline, Sample};
+pub use crate::victory::{
+    CaptureTheFlag, LastStanding, Score, ScoreThreshold, SurviveCycles, VictoryCondition,
+};
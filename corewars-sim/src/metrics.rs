@@ -0,0 +1,185 @@
+//! Aggregate metrics of the whole core sampled over time, e.g. for
+//! artificial-life style analyses of how battles evolve.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use corewars_core::load_file::Opcode;
+
+use crate::core::Core;
+
+/// Metrics of the core at a given cycle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoreMetrics {
+    pub cycle: usize,
+
+    /// The fraction of instructions in the core which are not `DAT`
+    pub non_dat: f64,
+
+    /// The number of distinct instructions in the core
+    pub unique_instructions: usize,
+
+    /// The Shannon entropy, in bits, of where writes landed since the
+    /// previous sample. 0 if all writes went to a single address (or there
+    /// were none), and higher the more evenly writes were spread out.
+    pub write_entropy: f64,
+}
+
+/// A timeline of [`CoreMetrics`](CoreMetrics), sampled every `interval` cycles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsTimeline {
+    pub interval: usize,
+    pub samples: Vec<CoreMetrics>,
+
+    /// Write counts at the previous sample, to find writes since then
+    previous_writes: Vec<u64>,
+}
+
+impl MetricsTimeline {
+    /// Create an empty timeline, which samples every `interval` cycles.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is 0.
+    pub fn new(interval: usize) -> Self {
+        assert!(interval > 0, "sample interval must be positive");
+
+        Self {
+            interval,
+            samples: Vec::new(),
+            previous_writes: Vec::new(),
+        }
+    }
+
+    /// Record a sample of `core` if it is due, like
+    /// [`OwnershipTimeline::record`](crate::OwnershipTimeline::record).
+    pub fn record(&mut self, core: &Core) {
+        let cycle = core.cycles();
+        let sampled = self.samples.last().map(|sample| sample.cycle);
+
+        if cycle.is_multiple_of(self.interval) && sampled != Some(cycle) {
+            self.sample(core);
+        }
+    }
+
+    /// Record a sample of `core` unconditionally.
+    pub fn sample(&mut self, core: &Core) {
+        let size = core.size() as i32;
+
+        let mut non_dat = 0;
+        let mut unique = HashSet::new();
+
+        for index in 0..size {
+            let instruction = core.get(index);
+            if instruction.opcode != Opcode::Dat {
+                non_dat += 1;
+            }
+
+            unique.insert((
+                instruction.opcode,
+                instruction.modifier,
+                instruction.a_field.address_mode,
+                instruction.a_field.unwrap_value(),
+                instruction.b_field.address_mode,
+                instruction.b_field.unwrap_value(),
+            ));
+        }
+
+        let writes = core.write_counts();
+        self.previous_writes.resize(writes.len(), 0);
+        let new_writes: Vec<u64> = writes
+            .iter()
+            .zip(&self.previous_writes)
+            .map(|(now, before)| now - before)
+            .collect();
+        self.previous_writes = writes.to_vec();
+
+        self.samples.push(CoreMetrics {
+            cycle: core.cycles(),
+            non_dat: f64::from(non_dat) / f64::from(size),
+            unique_instructions: unique.len(),
+            write_entropy: entropy(&new_writes),
+        });
+    }
+}
+
+impl fmt::Display for MetricsTimeline {
+    /// Write the timeline as CSV.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "cycle,non_dat,unique_instructions,write_entropy")?;
+
+        for sample in &self.samples {
+            write!(
+                formatter,
+                "\n{},{:.4},{},{:.4}",
+                sample.cycle, sample.non_dat, sample.unique_instructions, sample.write_entropy
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The Shannon entropy, in bits, of a distribution given as counts.
+fn entropy(counts: &[u64]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let total = total as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn computes_entropy() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[0, 5, 0]), 0.0);
+        assert_eq!(entropy(&[1, 1]), 1.0);
+        assert_eq!(entropy(&[2, 2, 2, 2]), 2.0);
+    }
+
+    #[test]
+    fn samples_metrics() {
+        let mut core = Core::new(100).unwrap();
+        let warrior = corewars_parser::parse("mov 0, 1").unwrap();
+        core.load_warrior(&warrior).unwrap();
+
+        let mut timeline = MetricsTimeline::new(2);
+        timeline.record(&core);
+        for _ in 0..4 {
+            core.step().unwrap();
+            timeline.record(&core);
+        }
+
+        assert_eq!(timeline.samples.len(), 3);
+        assert_eq!(timeline.samples[0].non_dat, 0.01);
+        assert_eq!(timeline.samples[0].unique_instructions, 2);
+        assert_eq!(timeline.samples[0].write_entropy, 0.0);
+
+        // The imp copied itself twice, to two different addresses
+        assert_eq!(timeline.samples[1].non_dat, 0.03);
+        assert_eq!(timeline.samples[1].write_entropy, 1.0);
+
+        assert_eq!(
+            timeline.to_string(),
+            "cycle,non_dat,unique_instructions,write_entropy\n\
+             0,0.0100,2,0.0000\n\
+             2,0.0300,2,1.0000\n\
+             4,0.0500,2,1.0000"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Explicit starting positions for each round of a match, so experiments can
+//! be reproduced exactly or specific separations can be tested.
+//!
+//! A schedule file has one round per line, listing the position of each
+//! warrior in the order they are given, separated by spaces or commas.
+//! Everything after a `;` is a comment, and blank lines are ignored, but there
+//! must be at least one round:
+//!
+//! ```text
+//! ; worst case for a step 4 bomber
+//! 0 4000
+//! 0, 4001
+//! ```
+
+use thiserror::Error as ThisError;
+
+use corewars_core::load_file::distinct_names;
+use corewars_core::Warrior;
+
+use crate::battle::{Battle, BattleConfig, ConfigError, Outcome};
+
+/// An error in a position schedule.
+#[derive(ThisError, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ScheduleError {
+    #[error("the schedule has no rounds")]
+    Empty,
+
+    #[error("invalid position on line {line}: {text:?}")]
+    InvalidLine { line: usize, text: String },
+
+    #[error("round {round} has {found} positions, but there are {expected} warriors")]
+    WrongCount {
+        round: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("round {round} places a warrior at {position}, outside a core of size {core_size}")]
+    OutsideCore {
+        round: usize,
+        position: u32,
+        core_size: u32,
+    },
+
+    #[error(
+        "round {round} places warriors at {first} and {second}, closer than the minimum \
+         distance {min_distance}"
+    )]
+    TooClose {
+        round: usize,
+        first: u32,
+        second: u32,
+        min_distance: u32,
+    },
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+/// The positions of every warrior for each round, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PositionSchedule {
+    rounds: Vec<Vec<u32>>,
+}
+
+impl PositionSchedule {
+    pub fn new(rounds: Vec<Vec<u32>>) -> Self {
+        Self { rounds }
+    }
+
+    /// Read a schedule in the format described in the [module
+    /// documentation](self).
+    pub fn parse(text: &str) -> Result<Self, ScheduleError> {
+        let mut rounds = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let content = line.split(';').next().unwrap_or_default();
+            let positions = content
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|_| ScheduleError::InvalidLine {
+                    line: i + 1,
+                    text: line.to_string(),
+                })?;
+
+            if !positions.is_empty() {
+                rounds.push(positions);
+            }
+        }
+
+        if rounds.is_empty() {
+            return Err(ScheduleError::Empty);
+        }
+
+        Ok(Self { rounds })
+    }
+
+    pub fn rounds(&self) -> &[Vec<u32>] {
+        &self.rounds
+    }
+
+    /// Check that every round places `warriors` warriors inside the core,
+    /// with every pair at least `min_distance` apart.
+    pub fn validate(&self, config: &BattleConfig, warriors: usize) -> Result<(), ScheduleError> {
+        config.validate(warriors)?;
+
+        if self.rounds.is_empty() {
+            return Err(ScheduleError::Empty);
+        }
+
+        for (i, positions) in self.rounds.iter().enumerate() {
+            let round = i + 1;
+
+            if positions.len() != warriors {
+                return Err(ScheduleError::WrongCount {
+                    round,
+                    expected: warriors,
+                    found: positions.len(),
+                });
+            }
+
+            if let Some(&position) = positions.iter().find(|&&p| p >= config.core_size) {
+                return Err(ScheduleError::OutsideCore {
+                    round,
+                    position,
+                    core_size: config.core_size,
+                });
+            }
+
+            for (j, &first) in positions.iter().enumerate() {
+                for &second in &positions[j + 1..] {
+                    let gap = first.abs_diff(second);
+
+                    // The core wraps around, so warriors are close from either side
+                    if gap.min(config.core_size - gap) < config.min_distance {
+                        return Err(ScheduleError::TooClose {
+                            round,
+                            first,
+                            second,
+                            min_distance: config.min_distance,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run one battle per round between `warriors`, loaded at the scheduled
+    /// positions, returning the outcome of each round. Warriors with the same
+    /// name are told apart by their [distinct names](Warrior::distinct_name),
+    /// e.g. `Imp` and `Imp (2)`.
+    pub fn run(
+        &self,
+        config: &BattleConfig,
+        warriors: &[Warrior],
+    ) -> Result<Vec<Outcome>, ScheduleError> {
+        self.run_with(config, warriors, |_| {})
+    }
+
+    /// Like [`run`](Self::run), but call `setup` with each round's battle
+    /// before loading the warriors, e.g. to [inject faults](Battle::set_faults).
+    pub fn run_with<F: FnMut(&mut Battle)>(
+        &self,
+        config: &BattleConfig,
+        warriors: &[Warrior],
+        setup: F,
+    ) -> Result<Vec<Outcome>, ScheduleError> {
+        self.run_inspected(config, warriors, setup, |_| {})
+    }
+
+    /// Like [`run_with`](Self::run_with), and also call `inspect` with each
+    /// round's battle once it's over, e.g. to collect statistics about it.
+    pub fn run_inspected<F, G>(
+        &self,
+        config: &BattleConfig,
+        warriors: &[Warrior],
+        mut setup: F,
+        mut inspect: G,
+    ) -> Result<Vec<Outcome>, ScheduleError>
+    where
+        F: FnMut(&mut Battle),
+        G: FnMut(&Battle),
+    {
+        self.validate(config, warriors.len())?;
+
+        // Outcomes name the warriors, so any with the same name are numbered
+        let renamed: Vec<Option<Warrior>> = warriors
+            .iter()
+            .zip(distinct_names(warriors))
+            .map(|(warrior, name)| {
+                (warrior.metadata.name.as_ref() != Some(&name)).then(|| warrior.renamed(name))
+            })
+            .collect();
+
+        self.rounds
+            .iter()
+            .map(|positions| {
+                let mut battle = Battle::new(config.clone()).map_err(ConfigError::from)?;
+                setup(&mut battle);
+
+                for ((warrior, renamed), &position) in warriors.iter().zip(&renamed).zip(positions)
+                {
+                    let warrior = renamed.as_ref().unwrap_or(warrior);
+                    battle.load(warrior, position).map_err(ConfigError::from)?;
+                }
+
+                let outcome = battle.run();
+                inspect(&battle);
+                Ok(outcome)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn config() -> BattleConfig {
+        BattleConfig {
+            core_size: 800,
+            max_cycles: 2000,
+            ..BattleConfig::default()
+        }
+    }
+
+    #[test]
+    fn parses_schedule() {
+        let schedule = PositionSchedule::parse("; comment\n0 400\n\n0, 401 ; trailing\n").unwrap();
+        assert_eq!(schedule.rounds(), &[vec![0, 400], vec![0, 401]]);
+
+        assert_eq!(
+            PositionSchedule::parse("0 400\n0 x"),
+            Err(ScheduleError::InvalidLine {
+                line: 2,
+                text: "0 x".into()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_empty_schedule() {
+        assert_eq!(PositionSchedule::parse(""), Err(ScheduleError::Empty));
+        assert_eq!(
+            PositionSchedule::parse("; only comments\n\n"),
+            Err(ScheduleError::Empty)
+        );
+        assert_eq!(
+            PositionSchedule::new(Vec::new()).validate(&config(), 2),
+            Err(ScheduleError::Empty)
+        );
+    }
+
+    #[test]
+    fn validates_rounds() {
+        let validate = |rounds: Vec<Vec<u32>>| PositionSchedule::new(rounds).validate(&config(), 2);
+
+        assert_eq!(validate(vec![vec![0, 400], vec![100, 0]]), Ok(()));
+        assert!(matches!(
+            validate(vec![vec![0]]),
+            Err(ScheduleError::WrongCount { found: 1, .. })
+        ));
+        assert!(matches!(
+            validate(vec![vec![0, 800]]),
+            Err(ScheduleError::OutsideCore { position: 800, .. })
+        ));
+        // 750 is only 50 away from 0, going around the end of the core
+        assert!(matches!(
+            validate(vec![vec![0, 750]]),
+            Err(ScheduleError::TooClose { second: 750, .. })
+        ));
+    }
+
+    #[test]
+    fn runs_each_round() {
+        let warriors = vec![
+            corewars_parser::parse(";name Imp\nmov 0, 1").unwrap(),
+            corewars_parser::parse(";name Dies\ndat 0, 0").unwrap(),
+        ];
+        let schedule = PositionSchedule::new(vec![vec![0, 400], vec![300, 0]]);
+
+        assert_eq!(
+            schedule.run(&config(), &warriors).unwrap(),
+            vec![Outcome::Win("Imp".into()), Outcome::Win("Imp".into())]
+        );
+    }
+
+    #[test]
+    fn numbers_same_names() {
+        let warriors = vec![
+            corewars_parser::parse(";name Same\nmov 0, 1").unwrap(),
+            corewars_parser::parse(";name Same\nmov 0, 1").unwrap(),
+        ];
+        let schedule = PositionSchedule::new(vec![vec![0, 400]]);
+
+        assert_eq!(
+            schedule.run(&config(), &warriors).unwrap(),
+            vec![Outcome::Tie(vec!["Same".into(), "Same (2)".into()])]
+        );
+    }
+}
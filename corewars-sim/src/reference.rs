@@ -0,0 +1,550 @@
+//! A deliberately simple executor, kept as the oracle for
+//! [differential testing](crate::Differential) and as a readable statement of
+//! the rules the [`Core`](Core) follows. It is only built for tests, or with
+//! the `reference` feature.
+//!
+//! Execution is written after the example MARS (`EMI94`) in the ICWS '94
+//! draft, `public/reference/icws94.txt`, which should be read alongside it:
+//! each warrior has its own queue of tasks, the warriors take turns, and
+//! each instruction is copied into registers before it is executed. Where
+//! the draft's code disagrees with its text, the text is followed, as it is
+//! by the `Core` and other simulators.
+//!
+//! Nothing here is optimized: memory is a plain `Vec`, and there are no
+//! statistics, schedulers or backends. Any change to how the `Core` executes
+//! instructions should be made here as well, or the differential tests will
+//! fail.
+
+use std::collections::VecDeque;
+
+use corewars_core::load_file::{
+    AddressMode, Field, FieldName, FieldPair, Instruction, Modifier, Opcode, Value,
+};
+use corewars_core::Warrior;
+
+use crate::battle::Outcome;
+use crate::core::{Core, Error};
+
+/// A core and process queues, executed by the simplest possible rules.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    /// Every instruction, with field values between 0 and the core size
+    memory: Vec<Instruction>,
+
+    /// The queue of processes of each warrior, as the address of the next
+    /// instruction of each
+    queues: Vec<VecDeque<u32>>,
+
+    /// The warrior whose turn it is, unless it has no processes left
+    turn: usize,
+
+    warriors: Vec<String>,
+    steps_taken: usize,
+    cycles: usize,
+    max_cycles: usize,
+
+    /// The most tasks each warrior may have (MAXPROCESSES)
+    max_processes: usize,
+}
+
+impl Reference {
+    /// An empty core of `core_size` instructions, in which battles last for
+    /// at most `max_cycles` cycles, and each warrior may have at most
+    /// `max_processes` processes.
+    ///
+    /// # Panics
+    ///
+    /// If `core_size` is 0.
+    pub fn new(core_size: u32, max_cycles: usize, max_processes: usize) -> Self {
+        assert!(core_size > 0, "core size must be positive");
+
+        Self {
+            memory: vec![Instruction::default(); core_size as usize],
+            queues: Vec::new(),
+            turn: 0,
+            warriors: Vec::new(),
+            steps_taken: 0,
+            cycles: 0,
+            max_cycles,
+            max_processes,
+        }
+    }
+
+    /// Load `warrior` with its first instruction at `position`, named the
+    /// same way as by [`Core::load_warrior_at`](Core::load_warrior_at). Like
+    /// the core, warriors using P-space are rejected, but nothing else is
+    /// checked.
+    pub fn load(&mut self, warrior: &Warrior, position: u32) -> Result<(), Error> {
+        Core::check_supported(warrior)?;
+
+        let name = warrior
+            .metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Warrior{}", self.warriors.len()));
+
+        for (i, instruction) in warrior.program.instructions.iter().enumerate() {
+            let mut instruction = instruction.clone();
+            for name in [FieldName::A, FieldName::B].iter().copied() {
+                let value = self.wrap(i64::from(instruction.field(name).unwrap_value()));
+                set(&mut instruction, name, value);
+            }
+
+            let address = self.wrap(i64::from(position) + i as i64);
+            self.memory[address as usize] = instruction;
+        }
+
+        let origin = warrior.program.origin.unwrap_or(0);
+        let origin = self.wrap(i64::from(position) + i64::from(origin));
+        self.queues.push(VecDeque::from(vec![origin]));
+        self.warriors.push(name);
+        Ok(())
+    }
+
+    /// The number of instructions executed, by all warriors.
+    pub fn steps_taken(&self) -> usize {
+        self.steps_taken
+    }
+
+    /// The number of cycles completed, in each of which every warrior still
+    /// running executed one instruction.
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// Whether `core` is in the same state: the same instructions, the same
+    /// processes queued for each warrior in the same order, the same warrior
+    /// to execute next, and the same number of steps and cycles taken.
+    pub fn matches(&self, core: &Core) -> bool {
+        let queue = core.process_queue();
+
+        let instructions_match = self.memory.len() as u32 == core.size()
+            && self
+                .memory
+                .iter()
+                .enumerate()
+                .all(|(address, instruction)| core.get(address as i32) == instruction);
+
+        let processes_match = self.queues.iter().enumerate().all(|(warrior, processes)| {
+            queue.thread_count(warrior) == processes.len()
+                && queue
+                    .warrior_processes(warrior)
+                    .zip(processes)
+                    .all(|(process, &address)| {
+                        self.warriors[warrior] == process.name && address == process.offset.value()
+                    })
+        });
+
+        self.steps_taken == core.steps_taken()
+            && self.cycles == core.cycles()
+            && queue.next_warrior() == self.next_warrior()
+            && instructions_match
+            && processes_match
+    }
+
+    /// Execute the next instruction of the warrior whose turn it is, unless
+    /// the battle is over, like [`Battle::step`](crate::Battle::step).
+    /// Returns the outcome once the battle is over, where surviving warriors
+    /// always tie.
+    pub fn step(&mut self) -> Option<Outcome> {
+        if !self.is_over() {
+            self.execute_next();
+        }
+
+        if self.is_over() {
+            Some(self.outcome())
+        } else {
+            None
+        }
+    }
+
+    fn survivors(&self) -> Vec<String> {
+        (0..self.warriors.len())
+            .filter(|&warrior| !self.queues[warrior].is_empty())
+            .map(|warrior| self.warriors[warrior].clone())
+            .collect()
+    }
+
+    fn is_over(&self) -> bool {
+        let min_alive = if self.warriors.len() > 1 { 1 } else { 0 };
+        self.cycles >= self.max_cycles || self.survivors().len() <= min_alive
+    }
+
+    fn outcome(&self) -> Outcome {
+        let mut survivors = self.survivors();
+        if survivors.len() == 1 && self.warriors.len() > 1 {
+            Outcome::Win(survivors.remove(0))
+        } else {
+            Outcome::Tie(survivors)
+        }
+    }
+
+    /// The first warrior with processes left, starting from `from`.
+    fn warrior_from(&self, from: usize) -> Option<usize> {
+        let count = self.warriors.len();
+        (0..count)
+            .map(|i| (from + i) % count)
+            .find(|&warrior| !self.queues[warrior].is_empty())
+    }
+
+    fn next_warrior(&self) -> Option<usize> {
+        self.warrior_from(self.turn)
+    }
+
+    /// Execute the process at the front of the queue of the warrior whose
+    /// turn it is, then pass the turn on to the next warrior.
+    fn execute_next(&mut self) {
+        let warrior = match self.next_warrior() {
+            Some(warrior) => warrior,
+            None => return,
+        };
+        let pc = self.queues[warrior]
+            .pop_front()
+            .expect("the warrior has processes");
+        self.steps_taken += 1;
+
+        self.execute(warrior, pc);
+
+        // The cycle is complete once the turn passes back to an earlier
+        // warrior, or no warrior is left
+        match self.warrior_from(warrior + 1) {
+            Some(next) => {
+                self.turn = next;
+                if next <= warrior {
+                    self.cycles += 1;
+                }
+            }
+            None => self.cycles += 1,
+        }
+    }
+
+    /// Execute the instruction at `pc` for `warrior`, following `EMI94` in
+    /// the ICWS '94 draft, without read or write limits. Pointers are kept as
+    /// absolute addresses rather than relative to `pc`.
+    fn execute(&mut self, warrior: usize, pc: u32) {
+        // The current instruction is copied into the instruction register
+        // before either operand is evaluated
+        let ir = self.memory[pc as usize].clone();
+
+        // The A operand is completely evaluated, then the B operand, each
+        // copying the instruction it points to into its register
+        let (rpa, ira) = self.evaluate_operand(pc, &ir.a_field);
+        let (rpb, irb) = self.evaluate_operand(pc, &ir.b_field);
+
+        let next = self.wrap(i64::from(pc) + 1);
+        let skip = self.wrap(i64::from(pc) + 2);
+        let size = self.memory.len() as u64;
+
+        // The pairs of fields of the A and B registers the modifier selects,
+        // e.g. for .AB the A-number of IRA and the B-number of IRB
+        let pairs = ir.modifier.field_pairs();
+        let a = |pair: &FieldPair| value(ira.field(pair.a));
+        let b = |pair: &FieldPair| value(irb.field(pair.b));
+
+        match ir.opcode {
+            // DAT doesn't queue anything, removing the task
+            Opcode::Dat => {}
+
+            // MOV replaces the B-target with the A-value, which for .I is the
+            // entire instruction
+            Opcode::Mov => {
+                if ir.modifier == Modifier::I {
+                    self.memory[rpb as usize] = ira.clone();
+                } else {
+                    for pair in pairs {
+                        self.write(rpb, pair.b, a(pair));
+                    }
+                }
+                self.queue(warrior, next);
+            }
+
+            // Arithmetic replaces the B-target with the B-value op the
+            // A-value. The draft's code has the operands the other way
+            // around for .AB and .X, which its text contradicts.
+            Opcode::Add | Opcode::Sub | Opcode::Mul => {
+                for pair in pairs {
+                    let (a, b) = (u64::from(a(pair)), u64::from(b(pair)));
+                    let result = match ir.opcode {
+                        Opcode::Add => b + a,
+                        Opcode::Sub => b + size - a,
+                        _ => b * a,
+                    };
+                    self.write(rpb, pair.b, (result % size) as u32);
+                }
+                self.queue(warrior, next);
+            }
+
+            // DIV and MOD replace the B-target with the quotient or
+            // remainder of the B-value by the A-value. Pairs with a non-zero
+            // A-value are still written, but the task is removed if any
+            // A-value is zero (the draft's code omits this for .A alone).
+            Opcode::Div | Opcode::Mod => {
+                for pair in pairs.iter().filter(|&pair| a(pair) != 0) {
+                    let result = if ir.opcode == Opcode::Div {
+                        b(pair) / a(pair)
+                    } else {
+                        b(pair) % a(pair)
+                    };
+                    self.write(rpb, pair.b, result);
+                }
+                if pairs.iter().all(|pair| a(pair) != 0) {
+                    self.queue(warrior, next);
+                }
+            }
+
+            Opcode::Jmp => self.queue(warrior, rpa),
+
+            // JMZ jumps if every selected field of the B-value is zero, and
+            // JMN if any of them isn't
+            Opcode::Jmz => {
+                let zero = pairs.iter().all(|pair| b(pair) == 0);
+                self.queue(warrior, if zero { rpa } else { next });
+            }
+            Opcode::Jmn => {
+                let zero = pairs.iter().all(|pair| b(pair) == 0);
+                self.queue(warrior, if zero { next } else { rpa });
+            }
+
+            // DJN decrements the B-target and the B-value, then jumps if any
+            // selected field of the B-value isn't zero
+            Opcode::Djn => {
+                for pair in pairs {
+                    let decremented = self.wrap(i64::from(self.read(rpb, pair.b)) - 1);
+                    self.write(rpb, pair.b, decremented);
+                }
+                let zero = pairs
+                    .iter()
+                    .all(|pair| self.wrap(i64::from(b(pair)) - 1) == 0);
+                self.queue(warrior, if zero { next } else { rpa });
+            }
+
+            // SEQ (CMP) and SNE compare the A-value and the B-value, which
+            // for .I are the entire instructions
+            Opcode::Cmp | Opcode::Seq | Opcode::Sne => {
+                let equal = if ir.modifier == Modifier::I {
+                    ira == irb
+                } else {
+                    pairs.iter().all(|pair| a(pair) == b(pair))
+                };
+                let skips = equal == (ir.opcode != Opcode::Sne);
+                self.queue(warrior, if skips { skip } else { next });
+            }
+
+            // SLT skips if every selected field of the A-value is less than
+            // the one of the B-value
+            Opcode::Slt => {
+                let less = pairs.iter().all(|pair| a(pair) < b(pair));
+                self.queue(warrior, if less { skip } else { next });
+            }
+
+            // SPL queues the next instruction, then the A-pointer
+            Opcode::Spl => {
+                self.queue(warrior, next);
+                self.queue(warrior, rpa);
+            }
+
+            Opcode::Nop => self.queue(warrior, next),
+
+            // Never loaded, and no other instruction can create one
+            Opcode::Ldp | Opcode::Stp => unreachable!("P-space is rejected by load"),
+        }
+    }
+
+    /// Add a task at `address` to the back of `warrior`'s queue, unless it
+    /// already has the maximum number of tasks, in which case nothing
+    /// happens.
+    fn queue(&mut self, warrior: usize, address: u32) {
+        if self.queues[warrior].len() < self.max_processes {
+            self.queues[warrior].push_back(address);
+        }
+    }
+
+    /// Evaluate `field`, an operand of the instruction copied from `pc`, and
+    /// return the address it points to and a copy of the instruction there
+    /// (the register). The instruction pointed to indirectly is decremented
+    /// before being followed, or incremented once the register is copied.
+    fn evaluate_operand(&mut self, pc: u32, field: &Field) -> (u32, Instruction) {
+        use AddressMode::*;
+
+        // An immediate operand points to the current instruction
+        if field.address_mode == Immediate {
+            return (pc, self.memory[pc as usize].clone());
+        }
+
+        let mut pointer = self.wrap(i64::from(pc) + i64::from(value(field)));
+
+        // The field followed by an indirect operand, if any
+        let indirect = match field.address_mode {
+            Immediate | Direct => None,
+            IndirectA | PreDecIndirectA | PostIncIndirectA => Some(FieldName::A),
+            IndirectB | PreDecIndirectB | PostIncIndirectB => Some(FieldName::B),
+        };
+        let mut increment = None;
+
+        if let Some(name) = indirect {
+            match field.address_mode {
+                PreDecIndirectA | PreDecIndirectB => {
+                    let decremented = self.wrap(i64::from(self.read(pointer, name)) - 1);
+                    self.write(pointer, name, decremented);
+                }
+                PostIncIndirectA | PostIncIndirectB => increment = Some(pointer),
+                _ => {}
+            }
+
+            pointer = self.wrap(i64::from(pointer) + i64::from(self.read(pointer, name)));
+        }
+
+        let register = self.memory[pointer as usize].clone();
+
+        if let (Some(address), Some(name)) = (increment, indirect) {
+            let incremented = self.wrap(i64::from(self.read(address, name)) + 1);
+            self.write(address, name, incremented);
+        }
+
+        (pointer, register)
+    }
+
+    fn read(&self, address: u32, name: FieldName) -> u32 {
+        value(self.memory[address as usize].field(name))
+    }
+
+    fn write(&mut self, address: u32, name: FieldName, value: u32) {
+        set(&mut self.memory[address as usize], name, value);
+    }
+
+    /// Wrap an address or field value to the size of the core.
+    fn wrap(&self, value: i64) -> u32 {
+        value.rem_euclid(self.memory.len() as i64) as u32
+    }
+}
+
+fn value(field: &Field) -> u32 {
+    field.unwrap_value() as u32
+}
+
+fn set(instruction: &mut Instruction, name: FieldName, value: u32) {
+    instruction.field_mut(name).value = Value::Literal(value as i32);
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn load(programs: &[&str]) -> (Reference, Core) {
+        let mut reference = Reference::new(100, 1000, 8000);
+        let mut core = Core::new(100).unwrap();
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            reference.load(&warrior, i as u32 * 50).unwrap();
+            core.load_warrior_at(&warrior, i as u32 * 50).unwrap();
+        }
+
+        (reference, core)
+    }
+
+    #[test]
+    fn matches_core() {
+        let (mut reference, mut core) = load(&[
+            ";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0",
+            ";name Splitter\nspl 0, }1\ndjn.f -1, {-1",
+        ]);
+        core.set_trace(false);
+        assert!(reference.matches(&core));
+
+        for _ in 0..200 {
+            let outcome = reference.step();
+            let _ = core.step();
+            assert!(
+                reference.matches(&core),
+                "diverged at {}",
+                core.steps_taken()
+            );
+
+            if outcome.is_some() {
+                break;
+            }
+        }
+        assert!(reference.steps_taken() > 10);
+
+        core.get_mut(0).opcode = Opcode::Nop;
+        assert!(!reference.matches(&core));
+    }
+
+    #[test]
+    fn follows_draft() {
+        let (mut reference, _) = load(&["mov.i 1, 2\ndat }3, >4"]);
+        reference.step();
+        assert_eq!(reference.memory[2], reference.memory[1]);
+
+        let (mut reference, _) = load(&["div.ab #2, 1\ndat #0, #10"]);
+        reference.step();
+        assert_eq!(reference.read(1, FieldName::B), 5);
+
+        // Only one field of the B-value is zero after decrementing
+        let (mut reference, _) = load(&["djn.f 0, 1\ndat #1, #2"]);
+        reference.step();
+        assert_eq!(reference.queues[0], VecDeque::from(vec![0]));
+
+        let (mut reference, _) = load(&["jmz.f 0, 1\ndat #0, #1"]);
+        reference.step();
+        assert_eq!(reference.queues[0], VecDeque::from(vec![1]));
+    }
+
+    #[test]
+    fn limits_processes() {
+        let warrior = corewars_parser::parse(";name Splitter\nspl 0\njmp -1").unwrap();
+        let mut reference = Reference::new(100, 1000, 4);
+        let mut core = Core::new(100).unwrap();
+        reference.load(&warrior, 0).unwrap();
+        core.load_warrior_at(&warrior, 0).unwrap();
+        core.set_trace(false);
+        core.set_max_processes(4);
+
+        for _ in 0..20 {
+            reference.step();
+            core.step().unwrap();
+            assert!(
+                reference.matches(&core),
+                "diverged at {}",
+                core.steps_taken()
+            );
+        }
+        assert_eq!(reference.queues[0].len(), 4);
+    }
+
+    #[test]
+    fn decides_outcome() {
+        let (mut reference, _) = load(&[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"]);
+
+        assert_eq!(reference.step(), None);
+        assert_eq!(reference.step(), Some(Outcome::Win("Imp".into())));
+        assert_eq!(reference.steps_taken(), 2);
+        assert_eq!(reference.cycles(), 1);
+
+        let (mut imps, _) = load(&[";name Imp\nmov 0, 1", ";name Imp2\nmov 0, 1"]);
+        let outcome = (0..2000).find_map(|_| imps.step());
+        assert_eq!(
+            outcome,
+            Some(Outcome::Tie(vec!["Imp".into(), "Imp2".into()]))
+        );
+    }
+
+    #[test]
+    fn rejects_p_space() {
+        let warrior = corewars_parser::parse(
+            "ldp.ab #0, 1
+stp.b 0, #0",
+        )
+        .unwrap();
+        let mut reference = Reference::new(100, 1000, 8000);
+
+        assert!(matches!(
+            reference.load(&warrior, 0),
+            Err(Error::UnsupportedOpcode { index: 0, .. })
+        ));
+        assert_eq!(reference, Reference::new(100, 1000, 8000));
+    }
+}
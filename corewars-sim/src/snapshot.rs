@@ -0,0 +1,119 @@
+//! Read-only snapshots of a core, e.g. after a battle, which are shared
+//! between threads so several analyses of the same core can run at once.
+
+use std::ops::Deref;
+use std::sync::Arc;
+use std::thread;
+
+use crate::core::Core;
+
+/// An analysis of a snapshot, see [`CoreSnapshot::analyze`]. Passes return
+/// their results by writing to variables they borrow.
+pub type AnalysisPass<'a> = Box<dyn FnOnce(&Core) + Send + 'a>;
+
+/// An immutable copy of a core, which is cheap to clone and can be sent to
+/// other threads. It dereferences to the [`Core`] it copies, so anything
+/// which reads a core, like a [`Heatmap`](crate::Heatmap) or
+/// [statistics](Core::warrior_stats), can read a snapshot.
+///
+/// ```
+/// use corewars_sim::{Activity, AnalysisPass, Core, Heatmap};
+///
+/// let mut core = Core::new(8).unwrap();
+/// core.set_trace(false);
+/// let imp = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+/// core.load_warrior(&imp).unwrap();
+/// core.run(4).unwrap();
+///
+/// let snapshot = core.snapshot();
+/// let mut heatmap = None;
+/// let mut stats = Vec::new();
+/// let passes: Vec<AnalysisPass> = vec![
+///     Box::new(|core| heatmap = Some(Heatmap::of(core, Activity::Writes))),
+///     Box::new(|core| stats = core.warrior_stats()),
+/// ];
+/// snapshot.analyze(passes);
+///
+/// assert!(heatmap.is_some());
+/// assert_eq!(stats[0].name, "Imp");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CoreSnapshot {
+    core: Arc<Core>,
+}
+
+impl CoreSnapshot {
+    /// Run each of `passes` over the snapshot, each on its own thread, and
+    /// wait for them all to finish. If any pass panics, so does this, once
+    /// the others have finished.
+    pub fn analyze(&self, passes: Vec<AnalysisPass>) {
+        if passes.len() < 2 {
+            passes.into_iter().for_each(|pass| pass(&self.core));
+            return;
+        }
+
+        thread::scope(|scope| {
+            for pass in passes {
+                let core = &*self.core;
+                scope.spawn(move || pass(core));
+            }
+        });
+    }
+}
+
+impl From<Core> for CoreSnapshot {
+    fn from(core: Core) -> Self {
+        Self {
+            core: Arc::new(core),
+        }
+    }
+}
+
+impl Deref for CoreSnapshot {
+    type Target = Core;
+
+    fn deref(&self) -> &Core {
+        &self.core
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{Activity, Heatmap};
+
+    #[test]
+    fn analyzes_concurrently() {
+        let mut core = Core::new(100).unwrap();
+        core.set_trace(false);
+        let warrior =
+            corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+                .expect("Failed to parse warrior");
+        core.load_warrior(&warrior).unwrap();
+        core.run(30).unwrap();
+
+        let snapshot = core.snapshot();
+        let shared = snapshot.clone();
+        let mut heatmaps = Vec::new();
+        let mut stats = Vec::new();
+        let mut steps = 0;
+        snapshot.analyze(vec![
+            Box::new(|core| {
+                heatmaps.push(Heatmap::of(core, Activity::Writes));
+                heatmaps.push(Heatmap::of(core, Activity::Executions));
+            }),
+            Box::new(|core| stats = core.warrior_stats()),
+            Box::new(|core| steps = core.steps_taken()),
+        ]);
+
+        // The snapshot is unchanged by stepping the core it was taken from
+        core.step().unwrap();
+        assert_eq!(steps, 30);
+        assert_eq!(shared.steps_taken(), 30);
+        assert_eq!(heatmaps[0], Heatmap::of(&shared, Activity::Writes));
+        assert_eq!(heatmaps[1], Heatmap::of(&shared, Activity::Executions));
+        assert_eq!(stats, shared.warrior_stats());
+    }
+}
@@ -0,0 +1,78 @@
+//! Helpers for running small fragments of Redcode in isolation, mostly useful
+//! for testing the semantics of individual instructions.
+
+use corewars_core::load_file::{Instruction, Program};
+use corewars_core::Warrior;
+
+use crate::core::{Core, ProcessError};
+
+/// The size of the core used by [`run_snippet`](run_snippet). This is kept
+/// small so the whole core can easily be inspected or printed.
+pub const SNIPPET_CORE_SIZE: u32 = 64;
+
+/// Load `instructions` at address 0 of a small core and execute up to `cycles`
+/// steps, starting from the first instruction. The resulting core is always
+/// returned, along with the error that stopped execution early, if any. The
+/// remaining processes can be inspected with [`Core::process_queue`](Core::process_queue).
+///
+/// # Panics
+///
+/// If there are more than [`SNIPPET_CORE_SIZE`](SNIPPET_CORE_SIZE) instructions.
+pub fn run_snippet(
+    instructions: &[Instruction],
+    cycles: usize,
+) -> (Core, Result<(), ProcessError>) {
+    let warrior = Warrior {
+        program: Program {
+            instructions: instructions.to_vec().into(),
+            origin: None,
+        },
+        ..Warrior::default()
+    };
+
+    let mut core = Core::new(SNIPPET_CORE_SIZE).expect("snippet core size is valid");
+    core.load_warrior(&warrior)
+        .expect("snippet does not fit in the core");
+
+    let result = core.run(cycles);
+
+    (core, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use corewars_core::load_file::{Field, Opcode};
+
+    use super::*;
+
+    #[test]
+    fn imp_moves_forward() {
+        let imp = Instruction::new(Opcode::Mov, Field::direct(0), Field::direct(1));
+
+        let (core, result) = run_snippet(std::slice::from_ref(&imp), 3);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(core.steps_taken(), 3);
+        for i in 0..4 {
+            assert_eq!(core.get(i), &imp);
+        }
+
+        let offsets: Vec<u32> = core
+            .process_queue()
+            .iter()
+            .map(|entry| entry.offset.value())
+            .collect();
+        assert_eq!(offsets, vec![3]);
+    }
+
+    #[test]
+    fn dat_terminates() {
+        let (core, result) = run_snippet(&[Instruction::default()], 10);
+
+        assert!(matches!(result, Err(ProcessError::ExecuteDat(_))));
+        assert_eq!(core.steps_taken(), 1);
+        assert!(core.process_queue().is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+//! Statistics about the state of each warrior in a core, such as how many
+//! processes it has left and whether it survives only as imps, and about each
+//! tagged component of a warrior.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use corewars_core::load_file::{AddressMode, Modifier, Opcode};
+
+use crate::core::Core;
+
+/// A group of imp processes which together form an imp ring or spiral.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImpRing {
+    /// The distance each imp copies itself forward
+    pub step: u32,
+
+    /// The number of points in the ring, i.e. the number of imps needed so
+    /// that each one copies over the next
+    pub points: u32,
+
+    /// The number of processes executing imps with this step
+    pub processes: usize,
+}
+
+/// Statistics about a single warrior in a core.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WarriorStats {
+    pub name: String,
+
+    /// The number of processes the warrior has in the queue
+    pub processes: usize,
+
+    /// The number of instructions the warrior loaded or last wrote
+    pub owned_cells: usize,
+
+    /// The number of processes executing an imp, e.g. `MOV.I 0, 1`
+    pub imp_processes: usize,
+
+    /// Imp rings formed by processes with a step other than 1
+    pub imp_rings: Vec<ImpRing>,
+
+    /// The cycle the warrior first cleared the core in, if it did since
+    /// [clear detection](Core::enable_clear_detection) was enabled
+    pub core_cleared: Option<usize>,
+}
+
+impl WarriorStats {
+    /// Whether the warrior is still alive, but only as imps. Imps can rarely
+    /// kill anything, so this usually means the warrior can only tie.
+    pub fn only_imps(&self) -> bool {
+        self.processes > 0 && self.imp_processes == self.processes
+    }
+}
+
+impl fmt::Display for WarriorStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}: {} processes ({} imps), {} instructions owned",
+            self.name, self.processes, self.imp_processes, self.owned_cells
+        )?;
+        if let Some(cycle) = self.core_cleared {
+            write!(formatter, ", cleared the core at cycle {}", cycle)?;
+        }
+
+        for ring in &self.imp_rings {
+            write!(
+                formatter,
+                "\n  {}-point imp ring with step {} ({} processes)",
+                ring.points, ring.step, ring.processes
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Statistics about a tagged component of a warrior, see
+/// [`tag_region`](Core::tag_region).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagStats {
+    /// The name of the warrior the component belongs to
+    pub warrior: String,
+
+    pub tag: String,
+
+    /// The number of processes the component has in the queue. This is 0 if
+    /// every process with the tag has died
+    pub processes: usize,
+}
+
+impl fmt::Display for TagStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} [{}]: ", self.warrior, self.tag)?;
+
+        if self.processes == 0 {
+            write!(formatter, "died")
+        } else {
+            write!(formatter, "{} processes", self.processes)
+        }
+    }
+}
+
+impl Core {
+    /// Statistics for each tag any process has had, sorted by warrior (in
+    /// load order) then tag.
+    pub fn tag_stats(&self) -> Vec<TagStats> {
+        self.process_queue()
+            .tag_counts()
+            .map(|(warrior, tag, processes)| TagStats {
+                warrior: self.warriors()[warrior].clone(),
+                tag: tag.to_string(),
+                processes,
+            })
+            .collect()
+    }
+
+    /// Statistics for each warrior loaded into the core, in load order.
+    pub fn warrior_stats(&self) -> Vec<WarriorStats> {
+        let mut stats: Vec<WarriorStats> = self
+            .warriors()
+            .iter()
+            .zip(self.owned_cells())
+            .enumerate()
+            .map(|(id, (name, &owned_cells))| WarriorStats {
+                name: name.clone(),
+                processes: 0,
+                owned_cells,
+                imp_processes: 0,
+                imp_rings: Vec::new(),
+                core_cleared: self
+                    .core_clears()
+                    .iter()
+                    .find(|clear| clear.id == id)
+                    .map(|clear| clear.cycle),
+            })
+            .collect();
+
+        // Imp processes per warrior, grouped by step
+        let mut imp_steps: Vec<BTreeMap<u32, usize>> = vec![BTreeMap::new(); stats.len()];
+
+        for process in self.process_queue().iter() {
+            let index = process.warrior;
+
+            stats[index].processes += 1;
+
+            // In an imp ring, each imp is only copied just before it executes,
+            // so also check the instruction the process just executed
+            let pc = process.offset.value() as i32;
+            if let Some(step) = self.imp_step(pc).or_else(|| self.imp_step(pc - 1)) {
+                stats[index].imp_processes += 1;
+                *imp_steps[index].entry(step).or_insert(0) += 1;
+            }
+        }
+
+        for (stats, steps) in stats.iter_mut().zip(imp_steps) {
+            stats.imp_rings = steps
+                .into_iter()
+                .filter(|&(step, _)| step != 1)
+                .filter_map(|(step, processes)| {
+                    let points = self.ring_points(step)?;
+                    Some(ImpRing {
+                        step,
+                        points,
+                        processes,
+                    })
+                })
+                .collect();
+        }
+
+        stats
+    }
+
+    /// If the instruction at `index` is an imp, i.e. it copies itself ahead
+    /// of the process executing it, the distance it is copied.
+    fn imp_step(&self, index: i32) -> Option<u32> {
+        let instruction = self.get(index);
+
+        let is_imp = instruction.opcode == Opcode::Mov
+            && instruction.modifier == Modifier::I
+            && instruction.a_field.address_mode == AddressMode::Direct
+            && instruction.a_field.unwrap_value() == 0
+            && instruction.b_field.address_mode == AddressMode::Direct
+            && instruction.b_field.unwrap_value() != 0;
+
+        if is_imp {
+            Some(instruction.b_field.unwrap_value() as u32)
+        } else {
+            None
+        }
+    }
+
+    /// The number of points in an imp ring with the given step, i.e. the
+    /// smallest `n` where `n * step` is one more than a multiple of the core size.
+    fn ring_points(&self, step: u32) -> Option<u32> {
+        let size = u64::from(self.size());
+        (1..=self.size()).find(|&points| u64::from(points) * u64::from(step) % size == 1 % size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn build_core(programs: &[&str]) -> Core {
+        let mut core = Core::new(8000).unwrap();
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            core.load_warrior_at(&warrior, i as u32 * 4000)
+                .expect("Failed to load warrior");
+        }
+
+        core
+    }
+
+    #[test]
+    fn tracks_tagged_components() {
+        let mut core = Core::new(8000).unwrap();
+        core.tag_region(0, 2, "stone");
+        core.tag_region(2, 1, "imp");
+
+        // The stone splits off an imp, then dies to its own bomb
+        let warrior = corewars_parser::parse(";name Pair\nspl 2\ndat #0, #0\nmov 0, 1").unwrap();
+        core.load_warrior(&warrior).unwrap();
+
+        assert_eq!(
+            core.tag_stats(),
+            vec![TagStats {
+                warrior: "Pair".into(),
+                tag: "stone".into(),
+                processes: 1,
+            }]
+        );
+
+        for _ in 0..4 {
+            core.step().unwrap();
+        }
+
+        let stats = core.tag_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].to_string(), "Pair [imp]: 1 processes");
+        assert_eq!(stats[1].to_string(), "Pair [stone]: died");
+    }
+
+    #[test]
+    fn detects_imps() {
+        let mut core = build_core(&[
+            ";name Imp\nmov 0, 1",
+            ";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0",
+        ]);
+
+        for _ in 0..10 {
+            core.step().unwrap();
+        }
+
+        let stats = core.warrior_stats();
+
+        assert_eq!(stats[0].name, "Imp");
+        assert_eq!(stats[0].processes, 1);
+        assert_eq!(stats[0].imp_processes, 1);
+        assert_eq!(stats[0].imp_rings, Vec::new());
+        assert!(stats[0].only_imps());
+
+        assert_eq!(stats[1].name, "Dwarf");
+        assert_eq!(stats[1].imp_processes, 0);
+        assert!(!stats[1].only_imps());
+    }
+
+    #[test]
+    fn detects_imp_rings() {
+        // A 3-point imp ring, with one process on each point: the launcher
+        // copies the imp to the other points, then jumps a process to each
+        let mut core = Core::new(8000).unwrap();
+        let ring = corewars_parser::parse(
+            ";name Ring
+            mov imp, imp+2667
+            mov imp, imp+5334
+            spl first
+            spl third
+            jmp imp+2667
+            first jmp imp
+            dat 0, 0
+            third jmp imp+5334
+            imp mov.i 0, 2667",
+        )
+        .unwrap();
+        core.load_warrior_at(&ring, 0).unwrap();
+
+        for _ in 0..30 {
+            core.step().unwrap();
+        }
+
+        let stats = core.warrior_stats();
+
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].imp_processes, 3);
+        assert_eq!(
+            stats[0].imp_rings,
+            vec![ImpRing {
+                step: 2667,
+                points: 3,
+                processes: 3
+            }]
+        );
+        assert_eq!(
+            stats[0].to_string(),
+            "Ring: 3 processes (3 imps), 32 instructions owned\n  \
+             3-point imp ring with step 2667 (3 processes)"
+        );
+    }
+}
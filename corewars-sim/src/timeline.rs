@@ -0,0 +1,151 @@
+//! Sampling how much of the core each warrior owns over time, e.g. to render
+//! territory-over-time charts.
+
+use std::fmt;
+
+use crate::core::Core;
+
+/// The number of instructions owned by each warrior at a given cycle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub cycle: usize,
+
+    /// Owned instructions per warrior, in the order of
+    /// [`OwnershipTimeline::warriors`](OwnershipTimeline::warriors)
+    pub owned: Vec<usize>,
+}
+
+/// A timeline of owned instruction counts, sampled every `interval` cycles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnershipTimeline {
+    pub interval: usize,
+    pub warriors: Vec<String>,
+    pub samples: Vec<Sample>,
+}
+
+impl OwnershipTimeline {
+    /// Create an empty timeline, which samples every `interval` cycles.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is 0.
+    pub fn new(interval: usize) -> Self {
+        assert!(interval > 0, "sample interval must be positive");
+
+        Self {
+            interval,
+            warriors: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Record a sample of `core` if it is due. This should be called before
+    /// the first step and after every following step.
+    pub fn record(&mut self, core: &Core) {
+        let cycle = core.cycles();
+        let sampled = self.samples.last().map(|sample| sample.cycle);
+
+        if cycle.is_multiple_of(self.interval) && sampled != Some(cycle) {
+            self.sample(core);
+        }
+    }
+
+    /// Record a sample of `core` unconditionally, e.g. at the end of a battle.
+    pub fn sample(&mut self, core: &Core) {
+        let owned = core.owned_cells().to_vec();
+
+        // Warriors may be added after sampling starts; earlier samples are
+        // implicitly zero for them
+        if core.warriors().len() > self.warriors.len() {
+            self.warriors = core.warriors().to_vec();
+        }
+
+        self.samples.push(Sample {
+            cycle: core.cycles(),
+            owned,
+        });
+    }
+
+    /// The owned instruction counts of a single warrior over time, suitable
+    /// for drawing a sparkline.
+    pub fn sparkline(&self, warrior: &str) -> Option<Vec<usize>> {
+        let index = self.warriors.iter().position(|name| name == warrior)?;
+
+        Some(
+            self.samples
+                .iter()
+                .map(|sample| sample.owned.get(index).copied().unwrap_or(0))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for OwnershipTimeline {
+    /// Write the timeline as CSV, with a column for each warrior.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "cycle")?;
+        for warrior in &self.warriors {
+            write!(formatter, ",{}", warrior)?;
+        }
+
+        for sample in &self.samples {
+            write!(formatter, "\n{}", sample.cycle)?;
+            for index in 0..self.warriors.len() {
+                write!(
+                    formatter,
+                    ",{}",
+                    sample.owned.get(index).copied().unwrap_or(0)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn build_core(programs: &[&str]) -> Core {
+        let mut core = Core::new(100).unwrap();
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            core.load_warrior_at(&warrior, i as u32 * 50)
+                .expect("Failed to load warrior");
+        }
+
+        core
+    }
+
+    #[test]
+    fn samples_ownership() {
+        let mut core = build_core(&[";name Imp\nmov 0, 1", ";name Wait\njmp 0"]);
+        let mut timeline = OwnershipTimeline::new(4);
+
+        timeline.record(&core);
+        for _ in 0..16 {
+            core.step().unwrap();
+            timeline.record(&core);
+        }
+
+        assert_eq!(timeline.warriors, vec!["Imp", "Wait"]);
+        assert_eq!(timeline.sparkline("Imp"), Some(vec![1, 5, 9]));
+        assert_eq!(timeline.sparkline("Wait"), Some(vec![1, 1, 1]));
+        assert_eq!(timeline.sparkline("Dwarf"), None);
+        assert_eq!(timeline.to_string(), "cycle,Imp,Wait\n0,1,1\n4,5,1\n8,9,1");
+    }
+
+    #[test]
+    fn overwriting_transfers_ownership() {
+        let mut core = build_core(&[";name Bomber\nmov 1, 50\ndat 0, 0", ";name Target\njmp 0"]);
+
+        core.step().unwrap();
+
+        assert_eq!(core.owner(50), Some("Bomber"));
+        assert_eq!(core.owned_cells(), &[3, 0]);
+    }
+}
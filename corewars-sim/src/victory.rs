@@ -0,0 +1,246 @@
+//! Deciding when a battle is over, and who won. The standard rule is
+//! [`LastStanding`](LastStanding), but a [`Battle`](crate::Battle) can be
+//! given another with
+//! [`set_victory_condition`](crate::Battle::set_victory_condition) to play a
+//! variant game. Whatever the condition, a battle always ends after the
+//! configured maximum number of cycles.
+
+use std::fmt;
+
+use crate::battle::Outcome;
+use crate::core::Core;
+
+/// A rule for ending a battle early, checked after every step.
+///
+/// ```
+/// use corewars_sim::{Battle, BattleConfig, Core, Outcome, VictoryCondition};
+///
+/// /// Every warrior ties after 100 cycles, even those which died
+/// #[derive(Debug)]
+/// struct Truce;
+///
+/// impl VictoryCondition for Truce {
+///     fn outcome(&self, core: &Core) -> Option<Outcome> {
+///         (core.cycles() >= 100).then(|| Outcome::Tie(core.warriors().to_vec()))
+///     }
+/// }
+///
+/// let mut battle = Battle::new(BattleConfig::default()).unwrap();
+/// battle.set_victory_condition(Truce);
+/// ```
+pub trait VictoryCondition: fmt::Debug {
+    /// The outcome of the battle if it is over given the state of `core`, or
+    /// `None` if it should carry on.
+    fn outcome(&self, core: &Core) -> Option<Outcome>;
+}
+
+/// The standard condition: the battle is over once at most one warrior is
+/// left alive, who wins. A lone warrior instead runs until it dies.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LastStanding;
+
+impl VictoryCondition for LastStanding {
+    fn outcome(&self, core: &Core) -> Option<Outcome> {
+        let mut survivors = survivors(core);
+        let min_alive = if core.warriors().len() > 1 { 1 } else { 0 };
+
+        match survivors.len() {
+            alive if alive > min_alive => None,
+            1 => Some(Outcome::Win(survivors.remove(0))),
+            _ => Some(Outcome::Tie(survivors)),
+        }
+    }
+}
+
+/// A measure of how well a warrior is doing, for
+/// [`ScoreThreshold`](ScoreThreshold).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// The number of processes the warrior has in the queue
+    Processes,
+
+    /// The number of instructions in the core the warrior owns
+    Territory,
+}
+
+/// The first warrior to reach a score wins, or the last one standing if
+/// nobody reaches it. If several reach it in the same cycle, the first loaded
+/// wins.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScoreThreshold {
+    pub score: Score,
+    pub threshold: usize,
+}
+
+impl VictoryCondition for ScoreThreshold {
+    fn outcome(&self, core: &Core) -> Option<Outcome> {
+        let queue = core.process_queue();
+        let leader = core
+            .warriors()
+            .iter()
+            .zip(core.owned_cells())
+            .enumerate()
+            .filter(|&(id, _)| queue.thread_count(id) > 0)
+            .find(|&(id, (_, &owned_cells))| {
+                let score = match self.score {
+                    Score::Processes => queue.thread_count(id),
+                    Score::Territory => owned_cells,
+                };
+                score >= self.threshold
+            });
+
+        match leader {
+            Some((_, (name, _))) => Some(Outcome::Win(name.clone())),
+            None => LastStanding.outcome(core),
+        }
+    }
+}
+
+/// Capture the flag: the first warrior to write to the instruction at
+/// `address` wins, unless it loaded that instruction itself, or the last one
+/// standing if nobody captures it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CaptureTheFlag {
+    pub address: u32,
+}
+
+impl VictoryCondition for CaptureTheFlag {
+    fn outcome(&self, core: &Core) -> Option<Outcome> {
+        let address = self.address % core.size();
+
+        // The warrior whose code the flag was loaded as can't capture it
+        let loaded_by = core
+            .loaded()
+            .iter()
+            .find(|handle| (address + core.size() - handle.position) % core.size() < handle.len)
+            .map(|handle| handle.id);
+
+        match core.owner_id(address as i32) {
+            Some(owner) if Some(owner) != loaded_by => {
+                Some(Outcome::Win(core.warriors()[owner].clone()))
+            }
+            _ => LastStanding.outcome(core),
+        }
+    }
+}
+
+/// Every warrior which survives `cycles` cycles wins, so the battle carries
+/// on when only one is left alive. If a single warrior survives it wins
+/// outright, otherwise the survivors tie; if all die, nobody wins.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SurviveCycles {
+    pub cycles: usize,
+}
+
+impl VictoryCondition for SurviveCycles {
+    fn outcome(&self, core: &Core) -> Option<Outcome> {
+        let mut survivors = survivors(core);
+
+        if survivors.is_empty() {
+            Some(Outcome::Tie(survivors))
+        } else if core.cycles() < self.cycles {
+            None
+        } else if survivors.len() == 1 {
+            Some(Outcome::Win(survivors.remove(0)))
+        } else {
+            Some(Outcome::Tie(survivors))
+        }
+    }
+}
+
+/// The warriors in `core` with any processes left, in load order.
+fn survivors(core: &Core) -> Vec<String> {
+    let queue = core.process_queue();
+    core.warriors()
+        .iter()
+        .enumerate()
+        .filter(|&(id, _)| queue.thread_count(id) > 0)
+        .map(|(_, name)| name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::battle::{Battle, BattleConfig};
+
+    fn battle<V: VictoryCondition + 'static>(condition: V, programs: &[&str]) -> Battle {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 800,
+            max_cycles: 200,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        battle.set_victory_condition(condition);
+
+        for (i, program) in programs.iter().enumerate() {
+            let warrior = corewars_parser::parse(program).expect("Failed to parse warrior");
+            battle
+                .load(&warrior, i as u32 * 400)
+                .expect("Failed to load warrior");
+        }
+
+        battle
+    }
+
+    #[test]
+    fn reaches_score() {
+        let mut splitters = battle(
+            ScoreThreshold {
+                score: Score::Processes,
+                threshold: 4,
+            },
+            &[";name Imp\nmov 0, 1", ";name Splitter\nspl 0\njmp -1"],
+        );
+        assert_eq!(splitters.run(), Outcome::Win("Splitter".into()));
+        assert!(splitters.core().steps_taken() < 200);
+
+        let mut imps = battle(
+            ScoreThreshold {
+                score: Score::Territory,
+                threshold: 10,
+            },
+            &[";name Imp\nmov 0, 1", ";name Imp2\nmov 0, 1"],
+        );
+        assert_eq!(imps.run(), Outcome::Win("Imp".into()));
+        assert_eq!(imps.core().steps_taken(), 17);
+    }
+
+    #[test]
+    fn captures_flag() {
+        // The imp writes over the flag at 5, but not the one it loaded at 0
+        let mut captured = battle(
+            CaptureTheFlag { address: 5 },
+            &[";name Imp\nmov 0, 1", ";name Waits\njmp 0"],
+        );
+        assert_eq!(captured.run(), Outcome::Win("Imp".into()));
+        assert_eq!(captured.core().steps_taken(), 9);
+
+        let mut home = battle(
+            CaptureTheFlag { address: 0 },
+            &[";name Imp\nmov 0, 1", ";name Waits\njmp 0"],
+        );
+        assert_eq!(home.run(), Outcome::Tie(vec!["Imp".into(), "Waits".into()]));
+    }
+
+    #[test]
+    fn survives_cycles() {
+        let mut survivor = battle(
+            SurviveCycles { cycles: 50 },
+            &[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"],
+        );
+        assert_eq!(survivor.run(), Outcome::Win("Imp".into()));
+        assert_eq!(survivor.core().cycles(), 50);
+        // Both warriors executed in the first cycle
+        assert_eq!(survivor.core().steps_taken(), 51);
+
+        let mut doomed = battle(
+            SurviveCycles { cycles: 50 },
+            &[";name Dies\ndat 0, 0", ";name Dies2\ndat 0, 0"],
+        );
+        assert_eq!(doomed.run(), Outcome::Tie(Vec::new()));
+        assert_eq!(doomed.core().steps_taken(), 2);
+    }
+}
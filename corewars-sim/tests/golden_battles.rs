@@ -0,0 +1,70 @@
+//! Regression tests for the whole parse, load and simulate pipeline, using
+//! well known matchups. The first warrior is always loaded at 0 and the
+//! second at 4000 with the default '94 hill settings, so each battle has a
+//! single deterministic outcome, which should be that of one round of pMARS
+//! with the same fixed position:
+//!
+//! ```text
+//! pmars -b -r 1 -F 4000 -s 8000 -c 80000 -p 8000 -l 100 -d 100 <first> <second>
+//! ```
+//!
+//! The expected outcomes were checked against the reference executor
+//! (`--features reference`), but not against pMARS itself yet.
+
+// test_case generates unit expressions which trip this lint
+#![allow(clippy::unused_unit)]
+
+use std::fs;
+use std::path::Path;
+
+use test_case::test_case;
+
+use corewars_core::Warrior;
+use corewars_sim::{Battle, BattleConfig, Outcome};
+
+const IMP: &str = ";name Imp\nmov 0, 1";
+
+/// Where the second warrior is loaded, as with `pmars -F`
+const FIXED_POSITION: u32 = 4000;
+
+fn load(path: &str) -> Warrior {
+    if path == "imp" {
+        return corewars_parser::parse(IMP).unwrap();
+    }
+
+    // Paths are relative to the workspace testdata, see validate.rs
+    let input_file = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("testdata/input")
+        .join(path);
+
+    let input = fs::read_to_string(input_file).unwrap();
+    corewars_parser::parse(&input).unwrap()
+}
+
+fn tie(names: &[&str]) -> Outcome {
+    Outcome::Tie(names.iter().map(|name| name.to_string()).collect())
+}
+
+fn win(name: &str) -> Outcome {
+    Outcome::Win(name.to_string())
+}
+
+#[test_case("simple/dwarf.redcode", "imp", tie(&["Dwarf", "Imp"]); "dwarf ties imp")]
+#[test_case("wilkie/irongate.redcode", "imp", tie(&["Iron Gate", "Imp"]); "gate stops imp")]
+#[test_case("wilkie/fstorm.redcode", "imp", tie(&["Fire Storm v1.1", "Imp"]); "imp survives core clear")]
+#[test_case("wilkie/rave.redcode", "imp", win("Rave"); "scanner kills imp")]
+#[test_case("wilkie/tornado.redcode", "simple/dwarf.redcode", win("Tornado"); "fast bomber beats dwarf")]
+#[test_case("wilkie/cannon.redcode", "simple/dwarf.redcode", win("Cannonade"); "stone beats dwarf")]
+#[test_case("wilkie/paperone.redcode", "simple/dwarf.redcode", win("Paperone"); "paper beats dwarf")]
+#[test_case("wilkie/tornado.redcode", "wilkie/rave.redcode", win("Tornado"); "bomber beats scanner")]
+fn golden_battle(first: &str, second: &str, expected: Outcome) {
+    use pretty_assertions::assert_eq;
+
+    let mut battle = Battle::new(BattleConfig::default()).unwrap();
+    battle.load(&load(first), 0).unwrap();
+    battle.load(&load(second), FIXED_POSITION).unwrap();
+
+    assert_eq!(battle.run(), expected);
+}
@@ -0,0 +1,174 @@
+//! Blind tournaments, where the judges shouldn't know whose warrior is whose
+//! until the results are in.
+//!
+//! [`anonymize`] strips the name, author and other identifying comments from
+//! each warrior and names it with a pseudonym instead, like `Anon-3f2a9c01`.
+//! The pseudonym is a hash of the warrior's [digest](Warrior::digest) and a
+//! key chosen by the organizers, so the same program gets the same pseudonym
+//! in every round of an event, but nobody without the key can tell which
+//! known warrior is behind one. The returned [`Mapping`] is kept by the
+//! organizers, and [reveals](Mapping::reveal) the real names afterwards.
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use corewars_core::load_file::Metadata;
+use corewars_core::Warrior;
+
+/// How many hex digits of the hash a pseudonym uses.
+const PSEUDONYM_DIGITS: usize = 8;
+
+/// Who is behind a pseudonym.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub pseudonym: String,
+    pub name: Option<String>,
+    pub author: Option<String>,
+
+    /// The [digest](Warrior::digest) of the warrior's program
+    pub digest: String,
+}
+
+/// The identity behind every pseudonym given by [`anonymize`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mapping {
+    pub identities: Vec<Identity>,
+}
+
+impl Mapping {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("mappings are serializable")
+    }
+
+    /// Read a mapping previously written by [`to_json`](Self::to_json).
+    pub fn from_json(text: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(text)
+    }
+
+    /// The identity behind `pseudonym`, if it is in the mapping.
+    pub fn identity(&self, pseudonym: &str) -> Option<&Identity> {
+        self.identities
+            .iter()
+            .find(|identity| identity.pseudonym == pseudonym)
+    }
+
+    /// Replace every pseudonym in `text`, e.g. a crosstable, with the name of
+    /// the warrior behind it. Unnamed warriors keep their pseudonym.
+    pub fn reveal(&self, text: &str) -> String {
+        // Longer pseudonyms first, so "Anon-1234abcd (2)" isn't revealed as
+        // the warrior behind "Anon-1234abcd"
+        let mut identities: Vec<&Identity> = self.identities.iter().collect();
+        identities.sort_by_key(|identity| std::cmp::Reverse(identity.pseudonym.len()));
+
+        let mut revealed = text.to_string();
+        for identity in identities {
+            if let Some(name) = &identity.name {
+                revealed = revealed.replace(&identity.pseudonym, name);
+            }
+        }
+        revealed
+    }
+}
+
+/// The pseudonym of `warrior` with `key`, which only depends on its program.
+pub fn pseudonym(key: &str, warrior: &Warrior) -> String {
+    let hash = Sha1::digest(format!("{}\0{}", key, warrior.digest()).as_bytes());
+    let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("Anon-{}", &hex[..PSEUDONYM_DIGITS])
+}
+
+/// Give each warrior its [pseudonym](pseudonym) with `key` as its name, and
+/// remove every comment which might identify it, keeping only those which
+/// affect how it runs. Identical programs get the same pseudonym, so the
+/// second and later copies are told apart like `Anon-3f2a9c01 (2)`.
+pub fn anonymize(key: &str, warriors: Vec<Warrior>) -> (Vec<Warrior>, Mapping) {
+    let mut mapping = Mapping::default();
+
+    let warriors = warriors
+        .into_iter()
+        .map(|warrior| {
+            let base = pseudonym(key, &warrior);
+            let mut pseudonym = base.clone();
+            let mut copy = 1;
+            while mapping.identity(&pseudonym).is_some() {
+                copy += 1;
+                pseudonym = format!("{} ({})", base, copy);
+            }
+
+            let digest = warrior.digest();
+            let Warrior { program, metadata } = warrior;
+            mapping.identities.push(Identity {
+                pseudonym: pseudonym.clone(),
+                name: metadata.name,
+                author: metadata.author,
+                digest,
+            });
+
+            Warrior {
+                program,
+                metadata: Metadata {
+                    redcode: metadata.redcode,
+                    name: Some(pseudonym),
+                    assertion: metadata.assertion,
+                    pin: metadata.pin,
+                    ..Metadata::default()
+                },
+            }
+        })
+        .collect();
+
+    (warriors, mapping)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn warrior(source: &str) -> Warrior {
+        corewars_parser::parse(source).unwrap()
+    }
+
+    #[test]
+    fn anonymizes_warriors() {
+        let imp = ";name Imp\n;author A. K. Dewdney\n;strategy Walk forward\nmov 0, 1";
+        let warriors = vec![
+            warrior(imp),
+            warrior(";name Dwarf\n;author A. K. Dewdney\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0"),
+            warrior(";name Copycat\nmov 0, 1"),
+        ];
+
+        let (anonymous, mapping) = anonymize("secret", warriors);
+
+        let pseudonym = pseudonym("secret", &warrior(imp));
+        assert!(pseudonym.starts_with("Anon-"));
+        assert_eq!(
+            anonymous[0].metadata,
+            Metadata {
+                name: Some(pseudonym.clone()),
+                ..Metadata::default()
+            }
+        );
+        assert_eq!(
+            anonymous[2].metadata.name,
+            Some(format!("{} (2)", pseudonym)),
+            "identical programs should be told apart"
+        );
+        assert_ne!(anonymous[1].metadata.name, anonymous[0].metadata.name);
+        assert_ne!(
+            Some(pseudonym.clone()),
+            anonymize("other", vec![warrior(imp)]).0[0].metadata.name,
+            "pseudonyms should depend on the key"
+        );
+
+        let identity = mapping.identity(&pseudonym).unwrap();
+        assert_eq!(identity.name.as_deref(), Some("Imp"));
+        assert_eq!(identity.author.as_deref(), Some("A. K. Dewdney"));
+        assert_eq!(identity.digest, warrior(imp).digest());
+        assert_eq!(Mapping::from_json(&mapping.to_json()).unwrap(), mapping);
+
+        let results = format!("{0}: 300\n{0} (2): 100\n", pseudonym);
+        assert_eq!(mapping.reveal(&results), "Imp: 300\nCopycat: 100\n");
+    }
+}
@@ -1,15 +1,46 @@
 use std::{
     error::Error,
-    fs,
-    io::{self, Read},
-    path::PathBuf,
+    fmt, fs,
+    io::{self, BufRead, Read},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
 };
 
 use lazy_static::lazy_static;
 use structopt::StructOpt;
 
+use corewars_core::analysis;
+use corewars_core::load_file::{distinct_names, AddressMode, Modifier, ModifierPolicy, Opcode};
+use corewars_core::perf::{PerfStats, PhaseStats};
+use corewars_core::text::{LineEnding, TextLayout};
+use corewars_core::Warrior;
 use corewars_parser as parser;
-use corewars_sim::Core;
+use corewars_sim::{
+    Activity, AnalysisPass, Backend, Battle, BattleConfig, Core, DumpFilter, Fault, Faults,
+    FieldRange, Heatmap, HeatmapStyle, MetricsTimeline, OwnershipTimeline, PositionSchedule,
+    ScheduleError,
+};
+
+use super::blind;
+use super::corewar_io;
+use super::corpus;
+use super::debugger::Debugger;
+use super::format::{self, Formats};
+use super::hill::{Hill, Server};
+use super::html::Report;
+use super::index;
+use super::interrupt::{self, StateToken};
+use super::messages::{self, Catalog, Message};
+use super::package::{self, Package};
+use super::pmars;
+use super::replay::Replay;
+use super::report::{Reporter, Severity};
+use super::signature::AuthorKeys;
+use super::spec;
+use super::stream::{self, Viewers};
+use super::telemetry::{JsonLines, Telemetry};
+use super::tournament::{self, Crosstable};
 
 lazy_static! {
     static ref IO_SENTINEL: PathBuf = PathBuf::from("-");
@@ -28,9 +59,48 @@ struct CliOptions {
     #[structopt(long, short)]
     verbose: bool,
 
-    /// Input file; use "-" to read from stdin
+    /// Print how long each phase of parsing and simulation took, and roughly
+    /// how much memory it used, to stderr
+    #[structopt(long)]
+    timings: bool,
+
+    /// Input file; use "-" to read from stdin. Required by commands which
+    /// operate on a warrior. A warrior exported by corewar.io as a `.json`
+    /// file is read as its Redcode
     #[structopt(parse(from_os_str))]
-    input_file: PathBuf,
+    input_file: Option<PathBuf>,
+
+    /// Another source file to link after the input file, sharing labels
+    /// with `EXPORT` and `IMPORT` lines. May be given more than once
+    #[structopt(long, parse(from_os_str), number_of_values = 1)]
+    link: Vec<PathBuf>,
+
+    /// Replace each `${NAME}` in the input with the environment variable
+    /// NAME, e.g. to try out different constants from a script
+    #[structopt(long)]
+    env: bool,
+
+    /// Read the input as an ICWS '94 load file, e.g. one written by another
+    /// assembler, instead of as Redcode source
+    #[structopt(long)]
+    load_file: bool,
+
+    /// How to choose the modifier of instructions written without one: "94"
+    /// for the ICWS'94 draft's table, or "88" to follow ICWS'88, which only
+    /// differs for `CMP` and `SLT` with an immediate B-operand
+    #[structopt(long, default_value = "94")]
+    modifiers: ModifierPolicy,
+
+    /// Refuse to run warriors with field values too big for the core,
+    /// instead of wrapping them into it with a warning
+    #[structopt(long)]
+    strict_fields: bool,
+
+    /// A message catalog replacing the text printed, e.g. to translate it,
+    /// with a `key = template` line per message as described in the
+    /// documentation of `corewars::messages`
+    #[structopt(long, parse(from_os_str))]
+    messages: Option<PathBuf>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -46,82 +116,1863 @@ enum Command {
         /// expanded in the output
         #[structopt(long, short = "E")]
         no_expand: bool,
+
+        /// Print an assembler listing instead, with each line of the input
+        /// alongside the offsets and resolved operands of its instructions
+        #[structopt(long)]
+        listing: bool,
+
+        /// The format to write the program in, e.g. "json"
+        #[structopt(long, default_value = "loadfile")]
+        format: String,
+
+        /// Keep the comment at the end of each line of the input, after the
+        /// instruction it assembled to. Only for the "loadfile" format
+        #[structopt(long)]
+        comments: bool,
+
+        /// Wrap field values and the origin to a core of this size, as
+        /// signed offsets like `$-1` rather than `$7999`
+        #[structopt(long)]
+        core_size: Option<u32>,
+
+        /// Leave out the metadata comments, e.g. `;name` and `;author`
+        #[structopt(long)]
+        no_metadata: bool,
+
+        /// Leave out the `ORG` line
+        #[structopt(long)]
+        no_origin: bool,
+
+        /// What to end each line with, "lf" or "crlf"
+        #[structopt(long, default_value = "lf")]
+        line_ending: LineEnding,
+
+        /// Don't end the last line, e.g. to match output which doesn't
+        #[structopt(long)]
+        no_trailing_newline: bool,
     },
 
-    /// Run a warrior to completion
+    /// Check that a program assembles, printing any errors and warnings.
+    /// Exits with a nonzero status if it doesn't assemble
+    #[structopt(name = "check")]
+    Check {
+        /// Also fail if there are any warnings
+        #[structopt(long)]
+        deny_warnings: bool,
+    },
+
+    /// Print a program as standard Redcode, with labels, macros and expressions
+    /// resolved, so it can be used with other assemblers
+    #[structopt(name = "preprocess")]
+    Preprocess {
+        /// Output file; defaults to stdout ("-")
+        #[structopt(long, short, parse(from_os_str), default_value = IO_SENTINEL.to_str().unwrap())]
+        output_file: PathBuf,
+    },
+
+    /// Run a warrior to completion, or the tournament described by a spec
     #[structopt(name = "run")]
     Run {
+        /// A tournament spec to run instead of a warrior, whose results are
+        /// printed and archived with it
+        #[structopt(parse(from_os_str))]
+        spec: Option<PathBuf>,
+
+        /// With a spec, the directory to archive it in with its warriors and
+        /// results. Defaults to the spec's path without its extension,
+        /// followed by "-results"
+        #[structopt(long, parse(from_os_str))]
+        archive: Option<PathBuf>,
+
         /// The max number of cycles to run. Defaults to
         #[structopt(long, short)]
         max_cycles: Option<usize>,
+
+        /// Print a CSV timeline of the instructions owned by each warrior,
+        /// sampled every this many cycles
+        #[structopt(long)]
+        timeline: Option<usize>,
+
+        /// Print a CSV timeline of aggregate core metrics (fraction of non-DAT
+        /// instructions, unique instructions and write entropy), sampled every
+        /// this many cycles
+        #[structopt(long)]
+        metrics: Option<usize>,
+
+        /// Print which combinations of opcode, modifier and address modes
+        /// were executed, and which were not
+        #[structopt(long)]
+        coverage: bool,
+
+        /// Print the last this many instructions executed, newest first, to
+        /// see what the warrior was doing when it stopped
+        #[structopt(long)]
+        history: Option<usize>,
+
+        /// Print the instructions left in the core which pass this filter:
+        /// "non-default", "executed" or "owner=NAME". May be given more than
+        /// once, to print only instructions which pass every filter
+        #[structopt(long, number_of_values = 1)]
+        dump: Vec<DumpFilter>,
+
+        /// Tag processes starting in part of the warrior, given as
+        /// "START-END=NAME" or "OFFSET=NAME", e.g. "0-3=stone". New processes
+        /// inherit the tag of the one which split them, unless they start in
+        /// another tagged part. How many processes each tag has left is
+        /// printed after running. May be given more than once
+        #[structopt(long, number_of_values = 1, parse(try_from_str = parse_tag))]
+        tag: Vec<(u32, u32, String)>,
+    },
+
+    /// Battle the warrior against one or more opponents, printing the outcome
+    /// of each round
+    #[structopt(name = "battle")]
+    Battle {
+        /// Files containing the opponents
+        #[structopt(parse(from_os_str), required = true)]
+        opponents: Vec<PathBuf>,
+
+        /// A file listing the position of every warrior for each round, one
+        /// round per line. Defaults to a single round with the warriors
+        /// spread evenly through the core
+        #[structopt(long, parse(from_os_str))]
+        positions: Option<PathBuf>,
+
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        #[structopt(long, default_value = "80000")]
+        max_cycles: usize,
+
+        /// How the core stores its instructions: "dense", or "cow" to share
+        /// unmodified pages between copies of the core
+        #[structopt(long, default_value = "dense")]
+        backend: Backend,
+
+        /// The format to print the outcome of each round in, e.g. "json", or
+        /// "nmars" for the wins, losses and ties of each warrior over every
+        /// round, as printed by nMARS and Exhaust
+        #[structopt(long, default_value = "loadfile")]
+        format: String,
+
+        /// Also write an HTML report of the battle to this file, with the
+        /// score of each warrior and a replay of the first round
+        #[structopt(long, parse(from_os_str))]
+        report: Option<PathBuf>,
+
+        /// Also write the first round to this file as a corewar.io battle,
+        /// in its JSON format
+        #[structopt(long, parse(from_os_str))]
+        corewar_io_replay: Option<PathBuf>,
+
+        /// Inject a fault into every round, given as "FAULT@CYCLE" to inject
+        /// it once CYCLE cycles were executed: "flip=ADDRESS" to flip a bit
+        /// of an instruction, "kill" or "kill=NAME" to kill a random process,
+        /// or "corrupt-a=ADDRESS" or "corrupt-b=ADDRESS" to replace a value
+        /// with a random one. May be given more than once
+        #[structopt(long, number_of_values = 1, parse(try_from_str = parse_fault))]
+        fault: Vec<(usize, Fault)>,
+
+        /// The seed for the random choices made by --fault
+        #[structopt(long, default_value = "0")]
+        fault_seed: u64,
+
+        /// After the outcomes, print a heatmap of the "writes" or
+        /// "executions" of each instruction in the first round
+        #[structopt(long)]
+        heatmap: Option<Activity>,
+
+        /// The number of instructions on each row of the heatmap
+        #[structopt(long, default_value = "100")]
+        heatmap_width: usize,
+
+        /// How to draw the heatmap: "ascii", or "ansi" for colored blocks
+        #[structopt(long, default_value = "ascii")]
+        heatmap_style: HeatmapStyle,
+
+        /// After the outcomes, print the cycle each warrior cleared the core
+        /// in, i.e. when every instruction but its own is a DAT, in each round
+        #[structopt(long)]
+        clears: bool,
+    },
+
+    /// Step through a battle interactively, reading debugger commands from
+    /// stdin. Addresses may be absolute or relative to a warrior's load
+    /// point, e.g. "w2+5"
+    #[structopt(name = "debug")]
+    Debug {
+        /// Files containing the opponents, loaded after the warrior
+        #[structopt(parse(from_os_str))]
+        opponents: Vec<PathBuf>,
+
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        /// A file to load address labels from, which is updated with any
+        /// labels added or removed during the session
+        #[structopt(long, parse(from_os_str))]
+        session: Option<PathBuf>,
+
+        /// Run the debugger commands in this file, one per line, instead of
+        /// reading them from stdin. Stops at the first command which fails
+        #[structopt(long, parse(from_os_str))]
+        script: Option<PathBuf>,
+    },
+
+    /// Report how the warrior could be made shorter, e.g. to fit under MAXLENGTH
+    #[structopt(name = "compress")]
+    Compress,
+
+    /// Print how many times the warrior uses each opcode, modifier and
+    /// address mode, one count per line, e.g. to check it only uses those
+    /// allowed on a hill
+    #[structopt(name = "stats")]
+    Stats,
+
+    /// Warn about likely mistakes in the warrior, such as bombing steps which
+    /// share large factors with the core size
+    #[structopt(name = "lint")]
+    Lint {
+        /// The size of the core the warrior is meant for
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        /// Warn about steps which could miss opponents this long entirely
+        #[structopt(long, default_value = "4")]
+        min_opponent_length: u32,
+    },
+
+    /// Run a warrior using pMARS-style options, e.g. "-s 8000 -c 80000 imp.red".
+    /// Options can also be read from a parameter file with "-@ file"
+    #[structopt(
+        name = "pmars",
+        setting = structopt::clap::AppSettings::TrailingVarArg,
+        setting = structopt::clap::AppSettings::AllowLeadingHyphen
+    )]
+    Pmars {
+        /// pMARS options and warrior files
+        #[structopt(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Index the metadata of all warriors in a directory, for searching and
+    /// finding duplicates
+    #[structopt(name = "index")]
+    Index {
+        /// The directory to crawl for Redcode files, which must not be
+        /// modified while it is indexed
+        #[structopt(parse(from_os_str))]
+        directory: PathBuf,
+
+        /// Output format, either "json" or "csv"
+        #[structopt(long, short, default_value = "json", possible_values = &["json", "csv"])]
+        format: String,
+
+        /// Output file; defaults to stdout ("-")
+        #[structopt(long, short, parse(from_os_str), default_value = IO_SENTINEL.to_str().unwrap())]
+        output_file: PathBuf,
+
+        /// The number of files to parse at once. Defaults to the number of
+        /// cores
+        #[structopt(long)]
+        jobs: Option<usize>,
+    },
+
+    /// Find warriors matching the given criteria, and print their paths one
+    /// per line
+    #[structopt(name = "search")]
+    Search {
+        /// A JSON index written by the "index" command, or a directory to scan
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+
+        /// The directory paths in the index are relative to. Defaults to the
+        /// directory being scanned, or the directory containing the index
+        #[structopt(long, parse(from_os_str))]
+        root: Option<PathBuf>,
+
+        /// Text which must appear in the warrior's name, ignoring case
+        #[structopt(long)]
+        name: Option<String>,
+
+        /// Text which must appear in the warrior's author, ignoring case
+        #[structopt(long)]
+        author: Option<String>,
+
+        /// The minimum number of instructions
+        #[structopt(long)]
+        min_length: Option<u32>,
+
+        /// The maximum number of instructions
+        #[structopt(long)]
+        max_length: Option<u32>,
+
+        /// The hash of the warrior's program, to find duplicates
+        #[structopt(long)]
+        hash: Option<String>,
+    },
+
+    /// Download a well-known set of warriors into a local cache, printing the
+    /// directory they were saved to
+    #[structopt(name = "fetch-corpus")]
+    FetchCorpus {
+        /// The corpus to download
+        #[structopt(possible_values = &["wilkie", "wilmoo", "icws"])]
+        name: String,
+
+        /// The directory to cache corpora in. Defaults to
+        /// $XDG_CACHE_HOME/corewars, or ~/.cache/corewars
+        #[structopt(long, parse(from_os_str))]
+        cache_dir: Option<PathBuf>,
+    },
+
+    /// Print the grammar of a line of Redcode accepted by the parser, e.g. to
+    /// keep an editor's syntax highlighting in sync with it
+    #[structopt(name = "grammar")]
+    Grammar {
+        /// Print the grammar as EBNF, instead of in pest's format
+        #[structopt(long)]
+        ebnf: bool,
+    },
+
+    /// Describe what an instruction does, e.g. "MOV.AB", or what causes an
+    /// error or warning, from its code, e.g. "E0001"
+    #[structopt(name = "explain")]
+    Explain {
+        /// The opcode and optional modifier to explain. If the modifier is
+        /// omitted, the ICWS'88 default is used. A diagnostic code is
+        /// explained instead
+        instruction: String,
+    },
+
+    /// Measure how long each opcode and modifier takes to execute
+    #[structopt(name = "bench")]
+    Bench {
+        /// The size of the core to execute in. Each instruction is executed
+        /// once per address in each round
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        /// The number of rounds to time; the fastest is reported
+        #[structopt(long, default_value = "5")]
+        rounds: usize,
+
+        /// Skip the instructions already timed by an interrupted run, given
+        /// the state it printed, e.g. "bench:37"
+        #[structopt(long)]
+        resume: Option<StateToken>,
+
+        /// Compare the core backends instead of timing each instruction
+        #[structopt(long)]
+        backends: bool,
+    },
+
+    /// Battle the warriors against each other, and print a crosstable of the
+    /// results
+    #[structopt(name = "tournament")]
+    Tournament {
+        /// Files containing the warriors
+        #[structopt(parse(from_os_str), required = true)]
+        warriors: Vec<PathBuf>,
+
+        /// The number of rounds each pair of warriors battles for, 100 by
+        /// default. With --time-budget, the most rounds each pair battles for,
+        /// without a limit by default
+        #[structopt(long)]
+        rounds: Option<u32>,
+
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        #[structopt(long, default_value = "80000")]
+        max_cycles: usize,
+
+        /// Play a round robin for as many rounds as fit in this many seconds,
+        /// and print how uncertain each score is
+        #[structopt(long)]
+        time_budget: Option<u64>,
+
+        /// How to pair warriors up: "round-robin", "swiss" (or "swiss=ROUNDS"),
+        /// "single-elimination" or "double-elimination". Every pairing but
+        /// round robin also prints the matches of each round
+        #[structopt(long, default_value = "round-robin")]
+        pairing: tournament::Pairing,
+
+        /// Finish an interrupted round robin, given the results it printed
+        /// with --format json, only playing the matches missing from them
+        #[structopt(long, parse(from_os_str))]
+        resume: Option<PathBuf>,
+
+        /// Hide who wrote each warrior: name warriors with pseudonyms instead,
+        /// and write who is behind each one to this file, for "reveal"
+        #[structopt(long, parse(from_os_str))]
+        blind: Option<PathBuf>,
+
+        /// The secret the pseudonyms of --blind are derived from. Keep the
+        /// same key to give warriors the same pseudonyms in several events.
+        /// Ignored without --blind
+        #[structopt(long, default_value = "")]
+        blind_key: String,
+
+        /// Output format, either "text" or "json"
+        #[structopt(long, short, default_value = "text", possible_values = &["text", "json"])]
+        format: String,
+    },
+
+    /// Measure how a warrior's score depends on how far from its opponents
+    /// it starts, e.g. whether it loses when they start close to it
+    #[structopt(name = "bias")]
+    Bias {
+        /// The file containing the warrior to measure
+        #[structopt(parse(from_os_str))]
+        warrior: PathBuf,
+
+        /// Files containing the warriors to battle it against
+        #[structopt(parse(from_os_str), required = true)]
+        opponents: Vec<PathBuf>,
+
+        /// The number of rounds against each opponent, at separations spread
+        /// evenly through the core
+        #[structopt(long, default_value = "100")]
+        rounds: u32,
+
+        /// The number of ranges of separations to group rounds into
+        #[structopt(long, default_value = "10")]
+        bins: u32,
+
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        #[structopt(long, default_value = "80000")]
+        max_cycles: usize,
+
+        /// Output format: "text", "csv" to plot the score in each range of
+        /// separations, or "json"
+        #[structopt(long, short, default_value = "text", possible_values = &["text", "csv", "json"])]
+        format: String,
+    },
+
+    /// Replace the pseudonyms of a blind tournament with the names of the
+    /// warriors behind them
+    #[structopt(name = "reveal")]
+    Reveal {
+        /// The file written by "tournament --blind"
+        #[structopt(parse(from_os_str))]
+        mapping: PathBuf,
+
+        /// The results to reveal; defaults to stdin ("-")
+        #[structopt(parse(from_os_str), default_value = IO_SENTINEL.to_str().unwrap())]
+        results: PathBuf,
+    },
+
+    /// Run a king of the hill
+    #[structopt(name = "hill")]
+    Hill {
+        #[structopt(subcommand)]
+        command: HillCommand,
+    },
+
+    /// Create or check a warrior package: a directory with a warrior, its
+    /// metadata, benchmarks and test battles. Commands which read a warrior
+    /// also accept the directory of a package
+    #[structopt(name = "package")]
+    Package {
+        #[structopt(subcommand)]
+        command: PackageCommand,
     },
 }
 
+#[derive(Debug, StructOpt)]
+enum PackageCommand {
+    /// Create a package in a new directory, with an imp to start from
+    #[structopt(name = "new")]
+    New {
+        /// The directory to create
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+
+        /// The name of the warrior. Defaults to the name of the directory
+        #[structopt(long)]
+        name: Option<String>,
+    },
+
+    /// Check that the package's warrior assembles and fits on its hill, then
+    /// play its test battles and benchmarks. Exits with a nonzero status if
+    /// a test battle is lost or a benchmark scores below its baseline
+    #[structopt(name = "check")]
+    Check {
+        /// The directory of the package
+        #[structopt(parse(from_os_str), default_value = ".")]
+        path: PathBuf,
+
+        /// Record the score of each benchmark as its new baseline
+        #[structopt(long)]
+        update_baselines: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum HillCommand {
+    /// Keep a hill in a directory: challenge the hill with each warrior saved
+    /// there, move it onto the hill or into the archive, and update the
+    /// standings in scores.txt. Everything is kept in the directory, so a
+    /// stopped server resumes where it left off when served again, without
+    /// --resume
+    #[structopt(name = "serve")]
+    Serve {
+        /// The directory to watch for new warriors
+        #[structopt(long, parse(from_os_str))]
+        watch: PathBuf,
+
+        /// The number of warriors which stay on the hill
+        #[structopt(long, default_value = "20")]
+        size: usize,
+
+        /// The number of rounds each pair of warriors battles for
+        #[structopt(long, default_value = "100")]
+        rounds: u32,
+
+        #[structopt(long, default_value = "8000")]
+        core_size: u32,
+
+        #[structopt(long, default_value = "80000")]
+        max_cycles: usize,
+
+        #[structopt(long, default_value = "100")]
+        max_length: u32,
+
+        #[structopt(long, default_value = "100")]
+        min_distance: u32,
+
+        /// How many seconds to wait between checks for new warriors
+        #[structopt(long, default_value = "5")]
+        interval: u64,
+
+        /// Challenge the hill with the warriors already there, then exit
+        /// instead of watching for more
+        #[structopt(long)]
+        once: bool,
+
+        /// Stream the first round of each challenge to web viewers, which
+        /// connect with a websocket to /battles at this address, e.g.
+        /// "127.0.0.1:8080"
+        #[structopt(long)]
+        stream: Option<String>,
+
+        /// Only accept warriors with an ed25519 ";signature" made by their
+        /// author, using the keys in this JSON file, e.g.
+        /// {"keys": {"Dewdney": ["<hex public key>"]}}
+        #[structopt(long, parse(from_os_str))]
+        author_keys: Option<PathBuf>,
+
+        /// Append a line of JSON to this file at most once per
+        /// --telemetry-interval, counting the battles run and the submissions
+        /// which failed to parse. Nothing else is reported, and nothing at all
+        /// unless this is given
+        #[structopt(long, parse(from_os_str))]
+        telemetry: Option<PathBuf>,
+
+        /// How many seconds to count events for between telemetry reports
+        #[structopt(long, default_value = "3600")]
+        telemetry_interval: u64,
+    },
+}
+
+/// An error which occurred while parsing the input file. This keeps the input
+/// around, so the error can be reported along with the offending line.
+#[derive(Debug)]
+pub struct ParseError {
+    pub error: parser::Error,
+    pub input: String,
+    pub file_name: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.error)
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
 pub fn run() -> Result<(), Box<dyn Error>> {
+    run_with(&Formats::builtin())
+}
+
+/// Run the command line interface, with `formats` available to the
+/// `--format` options. This lets other crates add their own output formats.
+pub fn run_with(formats: &Formats) -> Result<(), Box<dyn Error>> {
     let cli_options = CliOptions::from_args();
 
-    let mut input = String::new();
+    if let Some(path) = &cli_options.messages {
+        let catalog = Catalog::english()
+            .with_overrides(&fs::read_to_string(path)?)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        // Nothing has been printed yet, so this is the first catalog used
+        let _ = messages::install(catalog);
+    }
 
-    if cli_options.input_file == *IO_SENTINEL {
-        io::stdin().read_to_string(&mut input)?;
+    if let Command::Grammar { ebnf } = &cli_options.command {
+        if *ebnf {
+            print!("{}", parser::ebnf());
+        } else {
+            print!("{}", parser::GRAMMAR);
+        }
+        return Ok(());
+    }
+
+    if let Command::Explain { instruction } = &cli_options.command {
+        if is_diagnostic_code(instruction) {
+            let info = parser::explain_code(instruction).ok_or_else(|| {
+                Message::new("unknown-code")
+                    .arg("code", instruction.to_uppercase())
+                    .to_string()
+            })?;
+            println!("{}", info);
+            return Ok(());
+        }
+
+        let (opcode, modifier) = parse_opcode_and_modifier(instruction)?;
+        println!("{}", corewars_sim::explain(opcode, modifier));
+        return Ok(());
+    }
+
+    if let Command::Bench {
+        core_size,
+        rounds,
+        backends: true,
+        ..
+    } = &cli_options.command
+    {
+        if *core_size == 0 || *rounds == 0 {
+            return Err(Message::new("positive-core-size-and-rounds")
+                .to_string()
+                .into());
+        }
+        for timing in corewars_sim::time_backends(*core_size, *rounds) {
+            println!("{}", timing);
+        }
+        return Ok(());
+    }
+
+    if let Command::Bench {
+        core_size,
+        rounds,
+        resume,
+        ..
+    } = &cli_options.command
+    {
+        return run_bench(*core_size, *rounds, resume.as_ref());
+    }
+
+    if let Command::FetchCorpus { name, cache_dir } = &cli_options.command {
+        let corpus = corpus::find(name)?;
+        let cache_dir = match cache_dir {
+            Some(cache_dir) => cache_dir.clone(),
+            None => corpus::default_cache_dir()
+                .ok_or_else(|| Message::new("no-cache-directory").to_string())?,
+        };
+
+        let summary = corpus.fetch(&cache_dir, &corpus::Curl)?;
+        if cli_options.verbose {
+            eprintln!(
+                "{}",
+                Message::new("fetched-corpus")
+                    .arg("corpus", corpus.description)
+                    .arg("downloaded", summary.downloaded)
+                    .arg("cached", summary.cached)
+            );
+        }
+        println!("{}", corpus.directory(&cache_dir).display());
+        return Ok(());
+    }
+
+    if let Command::Index {
+        directory,
+        format,
+        output_file,
+        jobs,
+    } = &cli_options.command
+    {
+        let (entries, failures) = match jobs {
+            Some(jobs) => index::build_with_threads(directory, *jobs),
+            None => index::build(directory),
+        };
+
+        for failure in failures {
+            print_warning(
+                &Message::new("skipping-file")
+                    .arg("path", failure.path.display())
+                    .arg("reason", &failure.message)
+                    .to_string(),
+            );
+        }
+
+        let output = match format.as_str() {
+            "csv" => index::to_csv(&entries),
+            _ => index::to_json(&entries),
+        };
+        write_output(output_file, &output)?;
+        return Ok(());
+    }
+
+    if let Command::Search {
+        source,
+        root,
+        name,
+        author,
+        min_length,
+        max_length,
+        hash,
+    } = &cli_options.command
+    {
+        let (entries, default_root) = if source.is_dir() {
+            let (entries, failures) = index::build(source);
+            for failure in failures {
+                print_warning(
+                    &Message::new("skipping-file")
+                        .arg("path", failure.path.display())
+                        .arg("reason", &failure.message)
+                        .to_string(),
+                );
+            }
+            (entries, source.clone())
+        } else {
+            let entries = index::from_json(&fs::read_to_string(source)?)?;
+            let parent = source.parent().map(Path::to_path_buf).unwrap_or_default();
+            (entries, parent)
+        };
+
+        let query = index::Query {
+            name: name.clone(),
+            author: author.clone(),
+            min_length: *min_length,
+            max_length: *max_length,
+            hash: hash.clone(),
+        };
+
+        let root = root.as_ref().unwrap_or(&default_root);
+        for entry in query.search(&entries) {
+            println!("{}", root.join(&entry.path).display());
+        }
+        return Ok(());
+    }
+
+    if let Command::Hill {
+        command:
+            HillCommand::Serve {
+                watch,
+                size,
+                rounds,
+                core_size,
+                max_cycles,
+                max_length,
+                min_distance,
+                interval,
+                once,
+                stream,
+                author_keys,
+                telemetry,
+                telemetry_interval,
+            },
+    } = &cli_options.command
+    {
+        if *size == 0 || *rounds == 0 {
+            return Err(Message::new("positive-hill-size-and-rounds")
+                .to_string()
+                .into());
+        }
+
+        let config = BattleConfig {
+            core_size: *core_size,
+            max_cycles: *max_cycles,
+            max_length: *max_length,
+            min_distance: *min_distance,
+            ..BattleConfig::default()
+        };
+        config.validate(2)?;
+
+        let mut server = Server::open(watch, Hill::new(config, *size, *rounds))?;
+        if let Some(address) = stream {
+            let viewers = Viewers::bind(address.as_str())?;
+            let message = Message::new("hill-streaming")
+                .arg("address", viewers.local_addr()?)
+                .arg("endpoint", stream::ENDPOINT);
+            eprintln!("{}", message);
+            server.set_stream(viewers);
+        }
+        if let Some(author_keys) = author_keys {
+            server.set_author_keys(AuthorKeys::from_json(&fs::read_to_string(author_keys)?)?);
+        }
+        if let Some(path) = telemetry {
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            server.set_telemetry(Telemetry::new(
+                JsonLines(file),
+                Duration::from_secs(*telemetry_interval),
+            ));
+        }
+
+        return serve_hill(server, Duration::from_secs(*interval), *once);
+    }
+
+    if let Command::Tournament {
+        warriors,
+        rounds,
+        core_size,
+        max_cycles,
+        time_budget,
+        pairing,
+        resume,
+        blind,
+        blind_key,
+        format,
+    } = &cli_options.command
+    {
+        if *rounds == Some(0) {
+            return Err(Message::new("positive-rounds").to_string().into());
+        }
+        if time_budget.is_some() && *pairing != tournament::Pairing::RoundRobin {
+            return Err(Message::new("time-budget-needs-round-robin")
+                .to_string()
+                .into());
+        }
+        if resume.is_some()
+            && (time_budget.is_some() || *pairing != tournament::Pairing::RoundRobin)
+        {
+            return Err(Message::new("resume-needs-round-robin").to_string().into());
+        }
+
+        let mut parsed = Vec::new();
+        for path in warriors {
+            let (input, file_name) = read_input(path)?;
+            parsed.push(unwrap_parsed(parser::parse(&input), input, file_name)?);
+        }
+        if let Some(mapping_file) = blind {
+            let (anonymous, mapping) = blind::anonymize(blind_key, parsed);
+            fs::write(mapping_file, mapping.to_json())?;
+            parsed = anonymous;
+        }
+        let entrants: tournament::Entrants = parsed.into_iter().collect();
+
+        let config = BattleConfig {
+            core_size: *core_size,
+            max_cycles: *max_cycles,
+            ..BattleConfig::default()
+        };
+        interrupt::install();
+        let results = match (time_budget, resume) {
+            (Some(seconds), _) => tournament::run_timed(
+                &config,
+                &entrants,
+                rounds.unwrap_or(u32::MAX),
+                Duration::from_secs(*seconds),
+            )?,
+            (None, Some(previous)) => {
+                let previous = Crosstable::from_results_json(&fs::read_to_string(previous)?)?;
+                tournament::resume(&config, &entrants, rounds.unwrap_or(100), &previous)?
+            }
+            (None, None) => tournament::run(&config, &entrants, rounds.unwrap_or(100), *pairing)?,
+        };
+        match format.as_str() {
+            "json" => println!("{}", results.to_json()),
+            _ => println!("{}", results),
+        }
+        if results.interrupted {
+            return Err(Message::new("tournament-interrupted").to_string().into());
+        }
+        return Ok(());
+    }
+
+    if let Command::Bias {
+        warrior,
+        opponents,
+        rounds,
+        bins,
+        core_size,
+        max_cycles,
+        format,
+    } = &cli_options.command
+    {
+        if *rounds == 0 {
+            return Err(Message::new("positive-rounds").to_string().into());
+        }
+
+        let (input, file_name) = read_input(warrior)?;
+        let warrior = unwrap_parsed(parser::parse(&input), input, file_name)?;
+        let mut parsed = Vec::new();
+        for path in opponents {
+            let (input, file_name) = read_input(path)?;
+            parsed.push(unwrap_parsed(parser::parse(&input), input, file_name)?);
+        }
+
+        let config = BattleConfig {
+            core_size: *core_size,
+            max_cycles: *max_cycles,
+            ..BattleConfig::default()
+        };
+        let opponents: Vec<&Warrior> = parsed.iter().collect();
+        let bias =
+            tournament::PositionBias::measure(&config, &warrior, &opponents, *rounds, *bins)?;
+        match format.as_str() {
+            "csv" => print!("{}", bias.to_csv()),
+            "json" => println!("{}", bias.to_json()),
+            _ => println!("{}", bias),
+        }
+        return Ok(());
+    }
+
+    if let Command::Run {
+        spec: Some(spec_path),
+        archive,
+        ..
+    } = &cli_options.command
+    {
+        return run_spec(spec_path, archive.as_deref());
+    }
+
+    if let Command::Reveal { mapping, results } = &cli_options.command {
+        let mapping = blind::Mapping::from_json(&fs::read_to_string(mapping)?)?;
+        let (results, _) = read_input(results)?;
+        print!("{}", mapping.reveal(&results));
+        return Ok(());
+    }
+
+    if let Command::Package { command } = &cli_options.command {
+        return run_package(command);
+    }
+
+    if let Command::Pmars { args } = &cli_options.command {
+        return run_pmars(args, cli_options.verbose);
+    }
+
+    let input_file = cli_options
+        .input_file
+        .ok_or_else(|| Message::new("input-file-required").to_string())?;
+
+    let (input, file_name) = read_input(&input_file)?;
+    let source = parser::Source::resolve(&input, Path::new(&file_name))?;
+    let input = source.text.clone();
+
+    let parser = parser::Parser::new().modifiers(cli_options.modifiers);
+    let field_range = if cli_options.strict_fields {
+        FieldRange::Strict
     } else {
-        input = fs::read_to_string(cli_options.input_file)?;
+        FieldRange::Permissive
+    };
+    let parser = if cli_options.env {
+        parser.environment()
+    } else {
+        parser
+    };
+
+    if let Command::Preprocess { output_file } = &cli_options.command {
+        let preprocessed = unwrap_source(parser.preprocess(&input), &source)?;
+        write_output(output_file, &preprocessed)?;
+        return Ok(());
     }
 
-    let parsed_core = match parser::parse(input.as_str()) {
-        parser::Result::Ok(warrior, warnings) => {
-            print_warnings(&warnings);
-            Ok(warrior)
+    let layout = match &cli_options.command {
+        Command::Dump {
+            line_ending,
+            no_trailing_newline,
+            ..
+        } => TextLayout {
+            line_ending: *line_ending,
+            trailing_newline: !no_trailing_newline,
+        },
+        _ => TextLayout::default(),
+    };
+
+    if let Command::Dump {
+        output_file,
+        listing: true,
+        ..
+    } = &cli_options.command
+    {
+        let listing = unwrap_source(parser.listing(&input), &source)?;
+        write_text(output_file, &listing.to_string(), &layout)?;
+        return Ok(());
+    }
+
+    if let Command::Dump {
+        output_file,
+        format,
+        comments: true,
+        core_size,
+        no_metadata,
+        no_origin,
+        ..
+    } = &cli_options.command
+    {
+        if format != "loadfile" {
+            return Err(Message::new("comments-need-loadfile").to_string().into());
         }
-        parser::Result::Err(err, warnings) => {
-            print_warnings(&warnings);
-            Err(err)
+        if core_size.is_some() || *no_metadata || *no_origin {
+            return Err(Message::new("comments-conflict").to_string().into());
         }
-    }?;
+        let annotated = unwrap_source(parser.annotated(&input), &source)?;
+        write_text(output_file, &annotated, &layout)?;
+        return Ok(());
+    }
+
+    let (parsed_core, mut stats, warning_count) = if cli_options.load_file {
+        let parsed = parser::parse_load_file(&input);
+        let warning_count = parsed.warnings().len();
+        (
+            unwrap_source(parsed, &source)?,
+            PerfStats::new(),
+            warning_count,
+        )
+    } else if cli_options.link.is_empty() {
+        let (parsed, stats) = parser.parse_with_stats(&input);
+        let warning_count = parsed.warnings().len();
+        (unwrap_source(parsed, &source)?, stats, warning_count)
+    } else {
+        let mut sources = vec![(file_name.clone(), input)];
+        for path in &cli_options.link {
+            sources.push((path.display().to_string(), fs::read_to_string(path)?));
+        }
+        let modules: Vec<parser::Module> = sources
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .collect();
+        let parsed = parser.link(&modules);
+        let input = parser::combined_buffer(&modules);
+        let warning_count = parsed.warnings().len();
+        (
+            unwrap_parsed(parsed, input, file_name)?,
+            PerfStats::new(),
+            warning_count,
+        )
+    };
 
     match cli_options.command {
         Command::Dump {
             output_file,
             no_expand,
+            format,
+            core_size,
+            no_metadata,
+            no_origin,
+            ..
         } => {
             if no_expand {
                 unimplemented!()
             }
 
-            if output_file == *IO_SENTINEL {
-                println!("{}", parsed_core);
-            } else {
-                fs::write(output_file, format!("{}\n", parsed_core))?;
-            };
+            let mut warrior = parsed_core;
+            if let Some(core_size) = core_size {
+                if core_size == 0 {
+                    return Err(Message::new("positive-dump-core-size").to_string().into());
+                }
+                warrior.program = warrior.program.normalized(core_size);
+            }
+            if no_metadata {
+                warrior.metadata = Default::default();
+            }
+            if no_origin {
+                warrior.program.origin = None;
+            }
+
+            let format = formats.get(&format)?;
+            write_text(&output_file, &format.warrior(&warrior)?, &layout)?;
+        }
+        Command::Check { deny_warnings } => {
+            if deny_warnings && warning_count > 0 {
+                let message = match warning_count {
+                    1 => Message::new("denied-warning"),
+                    _ => Message::new("denied-warnings").arg("count", warning_count),
+                };
+                return Err(message.to_string().into());
+            }
         }
-        Command::Run { max_cycles } => {
+        Command::Run {
+            spec: _,
+            archive: _,
+            max_cycles,
+            timeline,
+            metrics,
+            coverage,
+            history,
+            dump,
+            tag,
+        } => {
             let mut core = Core::default();
+            for (start, len, name) in tag.iter().cloned() {
+                core.tag_region(start as i32, len, name);
+            }
+            core.set_field_range(field_range);
             core.load_warrior(&parsed_core)?;
+            for folded in core.folded_fields() {
+                print_warning(&folded.to_string());
+            }
+            if coverage {
+                core.enable_coverage();
+            }
+            if let Some(len) = history {
+                core.enable_history(len);
+            }
+
+            let mut recorder = timeline.map(OwnershipTimeline::new);
+            let mut metrics = metrics.map(MetricsTimeline::new);
+            let start = Instant::now();
+            run_core(&mut core, max_cycles, cli_options.verbose, |core| {
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(core);
+                }
+                if let Some(metrics) = metrics.as_mut() {
+                    metrics.record(core);
+                }
+            });
+            stats.phases.push(PhaseStats {
+                name: "simulate",
+                duration: start.elapsed(),
+                bytes: core.memory_bytes(),
+            });
+
+            if let Some(recorder) = recorder {
+                println!("{}", recorder);
+            }
+            if let Some(metrics) = metrics {
+                println!("{}", metrics);
+            }
+
+            if let Some(coverage) = core.coverage() {
+                println!("{}", coverage);
+            }
+            if let Some(history) = core.history() {
+                println!("{}", history);
+            }
+            if !dump.is_empty() {
+                print!("{}", core.dump_with(&dump));
+            }
+            if !tag.is_empty() {
+                for stats in core.tag_stats() {
+                    println!("{}", stats);
+                }
+            }
+        }
+        Command::Battle {
+            opponents,
+            positions,
+            core_size,
+            max_cycles,
+            backend,
+            format,
+            report,
+            corewar_io_replay,
+            fault,
+            fault_seed,
+            heatmap,
+            heatmap_width,
+            heatmap_style,
+            clears,
+        } => {
+            if heatmap_width == 0 {
+                return Err(Message::new("positive-heatmap-width").to_string().into());
+            }
+            let format = formats.get(&format)?;
+            let mut warriors = vec![parsed_core];
+            for opponent in &opponents {
+                let (input, file_name) = read_input(opponent)?;
+                warriors.push(unwrap_parsed(parser.parse(&input), input, file_name)?);
+            }
+
+            // Warriors with the same name are numbered, like tournament entrants
+            let names = distinct_names(&warriors);
+            let warriors: Vec<Warrior> = warriors
+                .into_iter()
+                .zip(&names)
+                .map(|(warrior, name)| match &warrior.metadata.name {
+                    Some(own) if own == name => warrior,
+                    _ => warrior.renamed(name.clone()),
+                })
+                .collect();
+
+            let config = BattleConfig {
+                core_size,
+                max_cycles,
+                backend,
+                field_range,
+                ..BattleConfig::default()
+            };
+
+            let schedule = match positions {
+                Some(path) => PositionSchedule::parse(&fs::read_to_string(path)?)?,
+                None => PositionSchedule::new(vec![(0..warriors.len() as u64)
+                    .map(|i| (i * u64::from(core_size) / warriors.len() as u64) as u32)
+                    .collect()]),
+            };
+
+            let faults = fault
+                .into_iter()
+                .fold(Faults::new(fault_seed), |faults, (cycle, fault)| {
+                    faults.at(cycle, fault)
+                });
+            let setup = |battle: &mut Battle| {
+                if !faults.is_empty() {
+                    battle.set_faults(faults.clone());
+                }
+                if clears {
+                    battle.core_mut().enable_clear_detection();
+                }
+            };
+            let mut round_clears = Vec::new();
+            let outcomes = schedule.run_inspected(&config, &warriors, setup, |battle| {
+                round_clears.push(battle.core().core_clears().to_vec());
+            })?;
+            match format.results(&names, &outcomes) {
+                Ok(results) => print!("{}", results),
+                Err(format::Error::Unsupported { .. }) => {
+                    for (round, outcome) in outcomes.iter().enumerate() {
+                        let message = Message::new("round-outcome")
+                            .arg("round", round + 1)
+                            .arg("outcome", format.outcome(outcome)?);
+                        println!("{}", message);
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
+            if clears {
+                println!();
+                for (round, clears) in round_clears.iter().enumerate() {
+                    let message = match clears.as_slice() {
+                        [] => Message::new("round-no-clear"),
+                        clears => {
+                            let clears: Vec<String> =
+                                clears.iter().map(ToString::to_string).collect();
+                            Message::new("round-clears").arg("clears", clears.join(", "))
+                        }
+                    };
+                    println!("{}", message.arg("round", round + 1));
+                }
+            }
+
+            // The first round again, to look into it
+            let first_positions = schedule.rounds().first().ok_or(ScheduleError::Empty)?;
+            let first_round = || -> Result<Battle, Box<dyn Error>> {
+                let mut battle = Battle::new(config.clone())?;
+                setup(&mut battle);
+                for (warrior, &position) in warriors.iter().zip(first_positions) {
+                    battle.load(warrior, position)?;
+                }
+                Ok(battle)
+            };
+
+            for folded in first_round()?.core().folded_fields() {
+                print_warning(&folded.to_string());
+            }
+
+            if heatmap.is_some() || report.is_some() || corewar_io_replay.is_some() {
+                // All show the first round, so it only runs once, and the
+                // heatmap is drawn while the report is rendered
+                let mut battle = first_round()?;
+                let replay = if report.is_some() || corewar_io_replay.is_some() {
+                    let (mut replay, outcome) = Replay::record_battle(&mut battle);
+                    if let Some(panic) = battle.observer_panic() {
+                        print_warning(&panic.to_string());
+                    }
+                    replay.digests = warriors.iter().map(Warrior::digest).collect();
+                    if let Some(path) = &corewar_io_replay {
+                        fs::write(path, corewar_io::replay_to_json(&replay, Some(&outcome)))?;
+                    }
+                    Some(replay).filter(|_| report.is_some())
+                } else {
+                    battle.run();
+                    None
+                };
+
+                let mut drawn = None;
+                let mut html = None;
+                let passes: Vec<AnalysisPass> = vec![
+                    Box::new(|core| {
+                        drawn = heatmap.map(|activity| {
+                            Heatmap::of(core, activity).render(heatmap_width, heatmap_style)
+                        });
+                    }),
+                    Box::new(|_| {
+                        html = replay.map(|replay| {
+                            let names: Vec<&str> = warriors
+                                .iter()
+                                .filter_map(|warrior| warrior.metadata.name.as_deref())
+                                .collect();
+                            let mut report = Report::new(&names.join(" vs "));
+                            for warrior in &warriors {
+                                report.add_warrior(warrior);
+                            }
+                            for outcome in outcomes {
+                                report.add_round(outcome);
+                            }
+                            report.set_replay(replay);
+                            report.to_html()
+                        });
+                    }),
+                ];
+                battle.into_snapshot().analyze(passes);
+
+                if let Some(drawn) = drawn {
+                    print!("\n{}", drawn);
+                }
+                if let (Some(path), Some(html)) = (report, html) {
+                    fs::write(path, html)?;
+                }
+            }
+        }
+        Command::Debug {
+            opponents,
+            core_size,
+            session,
+            script,
+        } => {
+            let mut warriors = vec![parsed_core];
+            for opponent in &opponents {
+                let (input, file_name) = read_input(opponent)?;
+                warriors.push(unwrap_parsed(parser.parse(&input), input, file_name)?);
+            }
 
-            match core.run(max_cycles) {
-                Ok(_) => println!(
-                    "Warrior stopped after {}max of {} cycles",
-                    if max_cycles.is_some() {
-                        "specified "
-                    } else {
-                        ""
-                    },
-                    core.steps_taken()
-                ),
-                Err(err) => println!("Warrior failed after {} steps: {}", core.steps_taken(), err),
+            let mut debugger = Debugger::new(core_size, &warriors)?;
+            if let Some(path) = session.as_ref().filter(|path| path.exists()) {
+                debugger.load_session(&fs::read_to_string(path)?)?;
             }
 
-            if cli_options.verbose {
-                println!("Core after execution:\n{}", core);
+            match script {
+                Some(path) => run_script(&mut debugger, &path)?,
+                None => run_debugger(&mut debugger)?,
             }
+
+            if let Some(path) = session {
+                fs::write(path, debugger.session())?;
+            }
+        }
+        Command::Compress => {
+            println!("{}", analysis::compression_report(&parsed_core.program));
+        }
+        Command::Stats => {
+            println!("{}", analysis::usage(&parsed_core.program));
+        }
+        Command::Lint {
+            core_size,
+            min_opponent_length,
+        } => {
+            let warnings =
+                analysis::step_warnings(&parsed_core.program, core_size, min_opponent_length);
+            for warning in warnings {
+                let message = Reporter::new().coded_message(
+                    Severity::Warning,
+                    analysis::StepWarning::CODE,
+                    &warning.to_string(),
+                );
+                eprintln!("{}", message);
+            }
+        }
+        Command::Grammar { .. } | Command::Explain { .. } | Command::Bench { .. } => {
+            unreachable!("handled before reading input")
+        }
+        Command::Preprocess { .. } => unreachable!("handled before parsing input"),
+        Command::Pmars { .. }
+        | Command::Index { .. }
+        | Command::Search { .. }
+        | Command::FetchCorpus { .. }
+        | Command::Tournament { .. }
+        | Command::Bias { .. }
+        | Command::Reveal { .. }
+        | Command::Hill { .. }
+        | Command::Package { .. } => {
+            unreachable!("handled before reading input")
         }
     };
 
+    if cli_options.timings {
+        eprintln!("{}", stats);
+    }
+
     Ok(())
 }
 
-fn print_warnings(warnings: &[parser::Warning]) {
-    for warning in warnings.iter() {
-        eprintln!("Warning: {}", warning)
+/// Run the tournament described by the spec at `path`, print the results,
+/// and archive them in `archive`.
+fn run_spec(path: &Path, archive: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let spec = spec::Spec::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut warriors = Vec::new();
+    for warrior_path in &spec.warriors {
+        let (input, file_name) = read_input(&base.join(warrior_path))?;
+        warriors.extend(unwrap_parsed(
+            parser::parse_archive(&input),
+            input,
+            file_name,
+        )?);
     }
+
+    let entrants = warriors
+        .iter()
+        .map(|warrior| Warrior {
+            program: warrior.program.clone(),
+            metadata: warrior.metadata.clone(),
+        })
+        .collect();
+    let results = spec.run(&entrants)?;
+    println!("{}", spec.summary(&results));
+
+    let archive = archive.map_or_else(
+        || {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            base.join(format!("{}-results", stem))
+        },
+        Path::to_path_buf,
+    );
+    spec::write_archive(&archive, &spec, &warriors, &results)?;
+    Ok(())
+}
+
+/// Run debugger commands from stdin until it ends or "quit" is entered.
+/// Errors in a command are reported without stopping the session.
+fn run_debugger(debugger: &mut Debugger) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut line = String::new();
+
+    loop {
+        eprint!("{}", Message::new("debug-prompt"));
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 || line.trim() == "quit" {
+            return Ok(());
+        }
+
+        match debugger.execute(&line) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(err) => eprintln!(
+                "{}",
+                Reporter::new().message(Severity::Error, &err.to_string())
+            ),
+        }
+    }
+}
+
+/// Run the debugger commands in the file at `path`, printing their output.
+fn run_script(debugger: &mut Debugger, path: &Path) -> Result<(), Box<dyn Error>> {
+    let script = fs::read_to_string(path)?;
+
+    for (i, line) in script.lines().enumerate() {
+        if line.trim() == "quit" {
+            break;
+        }
+
+        let output = debugger.execute(line).map_err(|err| {
+            Message::new("script-error")
+                .arg("path", path.display())
+                .arg("line", i + 1)
+                .arg("error", err)
+                .to_string()
+        })?;
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+    }
+
+    Ok(())
+}
+
+/// Time each instruction, starting after those completed in `resume`.
+/// Timings are printed as they are measured, so an interrupted run keeps them.
+fn run_bench(
+    core_size: u32,
+    rounds: usize,
+    resume: Option<&StateToken>,
+) -> Result<(), Box<dyn Error>> {
+    if core_size == 0 || rounds == 0 {
+        return Err(Message::new("positive-core-size-and-rounds")
+            .to_string()
+            .into());
+    }
+
+    let instructions = corewars_sim::benchmarked_instructions();
+    let skipped = match resume {
+        Some(token) if token.command != "bench" => {
+            let message = Message::new("bench-resume-mismatch").arg("state", token);
+            return Err(message.to_string().into());
+        }
+        Some(token) if token.completed > instructions.len() => {
+            let message = Message::new("bench-resume-past-end").arg("state", token);
+            return Err(message.to_string().into());
+        }
+        Some(token) => token.completed,
+        None => 0,
+    };
+
+    interrupt::install();
+
+    for (completed, instruction) in instructions.iter().enumerate().skip(skipped) {
+        if interrupt::requested() {
+            let token = StateToken {
+                command: "bench".into(),
+                completed,
+            };
+            let message = Message::new("bench-interrupted")
+                .arg("completed", completed)
+                .arg("total", instructions.len())
+                .arg("state", token);
+            return Err(message.to_string().into());
+        }
+
+        println!(
+            "{}",
+            corewars_sim::OpcodeTiming::measure(instruction, core_size, rounds)
+        );
+    }
+
+    Ok(())
+}
+
+/// Poll the hill's directory for new warriors every `interval`, printing
+/// what happened to each, until Ctrl-C is pressed. With `once`, only poll
+/// once.
+fn serve_hill(mut server: Server, interval: Duration, once: bool) -> Result<(), Box<dyn Error>> {
+    interrupt::install();
+
+    loop {
+        for event in server.poll()? {
+            println!("{}", event);
+        }
+
+        if once {
+            return Ok(());
+        }
+
+        thread::sleep(interval);
+        if interrupt::requested() {
+            return Ok(());
+        }
+    }
+}
+
+/// Read the input file, or stdin if it is "-". Returns the input and a name
+/// for it to use in error messages.
+fn read_input(input_file: &Path) -> io::Result<(String, String)> {
+    if input_file == IO_SENTINEL.as_path() {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Ok((input, String::from("<stdin>")))
+    } else {
+        let input_file = package::warrior_source(input_file).map_err(io::Error::other)?;
+        let mut input = fs::read_to_string(&input_file)?;
+        if input_file
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            // Other JSON, e.g. blind results, is read as it is
+            if let Ok(source) = corewar_io::warrior_source(&input) {
+                input = source;
+            }
+        }
+        Ok((input, input_file.display().to_string()))
+    }
+}
+
+fn run_package(command: &PackageCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        PackageCommand::New { path, name } => {
+            let name = match name {
+                Some(name) => name.clone(),
+                None => path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            Package::create(path, &name)?;
+            println!(
+                "{}",
+                Message::new("package-created")
+                    .arg("name", name)
+                    .arg("path", path.display())
+            );
+        }
+        PackageCommand::Check {
+            path,
+            update_baselines,
+        } => {
+            let mut package = Package::open(path)?;
+            let report = package.check()?;
+            for warning in &report.warnings {
+                print_warning(warning);
+            }
+            if !report.to_string().is_empty() {
+                println!("{}", report);
+            }
+
+            let passed = if *update_baselines {
+                package.write_baselines(&report.benchmarks)?;
+                println!(
+                    "{}",
+                    Message::new("package-baselines-updated").arg("count", report.benchmarks.len())
+                );
+                report.tests_passed()
+            } else {
+                report.passed()
+            };
+            if !passed {
+                return Err(Message::new("package-check-failed").to_string().into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run a core until it finishes or reaches `max_cycles`, and print the result.
+fn run_core<F: FnMut(&Core)>(
+    core: &mut Core,
+    max_cycles: Option<usize>,
+    verbose: bool,
+    observer: F,
+) {
+    let message = match core.run_observed(max_cycles, observer) {
+        Ok(_) if max_cycles.is_some() => Message::new("warrior-stopped-at-limit"),
+        Ok(_) => Message::new("warrior-stopped"),
+        Err(err) => Message::new("warrior-failed").arg("error", err),
+    };
+    println!(
+        "{}",
+        message
+            .arg("cycles", core.cycles())
+            .arg("steps", core.steps_taken())
+    );
+
+    if verbose {
+        for stats in core.warrior_stats() {
+            println!("{}", stats);
+        }
+        println!(
+            "{}",
+            Message::new("core-after-execution").arg("core", &*core)
+        );
+    }
+}
+
+/// Run a warrior with settings from pMARS-style arguments. Only the options
+/// supported by the simulator take effect; the rest are validated but ignored.
+fn run_pmars(args: &[String], verbose: bool) -> Result<(), Box<dyn Error>> {
+    let options = pmars::Options::from_args(args.iter().cloned())?;
+
+    let warrior_file = match options.warriors.as_slice() {
+        [warrior_file] => warrior_file,
+        [] => return Err(Message::new("warrior-file-required").to_string().into()),
+        _ => return Err(Message::new("one-warrior-supported").to_string().into()),
+    };
+
+    let (input, file_name) = read_input(warrior_file)?;
+    let warrior = unwrap_parsed(parser::parse(&input), input, file_name)?;
+
+    if warrior.len() > options.max_length {
+        let message = Message::new("warrior-too-long")
+            .arg("length", warrior.len())
+            .arg("max", options.max_length);
+        return Err(message.to_string().into());
+    }
+
+    let mut core = Core::new(options.core_size)?;
+    core.load_warrior(&warrior)?;
+    run_core(&mut core, Some(options.cycles as usize), verbose, |_| {});
+
+    Ok(())
+}
+
+/// Print any warnings from the parser, and convert its result into one that
+/// can be reported with the offending input.
+fn unwrap_parsed<T>(
+    result: parser::Result<T>,
+    input: String,
+    file_name: String,
+) -> Result<T, Box<dyn Error>> {
+    for warning in result.warnings() {
+        print_diagnostic(&parser::Diagnostic::from(warning), &input, &file_name);
+    }
+
+    match result {
+        parser::Result::Ok(value, _) => Ok(value),
+        parser::Result::Err(error, _) => Err(Box::new(ParseError {
+            error,
+            input,
+            file_name,
+        })),
+    }
+}
+
+/// Like [`unwrap_parsed`](unwrap_parsed), but reporting errors and warnings
+/// against the file they came from if `source` included other files.
+fn unwrap_source<T>(
+    result: parser::Result<T>,
+    source: &parser::Source,
+) -> Result<T, Box<dyn Error>> {
+    for warning in result.warnings() {
+        let mut diagnostic = parser::Diagnostic::from(warning);
+        let line = diagnostic.span.map_or(0, |span| span.line);
+        let (line, text, file_name) = source_location(source, line);
+
+        if let Some(span) = diagnostic.span.as_mut() {
+            span.line = line;
+        }
+        print_diagnostic(&diagnostic, text, &file_name);
+    }
+
+    match result {
+        parser::Result::Ok(value, _) => Ok(value),
+        parser::Result::Err(error, _) => {
+            let line = error.span().map_or(0, |span| span.line);
+            let (line, text, file_name) = source_location(source, line);
+
+            Err(Box::new(ParseError {
+                error: error.relocated(line),
+                input: text.to_string(),
+                file_name,
+            }))
+        }
+    }
+}
+
+/// Where `line` of `source` came from: the line number within its file, and
+/// the text and name of that file.
+fn source_location(source: &parser::Source, line: usize) -> (usize, &str, String) {
+    match source.source_map.locate(line) {
+        Some(location) => (
+            location.line,
+            location.text,
+            location.path.display().to_string(),
+        ),
+        None => {
+            let file_name = source.source_map.files().next().unwrap_or(Path::new(""));
+            (line, &source.text, file_name.display().to_string())
+        }
+    }
+}
+
+/// Write `text` to `output_file`, or to stdout if it is "-".
+fn write_output(output_file: &Path, text: &str) -> io::Result<()> {
+    if output_file == IO_SENTINEL.as_path() {
+        println!("{}", text);
+        Ok(())
+    } else {
+        fs::write(output_file, format!("{}\n", text))
+    }
+}
+
+/// Write `text` like [`write_output`], with its lines ended as in `layout`.
+fn write_text(output_file: &Path, text: &str, layout: &TextLayout) -> io::Result<()> {
+    let text = layout.apply(text);
+    if output_file == IO_SENTINEL.as_path() {
+        print!("{}", text);
+        Ok(())
+    } else {
+        fs::write(output_file, text)
+    }
+}
+
+/// Parse a fault to inject like `kill=Imp@100`, as the cycle to inject it at
+/// and the fault.
+fn parse_fault(text: &str) -> Result<(usize, Fault), String> {
+    let invalid = || {
+        Message::new("invalid-fault")
+            .arg("fault", format!("{:?}", text))
+            .to_string()
+    };
+
+    let (fault, cycle) = text.rsplit_once('@').ok_or_else(invalid)?;
+    let cycle = cycle.parse().map_err(|_| invalid())?;
+    Ok((cycle, fault.parse()?))
+}
+
+/// Parse a tagged part of a warrior like `0-3=stone` or `4=imp`, as the
+/// start, length and name of the tag.
+fn parse_tag(text: &str) -> Result<(u32, u32, String), String> {
+    let invalid = || {
+        Message::new("invalid-tag")
+            .arg("tag", format!("{:?}", text))
+            .to_string()
+    };
+
+    let mut parts = text.splitn(2, '=');
+    let range = parts.next().unwrap_or_default();
+    let name = parts
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(invalid)?;
+
+    let mut bounds = range
+        .splitn(2, '-')
+        .map(|bound| bound.trim().parse::<u32>());
+    let start = bounds.next().and_then(Result::ok).ok_or_else(invalid)?;
+    let end = match bounds.next() {
+        Some(end) => end.map_err(|_| invalid())?,
+        None => start,
+    };
+
+    if end < start {
+        return Err(Message::new("tag-ends-before-start")
+            .arg("tag", format!("{:?}", name))
+            .to_string());
+    }
+
+    Ok((start, end - start + 1, name.to_string()))
+}
+
+/// Parse an instruction name like `MOV.AB` or `mov`, case-insensitively.
+fn parse_opcode_and_modifier(name: &str) -> Result<(Opcode, Modifier), String> {
+    let name = name.to_uppercase();
+    let mut parts = name.splitn(2, '.');
+
+    let opcode: Opcode = parts.next().unwrap_or_default().parse()?;
+    let modifier = match parts.next() {
+        Some(modifier) => modifier.parse()?,
+        None => Modifier::default_88_to_94(opcode, AddressMode::Direct, AddressMode::Direct),
+    };
+
+    Ok((opcode, modifier))
+}
+
+/// Print an error returned by [`run`](run) to stderr.
+pub fn report_error(err: &(dyn Error + 'static)) {
+    let reporter = Reporter::new();
+
+    if let Some(parse_error) = err.downcast_ref::<ParseError>() {
+        eprintln!(
+            "{}",
+            reporter.parse_error(
+                &parse_error.error,
+                &parse_error.input,
+                &parse_error.file_name
+            )
+        );
+    } else {
+        eprintln!("{}", reporter.message(Severity::Error, &err.to_string()));
+    }
+}
+
+fn print_warning(message: &str) {
+    eprintln!("{}", Reporter::new().message(Severity::Warning, message));
+}
+
+/// Whether `text` looks like a diagnostic code, e.g. `E0001` or `w0002`,
+/// rather than an instruction.
+fn is_diagnostic_code(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some('E' | 'e' | 'W' | 'w'))
+        && text.len() == 5
+        && chars.all(|c| c.is_ascii_digit())
+}
+
+fn print_diagnostic(diagnostic: &parser::Diagnostic, input: &str, file_name: &str) {
+    eprintln!(
+        "{}",
+        Reporter::new().diagnostic(diagnostic, input, file_name)
+    );
 }
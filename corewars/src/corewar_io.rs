@@ -0,0 +1,384 @@
+//! Converting warriors and replays to and from the JSON used by the
+//! [corewar.io](https://corewar.io) web app and its `corewar` library.
+//!
+//! Warriors are parse results, with the warrior's metadata and its load
+//! file:
+//!
+//! ```text
+//! {"messages":[],"metaData":{"author":"A. K. Dewdney","name":"Imp","strategy":""},"warrior":";name Imp\n..."}
+//! ```
+//!
+//! Warriors saved by the app with their `source` instead are also read.
+//!
+//! Battles are the events the library emits as it runs one: a `CORE_ACCESS`
+//! event for each instruction a warrior executes or writes, and a
+//! `ROUND_END` event with the result. To replay writes, this crate adds the
+//! written `instruction` to each write; writes without one, as the app
+//! records them, are read as the default `DAT`, so the replay shows where
+//! each warrior wrote but not what.
+//!
+//! ```text
+//! {
+//!   "coreSize": 8000,
+//!   "warriors": [{"warriorId": 0, "name": "Imp"}],
+//!   "initial": [{"address": 0, "instruction": "MOV.I $0, $1"}],
+//!   "events": [
+//!     {"type": "CORE_ACCESS", "cycle": 0, "warriorId": 0, "accessType": 2, "address": 0},
+//!     {"type": "CORE_ACCESS", "cycle": 0, "warriorId": 0, "accessType": 1, "address": 1, "instruction": "MOV.I $0, $1"}
+//!   ],
+//!   "result": {"type": "ROUND_END", "winnerId": null, "outcome": "DRAW"}
+//! }
+//! ```
+
+use serde_json::{json, Value};
+use thiserror::Error as ThisError;
+
+use corewars_core::load_file::Instruction;
+use corewars_core::Warrior;
+use corewars_sim::Outcome;
+
+use crate::format::{self, OutputFormat};
+use crate::replay::{Frame, Replay};
+
+/// The access types of `CORE_ACCESS` events, other than 0 for reads.
+const WRITE: u64 = 1;
+const EXECUTE: u64 = 2;
+
+/// An error reading corewar.io JSON.
+#[derive(ThisError, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("expected {0}")]
+    Missing(&'static str),
+
+    #[error("invalid instruction {text:?}: {reason}")]
+    InvalidInstruction { text: String, reason: String },
+
+    #[error("unknown warrior ID {0}")]
+    UnknownWarrior(u64),
+}
+
+/// The corewar.io parse result of `warrior`.
+pub fn warrior_to_json(warrior: &Warrior) -> String {
+    let metadata = &warrior.metadata;
+    json!({
+        "metaData": {
+            "name": metadata.name.as_deref().unwrap_or_default(),
+            "author": metadata.author.as_deref().unwrap_or_default(),
+            "strategy": metadata.strategy.as_deref().unwrap_or_default(),
+        },
+        "messages": [],
+        "warrior": warrior.to_string(),
+    })
+    .to_string()
+}
+
+/// The Redcode of a corewar.io warrior, to parse like any other.
+pub fn warrior_source(json: &str) -> Result<String, Error> {
+    let value: Value = serde_json::from_str(json)?;
+    value
+        .get("source")
+        .or_else(|| value.get("warrior"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or(Error::Missing("a \"source\" or \"warrior\" string"))
+}
+
+/// `replay` as the events of a corewar.io battle, ending with its `outcome`
+/// if known.
+pub fn replay_to_json(replay: &Replay, outcome: Option<&Outcome>) -> String {
+    let warriors: Vec<Value> = replay
+        .warriors
+        .iter()
+        .enumerate()
+        .map(|(id, name)| json!({ "warriorId": id, "name": name }))
+        .collect();
+    let initial: Vec<Value> = replay
+        .initial
+        .iter()
+        .map(|(address, instruction)| {
+            json!({ "address": address, "instruction": instruction.to_string() })
+        })
+        .collect();
+
+    let mut events = Vec::new();
+    for frame in &replay.frames {
+        events.push(json!({
+            "type": "CORE_ACCESS",
+            "cycle": frame.cycle,
+            "warriorId": frame.warrior,
+            "accessType": EXECUTE,
+            "address": frame.address,
+        }));
+        for (address, instruction) in &frame.writes {
+            events.push(json!({
+                "type": "CORE_ACCESS",
+                "cycle": frame.cycle,
+                "warriorId": frame.warrior,
+                "accessType": WRITE,
+                "address": address,
+                "instruction": instruction.to_string(),
+            }));
+        }
+    }
+
+    let result = outcome.map(|outcome| {
+        let (winner, outcome) = match outcome {
+            Outcome::Win(name) => (replay.warriors.iter().position(|w| w == name), "WIN"),
+            Outcome::Tie(names) if names.is_empty() => (None, "NONE"),
+            Outcome::Tie(_) => (None, "DRAW"),
+        };
+        json!({ "type": "ROUND_END", "winnerId": winner, "outcome": outcome })
+    });
+
+    json!({
+        "coreSize": replay.core_size,
+        "warriors": warriors,
+        "initial": initial,
+        "events": events,
+        "result": result,
+    })
+    .to_string()
+}
+
+/// Read a battle's events as a replay, with the outcome of the round if
+/// they end with one. Reads are ignored, since replays only record
+/// executions and writes.
+pub fn replay_from_json(json: &str) -> Result<(Replay, Option<Outcome>), Error> {
+    let value: Value = serde_json::from_str(json)?;
+
+    let core_size = value
+        .get("coreSize")
+        .and_then(Value::as_u64)
+        .ok_or(Error::Missing("a \"coreSize\" number"))? as u32;
+
+    let mut warriors = Vec::new();
+    for warrior in array(&value, "warriors")? {
+        let id = integer(warrior, "warriorId")?;
+        if id as usize != warriors.len() {
+            return Err(Error::UnknownWarrior(id));
+        }
+        let name = warrior
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or(Error::Missing("a \"name\" for each warrior"))?;
+        warriors.push(name.to_string());
+    }
+
+    let mut initial = Vec::new();
+    for cell in value
+        .get("initial")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        initial.push((integer(cell, "address")? as u32, instruction(cell)?));
+    }
+
+    let mut frames: Vec<Frame> = Vec::new();
+    for event in array(&value, "events")? {
+        if event.get("type").and_then(Value::as_str) != Some("CORE_ACCESS") {
+            continue;
+        }
+        let warrior = integer(event, "warriorId")?;
+        if warrior as usize >= warriors.len() {
+            return Err(Error::UnknownWarrior(warrior));
+        }
+        let address = integer(event, "address")? as u32 % core_size.max(1);
+
+        match integer(event, "accessType")? {
+            EXECUTE => frames.push(Frame {
+                cycle: match event.get("cycle").and_then(Value::as_u64) {
+                    Some(cycle) => cycle as usize,
+                    None => frames.len(),
+                },
+                warrior: warrior as usize,
+                address,
+                writes: Vec::new(),
+            }),
+            WRITE => {
+                let instruction = match event.get("instruction") {
+                    Some(_) => instruction(event)?,
+                    None => Instruction::default(),
+                };
+                frames
+                    .last_mut()
+                    .ok_or(Error::Missing("an execution before each write"))?
+                    .writes
+                    .push((address, instruction));
+            }
+            _ => {}
+        }
+    }
+
+    let outcome = match value.get("result").filter(|result| !result.is_null()) {
+        Some(result) => Some(
+            match (
+                result.get("outcome").and_then(Value::as_str),
+                result.get("winnerId").and_then(Value::as_u64),
+            ) {
+                (Some("WIN"), Some(id)) => Outcome::Win(
+                    warriors
+                        .get(id as usize)
+                        .cloned()
+                        .ok_or(Error::UnknownWarrior(id))?,
+                ),
+                (Some("DRAW"), _) => Outcome::Tie(warriors.clone()),
+                (Some("NONE"), _) => Outcome::Tie(Vec::new()),
+                _ => return Err(Error::Missing("a \"WIN\", \"DRAW\" or \"NONE\" outcome")),
+            },
+        ),
+        None => None,
+    };
+
+    let replay = Replay {
+        core_size,
+        warriors,
+        digests: Vec::new(),
+        initial,
+        frames,
+    };
+    Ok((replay, outcome))
+}
+
+/// The `corewar-io` output format, for warriors.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CorewarIo;
+
+impl OutputFormat for CorewarIo {
+    fn name(&self) -> &'static str {
+        "corewar-io"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn warrior(&self, warrior: &Warrior) -> Result<String, format::Error> {
+        Ok(warrior_to_json(warrior))
+    }
+}
+
+fn array<'a>(value: &'a Value, key: &'static str) -> Result<&'a Vec<Value>, Error> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .ok_or(Error::Missing(key))
+}
+
+fn integer(value: &Value, key: &'static str) -> Result<u64, Error> {
+    value
+        .get(key)
+        .and_then(Value::as_u64)
+        .ok_or(Error::Missing(key))
+}
+
+fn instruction(value: &Value) -> Result<Instruction, Error> {
+    let text = value
+        .get("instruction")
+        .and_then(Value::as_str)
+        .ok_or(Error::Missing("instruction"))?;
+    let invalid = |reason: String| Error::InvalidInstruction {
+        text: text.to_string(),
+        reason,
+    };
+
+    match corewars_parser::parse_load_file(text) {
+        corewars_parser::Result::Ok(warrior, _) => warrior
+            .program
+            .instructions
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid("no instruction".into())),
+        corewars_parser::Result::Err(err, _) => Err(invalid(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use corewars_sim::{Battle, BattleConfig};
+
+    #[test]
+    fn converts_warriors() {
+        let warrior = corewars_parser::parse(
+            ";name Dwarf\n;author A. K. Dewdney\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0",
+        )
+        .unwrap();
+        let json = warrior_to_json(&warrior);
+
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["metaData"],
+            json!({ "name": "Dwarf", "author": "A. K. Dewdney", "strategy": "" })
+        );
+
+        let source = warrior_source(&json).unwrap();
+        let read = corewars_parser::parse(&source).unwrap();
+        assert_eq!(read.program, warrior.program);
+        assert_eq!(read.metadata.name, warrior.metadata.name);
+
+        assert_eq!(
+            warrior_source(r#"{"source": "mov 0, 1"}"#).unwrap(),
+            "mov 0, 1"
+        );
+        assert!(matches!(warrior_source("{}"), Err(Error::Missing(_))));
+    }
+
+    #[test]
+    fn converts_replays() {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 100,
+            max_cycles: 20,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        let warrior =
+            corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+                .unwrap();
+        battle.core_mut().load_warrior(&warrior).unwrap();
+        let (replay, outcome) = Replay::record_battle(&mut battle);
+
+        let json = replay_to_json(&replay, Some(&outcome));
+        let (read, read_outcome) = replay_from_json(&json).unwrap();
+        assert_eq!(read, replay);
+        assert_eq!(read_outcome, Some(Outcome::Tie(vec!["Dwarf".into()])));
+
+        // Without instructions, writes are read as DAT
+        let events = r#"{
+            "coreSize": 10,
+            "warriors": [{"warriorId": 0, "name": "Imp"}],
+            "events": [
+                {"type": "CORE_ACCESS", "warriorId": 0, "accessType": 0, "address": 0},
+                {"type": "CORE_ACCESS", "warriorId": 0, "accessType": 2, "address": 0},
+                {"type": "CORE_ACCESS", "warriorId": 0, "accessType": 1, "address": 11},
+                {"type": "TASK_COUNT", "warriorId": 0, "taskCount": 1}
+            ],
+            "result": {"type": "ROUND_END", "winnerId": 0, "outcome": "WIN"}
+        }"#;
+        let (read, outcome) = replay_from_json(events).unwrap();
+        assert_eq!(
+            read.frames,
+            vec![Frame {
+                cycle: 0,
+                warrior: 0,
+                address: 0,
+                writes: vec![(1, Instruction::default())],
+            }]
+        );
+        assert_eq!(outcome, Some(Outcome::Win("Imp".into())));
+
+        assert!(matches!(
+            replay_from_json(&events.replace(
+                r#""warriorId": 0, "accessType": 2"#,
+                r#""warriorId": 3, "accessType": 2"#
+            )),
+            Err(Error::UnknownWarrior(3))
+        ));
+    }
+}
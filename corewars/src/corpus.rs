@@ -0,0 +1,303 @@
+//! Well-known sets of warriors, which can be downloaded into a local cache to
+//! benchmark and test against without hunting them down by hand.
+//!
+//! Each file has a known SHA-1 checksum, and is only saved once its contents
+//! are verified. Files already in the cache with the right checksum are not
+//! downloaded again.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha1::{Digest, Sha1};
+use thiserror::Error as ThisError;
+
+/// Where corpus files are downloaded from.
+pub const BASE_URL: &str =
+    "https://raw.githubusercontent.com/corewa-rs/corewars/main/testdata/input";
+
+/// An error fetching a corpus.
+#[derive(ThisError, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("unknown corpus {0:?}, expected one of: {}", names().join(", "))]
+    UnknownCorpus(String),
+
+    #[error("could not download {url}: {source}")]
+    Download { url: String, source: io::Error },
+
+    #[error("{url} has checksum {actual}, expected {expected}")]
+    ChecksumMismatch {
+        url: String,
+        expected: &'static str,
+        actual: String,
+    },
+
+    #[error("could not write to the cache: {0}")]
+    Cache(#[from] io::Error),
+}
+
+/// A single file in a corpus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CorpusFile {
+    /// The path of the file, relative to [`BASE_URL`]
+    pub path: &'static str,
+
+    /// The hex-encoded SHA-1 of the file's contents
+    pub sha1: &'static str,
+}
+
+impl CorpusFile {
+    /// The name the file is saved as in the cache.
+    pub fn file_name(&self) -> &'static str {
+        self.path.rsplit('/').next().unwrap_or(self.path)
+    }
+
+    pub fn url(&self) -> String {
+        format!("{}/{}", BASE_URL, self.path)
+    }
+}
+
+/// A named set of warriors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Corpus {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub files: &'static [CorpusFile],
+}
+
+macro_rules! files {
+    ($($path:literal => $sha1:literal,)*) => {
+        &[$(CorpusFile { path: $path, sha1: $sha1 },)*]
+    };
+}
+
+/// All the known corpora.
+pub const CORPORA: &[Corpus] = &[
+    Corpus {
+        name: "wilkie",
+        description: "Wilkie's benchmark, from KOTH",
+        files: files! {
+            "wilkie/bluefunk.redcode" => "cd91fa068e17de5ad33dba8114a193f16086bcac",
+            "wilkie/cannon.redcode" => "66fd9846c2c5be088692450e8916742affff76c0",
+            "wilkie/fstorm.redcode" => "53a0118996a1bd987c1b2e13e761e6a47dafa3ce",
+            "wilkie/irongate.redcode" => "1a9ba75fc7719721f22db2736daeef0c909c0f8a",
+            "wilkie/marcia13.redcode" => "db4f1d5992f68a69a60e7ceb1c23ce493bdac932",
+            "wilkie/nobody.redcode" => "16a8fee3256a66983928a91b44fa123789e105f8",
+            "wilkie/paperone.redcode" => "b702331d2fc6fad211b5aae8f1ecb67b95ba59a0",
+            "wilkie/rave.redcode" => "21d43764555b13314e657fd868e905a8bf974267",
+            "wilkie/tornado.redcode" => "750a39519f748e30ab2cc5178632b2bbc62b05f5",
+            "unimplemented/wilkie/pswing.redcode" => "2b50541f8db8919c46776fb8d8a4a0a066b8b418",
+            "unimplemented/wilkie/thermite.redcode" => "e33584884c828a5711d77fb303fbda0769b70a87",
+            "unimplemented/wilkie/time.redcode" => "ae75414648edadcfc19123ce176834dc73e0b2f5",
+        },
+    },
+    Corpus {
+        name: "wilmoo",
+        description: "The WilMoo benchmark, from KOTH",
+        files: files! {
+            "wilmoo/Blur2.redcode" => "a272ab61e986bdb7ef77285d1006f9c13562fa42",
+            "wilmoo/HeScansAlone.redcode" => "269e2715715c46343abfdaedbd2cd6a6dec4a5ba",
+            "wilmoo/ScanMan.redcode" => "3cf8f87c282ad23a3cb3ce6d2bf6b4cdd023e1c8",
+            "wilmoo/Torcht18.redcode" => "ed6bb412dd49404563f164b2b0d16006643db2aa",
+            "unimplemented/wilmoo/Benj_sRevenge.redcode" => "a85c3e1d5a4d59c49a1873dfcffd8287c3cfe21c",
+            "unimplemented/wilmoo/ElectricHead.redcode" => "f29446e6b26a2462144c4a3be958e06d82f37649",
+            "unimplemented/wilmoo/Impfinityv4g1.redcode" => "2a0b700d12a52f90a8abfafe7221a5b49e9b2551",
+            "unimplemented/wilmoo/Jackinthebox.redcode" => "31afdb486569858dc183882ced3ad019570bf470",
+            "unimplemented/wilmoo/Newt.redcode" => "a06dfbcc110d76d186bea389b0b190bcbffd9937",
+            "unimplemented/wilmoo/SteppingStone.redcode" => "dc3239c1bd4fb81057af5be4aea0318edbac877a",
+            "unimplemented/wilmoo/TheFugitive.redcode" => "b738f9d3a3722bc33e30ddcf8718b60540ba807e",
+            "unimplemented/wilmoo/unrequitedlove.redcode" => "b184345e81505c9f1c19940b21f8a447c5597ecd",
+        },
+    },
+    Corpus {
+        name: "icws",
+        description: "Example warriors from the ICWS'94 draft standard",
+        files: files! {
+            "simple/dwarf.redcode" => "34fbfae5720ccc066a8489d7496beb4eda55a66a",
+            "simple/validate.redcode" => "a54ceed624212feb9653f6f99f66ab71f35c2046",
+        },
+    },
+];
+
+/// The names of all the known corpora.
+pub fn names() -> Vec<&'static str> {
+    CORPORA.iter().map(|corpus| corpus.name).collect()
+}
+
+pub fn find(name: &str) -> Result<&'static Corpus, Error> {
+    CORPORA
+        .iter()
+        .find(|corpus| corpus.name == name)
+        .ok_or_else(|| Error::UnknownCorpus(name.to_string()))
+}
+
+/// Something which downloads the contents of a URL.
+pub trait Fetcher {
+    fn fetch(&self, url: &str) -> io::Result<Vec<u8>>;
+}
+
+/// Downloads using the `curl` command, which is available on most systems.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Curl;
+
+impl Fetcher for Curl {
+    fn fetch(&self, url: &str) -> io::Result<Vec<u8>> {
+        let output = Command::new("curl")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--location")
+            .arg(url)
+            .output()?;
+
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            let message = String::from_utf8_lossy(&output.stderr);
+            Err(io::Error::other(message.trim().to_string()))
+        }
+    }
+}
+
+/// The default directory corpora are cached in: `$XDG_CACHE_HOME/corewars`,
+/// or `~/.cache/corewars` if that is not set.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+
+    Some(cache_home.join("corewars"))
+}
+
+/// How many files of a corpus were downloaded, or were already cached.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FetchSummary {
+    pub downloaded: usize,
+    pub cached: usize,
+}
+
+impl Corpus {
+    /// The directory this corpus is saved to within `cache_dir`.
+    pub fn directory(&self, cache_dir: &Path) -> PathBuf {
+        cache_dir.join(self.name)
+    }
+
+    /// Download any files of this corpus which aren't already in
+    /// `cache_dir`, verifying their checksums before saving them.
+    pub fn fetch(&self, cache_dir: &Path, fetcher: &dyn Fetcher) -> Result<FetchSummary, Error> {
+        let directory = self.directory(cache_dir);
+        fs::create_dir_all(&directory)?;
+
+        let mut summary = FetchSummary::default();
+
+        for file in self.files {
+            let path = directory.join(file.file_name());
+            if fs::read(&path).is_ok_and(|contents| sha1_hex(&contents) == file.sha1) {
+                summary.cached += 1;
+                continue;
+            }
+
+            let url = file.url();
+            let contents = fetcher.fetch(&url).map_err(|source| Error::Download {
+                url: url.clone(),
+                source,
+            })?;
+
+            let actual = sha1_hex(&contents);
+            if actual != file.sha1 {
+                return Err(Error::ChecksumMismatch {
+                    url,
+                    expected: file.sha1,
+                    actual,
+                });
+            }
+
+            fs::write(&path, contents)?;
+            summary.downloaded += 1;
+        }
+
+        Ok(summary)
+    }
+}
+
+fn sha1_hex(contents: &[u8]) -> String {
+    Sha1::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Serves files from the copies in the repository's test data.
+    #[derive(Default)]
+    struct Local {
+        fetched: Cell<usize>,
+    }
+
+    impl Fetcher for Local {
+        fn fetch(&self, url: &str) -> io::Result<Vec<u8>> {
+            self.fetched.set(self.fetched.get() + 1);
+            let path = url.strip_prefix(BASE_URL).unwrap();
+            fs::read(format!(
+                "{}/../testdata/input{}",
+                env!("CARGO_MANIFEST_DIR"),
+                path
+            ))
+        }
+    }
+
+    #[test]
+    fn fetches_and_caches_corpora() {
+        let cache = assert_fs::TempDir::new().unwrap();
+        let fetcher = Local::default();
+
+        for corpus in CORPORA {
+            let summary = corpus.fetch(cache.path(), &fetcher).unwrap();
+            assert_eq!(summary.downloaded, corpus.files.len());
+        }
+
+        let wilkie = find("wilkie").unwrap();
+        assert!(wilkie
+            .directory(cache.path())
+            .join("irongate.redcode")
+            .exists());
+
+        // Nothing is downloaded again once it's cached
+        let fetched = fetcher.fetched.get();
+        let summary = wilkie.fetch(cache.path(), &fetcher).unwrap();
+        assert_eq!(summary.cached, wilkie.files.len());
+        assert_eq!(fetcher.fetched.get(), fetched);
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        struct Tampered;
+
+        impl Fetcher for Tampered {
+            fn fetch(&self, _url: &str) -> io::Result<Vec<u8>> {
+                Ok(b"dat #0, #0".to_vec())
+            }
+        }
+
+        let cache = assert_fs::TempDir::new().unwrap();
+        let icws = find("icws").unwrap();
+
+        assert!(matches!(
+            icws.fetch(cache.path(), &Tampered),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(!icws.directory(cache.path()).join("dwarf.redcode").exists());
+        assert!(matches!(find("koth"), Err(Error::UnknownCorpus(_))));
+    }
+}
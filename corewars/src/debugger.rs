@@ -0,0 +1,1070 @@
+//! A line-oriented debugger for stepping through battles and inspecting the
+//! core.
+//!
+//! Addresses can be given either absolutely (`4021`) or relative to the
+//! load point of a warrior (`w1+5`, `w2-3`), where warriors are numbered from
+//! 1 in the order they were loaded. Printed addresses use the current view,
+//! set with `view abs` or `view w1`, so code relocated anywhere in the core
+//! reads the same as its source.
+//!
+//! Addresses can also be named with `label`, after which the name can be
+//! used wherever an address is expected, and is shown next to the address in
+//! printed output. Labels can be saved to and loaded from a session file,
+//! with one `address name` pair per line, so they carry over between runs.
+//!
+//! Breakpoints stop `continue` before the instruction at an address is
+//! executed, and may have a condition which must also be true, e.g.
+//! `break 100 if core[100].opcode == DAT && cycle > 5000`. Conditions use
+//! the same expressions as Redcode, with these names available:
+//!
+//! ```text
+//! cycle                   the number of cycles executed so far
+//! pc                      the address of the next instruction to execute
+//! warrior                 the number of the warrior which executes next
+//! processes               the number of processes, or warrior[n].processes
+//! core[address].opcode    also .modifier, .a and .b for the field values
+//! DAT, MOV, ..., A, AB, ... opcodes and modifiers, to compare with the above
+//! ```
+//!
+//! Labels can be used in conditions too, for their absolute address.
+//!
+//! Commands:
+//!
+//! ```text
+//! step [count]            execute one or more instructions
+//! print <address> [count] print instructions starting at an address
+//! view abs|w<n>           show addresses absolutely or relative to a warrior
+//! warriors                list the warriors and where they were loaded
+//! label <address> <name>  name an address
+//! unlabel <name>          remove a name
+//! labels                  list the named addresses
+//! break [address] [if condition]  stop when a condition is met
+//! delete <number>         remove a breakpoint
+//! breakpoints             list the breakpoints
+//! continue [max steps]    run until a breakpoint is hit
+//! contact [max steps]     run until a warrior first reads or writes another's code
+//! backtrace [count]       print the last instructions executed, newest first
+//! effects <address> [instruction]  preview what executing the instruction at
+//!                         an address, or the one given, would read and write
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error as ThisError;
+
+use corewars_core::load_file::{Modifier, Opcode};
+use corewars_core::text;
+use corewars_core::Warrior;
+use corewars_parser::Accessor;
+use corewars_sim::{Core, Event};
+
+/// The most instructions `continue` executes when no limit is given.
+const DEFAULT_CONTINUE_STEPS: usize = 80_000;
+
+/// How many of the last instructions executed are kept for `backtrace`.
+const HISTORY_LENGTH: usize = 1000;
+
+/// How many instructions `backtrace` prints when no count is given.
+const DEFAULT_BACKTRACE_LENGTH: usize = 10;
+
+/// An error running a debugger command.
+#[derive(ThisError, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("unknown command {0:?}")]
+    UnknownCommand(String),
+
+    #[error("invalid address {0:?}, expected e.g. 4021 or w1+5")]
+    InvalidAddress(String),
+
+    #[error("invalid argument {0:?}")]
+    InvalidArgument(String),
+
+    #[error("missing {0}")]
+    MissingArgument(&'static str),
+
+    #[error("no warrior w{0}")]
+    UnknownWarrior(usize),
+
+    #[error("no label {0:?}")]
+    UnknownLabel(String),
+
+    #[error("invalid label {0:?}, labels must start with a letter or underscore")]
+    InvalidLabel(String),
+
+    #[error("invalid session on line {line}: {text:?}")]
+    InvalidSession { line: usize, text: String },
+
+    #[error("invalid condition {condition:?}: {error}")]
+    InvalidCondition {
+        condition: String,
+        error: corewars_parser::Error,
+    },
+
+    #[error("invalid instruction {instruction:?}: {error}")]
+    InvalidInstruction {
+        instruction: String,
+        error: corewars_parser::Error,
+    },
+
+    #[error("no breakpoint {0}")]
+    UnknownBreakpoint(usize),
+
+    #[error("could not load warrior: {0}")]
+    Load(String),
+}
+
+/// An address as written by the user, either absolute, relative to the load
+/// point of a warrior, or a label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Absolute(i64),
+
+    /// An offset from the load point of a warrior, numbered from 1
+    Relative {
+        warrior: usize,
+        offset: i64,
+    },
+
+    Label(String),
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidAddress(text.to_string());
+        let text = text.trim();
+
+        if let Ok(address) = text.parse() {
+            return Ok(Self::Absolute(address));
+        }
+
+        let relative = match text.strip_prefix('w') {
+            Some(relative) if relative.starts_with(|c: char| c.is_ascii_digit()) => relative,
+            _ if is_label(text) => return Ok(Self::Label(text.to_string())),
+            _ => return Err(invalid()),
+        };
+
+        let split = relative.find(['+', '-']).unwrap_or(relative.len());
+        let warrior = relative[..split].parse().map_err(|_| invalid())?;
+        let offset = match &relative[split..] {
+            "" => 0,
+            offset => offset
+                .strip_prefix('+')
+                .unwrap_or(offset)
+                .parse()
+                .map_err(|_| invalid())?,
+        };
+
+        if warrior == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self::Relative { warrior, offset })
+    }
+}
+
+/// How addresses are printed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum View {
+    Absolute,
+
+    /// Relative to the load point of a warrior, numbered from 1
+    Relative(usize),
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Absolute => write!(formatter, "abs"),
+            Self::Relative(warrior) => write!(formatter, "w{}", warrior),
+        }
+    }
+}
+
+/// Where and when `continue` stops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// Stop before executing this address, or any address if `None`
+    pub address: Option<u32>,
+
+    /// An expression which must be non-zero to stop
+    pub condition: Option<String>,
+}
+
+/// A core being debugged, with the warriors loaded into it.
+#[derive(Debug)]
+pub struct Debugger {
+    core: Core,
+    load_points: Vec<u32>,
+    view: View,
+    labels: BTreeMap<u32, String>,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_breakpoint: usize,
+}
+
+impl Debugger {
+    /// Load `warriors` into a core of `core_size`, spread evenly through the
+    /// core in order.
+    pub fn new(core_size: u32, warriors: &[Warrior]) -> Result<Self, Error> {
+        let mut core = Core::new(core_size).map_err(|err| Error::Load(err.to_string()))?;
+        core.set_trace(false);
+        core.enable_contact_detection();
+        core.enable_history(HISTORY_LENGTH);
+        core.enable_events();
+
+        let count = warriors.len().max(1) as u64;
+        let mut load_points = Vec::new();
+        for (i, warrior) in warriors.iter().enumerate() {
+            let position = (i as u64 * u64::from(core_size) / count) as u32;
+            core.load_warrior_at(warrior, position)
+                .map_err(|err| Error::Load(err.to_string()))?;
+            load_points.push(position);
+        }
+
+        Ok(Self {
+            core,
+            load_points,
+            view: View::Absolute,
+            labels: BTreeMap::new(),
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 1,
+        })
+    }
+
+    pub fn core(&self) -> &Core {
+        &self.core
+    }
+
+    /// Run a single command line, returning its output. Blank lines and
+    /// lines starting with `#` do nothing.
+    pub fn execute(&mut self, line: &str) -> Result<String, Error> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) if !command.starts_with('#') => command,
+            _ => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+
+        match command {
+            "step" | "s" => self.step(parse_count(args.first())?),
+            "print" | "p" => {
+                let address = args.first().ok_or(Error::MissingArgument("address"))?;
+                let address = self.resolve(address.parse()?)?;
+                self.print(address, parse_count(args.get(1))?)
+            }
+            "view" => {
+                self.view = match args.first() {
+                    Some(&"abs") => View::Absolute,
+                    Some(view) => match view.parse()? {
+                        Address::Relative { warrior, offset: 0 } => {
+                            self.load_point(warrior)?;
+                            View::Relative(warrior)
+                        }
+                        _ => return Err(Error::InvalidArgument(view.to_string())),
+                    },
+                    None => return Err(Error::MissingArgument("view")),
+                };
+                Ok(format!("viewing addresses as {}", self.view))
+            }
+            "warriors" => Ok(self.warriors()),
+            "label" => {
+                let address = args.first().ok_or(Error::MissingArgument("address"))?;
+                let name = args.get(1).ok_or(Error::MissingArgument("label"))?;
+                let address = self.resolve(address.parse()?)?;
+                self.label(address, name)?;
+                Ok(format!("{} is {}", name, self.format_address(address)))
+            }
+            "unlabel" => {
+                let name = args.first().ok_or(Error::MissingArgument("label"))?;
+                self.unlabel(name)?;
+                Ok(String::new())
+            }
+            "labels" => Ok(self.labels()),
+            "break" | "b" => {
+                let (address, condition) = match args.split_first() {
+                    Some((&"if", condition)) => (None, Some(condition.join(" "))),
+                    Some((address, rest)) => {
+                        let address = self.resolve(address.parse()?)?;
+                        let condition = match rest.split_first() {
+                            Some((&"if", condition)) => Some(condition.join(" ")),
+                            Some((word, _)) => {
+                                return Err(Error::InvalidArgument(word.to_string()))
+                            }
+                            None => None,
+                        };
+                        (Some(address), condition)
+                    }
+                    None => return Err(Error::MissingArgument("address or condition")),
+                };
+
+                let number = self.add_breakpoint(Breakpoint { address, condition })?;
+                Ok(format!(
+                    "breakpoint {}: {}",
+                    number,
+                    self.format_breakpoint(number)
+                ))
+            }
+            "delete" => {
+                let number = args.first().ok_or(Error::MissingArgument("breakpoint"))?;
+                let number = number
+                    .parse()
+                    .map_err(|_| Error::InvalidArgument(number.to_string()))?;
+                self.breakpoints
+                    .remove(&number)
+                    .ok_or(Error::UnknownBreakpoint(number))?;
+                Ok(String::new())
+            }
+            "breakpoints" => Ok(self.breakpoints()),
+            "continue" | "c" => {
+                let max_steps = match args.first() {
+                    Some(_) => parse_count(args.first())?,
+                    None => DEFAULT_CONTINUE_STEPS,
+                };
+                self.continue_for(max_steps)
+            }
+            "contact" => {
+                let max_steps = match args.first() {
+                    Some(_) => parse_count(args.first())?,
+                    None => DEFAULT_CONTINUE_STEPS,
+                };
+                self.continue_to_contact(max_steps)
+            }
+            "backtrace" | "bt" => {
+                let count = match args.first() {
+                    Some(_) => parse_count(args.first())?,
+                    None => DEFAULT_BACKTRACE_LENGTH,
+                };
+                Ok(self.backtrace(count))
+            }
+            "effects" => {
+                let (address, instruction) = args
+                    .split_first()
+                    .ok_or(Error::MissingArgument("address"))?;
+                let address = self.resolve(address.parse()?)?;
+                let instruction = Some(instruction.join(" ")).filter(|text| !text.is_empty());
+                self.effects(address, instruction.as_deref())
+            }
+            _ => Err(Error::UnknownCommand(command.to_string())),
+        }
+    }
+
+    /// The absolute address, within the core, of `address`.
+    pub fn resolve(&self, address: Address) -> Result<u32, Error> {
+        let absolute = match address {
+            Address::Absolute(address) => address,
+            Address::Relative { warrior, offset } => i64::from(self.load_point(warrior)?) + offset,
+            Address::Label(name) => return self.find_label(&name),
+        };
+
+        Ok(absolute.rem_euclid(i64::from(self.core.size())) as u32)
+    }
+
+    /// Format an absolute address using the current view.
+    pub fn format_address(&self, address: u32) -> String {
+        match self.view {
+            View::Absolute => format!("{:0>5}", address),
+            View::Relative(warrior) => {
+                let size = i64::from(self.core.size());
+                let base = i64::from(self.load_points[warrior - 1]);
+                let mut offset = (i64::from(address) - base).rem_euclid(size);
+                if offset > size / 2 {
+                    offset -= size;
+                }
+                format!("w{}{:+}", warrior, offset)
+            }
+        }
+    }
+
+    /// Add a breakpoint, returning its number. The condition is checked
+    /// against the current state, so mistakes are found straight away.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<usize, Error> {
+        if let Some(condition) = &breakpoint.condition {
+            self.evaluate(condition)?;
+        }
+
+        let number = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.insert(number, breakpoint);
+        Ok(number)
+    }
+
+    /// Evaluate a condition against the current state of the core.
+    pub fn evaluate(&self, condition: &str) -> Result<i32, Error> {
+        corewars_parser::evaluate(condition, |accessor| self.lookup(accessor)).map_err(|error| {
+            Error::InvalidCondition {
+                condition: condition.to_string(),
+                error,
+            }
+        })
+    }
+
+    /// Name the absolute `address`, replacing any name it had before.
+    pub fn label(&mut self, address: u32, name: &str) -> Result<(), Error> {
+        if !is_label(name) {
+            return Err(Error::InvalidLabel(name.to_string()));
+        }
+
+        // Each name refers to a single address
+        self.labels.retain(|_, label| label != name);
+        self.labels
+            .insert(address % self.core.size(), name.to_string());
+        Ok(())
+    }
+
+    pub fn unlabel(&mut self, name: &str) -> Result<(), Error> {
+        let address = self.find_label(name)?;
+        self.labels.remove(&address);
+        Ok(())
+    }
+
+    /// Add the labels from a session file written by [`Debugger::session`].
+    pub fn load_session(&mut self, text: &str) -> Result<(), Error> {
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let invalid = || Error::InvalidSession {
+                line: i + 1,
+                text: line.to_string(),
+            };
+
+            let mut words = line.split_whitespace();
+            let address = words.next().and_then(|word| word.parse().ok());
+            match (address, words.next(), words.next()) {
+                (Some(address), Some(name), None) => {
+                    self.label(address, name).map_err(|_| invalid())?
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The labels, in the session file format read by
+    /// [`Debugger::load_session`].
+    pub fn session(&self) -> String {
+        self.labels
+            .iter()
+            .map(|(address, name)| format!("{} {}\n", address, name))
+            .collect()
+    }
+
+    fn find_label(&self, name: &str) -> Result<u32, Error> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(&address, _)| address)
+            .ok_or_else(|| Error::UnknownLabel(name.to_string()))
+    }
+
+    /// The label of `address`, formatted to follow an instruction.
+    fn label_suffix(&self, address: u32) -> String {
+        match self.labels.get(&address) {
+            Some(name) => format!("  ; {}", name),
+            None => String::new(),
+        }
+    }
+
+    /// The value of a name used in a condition, if there is one.
+    fn lookup(&self, accessor: &Accessor) -> Option<i32> {
+        let next = self.core.process_queue().peek().ok();
+        let fields: Vec<&str> = accessor.fields.iter().map(String::as_str).collect();
+
+        let value = match (accessor.name.as_str(), accessor.index, fields.as_slice()) {
+            ("cycle", None, []) => self.core.cycles() as i32,
+            ("pc", None, []) => next?.offset.value() as i32,
+            ("warrior", None, []) => {
+                let name = &next?.name;
+                self.core
+                    .warriors()
+                    .iter()
+                    .position(|warrior| warrior == name)? as i32
+                    + 1
+            }
+            ("processes", None, []) => self.core.process_queue().len() as i32,
+            ("warrior", Some(warrior), ["processes"]) => {
+                let index = (warrior as usize).checked_sub(1)?;
+                self.core.warriors().get(index)?;
+                self.core.process_queue().thread_count(index) as i32
+            }
+            ("core", Some(index), [field]) => {
+                let instruction = self.core.get(index);
+                match *field {
+                    "opcode" => instruction.opcode as i32,
+                    "modifier" => instruction.modifier as i32,
+                    "a" => instruction.a_field.unwrap_value(),
+                    "b" => instruction.b_field.unwrap_value(),
+                    _ => return None,
+                }
+            }
+            (name, None, []) => {
+                let upper = name.to_uppercase();
+                if let Ok(opcode) = upper.parse::<Opcode>() {
+                    opcode as i32
+                } else if let Ok(modifier) = upper.parse::<Modifier>() {
+                    modifier as i32
+                } else {
+                    self.find_label(name).ok()? as i32
+                }
+            }
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Whether any breakpoint stops before the next instruction is executed,
+    /// returning the number of the first which does.
+    fn breakpoint_hit(&self) -> Result<Option<usize>, Error> {
+        let next = match self.core.process_queue().peek() {
+            Ok(next) => next.offset.value(),
+            Err(_) => return Ok(None),
+        };
+
+        for (&number, breakpoint) in &self.breakpoints {
+            if breakpoint.address.is_some_and(|address| address != next) {
+                continue;
+            }
+
+            let condition_met = match &breakpoint.condition {
+                Some(condition) => self.evaluate(condition)? != 0,
+                None => true,
+            };
+            if condition_met {
+                return Ok(Some(number));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn format_breakpoint(&self, number: usize) -> String {
+        let breakpoint = &self.breakpoints[&number];
+        let address = match breakpoint.address {
+            Some(address) => self.format_address(address),
+            None => "any address".to_string(),
+        };
+
+        match &breakpoint.condition {
+            Some(condition) => format!("{} if {}", address, condition),
+            None => address,
+        }
+    }
+
+    fn breakpoints(&self) -> String {
+        let lines: Vec<String> = self
+            .breakpoints
+            .keys()
+            .map(|&number| format!("{} {}", number, self.format_breakpoint(number)))
+            .collect();
+
+        lines.join("\n")
+    }
+
+    /// Run until a breakpoint is hit, no processes are left, or `max_steps`
+    /// have been executed. Breakpoints at the instruction about to be
+    /// executed are ignored, so `continue` can be used after stopping at one.
+    fn continue_for(&mut self, max_steps: usize) -> Result<String, Error> {
+        for i in 0..max_steps {
+            if i > 0 {
+                if let Some(number) = self.breakpoint_hit()? {
+                    let address = self.core.process_queue().peek().unwrap().offset.value();
+                    return Ok(format!(
+                        "breakpoint {} hit at cycle {}: {:<8} {}{}",
+                        number,
+                        self.core.cycles(),
+                        self.format_address(address),
+                        self.core.get(address as i32),
+                        self.label_suffix(address)
+                    ));
+                }
+            }
+
+            if self.step_once().is_none() {
+                return Ok("no processes left to execute".to_string());
+            }
+        }
+
+        Ok(format!(
+            "stopped after {} steps at cycle {}",
+            max_steps,
+            self.core.cycles()
+        ))
+    }
+
+    /// Run until a warrior first reads or writes an instruction owned by
+    /// another, no processes are left, or `max_steps` have been executed.
+    fn continue_to_contact(&mut self, max_steps: usize) -> Result<String, Error> {
+        for _ in 0..max_steps {
+            if let Some(contact) = self.core.first_contact() {
+                return Ok(format!("{}{}", contact, self.label_suffix(contact.address)));
+            }
+
+            if self.step_once().is_none() {
+                return Ok("no processes left to execute".to_string());
+            }
+        }
+
+        match self.core.first_contact() {
+            Some(contact) => Ok(format!("{}{}", contact, self.label_suffix(contact.address))),
+            None => Ok(format!(
+                "no contact after {} steps, at cycle {}",
+                max_steps,
+                self.core.cycles()
+            )),
+        }
+    }
+
+    fn load_point(&self, warrior: usize) -> Result<u32, Error> {
+        warrior
+            .checked_sub(1)
+            .and_then(|index| self.load_points.get(index))
+            .copied()
+            .ok_or(Error::UnknownWarrior(warrior))
+    }
+
+    fn step(&mut self, count: usize) -> Result<String, Error> {
+        let mut lines = Vec::new();
+
+        for _ in 0..count {
+            match self.step_once() {
+                Some(line) => lines.push(line),
+                None => {
+                    lines.push("no processes left to execute".to_string());
+                    break;
+                }
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    /// Execute a single cycle, returning a description of what was executed,
+    /// or `None` if there were no processes left.
+    fn step_once(&mut self) -> Option<String> {
+        let result = self.core.step();
+
+        let (cycle, warrior, address, instruction) =
+            self.core
+                .last_events()
+                .iter()
+                .find_map(|event| match event {
+                    Event::InstructionExecuted {
+                        cycle,
+                        warrior,
+                        address,
+                        instruction,
+                    } => Some((cycle, warrior, *address, instruction)),
+                    _ => None,
+                })?;
+
+        let mut line = format!(
+            "{:>6} {} {:<8} {}{}",
+            cycle,
+            text::pad(warrior, 12),
+            self.format_address(address),
+            instruction,
+            self.label_suffix(address)
+        );
+        if let Err(err) = result {
+            line.push_str(&format!("  ; {}", err));
+        }
+        Some(line)
+    }
+
+    fn print(&self, address: u32, count: usize) -> Result<String, Error> {
+        let lines: Vec<String> = (0..count as u32)
+            .map(|i| {
+                let address = (address + i) % self.core.size();
+                format!(
+                    "{:<8} {}{}",
+                    self.format_address(address),
+                    self.core.get(address as i32),
+                    self.label_suffix(address)
+                )
+            })
+            .collect();
+
+        Ok(lines.join("\n"))
+    }
+
+    fn backtrace(&self, count: usize) -> String {
+        let lines: Vec<String> = self
+            .core
+            .history()
+            .into_iter()
+            .flat_map(|history| history.iter().rev())
+            .take(count)
+            .map(|executed| {
+                format!(
+                    "{:>6} {} {:<8} {}{}",
+                    executed.cycle,
+                    text::pad(&executed.warrior, 12),
+                    self.format_address(executed.address),
+                    executed.instruction,
+                    self.label_suffix(executed.address)
+                )
+            })
+            .collect();
+
+        lines.join("\n")
+    }
+
+    /// What executing `instruction`, or the instruction already there, at
+    /// `address` would do, without changing the core.
+    fn effects(&self, address: u32, instruction: Option<&str>) -> Result<String, Error> {
+        let instruction = match instruction {
+            Some(text) => match corewars_parser::parse(text) {
+                corewars_parser::Result::Ok(warrior, _)
+                    if warrior.program.instructions.len() == 1 =>
+                {
+                    warrior.program.instructions[0].clone()
+                }
+                corewars_parser::Result::Ok(..) => {
+                    return Err(Error::InvalidArgument(text.to_string()))
+                }
+                corewars_parser::Result::Err(error, _) => {
+                    return Err(Error::InvalidInstruction {
+                        instruction: text.to_string(),
+                        error,
+                    })
+                }
+            },
+            None => self.core.get(address as i32).clone(),
+        };
+        let effects = self.core.effects(address as i32, &instruction);
+
+        let addresses = |addresses: &[u32]| -> String {
+            if addresses.is_empty() {
+                return "nothing".to_string();
+            }
+            let formatted: Vec<String> = addresses
+                .iter()
+                .map(|&address| {
+                    format!(
+                        "{}{}",
+                        self.format_address(address),
+                        self.label_suffix(address)
+                    )
+                })
+                .collect();
+            formatted.join(", ")
+        };
+
+        let mut lines = vec![
+            format!(
+                "{:<8} {}{}",
+                self.format_address(address),
+                instruction,
+                self.label_suffix(address)
+            ),
+            format!("reads    {}", addresses(&effects.reads)),
+        ];
+        for (written, instruction) in &effects.writes {
+            lines.push(format!(
+                "writes   {:<8} {}{}",
+                self.format_address(*written),
+                instruction,
+                self.label_suffix(*written)
+            ));
+        }
+        match &effects.error {
+            Some(error) => lines.push(format!("stops    {}", error)),
+            None if effects.next.is_empty() => lines.push("stops".to_string()),
+            None => lines.push(format!("next     {}", addresses(&effects.next))),
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    fn warriors(&self) -> String {
+        let lines: Vec<String> = self
+            .core
+            .warriors()
+            .iter()
+            .zip(&self.load_points)
+            .enumerate()
+            .map(|(i, (name, load_point))| format!("w{} {} at {:0>5}", i + 1, name, load_point))
+            .collect();
+
+        lines.join("\n")
+    }
+
+    fn labels(&self) -> String {
+        let lines: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(&address, name)| format!("{:<8} {}", self.format_address(address), name))
+            .collect();
+
+        lines.join("\n")
+    }
+}
+
+/// Whether `name` can be used as a label without being mistaken for another
+/// kind of address.
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_with_letter = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_');
+    let is_relative = name.starts_with('w') && name[1..].starts_with(|c: char| c.is_ascii_digit());
+
+    starts_with_letter && !is_relative && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_count(arg: Option<&&str>) -> Result<usize, Error> {
+    match arg {
+        None => Ok(1),
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| Error::InvalidArgument(arg.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn debugger() -> Debugger {
+        let warriors = vec![
+            corewars_parser::parse(";name Imp\nmov 0, 1").unwrap(),
+            corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+                .unwrap(),
+        ];
+        Debugger::new(100, &warriors).unwrap()
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!("4021".parse(), Ok(Address::Absolute(4021)));
+        assert_eq!("-3".parse(), Ok(Address::Absolute(-3)));
+        assert_eq!(
+            "w1+5".parse(),
+            Ok(Address::Relative {
+                warrior: 1,
+                offset: 5
+            })
+        );
+        assert_eq!(
+            "w2-3".parse(),
+            Ok(Address::Relative {
+                warrior: 2,
+                offset: -3
+            })
+        );
+        assert_eq!(
+            "w2".parse(),
+            Ok(Address::Relative {
+                warrior: 2,
+                offset: 0
+            })
+        );
+        assert!("w0".parse::<Address>().is_err());
+        assert!("w1*2".parse::<Address>().is_err());
+        assert!("w1x".parse::<Address>().is_err());
+        assert_eq!("wall".parse(), Ok(Address::Label("wall".into())));
+    }
+
+    #[test]
+    fn resolves_relative_addresses() {
+        let debugger = debugger();
+
+        assert_eq!(debugger.resolve("w2+3".parse().unwrap()), Ok(53));
+        assert_eq!(debugger.resolve("w1-1".parse().unwrap()), Ok(99));
+        assert_eq!(
+            debugger.resolve("w3".parse().unwrap()),
+            Err(Error::UnknownWarrior(3))
+        );
+    }
+
+    #[test]
+    fn views_addresses_relative_to_warrior() {
+        let mut debugger = debugger();
+
+        assert_eq!(
+            debugger.execute("warriors").unwrap(),
+            "w1 Imp at 00000\nw2 Dwarf at 00050"
+        );
+        assert_eq!(
+            debugger.execute("print w2+3").unwrap(),
+            "00053    DAT.F   #0,     #0"
+        );
+
+        debugger.execute("view w2").unwrap();
+        assert_eq!(
+            debugger.execute("print 49 2").unwrap(),
+            "w2-1     DAT.F   $0,     $0\nw2+0     ADD.AB  #4,     $3"
+        );
+
+        let steps = debugger.execute("step 2").unwrap();
+        let lines: Vec<&str> = steps.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("     0 Imp          w2+50    MOV.I"));
+        assert!(lines[1].starts_with("     0 Dwarf        w2+0     ADD.AB"));
+
+        assert_eq!(debugger.execute("view w3"), Err(Error::UnknownWarrior(3)));
+        assert_eq!(
+            debugger.execute("jump"),
+            Err(Error::UnknownCommand("jump".into()))
+        );
+    }
+
+    #[test]
+    fn labels_addresses() {
+        let mut debugger = debugger();
+
+        assert_eq!(
+            debugger.execute("label w2+3 gate").unwrap(),
+            "gate is 00053"
+        );
+        assert_eq!(
+            debugger.execute("print gate").unwrap(),
+            "00053    DAT.F   #0,     #0  ; gate"
+        );
+        assert_eq!(
+            debugger.execute("label 5 w2"),
+            Err(Error::InvalidLabel("w2".into()))
+        );
+
+        // Moving a label replaces its previous address
+        debugger.execute("label 51 gate").unwrap();
+        debugger.execute("label 0 start").unwrap();
+        debugger.execute("view w2").unwrap();
+        assert_eq!(
+            debugger.execute("labels").unwrap(),
+            "w2+50    start\nw2+1     gate"
+        );
+        assert!(debugger
+            .execute("step")
+            .unwrap()
+            .ends_with("MOV.I   $0,     $1  ; start"));
+
+        debugger.execute("unlabel start").unwrap();
+        assert_eq!(
+            debugger.execute("print start"),
+            Err(Error::UnknownLabel("start".into()))
+        );
+    }
+
+    #[test]
+    fn session_round_trip() {
+        let mut debugger = debugger();
+        debugger.load_session("53 gate\n\n0 start\n").unwrap();
+        assert_eq!(debugger.session(), "0 start\n53 gate\n");
+
+        assert_eq!(
+            debugger.load_session("53\n"),
+            Err(Error::InvalidSession {
+                line: 1,
+                text: "53".into()
+            })
+        );
+    }
+
+    #[test]
+    fn continues_to_contact() {
+        let mut debugger = debugger();
+        assert_eq!(
+            debugger.execute("contact 10").unwrap(),
+            "no contact after 10 steps, at cycle 5"
+        );
+        assert_eq!(
+            debugger.execute("contact").unwrap(),
+            "first contact at cycle 34: Dwarf wrote 00001, owned by Imp"
+        );
+    }
+
+    #[test]
+    fn prints_backtrace() {
+        let mut debugger = debugger();
+        let steps = debugger.execute("step 4").unwrap();
+        let backtrace = debugger.execute("backtrace 3").unwrap();
+
+        let newest_first: Vec<&str> = steps.lines().rev().take(3).collect();
+        assert_eq!(backtrace.lines().collect::<Vec<_>>(), newest_first);
+        assert_eq!(debugger.execute("bt").unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn previews_effects() {
+        let mut debugger = debugger();
+        let before = debugger.execute("print w2 4").unwrap();
+
+        assert_eq!(
+            debugger.execute("effects w2").unwrap(),
+            "00050    ADD.AB  #4,     $3
+reads    00050, 00053
+writes   00053    DAT.F   #0,     #4
+next     00051"
+        );
+        assert_eq!(
+            debugger.execute("effects w1+1 dat 0, 0").unwrap(),
+            "00001    DAT.F   $0,     $0
+reads    00001
+stops    terminated due to reaching a DAT at offset 1"
+        );
+        assert_eq!(
+            debugger.execute("effects w1 spl 2").unwrap().lines().last(),
+            Some("next     00001, 00002")
+        );
+        assert!(matches!(
+            debugger.execute("effects w1 ; nothing"),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            debugger.execute("effects w1 mov 0, missing"),
+            Err(Error::InvalidInstruction { .. })
+        ));
+
+        assert_eq!(debugger.execute("print w2 4").unwrap(), before);
+        assert_eq!(debugger.core.steps_taken(), 0);
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        let mut debugger = debugger();
+        debugger.execute("label w2+3 bomb").unwrap();
+
+        assert_eq!(
+            debugger.execute("break w2+1 if core[bomb].b >= 8").unwrap(),
+            "breakpoint 1: 00051 if core[bomb].b >= 8"
+        );
+        assert!(debugger
+            .execute("continue")
+            .unwrap()
+            .starts_with("breakpoint 1 hit at cycle 4: 00051    MOV.I"));
+
+        debugger.execute("delete 1").unwrap();
+        debugger
+            .execute("break if cycle >= 20 && core[pc].opcode == MOV && warrior == 2")
+            .unwrap();
+        assert_eq!(
+            debugger.execute("breakpoints").unwrap(),
+            "2 any address if cycle >= 20 && core[pc].opcode == MOV && warrior == 2"
+        );
+        assert!(debugger
+            .execute("continue")
+            .unwrap()
+            .starts_with("breakpoint 2 hit at cycle 22: 00051 "));
+
+        assert_eq!(
+            debugger.execute("continue 2").unwrap(),
+            "stopped after 2 steps at cycle 23"
+        );
+        assert!(matches!(
+            debugger.execute("break if nonsense > 1"),
+            Err(Error::InvalidCondition { .. })
+        ));
+        assert_eq!(
+            debugger.execute("delete 1"),
+            Err(Error::UnknownBreakpoint(1))
+        );
+    }
+}
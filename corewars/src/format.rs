@@ -0,0 +1,326 @@
+//! Output formats for warriors, cores and battle outcomes. Formats are looked
+//! up by name in a [`Formats`] registry, which downstream crates can extend
+//! with their own exporters (e.g. HTML reports) and pass to
+//! [`cli::run_with`](crate::cli::run_with).
+//!
+//! The `loadfile`, `nmars` and [`html`](crate::html) formats are always
+//! available. The
+//! `json` and [`corewar-io`](crate::corewar_io) formats are enabled by the
+//! `json` feature, which is on by default.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error as ThisError;
+
+use corewars_core::Warrior;
+use corewars_sim::{Core, Outcome};
+
+/// An error writing something in an output format.
+#[derive(ThisError, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("the {format} format cannot write {subject}")]
+    Unsupported {
+        format: &'static str,
+        subject: &'static str,
+    },
+
+    #[error("unknown output format {name:?}, expected one of: {}", available.join(", "))]
+    UnknownFormat {
+        name: String,
+        available: Vec<&'static str>,
+    },
+
+    #[error("{0}")]
+    Custom(String),
+}
+
+/// A way of writing warriors, cores or battle outcomes as text. Formats only
+/// need to support what makes sense for them; the rest report
+/// [`Error::Unsupported`].
+pub trait OutputFormat {
+    /// The name used to select this format, e.g. on the command line
+    fn name(&self) -> &'static str;
+
+    /// The usual file extension of this format, without a leading `.`
+    fn extension(&self) -> &'static str;
+
+    fn warrior(&self, _warrior: &Warrior) -> Result<String, Error> {
+        Err(self.unsupported("warriors"))
+    }
+
+    fn core(&self, _core: &Core) -> Result<String, Error> {
+        Err(self.unsupported("cores"))
+    }
+
+    fn outcome(&self, _outcome: &Outcome) -> Result<String, Error> {
+        Err(self.unsupported("battle outcomes"))
+    }
+
+    /// The outcomes of every round of a battle between `warriors`, for
+    /// formats which summarize a battle as a whole rather than each round
+    fn results(&self, _warriors: &[String], _outcomes: &[Outcome]) -> Result<String, Error> {
+        Err(self.unsupported("battle results"))
+    }
+
+    fn unsupported(&self, subject: &'static str) -> Error {
+        Error::Unsupported {
+            format: self.name(),
+            subject,
+        }
+    }
+}
+
+/// The standard "load file" format, as printed by `dump`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoadFile;
+
+impl OutputFormat for LoadFile {
+    fn name(&self) -> &'static str {
+        "loadfile"
+    }
+
+    fn extension(&self) -> &'static str {
+        "red"
+    }
+
+    fn warrior(&self, warrior: &Warrior) -> Result<String, Error> {
+        Ok(warrior.to_string())
+    }
+
+    fn core(&self, core: &Core) -> Result<String, Error> {
+        Ok(core.to_string())
+    }
+
+    fn outcome(&self, outcome: &Outcome) -> Result<String, Error> {
+        Ok(outcome.to_string())
+    }
+}
+
+/// The results printed by nMARS and Exhaust: a `;results` line, then the
+/// wins, losses and ties of each warrior over every round, e.g. `W 1 L 2 T 3`.
+/// A warrior loses each round it did not win or survive to the end of.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Nmars;
+
+impl OutputFormat for Nmars {
+    fn name(&self) -> &'static str {
+        "nmars"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn results(&self, warriors: &[String], outcomes: &[Outcome]) -> Result<String, Error> {
+        let mut results = String::from(";results\n");
+        for warrior in warriors {
+            let (mut wins, mut ties) = (0, 0);
+            for outcome in outcomes {
+                match outcome {
+                    Outcome::Win(winner) if winner == warrior => wins += 1,
+                    Outcome::Tie(survivors) if survivors.contains(warrior) => ties += 1,
+                    _ => (),
+                }
+            }
+            let losses = outcomes.len() - wins - ties;
+            results.push_str(&format!("{} W {} L {} T {}\n", warrior, wins, losses, ties));
+        }
+
+        Ok(results)
+    }
+}
+
+/// JSON, for reading by other programs. Instructions are written as strings
+/// in load file syntax.
+#[cfg(feature = "json")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl OutputFormat for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn warrior(&self, warrior: &Warrior) -> Result<String, Error> {
+        let metadata = &warrior.metadata;
+        let instructions: Vec<String> = warrior
+            .program
+            .instructions
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        Ok(serde_json::json!({
+            "name": metadata.name,
+            "author": metadata.author,
+            "date": metadata.date,
+            "version": metadata.version,
+            "strategy": metadata.strategy,
+            "origin": warrior.program.origin,
+            "instructions": instructions,
+        })
+        .to_string())
+    }
+
+    fn core(&self, core: &Core) -> Result<String, Error> {
+        let instructions: Vec<String> = (0..core.size() as i32)
+            .map(|i| core.get(i).to_string())
+            .collect();
+
+        Ok(serde_json::json!({
+            "size": core.size(),
+            "cycle": core.cycles(),
+            "warriors": core.warriors(),
+            "instructions": instructions,
+        })
+        .to_string())
+    }
+
+    fn outcome(&self, outcome: &Outcome) -> Result<String, Error> {
+        let value = match outcome {
+            Outcome::Win(name) => serde_json::json!({ "result": "win", "winner": name }),
+            Outcome::Tie(names) => serde_json::json!({ "result": "tie", "survivors": names }),
+        };
+
+        Ok(value.to_string())
+    }
+}
+
+/// A set of output formats, keyed by name.
+#[derive(Default)]
+pub struct Formats {
+    formats: BTreeMap<&'static str, Box<dyn OutputFormat>>,
+}
+
+impl Formats {
+    /// A registry with no formats.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the formats built in to this crate, depending on
+    /// which features are enabled.
+    pub fn builtin() -> Self {
+        let mut formats = Self::new();
+        formats.register(LoadFile);
+        formats.register(Nmars);
+        formats.register(crate::html::Html);
+
+        #[cfg(feature = "json")]
+        formats.register(Json);
+        #[cfg(feature = "json")]
+        formats.register(crate::corewar_io::CorewarIo);
+
+        formats
+    }
+
+    /// Add a format, replacing any other with the same name.
+    pub fn register<F: OutputFormat + 'static>(&mut self, format: F) {
+        self.formats.insert(format.name(), Box::new(format));
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn OutputFormat, Error> {
+        self.formats
+            .get(name)
+            .map(Box::as_ref)
+            .ok_or_else(|| Error::UnknownFormat {
+                name: name.to_string(),
+                available: self.names(),
+            })
+    }
+
+    /// The names of all the formats, in alphabetical order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.formats.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    struct Names;
+
+    impl OutputFormat for Names {
+        fn name(&self) -> &'static str {
+            "names"
+        }
+
+        fn extension(&self) -> &'static str {
+            "txt"
+        }
+
+        fn warrior(&self, warrior: &Warrior) -> Result<String, Error> {
+            Ok(warrior.metadata.name.clone().unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn registers_formats() {
+        let mut formats = Formats::builtin();
+        formats.register(Names);
+
+        let warrior = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+        let names = formats.get("names").unwrap();
+        assert_eq!(names.extension(), "txt");
+        assert_eq!(names.warrior(&warrior), Ok("Imp".into()));
+        assert_eq!(
+            names.outcome(&Outcome::Win("Imp".into())),
+            Err(Error::Unsupported {
+                format: "names",
+                subject: "battle outcomes"
+            })
+        );
+
+        assert!(formats.names().contains(&"loadfile"));
+        assert!(matches!(
+            formats.get("xml"),
+            Err(Error::UnknownFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn writes_nmars_results() {
+        let warriors = vec!["Imp".into(), "Dwarf".into(), "Scanner".into()];
+        let outcomes = vec![
+            Outcome::Win("Dwarf".into()),
+            Outcome::Tie(vec!["Imp".into(), "Dwarf".into()]),
+            Outcome::Win("Imp".into()),
+        ];
+
+        assert_eq!(
+            Nmars.results(&warriors, &outcomes),
+            Ok(";results\nImp W 1 L 1 T 1\nDwarf W 1 L 1 T 1\nScanner W 0 L 3 T 0\n".into())
+        );
+        assert_eq!(
+            LoadFile.results(&warriors, &outcomes),
+            Err(Error::Unsupported {
+                format: "loadfile",
+                subject: "battle results"
+            })
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn writes_json() {
+        let warrior = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&Json.warrior(&warrior).unwrap()).unwrap();
+        assert_eq!(json["name"], "Imp");
+        assert_eq!(json["instructions"][0], "MOV.I   $0,     $1");
+
+        assert_eq!(
+            Json.outcome(&Outcome::Tie(vec!["A".into(), "B".into()])),
+            Ok(r#"{"result":"tie","survivors":["A","B"]}"#.into())
+        );
+    }
+}
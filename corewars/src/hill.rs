@@ -0,0 +1,886 @@
+//! Running a king of the hill ("KOTH"): a fixed number of warriors, each of
+//! which has battled every other. A challenger battles everyone on the hill,
+//! and stays on if it ranks high enough, pushing the lowest ranked warrior
+//! off a full hill.
+//!
+//! A [`Server`] keeps a hill in a directory, so a club can run one just by
+//! sharing a folder. New warriors are saved directly in the directory, and
+//! the server moves each one it challenges with into a subdirectory:
+//!
+//! ```text
+//! submissions/             new warriors
+//! submissions/hill/        the warriors on the hill
+//! submissions/archive/     rejected warriors, and those pushed off the hill
+//! submissions/scores.txt   the standings, in the KOTH scores format
+//! submissions/crosstable.* the results of each pair of warriors on the hill
+//! ```
+//!
+//! Moved files are prefixed with their submission number, e.g.
+//! `0012-imp.red`, so a restarted server knows the order warriors arrived in.
+//! New warriors may also be [packages](crate::package), which are moved
+//! with everything in them.
+//!
+//! A server can also [stream](Server::set_stream) the first round of each
+//! challenge to web viewers as it is fought, [require](Server::set_author_keys)
+//! every new warrior to be [signed](crate::signature) by its author, and
+//! [report](Server::set_telemetry) how much it is used.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error as ThisError;
+
+use corewars_core::Warrior;
+use corewars_sim::{Battle, BattleConfig, ConfigError, Core, CoreError};
+
+use crate::koth::{self, Standing};
+use crate::messages::Message;
+use crate::package::{self, Package};
+use crate::signature::{self, AuthorKeys, Ed25519};
+use crate::stream::Viewers;
+use crate::telemetry::{self, Telemetry};
+use crate::tournament::{self, Crosstable, Record};
+
+/// The name of the standings file written by a [`Server`].
+pub const SCORES_FILE: &str = "scores.txt";
+
+/// The names of the crosstable files written by a [`Server`], as plain text
+/// and JSON.
+pub const CROSSTABLE_FILES: [&str; 2] = ["crosstable.txt", "crosstable.json"];
+
+/// An error running a hill.
+#[derive(ThisError, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A warrior already on the hill couldn't be loaded.
+    #[error("invalid warrior on the hill {path}: {message}")]
+    InvalidMember { path: PathBuf, message: String },
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+/// A warrior which has challenged the hill.
+#[derive(Debug)]
+pub struct Member {
+    /// The submission number, counting from 1, which is unique on a hill
+    pub id: u32,
+
+    pub warrior: Warrior,
+}
+
+impl Member {
+    pub fn name(&self) -> String {
+        self.warrior
+            .metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Warrior{}", self.id))
+    }
+}
+
+/// The result of challenging a hill.
+#[derive(Debug)]
+pub enum Verdict {
+    /// The challenger stays on the hill at `rank`, counting from 1, pushing
+    /// off the lowest ranked warrior if the hill was full.
+    Accepted {
+        rank: u32,
+        pushed_off: Option<Member>,
+    },
+
+    /// The challenger ranked too low to stay on the hill.
+    Rejected { rank: u32, challenger: Member },
+}
+
+/// The warriors on a hill, and the results of every battle between them.
+#[derive(Debug)]
+pub struct Hill {
+    config: BattleConfig,
+    size: usize,
+    rounds: u32,
+    members: Vec<Member>,
+
+    /// The record of each pair of members, keyed by their ids, from the
+    /// point of view of the member with the lower id
+    records: BTreeMap<(u32, u32), Record>,
+
+    /// The id of the latest submission, to work out the age of each member
+    latest: u32,
+}
+
+impl Hill {
+    /// An empty hill with room for `size` warriors, which battle for
+    /// `rounds` rounds against each opponent.
+    ///
+    /// # Panics
+    ///
+    /// If `size` or `rounds` is 0.
+    pub fn new(config: BattleConfig, size: usize, rounds: u32) -> Self {
+        assert!(size > 0, "hill must have room for at least one warrior");
+        assert!(rounds > 0, "warriors must battle for at least one round");
+
+        Self {
+            config,
+            size,
+            rounds,
+            members: Vec::new(),
+            records: BTreeMap::new(),
+            latest: 0,
+        }
+    }
+
+    /// The warriors on the hill, in the order they were submitted.
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    /// The record of the member with id `first` against the one with id
+    /// `second`, if both are on the hill.
+    pub fn record(&self, first: u32, second: u32) -> Option<Record> {
+        if first < second {
+            self.records.get(&(first, second)).copied()
+        } else {
+            self.records.get(&(second, first)).map(|r| r.reversed())
+        }
+    }
+
+    /// The first round of `challenger` against `member`, ready to run, like
+    /// the first of the rounds [`challenge`](Hill::challenge) plays.
+    fn first_round(&self, member: &Warrior, challenger: &Warrior) -> Result<Battle, CoreError> {
+        let mut battle = Battle::new(self.config.clone())?;
+        battle.load(member, 0)?;
+        battle.load(challenger, self.config.min_distance)?;
+        Ok(battle)
+    }
+
+    /// The member with the same program as `warrior`, ignoring comments and
+    /// formatting, if any.
+    pub fn duplicate_of(&self, warrior: &Warrior) -> Option<&Member> {
+        let digest = warrior.digest();
+        self.members
+            .iter()
+            .find(|member| member.warrior.digest() == digest)
+    }
+
+    /// Count a submission which never challenged the hill, e.g. because it
+    /// didn't assemble, towards the age of every member.
+    pub fn skip(&mut self, id: u32) {
+        self.latest = self.latest.max(id);
+    }
+
+    /// Battle `warrior` against every member, and keep it on the hill if it
+    /// ranks high enough. `id` must be greater than that of every member.
+    pub fn challenge(&mut self, id: u32, warrior: Warrior) -> Result<Verdict, ConfigError> {
+        self.config.validate(2)?;
+        // Checked even if the hill is empty, so no member can break later battles
+        Core::check_supported(&warrior)?;
+
+        let challenger = Member { id, warrior };
+        let mut records = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            records.push((
+                member.id,
+                tournament::play(
+                    &self.config,
+                    &member.warrior,
+                    &challenger.warrior,
+                    self.rounds,
+                )?,
+            ));
+        }
+
+        self.skip(id);
+        for (member, record) in records {
+            self.records.insert((member, id), record);
+        }
+        self.members.push(challenger);
+
+        let rank = self.rank(id);
+        if self.members.len() <= self.size {
+            return Ok(Verdict::Accepted {
+                rank,
+                pushed_off: None,
+            });
+        }
+
+        let last = self.standings_by_id().last().map(|&(id, _)| id);
+        let removed = self.remove(last.expect("hill should not be empty"));
+        if removed.id == id {
+            Ok(Verdict::Rejected {
+                rank,
+                challenger: removed,
+            })
+        } else {
+            Ok(Verdict::Accepted {
+                rank,
+                pushed_off: Some(removed),
+            })
+        }
+    }
+
+    /// The standings of the members, from the highest score to the lowest.
+    pub fn standings(&self) -> Vec<Standing> {
+        self.standings_by_id()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (id, total))| {
+                let member = self.member(id);
+                let rounds = total.rounds().max(1) as f64;
+                let percent = |count: u32| (f64::from(count) * 100.0 / rounds).round() as u32;
+
+                Standing {
+                    rank: i as u32 + 1,
+                    wins: percent(total.wins),
+                    losses: percent(total.losses),
+                    ties: percent(total.ties),
+                    name: member.name(),
+                    author: member
+                        .warrior
+                        .metadata
+                        .author
+                        .clone()
+                        .unwrap_or_else(|| "Anonymous".into()),
+                    score: total.score(),
+                    age: self.latest - id,
+                }
+            })
+            .collect()
+    }
+
+    /// The record of each member against every other, ranked like the
+    /// [standings](Hill::standings).
+    pub fn crosstable(&self) -> Crosstable {
+        let ids: Vec<u32> = self.standings_by_id().iter().map(|&(id, _)| id).collect();
+        let names = ids.iter().map(|&id| self.member(id).name()).collect();
+        Crosstable::new(names, |i, j| self.record(ids[i], ids[j]))
+    }
+
+    /// The id and total record of each member, from the highest score to the
+    /// lowest. Members with equal scores are ranked oldest first.
+    fn standings_by_id(&self) -> Vec<(u32, Record)> {
+        let mut totals: Vec<(u32, Record)> = self
+            .members
+            .iter()
+            .map(|member| {
+                let total = self
+                    .members
+                    .iter()
+                    .filter_map(|other| self.record(member.id, other.id))
+                    .fold(Record::default(), |total, record| total + record);
+                (member.id, total)
+            })
+            .collect();
+
+        totals.sort_by(|(a_id, a), (b_id, b)| b.score().total_cmp(&a.score()).then(a_id.cmp(b_id)));
+        totals
+    }
+
+    fn rank(&self, id: u32) -> u32 {
+        let index = self
+            .standings_by_id()
+            .iter()
+            .position(|&(member, _)| member == id)
+            .expect("member should be on the hill");
+        index as u32 + 1
+    }
+
+    fn member(&self, id: u32) -> &Member {
+        self.members
+            .iter()
+            .find(|member| member.id == id)
+            .expect("member should be on the hill")
+    }
+
+    fn remove(&mut self, id: u32) -> Member {
+        self.records
+            .retain(|&(first, second), _| first != id && second != id);
+        let index = self
+            .members
+            .iter()
+            .position(|member| member.id == id)
+            .expect("member should be on the hill");
+        self.members.remove(index)
+    }
+}
+
+/// Something which happened to a submission, or to a warrior on the hill.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Accepted {
+        name: String,
+        rank: u32,
+    },
+    Rejected {
+        name: String,
+        rank: u32,
+    },
+    PushedOff {
+        name: String,
+    },
+
+    /// The submission at `path` couldn't challenge the hill, e.g. because it
+    /// didn't assemble
+    Invalid {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            Self::Accepted { name, rank } => Message::new("hill-accepted")
+                .arg("name", name)
+                .arg("rank", rank),
+            Self::Rejected { name, rank } => Message::new("hill-rejected")
+                .arg("name", name)
+                .arg("rank", rank),
+            Self::PushedOff { name } => Message::new("hill-pushed-off").arg("name", name),
+            Self::Invalid { path, message } => Message::new("hill-invalid")
+                .arg("path", path.display())
+                .arg("reason", message),
+        };
+        write!(formatter, "{}", message)
+    }
+}
+
+/// A hill kept in a directory, as described in the [module
+/// documentation](self).
+#[derive(Debug)]
+pub struct Server {
+    directory: PathBuf,
+    hill: Hill,
+
+    /// Where the file of each member of the hill is
+    paths: BTreeMap<u32, PathBuf>,
+
+    stream: Option<Viewers>,
+
+    /// The keys new warriors must be signed with, if any
+    author_keys: Option<AuthorKeys>,
+
+    telemetry: Telemetry,
+}
+
+impl Server {
+    /// Serve `hill` from `directory`, first challenging it with the warriors
+    /// already on the hill there, and write the standings.
+    pub fn open<P: Into<PathBuf>>(directory: P, hill: Hill) -> Result<Self, Error> {
+        let directory = directory.into();
+        let mut server = Self {
+            hill,
+            paths: BTreeMap::new(),
+            stream: None,
+            author_keys: None,
+            telemetry: Telemetry::disabled(),
+            directory,
+        };
+
+        fs::create_dir_all(server.hill_dir())?;
+        fs::create_dir_all(server.archive_dir())?;
+
+        for (id, _) in numbered_files(&server.archive_dir())? {
+            server.hill.skip(id);
+        }
+
+        for (id, path) in numbered_files(&server.hill_dir())? {
+            let warrior = read_warrior(&path).map_err(|message| Error::InvalidMember {
+                path: path.clone(),
+                message,
+            })?;
+            server.paths.insert(id, path.clone());
+
+            match server.hill.challenge(id, warrior)? {
+                Verdict::Accepted { pushed_off, .. } => {
+                    if let Some(member) = pushed_off {
+                        server.archive_member(member.id)?;
+                    }
+                }
+                Verdict::Rejected { challenger, .. } => server.archive_member(challenger.id)?,
+            }
+        }
+
+        server.write_scores()?;
+        Ok(server)
+    }
+
+    pub fn hill(&self) -> &Hill {
+        &self.hill
+    }
+
+    /// Stream the first round of each challenger against each member to
+    /// `viewers` before it challenges the hill, while anyone is watching.
+    pub fn set_stream(&mut self, viewers: Viewers) {
+        self.stream = Some(viewers);
+    }
+
+    /// Only let new warriors challenge the hill if they have an ed25519
+    /// signature made with one of their author's `keys`. Warriors already on
+    /// the hill were checked when they were submitted.
+    pub fn set_author_keys(&mut self, keys: AuthorKeys) {
+        self.author_keys = Some(keys);
+    }
+
+    /// Count the battles new warriors fight, and the submissions which fail
+    /// to parse, in reports to `telemetry`. Nothing is reported by default.
+    pub fn set_telemetry(&mut self, telemetry: Telemetry) {
+        self.telemetry = telemetry;
+    }
+
+    /// Challenge the hill with every new file in the directory, oldest
+    /// first, and update the standings if any were found.
+    pub fn poll(&mut self) -> Result<Vec<Event>, Error> {
+        let mut submissions: Vec<(std::time::SystemTime, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !(entry.file_type()?.is_file() || Package::is_package(&entry.path()))
+                || name.starts_with('.')
+                || name == SCORES_FILE
+                || CROSSTABLE_FILES.contains(&name.as_ref())
+            {
+                continue;
+            }
+            submissions.push((entry.metadata()?.modified()?, entry.path()));
+        }
+        submissions.sort();
+
+        let mut events = Vec::new();
+        for (_, path) in &submissions {
+            self.submit(path, &mut events)?;
+        }
+
+        if !submissions.is_empty() {
+            self.write_scores()?;
+        }
+
+        Ok(events)
+    }
+
+    fn submit(&mut self, path: &Path, events: &mut Vec<Event>) -> Result<(), Error> {
+        let id = self.hill.latest + 1;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let numbered = format!("{:04}-{}", id, file_name);
+
+        let warrior = read_warrior(path);
+        if warrior.is_err() {
+            self.telemetry.record(telemetry::Event::ParseFailure);
+        }
+
+        let battles = self.hill.members().len() as u64 * u64::from(self.hill.rounds);
+        let verdict = warrior.and_then(|mut warrior| {
+            if warrior.metadata.name.is_none() {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                warrior.metadata.name = Some(stem.into_owned());
+            }
+            if let Some(keys) = &self.author_keys {
+                signature::verify(&warrior, keys, &Ed25519).map_err(|err| err.to_string())?;
+            }
+            if let Some(member) = self.hill.duplicate_of(&warrior) {
+                return Err(Message::new("hill-duplicate")
+                    .arg("name", member.name())
+                    .to_string());
+            }
+            self.stream_challenge(&warrior);
+            self.hill
+                .challenge(id, warrior)
+                .map_err(|err| err.to_string())
+        });
+
+        if verdict.is_ok() {
+            self.telemetry
+                .record_many(telemetry::Event::BattleRun, battles);
+        }
+
+        match verdict {
+            Ok(Verdict::Accepted { rank, pushed_off }) => {
+                let destination = self.hill_dir().join(numbered);
+                fs::rename(path, &destination)?;
+                self.paths.insert(id, destination);
+
+                events.push(Event::Accepted {
+                    name: self.hill.member(id).name(),
+                    rank,
+                });
+                if let Some(member) = pushed_off {
+                    self.archive_member(member.id)?;
+                    events.push(Event::PushedOff {
+                        name: member.name(),
+                    });
+                }
+            }
+            Ok(Verdict::Rejected { rank, challenger }) => {
+                fs::rename(path, self.archive_dir().join(numbered))?;
+                events.push(Event::Rejected {
+                    name: challenger.name(),
+                    rank,
+                });
+            }
+            Err(message) => {
+                self.hill.skip(id);
+                fs::rename(path, self.archive_dir().join(numbered))?;
+                events.push(Event::Invalid {
+                    path: path.to_path_buf(),
+                    message,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stream_challenge(&mut self, challenger: &Warrior) {
+        let viewers = match self.stream.as_mut() {
+            Some(viewers) => viewers,
+            None => return,
+        };
+        if !viewers.is_watched() {
+            return;
+        }
+
+        for member in self.hill.members() {
+            // Warriors which can't battle are reported by the challenge itself
+            if let Ok(mut battle) = self.hill.first_round(&member.warrior, challenger) {
+                viewers.stream(&mut battle);
+            }
+        }
+    }
+
+    fn archive_member(&mut self, id: u32) -> io::Result<()> {
+        if let Some(path) = self.paths.remove(&id) {
+            let file_name = path.file_name().unwrap_or_default();
+            fs::rename(&path, self.archive_dir().join(file_name))?;
+        }
+        Ok(())
+    }
+
+    fn write_scores(&self) -> io::Result<()> {
+        let scores = koth::write_scores(&self.hill.standings());
+        fs::write(self.directory.join(SCORES_FILE), format!("{}\n", scores))?;
+
+        let crosstable = self.hill.crosstable();
+        let [text, json] = CROSSTABLE_FILES;
+        fs::write(self.directory.join(text), format!("{}\n", crosstable))?;
+        fs::write(self.directory.join(json), crosstable.to_json())
+    }
+
+    fn hill_dir(&self) -> PathBuf {
+        self.directory.join("hill")
+    }
+
+    fn archive_dir(&self) -> PathBuf {
+        self.directory.join("archive")
+    }
+}
+
+/// The files in `directory` whose names start with a submission number, in
+/// the order they were submitted.
+fn numbered_files(directory: &Path) -> io::Result<Vec<(u32, PathBuf)>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let id = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split('-').next())
+            .and_then(|number| number.parse().ok());
+
+        if let Some(id) = id {
+            files.push((id, path));
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Assemble the warrior in `path`, which may be a [package](crate::package).
+fn read_warrior(path: &Path) -> Result<Warrior, String> {
+    let path = package::warrior_source(path).map_err(|err| err.to_string())?;
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    match corewars_parser::parse(&source) {
+        corewars_parser::Result::Ok(warrior, _) => Ok(warrior),
+        corewars_parser::Result::Err(err, _) => Err(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::telemetry::JsonLines;
+
+    fn hill(size: usize) -> Hill {
+        let config = BattleConfig {
+            core_size: 800,
+            max_cycles: 2000,
+            max_length: 20,
+            min_distance: 20,
+            ..BattleConfig::default()
+        };
+        Hill::new(config, size, 4)
+    }
+
+    fn warrior(name: &str, program: &str) -> Warrior {
+        corewars_parser::parse(&format!(";name {}\n;author Tester\n{}", name, program))
+            .expect("Failed to parse warrior")
+    }
+
+    fn imp() -> Warrior {
+        warrior("Imp", "mov 0, 1")
+    }
+
+    fn dwarf() -> Warrior {
+        warrior("Dwarf", "add #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+    }
+
+    fn suicide() -> Warrior {
+        warrior("Suicide", "dat 0, 0")
+    }
+
+    #[test]
+    fn ranks_challengers() {
+        let mut hill = hill(2);
+
+        assert!(matches!(
+            hill.challenge(1, suicide()).unwrap(),
+            Verdict::Accepted {
+                rank: 1,
+                pushed_off: None
+            }
+        ));
+        assert!(matches!(
+            hill.challenge(2, imp()).unwrap(),
+            Verdict::Accepted {
+                rank: 1,
+                pushed_off: None
+            }
+        ));
+        assert_eq!(
+            hill.record(2, 1),
+            Some(Record {
+                wins: 4,
+                losses: 0,
+                ties: 0
+            })
+        );
+        assert_eq!(hill.record(1, 2).unwrap().losses, 4);
+
+        // The hill is full, so the suicide is pushed off
+        match hill.challenge(3, dwarf()).unwrap() {
+            Verdict::Accepted {
+                pushed_off: Some(member),
+                ..
+            } => assert_eq!(member.name(), "Suicide"),
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+        assert_eq!(hill.record(1, 3), None);
+
+        // Another suicide can't beat anything on the hill
+        match hill.challenge(5, suicide()).unwrap() {
+            Verdict::Rejected { rank, challenger } => {
+                assert_eq!(rank, 3);
+                assert_eq!(challenger.id, 5);
+            }
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+        assert_eq!(hill.members().len(), 2);
+    }
+
+    #[test]
+    fn rejects_unsupported_opcodes() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let mut server = Server::open(directory.path(), hill(2)).unwrap();
+        fs::write(
+            directory.path().join("pspace.red"),
+            ";name PSpace\nldp.ab #0, 1\njmp -1",
+        )
+        .unwrap();
+
+        let events = server.poll().unwrap();
+        match events.as_slice() {
+            [Event::Invalid { message, .. }] => {
+                assert!(
+                    message.contains("LDP, which is not supported yet"),
+                    "{}",
+                    message
+                )
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+        assert!(server.hill().members().is_empty());
+        assert!(!directory.path().join("pspace.red").exists());
+        assert!(directory.path().join("archive/0001-pspace.red").exists());
+
+        // Nothing is left to challenge with again after a restart
+        let mut server = Server::open(directory.path(), hill(2)).unwrap();
+        assert!(server.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn requires_signatures() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let mut server = Server::open(directory.path(), hill(2)).unwrap();
+        let mut keys = AuthorKeys::new();
+        keys.register(
+            "Tester",
+            "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8",
+        )
+        .unwrap();
+        server.set_author_keys(keys);
+
+        let mut submit = |file: &str, source: &str| {
+            fs::write(directory.path().join(file), source).unwrap();
+            server.poll().unwrap()
+        };
+
+        // Signed by the key registered for Tester
+        let events = submit(
+            "imp.red",
+            ";name Imp\n;author Tester\n\
+             ;signature 70a62050345860b2662955d4e9eea7c96da730155d25a82acad728ccd251b55b\
+             4ee5014387964e9b36c6478f6f431a0eaed78cf4f11e8aacb9a6c55f1b56da03\n\
+             mov 0, 1",
+        );
+        assert_eq!(
+            events,
+            [Event::Accepted {
+                name: "Imp".into(),
+                rank: 1
+            }]
+        );
+
+        // Signed by someone else's key
+        let events = submit(
+            "dwarf.red",
+            ";name Dwarf\n;author Tester\n\
+             ;signature 0dd899e8c8cbbc7272fa2d653ffa4e921e911c4b30c62bbe122078430c82e507\
+             0b34efe6f4f3b0bd9b6afd0d48a97d2f2d05f68cffd8beab4a4fc686fb814302\n\
+             add #4, 3\nmov 2, @2\njmp -2\ndat #0, #0",
+        );
+        match events.as_slice() {
+            [Event::Invalid { message, .. }] => assert_eq!(
+                message,
+                "signature does not match any key registered for \"Tester\""
+            ),
+            events => panic!("unexpected events {:?}", events),
+        }
+
+        let events = submit("suicide.red", ";name Suicide\n;author Tester\ndat 0, 0");
+        match events.as_slice() {
+            [Event::Invalid { message, .. }] => assert_eq!(message, "warrior is not signed"),
+            events => panic!("unexpected events {:?}", events),
+        }
+
+        assert_eq!(server.hill().members().len(), 1);
+        assert!(directory.path().join("hill/0001-imp.red").exists());
+        assert!(directory.path().join("archive/0002-dwarf.red").exists());
+        assert!(directory.path().join("archive/0003-suicide.red").exists());
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let mut server = Server::open(directory.path(), hill(2)).unwrap();
+
+        fs::write(directory.path().join("imp.red"), ";name Imp\nmov 0, 1").unwrap();
+        server.poll().unwrap();
+
+        // Renamed and reformatted, but the same program
+        fs::write(
+            directory.path().join("copy.red"),
+            ";name Copy\n;author Someone\nstart   MOV.I $0, $1 ; step\n",
+        )
+        .unwrap();
+        let events = server.poll().unwrap();
+        match events.as_slice() {
+            [Event::Invalid { message, .. }] => assert_eq!(
+                message,
+                "it is the same program as Imp, already on the hill"
+            ),
+            events => panic!("unexpected events {:?}", events),
+        }
+        assert!(directory.path().join("archive/0002-copy.red").exists());
+
+        fs::write(directory.path().join("imp2.red"), ";name Imp 2\nmov 0, 2").unwrap();
+        assert!(matches!(
+            server.poll().unwrap().as_slice(),
+            [Event::Accepted { .. }]
+        ));
+    }
+
+    #[test]
+    fn reports_telemetry() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let reports = assert_fs::TempDir::new().unwrap();
+        let report_path = reports.path().join("telemetry.jsonl");
+        let mut server = Server::open(directory.path(), hill(2)).unwrap();
+        server.set_telemetry(Telemetry::new(
+            JsonLines(fs::File::create(&report_path).unwrap()),
+            Duration::from_secs(3600),
+        ));
+
+        // Nothing to battle, then 4 rounds against the imp, then nothing parsed
+        for (file, source) in [
+            ("imp.red", ";name Imp\nmov 0, 1"),
+            (
+                "dwarf.red",
+                ";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0",
+            ),
+            ("broken.red", ";name Broken\nmov 0,"),
+        ] {
+            fs::write(directory.path().join(file), source).unwrap();
+            server.poll().unwrap();
+        }
+        assert_eq!(fs::read_to_string(&report_path).unwrap(), "");
+
+        drop(server);
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["battles_run"], 4);
+        assert_eq!(report["parse_failures"], 1);
+    }
+
+    #[test]
+    fn standings() {
+        let mut hill = hill(5);
+        hill.challenge(1, imp()).unwrap();
+        hill.challenge(2, suicide()).unwrap();
+        hill.skip(4);
+
+        let standings = hill.standings();
+        assert_eq!(
+            standings[0],
+            Standing {
+                rank: 1,
+                wins: 100,
+                losses: 0,
+                ties: 0,
+                name: "Imp".into(),
+                author: "Tester".into(),
+                score: 300.0,
+                age: 3,
+            }
+        );
+        assert_eq!(
+            (
+                standings[1].name.as_str(),
+                standings[1].losses,
+                standings[1].age
+            ),
+            ("Suicide", 100, 2)
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! Self-contained HTML reports of battles and tournaments, with tables of
+//! scores and per-warrior statistics, and a replay viewer which draws the core
+//! on a canvas.
+//!
+//! Reports don't load anything from elsewhere, so they can be opened straight
+//! from disk or attached to an email. The replay is embedded as the JSON
+//! written by [`Replay::to_json`].
+
+use std::fmt::Write;
+
+use corewars_core::Warrior;
+use corewars_sim::{Core, Outcome};
+
+use super::format::{Error, OutputFormat};
+use super::replay::Replay;
+
+/// Points scored for winning a round.
+pub const WIN_POINTS: usize = 3;
+
+/// Points scored by each survivor of a tied round.
+pub const TIE_POINTS: usize = 1;
+
+/// A warrior's results over every round of a report.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Standing {
+    pub name: String,
+    pub author: Option<String>,
+
+    /// The number of instructions, if the warrior was added to the report
+    pub length: Option<u32>,
+
+    pub wins: usize,
+    pub ties: usize,
+    pub losses: usize,
+    pub score: usize,
+}
+
+/// A report of the rounds of a battle or tournament.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    title: String,
+    warriors: Vec<Standing>,
+    rounds: Vec<Outcome>,
+    replay: Option<Replay>,
+}
+
+impl Report {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Add a warrior which took part, so it's listed with its author and
+    /// length even if it never survived a round.
+    pub fn add_warrior(&mut self, warrior: &Warrior) {
+        let name = warrior
+            .metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Warrior{}", self.warriors.len()));
+
+        self.warriors.push(Standing {
+            name,
+            author: warrior.metadata.author.clone(),
+            length: Some(warrior.len()),
+            ..Standing::default()
+        });
+    }
+
+    pub fn add_round(&mut self, outcome: Outcome) {
+        self.rounds.push(outcome);
+    }
+
+    /// Embed a replay of one of the rounds, to be shown in the viewer.
+    pub fn set_replay(&mut self, replay: Replay) {
+        self.replay = Some(replay);
+    }
+
+    /// Every warrior's results, from highest to lowest score. Warriors with
+    /// the same score stay in the order they were added. Warriors which
+    /// weren't added but appear in an outcome are listed after them.
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings = self.warriors.clone();
+
+        for outcome in &self.rounds {
+            let survivors = match outcome {
+                Outcome::Win(name) => std::slice::from_ref(name),
+                Outcome::Tie(names) => names.as_slice(),
+            };
+            for name in survivors {
+                if !standings.iter().any(|standing| &standing.name == name) {
+                    standings.push(Standing {
+                        name: name.clone(),
+                        ..Standing::default()
+                    });
+                }
+            }
+        }
+
+        for standing in &mut standings {
+            for outcome in &self.rounds {
+                match outcome {
+                    Outcome::Win(name) if *name == standing.name => standing.wins += 1,
+                    Outcome::Tie(names) if names.contains(&standing.name) => standing.ties += 1,
+                    _ => standing.losses += 1,
+                }
+            }
+            standing.score = standing.wins * WIN_POINTS + standing.ties * TIE_POINTS;
+        }
+
+        standings.sort_by_key(|standing| std::cmp::Reverse(standing.score));
+        standings
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut body = String::new();
+
+        body.push_str("<h2>Standings</h2>\n<table>\n");
+        body.push_str(
+            "<tr><th>#</th><th>Warrior</th><th>Author</th><th>Length</th>\
+             <th>Wins</th><th>Ties</th><th>Losses</th><th>Score</th></tr>\n",
+        );
+        for (rank, standing) in self.standings().iter().enumerate() {
+            let length = standing
+                .length
+                .map_or_else(String::new, |length| length.to_string());
+
+            let _ = writeln!(
+                body,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                rank + 1,
+                escape(&standing.name),
+                escape(standing.author.as_deref().unwrap_or("")),
+                length,
+                standing.wins,
+                standing.ties,
+                standing.losses,
+                standing.score,
+            );
+        }
+        body.push_str("</table>\n");
+
+        body.push_str("<h2>Rounds</h2>\n<table>\n<tr><th>Round</th><th>Result</th></tr>\n");
+        for (round, outcome) in self.rounds.iter().enumerate() {
+            let _ = writeln!(
+                body,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                round + 1,
+                escape(&outcome.to_string())
+            );
+        }
+        body.push_str("</table>\n");
+
+        if let Some(replay) = &self.replay {
+            body.push_str(VIEWER);
+            // The JSON is inside a script element, so it mustn't end it early
+            let _ = writeln!(
+                body,
+                "<script id=\"replay\" type=\"application/json\">{}</script>",
+                replay.to_json().replace("</", "<\\/")
+            );
+            body.push_str(VIEWER_SCRIPT);
+        }
+
+        page(&self.title, &body)
+    }
+}
+
+/// Reports for a single battle outcome, and pages showing a warrior or core
+/// in load file format.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Html;
+
+impl OutputFormat for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn warrior(&self, warrior: &Warrior) -> Result<String, Error> {
+        let title = warrior.metadata.name.as_deref().unwrap_or("Warrior");
+        let body = format!("<pre>{}</pre>\n", escape(&warrior.to_string()));
+        Ok(page(title, &body))
+    }
+
+    fn core(&self, core: &Core) -> Result<String, Error> {
+        let title = format!("Core after {} cycles", core.cycles());
+        let body = format!("<pre>{}</pre>\n", escape(&core.to_string()));
+        Ok(page(&title, &body))
+    }
+
+    fn outcome(&self, outcome: &Outcome) -> Result<String, Error> {
+        let mut report = Report::new("Battle");
+        report.add_round(outcome.clone());
+        Ok(report.to_html())
+    }
+}
+
+/// Escape `text` for use in HTML content or attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n{body}</body>\n</html>\n",
+        title = escape(title),
+        style = STYLE,
+        body = body,
+    )
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left; }
+th { background: #eee; }
+pre { background: #f6f6f6; padding: 1em; }
+canvas { border: 1px solid #ccc; image-rendering: pixelated; }
+#controls { margin: 0.5em 0; }
+#legend span { margin-right: 1em; }
+";
+
+const VIEWER: &str = r#"<h2>Replay</h2>
+<div id="legend"></div>
+<canvas id="core"></canvas>
+<div id="controls">
+<button id="play">Play</button>
+<input id="seek" type="range" min="0" value="0">
+<span id="cycle"></span>
+</div>
+"#;
+
+// Each cell is coloured by the warrior which last wrote it, with the cell
+// executed in the current cycle highlighted. Seeking backwards replays the
+// frames from the start, since frames only record what changed.
+const VIEWER_SCRIPT: &str = r##"<script>
+(function () {
+  var replay = JSON.parse(document.getElementById("replay").textContent);
+  var colors = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf"];
+  var columns = 100, cell = 6;
+  var rows = Math.ceil(replay.core_size / columns);
+  var canvas = document.getElementById("core");
+  canvas.width = columns * cell;
+  canvas.height = rows * cell;
+  var context = canvas.getContext("2d");
+  var seek = document.getElementById("seek");
+  seek.max = replay.frames.length;
+
+  var legend = document.getElementById("legend");
+  replay.warriors.forEach(function (name, i) {
+    var span = document.createElement("span");
+    span.style.color = colors[i % colors.length];
+    span.textContent = "■ " + name;
+    legend.appendChild(span);
+  });
+
+  var owner, position, executed;
+  function reset() {
+    owner = new Int16Array(replay.core_size).fill(-1);
+    replay.initial.forEach(function (address) { owner[address] = -2; });
+    position = 0;
+    executed = -1;
+  }
+
+  function advance(to) {
+    if (to < position) { reset(); }
+    for (; position < to; position++) {
+      var frame = replay.frames[position];
+      frame[2].forEach(function (address) { owner[address] = frame[0]; });
+      executed = frame[1];
+    }
+  }
+
+  function draw() {
+    context.fillStyle = "#fff";
+    context.fillRect(0, 0, canvas.width, canvas.height);
+    for (var address = 0; address < replay.core_size; address++) {
+      var who = owner[address];
+      if (who === -1 && address !== executed) { continue; }
+      context.fillStyle = address === executed ? "#000" : who === -2 ? "#999" : colors[who % colors.length];
+      context.fillRect((address % columns) * cell, Math.floor(address / columns) * cell, cell - 1, cell - 1);
+    }
+    document.getElementById("cycle").textContent = "cycle " + position + " of " + replay.frames.length;
+    seek.value = position;
+  }
+
+  var timer = null;
+  var play = document.getElementById("play");
+  play.onclick = function () {
+    if (timer) {
+      clearInterval(timer);
+      timer = null;
+      play.textContent = "Play";
+      return;
+    }
+    if (position >= replay.frames.length) { reset(); }
+    play.textContent = "Pause";
+    timer = setInterval(function () {
+      advance(Math.min(position + 50, replay.frames.length));
+      draw();
+      if (position >= replay.frames.length) { play.onclick(); }
+    }, 30);
+  };
+  seek.oninput = function () { advance(Number(seek.value)); draw(); };
+
+  reset();
+  draw();
+})();
+</script>
+"##;
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn scores_rounds() {
+        let mut report = Report::new("Imp <vs> Dwarf");
+        report.add_warrior(
+            &corewars_parser::parse(";name Imp\n;author A. K. Dewdney\nmov 0, 1").unwrap(),
+        );
+        report.add_warrior(
+            &corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+                .unwrap(),
+        );
+
+        report.add_round(Outcome::Win("Dwarf".into()));
+        report.add_round(Outcome::Tie(vec!["Imp".into(), "Dwarf".into()]));
+        report.add_round(Outcome::Win("Dwarf".into()));
+
+        let standings = report.standings();
+        assert_eq!(
+            standings,
+            vec![
+                Standing {
+                    name: "Dwarf".into(),
+                    author: None,
+                    length: Some(4),
+                    wins: 2,
+                    ties: 1,
+                    losses: 0,
+                    score: 7,
+                },
+                Standing {
+                    name: "Imp".into(),
+                    author: Some("A. K. Dewdney".into()),
+                    length: Some(1),
+                    wins: 0,
+                    ties: 1,
+                    losses: 2,
+                    score: 1,
+                },
+            ]
+        );
+
+        let html = report.to_html();
+        assert!(html.contains("<title>Imp &lt;vs&gt; Dwarf</title>"));
+        assert!(html.contains("<td>1</td><td>Dwarf</td><td></td><td>4</td>"));
+        assert!(!html.contains("<canvas"));
+    }
+
+    #[test]
+    fn embeds_replay() {
+        let mut core = Core::new(100).unwrap();
+        core.set_trace(false);
+        core.load_warrior(&corewars_parser::parse(";name </script>\nmov 0, 1").unwrap())
+            .unwrap();
+
+        let mut replay = Replay::new(&core);
+        core.step().unwrap();
+        replay.record(&core);
+
+        let mut report = Report::new("Replay");
+        report.set_replay(replay);
+
+        let html = report.to_html();
+        assert!(html.contains("<canvas id=\"core\">"));
+        assert!(html.contains(r#""warriors":["<\/script>"]"#));
+        assert_eq!(html.matches("</script>").count(), 2);
+    }
+}
@@ -0,0 +1,361 @@
+//! An index of warrior metadata across a corpus of Redcode files, which can be
+//! searched or used to find duplicate warriors.
+
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use corewars_core::Warrior;
+use corewars_parser as parser;
+
+use crate::mapped::Mapped;
+
+/// File extensions which are treated as Redcode when crawling a corpus.
+pub const EXTENSIONS: &[&str] = &["red", "redcode"];
+
+/// Metadata about a single warrior file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The path of the file, relative to the root of the corpus
+    pub path: PathBuf,
+
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+
+    /// The `;assert` expression of the warrior, if any
+    pub assertion: Option<String>,
+
+    /// The number of instructions in the assembled warrior
+    pub length: u32,
+
+    /// The [digest](Warrior::digest) of the assembled program, so warriors
+    /// with the same program have the same hash.
+    pub hash: String,
+}
+
+impl Entry {
+    /// Create an entry for a warrior loaded from `path`.
+    pub fn new(path: PathBuf, warrior: &Warrior) -> Self {
+        let metadata = &warrior.metadata;
+
+        Self {
+            path,
+            name: metadata.name.clone(),
+            author: metadata.author.clone(),
+            date: metadata.date.clone(),
+            assertion: metadata.assertion.clone(),
+            length: warrior.len(),
+            hash: warrior.digest(),
+        }
+    }
+}
+
+/// A file in the corpus which could not be indexed.
+#[derive(Debug)]
+pub struct Failure {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// Crawl `root` for Redcode files and index each warrior in them, parsing
+/// files in parallel on as many threads as there are cores. Files which
+/// cannot be read or parsed are returned separately. Entries are sorted by path.
+///
+/// The files are mapped into memory rather than copied, so nothing may write
+/// to or truncate them until this returns.
+pub fn build(root: &Path) -> (Vec<Entry>, Vec<Failure>) {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    build_with_threads(root, threads)
+}
+
+/// Like [`build`], parsing files on at most `threads` threads. Each file is
+/// indexed on its own, so a file which fails to parse, or even crashes the
+/// parser, is returned as a failure without affecting any other.
+pub fn build_with_threads(root: &Path, threads: usize) -> (Vec<Entry>, Vec<Failure>) {
+    let files: Vec<PathBuf> = WalkDir::new(root)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| EXTENSIONS.contains(&extension))
+        })
+        .map(|entry| entry.into_path())
+        .collect();
+
+    // Workers take the next file until there are none left, so a few slow
+    // files don't hold up the rest
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<Warrior, String>>>> =
+        Mutex::new((0..files.len()).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, files.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let path = match files.get(i) {
+                    Some(path) => path,
+                    None => break,
+                };
+
+                let warrior = index_file(path);
+                results.lock().unwrap_or_else(PoisonError::into_inner)[i] = Some(warrior);
+            });
+        }
+    });
+
+    let mut entries = Vec::new();
+    let mut failures = Vec::new();
+    let results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    for (path, warrior) in files.iter().zip(results) {
+        let relative = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        match warrior.expect("every file should be indexed") {
+            Ok(warrior) => entries.push(Entry::new(relative, &warrior)),
+            Err(message) => failures.push(Failure {
+                path: relative,
+                message,
+            }),
+        }
+    }
+
+    (entries, failures)
+}
+
+/// Read and parse the warrior in the file at `path`, which must not be
+/// changed while it is indexed.
+fn index_file(path: &Path) -> Result<Warrior, String> {
+    // SAFETY: the corpus isn't written to while it is being indexed, and the
+    // mapping is dropped before this returns
+    let contents = unsafe { Mapped::open(path) }.map_err(|err| err.to_string())?;
+    let input = str::from_utf8(&contents).map_err(|err| err.to_string())?;
+
+    match panic::catch_unwind(|| parser::parse(input)) {
+        Ok(parser::Result::Ok(warrior, _)) => Ok(warrior),
+        Ok(parser::Result::Err(err, _)) => Err(err.to_string()),
+        Err(_) => Err("the parser crashed on this file".into()),
+    }
+}
+
+/// Criteria for searching an index. Every criterion that is set must match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    /// Text which must appear in the warrior's name, ignoring case
+    pub name: Option<String>,
+
+    /// Text which must appear in the warrior's author, ignoring case
+    pub author: Option<String>,
+
+    pub min_length: Option<u32>,
+    pub max_length: Option<u32>,
+
+    /// The exact hash of the warrior's program
+    pub hash: Option<String>,
+}
+
+impl Query {
+    /// Whether an entry matches all criteria of this query.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let contains = |field: &Option<String>, text: &Option<String>| match text {
+            Some(text) => field
+                .as_deref()
+                .is_some_and(|field| field.to_lowercase().contains(&text.to_lowercase())),
+            None => true,
+        };
+
+        contains(&entry.name, &self.name)
+            && contains(&entry.author, &self.author)
+            && self.min_length.is_none_or(|min| entry.length >= min)
+            && self.max_length.is_none_or(|max| entry.length <= max)
+            && self.hash.as_ref().is_none_or(|hash| entry.hash == *hash)
+    }
+
+    /// All entries matching this query, in their original order.
+    pub fn search<'a>(&self, entries: &'a [Entry]) -> Vec<&'a Entry> {
+        entries.iter().filter(|entry| self.matches(entry)).collect()
+    }
+}
+
+/// Write an index as a JSON array.
+pub fn to_json(entries: &[Entry]) -> String {
+    serde_json::to_string_pretty(entries).expect("index entries are serializable")
+}
+
+/// Read an index previously written by [`to_json`](to_json).
+pub fn from_json(text: &str) -> serde_json::Result<Vec<Entry>> {
+    serde_json::from_str(text)
+}
+
+/// Write an index as CSV, with a header row.
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut lines = vec!["path,name,author,date,assertion,length,hash".to_string()];
+
+    for entry in entries {
+        let fields = [
+            entry.path.display().to_string(),
+            entry.name.clone().unwrap_or_default(),
+            entry.author.clone().unwrap_or_default(),
+            entry.date.clone().unwrap_or_default(),
+            entry.assertion.clone().unwrap_or_default(),
+            entry.length.to_string(),
+            entry.hash.clone(),
+        ];
+
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        lines.push(fields.join(","));
+    }
+
+    lines.join("\n")
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn parse(input: &str) -> Warrior {
+        match parser::parse(input) {
+            parser::Result::Ok(warrior, _) => warrior,
+            parser::Result::Err(err, _) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn hash_ignores_formatting() {
+        let plain = Entry::new(PathBuf::from("a.red"), &parse(";name Imp\nmov 0, 1\n"));
+        let formatted = Entry::new(
+            PathBuf::from("b.red"),
+            &parse(";name Imp, again\n;author Somebody\nstart  MOV.I $0, $1 ; imp\n"),
+        );
+
+        assert_eq!(plain.hash, formatted.hash);
+        assert_eq!(plain.length, 1);
+        assert_ne!(
+            plain.hash,
+            Entry::new(PathBuf::from("c.red"), &parse("mov 0, 2\n")).hash
+        );
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let entries = vec![Entry::new(
+            PathBuf::from("imp.red"),
+            &parse(";name Imp\n;author A. K. Dewdney\n;assert 1\nmov 0, 1\n"),
+        )];
+
+        assert_eq!(from_json(&to_json(&entries)).unwrap(), entries);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let entries = vec![Entry::new(
+            PathBuf::from("imp.red"),
+            &parse(";name Imp, \"the\" original\nmov 0, 1\n"),
+        )];
+
+        let csv = to_csv(&entries);
+        let row = csv.lines().nth(1).unwrap();
+
+        assert!(row.starts_with("imp.red,\"Imp, \"\"the\"\" original\",,,,1,"));
+    }
+
+    #[test]
+    fn query() {
+        let entries = vec![
+            Entry::new(
+                PathBuf::from("imp.red"),
+                &parse(";name Imp\n;author A. K. Dewdney\nmov 0, 1\n"),
+            ),
+            Entry::new(
+                PathBuf::from("dwarf.red"),
+                &parse(";name Dwarf\n;author A. K. Dewdney\nadd #4, 3\nmov 2, @2\njmp -2\n"),
+            ),
+            Entry::new(PathBuf::from("anonymous.red"), &parse("mov 0, 1\n")),
+        ];
+
+        let paths = |query: Query| -> Vec<&Path> {
+            query
+                .search(&entries)
+                .iter()
+                .map(|entry| entry.path.as_path())
+                .collect()
+        };
+
+        assert_eq!(
+            paths(Query {
+                author: Some("dewdney".into()),
+                ..Query::default()
+            }),
+            vec![Path::new("imp.red"), Path::new("dwarf.red")]
+        );
+        assert_eq!(
+            paths(Query {
+                author: Some("dewdney".into()),
+                min_length: Some(2),
+                ..Query::default()
+            }),
+            vec![Path::new("dwarf.red")]
+        );
+        assert_eq!(
+            paths(Query {
+                hash: Some(entries[0].hash.clone()),
+                ..Query::default()
+            }),
+            vec![Path::new("imp.red"), Path::new("anonymous.red")]
+        );
+    }
+
+    #[test]
+    fn builds_from_directory() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata/input");
+        let (entries, _) = build(&root);
+
+        assert!(entries
+            .iter()
+            .any(|entry| entry.path == Path::new("simple").join("basic.redcode")));
+    }
+
+    #[test]
+    fn isolates_failures() {
+        let root = assert_fs::TempDir::new().unwrap();
+        let write = |name: &str, contents: &[u8]| fs::write(root.path().join(name), contents);
+        write("imp.red", b";name Imp\nmov 0, 1\n").unwrap();
+        write("broken.red", b"mov 0, 1, 2\n").unwrap();
+        write("binary.red", &[0xff, 0xfe, 0]).unwrap();
+        write("dwarf.red", b";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\n").unwrap();
+
+        let (entries, failures) = build_with_threads(root.path(), 3);
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_deref()).collect();
+        assert_eq!(names, vec![Some("Dwarf"), Some("Imp")]);
+
+        let failed: Vec<_> = failures.iter().map(|failure| &failure.path).collect();
+        assert_eq!(
+            failed,
+            vec![Path::new("binary.red"), Path::new("broken.red")]
+        );
+
+        let (serial, _) = build_with_threads(root.path(), 1);
+        assert_eq!(serial, entries);
+    }
+}
@@ -0,0 +1,100 @@
+//! Stopping long runs cleanly on Ctrl-C, so they can be resumed later rather
+//! than losing everything computed so far.
+//!
+//! After [`install`] is called, the first Ctrl-C only sets a flag, which long
+//! running commands check with [`requested`] between units of work. They then
+//! stop, keep the results they have, and print a [`StateToken`] describing
+//! how far they got. A second Ctrl-C exits immediately.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl-C (`SIGINT`) for the rest of the program. This does nothing on
+/// platforms other than Unix, where Ctrl-C still exits immediately.
+pub fn install() {
+    #[cfg(unix)]
+    {
+        extern "C" fn handle(_signal: libc::c_int) {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                // SAFETY: _exit is async-signal-safe, unlike process::exit
+                unsafe { libc::_exit(130) };
+            }
+        }
+
+        // SAFETY: the handler only touches an atomic and calls _exit
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+/// Whether Ctrl-C was pressed since [`install`] was called.
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// How far an interrupted command got: the command's name and how many of
+/// its units of work (e.g. battles or rounds) were completed, in order.
+/// Written as `command:completed`, e.g. `bench:37`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateToken {
+    pub command: String,
+    pub completed: usize,
+}
+
+impl fmt::Display for StateToken {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}:{}", self.command, self.completed)
+    }
+}
+
+impl FromStr for StateToken {
+    type Err = String;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid state token {:?}", token);
+
+        let (command, completed) = token.rsplit_once(':').ok_or_else(invalid)?;
+        if command.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            command: command.to_string(),
+            completed: completed.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn state_token_round_trip() {
+        let token = StateToken {
+            command: "bench".into(),
+            completed: 37,
+        };
+
+        assert_eq!(token.to_string(), "bench:37");
+        assert_eq!("bench:37".parse(), Ok(token));
+        assert!("bench".parse::<StateToken>().is_err());
+        assert!(":3".parse::<StateToken>().is_err());
+        assert!("bench:x".parse::<StateToken>().is_err());
+    }
+
+    #[test]
+    fn not_requested_by_default() {
+        install();
+        assert!(!requested());
+    }
+}
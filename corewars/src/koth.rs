@@ -0,0 +1,339 @@
+//! Reading and writing the result formats used by KOTH ("King of the Hill")
+//! servers, so results can be exchanged with existing hill frontends and
+//! archives.
+//!
+//! A scores file lists the standings on a hill, one warrior per line, with
+//! the name and author right-aligned under their column headers:
+//!
+//! ```text
+//!  #  %W/ %L/ %T                      Name               Author   Score     Age
+//!  1  43/ 32/ 25                 Firestorm         John Metcalf   153.2      20
+//! ```
+//!
+//! A results file is the output of pMARS for one or more battles:
+//!
+//! ```text
+//! Firestorm by John Metcalf scores 153
+//! Imp by A. K. Dewdney scores 75
+//! Results: 43 25 32
+//! ```
+
+use std::fmt;
+
+use thiserror::Error as ThisError;
+
+/// An error in a score or results file.
+#[derive(ThisError, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The header line with column names was missing or malformed.
+    #[error("missing or invalid header line")]
+    InvalidHeader,
+
+    /// A line could not be parsed.
+    #[error("invalid line {line}: {text:?}")]
+    InvalidLine { line: usize, text: String },
+}
+
+/// The header of a scores file. Names and authors are right-aligned to the
+/// end of their column name.
+const SCORES_HEADER: &str =
+    " #  %W/ %L/ %T                      Name               Author   Score     Age";
+
+/// One warrior's standing on a hill.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub rank: u32,
+
+    /// Percentage of rounds won, lost and tied
+    pub wins: u32,
+    pub losses: u32,
+    pub ties: u32,
+
+    pub name: String,
+    pub author: String,
+    pub score: f64,
+
+    /// The number of warriors that have been submitted since this one
+    pub age: u32,
+}
+
+/// Parse the standings in a scores file. Any lines before the header are ignored.
+pub fn read_scores(text: &str) -> Result<Vec<Standing>, Error> {
+    let mut lines = text.lines().enumerate();
+
+    let header = lines
+        .by_ref()
+        .map(|(_, line)| line)
+        .find(|line| line.trim_start().starts_with('#'))
+        .ok_or(Error::InvalidHeader)?;
+
+    let column_end = |name: &str| header.find(name).map(|start| start + name.len());
+    let (name_end, author_end) = match (column_end("Name"), column_end("Author")) {
+        (Some(name_end), Some(author_end)) if name_end < author_end => (name_end, author_end),
+        _ => return Err(Error::InvalidHeader),
+    };
+    let results_end = header.find("%T").ok_or(Error::InvalidHeader)? + "%T".len();
+
+    let mut standings = Vec::new();
+
+    for (index, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+        let invalid = || Error::InvalidLine {
+            line: index + 1,
+            text: line.to_string(),
+        };
+
+        let column = |start: usize, end: usize| line.get(start..end).map(str::trim);
+        let results = column(0, results_end).ok_or_else(invalid)?;
+        let name = column(results_end, name_end).ok_or_else(invalid)?;
+        let author = column(name_end, author_end).ok_or_else(invalid)?;
+        let rest = line.get(author_end..).ok_or_else(invalid)?;
+
+        let numbers: Vec<&str> = results
+            .split(|c: char| c == '/' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .chain(rest.split_whitespace())
+            .collect();
+
+        let standing = match numbers.as_slice() {
+            [rank, wins, losses, ties, score, age] => (|| {
+                Some(Standing {
+                    rank: rank.parse().ok()?,
+                    wins: wins.parse().ok()?,
+                    losses: losses.parse().ok()?,
+                    ties: ties.parse().ok()?,
+                    name: name.to_string(),
+                    author: author.to_string(),
+                    score: score.parse().ok()?,
+                    age: age.parse().ok()?,
+                })
+            })(),
+            _ => None,
+        };
+
+        standings.push(standing.ok_or_else(invalid)?);
+    }
+
+    Ok(standings)
+}
+
+/// Write standings in the scores file format. Names and authors which are
+/// too long for their column are truncated.
+pub fn write_scores(standings: &[Standing]) -> String {
+    let mut lines = vec![SCORES_HEADER.to_string()];
+
+    for standing in standings {
+        lines.push(format!(
+            "{:>2}  {:>2}/ {:>2}/ {:>2} {:>25} {:>20} {:>7.1} {:>7}",
+            standing.rank,
+            standing.wins,
+            standing.losses,
+            standing.ties,
+            truncate(&standing.name, 25),
+            truncate(&standing.author, 20),
+            standing.score,
+            standing.age,
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn truncate(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// One warrior's result in a battle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WarriorScore {
+    pub name: String,
+    pub author: String,
+    pub score: u32,
+}
+
+/// The result of a battle, as printed by pMARS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BattleResult {
+    /// The score of each warrior, in the order they were loaded
+    pub scores: Vec<WarriorScore>,
+
+    /// The values of the `Results:` line. For a battle between two warriors,
+    /// these are the first warrior's wins, the second warrior's wins and the
+    /// number of ties.
+    pub results: Vec<u32>,
+}
+
+impl fmt::Display for BattleResult {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for score in &self.scores {
+            writeln!(
+                formatter,
+                "{} by {} scores {}",
+                score.name, score.author, score.score
+            )?;
+        }
+
+        let results: Vec<String> = self.results.iter().map(u32::to_string).collect();
+        write!(formatter, "Results: {}", results.join(" "))
+    }
+}
+
+/// Parse the results of one or more battles from pMARS output.
+pub fn read_results(text: &str) -> Result<Vec<BattleResult>, Error> {
+    let mut battles = Vec::new();
+    let mut scores = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = || Error::InvalidLine {
+            line: index + 1,
+            text: line.to_string(),
+        };
+
+        if let Some(results) = line.strip_prefix("Results:") {
+            let results = results
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?;
+
+            battles.push(BattleResult {
+                scores: std::mem::take(&mut scores),
+                results,
+            });
+            continue;
+        }
+
+        let (warrior, score) = line.rsplit_once(" scores ").ok_or_else(invalid)?;
+        let (name, author) = warrior.rsplit_once(" by ").ok_or_else(invalid)?;
+
+        scores.push(WarriorScore {
+            name: name.to_string(),
+            author: author.to_string(),
+            score: score.trim().parse().map_err(|_| invalid())?,
+        });
+    }
+
+    if let Some(score) = scores.first() {
+        // Scores without a "Results:" line, e.g. from truncated output
+        return Err(Error::InvalidLine {
+            line: text.lines().count(),
+            text: format!("{} by {} scores {}", score.name, score.author, score.score),
+        });
+    }
+
+    Ok(battles)
+}
+
+/// Write the results of battles in the pMARS output format.
+pub fn write_results(battles: &[BattleResult]) -> String {
+    let battles: Vec<String> = battles.iter().map(BattleResult::to_string).collect();
+    battles.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn standings() -> Vec<Standing> {
+        vec![
+            Standing {
+                rank: 1,
+                wins: 43,
+                losses: 32,
+                ties: 25,
+                name: "Firestorm".into(),
+                author: "John Metcalf".into(),
+                score: 153.2,
+                age: 20,
+            },
+            Standing {
+                rank: 2,
+                wins: 5,
+                losses: 60,
+                ties: 35,
+                name: "Imp".into(),
+                author: "A. K. Dewdney".into(),
+                score: 50.0,
+                age: 1234,
+            },
+        ]
+    }
+
+    #[test]
+    fn scores_roundtrip() {
+        let text = write_scores(&standings());
+
+        assert_eq!(
+            text.lines().nth(1).unwrap(),
+            " 1  43/ 32/ 25                 Firestorm         John Metcalf   153.2      20"
+        );
+        assert_eq!(read_scores(&text).unwrap(), standings());
+    }
+
+    #[test]
+    fn scores_with_preamble() {
+        let text = format!("94nop hill standings\n\n{}\n", write_scores(&standings()));
+        assert_eq!(read_scores(&text).unwrap(), standings());
+    }
+
+    #[test]
+    fn invalid_scores() {
+        assert_eq!(read_scores("1 2 3"), Err(Error::InvalidHeader));
+
+        let text = format!("{}\n 1  oops", SCORES_HEADER);
+        assert_eq!(
+            read_scores(&text),
+            Err(Error::InvalidLine {
+                line: 2,
+                text: " 1  oops".into()
+            })
+        );
+    }
+
+    #[test]
+    fn results_roundtrip() {
+        let text = [
+            "Firestorm by John Metcalf scores 153",
+            "Imp by A. K. Dewdney scores 75",
+            "Results: 43 25 32",
+        ]
+        .join("\n");
+
+        let battles = read_results(&text).unwrap();
+
+        assert_eq!(
+            battles,
+            vec![BattleResult {
+                scores: vec![
+                    WarriorScore {
+                        name: "Firestorm".into(),
+                        author: "John Metcalf".into(),
+                        score: 153,
+                    },
+                    WarriorScore {
+                        name: "Imp".into(),
+                        author: "A. K. Dewdney".into(),
+                        score: 75,
+                    },
+                ],
+                results: vec![43, 25, 32],
+            }]
+        );
+        assert_eq!(write_results(&battles), text);
+    }
+
+    #[test]
+    fn truncated_results() {
+        assert!(read_results("Imp by A. K. Dewdney scores 75\n").is_err());
+    }
+}
@@ -1,2 +1,27 @@
 // Public modules
+pub mod blind;
 pub mod cli;
+pub mod corewar_io;
+pub mod corpus;
+pub mod debugger;
+pub mod format;
+pub mod hill;
+pub mod html;
+pub mod index;
+pub mod interrupt;
+pub mod koth;
+pub mod messages;
+pub mod package;
+pub mod pmars;
+pub mod pool;
+pub mod prelude;
+pub mod replay;
+pub mod signature;
+pub mod spec;
+pub mod stream;
+pub mod telemetry;
+pub mod tournament;
+
+// Private modules
+mod mapped;
+mod report;
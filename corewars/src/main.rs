@@ -4,7 +4,7 @@ fn main() {
     std::process::exit(
         // TODO use exitcode lib or something like that
         if let Err(err) = cli::run() {
-            eprintln!("Error: {}", err);
+            cli::report_error(&*err);
             -1
         } else {
             // TODO use exit codes for warnings?
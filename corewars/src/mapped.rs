@@ -0,0 +1,141 @@
+//! Reading files by mapping them into memory, which saves copying each one
+//! into a buffer when scanning a corpus of thousands of small files.
+//!
+//! On platforms other than Unix, files are read into memory instead.
+
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// The contents of a file, mapped read-only into memory.
+///
+/// The mapping shares its pages with the file, so if the file is written or
+/// truncated while it is mapped, the contents change underneath the slice or
+/// reading them raises `SIGBUS`. This is only meant for files which are read
+/// once and dropped, like those of a corpus nothing else is writing to.
+#[derive(Debug)]
+pub struct Mapped {
+    #[cfg(unix)]
+    pointer: *const u8,
+    #[cfg(unix)]
+    len: usize,
+
+    #[cfg(not(unix))]
+    contents: Vec<u8>,
+}
+
+// SAFETY: the mapping is read-only and owned by this value, so sharing it
+// between threads is no different from sharing the file's contents
+unsafe impl Send for Mapped {}
+unsafe impl Sync for Mapped {}
+
+impl Mapped {
+    /// Map the file at `path` into memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written to or truncated until the returned value
+    /// is dropped, by this process or any other.
+    #[cfg(unix)]
+    pub unsafe fn open(path: &Path) -> io::Result<Self> {
+        use std::convert::TryFrom;
+        use std::fs::File;
+        use std::os::unix::io::AsRawFd;
+
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("file too large to map"))?;
+        if len == 0 {
+            // Empty mappings aren't allowed
+            return Ok(Self {
+                pointer: std::ptr::null(),
+                len,
+            });
+        }
+
+        // SAFETY: the file is open for reading and is `len` bytes long. The
+        // mapping stays valid after the file is closed, and the caller ensures
+        // the file isn't changed while it is mapped. MAP_PRIVATE doesn't help
+        // there: pages which haven't been read yet still see changes to the
+        // file, and reading past its end after truncation raises SIGBUS.
+        let pointer = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if pointer == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            pointer: pointer as *const u8,
+            len,
+        })
+    }
+
+    /// Read the file at `path` into memory.
+    ///
+    /// # Safety
+    ///
+    /// This is always safe, but is unsafe to match the Unix version.
+    #[cfg(not(unix))]
+    pub unsafe fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            contents: std::fs::read(path)?,
+        })
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    #[cfg(unix)]
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the mapping is `len` bytes long and lives as long as self,
+        // and `open`'s caller ensures the file doesn't change under it
+        unsafe { std::slice::from_raw_parts(self.pointer, self.len) }
+    }
+
+    #[cfg(not(unix))]
+    fn deref(&self) -> &[u8] {
+        &self.contents
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: the mapping was created by mmap with this length
+            unsafe { libc::munmap(self.pointer as *mut libc::c_void, self.len) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_files() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata/input");
+        let path = root.join("simple/basic.redcode");
+
+        // SAFETY: nothing writes to the test data
+        let mapped = unsafe { Mapped::open(&path) }.unwrap();
+        assert_eq!(&*mapped, &std::fs::read(&path).unwrap()[..]);
+
+        let empty = assert_fs::NamedTempFile::new("empty.red").unwrap();
+        std::fs::write(empty.path(), "").unwrap();
+        // SAFETY: the file is only written before it is mapped
+        assert!(unsafe { Mapped::open(empty.path()) }.unwrap().is_empty());
+    }
+}
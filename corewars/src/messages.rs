@@ -0,0 +1,470 @@
+//! The text printed for people to read, kept apart from the code deciding
+//! what to print, so it can be translated or reworded without changing that
+//! code.
+//!
+//! Each [`Message`] has a key naming it and named arguments, and a
+//! [`Catalog`] turns it into text with a template, e.g. `Round {round}:
+//! {outcome}`. The built-in templates are in [`ENGLISH`], and a catalog file
+//! can replace any of them, with a `key = template` line each:
+//!
+//! ```text
+//! # Messages for a French hill
+//! round-outcome = Manche {round} : {outcome}
+//! hill-accepted = {name} entre sur la colline au rang {rank}
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored, `\n` in a template
+//! is a line break, and `{{` and `}}` are literal braces. The command line
+//! interface reads a catalog file given with `--messages`, and
+//! [installs](install) it for everything it prints, including the files
+//! written for a hill.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use thiserror::Error as ThisError;
+
+/// The key and template of every message, in English.
+pub const ENGLISH: &[(&str, &str)] = &[
+    ("severity-error", "error"),
+    ("severity-warning", "warning"),
+    (
+        "input-file-required",
+        "an input file is required for this command",
+    ),
+    ("warrior-file-required", "a warrior file is required"),
+    (
+        "one-warrior-supported",
+        "only one warrior is currently supported",
+    ),
+    (
+        "warrior-too-long",
+        "warrior has {length} instructions, more than the maximum length of {max}",
+    ),
+    ("skipping-file", "skipping {path}: {reason}"),
+    ("no-cache-directory", "no cache directory, use --cache-dir"),
+    (
+        "fetched-corpus",
+        "{corpus}: downloaded {downloaded} files, {cached} already cached",
+    ),
+    ("positive-rounds", "rounds must be positive"),
+    (
+        "positive-core-size-and-rounds",
+        "core size and rounds must be positive",
+    ),
+    (
+        "positive-hill-size-and-rounds",
+        "hill size and rounds must be positive",
+    ),
+    (
+        "positive-heatmap-width",
+        "--heatmap-width must be greater than 0",
+    ),
+    (
+        "positive-dump-core-size",
+        "--core-size must be greater than 0",
+    ),
+    (
+        "comments-need-loadfile",
+        "--comments is only supported by the loadfile format",
+    ),
+    (
+        "comments-conflict",
+        "--comments can't be combined with --core-size, --no-metadata or --no-origin",
+    ),
+    ("denied-warning", "1 warning with --deny-warnings"),
+    ("denied-warnings", "{count} warnings with --deny-warnings"),
+    (
+        "time-budget-needs-round-robin",
+        "a time budget only works with round robin pairing",
+    ),
+    (
+        "resume-needs-round-robin",
+        "only round robins without a time budget can be resumed",
+    ),
+    (
+        "invalid-tag",
+        "expected START-END=NAME or OFFSET=NAME, got {tag}",
+    ),
+    ("tag-ends-before-start", "tag {tag} ends before it starts"),
+    ("invalid-fault", "expected FAULT@CYCLE, got {fault}"),
+    ("unknown-code", "no error or warning has the code {code}"),
+    ("round-outcome", "Round {round}: {outcome}"),
+    ("round-clears", "Round {round}: {clears}"),
+    (
+        "round-no-clear",
+        "Round {round}: no warrior cleared the core",
+    ),
+    (
+        "warrior-stopped",
+        "Warrior stopped after max of {cycles} cycles",
+    ),
+    (
+        "warrior-stopped-at-limit",
+        "Warrior stopped after specified max of {cycles} cycles",
+    ),
+    (
+        "warrior-failed",
+        "Warrior failed after {steps} steps: {error}",
+    ),
+    ("core-after-execution", "Core after execution:\n{core}"),
+    ("debug-prompt", "(debug) "),
+    ("script-error", "{path}:{line}: {error}"),
+    (
+        "bench-resume-mismatch",
+        "cannot resume bench from state {state}",
+    ),
+    (
+        "bench-resume-past-end",
+        "state {state} is past the end of the bench",
+    ),
+    (
+        "bench-interrupted",
+        "interrupted after {completed} of {total} instructions; resume with --resume {state}",
+    ),
+    (
+        "tournament-interrupted",
+        "interrupted, so the results only include the matches played before; \
+         finish them with --resume and the JSON results",
+    ),
+    ("hill-accepted", "{name} entered the hill at rank {rank}"),
+    ("hill-rejected", "{name} was rejected at rank {rank}"),
+    ("hill-pushed-off", "{name} was pushed off the hill"),
+    ("hill-invalid", "{path} was rejected: {reason}"),
+    (
+        "hill-duplicate",
+        "it is the same program as {name}, already on the hill",
+    ),
+    (
+        "hill-streaming",
+        "streaming challenges to ws://{address}{endpoint}",
+    ),
+    ("crosstable-name", "Name"),
+    ("crosstable-score", "Score"),
+    ("match-won", "{first} vs {second}: {record}, {winner} wins"),
+    ("match-tied", "{first} vs {second}: {record}, tie"),
+    ("match-bye", "{warrior} has a bye"),
+    ("bracket-stage", "{stage}:"),
+    ("bracket-stage-round", "{stage} round {round}:"),
+    ("bracket-winner", "Winner: {winner}"),
+    ("separation", "Separation"),
+    (
+        "separation-correlation",
+        "Correlation of score with separation: {correlation}",
+    ),
+    (
+        "position-sensitive",
+        "{warrior} is position sensitive, scoring least at separations {start}-{end}: {score}",
+    ),
+    (
+        "position-insensitive",
+        "{warrior} scores about the same at every separation",
+    ),
+    (
+        "score-margins",
+        "Every pair finished {rounds} rounds; scores with 95% margins of error:",
+    ),
+    (
+        "spec-standings",
+        "Standings with {win} points per win, {tie} per tie and {loss} per loss:",
+    ),
+    ("package-unnamed", "every package needs a name"),
+    (
+        "package-name-mismatch",
+        "the warrior is named {source}, but the package is {name}",
+    ),
+    (
+        "package-too-long",
+        "the warrior is {length} instructions long, longer than the {max} allowed",
+    ),
+    ("package-test-passed", "test {opponent}: {record} ok"),
+    (
+        "package-test-failed",
+        "test {opponent}: {record} FAILED, lost more rounds than it won",
+    ),
+    (
+        "package-benchmark",
+        "benchmark {opponent}: {score} ± {margin}",
+    ),
+    (
+        "package-benchmark-baseline",
+        "benchmark {opponent}: {score} ± {margin}, baseline {baseline}",
+    ),
+    (
+        "package-benchmark-regressed",
+        "benchmark {opponent}: {score} ± {margin} REGRESSED from baseline {baseline}",
+    ),
+    ("package-created", "created package {name} in {path}"),
+    (
+        "package-baselines-updated",
+        "updated the baselines of {count} benchmarks",
+    ),
+    ("package-check-failed", "package check failed"),
+];
+
+/// An error in a catalog file.
+#[derive(ThisError, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("line {line}: expected KEY = TEMPLATE")]
+    Syntax { line: usize },
+
+    #[error("line {line}: unknown message {key:?}")]
+    UnknownKey { line: usize, key: String },
+
+    #[error("line {line}: message {key:?} has no argument {argument:?}")]
+    UnknownArgument {
+        line: usize,
+        key: String,
+        argument: String,
+    },
+}
+
+/// A message to print, with the value of each of its arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    key: &'static str,
+    args: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn new(key: &'static str) -> Self {
+        Self {
+            key,
+            args: Vec::new(),
+        }
+    }
+
+    /// Give the argument `name` a value.
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> Self {
+        self.args.push((name, value.to_string()));
+        self
+    }
+
+    pub fn key(&self) -> &'static str {
+        self.key
+    }
+}
+
+/// The [installed](install) catalog's text for the message.
+impl fmt::Display for Message {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", catalog().format(self))
+    }
+}
+
+/// The template for each message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Catalog {
+    templates: HashMap<&'static str, String>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+impl Catalog {
+    /// The built-in [`ENGLISH`] templates.
+    pub fn english() -> Self {
+        Self {
+            templates: ENGLISH
+                .iter()
+                .map(|&(key, template)| (key, template.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Replace templates with those in a catalog file, as described in the
+    /// [module documentation](self). Templates may only use the arguments
+    /// the built-in ones do.
+    pub fn with_overrides(mut self, text: &str) -> Result<Self, Error> {
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+            let template = parts
+                .next()
+                .ok_or(Error::Syntax { line: index + 1 })?
+                .trim()
+                .replace("\\n", "\n");
+
+            let (key, english) =
+                ENGLISH
+                    .iter()
+                    .find(|(name, _)| *name == key)
+                    .ok_or_else(|| Error::UnknownKey {
+                        line: index + 1,
+                        key: key.to_string(),
+                    })?;
+
+            let allowed = arguments(english);
+            if let Some(argument) = arguments(&template)
+                .into_iter()
+                .find(|argument| !allowed.contains(argument))
+            {
+                return Err(Error::UnknownArgument {
+                    line: index + 1,
+                    key: key.to_string(),
+                    argument: argument.to_string(),
+                });
+            }
+
+            self.templates.insert(key, template);
+        }
+
+        Ok(self)
+    }
+
+    /// The text of `message`. Arguments the message wasn't given a value
+    /// for are left as they are in the template.
+    pub fn format(&self, message: &Message) -> String {
+        let template = self
+            .templates
+            .get(message.key)
+            .map_or(message.key, String::as_str);
+
+        let mut text = String::with_capacity(template.len());
+        for piece in pieces(template) {
+            match piece {
+                Piece::Text(literal) => text.push_str(literal),
+                Piece::Argument(name) => match message.args.iter().find(|(arg, _)| *arg == name) {
+                    Some((_, value)) => text.push_str(value),
+                    None => text.push_str(&format!("{{{}}}", name)),
+                },
+            }
+        }
+        text
+    }
+}
+
+/// A part of a template.
+enum Piece<'a> {
+    Text(&'a str),
+    Argument(&'a str),
+}
+
+fn pieces(template: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        pieces.push(Piece::Text(&rest[..index]));
+        let (brace, after) = rest[index..].split_at(1);
+
+        let end = after.find('}').filter(|_| brace == "{");
+        match end {
+            _ if after.starts_with(brace) => {
+                pieces.push(Piece::Text(brace));
+                rest = &after[1..];
+            }
+            Some(end) => {
+                pieces.push(Piece::Argument(&after[..end]));
+                rest = &after[end + 1..];
+            }
+            None => {
+                pieces.push(Piece::Text(brace));
+                rest = after;
+            }
+        }
+    }
+
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+/// The names of the arguments used in `template`.
+fn arguments(template: &str) -> Vec<&str> {
+    pieces(template)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Argument(name) => Some(name),
+            Piece::Text(_) => None,
+        })
+        .collect()
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Use `catalog` for every message printed from now on, instead of the
+/// built-in one. This has no effect once a message has been printed, and
+/// returns the catalog back.
+pub fn install(catalog: Catalog) -> Result<(), Catalog> {
+    CATALOG.set(catalog)
+}
+
+/// The installed catalog, or the built-in one if none was installed.
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(Catalog::english)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn formats_arguments() {
+        let catalog = Catalog::english();
+        let message = Message::new("round-outcome")
+            .arg("round", 2)
+            .arg("outcome", "Imp wins");
+        assert_eq!(catalog.format(&message), "Round 2: Imp wins");
+
+        // Missing arguments and unknown keys are left for someone to notice
+        assert_eq!(
+            catalog.format(&Message::new("round-outcome")),
+            "Round {round}: {outcome}"
+        );
+        assert_eq!(catalog.format(&Message::new("no-such-key")), "no-such-key");
+    }
+
+    #[test]
+    fn overrides_templates() {
+        let catalog = Catalog::english()
+            .with_overrides(
+                "# French\n\nround-outcome = Manche {round} : {outcome} {{sic}}\n\
+                 core-after-execution = Mémoire :\\n{core}",
+            )
+            .unwrap();
+
+        let message = Message::new("round-outcome")
+            .arg("round", 2)
+            .arg("outcome", "Imp");
+        assert_eq!(catalog.format(&message), "Manche 2 : Imp {sic}");
+        assert_eq!(
+            catalog.format(&Message::new("core-after-execution").arg("core", "...")),
+            "Mémoire :\n..."
+        );
+        assert_eq!(
+            catalog.format(&Message::new("debug-prompt")),
+            "(debug) ",
+            "other messages should keep their templates"
+        );
+
+        let error = |text: &str| Catalog::english().with_overrides(text).unwrap_err();
+        assert_eq!(error("round-outcome"), Error::Syntax { line: 1 });
+        assert_eq!(
+            error("\nround = {round}"),
+            Error::UnknownKey {
+                line: 2,
+                key: "round".into()
+            }
+        );
+        assert_eq!(
+            error("round-outcome = {winner}"),
+            Error::UnknownArgument {
+                line: 1,
+                key: "round-outcome".into(),
+                argument: "winner".into()
+            }
+        );
+    }
+}
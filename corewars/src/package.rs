@@ -0,0 +1,667 @@
+//! Warrior packages: a directory holding a warrior with everything needed to
+//! work on it, like a cargo project for Redcode.
+//!
+//! ```text
+//! imp/
+//! ├── metadata.toml         the name, author and version, and the hill
+//! ├── src/warrior.red       the warrior
+//! ├── benchmarks/           opponents to score the warrior against
+//! │   └── baselines.toml    the score last recorded against each
+//! └── tests/                opponents the warrior must beat
+//! ```
+//!
+//! `metadata.toml` is in the same subset of TOML as a [spec](crate::spec):
+//!
+//! ```toml
+//! name = "Imp"
+//! author = "A. K. Dewdney"
+//! version = "0.1.0"
+//! source = "src/warrior.red"
+//! rounds = 100
+//!
+//! [hill]
+//! core_size = 8000
+//! max_cycles = 80000
+//! max_length = 100
+//! min_distance = 100
+//! ```
+//!
+//! Only `name` is required, and the rest default to the values above. Each
+//! `.red` file in `tests` is a test battle, which passes unless the warrior
+//! loses more rounds than it wins. Each in `benchmarks` is scored, and
+//! compared with its baseline, keyed by the file's name without `.red`:
+//! scores more than their margin of error below the baseline are
+//! regressions. [`Package::write_baselines`] records new baselines.
+//!
+//! Commands which read a warrior also accept the directory of a package,
+//! and read its source.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error as ThisError;
+
+use corewars_core::Warrior;
+use corewars_sim::{BattleConfig, ConfigError};
+
+use crate::messages::Message;
+use crate::spec::{self, quote};
+use crate::tournament::{self, Record};
+
+/// The file describing a package, at its root.
+pub const METADATA_FILE: &str = "metadata.toml";
+
+/// The file in the benchmarks directory with the baseline scores.
+pub const BASELINES_FILE: &str = "baselines.toml";
+
+const DEFAULT_SOURCE: &str = "src/warrior.red";
+const TESTS_DIR: &str = "tests";
+const BENCHMARKS_DIR: &str = "benchmarks";
+
+/// An error reading or creating a package.
+#[derive(ThisError, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("{}: {source}", path.display())]
+    Metadata { path: PathBuf, source: spec::Error },
+
+    #[error("{} is not a package: it has no metadata.toml", .0.display())]
+    NotAPackage(PathBuf),
+
+    #[error("{} already exists", .0.display())]
+    Exists(PathBuf),
+
+    #[error("{}: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("the source {} is not inside the package", .0.display())]
+    SourceOutsidePackage(PathBuf),
+}
+
+/// The contents of `metadata.toml`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    pub name: String,
+    pub author: Option<String>,
+    pub version: String,
+
+    /// The warrior's source, relative to the package
+    pub source: PathBuf,
+
+    /// The number of rounds of each test battle and benchmark
+    pub rounds: u32,
+
+    pub config: BattleConfig,
+}
+
+impl Metadata {
+    /// The metadata of a new package named `name`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            author: None,
+            version: "0.1.0".into(),
+            source: PathBuf::from(DEFAULT_SOURCE),
+            rounds: 100,
+            config: BattleConfig::default(),
+        }
+    }
+
+    /// Parse `metadata.toml`, as described in the [module documentation](self).
+    pub fn parse(text: &str) -> Result<Self, spec::Error> {
+        let mut metadata = Self::new("");
+        let mut named = false;
+
+        for setting in spec::settings(text)? {
+            let invalid = |reason: String| spec::Error::InvalidValue {
+                line: setting.line,
+                key: setting.key.clone(),
+                reason,
+            };
+            let value = &setting.value;
+
+            match setting.key.as_str() {
+                "name" => {
+                    metadata.name = value.string().map_err(invalid)?;
+                    named = true;
+                }
+                "author" => metadata.author = Some(value.string().map_err(invalid)?),
+                "version" => metadata.version = value.string().map_err(invalid)?,
+                "source" => metadata.source = value.string().map_err(invalid)?.into(),
+                "rounds" => metadata.rounds = value.number().map_err(invalid)?,
+                "hill.core_size" => metadata.config.core_size = value.number().map_err(invalid)?,
+                "hill.max_cycles" => {
+                    metadata.config.max_cycles = value.number().map_err(invalid)?
+                }
+                "hill.max_length" => {
+                    metadata.config.max_length = value.number().map_err(invalid)?
+                }
+                "hill.min_distance" => {
+                    metadata.config.min_distance = value.number().map_err(invalid)?
+                }
+                _ => {
+                    return Err(spec::Error::UnknownKey {
+                        line: setting.line,
+                        key: setting.key,
+                    })
+                }
+            }
+
+            if setting.key == "rounds" && metadata.rounds == 0 {
+                return Err(invalid(Message::new("positive-rounds").to_string()));
+            }
+        }
+
+        if !named {
+            return Err(spec::Error::InvalidValue {
+                line: 1,
+                key: "name".into(),
+                reason: Message::new("package-unnamed").to_string(),
+            });
+        }
+        Ok(metadata)
+    }
+}
+
+/// The metadata file, with every setting written out.
+impl fmt::Display for Metadata {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(formatter, "name = {}", quote(&self.name))?;
+        if let Some(author) = &self.author {
+            writeln!(formatter, "author = {}", quote(author))?;
+        }
+        writeln!(formatter, "version = {}", quote(&self.version))?;
+        writeln!(
+            formatter,
+            "source = {}",
+            quote(&self.source.to_string_lossy())
+        )?;
+        writeln!(formatter, "rounds = {}", self.rounds)?;
+
+        writeln!(formatter, "\n[hill]")?;
+        writeln!(formatter, "core_size = {}", self.config.core_size)?;
+        writeln!(formatter, "max_cycles = {}", self.config.max_cycles)?;
+        writeln!(formatter, "max_length = {}", self.config.max_length)?;
+        write!(formatter, "min_distance = {}", self.config.min_distance)
+    }
+}
+
+/// A warrior package on disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    pub root: PathBuf,
+    pub metadata: Metadata,
+
+    /// The baseline score against each benchmark, by name
+    pub baselines: BTreeMap<String, f64>,
+}
+
+impl Package {
+    /// Whether `path` is the directory of a package.
+    pub fn is_package(path: &Path) -> bool {
+        path.join(METADATA_FILE).is_file()
+    }
+
+    /// Read the package in `root`.
+    pub fn open(root: &Path) -> Result<Self, Error> {
+        let path = root.join(METADATA_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::NotAPackage(root.to_path_buf()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let metadata = Metadata::parse(&text).map_err(|source| Error::Metadata {
+            path: path.clone(),
+            source,
+        })?;
+
+        let path = root.join(BENCHMARKS_DIR).join(BASELINES_FILE);
+        let mut baselines = BTreeMap::new();
+        if path.is_file() {
+            let settings = spec::settings(&fs::read_to_string(&path)?);
+            for setting in settings.map_err(|source| Error::Metadata {
+                path: path.clone(),
+                source,
+            })? {
+                let score = setting.value.number().map_err(|reason| Error::Metadata {
+                    path: path.clone(),
+                    source: spec::Error::InvalidValue {
+                        line: setting.line,
+                        key: setting.key.clone(),
+                        reason,
+                    },
+                })?;
+                baselines.insert(setting.key, score);
+            }
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            metadata,
+            baselines,
+        })
+    }
+
+    /// Create a package named `name` in `root`, which must not exist yet,
+    /// with an imp as its warrior and empty directories for tests and
+    /// benchmarks.
+    pub fn create(root: &Path, name: &str) -> Result<Self, Error> {
+        if root.exists() {
+            return Err(Error::Exists(root.to_path_buf()));
+        }
+
+        let package = Self {
+            root: root.to_path_buf(),
+            metadata: Metadata::new(name),
+            baselines: BTreeMap::new(),
+        };
+
+        let source = root.join(&package.metadata.source);
+        if let Some(parent) = source.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            &source,
+            format!(";redcode-94\n;name {}\n;assert 1\n\nmov 0, 1\n", name),
+        )?;
+        fs::create_dir_all(root.join(TESTS_DIR))?;
+        fs::create_dir_all(root.join(BENCHMARKS_DIR))?;
+        fs::write(root.join(METADATA_FILE), format!("{}\n", package.metadata))?;
+
+        Ok(package)
+    }
+
+    /// The path of the warrior's source, with symbolic links resolved. This
+    /// must be inside the package, so a package can't be used to read any
+    /// other file, e.g. when it is submitted to a hill.
+    pub fn source_path(&self) -> Result<PathBuf, Error> {
+        let source = &self.metadata.source;
+        if source.is_absolute() || source.has_root() {
+            return Err(Error::SourceOutsidePackage(source.clone()));
+        }
+
+        let root = self.root.canonicalize()?;
+        let path = root.join(source).canonicalize()?;
+        if path.starts_with(&root) {
+            Ok(path)
+        } else {
+            Err(Error::SourceOutsidePackage(source.clone()))
+        }
+    }
+
+    /// The opponents of each test battle.
+    pub fn tests(&self) -> io::Result<Vec<PathBuf>> {
+        warrior_files(&self.root.join(TESTS_DIR))
+    }
+
+    /// The opponents of each benchmark.
+    pub fn benchmarks(&self) -> io::Result<Vec<PathBuf>> {
+        warrior_files(&self.root.join(BENCHMARKS_DIR))
+    }
+
+    /// Read and check the package: that its warrior assembles and fits on
+    /// its hill, then play its test battles and benchmarks.
+    pub fn check(&self) -> Result<CheckReport, Error> {
+        let mut report = CheckReport::default();
+        let warrior = read_warrior(&self.source_path()?, &mut report.warnings)?;
+
+        match &warrior.metadata.name {
+            Some(name) if *name != self.metadata.name => report.problems.push(
+                Message::new("package-name-mismatch")
+                    .arg("source", name)
+                    .arg("name", &self.metadata.name)
+                    .to_string(),
+            ),
+            _ => {}
+        }
+        if warrior.len() > self.metadata.config.max_length {
+            report.problems.push(
+                Message::new("package-too-long")
+                    .arg("length", warrior.len())
+                    .arg("max", self.metadata.config.max_length)
+                    .to_string(),
+            );
+            return Ok(report);
+        }
+
+        for path in self.tests()? {
+            let record = self.play(&warrior, &path, &mut report.warnings)?;
+            report.tests.push((opponent_name(&path), record));
+        }
+        for path in self.benchmarks()? {
+            let record = self.play(&warrior, &path, &mut report.warnings)?;
+            let name = opponent_name(&path);
+            report.benchmarks.push(Benchmark {
+                baseline: self.baselines.get(&name).copied(),
+                name,
+                record,
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Replace the baselines with the scores of `benchmarks`, and save them.
+    pub fn write_baselines(&mut self, benchmarks: &[Benchmark]) -> io::Result<()> {
+        self.baselines = benchmarks
+            .iter()
+            .map(|benchmark| (benchmark.name.clone(), benchmark.record.score()))
+            .collect();
+
+        let text: String = self
+            .baselines
+            .iter()
+            .map(|(name, score)| format!("{} = {:.1}\n", name, score))
+            .collect();
+        fs::write(self.root.join(BENCHMARKS_DIR).join(BASELINES_FILE), text)
+    }
+
+    fn play(
+        &self,
+        warrior: &Warrior,
+        opponent: &Path,
+        warnings: &mut Vec<String>,
+    ) -> Result<Record, Error> {
+        let opponent = read_warrior(opponent, warnings)?;
+        Ok(tournament::play(
+            &self.metadata.config,
+            warrior,
+            &opponent,
+            self.metadata.rounds,
+        )?)
+    }
+}
+
+/// The file to read a warrior from, given a path to it: the source of the
+/// package if `path` is the directory of one, and otherwise `path` itself.
+pub fn warrior_source(path: &Path) -> Result<PathBuf, Error> {
+    if path.is_dir() && Package::is_package(path) {
+        Package::open(path)?.source_path()
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+/// The score of a package's warrior against a benchmark.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Benchmark {
+    pub name: String,
+    pub record: Record,
+    pub baseline: Option<f64>,
+}
+
+impl Benchmark {
+    /// Whether the score is more than its margin of error below the baseline.
+    pub fn regressed(&self) -> bool {
+        self.baseline
+            .is_some_and(|baseline| self.record.score() + self.record.margin() < baseline)
+    }
+}
+
+/// The results of [checking](Package::check) a package.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckReport {
+    /// Warnings assembling the warrior and opponents, with the file of each
+    pub warnings: Vec<String>,
+
+    /// Every problem with the package other than failed tests and
+    /// regressions
+    pub problems: Vec<String>,
+
+    /// The record against the opponent of each test battle, by name
+    pub tests: Vec<(String, Record)>,
+
+    pub benchmarks: Vec<Benchmark>,
+}
+
+impl CheckReport {
+    /// Whether there are no problems, and every test battle passed.
+    pub fn tests_passed(&self) -> bool {
+        self.problems.is_empty() && self.tests.iter().all(|(_, record)| test_passed(record))
+    }
+
+    /// Whether the tests passed, and no benchmark regressed.
+    pub fn passed(&self) -> bool {
+        self.tests_passed() && !self.benchmarks.iter().any(Benchmark::regressed)
+    }
+}
+
+/// A line for each problem, test and benchmark.
+impl fmt::Display for CheckReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines = self.problems.clone();
+
+        for (name, record) in &self.tests {
+            let key = if test_passed(record) {
+                "package-test-passed"
+            } else {
+                "package-test-failed"
+            };
+            lines.push(
+                Message::new(key)
+                    .arg("opponent", name)
+                    .arg("record", record)
+                    .to_string(),
+            );
+        }
+
+        for benchmark in &self.benchmarks {
+            let key = match benchmark.baseline {
+                None => "package-benchmark",
+                Some(_) if benchmark.regressed() => "package-benchmark-regressed",
+                Some(_) => "package-benchmark-baseline",
+            };
+            lines.push(
+                Message::new(key)
+                    .arg("opponent", &benchmark.name)
+                    .arg("score", format!("{:.1}", benchmark.record.score()))
+                    .arg("margin", format!("{:.1}", benchmark.record.margin()))
+                    .arg(
+                        "baseline",
+                        format!("{:.1}", benchmark.baseline.unwrap_or_default()),
+                    )
+                    .to_string(),
+            );
+        }
+
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+/// A test battle passes unless the warrior lost more rounds than it won.
+fn test_passed(record: &Record) -> bool {
+    record.losses <= record.wins
+}
+
+/// Every Redcode file in `directory`, by name, if it exists.
+fn warrior_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "red") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn opponent_name(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Assemble the warrior in `path`, adding any warnings to `warnings`.
+fn read_warrior(path: &Path, warnings: &mut Vec<String>) -> Result<Warrior, Error> {
+    let text = fs::read_to_string(path).map_err(|err| Error::Parse {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+
+    let (result, found) = match corewars_parser::parse(&text) {
+        corewars_parser::Result::Ok(warrior, found) => (Ok(warrior), found),
+        corewars_parser::Result::Err(err, found) => (Err(err), found),
+    };
+    warnings.extend(
+        found
+            .iter()
+            .map(|warning| format!("{}: {}", path.display(), warning)),
+    );
+
+    result.map_err(|err| Error::Parse {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_metadata() {
+        let metadata = Metadata::parse(
+            "name = \"Dwarf\"\n\
+             author = \"A. K. Dewdney\"\n\
+             rounds = 10\n\n\
+             [hill]\n\
+             core_size = 800\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata,
+            Metadata {
+                name: "Dwarf".into(),
+                author: Some("A. K. Dewdney".into()),
+                rounds: 10,
+                config: BattleConfig {
+                    core_size: 800,
+                    ..BattleConfig::default()
+                },
+                ..Metadata::new("")
+            }
+        );
+        assert_eq!(Metadata::parse(&metadata.to_string()).unwrap(), metadata);
+
+        assert!(matches!(
+            Metadata::parse("version = \"1.0\""),
+            Err(spec::Error::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Metadata::parse("name = \"Dwarf\"\nlicense = \"MIT\""),
+            Err(spec::Error::UnknownKey { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn checks_packages() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let root = directory.path().join("dwarf");
+
+        let mut package = Package::create(&root, "Dwarf").unwrap();
+        assert!(matches!(
+            Package::create(&root, "Dwarf"),
+            Err(Error::Exists(_))
+        ));
+        assert!(Package::is_package(&root));
+        assert_eq!(Package::open(&root).unwrap(), package);
+
+        // A new package is an imp, which doesn't lose to itself
+        package.metadata.rounds = 4;
+        fs::write(
+            root.join(TESTS_DIR).join("dat.red"),
+            ";name Sitting Duck\ndat 0, 0\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(BENCHMARKS_DIR).join("imp.red"),
+            ";name Imp\nmov 0, 1\n",
+        )
+        .unwrap();
+
+        let report = package.check().unwrap();
+        assert!(report.passed(), "{}", report);
+        assert_eq!(
+            report.tests,
+            vec![(
+                "dat".to_string(),
+                Record {
+                    wins: 4,
+                    losses: 0,
+                    ties: 0,
+                }
+            )]
+        );
+        assert_eq!(report.benchmarks[0].baseline, None);
+
+        // Scoring less than the baseline is a regression
+        package.write_baselines(&report.benchmarks).unwrap();
+        let mut package = Package::open(&root).unwrap();
+        assert_eq!(package.baselines["imp"], 100.0);
+        package.metadata.rounds = 4;
+        package.baselines.insert("imp".into(), 300.0);
+
+        let report = package.check().unwrap();
+        assert!(!report.passed());
+        assert!(report.benchmarks[0].regressed());
+    }
+
+    #[test]
+    fn confines_source() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let root = directory.path().join("dwarf");
+        let mut package = Package::create(&root, "Dwarf").unwrap();
+        fs::write(directory.path().join("secret.red"), "secret\n").unwrap();
+
+        for source in ["../secret.red", "src/../../secret.red"] {
+            package.metadata.source = source.into();
+            assert!(matches!(
+                package.source_path(),
+                Err(Error::SourceOutsidePackage(_))
+            ));
+        }
+
+        let absolute = directory.path().join("secret.red");
+        package.metadata.source = absolute.clone();
+        assert!(matches!(
+            package.source_path(),
+            Err(Error::SourceOutsidePackage(path)) if path == absolute
+        ));
+
+        // Also when read through the package's metadata, as a hill does
+        fs::write(
+            root.join(METADATA_FILE),
+            "name = \"Dwarf\"\nsource = \"../secret.red\"\n",
+        )
+        .unwrap();
+        let err = warrior_source(&root).unwrap_err();
+        assert!(!err.to_string().contains("secret\n"), "{}", err);
+        assert!(matches!(err, Error::SourceOutsidePackage(_)));
+
+        package.metadata.source = "src/./warrior.red".into();
+        assert_eq!(
+            package.source_path().unwrap(),
+            root.join("src/warrior.red").canonicalize().unwrap()
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Compatibility with the command line conventions of pMARS, the reference
+//! Core Wars simulator. Options may be given as arguments, e.g.
+//! `-s 8000 -c 80000 imp.red`, or read from a parameter file with `-@ file`.
+//!
+//! See `pmars.6` in the pMARS distribution for the meaning of each option.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use thiserror::Error as ThisError;
+
+/// An error in pMARS-style options.
+#[derive(ThisError, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// An option that takes a value was given without one.
+    #[error("option -{0} requires a value")]
+    MissingValue(char),
+
+    /// An option was given a value which is not a valid number.
+    #[error("invalid value {value:?} for option -{option}")]
+    InvalidValue { option: char, value: String },
+
+    /// An option which is not known to pMARS.
+    #[error("unknown option -{0}")]
+    UnknownOption(String),
+
+    /// A parameter file could not be read.
+    #[error("unable to read parameter file {path:?}: {message}")]
+    ParameterFile { path: PathBuf, message: String },
+}
+
+/// Battle settings as understood by pMARS. Defaults match those of pMARS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// Number of rounds to fight (`-r`)
+    pub rounds: u32,
+
+    /// Size of the core (`-s`)
+    pub core_size: u32,
+
+    /// Cycles until a round is declared a tie (`-c`)
+    pub cycles: u32,
+
+    /// Maximum number of processes per warrior (`-p`)
+    pub max_processes: u32,
+
+    /// Maximum number of instructions in a warrior (`-l`)
+    pub max_length: u32,
+
+    /// Minimum distance between the start of each warrior (`-d`)
+    pub min_distance: u32,
+
+    /// Fixed position of the second warrior, instead of a random one (`-F`)
+    pub fixed_position: Option<u32>,
+
+    /// Warrior files to load, in order
+    pub warriors: Vec<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            rounds: 1,
+            core_size: 8000,
+            cycles: 80_000,
+            max_processes: 8000,
+            max_length: 100,
+            min_distance: 100,
+            fixed_position: None,
+            warriors: Vec::new(),
+        }
+    }
+}
+
+/// Display options of pMARS, which take no value and have no effect here.
+const IGNORED_FLAGS: &str = "bekoV";
+
+impl Options {
+    /// Parse pMARS-style arguments, not including the program name. Values may
+    /// be attached to their option (`-s8000`) or follow it (`-s 8000`).
+    pub fn from_args<I, S>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut options = Self::default();
+        options.apply(args.into_iter().map(Into::into).collect())?;
+        Ok(options)
+    }
+
+    /// Parse the contents of a pMARS parameter file, as would be read by `-@`.
+    /// Options are separated by whitespace, and `;` or `#` start a comment
+    /// which lasts until the end of the line.
+    pub fn from_parameter_file(contents: &str) -> Result<Self, Error> {
+        Self::from_args(parameter_file_args(contents))
+    }
+
+    fn apply(&mut self, args: Vec<String>) -> Result<(), Error> {
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let flag = match arg.strip_prefix('-') {
+                Some(flag) if !flag.is_empty() => flag,
+                _ => {
+                    self.warriors.push(PathBuf::from(arg));
+                    continue;
+                }
+            };
+
+            let mut chars = flag.chars();
+            let option = chars.next().expect("flag is not empty");
+            let attached = chars.as_str();
+
+            if IGNORED_FLAGS.contains(option) && attached.is_empty() {
+                continue;
+            }
+
+            let mut value = || match attached {
+                "" => args.next().ok_or(Error::MissingValue(option)),
+                attached => Ok(attached.to_string()),
+            };
+
+            match option {
+                'r' => self.rounds = number(option, value()?)?,
+                's' => self.core_size = number(option, value()?)?,
+                'c' => self.cycles = number(option, value()?)?,
+                'p' => self.max_processes = number(option, value()?)?,
+                'l' => self.max_length = number(option, value()?)?,
+                'd' => self.min_distance = number(option, value()?)?,
+                'F' => self.fixed_position = Some(number(option, value()?)?),
+                '@' => {
+                    let path = PathBuf::from(value()?);
+                    let contents =
+                        fs::read_to_string(&path).map_err(|err| Error::ParameterFile {
+                            path: path.clone(),
+                            message: err.to_string(),
+                        })?;
+                    self.apply(parameter_file_args(&contents))?;
+                }
+                _ => return Err(Error::UnknownOption(flag.to_string())),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn number<T: FromStr>(option: char, value: String) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::InvalidValue { option, value })
+}
+
+fn parameter_file_args(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split([';', '#']).next().unwrap_or_default())
+        .flat_map(str::split_whitespace)
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        assert_eq!(
+            Options::from_args(Vec::<String>::new()).unwrap(),
+            Options::default()
+        );
+    }
+
+    #[test]
+    fn separate_and_attached_values() {
+        let options =
+            Options::from_args(vec!["-s", "800", "-c8000", "-b", "-F", "400", "imp.red"]).unwrap();
+
+        assert_eq!(
+            options,
+            Options {
+                core_size: 800,
+                cycles: 8000,
+                fixed_position: Some(400),
+                warriors: vec![PathBuf::from("imp.red")],
+                ..Options::default()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert_eq!(
+            Options::from_args(vec!["-s"]),
+            Err(Error::MissingValue('s'))
+        );
+        assert_eq!(
+            Options::from_args(vec!["-p", "many"]),
+            Err(Error::InvalidValue {
+                option: 'p',
+                value: "many".into()
+            })
+        );
+        assert_eq!(
+            Options::from_args(vec!["-x"]),
+            Err(Error::UnknownOption("x".into()))
+        );
+    }
+
+    #[test]
+    fn parameter_file() {
+        let contents = "; settings for the 94nop hill\n-r 250 -l 100\n-d 100 # min distance\n";
+
+        assert_eq!(
+            Options::from_parameter_file(contents).unwrap(),
+            Options {
+                rounds: 250,
+                ..Options::default()
+            }
+        );
+    }
+}
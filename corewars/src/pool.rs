@@ -0,0 +1,290 @@
+//! A bounded pool of worker threads for running battles, e.g. on behalf of
+//! a server handling many requests at once.
+//!
+//! Submitted battles wait in a queue of fixed capacity until a worker is free.
+//! When the queue is full, [`submit`](WorkerPool::submit) fails immediately
+//! with [`Error::Full`] instead of blocking, so callers can push back on
+//! clients rather than accumulating unbounded work.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use thiserror::Error as ThisError;
+
+use corewars_core::Warrior;
+use corewars_sim::{Battle, BattleConfig, Outcome};
+
+/// An error from submitting or running a battle in the pool.
+#[derive(ThisError, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The queue already has as many battles waiting as it can hold.
+    #[error("too many battles queued, try again later")]
+    Full,
+
+    /// The battle did not finish within the pool's timeout.
+    #[error("battle did not finish within {0:?}")]
+    Timeout(Duration),
+
+    /// The battle could not be set up, e.g. because a warrior didn't fit.
+    #[error("invalid battle: {0}")]
+    Invalid(String),
+
+    /// The worker running the battle stopped without a result.
+    #[error("worker stopped unexpectedly")]
+    Disconnected,
+
+    /// The battle panicked. The worker survives, and goes on to the next one.
+    #[error("battle panicked: {0}")]
+    Panicked(String),
+}
+
+impl Error {
+    /// The HTTP status code a server should respond with for this error.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::Full => 429,
+            Self::Timeout(_) => 504,
+            Self::Invalid(_) => 400,
+            Self::Disconnected | Self::Panicked(_) => 500,
+        }
+    }
+}
+
+/// A battle to run in the pool: the configuration and each warrior with the
+/// position to load it at.
+#[derive(Debug)]
+pub struct BattleJob {
+    pub config: BattleConfig,
+    pub warriors: Vec<(Warrior, u32)>,
+}
+
+impl BattleJob {
+    fn run(self, timeout: Duration) -> Result<Outcome, Error> {
+        let deadline = Instant::now() + timeout;
+        self.battle()?
+            .run_until(deadline)
+            .ok_or(Error::Timeout(timeout))
+    }
+
+    /// The battle with every warrior loaded, ready to run. Like any
+    /// [`Battle`], it doesn't trace the instructions it executes, which
+    /// would slow down every worker.
+    fn battle(self) -> Result<Battle, Error> {
+        let mut battle = Battle::new(self.config).map_err(|err| Error::Invalid(err.to_string()))?;
+        for (warrior, position) in &self.warriors {
+            battle
+                .load(warrior, *position)
+                .map_err(|err| Error::Invalid(err.to_string()))?;
+        }
+        Ok(battle)
+    }
+}
+
+/// Run `battle`, turning a panic into an error so the worker can carry on.
+fn catch_panic<F>(battle: F) -> Result<Outcome, Error>
+where
+    F: FnOnce() -> Result<Outcome, Error>,
+{
+    panic::catch_unwind(AssertUnwindSafe(battle))
+        .unwrap_or_else(|payload| Err(Error::Panicked(panic_message(payload))))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map_or("unknown panic", |message| message)
+            .to_string(),
+    }
+}
+
+struct Task {
+    job: BattleJob,
+    result: mpsc::Sender<Result<Outcome, Error>>,
+}
+
+/// A handle to a submitted battle, used to wait for its outcome.
+#[derive(Debug)]
+pub struct JobHandle {
+    result: Receiver<Result<Outcome, Error>>,
+}
+
+impl JobHandle {
+    /// Block until the battle has finished or timed out.
+    pub fn wait(self) -> Result<Outcome, Error> {
+        self.result.recv().unwrap_or(Err(Error::Disconnected))
+    }
+}
+
+/// A fixed number of worker threads running battles from a bounded queue.
+/// Dropping the pool waits for every queued battle to finish.
+#[derive(Debug)]
+pub struct WorkerPool {
+    sender: Option<SyncSender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+    timeout: Duration,
+}
+
+impl WorkerPool {
+    /// Start `workers` threads, with room for `capacity` battles to wait for
+    /// a free worker. Each battle is abandoned after running for `timeout`.
+    ///
+    /// # Panics
+    ///
+    /// If `workers` is 0.
+    pub fn new(workers: usize, capacity: usize, timeout: Duration) -> Self {
+        assert!(workers > 0, "pool must have at least one worker");
+
+        let (sender, receiver) = mpsc::sync_channel::<Task>(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..workers)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    // Only hold the lock while waiting, not while running
+                    let task = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+
+                    match task {
+                        Ok(Task { job, result }) => {
+                            // The submitter may have stopped waiting, which is fine
+                            let _ = result.send(catch_panic(|| job.run(timeout)));
+                        }
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+            timeout,
+        }
+    }
+
+    /// The longest a battle may run before it is abandoned.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Queue a battle to run, without blocking. Fails with [`Error::Full`] if
+    /// the queue is at capacity.
+    pub fn submit(&self, job: BattleJob) -> Result<JobHandle, Error> {
+        let (result, receiver) = mpsc::channel();
+        let sender = self.sender.as_ref().expect("sender is only taken on drop");
+
+        match sender.try_send(Task { job, result }) {
+            Ok(()) => Ok(JobHandle { result: receiver }),
+            Err(TrySendError::Full(_)) => Err(Error::Full),
+            Err(TrySendError::Disconnected(_)) => Err(Error::Disconnected),
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the queue stops each worker once it is empty
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn job(max_cycles: usize, programs: &[&str]) -> BattleJob {
+        BattleJob {
+            config: BattleConfig {
+                core_size: 800,
+                max_cycles,
+                ..BattleConfig::default()
+            },
+            warriors: programs
+                .iter()
+                .enumerate()
+                .map(|(i, program)| (corewars_parser::parse(program).unwrap(), i as u32 * 400))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn runs_battles() {
+        let pool = WorkerPool::new(2, 4, Duration::from_secs(60));
+
+        let handles: Vec<JobHandle> = (0..3)
+            .map(|_| {
+                pool.submit(job(100, &[";name Imp\nmov 0, 1", ";name Dies\ndat 0, 0"]))
+                    .unwrap()
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.wait(), Ok(Outcome::Win("Imp".into())));
+        }
+
+        let mut invalid = job(100, &[";name Long\nmov 0, 1\nmov 0, 1"]);
+        invalid.config.core_size = 1;
+        let error = pool.submit(invalid).unwrap().wait().unwrap_err();
+        assert_eq!(error.status_code(), 400);
+    }
+
+    #[test]
+    fn does_not_trace() {
+        let battle = job(100, &[";name Imp\nmov 0, 1"]).battle().unwrap();
+        assert!(!battle.core().is_tracing());
+    }
+
+    #[test]
+    fn catches_panics() {
+        assert_eq!(
+            catch_panic(|| panic!("out of {}", "bounds")),
+            Err(Error::Panicked("out of bounds".into()))
+        );
+        assert_eq!(
+            catch_panic(|| panic!("static")),
+            Err(Error::Panicked("static".into()))
+        );
+        assert_eq!(Error::Panicked(String::new()).status_code(), 500);
+        assert_eq!(
+            catch_panic(|| Ok(Outcome::Tie(vec![]))),
+            Ok(Outcome::Tie(vec![]))
+        );
+    }
+
+    #[test]
+    fn applies_backpressure_and_timeouts() {
+        let timeout = Duration::from_millis(50);
+        let pool = WorkerPool::new(1, 1, timeout);
+        let endless = || job(usize::MAX, &[";name Imp\nmov 0, 1", ";name Wait\njmp 0"]);
+
+        // One battle is running and one waiting at most, so one of these
+        // three must be rejected, depending on when the worker starts.
+        let results: Vec<_> = (0..3).map(|_| pool.submit(endless())).collect();
+        let rejected = results
+            .iter()
+            .filter(|result| matches!(result, Err(Error::Full)))
+            .count();
+        assert!(rejected >= 1);
+        assert_eq!(Error::Full.status_code(), 429);
+
+        for handle in results.into_iter().filter_map(Result::ok) {
+            assert_eq!(handle.wait(), Err(Error::Timeout(timeout)));
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! The most commonly used types from across the corewars crates, so they can
+//! be imported together:
+//!
+//! ```
+//! use corewars::prelude::*;
+//!
+//! let imp: Warrior = Parser::new().parse("mov 0, 1").unwrap();
+//!
+//! let mut battle = Battle::new(BattleConfig::default()).unwrap();
+//! battle.load(&imp, 0).unwrap();
+//! assert_eq!(battle.core().get(0).opcode, Opcode::Mov);
+//! ```
+//!
+//! Battles are run by a [`Battle`] (also called a [`Mars`]), which owns the
+//! [`Core`] the warriors are loaded into. Parsing is configured with the
+//! [`Parser`] builder.
+
+pub use corewars_core::load_file::{
+    AddressMode, Field, Instruction, Metadata, Modifier, Opcode, Program,
+};
+pub use corewars_core::{CancellationToken, Warrior};
+pub use corewars_parser::{ExpansionLimits, Parser};
+pub use corewars_sim::{Battle, BattleConfig, Core, Mars, Outcome};
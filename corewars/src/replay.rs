@@ -0,0 +1,856 @@
+//! Recording battles cycle by cycle, and storing the recordings on disk so
+//! they can be replayed later.
+//!
+//! A [`ReplayStore`] is a directory with one file per battle, plus an index of
+//! every stored battle by ID. Full battles can run for tens of thousands of
+//! cycles, so replays are stored compactly: frames are delta and varint
+//! encoded, in zstd compressed blocks that each start with the range of cycles
+//! they cover. Reading a range of cycles with [`ReplayStore::read_range`]
+//! skips blocks outside of it without decompressing them.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+
+use corewars_core::load_file::{AddressMode, Field, Instruction, Modifier, Opcode};
+use corewars_sim::{Battle, Core, Event, Outcome};
+
+mod compress;
+
+/// Identifies the replay file format, followed by a version byte.
+const MAGIC: &[u8; 4] = b"CWRP";
+const VERSION: u8 = 3;
+
+/// The number of frames encoded together in a block.
+const BLOCK_FRAMES: usize = 1024;
+
+const INDEX_FILE: &str = "index.json";
+
+/// An error reading or writing stored replays.
+#[derive(ThisError, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("invalid replay index: {0}")]
+    Index(#[from] serde_json::Error),
+
+    /// No replay has been stored with this ID.
+    #[error("no replay stored for battle {0:?}")]
+    UnknownBattle(String),
+
+    /// Battle IDs are used as file names, so are restricted to ASCII letters,
+    /// digits, `-` and `_`.
+    #[error("invalid battle ID {0:?}")]
+    InvalidId(String),
+
+    /// The replay file was truncated or not written by this module.
+    #[error("corrupt replay file: {0}")]
+    Corrupt(&'static str),
+}
+
+/// Everything that happened when a warrior executed an instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// The cycle the instruction was executed in, which each warrior still
+    /// running executes one instruction of
+    pub cycle: usize,
+
+    /// The executing warrior, as an index into [`Replay::warriors`]
+    pub warrior: usize,
+
+    /// The address of the executed instruction
+    pub address: u32,
+
+    /// Each instruction modified by the execution, with its new value
+    pub writes: Vec<(u32, Instruction)>,
+}
+
+impl Frame {
+    /// Capture the instruction `core` just executed, or `None` if it hasn't
+    /// executed any yet.
+    pub fn capture(core: &Core) -> Option<Self> {
+        let (warrior, address) = core.last_executed()?;
+
+        // Executing the last warrior's instruction completed the cycle
+        let completed = core
+            .process_queue()
+            .next_warrior()
+            .is_none_or(|next| next <= warrior);
+
+        Some(Self {
+            cycle: core.cycles() - usize::from(completed),
+            warrior,
+            address,
+            writes: core
+                .last_writes()
+                .iter()
+                .map(|&index| (index as u32, core.get(index as i32).clone()))
+                .collect(),
+        })
+    }
+}
+
+/// A recording of a battle: the core before the first cycle, and a frame for
+/// each cycle executed after that.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub core_size: u32,
+    pub warriors: Vec<String>,
+
+    /// The [digest](corewars_core::Warrior::digest) of each warrior, in the
+    /// same order as `warriors`. The core doesn't keep the warriors loaded
+    /// into it, so this is empty unless set after [`new`](Replay::new).
+    pub digests: Vec<String>,
+
+    /// Every instruction which differed from those of an empty core at the start
+    pub initial: Vec<(u32, Instruction)>,
+
+    pub frames: Vec<Frame>,
+}
+
+impl Replay {
+    /// Start recording a battle in `core`, after loading its warriors.
+    pub fn new(core: &Core) -> Self {
+        let default = Instruction::default();
+
+        Self {
+            core_size: core.size(),
+            warriors: core.warriors().to_vec(),
+            digests: Vec::new(),
+            initial: (0..core.size())
+                .map(|index| (index, core.get(index as i32)))
+                .filter(|(_, instruction)| **instruction != default)
+                .map(|(index, instruction)| (index, instruction.clone()))
+                .collect(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Record the instruction `core` just executed. This should be called
+    /// after every step, e.g. from [`Core::run_observed`](Core::run_observed).
+    pub fn record(&mut self, core: &Core) {
+        let frame = match Frame::capture(core) {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        if !self.recorded(frame.cycle, frame.warrior) {
+            self.frames.push(frame);
+        }
+    }
+
+    /// Whether the last frame is of `warrior` executing in `cycle`, which it
+    /// only does once.
+    fn recorded(&self, cycle: usize, warrior: usize) -> bool {
+        self.frames
+            .last()
+            .is_some_and(|last| (last.cycle, last.warrior) == (cycle, warrior))
+    }
+
+    /// Record an event of the battle, from [`Battle::run_with_events`]. Only
+    /// executions and writes are recorded, and other events are ignored.
+    pub fn record_event(&mut self, event: &Event) {
+        match event {
+            Event::InstructionExecuted {
+                cycle,
+                warrior,
+                address,
+                ..
+            } => {
+                let warrior = self
+                    .warriors
+                    .iter()
+                    .position(|name| name == warrior)
+                    .unwrap_or_default();
+                if !self.recorded(*cycle, warrior) {
+                    self.frames.push(Frame {
+                        cycle: *cycle,
+                        warrior,
+                        address: *address,
+                        writes: Vec::new(),
+                    });
+                }
+            }
+            Event::CellWritten {
+                address,
+                instruction,
+                ..
+            } => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.writes.push((*address, instruction.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Run `battle` to the end, recording every cycle, after its warriors
+    /// have been loaded.
+    pub fn record_battle(battle: &mut Battle) -> (Self, Outcome) {
+        let mut replay = Self::new(battle.core());
+        let outcome = battle.run_with_events(|event| replay.record_event(event));
+        (replay, outcome)
+    }
+
+    /// The events of the recording, in order, as they were passed to
+    /// [`record_event`](Replay::record_event). Each executed instruction is
+    /// found by replaying the writes before it.
+    pub fn events(&self) -> Vec<Event> {
+        let mut core = vec![Instruction::default(); self.core_size as usize];
+        for (address, instruction) in &self.initial {
+            core[*address as usize] = instruction.clone();
+        }
+
+        let mut events = Vec::new();
+        for frame in &self.frames {
+            let warrior = self
+                .warriors
+                .get(frame.warrior)
+                .cloned()
+                .unwrap_or_default();
+            events.push(Event::InstructionExecuted {
+                cycle: frame.cycle,
+                warrior: warrior.clone(),
+                address: frame.address,
+                instruction: core[frame.address as usize].clone(),
+            });
+
+            for (address, instruction) in &frame.writes {
+                core[*address as usize] = instruction.clone();
+                events.push(Event::CellWritten {
+                    cycle: frame.cycle,
+                    warrior: warrior.clone(),
+                    address: *address,
+                    instruction: instruction.clone(),
+                });
+            }
+        }
+        events
+    }
+
+    /// The replay as compact JSON, for viewers which only need to know which
+    /// addresses each warrior executed and wrote. Each frame is written as
+    /// `[warrior, address, [written addresses...]]`, in cycle order.
+    pub fn to_json(&self) -> String {
+        let initial: Vec<u32> = self.initial.iter().map(|(address, _)| *address).collect();
+        let frames: Vec<serde_json::Value> = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let writes: Vec<u32> = frame.writes.iter().map(|(address, _)| *address).collect();
+                serde_json::json!([frame.warrior, frame.address, writes])
+            })
+            .collect();
+
+        serde_json::json!({
+            "core_size": self.core_size,
+            "warriors": self.warriors,
+            "initial": initial,
+            "frames": frames,
+        })
+        .to_string()
+    }
+}
+
+/// Summary of a stored replay, as listed in the store's index.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub file: String,
+    pub core_size: u32,
+    pub warriors: Vec<String>,
+    pub digests: Vec<String>,
+    pub frames: usize,
+}
+
+/// A directory of stored replays, indexed by battle ID.
+#[derive(Debug)]
+pub struct ReplayStore {
+    directory: PathBuf,
+    index: BTreeMap<String, IndexEntry>,
+}
+
+impl ReplayStore {
+    /// Open the store in `directory`, creating it if it doesn't exist.
+    pub fn open(directory: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(directory)?;
+
+        let index = match fs::read_to_string(directory.join(INDEX_FILE)) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            index,
+        })
+    }
+
+    /// All stored replays, by battle ID.
+    pub fn index(&self) -> &BTreeMap<String, IndexEntry> {
+        &self.index
+    }
+
+    /// Store `replay` as battle `id`, replacing any replay stored with that ID.
+    pub fn save(&mut self, id: &str, replay: &Replay) -> Result<(), Error> {
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::InvalidId(id.to_string()));
+        }
+
+        let file = format!("{}.replay", id);
+        let mut writer = BufWriter::new(File::create(self.directory.join(&file))?);
+        write_replay(&mut writer, replay)?;
+        writer.flush()?;
+
+        self.index.insert(
+            id.to_string(),
+            IndexEntry {
+                file,
+                core_size: replay.core_size,
+                warriors: replay.warriors.clone(),
+                digests: replay.digests.clone(),
+                frames: replay.frames.len(),
+            },
+        );
+
+        let index = serde_json::to_string_pretty(&self.index)?;
+        fs::write(self.directory.join(INDEX_FILE), index)?;
+
+        Ok(())
+    }
+
+    /// Read a whole stored replay.
+    pub fn load(&self, id: &str) -> Result<Replay, Error> {
+        let mut reader = self.open_replay(id)?;
+        let Header {
+            core_size,
+            warriors,
+            digests,
+            initial,
+        } = read_header(&mut reader)?;
+
+        let frames = FrameReader {
+            reader,
+            range: 0..usize::MAX,
+            block: Vec::new().into_iter(),
+        }
+        .collect::<Result<_, _>>()?;
+
+        Ok(Replay {
+            core_size,
+            warriors,
+            digests,
+            initial,
+            frames,
+        })
+    }
+
+    /// Stream the frames of battle `id` for cycles in `range`, only decoding
+    /// the blocks which overlap it.
+    pub fn read_range(&self, id: &str, range: Range<usize>) -> Result<FrameReader, Error> {
+        let mut reader = self.open_replay(id)?;
+        read_header(&mut reader)?;
+
+        Ok(FrameReader {
+            reader,
+            range,
+            block: Vec::new().into_iter(),
+        })
+    }
+
+    fn open_replay(&self, id: &str) -> Result<BufReader<File>, Error> {
+        let entry = self
+            .index
+            .get(id)
+            .ok_or_else(|| Error::UnknownBattle(id.to_string()))?;
+
+        Ok(BufReader::new(File::open(
+            self.directory.join(&entry.file),
+        )?))
+    }
+}
+
+/// An iterator over the frames of a stored replay within a range of cycles.
+#[derive(Debug)]
+pub struct FrameReader {
+    reader: BufReader<File>,
+    range: Range<usize>,
+    block: std::vec::IntoIter<Frame>,
+}
+
+impl FrameReader {
+    /// Decode the next block overlapping the range, or return `false` once
+    /// there are no more.
+    fn next_block(&mut self) -> Result<bool, Error> {
+        loop {
+            let first_cycle = match read_varint_or_end(&mut self.reader)? {
+                Some(cycle) => cycle as usize,
+                None => return Ok(false),
+            };
+            let last_cycle = read_varint(&mut self.reader)? as usize;
+            let length = read_varint(&mut self.reader)?;
+
+            if first_cycle >= self.range.end {
+                // Blocks are in cycle order, so nothing later can overlap
+                return Ok(false);
+            }
+
+            if last_cycle < self.range.start {
+                let length =
+                    i64::try_from(length).map_err(|_| Error::Corrupt("block is too long"))?;
+                self.reader.seek(SeekFrom::Current(length))?;
+                continue;
+            }
+
+            let bytes = compress::decompress(&read_bytes(&mut self.reader, length)?)?;
+
+            let range = self.range.clone();
+            let frames: Vec<Frame> = decode_block(&mut &bytes[..])?
+                .into_iter()
+                .filter(|frame| range.contains(&frame.cycle))
+                .collect();
+            self.block = frames.into_iter();
+            return Ok(true);
+        }
+    }
+}
+
+impl Iterator for FrameReader {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.block.next() {
+                return Some(Ok(frame));
+            }
+
+            match self.next_block() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(err) => {
+                    // Stop after the first error, rather than retrying forever
+                    self.range = 0..0;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+fn write_replay<W: Write>(writer: &mut W, replay: &Replay) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+
+    write_varint(writer, u64::from(replay.core_size))?;
+    write_strings(writer, &replay.warriors)?;
+    write_strings(writer, &replay.digests)?;
+
+    write_varint(writer, replay.initial.len() as u64)?;
+    for (address, instruction) in &replay.initial {
+        write_varint(writer, u64::from(*address))?;
+        write_instruction(writer, instruction)?;
+    }
+
+    for frames in replay.frames.chunks(BLOCK_FRAMES) {
+        let mut block = Vec::new();
+        encode_block(&mut block, frames)?;
+        let block = compress::compress(&block);
+
+        write_varint(writer, frames[0].cycle as u64)?;
+        write_varint(writer, frames[frames.len() - 1].cycle as u64)?;
+        write_varint(writer, block.len() as u64)?;
+        writer.write_all(&block)?;
+    }
+
+    Ok(())
+}
+
+/// Everything in a replay file before the first block.
+struct Header {
+    core_size: u32,
+    warriors: Vec<String>,
+    digests: Vec<String>,
+    initial: Vec<(u32, Instruction)>,
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<Header, Error> {
+    let mut magic = [0; 5];
+    reader.read_exact(&mut magic)?;
+    if &magic[..4] != MAGIC || magic[4] != VERSION {
+        return Err(Error::Corrupt("not a replay file"));
+    }
+
+    let core_size = read_varint(reader)? as u32;
+
+    let warriors = read_strings(reader)?;
+    let digests = read_strings(reader)?;
+
+    let initial = (0..read_varint(reader)?)
+        .map(|_| Ok((read_varint(reader)? as u32, read_instruction(reader)?)))
+        .collect::<Result<_, Error>>()?;
+
+    Ok(Header {
+        core_size,
+        warriors,
+        digests,
+        initial,
+    })
+}
+
+fn write_strings<W: Write>(writer: &mut W, strings: &[String]) -> io::Result<()> {
+    write_varint(writer, strings.len() as u64)?;
+    for string in strings {
+        write_varint(writer, string.len() as u64)?;
+        writer.write_all(string.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_strings<R: Read>(reader: &mut R) -> Result<Vec<String>, Error> {
+    (0..read_varint(reader)?)
+        .map(|_| {
+            let length = read_varint(reader)?;
+            String::from_utf8(read_bytes(reader, length)?)
+                .map_err(|_| Error::Corrupt("invalid string"))
+        })
+        .collect()
+}
+
+/// Read `length` bytes, only allocating as many as the reader actually has,
+/// since the length is read from the file and may be corrupt.
+fn read_bytes<R: Read>(reader: &mut R, length: u64) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < length {
+        return Err(Error::Corrupt("unexpected end of file"));
+    }
+    Ok(bytes)
+}
+
+/// Frames in a block are encoded relative to the previous one, since cycles
+/// are consecutive and processes mostly execute nearby addresses.
+fn encode_block<W: Write>(writer: &mut W, frames: &[Frame]) -> io::Result<()> {
+    write_varint(writer, frames.len() as u64)?;
+
+    let mut cycle = 0;
+    let mut address = 0;
+    for frame in frames {
+        write_varint(writer, (frame.cycle - cycle) as u64)?;
+        write_varint(writer, frame.warrior as u64)?;
+        write_signed(writer, i64::from(frame.address) - i64::from(address))?;
+
+        write_varint(writer, frame.writes.len() as u64)?;
+        for (written, instruction) in &frame.writes {
+            write_signed(writer, i64::from(*written) - i64::from(frame.address))?;
+            write_instruction(writer, instruction)?;
+        }
+
+        cycle = frame.cycle;
+        address = frame.address;
+    }
+
+    Ok(())
+}
+
+fn decode_block<R: Read>(reader: &mut R) -> Result<Vec<Frame>, Error> {
+    let count = read_varint(reader)?;
+    if count > BLOCK_FRAMES as u64 {
+        return Err(Error::Corrupt("too many frames in block"));
+    }
+
+    let mut frames = Vec::with_capacity(count as usize);
+    let mut cycle: usize = 0;
+    let mut address = 0;
+    for _ in 0..count {
+        cycle = usize::try_from(read_varint(reader)?)
+            .ok()
+            .and_then(|delta| cycle.checked_add(delta))
+            .ok_or(Error::Corrupt("cycle out of range"))?;
+        let warrior = read_varint(reader)? as usize;
+        address = offset_address(address, read_signed(reader)?)?;
+
+        let writes = (0..read_varint(reader)?)
+            .map(|_| {
+                let written = offset_address(address, read_signed(reader)?)?;
+                Ok((written, read_instruction(reader)?))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        frames.push(Frame {
+            cycle,
+            warrior,
+            address,
+            writes,
+        });
+    }
+
+    Ok(frames)
+}
+
+fn offset_address(address: u32, delta: i64) -> Result<u32, Error> {
+    i64::from(address)
+        .checked_add(delta)
+        .and_then(|address| u32::try_from(address).ok())
+        .ok_or(Error::Corrupt("address out of range"))
+}
+
+fn write_instruction<W: Write>(writer: &mut W, instruction: &Instruction) -> io::Result<()> {
+    let opcode = Opcode::iter_values()
+        .position(|&opcode| opcode == instruction.opcode)
+        .unwrap();
+    let modifier = Modifier::iter_values()
+        .position(|&modifier| modifier == instruction.modifier)
+        .unwrap();
+    writer.write_all(&[opcode as u8, modifier as u8])?;
+
+    for field in &[&instruction.a_field, &instruction.b_field] {
+        let mode = AddressMode::iter_values()
+            .position(|&mode| mode == field.address_mode)
+            .unwrap();
+        writer.write_all(&[mode as u8])?;
+        write_signed(writer, i64::from(field.unwrap_value()))?;
+    }
+
+    Ok(())
+}
+
+fn read_instruction<R: Read>(reader: &mut R) -> Result<Instruction, Error> {
+    let mut codes = [0; 2];
+    reader.read_exact(&mut codes)?;
+
+    let opcode = *Opcode::iter_values()
+        .nth(codes[0].into())
+        .ok_or(Error::Corrupt("invalid opcode"))?;
+    let modifier = *Modifier::iter_values()
+        .nth(codes[1].into())
+        .ok_or(Error::Corrupt("invalid modifier"))?;
+
+    let mut read_field = || -> Result<Field, Error> {
+        let mut mode = [0];
+        reader.read_exact(&mut mode)?;
+        let address_mode = *AddressMode::iter_values()
+            .nth(mode[0].into())
+            .ok_or(Error::Corrupt("invalid address mode"))?;
+
+        let mut field = Field::direct(read_signed(reader)? as i32);
+        field.address_mode = address_mode;
+        Ok(field)
+    };
+
+    Ok(Instruction {
+        opcode,
+        modifier,
+        a_field: read_field()?,
+        b_field: read_field()?,
+    })
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Signed values are zigzag encoded, so small negative deltas stay small.
+fn write_signed<W: Write>(writer: &mut W, value: i64) -> io::Result<()> {
+    write_varint(writer, ((value << 1) ^ (value >> 63)) as u64)
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    read_varint_or_end(reader)?.ok_or(Error::Corrupt("unexpected end of file"))
+}
+
+/// Read a varint, or `None` if the reader was already at the end.
+fn read_varint_or_end<R: Read>(reader: &mut R) -> Result<Option<u64>, Error> {
+    let mut value = 0;
+
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(Error::Corrupt("unexpected end of file"))
+            };
+        }
+
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Err(Error::Corrupt("varint too long"))
+}
+
+fn read_signed<R: Read>(reader: &mut R) -> Result<i64, Error> {
+    let value = read_varint(reader)?;
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn record(cycles: usize) -> Replay {
+        let mut core = Core::new(100).unwrap();
+        core.set_trace(false);
+        let warrior =
+            corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+                .unwrap();
+        core.load_warrior(&warrior).unwrap();
+
+        let mut replay = Replay::new(&core);
+        replay.digests = vec![warrior.digest()];
+        for _ in 0..cycles {
+            core.step().unwrap();
+            replay.record(&core);
+        }
+
+        replay
+    }
+
+    #[test]
+    fn records_frames() {
+        let replay = record(3);
+
+        assert_eq!(replay.warriors, vec!["Dwarf".to_string()]);
+        assert_eq!(replay.initial.len(), 4);
+        assert_eq!(replay.frames.len(), 3);
+
+        // ADD writes its B target, MOV writes where that points, JMP writes nothing
+        assert_eq!(replay.frames[0].address, 0);
+        assert_eq!(replay.frames[0].writes[0].0, 3);
+        assert_eq!(replay.frames[1].writes[0].0, 7);
+        assert_eq!(replay.frames[2].writes, Vec::new());
+
+        let json: serde_json::Value = serde_json::from_str(&replay.to_json()).unwrap();
+        assert_eq!(json["initial"], serde_json::json!([0, 1, 2, 3]));
+        assert_eq!(json["frames"][1], serde_json::json!([0, 1, [7]]));
+    }
+
+    #[test]
+    fn records_events() {
+        let mut battle = Battle::new(corewars_sim::BattleConfig {
+            core_size: 100,
+            max_cycles: 50,
+            ..corewars_sim::BattleConfig::default()
+        })
+        .unwrap();
+        let warrior =
+            corewars_parser::parse(";name Dwarf\nadd #4, 3\nmov 2, @2\njmp -2\ndat #0, #0")
+                .unwrap();
+        battle.core_mut().load_warrior(&warrior).unwrap();
+
+        let mut events = Vec::new();
+        let mut replay = Replay::new(battle.core());
+        battle.run_with_events(|event| {
+            replay.record_event(event);
+            events.push(event.clone());
+        });
+        assert_eq!(replay.frames, record(50).frames);
+
+        // Everything but the end of the round is recorded
+        events.pop();
+        assert_eq!(replay.events(), events);
+    }
+
+    #[test]
+    fn stores_and_streams_replays() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let replay = record(3000);
+
+        let mut store = ReplayStore::open(directory.path()).unwrap();
+        store.save("dwarf-1", &replay).unwrap();
+        assert!(matches!(
+            store.save("../dwarf", &replay),
+            Err(Error::InvalidId(_))
+        ));
+
+        // The index is persisted, so a new store finds the replay
+        let store = ReplayStore::open(directory.path()).unwrap();
+        assert_eq!(store.index()["dwarf-1"].frames, 3000);
+        assert_eq!(store.index()["dwarf-1"].digests, replay.digests);
+        assert_eq!(store.load("dwarf-1").unwrap(), replay);
+
+        let range: Vec<Frame> = store
+            .read_range("dwarf-1", 1020..2050)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(range, replay.frames[1020..2050].to_vec());
+
+        assert!(matches!(
+            store.read_range("missing", 0..1),
+            Err(Error::UnknownBattle(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_replays() {
+        let directory = assert_fs::TempDir::new().unwrap();
+        let mut store = ReplayStore::open(directory.path()).unwrap();
+        let replay = record(0);
+        store.save("dwarf", &replay).unwrap();
+        let path = directory.path().join("dwarf.replay");
+
+        let mut header = Vec::new();
+        write_replay(&mut header, &replay).unwrap();
+
+        // A warrior name claiming to be longer than anything could be
+        let mut file = header[..6].to_vec();
+        write_varint(&mut file, 1).unwrap();
+        write_varint(&mut file, u64::MAX).unwrap();
+        fs::write(&path, &file).unwrap();
+        assert!(matches!(store.load("dwarf"), Err(Error::Corrupt(_))));
+
+        // Cycles which overflow
+        let mut block = Vec::new();
+        write_varint(&mut block, 2).unwrap();
+        for _ in 0..2 {
+            write_varint(&mut block, u64::MAX).unwrap();
+            block.extend_from_slice(&[0, 0, 0]);
+        }
+        let block = compress::compress(&block);
+        let mut file = header.clone();
+        for value in [0, 1, block.len() as u64] {
+            write_varint(&mut file, value).unwrap();
+        }
+        file.extend_from_slice(&block);
+        fs::write(&path, &file).unwrap();
+        assert!(matches!(store.load("dwarf"), Err(Error::Corrupt(_))));
+
+        // A block longer than the rest of the file
+        let mut file = header;
+        for value in [0, 1, u64::MAX] {
+            write_varint(&mut file, value).unwrap();
+        }
+        fs::write(&path, &file).unwrap();
+        assert!(matches!(store.load("dwarf"), Err(Error::Corrupt(_))));
+        assert!(matches!(
+            store.read_range("dwarf", 5..10).unwrap().next(),
+            Some(Err(Error::Corrupt(_)))
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+//! Compressing replay blocks with zstd. Blocks repeat the same few
+//! instructions and deltas over and over, so even a fast level shrinks them
+//! well.
+
+use std::io::Read;
+
+use super::Error;
+
+/// The zstd compression level, favouring speed since replays are written
+/// while battles run
+const LEVEL: i32 = 3;
+
+/// The most a block may decompress to, which limits how much memory a
+/// corrupt or malicious block can use
+const MAX_BLOCK: u64 = 64 * 1024 * 1024;
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    zstd::bulk::compress(data, LEVEL).expect("compressing in memory can't fail")
+}
+
+pub fn decompress(compressed: &[u8]) -> Result<Vec<u8>, Error> {
+    let corrupt = |_| Error::Corrupt("invalid compressed block");
+
+    let mut data = Vec::new();
+    zstd::stream::Decoder::new(compressed)
+        .map_err(corrupt)?
+        .single_frame()
+        .take(MAX_BLOCK + 1)
+        .read_to_end(&mut data)
+        .map_err(corrupt)?;
+
+    if data.len() as u64 > MAX_BLOCK {
+        return Err(Error::Corrupt("compressed block is too long"));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let repetitive: Vec<u8> = (0..5000).map(|i| (i % 7 * i % 3) as u8).collect();
+        let mixed: Vec<u8> = (0..3000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .chain(repetitive.iter().copied())
+            .collect();
+
+        for data in [&b""[..], b"abc", b"aaaaaaaaaa", &repetitive, &mixed] {
+            let compressed = compress(data);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+        assert!(compress(&repetitive).len() < repetitive.len() / 10);
+    }
+
+    #[test]
+    fn rejects_corrupt_blocks() {
+        let compressed = compress(b"abcdabcdabcdabcd");
+        assert!(matches!(
+            decompress(b"not zstd at all"),
+            Err(Error::Corrupt(_))
+        ));
+
+        // Claims to be far longer than any block could be
+        let huge = compress(&vec![0; MAX_BLOCK as usize + 1]);
+        assert!(matches!(decompress(&huge), Err(Error::Corrupt(_))));
+
+        for length in 0..compressed.len() - 1 {
+            assert!(decompress(&compressed[..length]).is_err());
+        }
+    }
+}
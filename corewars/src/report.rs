@@ -0,0 +1,281 @@
+//! Reporting of errors and warnings on the command line. Where possible, errors
+//! are rendered similarly to rustc diagnostics, with the offending line of
+//! input and a marker underneath the part of the line that caused the error.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use corewars_core::text;
+use corewars_parser as parser;
+
+use crate::messages::Message;
+
+/// ANSI escape codes used for styling output
+mod style {
+    pub const RESET: &str = "\x1b[0m";
+    pub const BOLD: &str = "\x1b[1m";
+    pub const RED: &str = "\x1b[1;31m";
+    pub const YELLOW: &str = "\x1b[1;33m";
+    pub const BLUE: &str = "\x1b[1;34m";
+}
+
+/// The severity of a reported message, which determines its label and color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> String {
+        let key = match self {
+            Self::Error => "severity-error",
+            Self::Warning => "severity-warning",
+        };
+        Message::new(key).to_string()
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Self::Error => style::RED,
+            Self::Warning => style::YELLOW,
+        }
+    }
+}
+
+impl From<parser::Severity> for Severity {
+    fn from(severity: parser::Severity) -> Self {
+        match severity {
+            parser::Severity::Error => Self::Error,
+            parser::Severity::Warning => Self::Warning,
+        }
+    }
+}
+
+/// Formats messages for display, optionally using ANSI colors.
+#[derive(Clone, Copy, Debug)]
+pub struct Reporter {
+    color: bool,
+}
+
+impl Reporter {
+    /// Create a reporter, using colors only if they are supported. Colors are
+    /// disabled if stderr is not a terminal or if `NO_COLOR` is set, as
+    /// described by <https://no-color.org>.
+    pub fn new() -> Self {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+        Self::with_color(!no_color && io::stderr().is_terminal())
+    }
+
+    /// Create a reporter which does or does not use colors.
+    pub fn with_color(color: bool) -> Self {
+        Self { color }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, style::RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Render a single-line message with no source context.
+    pub fn message(&self, severity: Severity, message: &str) -> String {
+        self.header(severity, &severity.label(), message)
+    }
+
+    /// Render a single-line message with its diagnostic code, like those of
+    /// the parser, e.g. "warning[W0007]: ...".
+    pub fn coded_message(&self, severity: Severity, code: &str, message: &str) -> String {
+        let label = format!("{}[{}]", severity.label(), code);
+        self.header(severity, &label, message)
+    }
+
+    fn header(&self, severity: Severity, label: &str, message: &str) -> String {
+        format!(
+            "{}{}",
+            self.paint(severity.color(), label),
+            self.paint(style::BOLD, &format!(": {}", message)),
+        )
+    }
+
+    /// Render a parser error, including the input line that caused it if
+    /// the error's location is known. `file_name` is used to refer to the input.
+    pub fn parse_error(&self, error: &parser::Error, input: &str, file_name: &str) -> String {
+        self.diagnostic(&parser::Diagnostic::from(error), input, file_name)
+    }
+
+    /// Render a parser error or warning, like [`parse_error`](Self::parse_error).
+    pub fn diagnostic(
+        &self,
+        diagnostic: &parser::Diagnostic,
+        input: &str,
+        file_name: &str,
+    ) -> String {
+        let severity = Severity::from(diagnostic.severity);
+        let label = format!("{}[{}]", severity.label(), diagnostic.code);
+        let header = self.header(severity, &label, &diagnostic.message);
+
+        let span = match diagnostic.span {
+            Some(span) => span,
+            None => return header,
+        };
+
+        let source = match input.lines().nth(span.line.saturating_sub(1)) {
+            Some(source) => source,
+            None => return header,
+        };
+
+        let start = floor_char_boundary(source, span.start);
+        let end = floor_char_boundary(source, span.end.max(start));
+
+        // Tabs and wide characters take up more than one column, so the
+        // marker is placed by display width rather than by bytes or chars
+        let column = text::width(&source[..start]);
+        let marker_len = text::width(&source[..end]).saturating_sub(column).max(1);
+
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        let lines = [
+            header,
+            format!(
+                "{}{} {}:{}:{}",
+                gutter,
+                self.paint(style::BLUE, "-->"),
+                file_name,
+                span.line,
+                text::characters(&source[..start]) + 1
+            ),
+            format!("{} {}", gutter, self.paint(style::BLUE, "|")),
+            format!(
+                "{} {}",
+                self.paint(style::BLUE, &format!("{} |", line_number)),
+                text::expand_tabs(source)
+            ),
+            format!(
+                "{} {} {}{}",
+                gutter,
+                self.paint(style::BLUE, "|"),
+                " ".repeat(column),
+                self.paint(severity.color(), &"^".repeat(marker_len)),
+            ),
+        ];
+
+        lines.join("\n")
+    }
+}
+
+/// The largest index no greater than `index` which is on a character
+/// boundary of `text`, so it can be sliced without panicking.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn parse_error(input: &str) -> parser::Error {
+        match parser::parse(input) {
+            parser::Result::Err(err, _) => err,
+            parser::Result::Ok(warrior, _) => panic!("Expected error, got {:?}", warrior),
+        }
+    }
+
+    #[test]
+    fn renders_source_line() {
+        let input = "mov 0, 1\n    jmp missing\n";
+
+        let rendered =
+            Reporter::with_color(false).parse_error(&parse_error(input), input, "warrior.red");
+
+        assert_eq!(
+            rendered,
+            [
+                r#"error[E0001]: no such label "missing""#,
+                " --> warrior.red:2:9",
+                "  |",
+                "2 |     jmp missing",
+                "  |         ^^^^^^^",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn renders_warning() {
+        let input = "org 0\norg 1\nmov 0, 1";
+        let result = parser::parse(input);
+
+        let rendered =
+            Reporter::with_color(false).diagnostic(&result.diagnostics()[0], input, "warrior.red");
+
+        assert_eq!(
+            rendered,
+            [
+                r#"warning[W0001]: origin already defined as "0", new definition "1" will be ignored"#,
+                " --> warrior.red:2:1",
+                "  |",
+                "2 | org 1",
+                "  | ^^^^^",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn renders_tabs_and_unicode() {
+        let input = ";author José 戦争\n\tmov\t0, missing\n";
+
+        let rendered =
+            Reporter::with_color(false).parse_error(&parse_error(input), input, "warrior.red");
+
+        assert_eq!(
+            rendered,
+            [
+                r#"error[E0001]: no such label "missing""#,
+                " --> warrior.red:2:9",
+                "  |",
+                "2 |         mov     0, missing",
+                "  |                    ^^^^^^^",
+            ]
+            .join("\n")
+        );
+
+        let input = "; José 戦争\nmov 0, 1 2 ; 戦争";
+        let rendered = Reporter::with_color(false).parse_error(&parse_error(input), input, "-");
+        assert!(rendered.ends_with("2 | mov 0, 1 2 ; 戦争\n  |          ^"));
+    }
+
+    #[test]
+    fn renders_colors() {
+        let input = "mov 0, 1 2";
+
+        let rendered = Reporter::with_color(true).parse_error(&parse_error(input), input, "-");
+
+        assert!(rendered.starts_with("\x1b[1;31merror[E0003]\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;34m1 |\x1b[0m mov 0, 1 2"));
+    }
+
+    #[test]
+    fn renders_without_span() {
+        let rendered = Reporter::with_color(false).message(Severity::Warning, "something odd");
+
+        assert_eq!(rendered, "warning: something odd");
+    }
+}
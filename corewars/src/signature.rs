@@ -0,0 +1,211 @@
+//! Checking that submitted warriors were signed by their author, so that
+//! public hills can reject warriors submitted under someone else's name.
+//!
+//! Warriors carry a detached signature in a `;signature` comment, which signs
+//! the hex SHA-256 [digest](Warrior::digest) of the warrior's program, as
+//! text. No other program can feasibly be found with the same digest, so
+//! changing the program invalidates the signature, but comments and
+//! formatting don't. The signature is checked against the public keys
+//! registered for the warrior's `;author` in [`AuthorKeys`], using a
+//! [`Verifier`] for the signature scheme the hill has chosen, such as
+//! [`Ed25519`].
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+
+use corewars_core::Warrior;
+
+mod ed25519;
+
+pub use ed25519::Ed25519;
+
+/// An error verifying the signature of a warrior.
+#[derive(ThisError, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The warrior has no `;signature` comment.
+    #[error("warrior is not signed")]
+    Unsigned,
+
+    /// The warrior has no `;author` comment, so there is no key to check.
+    #[error("warrior has no author")]
+    NoAuthor,
+
+    /// No keys are registered for the warrior's author.
+    #[error("no keys registered for author {0:?}")]
+    UnknownAuthor(String),
+
+    /// A signature or key was not valid hexadecimal.
+    #[error("invalid hex string {0:?}")]
+    InvalidHex(String),
+
+    /// The signature was not made with any of the author's keys.
+    #[error("signature does not match any key registered for {0:?}")]
+    BadSignature(String),
+}
+
+/// A signature scheme which can check that a signature was made with the
+/// private key corresponding to a public key.
+pub trait Verifier {
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// The public keys registered for each author, hex-encoded. In JSON, this
+/// is an object mapping each author to a list of keys, e.g.
+/// `{"keys": {"Dewdney": ["ab12..."]}}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorKeys {
+    keys: BTreeMap<String, Vec<String>>,
+}
+
+impl AuthorKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a hex-encoded public key for `author`. Authors may have
+    /// several keys, e.g. while rotating to a new one.
+    pub fn register(&mut self, author: &str, key: &str) -> Result<(), Error> {
+        decode_hex(key)?;
+
+        self.keys
+            .entry(author.to_string())
+            .or_default()
+            .push(key.to_lowercase());
+        Ok(())
+    }
+
+    /// Read keys written as JSON, checking that each is valid hex.
+    pub fn from_json(text: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let parsed: Self = serde_json::from_str(text)?;
+        let mut keys = Self::new();
+        for (author, author_keys) in &parsed.keys {
+            for key in author_keys {
+                keys.register(author, key)?;
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The keys registered for `author`.
+    pub fn keys(&self, author: &str) -> &[String] {
+        self.keys.get(author).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Check that `warrior` was signed by a key registered for its author.
+pub fn verify(warrior: &Warrior, keys: &AuthorKeys, verifier: &dyn Verifier) -> Result<(), Error> {
+    let signature = warrior
+        .metadata
+        .signature
+        .as_deref()
+        .ok_or(Error::Unsigned)?;
+    let signature = decode_hex(signature)?;
+
+    let author = warrior.metadata.author.as_deref().ok_or(Error::NoAuthor)?;
+    let author_keys = keys.keys(author);
+    if author_keys.is_empty() {
+        return Err(Error::UnknownAuthor(author.to_string()));
+    }
+
+    let digest = warrior.digest();
+    for key in author_keys {
+        if verifier.verify(&decode_hex(key)?, digest.as_bytes(), &signature) {
+            return Ok(());
+        }
+    }
+
+    Err(Error::BadSignature(author.to_string()))
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::InvalidHex(text.to_string());
+
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(invalid());
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// A stand-in for a real signature scheme: a signature is valid if it is
+    /// the first byte of the key followed by the message.
+    struct Prefix;
+
+    impl Verifier for Prefix {
+        fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+            signature.split_first() == Some((&public_key[0], message))
+        }
+    }
+
+    fn signed(author: &str, key_byte: u8) -> Warrior {
+        let mut warrior = corewars_parser::parse(&format!(";author {}\nmov 0, 1", author)).unwrap();
+
+        let mut signature = format!("{:02x}", key_byte);
+        for byte in warrior.digest().bytes() {
+            signature.push_str(&format!("{:02x}", byte));
+        }
+        warrior.metadata.signature = Some(signature);
+        warrior
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let mut keys = AuthorKeys::new();
+        keys.register("Dewdney", "AB").unwrap();
+        keys.register("Dewdney", "cd").unwrap();
+        assert_eq!(keys.keys("Dewdney"), ["ab", "cd"]);
+        assert_eq!(
+            keys.register("Dewdney", "xyz"),
+            Err(Error::InvalidHex("xyz".into()))
+        );
+
+        assert_eq!(verify(&signed("Dewdney", 0xcd), &keys, &Prefix), Ok(()));
+        assert_eq!(
+            verify(&signed("Dewdney", 0x12), &keys, &Prefix),
+            Err(Error::BadSignature("Dewdney".into()))
+        );
+        assert_eq!(
+            verify(&signed("Impostor", 0xab), &keys, &Prefix),
+            Err(Error::UnknownAuthor("Impostor".into()))
+        );
+
+        // Changing the program invalidates the signature
+        let mut modified = signed("Dewdney", 0xab);
+        modified.program.instructions[0].b_field = corewars_core::load_file::Field::direct(2);
+        assert_eq!(
+            verify(&modified, &keys, &Prefix),
+            Err(Error::BadSignature("Dewdney".into()))
+        );
+
+        let unsigned = corewars_parser::parse(";author Dewdney\nmov 0, 1").unwrap();
+        assert_eq!(verify(&unsigned, &keys, &Prefix), Err(Error::Unsigned));
+    }
+
+    #[test]
+    fn reads_keys() {
+        let keys = AuthorKeys::from_json(r#"{"keys": {"Dewdney": ["AB", "cd"]}}"#).unwrap();
+        assert_eq!(keys.keys("Dewdney"), ["ab", "cd"]);
+
+        let error = AuthorKeys::from_json(r#"{"keys": {"Dewdney": ["xyz"]}}"#).unwrap_err();
+        assert_eq!(error.to_string(), "invalid hex string \"xyz\"");
+    }
+
+    #[test]
+    fn reads_signature_comment() {
+        let warrior = corewars_parser::parse(";author Dewdney\n;signature 00ff\nmov 0, 1").unwrap();
+        assert_eq!(warrior.metadata.signature.as_deref(), Some("00ff"));
+        assert!(warrior.to_string().contains(";signature 00ff\n"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Verifying ed25519 signatures, as specified in RFC 8032.
+
+use std::convert::TryInto;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use super::Verifier;
+
+/// The ed25519 signature scheme, with 32 byte public keys and 64 byte
+/// signatures. Signatures are checked strictly, so non-canonical encodings
+/// and keys of small order are rejected.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ed25519;
+
+impl Verifier for Ed25519 {
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        let key = match public_key.try_into().map(VerifyingKey::from_bytes) {
+            Ok(Ok(key)) => key,
+            _ => return false,
+        };
+        let signature = match Signature::from_slice(signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+
+        key.verify_strict(message, &signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::decode_hex;
+    use super::*;
+
+    /// Public key, message and signature, from RFC 8032 section 7.1
+    const VECTORS: [(&str, &str, &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    /// The order of the base point, little-endian
+    const ORDER: [u8; 32] = [
+        0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde,
+        0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x10,
+    ];
+
+    #[test]
+    fn verifies_test_vectors() {
+        for (key, message, signature) in VECTORS {
+            let key = decode_hex(key).unwrap();
+            let message = decode_hex(message).unwrap();
+            let signature = decode_hex(signature).unwrap();
+            assert!(Ed25519.verify(&key, &message, &signature));
+
+            // Any change to the message or signature invalidates it
+            let mut changed = message.clone();
+            changed.push(0);
+            assert!(!Ed25519.verify(&key, &changed, &signature));
+            for i in [0, 31, 32, 63] {
+                let mut changed = signature.clone();
+                changed[i] ^= 1;
+                assert!(!Ed25519.verify(&key, &message, &changed));
+            }
+        }
+
+        let (key, message, signature) = VECTORS[0];
+        let (other_key, _, _) = VECTORS[1];
+        assert!(!Ed25519.verify(
+            &decode_hex(other_key).unwrap(),
+            &decode_hex(message).unwrap(),
+            &decode_hex(signature).unwrap()
+        ));
+        assert!(!Ed25519.verify(&decode_hex(key).unwrap()[1..], b"", &[0; 64]));
+        assert!(!Ed25519.verify(&decode_hex(key).unwrap(), b"", &[0; 63]));
+    }
+
+    #[test]
+    fn rejects_non_canonical_scalars() {
+        let (key, message, signature) = VECTORS[0];
+        let mut signature = decode_hex(signature).unwrap();
+
+        // S + L is an equivalent scalar, but a different signature
+        let mut carry = 0;
+        for (byte, order) in signature[32..].iter_mut().zip(ORDER) {
+            let sum = u16::from(*byte) + u16::from(order) + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!Ed25519.verify(
+            &decode_hex(key).unwrap(),
+            &decode_hex(message).unwrap(),
+            &signature
+        ));
+    }
+
+    #[test]
+    fn rejects_points_off_the_curve() {
+        let (_, message, signature) = VECTORS[0];
+        let message = decode_hex(message).unwrap();
+        let signature = decode_hex(signature).unwrap();
+
+        // y = 2 has no x on the curve
+        let mut key = [0; 32];
+        key[0] = 2;
+        assert!(!Ed25519.verify(&key, &message, &signature));
+
+        // The identity has small order
+        let mut key = [0; 32];
+        key[0] = 1;
+        assert!(!Ed25519.verify(&key, &message, &signature));
+    }
+}
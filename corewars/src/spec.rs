@@ -0,0 +1,528 @@
+//! Tournament specs: files describing everything about a tournament, from
+//! the warriors to the settings of the hill and how rounds are scored, so an
+//! experiment can be repeated exactly and its results say how they were made.
+//!
+//! Specs are written in a small subset of TOML:
+//!
+//! ```toml
+//! # Do stones beat imps?
+//! name = "stones-vs-imps"
+//! warriors = ["imp.red", "dwarf.red", "stones.red"]
+//! rounds = 100
+//! seed = 0
+//! pairing = "round-robin"
+//!
+//! [hill]
+//! core_size = 8000
+//! max_cycles = 80000
+//! max_length = 100
+//! min_distance = 100
+//!
+//! [scoring]
+//! win = 3
+//! tie = 1
+//! loss = 0
+//! ```
+//!
+//! Only `warriors` is required, and the rest default to the values above,
+//! those of the standard '94 hill. Warriors are found relative to the spec,
+//! and each file may hold an [archive](corewars_parser::archive) of several.
+//! The `seed` shifts where warriors are placed, as in
+//! [`run_seeded`](tournament::run_seeded), and `pairing` is any
+//! [pairing](Pairing). Values are strings, numbers, or arrays of strings,
+//! which may span several lines.
+//!
+//! [`write_archive`] saves a spec with its warriors and results, in a
+//! directory the spec can be run from again.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error as ThisError;
+
+use corewars_core::Warrior;
+use corewars_sim::{BattleConfig, ConfigError};
+
+use crate::messages::Message;
+use crate::tournament::{self, Entrants, Pairing, Record, Results};
+
+/// The file [`write_archive`] saves the warriors of a tournament in.
+pub const ARCHIVED_WARRIORS: &str = "warriors.red";
+
+/// An error in a spec file.
+#[derive(ThisError, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("line {line}: expected KEY = VALUE or [SECTION]")]
+    Syntax { line: usize },
+
+    #[error("line {line}: unknown setting {key:?}")]
+    UnknownKey { line: usize, key: String },
+
+    #[error("line {line}: invalid value for {key:?}: {reason}")]
+    InvalidValue {
+        line: usize,
+        key: String,
+        reason: String,
+    },
+
+    #[error("no warriors given")]
+    NoWarriors,
+}
+
+/// How many points each round is worth, by its outcome for a warrior.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Scoring {
+    pub win: f64,
+    pub tie: f64,
+    pub loss: f64,
+}
+
+/// The KOTH scoring of [`Record::score`].
+impl Default for Scoring {
+    fn default() -> Self {
+        Self {
+            win: 3.0,
+            tie: 1.0,
+            loss: 0.0,
+        }
+    }
+}
+
+impl Scoring {
+    /// The points of `record`, averaged over the rounds and multiplied by
+    /// 100 like [`Record::score`].
+    pub fn score(&self, record: &Record) -> f64 {
+        let points = f64::from(record.wins) * self.win
+            + f64::from(record.ties) * self.tie
+            + f64::from(record.losses) * self.loss;
+        points * 100.0 / f64::from(record.rounds().max(1))
+    }
+}
+
+/// A tournament described by a spec file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spec {
+    pub name: Option<String>,
+
+    /// The files containing the warriors, relative to the spec
+    pub warriors: Vec<PathBuf>,
+
+    pub rounds: u32,
+    pub seed: u64,
+    pub pairing: Pairing,
+    pub config: BattleConfig,
+    pub scoring: Scoring,
+}
+
+impl Spec {
+    /// Parse a spec file, as described in the [module documentation](self).
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut spec = Self {
+            name: None,
+            warriors: Vec::new(),
+            rounds: 100,
+            seed: 0,
+            pairing: Pairing::default(),
+            config: BattleConfig::default(),
+            scoring: Scoring::default(),
+        };
+
+        for setting in settings(text)? {
+            let invalid = |reason: String| Error::InvalidValue {
+                line: setting.line,
+                key: setting.key.clone(),
+                reason,
+            };
+            let value = &setting.value;
+
+            match setting.key.as_str() {
+                "name" => spec.name = Some(value.string().map_err(invalid)?),
+                "warriors" => {
+                    spec.warriors = value
+                        .strings()
+                        .map_err(invalid)?
+                        .into_iter()
+                        .map(PathBuf::from)
+                        .collect()
+                }
+                "rounds" => spec.rounds = value.number().map_err(invalid)?,
+                "seed" => spec.seed = value.number().map_err(invalid)?,
+                "pairing" => {
+                    spec.pairing = value.string().map_err(invalid)?.parse().map_err(invalid)?
+                }
+                "hill.core_size" => spec.config.core_size = value.number().map_err(invalid)?,
+                "hill.max_cycles" => spec.config.max_cycles = value.number().map_err(invalid)?,
+                "hill.max_length" => spec.config.max_length = value.number().map_err(invalid)?,
+                "hill.min_distance" => {
+                    spec.config.min_distance = value.number().map_err(invalid)?
+                }
+                "scoring.win" => spec.scoring.win = value.number().map_err(invalid)?,
+                "scoring.tie" => spec.scoring.tie = value.number().map_err(invalid)?,
+                "scoring.loss" => spec.scoring.loss = value.number().map_err(invalid)?,
+                _ => {
+                    return Err(Error::UnknownKey {
+                        line: setting.line,
+                        key: setting.key,
+                    })
+                }
+            }
+
+            if setting.key == "rounds" && spec.rounds == 0 {
+                return Err(invalid(Message::new("positive-rounds").to_string()));
+            }
+        }
+
+        if spec.warriors.is_empty() {
+            return Err(Error::NoWarriors);
+        }
+        Ok(spec)
+    }
+
+    /// Run the tournament between `entrants`, which should be the warriors
+    /// of the spec.
+    pub fn run(&self, entrants: &Entrants) -> Result<Results, ConfigError> {
+        tournament::run_seeded(&self.config, entrants, self.rounds, self.pairing, self.seed)
+    }
+
+    /// Each warrior's name and score with the spec's [scoring](Scoring),
+    /// from the highest score to the lowest.
+    pub fn standings(&self, results: &Results) -> Vec<(String, f64)> {
+        let crosstable = &results.crosstable;
+        let mut standings: Vec<(String, f64)> = (0..crosstable.warriors.len())
+            .map(|i| {
+                let score = self.scoring.score(&crosstable.total(i));
+                (crosstable.warriors[i].clone(), score)
+            })
+            .collect();
+        standings.sort_by(|a, b| b.1.total_cmp(&a.1));
+        standings
+    }
+
+    /// The `results` as text, followed by the [standings](Self::standings).
+    pub fn summary(&self, results: &Results) -> String {
+        let mut text = format!("{}\n\n", results);
+        text.push_str(
+            &Message::new("spec-standings")
+                .arg("win", self.scoring.win)
+                .arg("tie", self.scoring.tie)
+                .arg("loss", self.scoring.loss)
+                .to_string(),
+        );
+        for (name, score) in self.standings(results) {
+            text.push_str(&format!("\n  {}: {:.1}", name, score));
+        }
+        text
+    }
+}
+
+/// The spec file, with every setting written out.
+impl fmt::Display for Spec {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(name) = &self.name {
+            writeln!(formatter, "name = {}", quote(name))?;
+        }
+        let warriors: Vec<String> = self
+            .warriors
+            .iter()
+            .map(|path| quote(&path.to_string_lossy()))
+            .collect();
+        writeln!(formatter, "warriors = [{}]", warriors.join(", "))?;
+        writeln!(formatter, "rounds = {}", self.rounds)?;
+        writeln!(formatter, "seed = {}", self.seed)?;
+        writeln!(formatter, "pairing = {}", quote(&self.pairing.to_string()))?;
+
+        writeln!(formatter, "\n[hill]")?;
+        writeln!(formatter, "core_size = {}", self.config.core_size)?;
+        writeln!(formatter, "max_cycles = {}", self.config.max_cycles)?;
+        writeln!(formatter, "max_length = {}", self.config.max_length)?;
+        writeln!(formatter, "min_distance = {}", self.config.min_distance)?;
+
+        writeln!(formatter, "\n[scoring]")?;
+        writeln!(formatter, "win = {}", self.scoring.win)?;
+        writeln!(formatter, "tie = {}", self.scoring.tie)?;
+        write!(formatter, "loss = {}", self.scoring.loss)
+    }
+}
+
+/// Save a tournament in `directory`: the spec, as `spec.toml`, its
+/// `warriors`, as an [archive](corewars_parser::archive) the saved spec
+/// reads them from, and its `results`, as `results.txt` with the
+/// [summary](Spec::summary) and as `results.json`.
+pub fn write_archive(
+    directory: &Path,
+    spec: &Spec,
+    warriors: &[Warrior],
+    results: &Results,
+) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+
+    let archived = Spec {
+        warriors: vec![PathBuf::from(ARCHIVED_WARRIORS)],
+        ..spec.clone()
+    };
+    fs::write(directory.join("spec.toml"), format!("{}\n", archived))?;
+    fs::write(
+        directory.join(ARCHIVED_WARRIORS),
+        corewars_parser::archive(warriors),
+    )?;
+
+    fs::write(
+        directory.join("results.txt"),
+        format!("{}\n", spec.summary(results)),
+    )?;
+    fs::write(directory.join("results.json"), results.to_json())
+}
+
+/// A `key = value` line of a spec, with the key prefixed by its section.
+pub(crate) struct Setting {
+    pub line: usize,
+    pub key: String,
+    pub value: Value,
+}
+
+/// The value of a setting.
+pub(crate) enum Value {
+    String(String),
+    Number(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn string(&self) -> Result<String, String> {
+        match self {
+            Self::String(string) => Ok(string.clone()),
+            _ => Err("expected a string".into()),
+        }
+    }
+
+    pub fn strings(&self) -> Result<Vec<String>, String> {
+        match self {
+            Self::Array(values) => values.iter().map(Value::string).collect(),
+            _ => Err("expected an array of strings".into()),
+        }
+    }
+
+    pub fn number<T: std::str::FromStr>(&self) -> Result<T, String> {
+        match self {
+            Self::Number(number) => number
+                .replace('_', "")
+                .parse()
+                .map_err(|_| format!("invalid number {:?}", number)),
+            _ => Err("expected a number".into()),
+        }
+    }
+}
+
+/// Every setting in a spec, or another file in the same subset of TOML, in
+/// order.
+pub(crate) fn settings(text: &str) -> Result<Vec<Setting>, Error> {
+    let mut settings = Vec::new();
+    let mut section = String::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let syntax = Error::Syntax { line: line_number };
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = format!("{}.", name.trim());
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap_or_default().trim();
+        let mut value = parts
+            .next()
+            .ok_or_else(|| syntax.clone())?
+            .trim()
+            .to_string();
+        if key.is_empty() {
+            return Err(syntax);
+        }
+
+        // Arrays may carry on over the following lines
+        while value.starts_with('[') && !value.ends_with(']') {
+            let (_, next) = lines.next().ok_or_else(|| syntax.clone())?;
+            value.push(' ');
+            value.push_str(strip_comment(next).trim());
+        }
+
+        settings.push(Setting {
+            line: line_number,
+            key: format!("{}{}", section, key),
+            value: parse_value(&value).ok_or(syntax)?,
+        });
+    }
+
+    Ok(settings)
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(items) = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        let mut values = Vec::new();
+        let mut rest = items.trim();
+        while !rest.is_empty() {
+            let (value, after) = parse_string(rest)?;
+            values.push(Value::String(value));
+            rest = after.trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(after) => after.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return None,
+            };
+        }
+        Some(Value::Array(values))
+    } else if text.starts_with('"') {
+        match parse_string(text)? {
+            (value, "") => Some(Value::String(value)),
+            _ => None,
+        }
+    } else if !text.is_empty() && !text.contains(char::is_whitespace) {
+        Some(Value::Number(text.to_string()))
+    } else {
+        None
+    }
+}
+
+/// A quoted string at the start of `text`, and the text after it.
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut value = String::new();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[index + 2..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                escaped @ ('"' | '\\') => value.push(escaped),
+                _ => return None,
+            },
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+/// `line` without any `#` comment, which may not start inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+pub(crate) fn quote(text: &str) -> String {
+    format!("{:?}", text)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_spec() {
+        let spec = Spec::parse(
+            "# Do stones beat imps?\n\
+             name = \"stones # vs imps\"\n\
+             warriors = [\n  \"imp.red\", # the imp\n  \"dwarf.red\",\n]\n\
+             seed = 7\n\
+             pairing = \"swiss=3\"\n\n\
+             [hill]\n\
+             core_size = 8_000\n\
+             max_cycles = 2000\n\n\
+             [scoring]\n\
+             tie = 1.5\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            spec,
+            Spec {
+                name: Some("stones # vs imps".into()),
+                warriors: vec!["imp.red".into(), "dwarf.red".into()],
+                rounds: 100,
+                seed: 7,
+                pairing: Pairing::Swiss { rounds: Some(3) },
+                config: BattleConfig {
+                    core_size: 8000,
+                    max_cycles: 2000,
+                    ..BattleConfig::default()
+                },
+                scoring: Scoring {
+                    tie: 1.5,
+                    ..Scoring::default()
+                },
+            }
+        );
+        assert_eq!(Spec::parse(&spec.to_string()).unwrap(), spec);
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        let error = |text: &str| Spec::parse(text).unwrap_err();
+
+        assert_eq!(error("rounds = 10"), Error::NoWarriors);
+        assert_eq!(error("warriors = [\"imp.red\""), Error::Syntax { line: 1 });
+        assert_eq!(error("\nwarriors"), Error::Syntax { line: 2 });
+        assert_eq!(
+            error("[hill]\nrounds = 10"),
+            Error::UnknownKey {
+                line: 2,
+                key: "hill.rounds".into()
+            }
+        );
+        assert_eq!(
+            error("rounds = \"many\""),
+            Error::InvalidValue {
+                line: 1,
+                key: "rounds".into(),
+                reason: "expected a number".into()
+            }
+        );
+        assert!(matches!(
+            error("pairing = \"ladder\""),
+            Error::InvalidValue { line: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn scores_standings() {
+        let scoring = Scoring {
+            win: 2.0,
+            tie: 1.0,
+            loss: -1.0,
+        };
+        let record = Record {
+            wins: 2,
+            losses: 1,
+            ties: 1,
+        };
+        assert_eq!(scoring.score(&record), 100.0);
+        assert_eq!(Scoring::default().score(&record), record.score());
+    }
+}
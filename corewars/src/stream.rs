@@ -0,0 +1,888 @@
+//! Streaming battles live to web viewers over a websocket, e.g. to watch the
+//! challenges of a hill as they are fought.
+//!
+//! Viewers connect to [`ENDPOINT`] on the address given to
+//! [`Viewers::bind`]. Each battle is sent as text messages of JSON: first a
+//! `start` message with every instruction which differs from an empty core,
+//! then a `tick` message for each batch of cycles, with the instructions
+//! changed by the batch, the address of every process after it and the
+//! [events](Event) of the batch, and finally an `end` message with the
+//! outcome:
+//!
+//! ```text
+//! {"cells":[[0,"MOV.I   $0,     $1"]],"core_size":8000,"type":"start","warriors":["Imp"]}
+//! {"changed":[[1,"MOV.I   $0,     $1"]],"cycle":1,"events":[...],"processes":{"Imp":[1]},"type":"tick","version":1}
+//! {"cycle":1,"outcome":"Imp wins","type":"end"}
+//! ```
+//!
+//! Each event is an object with its kind as `type`, and the fields of the
+//! event, see [`event_json`]. The `version` of a tick is [`Event::VERSION`].
+//!
+//! Viewers control the speed by sending one of these commands as a text
+//! message, which applies to every viewer of the stream:
+//!
+//! - `speed N` to execute N cycles per batch
+//! - `pause` to stop sending batches, and `resume` to start again
+//! - `step` to send a single batch while paused
+//!
+//! A battle stays paused for at most [`DEFAULT_MAX_PAUSE`] after it is first
+//! paused, see [`Viewers::set_max_pause`], so a viewer can't stop a hill.
+//!
+//! Battles with nobody watching are run at full speed without being
+//! streamed, so a hill isn't slowed down by its stream until someone opens
+//! it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use corewars_core::load_file::Instruction;
+use corewars_sim::{Battle, Core, Event, Outcome};
+
+/// The path viewers connect to.
+pub const ENDPOINT: &str = "/battles";
+
+/// The number of cycles in each batch until a viewer changes it.
+pub const DEFAULT_SPEED: usize = 100;
+
+/// How long to wait between batches by default, which is about as often as a
+/// browser redraws.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Appended to the key of a websocket handshake before hashing it, as
+/// defined by RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// How long a battle stays paused by default, from when it is first paused.
+pub const DEFAULT_MAX_PAUSE: Duration = Duration::from_secs(60);
+
+/// The longest handshake request read from a viewer.
+const MAX_REQUEST_LENGTH: usize = 8192;
+
+/// How long a client has to send its whole handshake request.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long sending to a viewer may block before it is dropped, e.g. if it
+/// stopped reading.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The most bytes kept from a viewer before they make up whole frames.
+/// Viewers only send short commands, so anything more is dropped.
+const MAX_RECEIVED_LENGTH: usize = 64 * 1024;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// A command sent by a viewer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// Execute this many cycles per batch
+    Speed(usize),
+    Pause,
+    Resume,
+
+    /// Send a single batch while paused
+    Step,
+}
+
+impl FromStr for Control {
+    type Err = String;
+
+    fn from_str(command: &str) -> Result<Self, Self::Err> {
+        let mut words = command.split_whitespace();
+        let control = match (words.next(), words.next()) {
+            (Some("speed"), Some(cycles)) => match cycles.parse() {
+                Ok(cycles) if cycles > 0 => Self::Speed(cycles),
+                _ => return Err(format!("invalid speed {:?}", cycles)),
+            },
+            (Some("pause"), None) => Self::Pause,
+            (Some("resume"), None) => Self::Resume,
+            (Some("step"), None) => Self::Step,
+            _ => return Err(format!("unknown command {:?}", command)),
+        };
+
+        if words.next().is_some() {
+            return Err(format!("unknown command {:?}", command));
+        }
+        Ok(control)
+    }
+}
+
+/// What changed in a core during a batch of cycles.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tick {
+    /// The number of cycles executed at the end of the batch
+    pub cycle: usize,
+
+    /// Each instruction written during the batch, with its value at the end
+    pub changed: BTreeMap<u32, Instruction>,
+
+    /// The address of each process of each warrior, in the order they will
+    /// execute
+    pub processes: BTreeMap<String, Vec<u32>>,
+
+    /// The events of the batch, in order
+    pub events: Vec<Event>,
+}
+
+impl Tick {
+    /// The state of `core` after a batch which wrote to `written`.
+    pub fn capture(core: &Core, written: &BTreeSet<usize>) -> Self {
+        let mut processes: BTreeMap<String, Vec<u32>> = core
+            .warriors()
+            .iter()
+            .map(|name| (name.clone(), Vec::new()))
+            .collect();
+        for process in core.process_queue().iter() {
+            processes
+                .entry(process.name.clone())
+                .or_default()
+                .push(process.offset.value());
+        }
+
+        Self {
+            cycle: core.cycles(),
+            changed: written
+                .iter()
+                .map(|&index| (index as u32, core.get(index as i32).clone()))
+                .collect(),
+            processes,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let changed: Vec<serde_json::Value> = self
+            .changed
+            .iter()
+            .map(|(address, instruction)| json!([address, instruction.to_string()]))
+            .collect();
+
+        json!({
+            "type": "tick",
+            "version": Event::VERSION,
+            "cycle": self.cycle,
+            "changed": changed,
+            "processes": self.processes,
+            "events": self.events.iter().map(event_json).collect::<Vec<_>>(),
+        })
+        .to_string()
+    }
+}
+
+/// An event as a JSON object, with its [kind](Event::kind) as `type` and
+/// instructions and outcomes as text, e.g.
+/// `{"address":5,"cycle":3,"type":"process_died","warrior":"Imp"}`.
+pub fn event_json(event: &Event) -> serde_json::Value {
+    let mut value = match event {
+        Event::InstructionExecuted {
+            warrior,
+            address,
+            instruction,
+            ..
+        }
+        | Event::CellWritten {
+            warrior,
+            address,
+            instruction,
+            ..
+        } => json!({
+            "warrior": warrior,
+            "address": address,
+            "instruction": instruction.to_string(),
+        }),
+        Event::ProcessSpawned {
+            warrior, address, ..
+        }
+        | Event::ProcessDied {
+            warrior, address, ..
+        } => json!({ "warrior": warrior, "address": address }),
+        Event::WarriorEliminated { warrior, .. } => json!({ "warrior": warrior }),
+        Event::RoundEnded { outcome, .. } => json!({ "outcome": outcome.to_string() }),
+        _ => json!({}),
+    };
+    value["type"] = json!(event.kind());
+    value["cycle"] = json!(event.cycle());
+    value
+}
+
+/// A viewer connected over a websocket.
+#[derive(Debug)]
+pub struct Viewer {
+    stream: TcpStream,
+
+    /// Bytes received which don't make up a whole frame yet
+    received: Vec<u8>,
+}
+
+impl Viewer {
+    /// Complete the websocket handshake with a client which just connected,
+    /// waiting up to a couple of seconds for its request.
+    pub fn accept(mut stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(invalid("incomplete handshake"));
+            }
+            stream.set_read_timeout(Some(remaining))?;
+            let read = stream.read(&mut buffer)?;
+            if read == 0 || request.len() + read > MAX_REQUEST_LENGTH {
+                return Err(invalid("incomplete handshake"));
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+
+        Self::handshake(stream, &request)
+    }
+
+    /// Answer the complete handshake `request` read from `stream`.
+    fn handshake(mut stream: TcpStream, request: &[u8]) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+
+        let request = String::from_utf8_lossy(request);
+        let mut lines = request.lines();
+        let path = lines
+            .next()
+            .and_then(|line| line.strip_prefix("GET "))
+            .and_then(|line| line.split_whitespace().next());
+        if path != Some(ENDPOINT) {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")?;
+            return Err(invalid("not a request for the battle stream"));
+        }
+
+        let key = lines.find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                Some(value.trim())
+            } else {
+                None
+            }
+        });
+        let key = match key {
+            Some(key) => key,
+            None => {
+                stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+                return Err(invalid("not a websocket request"));
+            }
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        )?;
+
+        Ok(Self {
+            stream,
+            received: Vec::new(),
+        })
+    }
+
+    /// Send a text message.
+    pub fn send(&mut self, text: &str) -> io::Result<()> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    /// Read the commands sent since the last call, without waiting for more.
+    /// Returns `None` once the viewer has disconnected.
+    pub fn poll(&mut self) -> io::Result<Option<Vec<Control>>> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 1024];
+        let result = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break Ok(false),
+                Ok(_) if self.received.len() > MAX_RECEIVED_LENGTH => {
+                    break Err(invalid("too much input from viewer"))
+                }
+                Ok(read) => self.received.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(true),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => break Err(err),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        let mut connected = result?;
+
+        let mut controls = Vec::new();
+        while let Some((opcode, payload)) = self.next_frame() {
+            match opcode {
+                OPCODE_TEXT => {
+                    // Unknown commands are ignored, to be forward compatible
+                    if let Ok(control) = String::from_utf8_lossy(&payload).parse() {
+                        controls.push(control);
+                    }
+                }
+                OPCODE_PING => self.send_frame(OPCODE_PONG, &payload)?,
+                OPCODE_CLOSE => {
+                    // The close may race with the viewer going away
+                    let _ = self.send_frame(OPCODE_CLOSE, &[]);
+                    connected = false;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(if connected { Some(controls) } else { None })
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(len as u8),
+            len if len <= usize::from(u16::MAX) => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+
+        self.stream.write_all(&frame)
+    }
+
+    /// Take the next whole frame out of the received bytes, with its opcode
+    /// and unmasked payload.
+    fn next_frame(&mut self) -> Option<(u8, Vec<u8>)> {
+        let bytes = &self.received;
+        if bytes.len() < 2 {
+            return None;
+        }
+
+        let opcode = bytes[0] & 0x0f;
+        let masked = bytes[1] & 0x80 != 0;
+        let (len, mut start) = match bytes[1] & 0x7f {
+            126 => (
+                u64::from(u16::from_be_bytes([*bytes.get(2)?, *bytes.get(3)?])),
+                4,
+            ),
+            127 => {
+                let mut len = [0; 8];
+                len.copy_from_slice(bytes.get(2..10)?);
+                (u64::from_be_bytes(len), 10)
+            }
+            len => (u64::from(len), 2),
+        };
+
+        let mask = if masked {
+            let mask = bytes.get(start..start + 4)?.to_vec();
+            start += 4;
+            mask
+        } else {
+            vec![0; 4]
+        };
+        let end = start.checked_add(usize::try_from(len).ok()?)?;
+        let mut payload = bytes.get(start..end)?.to_vec();
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        self.received.drain(..end);
+        Some((opcode, payload))
+    }
+}
+
+/// The viewers of a stream of battles, and the speed they chose.
+#[derive(Debug)]
+pub struct Viewers {
+    listener: TcpListener,
+
+    /// Clients which connected but haven't sent their whole handshake yet
+    pending: Vec<Pending>,
+    viewers: Vec<Viewer>,
+    speed: usize,
+    paused: bool,
+    interval: Duration,
+    max_pause: Duration,
+}
+
+/// A client which connected, and the part of its handshake request read so
+/// far. Handshakes are read without blocking, so a client which never sends
+/// one can't hold up the battles.
+#[derive(Debug)]
+struct Pending {
+    stream: TcpStream,
+    request: Vec<u8>,
+    connected: Instant,
+}
+
+impl Pending {
+    /// Read what the client sent, returning the whole request once it has
+    /// arrived, and an error if it never will.
+    fn poll(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = [0; 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(invalid("incomplete handshake")),
+                Ok(read) => {
+                    if self.request.len() + read > MAX_REQUEST_LENGTH {
+                        return Err(invalid("handshake request too long"));
+                    }
+                    self.request.extend_from_slice(&buffer[..read]);
+                    if self.request.ends_with(b"\r\n\r\n") {
+                        return Ok(Some(std::mem::take(&mut self.request)));
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        if self.connected.elapsed() > HANDSHAKE_TIMEOUT {
+            Err(invalid("incomplete handshake"))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Viewers {
+    /// Listen for viewers on `address`.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            pending: Vec::new(),
+            viewers: Vec::new(),
+            speed: DEFAULT_SPEED,
+            paused: false,
+            interval: DEFAULT_INTERVAL,
+            max_pause: DEFAULT_MAX_PAUSE,
+        })
+    }
+
+    /// The address viewers can connect to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Change how long to wait between batches.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Change how long a battle stays paused, from when it is first paused.
+    /// Once that has passed, pausing has no effect until the next battle.
+    pub fn set_max_pause(&mut self, max_pause: Duration) {
+        self.max_pause = max_pause;
+    }
+
+    /// The number of cycles in each batch.
+    pub fn speed(&self) -> usize {
+        self.speed
+    }
+
+    /// Accept any viewers who connected since the last call, and return
+    /// whether anyone is watching.
+    pub fn is_watched(&mut self) -> bool {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.pending.push(Pending {
+                    stream,
+                    request: Vec::new(),
+                    connected: Instant::now(),
+                });
+            }
+        }
+
+        for mut client in std::mem::take(&mut self.pending) {
+            match client.poll() {
+                Ok(None) => self.pending.push(client),
+                Ok(Some(request)) => {
+                    // A client which fails the handshake isn't a viewer
+                    if let Ok(viewer) = Viewer::handshake(client.stream, &request) {
+                        self.viewers.push(viewer);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+
+        !self.viewers.is_empty()
+    }
+
+    /// Run `battle` to the end, streaming it to the viewers if anyone is
+    /// watching, at the speed they choose.
+    pub fn stream(&mut self, battle: &mut Battle) -> Outcome {
+        if !self.is_watched() {
+            return battle.run();
+        }
+        battle.core_mut().enable_events();
+        self.broadcast(&start_message(battle.core()));
+
+        let mut pause_deadline = None;
+        loop {
+            self.is_watched();
+            let step = self.apply_controls();
+            if self.viewers.is_empty() {
+                return battle.run();
+            }
+            if self.paused {
+                let deadline =
+                    *pause_deadline.get_or_insert_with(|| Instant::now() + self.max_pause);
+                if Instant::now() >= deadline {
+                    self.paused = false;
+                }
+            }
+            if self.paused && !step {
+                thread::sleep(self.interval);
+                continue;
+            }
+
+            let mut written = BTreeSet::new();
+            let mut events = Vec::new();
+            let mut outcome = None;
+            for _ in 0..self.speed {
+                outcome = battle.step();
+                written.extend(battle.core().last_writes());
+                events.extend_from_slice(battle.core().last_events());
+                if outcome.is_some() {
+                    break;
+                }
+            }
+            let tick = Tick {
+                events,
+                ..Tick::capture(battle.core(), &written)
+            };
+            self.broadcast(&tick.to_json());
+
+            if let Some(outcome) = outcome {
+                let end = json!({
+                    "type": "end",
+                    "cycle": battle.core().cycles(),
+                    "outcome": outcome.to_string(),
+                });
+                self.broadcast(&end.to_string());
+                return outcome;
+            }
+            thread::sleep(self.interval);
+        }
+    }
+
+    /// Apply the commands sent by every viewer, forgetting any who
+    /// disconnected. Returns whether a viewer asked for a single step.
+    fn apply_controls(&mut self) -> bool {
+        let mut step = false;
+        let mut controls = Vec::new();
+        self.viewers.retain_mut(|viewer| match viewer.poll() {
+            Ok(Some(sent)) => {
+                controls.extend(sent);
+                true
+            }
+            Ok(None) | Err(_) => false,
+        });
+
+        for control in controls {
+            match control {
+                Control::Speed(speed) => self.speed = speed,
+                Control::Pause => self.paused = true,
+                Control::Resume => self.paused = false,
+                Control::Step => step = true,
+            }
+        }
+        step
+    }
+
+    /// Send `text` to every viewer, forgetting any who can't be reached.
+    fn broadcast(&mut self, text: &str) {
+        self.viewers.retain_mut(|viewer| viewer.send(text).is_ok());
+    }
+}
+
+/// The message starting the stream of the battle in `core`.
+fn start_message(core: &Core) -> String {
+    let default = Instruction::default();
+    let cells: Vec<serde_json::Value> = (0..core.size())
+        .map(|address| (address, core.get(address as i32)))
+        .filter(|(_, instruction)| **instruction != default)
+        .map(|(address, instruction)| json!([address, instruction.to_string()]))
+        .collect();
+
+    json!({
+        "type": "start",
+        "core_size": core.size(),
+        "warriors": core.warriors(),
+        "cells": cells,
+    })
+    .to_string()
+}
+
+/// The accept key answering the handshake key sent by a client.
+fn accept_key(key: &str) -> String {
+    base64(&Sha1::digest(
+        format!("{}{}", key, HANDSHAKE_GUID).as_bytes(),
+    ))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(char::from(
+                    ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize],
+                ));
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use corewars_sim::BattleConfig;
+
+    /// Connect to `viewers` like a browser would, returning the stream after
+    /// the handshake.
+    fn connect(viewers: &Viewers) -> TcpStream {
+        let mut stream = TcpStream::connect(viewers.local_addr().unwrap()).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\n\r\n",
+            ENDPOINT
+        )
+        .unwrap();
+        stream
+    }
+
+    fn read_response(stream: &mut TcpStream) -> String {
+        let mut response = Vec::new();
+        let mut byte = [0];
+        while !response.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+        String::from_utf8(response).unwrap()
+    }
+
+    /// Read a text frame sent by the server, which are never masked.
+    fn read_message(stream: &mut TcpStream) -> String {
+        let mut header = [0; 2];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        let len = match header[1] {
+            126 => {
+                let mut len = [0; 2];
+                stream.read_exact(&mut len).unwrap();
+                usize::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0; 8];
+                stream.read_exact(&mut len).unwrap();
+                u64::from_be_bytes(len) as usize
+            }
+            len => usize::from(len),
+        };
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    }
+
+    /// Send a masked text frame, which clients must always send.
+    fn send_message(stream: &mut TcpStream, text: &str) {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        stream.write_all(&frame).unwrap();
+    }
+
+    #[test]
+    fn parses_controls() {
+        assert_eq!("speed 10".parse(), Ok(Control::Speed(10)));
+        assert_eq!(" pause ".parse(), Ok(Control::Pause));
+        assert!("speed 0".parse::<Control>().is_err());
+        assert!("resume now".parse::<Control>().is_err());
+    }
+
+    #[test]
+    fn computes_accept_key() {
+        // The example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn streams_battle() {
+        let mut viewers = Viewers::bind("127.0.0.1:0").unwrap();
+        viewers.set_interval(Duration::from_millis(1));
+
+        let mut stream = connect(&viewers);
+        assert!(viewers.is_watched());
+        assert!(read_response(&mut stream).contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        send_message(&mut stream, "speed 2");
+        thread::sleep(Duration::from_millis(50));
+
+        let outcome = viewers.stream(&mut battle());
+        assert_eq!(outcome, Outcome::Tie(vec!["Imp".into()]));
+        assert_eq!(viewers.speed(), 2);
+
+        assert_eq!(
+            read_message(&mut stream),
+            r#"{"cells":[[0,"MOV.I   $0,     $1"]],"core_size":100,"type":"start","warriors":["Imp"]}"#
+        );
+        let tick: serde_json::Value = serde_json::from_str(&read_message(&mut stream)).unwrap();
+        let imp = "MOV.I   $0,     $1";
+        assert_eq!(
+            tick,
+            json!({
+                "type": "tick",
+                "version": 1,
+                "cycle": 2,
+                "changed": [[1, imp], [2, imp]],
+                "processes": {"Imp": [2]},
+                "events": [
+                    {"type": "instruction_executed", "cycle": 0, "warrior": "Imp", "address": 0, "instruction": imp},
+                    {"type": "cell_written", "cycle": 0, "warrior": "Imp", "address": 1, "instruction": imp},
+                    {"type": "instruction_executed", "cycle": 1, "warrior": "Imp", "address": 1, "instruction": imp},
+                    {"type": "cell_written", "cycle": 1, "warrior": "Imp", "address": 2, "instruction": imp},
+                ],
+            })
+        );
+        assert!(read_message(&mut stream).contains(r#""cycle":4"#));
+        assert_eq!(
+            read_message(&mut stream),
+            r#"{"cycle":4,"outcome":"tie between Imp","type":"end"}"#
+        );
+    }
+
+    fn battle() -> Battle {
+        let mut battle = Battle::new(BattleConfig {
+            core_size: 100,
+            max_cycles: 4,
+            min_distance: 10,
+            max_length: 10,
+            ..BattleConfig::default()
+        })
+        .unwrap();
+        let imp = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+        battle.load(&imp, 0).unwrap();
+        battle
+    }
+
+    #[test]
+    fn does_not_wait_for_handshakes() {
+        let mut viewers = Viewers::bind("127.0.0.1:0").unwrap();
+        let mut idle = TcpStream::connect(viewers.local_addr().unwrap()).unwrap();
+
+        let start = Instant::now();
+        assert!(!viewers.is_watched());
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT);
+
+        // The handshake may still arrive later, in parts
+        idle.write_all(b"GET /battles HTTP/1.1\r\nSec-WebSocket-Key: ")
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert!(!viewers.is_watched());
+        idle.write_all(b"dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert!(viewers.is_watched());
+        assert!(read_response(&mut idle).starts_with("HTTP/1.1 101"));
+
+        // Clients which never finish are dropped
+        let _silent = TcpStream::connect(viewers.local_addr().unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        viewers.is_watched();
+        assert_eq!(viewers.pending.len(), 1);
+        thread::sleep(HANDSHAKE_TIMEOUT);
+        viewers.is_watched();
+        assert!(viewers.pending.is_empty());
+    }
+
+    #[test]
+    fn limits_pauses() {
+        let mut viewers = Viewers::bind("127.0.0.1:0").unwrap();
+        viewers.set_interval(Duration::from_millis(1));
+        viewers.set_max_pause(Duration::from_millis(50));
+
+        let mut stream = connect(&viewers);
+        assert!(viewers.is_watched());
+        read_response(&mut stream);
+        send_message(&mut stream, "pause");
+        thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        let outcome = viewers.stream(&mut battle());
+        assert_eq!(outcome, Outcome::Tie(vec!["Imp".into()]));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn drops_floods() {
+        let mut viewers = Viewers::bind("127.0.0.1:0").unwrap();
+        let mut stream = connect(&viewers);
+        assert!(viewers.is_watched());
+        read_response(&mut stream);
+
+        // The start of a frame which never ends
+        let mut frame = vec![0x81, 0xff];
+        frame.extend_from_slice(&u64::MAX.to_be_bytes());
+        stream.write_all(&frame).unwrap();
+        stream.write_all(&vec![0; 2 * MAX_RECEIVED_LENGTH]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        for _ in 0..4 {
+            viewers.apply_controls();
+        }
+        assert!(viewers.viewers.is_empty());
+    }
+
+    #[test]
+    fn rejects_other_paths() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        assert!(Viewer::accept(stream).is_err());
+        assert!(read_response(&mut client).starts_with("HTTP/1.1 404"));
+    }
+}
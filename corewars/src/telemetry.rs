@@ -0,0 +1,221 @@
+//! Opt-in reporting of aggregate usage, for operators of public instances who
+//! want to know how much their instance is used, such as a
+//! [hill server](crate::hill::Server::set_telemetry).
+//!
+//! Telemetry is off unless a [`Sink`] is given to [`Telemetry::new`]. Only
+//! counts of events are ever reported, never warriors, names, or paths, and
+//! reports are sent at most once per interval no matter how many events are
+//! recorded.
+
+use std::fmt;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Something that happened which is counted in reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A battle finished
+    BattleRun,
+
+    /// A warrior failed to parse
+    ParseFailure,
+}
+
+/// The number of each event recorded during a reporting period.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// The length of the period covered, in whole seconds
+    pub period_seconds: u64,
+    pub battles_run: u64,
+    pub parse_failures: u64,
+}
+
+impl Report {
+    fn is_empty(&self) -> bool {
+        self.battles_run == 0 && self.parse_failures == 0
+    }
+}
+
+/// Where reports are sent, e.g. an endpoint configured by the operator.
+pub trait Sink: Send {
+    fn send(&mut self, report: &Report);
+}
+
+/// A sink writing each report as a line of JSON, e.g. to a file which is
+/// collected by the operator.
+#[derive(Debug)]
+pub struct JsonLines<W>(pub W);
+
+impl<W: Write + Send> Sink for JsonLines<W> {
+    fn send(&mut self, report: &Report) {
+        // Telemetry must never interfere with the program, so errors are dropped
+        if let Ok(line) = serde_json::to_string(report) {
+            let _ = writeln!(self.0, "{}", line);
+        }
+    }
+}
+
+/// Counts events and periodically reports them to a [`Sink`], if enabled.
+pub struct Telemetry {
+    sink: Option<Box<dyn Sink>>,
+    interval: Duration,
+    period_start: Instant,
+    pending: Report,
+}
+
+impl Telemetry {
+    /// Telemetry which records nothing. This is the default.
+    pub fn disabled() -> Self {
+        Self {
+            sink: None,
+            interval: Duration::default(),
+            period_start: Instant::now(),
+            pending: Report::default(),
+        }
+    }
+
+    /// Report to `sink` at most once per `interval`.
+    pub fn new<S: Sink + 'static>(sink: S, interval: Duration) -> Self {
+        Self {
+            sink: Some(Box::new(sink)),
+            interval,
+            period_start: Instant::now(),
+            pending: Report::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Count an event, and send a report if the interval has passed.
+    pub fn record(&mut self, event: Event) {
+        self.record_many(event, 1);
+    }
+
+    /// Count `count` of the same event at once, e.g. every round of a match.
+    pub fn record_many(&mut self, event: Event, count: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        match event {
+            Event::BattleRun => self.pending.battles_run += count,
+            Event::ParseFailure => self.pending.parse_failures += count,
+        }
+
+        if self.period_start.elapsed() >= self.interval {
+            self.flush();
+        }
+    }
+
+    /// Send a report of any events recorded since the last one, regardless
+    /// of the interval, e.g. before exiting.
+    pub fn flush(&mut self) {
+        let sink = match self.sink.as_mut() {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        if !self.pending.is_empty() {
+            self.pending.period_seconds = self.period_start.elapsed().as_secs();
+            sink.send(&self.pending);
+        }
+
+        self.pending = Report::default();
+        self.period_start = Instant::now();
+    }
+}
+
+impl fmt::Debug for Telemetry {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Telemetry")
+            .field("enabled", &self.is_enabled())
+            .field("interval", &self.interval)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<Report>>>);
+
+    impl Sink for Collect {
+        fn send(&mut self, report: &Report) {
+            self.0.lock().unwrap().push(report.clone());
+        }
+    }
+
+    #[test]
+    fn rate_limits_reports() {
+        let reports = Collect::default();
+        let mut telemetry = Telemetry::new(reports.clone(), Duration::from_secs(3600));
+
+        telemetry.record(Event::BattleRun);
+        telemetry.record(Event::BattleRun);
+        telemetry.record(Event::ParseFailure);
+        assert!(reports.0.lock().unwrap().is_empty());
+
+        drop(telemetry);
+        let sent = reports.0.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].battles_run, sent[0].parse_failures), (2, 1));
+    }
+
+    #[test]
+    fn reports_every_interval() {
+        let reports = Collect::default();
+        let mut telemetry = Telemetry::new(reports.clone(), Duration::from_secs(0));
+
+        telemetry.record(Event::BattleRun);
+        telemetry.record(Event::BattleRun);
+        telemetry.flush();
+        assert_eq!(reports.0.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let mut sink = JsonLines(Vec::new());
+        sink.send(&Report {
+            period_seconds: 60,
+            battles_run: 3,
+            parse_failures: 0,
+        });
+
+        assert_eq!(
+            String::from_utf8(sink.0).unwrap(),
+            "{\"period_seconds\":60,\"battles_run\":3,\"parse_failures\":0}\n"
+        );
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let mut telemetry = Telemetry::default();
+        telemetry.record(Event::BattleRun);
+        assert!(!telemetry.is_enabled());
+    }
+}
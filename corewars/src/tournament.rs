@@ -0,0 +1,1794 @@
+//! Tournaments between several warriors, where each match between two
+//! warriors lasts a number of rounds. The results are collected in a
+//! [`Crosstable`], which shows how each warrior fared against every other,
+//! since a warrior's total score can hide that it beats one opponent and
+//! loses to another.
+//!
+//! A round robin, where every pair of warriors battles, takes too long for
+//! large pools of warriors, so other [pairings](Pairing) play fewer matches
+//! and record which warriors met in a [`Bracket`]. When the time to run a
+//! tournament is fixed rather than the number of rounds, e.g. in a CI job,
+//! [`run_timed`] plays as many rounds as fit instead, and reports how
+//! uncertain the scores are. A warrior's score is also averaged over where it
+//! starts, so [`PositionBias`] breaks it down by separation from its
+//! opponents instead.
+//!
+//! The warriors of a tournament are collected as [`Entrants`], each parsed
+//! once and shared between the threads battling it, which callers can also
+//! fill with warriors they built themselves.
+
+use std::fmt;
+use std::iter::FromIterator;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use corewars_core::load_file::Metadata;
+use corewars_core::Warrior;
+use corewars_sim::{Battle, BattleConfig, ConfigError, Outcome};
+
+use crate::interrupt;
+use crate::messages::Message;
+
+/// The rounds played between two warriors, from the point of view of the
+/// first.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub wins: u32,
+    pub losses: u32,
+    pub ties: u32,
+}
+
+impl Record {
+    pub fn rounds(&self) -> u32 {
+        self.wins + self.losses + self.ties
+    }
+
+    /// The same record, from the point of view of the other warrior.
+    pub fn reversed(self) -> Self {
+        Self {
+            wins: self.losses,
+            losses: self.wins,
+            ties: self.ties,
+        }
+    }
+
+    /// The KOTH score: 3 points per win and 1 per tie, averaged over the
+    /// rounds and multiplied by 100.
+    pub fn score(&self) -> f64 {
+        f64::from(3 * self.wins + self.ties) * 100.0 / f64::from(self.rounds().max(1))
+    }
+
+    /// The margin of error of the [score](Record::score), as the half-width of
+    /// its 95% confidence interval. This is infinite with fewer than two
+    /// rounds.
+    pub fn margin(&self) -> f64 {
+        let rounds = f64::from(self.rounds());
+        if self.rounds() < 2 {
+            return f64::INFINITY;
+        }
+
+        let mean = self.score();
+        let squares = f64::from(self.wins) * 300.0 * 300.0 + f64::from(self.ties) * 100.0 * 100.0;
+        let variance = (squares - rounds * mean * mean).max(0.0) / (rounds - 1.0);
+        1.96 * (variance / rounds).sqrt()
+    }
+}
+
+impl std::ops::Add for Record {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            wins: self.wins + other.wins,
+            losses: self.losses + other.losses,
+            ties: self.ties + other.ties,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}/{}/{}", self.wins, self.losses, self.ties)
+    }
+}
+
+/// The warriors entering a tournament. Outcomes name the warriors, so each
+/// is given a name no other entrant has when it enters, and is then
+/// shared, without being copied, by every battle it takes part in.
+#[derive(Clone, Debug, Default)]
+pub struct Entrants {
+    warriors: Vec<Arc<Warrior>>,
+}
+
+impl Entrants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enter a warrior, returning it as entered. A warrior without a name is
+    /// named after its index, e.g. `Warrior3`, and one with the same name as
+    /// another entrant is numbered, e.g. `Imp (2)`.
+    pub fn push(&mut self, warrior: Warrior) -> Arc<Warrior> {
+        self.push_shared(Arc::new(warrior))
+    }
+
+    /// Like [`push`](Entrants::push), for a warrior shared with the caller,
+    /// which is only copied if it has to be renamed.
+    pub fn push_shared(&mut self, warrior: Arc<Warrior>) -> Arc<Warrior> {
+        let name = warrior.distinct_name(self.warriors.len(), |name| {
+            self.warriors
+                .iter()
+                .any(|w| w.metadata.name.as_deref() == Some(name))
+        });
+
+        let warrior = if warrior.metadata.name.as_ref() == Some(&name) {
+            warrior
+        } else {
+            Arc::new(warrior.renamed(name))
+        };
+        self.warriors.push(Arc::clone(&warrior));
+        warrior
+    }
+
+    /// The warriors in the order they entered.
+    pub fn warriors(&self) -> &[Arc<Warrior>] {
+        &self.warriors
+    }
+
+    pub fn len(&self) -> usize {
+        self.warriors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warriors.is_empty()
+    }
+
+    fn name(&self, index: usize) -> String {
+        self.warriors[index]
+            .metadata
+            .name
+            .clone()
+            .unwrap_or_default()
+    }
+}
+
+impl FromIterator<Warrior> for Entrants {
+    fn from_iter<I: IntoIterator<Item = Warrior>>(warriors: I) -> Self {
+        let mut entrants = Self::new();
+        for warrior in warriors {
+            entrants.push(warrior);
+        }
+        entrants
+    }
+}
+
+/// Battle `first` against `second` for `rounds` rounds. The second warrior is
+/// placed at a different distance from the first each round, spread evenly
+/// through the core, and the warriors take turns to execute first.
+pub fn play(
+    config: &BattleConfig,
+    first: &Warrior,
+    second: &Warrior,
+    rounds: u32,
+) -> Result<Record, ConfigError> {
+    config.validate(2)?;
+
+    let (first_name, second_name) = (&first.metadata.name, &second.metadata.name);
+    if first_name.is_some() && second_name.is_some() && first_name != second_name {
+        play_named(config, [first, second], rounds, 0)
+    } else {
+        let [first, second] = contestants(first, second);
+        play_named(config, [&first, &second], rounds, 0)
+    }
+}
+
+/// Like [`play`], between warriors with different names, with the sweep of
+/// distances between them shifted by `seed`.
+fn play_named(
+    config: &BattleConfig,
+    warriors: [&Warrior; 2],
+    rounds: u32,
+    seed: u64,
+) -> Result<Record, ConfigError> {
+    let span = u64::from(config.core_size - 2 * config.min_distance + 1);
+    let mut record = Record::default();
+
+    for round in 0..rounds {
+        let step = u64::from(round) * span / u64::from(rounds);
+        let distance = config.min_distance + ((step + seed % span) % span) as u32;
+        record = record
+            + play_round(config, warriors, round, distance, None)?
+                .expect("rounds without a deadline finish");
+    }
+
+    Ok(record)
+}
+
+/// Copies of two warriors renamed "first" and "second", since battles
+/// identify warriors by name, which the warriors may share.
+fn contestants(first: &Warrior, second: &Warrior) -> [Warrior; 2] {
+    let contestant = |warrior: &Warrior, name: &str| Warrior {
+        program: warrior.program.clone(),
+        metadata: Metadata {
+            name: Some(name.into()),
+            ..Metadata::default()
+        },
+    };
+    [contestant(first, "first"), contestant(second, "second")]
+}
+
+/// Play a single round between two warriors with different names, with the
+/// second `distance` after the first, and the first executing first in even
+/// rounds. Returns the record of the first, or `None` if the round didn't
+/// finish before the `deadline`.
+fn play_round(
+    config: &BattleConfig,
+    warriors: [&Warrior; 2],
+    round: u32,
+    distance: u32,
+    deadline: Option<Instant>,
+) -> Result<Option<Record>, ConfigError> {
+    let mut battle = Battle::new(config.clone())?;
+    let (leader, follower) = if round.is_multiple_of(2) {
+        (0, 1)
+    } else {
+        (1, 0)
+    };
+    battle.load(warriors[leader], 0)?;
+    battle.load(warriors[follower], distance)?;
+
+    let outcome = match deadline {
+        Some(deadline) => battle.run_until(deadline),
+        None => Some(battle.run()),
+    };
+
+    let mut record = Record::default();
+    match outcome {
+        Some(Outcome::Win(name)) if Some(&name) == warriors[0].metadata.name.as_ref() => {
+            record.wins += 1
+        }
+        Some(Outcome::Win(_)) => record.losses += 1,
+        Some(Outcome::Tie(_)) => record.ties += 1,
+        None => return Ok(None),
+    }
+    Ok(Some(record))
+}
+
+/// How to choose which warriors battle each other.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Pairing {
+    /// Every warrior battles every other, which takes a number of matches
+    /// quadratic in the number of warriors
+    #[default]
+    RoundRobin,
+
+    /// Each round, warriors battle an opponent they haven't met with a similar
+    /// score so far. Defaults to enough rounds to tell the winner apart, the
+    /// base 2 logarithm of the number of warriors
+    Swiss { rounds: Option<usize> },
+
+    /// Warriors are knocked out by their first loss
+    SingleElimination,
+
+    /// Warriors are knocked out by their second loss, after playing on in a
+    /// losers' bracket
+    DoubleElimination,
+}
+
+impl FromStr for Pairing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "swiss" => Ok(Self::Swiss { rounds: None }),
+            "single-elimination" => Ok(Self::SingleElimination),
+            "double-elimination" => Ok(Self::DoubleElimination),
+            _ => match s.strip_prefix("swiss=").map(str::parse) {
+                Some(Ok(rounds)) if rounds > 0 => Ok(Self::Swiss {
+                    rounds: Some(rounds),
+                }),
+                _ => Err(format!(
+                    "unknown pairing {:?}, expected \"round-robin\", \"swiss\", \"swiss=ROUNDS\", \
+                     \"single-elimination\" or \"double-elimination\"",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// The inverse of [`from_str`](Pairing::from_str).
+impl fmt::Display for Pairing {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RoundRobin => write!(formatter, "round-robin"),
+            Self::Swiss { rounds: None } => write!(formatter, "swiss"),
+            Self::Swiss {
+                rounds: Some(rounds),
+            } => write!(formatter, "swiss={}", rounds),
+            Self::SingleElimination => write!(formatter, "single-elimination"),
+            Self::DoubleElimination => write!(formatter, "double-elimination"),
+        }
+    }
+}
+
+/// Run a tournament between `entrants`, where each match lasts `rounds`
+/// rounds. In elimination brackets, the warrior with more wins in a match
+/// advances, or the one which entered first if they won as many rounds; the
+/// first entrants also get any byes.
+///
+/// The matches of a round robin are played in parallel, on a thread per CPU.
+/// Once Ctrl-C is [requested](crate::interrupt), a round robin stops starting
+/// new matches, and the results only include those already played. Brackets
+/// are always played to the end, since each match decides who plays next.
+pub fn run(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    rounds: u32,
+    pairing: Pairing,
+) -> Result<Results, ConfigError> {
+    run_seeded(config, entrants, rounds, pairing, 0)
+}
+
+/// Like [`run`], but shift the distances the warriors of each match are
+/// placed at by `seed`, to play the same tournament from other positions.
+/// The results only depend on the seed, so a tournament can be repeated
+/// exactly.
+pub fn run_seeded(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    rounds: u32,
+    pairing: Pairing,
+    seed: u64,
+) -> Result<Results, ConfigError> {
+    run_stoppable(
+        config,
+        entrants,
+        rounds,
+        pairing,
+        seed,
+        interrupt::requested,
+    )
+}
+
+/// Finish a round robin which was [interrupted](Results::interrupted), only
+/// playing the matches missing from its `previous` crosstable. Matches are
+/// played the same way whenever they are played, so the results are those of
+/// an uninterrupted [`run`]. Warriors are matched up with the crosstable by
+/// name, and previous records of a different number of `rounds` are played
+/// again.
+pub fn resume(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    rounds: u32,
+    previous: &Crosstable,
+) -> Result<Results, ConfigError> {
+    let names: Vec<String> = (0..entrants.len()).map(|i| entrants.name(i)).collect();
+    let index = |name: &String| previous.warriors.iter().position(|other| other == name);
+
+    let mut records = empty_records(entrants);
+    for (i, j) in pairs(entrants) {
+        let record = index(&names[i])
+            .zip(index(&names[j]))
+            .and_then(|(a, b)| previous.records[a][b])
+            .filter(|record| record.rounds() == rounds);
+        if let Some(record) = record {
+            records[i][j] = Some(record);
+            records[j][i] = Some(record.reversed());
+        }
+    }
+
+    run_from(
+        config,
+        entrants,
+        rounds,
+        Pairing::RoundRobin,
+        0,
+        interrupt::requested,
+        records,
+    )
+}
+
+/// Like [`run_seeded`], stopping a round robin once `stop` returns true.
+fn run_stoppable(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    rounds: u32,
+    pairing: Pairing,
+    seed: u64,
+    stop: fn() -> bool,
+) -> Result<Results, ConfigError> {
+    let records = empty_records(entrants);
+    run_from(config, entrants, rounds, pairing, seed, stop, records)
+}
+
+fn empty_records(entrants: &Entrants) -> Vec<Vec<Option<Record>>> {
+    vec![vec![None; entrants.len()]; entrants.len()]
+}
+
+/// Run a tournament, where a round robin skips the pairs already in `records`.
+fn run_from(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    rounds: u32,
+    pairing: Pairing,
+    seed: u64,
+    stop: fn() -> bool,
+    records: Vec<Vec<Option<Record>>>,
+) -> Result<Results, ConfigError> {
+    let warriors = entrants.warriors();
+    let mut games = Games {
+        config,
+        entrants,
+        rounds,
+        seed,
+        records,
+        stop,
+        interrupted: false,
+    };
+
+    let bracket = match pairing {
+        Pairing::RoundRobin => {
+            games.round_robin()?;
+            None
+        }
+        Pairing::Swiss { rounds } => {
+            // The smallest number of rounds such that 2^rounds >= warriors
+            let default = (usize::BITS - warriors.len().saturating_sub(1).leading_zeros()) as usize;
+            Some(games.swiss(rounds.unwrap_or(default).max(1))?)
+        }
+        Pairing::SingleElimination => Some(games.single_elimination()?),
+        Pairing::DoubleElimination => Some(games.double_elimination()?),
+    };
+
+    let names = (0..warriors.len()).map(|i| games.name(i)).collect();
+    let records = games.records;
+    Ok(Results {
+        crosstable: Crosstable::new(names, |i, j| records[i][j]),
+        bracket,
+        complete_rounds: None,
+        interrupted: games.interrupted,
+    })
+}
+
+/// Every pair of `entrants`' indices, in order.
+fn pairs(entrants: &Entrants) -> Vec<(usize, usize)> {
+    (0..entrants.len())
+        .flat_map(|i| (i + 1..entrants.len()).map(move |j| (i, j)))
+        .collect()
+}
+
+/// Play a round robin for as long as the `budget` allows, up to `max_rounds`
+/// rounds per pair. Each pair plays a round before any plays the next, so
+/// every pair plays the same number of rounds, or one more if time ran out
+/// partway through. The round cut off by the deadline is discarded.
+///
+/// Since the number of rounds isn't known in advance, the distance between
+/// the warriors doesn't sweep the core as in [`play`], but jumps around it so
+/// that any number of rounds covers it evenly.
+///
+/// Ctrl-C, once [requested](crate::interrupt), cuts the time short like the
+/// deadline does.
+pub fn run_timed(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    max_rounds: u32,
+    budget: Duration,
+) -> Result<Results, ConfigError> {
+    run_timed_stoppable(config, entrants, max_rounds, budget, interrupt::requested)
+}
+
+/// Like [`run_timed`], stopping early once `stop` returns true.
+fn run_timed_stoppable(
+    config: &BattleConfig,
+    entrants: &Entrants,
+    max_rounds: u32,
+    budget: Duration,
+    stop: fn() -> bool,
+) -> Result<Results, ConfigError> {
+    config.validate(2)?;
+    let deadline = Instant::now() + budget;
+    let warriors = entrants.warriors();
+    let pairs = pairs(entrants);
+
+    let span = u64::from(config.core_size - 2 * config.min_distance + 1);
+    let mut records = vec![vec![None; warriors.len()]; warriors.len()];
+    let mut complete_rounds = 0;
+    let mut interrupted = false;
+
+    'rounds: for round in 0..max_rounds {
+        // Without any pairs no round would reach the deadline, so it's checked
+        // here as well as during each battle
+        if pairs.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+
+        // The fractional parts of multiples of the golden ratio, as 32 bits
+        let fraction = u64::from(round.wrapping_mul(0x9e37_79b9));
+        let distance = config.min_distance + ((fraction * span) >> 32) as u32;
+
+        // Start from a different pair each round, so it isn't always the
+        // same pairs which play an extra round when time runs out
+        for k in 0..pairs.len() {
+            let index = (k + round as usize) % pairs.len();
+            let (i, j) = pairs[index];
+            if stop() {
+                interrupted = true;
+                break 'rounds;
+            }
+
+            let record = match play_round(
+                config,
+                [&warriors[i], &warriors[j]],
+                round,
+                distance,
+                Some(deadline),
+            )? {
+                Some(record) => record,
+                None => break 'rounds,
+            };
+            let total: Record = records[i][j].unwrap_or_default() + record;
+            records[i][j] = Some(total);
+            records[j][i] = Some(total.reversed());
+        }
+
+        complete_rounds += 1;
+    }
+
+    let names = (0..warriors.len()).map(|i| entrants.name(i)).collect();
+    Ok(Results {
+        crosstable: Crosstable::new(names, |i, j| records[i][j]),
+        bracket: None,
+        complete_rounds: Some(complete_rounds),
+        interrupted,
+    })
+}
+
+/// The results of a tournament.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Results {
+    /// The record of every pair of warriors which battled
+    pub crosstable: Crosstable,
+
+    /// The matches played in each round, unless every pair battled
+    pub bracket: Option<Bracket>,
+
+    /// For a [time-boxed](run_timed) tournament, the number of rounds every
+    /// pair finished, after which the scores are only estimates
+    pub complete_rounds: Option<u32>,
+
+    /// Whether Ctrl-C stopped the tournament before every match was played
+    pub interrupted: bool,
+}
+
+impl Results {
+    /// The results as JSON, with the crosstable as in
+    /// [`Crosstable::to_json`](Crosstable::to_json).
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            crosstable: Vec<Entry<'a>>,
+            bracket: &'a Option<Bracket>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            complete_rounds: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            margins: Option<Vec<f64>>,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            interrupted: bool,
+        }
+
+        let json = Json {
+            crosstable: self.crosstable.entries(),
+            bracket: &self.bracket,
+            complete_rounds: self.complete_rounds,
+            margins: self.complete_rounds.map(|_| self.margins()),
+            interrupted: self.interrupted,
+        };
+        serde_json::to_string_pretty(&json).expect("results are serializable")
+    }
+
+    /// The [margin of error](Record::margin) of each warrior's score, in the
+    /// order of the crosstable.
+    pub fn margins(&self) -> Vec<f64> {
+        (0..self.crosstable.warriors.len())
+            .map(|i| self.crosstable.total(i).margin())
+            .collect()
+    }
+}
+
+impl fmt::Display for Results {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(bracket) = &self.bracket {
+            write!(formatter, "{}\n\n", bracket)?;
+        }
+        write!(formatter, "{}", self.crosstable)?;
+
+        if let Some(rounds) = self.complete_rounds {
+            let message = Message::new("score-margins").arg("rounds", rounds);
+            write!(formatter, "\n\n{}", message)?;
+            for (i, (name, margin)) in self
+                .crosstable
+                .warriors
+                .iter()
+                .zip(self.margins())
+                .enumerate()
+            {
+                write!(
+                    formatter,
+                    "\n  {}: {:.1} ± {:.1}",
+                    name,
+                    self.crosstable.total(i).score(),
+                    margin
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The record of a warrior against opponents starting within a range of
+/// separations from it, in a [`PositionBias`](PositionBias).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SeparationBin {
+    /// The smallest separation in the range, i.e. how many addresses apart
+    /// the warriors started, in whichever direction is shorter
+    pub start: u32,
+
+    /// The largest separation in the range
+    pub end: u32,
+
+    pub record: Record,
+}
+
+/// How a warrior's results depend on how far away its opponents start, e.g.
+/// because it loses to opponents which start too close for it to finish
+/// booting. The total score of a tournament averages this away.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PositionBias {
+    pub warrior: String,
+
+    /// The warrior's record in each range of separations, in order
+    pub bins: Vec<SeparationBin>,
+
+    /// The correlation of the score of each round with the separation it was
+    /// played at, from -1 if the warrior does better the closer its opponents
+    /// start, to 1 if it does better the further. `None` if the score or
+    /// separation never changed.
+    pub correlation: Option<f64>,
+}
+
+impl PositionBias {
+    /// Play `rounds` rounds between `warrior` and each of `opponents`, with
+    /// separations swept evenly through the core, and group the results into
+    /// `bins` ranges of separations of the same size.
+    pub fn measure(
+        config: &BattleConfig,
+        warrior: &Warrior,
+        opponents: &[&Warrior],
+        rounds: u32,
+        bins: u32,
+    ) -> Result<Self, ConfigError> {
+        config.validate(2)?;
+
+        // Warriors take turns to start first, so whichever distance is
+        // shorter, forwards or backwards, is the separation
+        let span = config.core_size - 2 * config.min_distance + 1;
+        let widest = config.core_size / 2 - config.min_distance + 1;
+        let bins = bins.clamp(1, widest);
+        let mut records = vec![Record::default(); bins as usize];
+        let mut samples = Vec::new();
+
+        for opponent in opponents {
+            let contestants = contestants(warrior, opponent);
+            for round in 0..rounds {
+                let step = (u64::from(round) * u64::from(span) / u64::from(rounds)) as u32;
+                let distance = config.min_distance + step;
+                let record = play_round(
+                    config,
+                    [&contestants[0], &contestants[1]],
+                    round,
+                    distance,
+                    None,
+                )?
+                .expect("rounds without a deadline finish");
+
+                let separation = distance.min(config.core_size - distance);
+                let offset = u64::from(separation - config.min_distance);
+                let bin = (offset * u64::from(bins) / u64::from(widest)) as usize;
+                records[bin] = records[bin] + record;
+                samples.push((f64::from(separation), record.score()));
+            }
+        }
+
+        let bins = records
+            .into_iter()
+            .enumerate()
+            .map(|(i, record)| {
+                let (i, bins, widest) = (i as u64, u64::from(bins), u64::from(widest));
+                let start = (i * widest).div_ceil(bins) as u32;
+                let end = ((i + 1) * widest).div_ceil(bins) as u32 - 1;
+                SeparationBin {
+                    start: config.min_distance + start,
+                    end: config.min_distance + end,
+                    record,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            warrior: warrior.metadata.name.clone().unwrap_or_default(),
+            bins,
+            correlation: correlation(&samples),
+        })
+    }
+
+    /// The warrior's record at every separation.
+    pub fn total(&self) -> Record {
+        self.bins
+            .iter()
+            .fold(Record::default(), |total, bin| total + bin.record)
+    }
+
+    /// The range of separations where the warrior scores least, if any
+    /// rounds were played.
+    pub fn weakest(&self) -> Option<&SeparationBin> {
+        self.bins
+            .iter()
+            .filter(|bin| bin.record.rounds() > 0)
+            .min_by(|a, b| a.record.score().total_cmp(&b.record.score()))
+    }
+
+    /// Whether the warrior's score depends on its separation from its
+    /// opponents: whether its total score is outside the 95% confidence
+    /// interval of its score in any range of separations.
+    pub fn is_sensitive(&self) -> bool {
+        let total = self.total().score();
+        self.bins
+            .iter()
+            .any(|bin| (bin.record.score() - total).abs() > bin.record.margin())
+    }
+
+    /// The score in each range of separations as CSV, one range per row, to
+    /// plot as a data series.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("start,end,wins,losses,ties,score,margin\n");
+        for bin in &self.bins {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{:.1},{:.1}\n",
+                bin.start,
+                bin.end,
+                bin.record.wins,
+                bin.record.losses,
+                bin.record.ties,
+                bin.record.score(),
+                bin.record.margin()
+            ));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("position bias is serializable")
+    }
+}
+
+/// A table with a row per range of separations, with the record and score
+/// with its margin of error, followed by whether the score is sensitive to
+/// the separation.
+impl fmt::Display for PositionBias {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let separation_label = Message::new("separation").to_string();
+        let score_label = Message::new("crosstable-score").to_string();
+
+        let ranges: Vec<String> = self
+            .bins
+            .iter()
+            .map(|bin| format!("{}-{}", bin.start, bin.end))
+            .collect();
+        let range_width = ranges
+            .iter()
+            .map(String::len)
+            .chain(std::iter::once(separation_label.chars().count()))
+            .max()
+            .unwrap_or_default();
+        let record_width = self
+            .bins
+            .iter()
+            .map(|bin| bin.record.to_string().len())
+            .max()
+            .unwrap_or_default();
+
+        write!(
+            formatter,
+            "{:<range$}  {:>record$}  {}",
+            separation_label,
+            "",
+            score_label,
+            range = range_width,
+            record = record_width
+        )?;
+        for (range, bin) in ranges.iter().zip(&self.bins) {
+            write!(
+                formatter,
+                "\n{:<range$}  {:>record$}  {:>5.1} ± {:.1}",
+                range,
+                bin.record.to_string(),
+                bin.record.score(),
+                bin.record.margin(),
+                range = range_width,
+                record = record_width
+            )?;
+        }
+
+        if let Some(correlation) = self.correlation {
+            let message = Message::new("separation-correlation")
+                .arg("correlation", format!("{:.2}", correlation));
+            write!(formatter, "\n\n{}", message)?;
+        } else {
+            writeln!(formatter)?;
+        }
+
+        let message = match self.weakest() {
+            Some(weakest) if self.is_sensitive() => Message::new("position-sensitive")
+                .arg("warrior", &self.warrior)
+                .arg("score", format!("{:.1}", weakest.record.score()))
+                .arg("start", weakest.start)
+                .arg("end", weakest.end),
+            _ => Message::new("position-insensitive").arg("warrior", &self.warrior),
+        };
+        write!(formatter, "\n{}", message)
+    }
+}
+
+/// The Pearson correlation coefficient of pairs of values, or `None` if
+/// either value never changes.
+fn correlation(samples: &[(f64, f64)]) -> Option<f64> {
+    let count = samples.len() as f64;
+    let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / count;
+
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in samples {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x) * (x - mean_x);
+        variance_y += (y - mean_y) * (y - mean_y);
+    }
+
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return None;
+    }
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+/// A match between two warriors in a [`Bracket`](Bracket).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Match {
+    pub first: String,
+
+    /// The opponent of the first warrior, or `None` if it had a bye
+    pub second: Option<String>,
+
+    /// The record of the first warrior against the second
+    pub record: Option<Record>,
+
+    /// The warrior which won the match or advanced, or `None` for a tie
+    pub winner: Option<String>,
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let message = match (&self.second, &self.record, &self.winner) {
+            (Some(second), Some(record), winner) => {
+                let message = match winner {
+                    Some(winner) => Message::new("match-won").arg("winner", winner),
+                    None => Message::new("match-tied"),
+                };
+                message
+                    .arg("first", &self.first)
+                    .arg("second", second)
+                    .arg("record", record)
+            }
+            _ => Message::new("match-bye").arg("warrior", &self.first),
+        };
+        write!(formatter, "{}", message)
+    }
+}
+
+/// A named part of a bracket, e.g. the losers' bracket of a double
+/// elimination tournament.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Stage {
+    pub name: String,
+
+    /// The matches of each round of the stage
+    pub rounds: Vec<Vec<Match>>,
+}
+
+/// The matches played in a tournament which wasn't a round robin.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Bracket {
+    pub stages: Vec<Stage>,
+
+    /// The winner of an elimination tournament
+    pub winner: Option<String>,
+}
+
+impl fmt::Display for Bracket {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines = Vec::new();
+
+        for stage in &self.stages {
+            for (i, round) in stage.rounds.iter().enumerate() {
+                let heading = if stage.rounds.len() == 1 {
+                    Message::new("bracket-stage")
+                } else {
+                    Message::new("bracket-stage-round").arg("round", i + 1)
+                };
+                lines.push(heading.arg("stage", &stage.name).to_string());
+                lines.extend(round.iter().map(|game| format!("  {}", game)));
+            }
+        }
+
+        if let Some(winner) = &self.winner {
+            lines.push(
+                Message::new("bracket-winner")
+                    .arg("winner", winner)
+                    .to_string(),
+            );
+        }
+
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+/// The matches of a round of an elimination bracket, and the indices of the
+/// warriors which advanced and lost, in the order they were given.
+struct EliminationRound {
+    matches: Vec<Match>,
+    advancing: Vec<usize>,
+    losers: Vec<usize>,
+}
+
+/// The warriors of a tournament, and the record of every pair so far.
+struct Games<'a> {
+    config: &'a BattleConfig,
+    entrants: &'a Entrants,
+    rounds: u32,
+    seed: u64,
+    records: Vec<Vec<Option<Record>>>,
+
+    /// Whether to stop starting matches of a round robin
+    stop: fn() -> bool,
+    interrupted: bool,
+}
+
+impl Games<'_> {
+    fn name(&self, index: usize) -> String {
+        self.entrants.name(index)
+    }
+
+    fn total(&self, index: usize) -> Record {
+        self.records[index]
+            .iter()
+            .flatten()
+            .fold(Record::default(), |total, &record| total + record)
+    }
+
+    /// Battle two warriors, adding to their record if they met before.
+    fn play(&mut self, first: usize, second: usize) -> Result<Record, ConfigError> {
+        self.config.validate(2)?;
+        let warriors = self.entrants.warriors();
+        let record = play_named(
+            self.config,
+            [&warriors[first], &warriors[second]],
+            self.rounds,
+            self.seed,
+        )?;
+        self.add(first, second, record);
+        Ok(record)
+    }
+
+    /// Play every pair which hasn't played yet, sharing the pairs between a
+    /// thread per CPU.
+    fn round_robin(&mut self) -> Result<(), ConfigError> {
+        self.config.validate(2)?;
+
+        let pairs: Vec<(usize, usize)> = pairs(self.entrants)
+            .into_iter()
+            .filter(|&(i, j)| self.records[i][j].is_none())
+            .collect();
+        let threads = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(pairs.len())
+            .max(1);
+
+        let workers: Vec<_> = (0..threads)
+            .map(|thread| {
+                let (config, rounds, seed, stop) =
+                    (self.config.clone(), self.rounds, self.seed, self.stop);
+                let warriors = self.entrants.warriors.clone();
+                let pairs: Vec<(usize, usize)> = pairs
+                    .iter()
+                    .copied()
+                    .skip(thread)
+                    .step_by(threads)
+                    .collect();
+
+                thread::spawn(move || {
+                    pairs
+                        .into_iter()
+                        .take_while(|_| !stop())
+                        .map(|(i, j)| {
+                            let record =
+                                play_named(&config, [&warriors[i], &warriors[j]], rounds, seed)?;
+                            Ok((i, j, record))
+                        })
+                        .collect::<Result<Vec<_>, ConfigError>>()
+                })
+            })
+            .collect();
+
+        let mut count = 0;
+        for worker in workers {
+            let played = worker
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+            count += played.len();
+            for (first, second, record) in played {
+                self.add(first, second, record);
+            }
+        }
+        self.interrupted = count < pairs.len();
+        Ok(())
+    }
+
+    fn add(&mut self, first: usize, second: usize, record: Record) {
+        let total = self.records[first][second].unwrap_or_default() + record;
+        self.records[first][second] = Some(total);
+        self.records[second][first] = Some(total.reversed());
+    }
+
+    /// Play a match, in which a tie is won by `first`.
+    fn knockout(&mut self, first: usize, second: usize) -> Result<(Match, usize), ConfigError> {
+        let record = self.play(first, second)?;
+        let winner = if record.losses > record.wins {
+            second
+        } else {
+            first
+        };
+
+        let game = Match {
+            first: self.name(first),
+            second: Some(self.name(second)),
+            record: Some(record),
+            winner: Some(self.name(winner)),
+        };
+        Ok((game, winner))
+    }
+
+    fn bye(&self, index: usize) -> Match {
+        Match {
+            first: self.name(index),
+            second: None,
+            record: None,
+            winner: Some(self.name(index)),
+        }
+    }
+
+    fn swiss(&mut self, rounds: usize) -> Result<Bracket, ConfigError> {
+        let mut stage = Stage {
+            name: "Swiss".into(),
+            rounds: Vec::new(),
+        };
+        let mut had_bye = vec![false; self.entrants.len()];
+
+        for _ in 0..rounds {
+            let mut unpaired: Vec<usize> = (0..self.entrants.len()).collect();
+            unpaired.sort_by(|&a, &b| {
+                let score = |i: usize| self.total(i).score();
+                score(b).total_cmp(&score(a)).then(a.cmp(&b))
+            });
+
+            let mut bye = None;
+            if unpaired.len() % 2 == 1 {
+                // The lowest ranked warrior which hasn't had a bye sits out
+                let index = unpaired
+                    .iter()
+                    .rposition(|&i| !had_bye[i])
+                    .unwrap_or(unpaired.len() - 1);
+                let warrior = unpaired.remove(index);
+                had_bye[warrior] = true;
+                bye = Some(self.bye(warrior));
+            }
+
+            let mut matches = Vec::new();
+
+            while !unpaired.is_empty() {
+                let first = unpaired.remove(0);
+                let opponent = unpaired
+                    .iter()
+                    .position(|&j| self.records[first][j].is_none())
+                    .unwrap_or(0);
+                let second = unpaired.remove(opponent);
+
+                let record = self.play(first, second)?;
+                let winner = match record.wins.cmp(&record.losses) {
+                    std::cmp::Ordering::Greater => Some(self.name(first)),
+                    std::cmp::Ordering::Less => Some(self.name(second)),
+                    std::cmp::Ordering::Equal => None,
+                };
+                matches.push(Match {
+                    first: self.name(first),
+                    second: Some(self.name(second)),
+                    record: Some(record),
+                    winner,
+                });
+            }
+
+            matches.extend(bye);
+            stage.rounds.push(matches);
+        }
+
+        Ok(Bracket {
+            stages: vec![stage],
+            winner: None,
+        })
+    }
+
+    /// Play a round of an elimination bracket between `pool`, in which the
+    /// first warriors play the last.
+    fn elimination_round(&mut self, pool: &[usize]) -> Result<EliminationRound, ConfigError> {
+        let mut matches = Vec::new();
+        let mut advancing = Vec::new();
+        let mut losers = Vec::new();
+
+        let mut pool = pool.to_vec();
+        if pool.len() % 2 == 1 {
+            let warrior = pool.remove(0);
+            matches.push(self.bye(warrior));
+            advancing.push(warrior);
+        }
+
+        let half = pool.len() / 2;
+        for i in 0..half {
+            let (first, second) = (pool[i], pool[pool.len() - 1 - i]);
+            let (game, winner) = self.knockout(first, second)?;
+            matches.push(game);
+            advancing.push(winner);
+            losers.push(if winner == first { second } else { first });
+        }
+
+        advancing.sort_unstable();
+        losers.sort_unstable();
+        Ok(EliminationRound {
+            matches,
+            advancing,
+            losers,
+        })
+    }
+
+    fn single_elimination(&mut self) -> Result<Bracket, ConfigError> {
+        let mut stage = Stage {
+            name: "Knockout".into(),
+            rounds: Vec::new(),
+        };
+        let mut pool: Vec<usize> = (0..self.entrants.len()).collect();
+
+        while pool.len() > 1 {
+            let round = self.elimination_round(&pool)?;
+            stage.rounds.push(round.matches);
+            pool = round.advancing;
+        }
+
+        Ok(Bracket {
+            stages: vec![stage],
+            winner: pool.first().map(|&i| self.name(i)),
+        })
+    }
+
+    fn double_elimination(&mut self) -> Result<Bracket, ConfigError> {
+        let mut winners = Stage {
+            name: "Winners".into(),
+            rounds: Vec::new(),
+        };
+        let mut losers = Stage {
+            name: "Losers".into(),
+            rounds: Vec::new(),
+        };
+        let mut final_stage = Stage {
+            name: "Final".into(),
+            rounds: Vec::new(),
+        };
+
+        let mut unbeaten: Vec<usize> = (0..self.entrants.len()).collect();
+        let mut beaten_once: Vec<usize> = Vec::new();
+
+        while unbeaten.len() > 1 || beaten_once.len() > 1 {
+            if unbeaten.len() > 1 {
+                let round = self.elimination_round(&unbeaten)?;
+                winners.rounds.push(round.matches);
+                unbeaten = round.advancing;
+                beaten_once.extend(round.losers);
+            }
+
+            if beaten_once.len() > 1 {
+                let round = self.elimination_round(&beaten_once)?;
+                losers.rounds.push(round.matches);
+                beaten_once = round.advancing;
+            }
+        }
+
+        let champion = match (unbeaten.first(), beaten_once.first()) {
+            (Some(&unbeaten), Some(&challenger)) => {
+                let (game, winner) = self.knockout(unbeaten, challenger)?;
+                final_stage.rounds.push(vec![game]);
+
+                // Both have lost once if the challenger won, so they play again
+                if winner == challenger {
+                    let (game, winner) = self.knockout(unbeaten, challenger)?;
+                    final_stage.rounds.push(vec![game]);
+                    Some(winner)
+                } else {
+                    Some(winner)
+                }
+            }
+            (Some(&winner), None) | (None, Some(&winner)) => Some(winner),
+            (None, None) => None,
+        };
+
+        Ok(Bracket {
+            stages: vec![winners, losers, final_stage]
+                .into_iter()
+                .filter(|stage| !stage.rounds.is_empty())
+                .collect(),
+            winner: champion.map(|i| self.name(i)),
+        })
+    }
+}
+
+/// The results of each warrior against every other, ranked from the highest
+/// total score to the lowest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Crosstable {
+    pub warriors: Vec<String>,
+
+    /// The record of each warrior against each other, in the same order as
+    /// `warriors`. This is `None` for a warrior against itself, and any pair
+    /// which didn't battle.
+    pub records: Vec<Vec<Option<Record>>>,
+}
+
+impl Crosstable {
+    /// Collect the `record` of each pair of `warriors`, given their indices,
+    /// and rank them. Warriors with the same score keep their order.
+    pub fn new<F>(warriors: Vec<String>, record: F) -> Self
+    where
+        F: Fn(usize, usize) -> Option<Record>,
+    {
+        let count = warriors.len();
+        let total = |i: usize| {
+            (0..count)
+                .filter(|&j| j != i)
+                .filter_map(|j| record(i, j))
+                .fold(Record::default(), |total, record| total + record)
+        };
+
+        let mut order: Vec<usize> = (0..count).collect();
+        order.sort_by(|&a, &b| total(b).score().total_cmp(&total(a).score()));
+
+        Self {
+            records: order
+                .iter()
+                .map(|&i| {
+                    order
+                        .iter()
+                        .map(|&j| Some(j).filter(|&j| j != i).and_then(|j| record(i, j)))
+                        .collect()
+                })
+                .collect(),
+            warriors: order.iter().map(|&i| warriors[i].clone()).collect(),
+        }
+    }
+
+    /// The record of the warrior at `index` against all the others.
+    pub fn total(&self, index: usize) -> Record {
+        self.records[index]
+            .iter()
+            .flatten()
+            .fold(Record::default(), |total, &record| total + record)
+    }
+
+    /// The crosstable as JSON, with the total record and score of each
+    /// warrior.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.entries()).expect("crosstables are serializable")
+    }
+
+    /// Read the crosstable of [results](Results::to_json) written as JSON.
+    pub fn from_results_json(text: &str) -> serde_json::Result<Self> {
+        #[derive(Deserialize)]
+        struct Json {
+            crosstable: Vec<SavedEntry>,
+        }
+
+        #[derive(Deserialize)]
+        struct SavedEntry {
+            name: String,
+            records: Vec<Option<Record>>,
+        }
+
+        let json: Json = serde_json::from_str(text)?;
+        let warriors = json
+            .crosstable
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect();
+        let records = json
+            .crosstable
+            .into_iter()
+            .map(|entry| entry.records)
+            .collect::<Vec<_>>();
+
+        let size = records.len();
+        if records.iter().any(|row| row.len() != size) {
+            return Err(serde::de::Error::custom("crosstable is not square"));
+        }
+        Ok(Self { warriors, records })
+    }
+
+    fn entries(&self) -> Vec<Entry<'_>> {
+        self.warriors
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Entry {
+                name,
+                total: self.total(i),
+                score: self.total(i).score(),
+                records: &self.records[i],
+            })
+            .collect()
+    }
+}
+
+/// A row of a crosstable, as JSON.
+#[derive(Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    total: Record,
+    score: f64,
+    records: &'a [Option<Record>],
+}
+
+/// A table with a row per warrior, with its record against each column's
+/// warrior as wins/losses/ties, and its total score.
+impl fmt::Display for Crosstable {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let cells: Vec<Vec<String>> = self
+            .records
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|record| record.map_or_else(|| "-".to_string(), |r| r.to_string()))
+                    .collect()
+            })
+            .collect();
+
+        let name_label = Message::new("crosstable-name").to_string();
+        let score_label = Message::new("crosstable-score").to_string();
+
+        let rank_width = self.warriors.len().to_string().len().max(1);
+        let name_width = self
+            .warriors
+            .iter()
+            .map(|name| name.chars().count())
+            .chain(std::iter::once(name_label.chars().count()))
+            .max()
+            .unwrap_or_default();
+        let cell_width = cells
+            .iter()
+            .flatten()
+            .map(String::len)
+            .chain(std::iter::once(rank_width))
+            .max()
+            .unwrap_or_default();
+
+        write!(
+            formatter,
+            "{:>rank$}  {:<name$}",
+            "#",
+            name_label,
+            rank = rank_width,
+            name = name_width
+        )?;
+        for column in 1..=self.warriors.len() {
+            write!(formatter, "  {:>width$}", column, width = cell_width)?;
+        }
+        write!(formatter, "  {:>7}", score_label)?;
+
+        for (i, (name, row)) in self.warriors.iter().zip(&cells).enumerate() {
+            write!(
+                formatter,
+                "\n{:>rank$}  {:<name$}",
+                i + 1,
+                name,
+                rank = rank_width,
+                name = name_width
+            )?;
+            for cell in row {
+                write!(formatter, "  {:>width$}", cell, width = cell_width)?;
+            }
+            write!(formatter, "  {:>7.1}", self.total(i).score())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn warrior(name: &str, program: &str) -> Warrior {
+        corewars_parser::parse(&format!(";name {}\n{}", name, program))
+            .expect("Failed to parse warrior")
+    }
+
+    fn config() -> BattleConfig {
+        BattleConfig {
+            core_size: 800,
+            max_cycles: 1000,
+            max_length: 20,
+            min_distance: 20,
+            ..BattleConfig::default()
+        }
+    }
+
+    /// Warriors which lose to every warrior before them, since they die
+    /// after more cycles the later they come
+    fn ladder(count: usize) -> Entrants {
+        (0..count)
+            .map(|i| {
+                let program = format!("jmp 1\n{}dat 0, 0", "jmp 1\n".repeat(count - i));
+                warrior(&format!("W{}", i), &program)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranks_round_robin() {
+        let warriors = vec![warrior("Suicide", "dat 0, 0"), warrior("Imp", "mov 0, 1")];
+
+        let crosstable = run(
+            &config(),
+            &warriors.into_iter().collect(),
+            4,
+            Pairing::RoundRobin,
+        )
+        .unwrap()
+        .crosstable;
+        assert_eq!(crosstable.warriors, vec!["Imp", "Suicide"]);
+        assert_eq!(
+            crosstable.records,
+            vec![
+                vec![
+                    None,
+                    Some(Record {
+                        wins: 4,
+                        losses: 0,
+                        ties: 0
+                    })
+                ],
+                vec![
+                    Some(Record {
+                        wins: 0,
+                        losses: 4,
+                        ties: 0
+                    }),
+                    None
+                ],
+            ]
+        );
+
+        assert_eq!(
+            crosstable.to_string(),
+            [
+                "#  Name         1      2    Score",
+                "1  Imp          -  4/0/0    300.0",
+                "2  Suicide  0/4/0      -      0.0",
+            ]
+            .join("\n")
+        );
+        assert!(crosstable
+            .to_json()
+            .contains("\"total\": {\n      \"wins\": 0,\n      \"losses\": 4,"));
+    }
+
+    #[test]
+    fn enters_warriors() {
+        let imp = Arc::new(warrior("Imp", "mov 0, 1"));
+        let mut entrants = Entrants::new();
+
+        assert!(Arc::ptr_eq(&entrants.push_shared(Arc::clone(&imp)), &imp));
+        let renamed = entrants.push_shared(Arc::clone(&imp));
+        assert_eq!(renamed.metadata.name.as_deref(), Some("Imp (2)"));
+        assert_eq!(renamed.program, imp.program);
+        entrants.push(corewars_parser::parse("dat 0, 0").unwrap());
+        entrants.push(warrior("Imp", "mov 0, 1"));
+
+        let names: Vec<String> = (0..entrants.len()).map(|i| entrants.name(i)).collect();
+        assert_eq!(names, vec!["Imp", "Imp (2)", "Warrior2", "Imp (3)"]);
+
+        // Warriors with the same name still have their own records
+        let results = run(&config(), &entrants, 2, Pairing::RoundRobin).unwrap();
+        assert_eq!(results.crosstable.total(0).rounds(), 6);
+    }
+
+    #[test]
+    fn estimates_margin() {
+        let record = |wins, losses, ties| Record { wins, losses, ties };
+        assert_eq!(record(4, 0, 0).margin(), 0.0);
+        assert_eq!(record(1, 1, 0).margin(), 1.96 * 150.0);
+        assert_eq!(record(1, 0, 0).margin(), f64::INFINITY);
+    }
+
+    #[test]
+    fn runs_within_budget() {
+        let warriors = ladder(3);
+
+        let results = run_timed(&config(), &warriors, 5, Duration::from_secs(60)).unwrap();
+        assert_eq!(results.complete_rounds, Some(5));
+        assert_eq!(results.crosstable.warriors, vec!["W0", "W1", "W2"]);
+        assert_eq!(results.crosstable.records[0][2].unwrap().wins, 5);
+        for i in 0..3 {
+            assert_eq!(results.crosstable.total(i).rounds(), 10);
+        }
+        assert_eq!(results.margins()[0], 0.0);
+        assert!(results
+            .to_string()
+            .ends_with("Every pair finished 5 rounds; scores with 95% margins of error:\n  W0: 300.0 ± 0.0\n  W1: 150.0 ± 98.0\n  W2: 0.0 ± 0.0"));
+
+        let out_of_time = run_timed(&config(), &warriors, 5, Duration::ZERO).unwrap();
+        assert_eq!(out_of_time.complete_rounds, Some(0));
+        assert!(out_of_time
+            .crosstable
+            .records
+            .iter()
+            .flatten()
+            .all(Option::is_none));
+
+        let alone = run_timed(&config(), &ladder(1), u32::MAX, Duration::from_secs(60)).unwrap();
+        assert_eq!(alone.complete_rounds, Some(0));
+        assert_eq!(alone.crosstable.warriors, vec!["W0"]);
+    }
+
+    #[test]
+    fn stops_when_interrupted() {
+        static CHECKS: AtomicUsize = AtomicUsize::new(0);
+        fn after_one_match() -> bool {
+            CHECKS.fetch_add(1, Ordering::SeqCst) > 0
+        }
+        fn always() -> bool {
+            true
+        }
+
+        let warriors = ladder(4);
+        let results = run_stoppable(
+            &config(),
+            &warriors,
+            4,
+            Pairing::RoundRobin,
+            0,
+            after_one_match,
+        )
+        .unwrap();
+        assert!(results.interrupted);
+        let played = results
+            .crosstable
+            .records
+            .iter()
+            .flatten()
+            .flatten()
+            .count();
+        assert_eq!(played, 2);
+
+        let finished = run(&config(), &warriors, 4, Pairing::RoundRobin).unwrap();
+        assert!(!finished.interrupted);
+
+        let timed =
+            run_timed_stoppable(&config(), &warriors, 5, Duration::from_secs(60), always).unwrap();
+        assert!(timed.interrupted);
+        assert_eq!(timed.complete_rounds, Some(0));
+    }
+
+    #[test]
+    fn resumes_round_robins() {
+        let warriors = ladder(4);
+        let finished = run(&config(), &warriors, 4, Pairing::RoundRobin).unwrap();
+
+        // Keep the matches of W0 and W1, and one with a different number of rounds
+        let mut partial = finished.crosstable.clone();
+        let names = partial.warriors.clone();
+        for (i, first) in names.iter().enumerate() {
+            for (j, second) in names.iter().enumerate() {
+                match (first.as_str(), second.as_str()) {
+                    ("W0", _) | (_, "W0") | ("W1", _) | (_, "W1") => {}
+                    _ => partial.records[i][j] = None,
+                }
+                if [first.as_str(), second.as_str()] == ["W1", "W3"] {
+                    partial.records[i][j] = Some(Record {
+                        wins: 9,
+                        losses: 0,
+                        ties: 0,
+                    });
+                }
+            }
+        }
+        let partial = Results {
+            crosstable: partial,
+            bracket: None,
+            complete_rounds: None,
+            interrupted: true,
+        };
+        let read = Crosstable::from_results_json(&partial.to_json()).unwrap();
+        assert_eq!(read, partial.crosstable);
+
+        let resumed = resume(&config(), &warriors, 4, &read).unwrap();
+        assert_eq!(resumed, finished);
+
+        assert!(Crosstable::from_results_json(
+            r#"{"crosstable": [{"name": "W0", "records": []}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn measures_position_bias() {
+        // Bombs forward one address at a time, so it only reaches opponents
+        // which start close enough
+        let bomber = warrior(
+            "Bomber",
+            "loop mov bomb, @ptr\nadd #1, ptr\njmp loop\nptr dat 0, 2\nbomb dat 0, 0",
+        );
+        let sitter = warrior("Sitter", "jmp 0");
+        let short = BattleConfig {
+            max_cycles: 500,
+            ..config()
+        };
+
+        let bias = PositionBias::measure(&short, &bomber, &[&sitter], 40, 4).unwrap();
+        assert_eq!(bias.warrior, "Bomber");
+        assert_eq!(
+            bias.bins
+                .iter()
+                .map(|bin| (bin.start, bin.end))
+                .collect::<Vec<_>>(),
+            vec![(20, 115), (116, 210), (211, 305), (306, 400)]
+        );
+        assert_eq!(bias.total().rounds(), 40);
+        assert!(bias.bins[0].record.wins > bias.bins[1].record.wins);
+        assert_eq!(bias.bins[3].record.wins, 0);
+        assert!(bias.correlation.unwrap() < 0.0);
+        assert!(bias.is_sensitive());
+        assert_eq!(bias.weakest().map(|bin| bin.start), Some(211));
+        assert!(bias
+            .to_csv()
+            .starts_with("start,end,wins,losses,ties,score,margin\n20,115,"));
+
+        let bias = PositionBias::measure(&short, &sitter, &[&bomber], 40, 4).unwrap();
+        assert!(bias.is_sensitive());
+        assert!(bias.correlation.unwrap() > 0.0);
+
+        let bias =
+            PositionBias::measure(&config(), &warrior("Imp", "mov 0, 1"), &[], 40, 4).unwrap();
+        assert_eq!(bias.correlation, None);
+        assert!(!bias.is_sensitive());
+        assert!(bias
+            .to_string()
+            .ends_with("Imp scores about the same at every separation"));
+    }
+
+    #[test]
+    fn parses_pairing() {
+        assert_eq!("round-robin".parse(), Ok(Pairing::RoundRobin));
+        assert_eq!("swiss".parse(), Ok(Pairing::Swiss { rounds: None }));
+        assert_eq!("swiss=3".parse(), Ok(Pairing::Swiss { rounds: Some(3) }));
+        assert_eq!("double-elimination".parse(), Ok(Pairing::DoubleElimination));
+        assert!("swiss=0".parse::<Pairing>().is_err());
+        assert!("knockout".parse::<Pairing>().is_err());
+    }
+
+    #[test]
+    fn swiss_pairs_by_score() {
+        let results = run(&config(), &ladder(5), 2, Pairing::Swiss { rounds: None }).unwrap();
+        let bracket = results.bracket.unwrap();
+        let round = |i: usize| -> Vec<String> {
+            bracket.stages[0].rounds[i]
+                .iter()
+                .map(Match::to_string)
+                .collect()
+        };
+
+        assert_eq!(bracket.stages[0].rounds.len(), 3);
+        assert_eq!(
+            round(0),
+            vec![
+                "W0 vs W1: 2/0/0, W0 wins",
+                "W2 vs W3: 2/0/0, W2 wins",
+                "W4 has a bye"
+            ]
+        );
+        // Nobody plays the same opponent twice, or has two byes
+        assert_eq!(
+            round(1),
+            vec![
+                "W0 vs W2: 2/0/0, W0 wins",
+                "W1 vs W4: 2/0/0, W1 wins",
+                "W3 has a bye"
+            ]
+        );
+        assert_eq!(results.crosstable.warriors[0], "W0");
+    }
+
+    #[test]
+    fn single_elimination() {
+        let results = run(&config(), &ladder(5), 2, Pairing::SingleElimination).unwrap();
+        let bracket = results.bracket.unwrap();
+
+        assert_eq!(
+            bracket.to_string(),
+            [
+                "Knockout round 1:",
+                "  W0 has a bye",
+                "  W1 vs W4: 2/0/0, W1 wins",
+                "  W2 vs W3: 2/0/0, W2 wins",
+                "Knockout round 2:",
+                "  W0 has a bye",
+                "  W1 vs W2: 2/0/0, W1 wins",
+                "Knockout round 3:",
+                "  W0 vs W1: 2/0/0, W0 wins",
+                "Winner: W0",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn double_elimination() {
+        let warriors = ladder(4);
+        let results = run(&config(), &warriors, 2, Pairing::DoubleElimination).unwrap();
+        let bracket = results.bracket.as_ref().unwrap();
+
+        let names: Vec<&str> = bracket
+            .stages
+            .iter()
+            .map(|stage| stage.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Winners", "Losers", "Final"]);
+        assert_eq!(bracket.winner.as_deref(), Some("W0"));
+
+        // Every warrior but the winner lost twice, or never got the chance
+        let losses = |name: &str| {
+            bracket
+                .stages
+                .iter()
+                .flat_map(|stage| stage.rounds.iter().flatten())
+                .filter(|game| game.second.is_some() && game.winner.as_deref() != Some(name))
+                .filter(|game| game.first == name || game.second.as_deref() == Some(name))
+                .count()
+        };
+        assert_eq!(losses("W0"), 0);
+        assert_eq!(losses("W3"), 2);
+        assert!(results.to_json().contains("\"name\": \"Losers\""));
+    }
+}
@@ -2,6 +2,7 @@ use std::fs;
 use std::process::Command;
 
 use assert_cmd::prelude::*;
+use assert_fs::prelude::*;
 use lazy_static::lazy_static;
 use normalize_line_endings::normalized;
 use predicates::prelude::*;
@@ -81,3 +82,1272 @@ fn dump_file() {
 
     assert_eq!(file_contents, &**EXPECTED_OUT);
 }
+
+#[test]
+fn explain() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("explain")
+        .arg("mov.ab")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("MOV.AB: copy A to B\n"));
+}
+
+#[test]
+fn explain_code() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("explain")
+        .arg("e0007")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "E0007 (error): FOR loop repeats too many times\n",
+        ))
+        .stdout(predicate::str::contains("    for 10000\n"));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("explain")
+        .arg("E9999")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "no error or warning has the code E9999",
+        ));
+}
+
+#[test]
+fn bench() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("bench")
+        .arg("--core-size")
+        .arg("10")
+        .arg("--rounds")
+        .arg("1")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("ADD.A "))
+        .stdout(predicate::str::contains("\nMOV.I "));
+}
+
+#[test]
+fn bench_backends() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("bench")
+        .arg("--core-size")
+        .arg("800")
+        .arg("--rounds")
+        .arg("1")
+        .arg("--backends")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("dense "))
+        .stdout(predicate::str::contains("\ncow "));
+}
+
+#[test]
+fn bench_resume() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("bench")
+        .arg("--core-size")
+        .arg("10")
+        .arg("--rounds")
+        .arg("1")
+        .arg("--resume")
+        .arg("bench:118")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("SUB.I "))
+        .stdout(predicate::str::is_match("^[^\\n]*\\n$").unwrap());
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("bench")
+        .arg("--resume")
+        .arg("tournament:3")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot resume bench"));
+}
+
+#[test]
+fn timings() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--timings")
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("10")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("\nexpand "))
+        .stderr(predicate::str::contains("\nsimulate "))
+        .stderr(predicate::str::contains("\ntotal "));
+}
+
+#[test]
+fn battle_positions() {
+    let positions = assert_fs::NamedTempFile::new("positions.txt").unwrap();
+    positions
+        .write_str("; dwarf, then rave\n0 4000\n0 4001\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .arg("--positions")
+        .arg(positions.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("Round 1: "))
+        .stdout(predicate::str::contains("\nRound 2: "));
+
+    positions.write_str("0 50\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .arg("--positions")
+        .arg(positions.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "closer than the minimum distance 100",
+        ));
+
+    positions.write_str("; no rounds\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .arg("--positions")
+        .arg(positions.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("the schedule has no rounds"))
+        .stderr(predicate::str::contains("panicked").not());
+}
+
+#[test]
+fn preprocess() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/basic.redcode")
+        .arg("preprocess")
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with("END\n"));
+}
+
+#[test]
+fn pmars_options() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("pmars")
+        .arg("-s")
+        .arg("80")
+        .arg("-c3")
+        .arg("../testdata/input/simple/basic.redcode")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("after specified max of 3 cycles"));
+}
+
+#[test]
+fn index_csv() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("index")
+        .arg("../testdata/input/simple")
+        .arg("--format")
+        .arg("csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "path,name,author,date,assertion,length,hash\nbasic.redcode,",
+        ));
+}
+
+#[test]
+fn search_directory() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("search")
+        .arg("../testdata/input/simple")
+        .arg("--author")
+        .arg("dewdney")
+        .assert()
+        .success()
+        .stdout("../testdata/input/simple/dwarf.redcode\n");
+}
+
+#[test]
+fn lint_steps() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("lint")
+        .arg("--min-opponent-length")
+        .arg("2")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning[W0007]: instruction 1 uses step 4, which is mod-4",
+        ));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("explain")
+        .arg("W0007")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "W0007 (warning): step only reaches part of the core\n",
+        ));
+}
+
+#[test]
+fn run_coverage() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("10")
+        .arg("--coverage")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "3 of 8512 instruction combinations executed",
+        ));
+}
+
+#[test]
+fn run_history() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("10")
+        .arg("--history")
+        .arg("2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "cycle      9: Dwarf 000001 ADD.AB  #4,     $7999\ncycle      8: Dwarf 000003 JMP.A ",
+        ));
+}
+
+#[test]
+fn stats() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("stats")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("opcode MOV 1\n"))
+        .stdout(predicate::str::contains("address mode @ "));
+}
+
+#[test]
+fn grammar() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("grammar")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Field = { AddressMode? ~ Expression }",
+        ));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("grammar")
+        .arg("--ebnf")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Field ::= AddressMode? Expression",
+        ));
+}
+
+#[test]
+fn modifier_policy() {
+    for (policy, expected) in [("94", "CMP.B"), ("88", "CMP.I")] {
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .arg("--modifiers")
+            .arg(policy)
+            .arg("-")
+            .arg("dump")
+            .with_stdin()
+            .buffer("cmp 1, #2\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(expected));
+    }
+}
+
+#[test]
+fn field_range() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-")
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("1")
+        .with_stdin()
+        .buffer(";name Big\njmp 9000\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Big: the A-field value 9000 of instruction 0 is outside the core, wrapped to 1000",
+        ));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--strict-fields")
+        .arg("-")
+        .arg("run")
+        .with_stdin()
+        .buffer(";name Big\njmp 9000\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "the A-field value 9000 of instruction 0 is outside the core of 8000 instructions",
+        ));
+}
+
+#[test]
+fn run_dump() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("10")
+        .arg("--dump")
+        .arg("executed")
+        .arg("--dump")
+        .arg("owner=Dwarf")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\n000001 ADD.AB "))
+        .stdout(predicate::str::contains("\n000004 DAT").not());
+}
+
+#[test]
+fn debug_relative_addresses() {
+    let imp = assert_fs::NamedTempFile::new("imp.red").unwrap();
+    imp.write_str(";name Imp\nmov 0, 1\n").unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("debug")
+        .arg(imp.path())
+        .with_stdin()
+        .buffer("warriors\nview w2\nprint w2 1\nprint w9\nquit\nstep\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\nw2 "))
+        .stdout(predicate::str::contains("\nw2+0     MOV.I"))
+        .stdout(predicate::str::contains("\n     0 ").not())
+        .stderr(predicate::str::contains("no warrior w9"));
+}
+
+#[test]
+fn debug_session_labels() {
+    let session = assert_fs::NamedTempFile::new("session.txt").unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("debug")
+        .arg("--session")
+        .arg(session.path())
+        .with_stdin()
+        .buffer("label 3 target\n")
+        .assert()
+        .success();
+
+    session.assert("3 target\n");
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("debug")
+        .arg("--session")
+        .arg(session.path())
+        .with_stdin()
+        .buffer("print target\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("00003 "))
+        .stdout(predicate::str::contains("; target"));
+}
+
+#[test]
+fn debug_script() {
+    let script = assert_fs::NamedTempFile::new("cmds.txt").unwrap();
+    script
+        .write_str("# check the state after the first loop\nstep 3\nprint 3\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("debug")
+        .arg("--script")
+        .arg(script.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("     0 "))
+        .stdout(predicate::str::contains("\n     2 "))
+        .stdout(predicate::str::contains("\n00003    JMP.A"));
+
+    script.write_str("step\nprint w5\nstep\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("debug")
+        .arg("--script")
+        .arg(script.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\n     1 ").not())
+        .stderr(predicate::str::contains("cmds.txt:2: no warrior w5"));
+}
+
+#[test]
+fn dump_line_endings() {
+    let cmd = Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/basic.redcode")
+        .arg("dump")
+        .arg("--line-ending")
+        .arg("crlf")
+        .assert()
+        .success();
+
+    let out_text = String::from_utf8(cmd.get_output().stdout.to_owned()).unwrap();
+    assert_eq!(out_text, EXPECTED_OUT.replace('\n', "\r\n"));
+
+    let out_file = assert_fs::NamedTempFile::new("out.redcode").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/basic.redcode")
+        .arg("dump")
+        .arg("--no-trailing-newline")
+        .arg("--output-file")
+        .arg(out_file.path())
+        .assert()
+        .success();
+
+    out_file.assert(EXPECTED_OUT.trim_end());
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/basic.redcode")
+        .arg("dump")
+        .arg("--line-ending")
+        .arg("cr")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn dump_comments() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("dump")
+        .arg("--comments")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ADD.AB  #4,     $-1  ; Increments pointer by step.\n",
+        ));
+}
+
+#[test]
+fn dump_normalized() {
+    let warrior = assert_fs::NamedTempFile::new("wrap.red").unwrap();
+    warrior
+        .write_str(";name Wrap\norg 9001\nmov 7999, -4001\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("dump")
+        .arg("--core-size")
+        .arg("8000")
+        .assert()
+        .success()
+        .stdout(";name Wrap\nORG     1001\nMOV.I   $-1,    $3999\n");
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("dump")
+        .arg("--no-metadata")
+        .arg("--no-origin")
+        .assert()
+        .success()
+        .stdout("MOV.I   $7999,  $-4001\n");
+}
+
+#[test]
+fn check() {
+    let warrior = assert_fs::NamedTempFile::new("imp.red").unwrap();
+    warrior.write_str("mov 0, 1\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("check")
+        .assert()
+        .success()
+        .stdout("");
+
+    // A label after the last instruction has nothing to point to
+    warrior.write_str("mov 0, 1\nlonely\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("check")
+        .arg("--deny-warnings")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 warning with --deny-warnings"));
+
+    warrior.write_str("mov 0, nowhere\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no such label \"nowhere\""));
+}
+
+#[test]
+fn run_tagged() {
+    let warrior = assert_fs::NamedTempFile::new("pair.red").unwrap();
+    warrior
+        .write_str(";name Pair\nspl imp\ndat #0, #0\nimp mov 0, 1\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("10")
+        .arg("--tag")
+        .arg("0-1=stone")
+        .arg("--tag")
+        .arg("2=imp")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Pair [imp]: 1 processes\nPair [stone]: died\n",
+        ));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(warrior.path())
+        .arg("run")
+        .arg("--tag")
+        .arg("3-1=imp")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "tag \"imp\" ends before it starts",
+        ));
+}
+
+#[test]
+fn dump_listing() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("dump")
+        .arg("--listing")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(" line  offset  instruction"))
+        .stdout(predicate::str::contains("  00000   DAT.F "));
+}
+
+#[test]
+fn dump_formats() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("dump")
+        .arg("--format")
+        .arg("json")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{"))
+        .stdout(predicate::str::contains(r#""name":"Dwarf""#));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("dump")
+        .arg("--format")
+        .arg("xml")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown output format \"xml\""));
+}
+
+#[test]
+fn battle_numbers_same_names() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .assert()
+        .success()
+        .stdout("Round 1: tie between Dwarf, Dwarf (2)\n");
+}
+
+#[test]
+fn battle_clears() {
+    let stone = assert_fs::NamedTempFile::new("stone.red").unwrap();
+    stone
+        .write_str(
+            ";name Clear\norg loop\nptr dat 0, 4\nloop mov bomb, >ptr\njmp loop\nbomb dat 0, 0\n",
+        )
+        .unwrap();
+    let sitter = assert_fs::NamedTempFile::new("sitter.red").unwrap();
+    sitter.write_str(";name Sitter\njmp 0\n").unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(stone.path())
+        .arg("battle")
+        .arg(sitter.path())
+        .args(["--core-size", "800", "--max-cycles", "8000", "--clears"])
+        .assert()
+        .success()
+        .stdout("Round 1: Clear wins\n\nRound 1: core cleared at cycle 792 by Clear\n");
+}
+
+#[test]
+fn battle_faults() {
+    let imp = assert_fs::NamedTempFile::new("imp.red").unwrap();
+    imp.write_str(";name Imp\nmov 0, 1\n").unwrap();
+
+    let battle = |fault: &str| {
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .arg(imp.path())
+            .arg("battle")
+            .arg("../testdata/input/simple/dwarf.redcode")
+            .args(["--max-cycles", "2000", "--fault", fault])
+            .assert()
+    };
+
+    // The imp has a single process, so it dies straight away
+    battle("kill=Imp@0")
+        .success()
+        .stdout(predicate::str::contains("Round 1: Dwarf wins"));
+    battle("kill=Imp")
+        .failure()
+        .stderr(predicate::str::contains("expected FAULT@CYCLE"));
+}
+
+#[test]
+fn battle_heatmap() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .args(["--max-cycles", "2000", "--core-size", "800"])
+        .args(["--heatmap", "writes", "--heatmap-width", "80"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\n\n000000 "))
+        .stdout(predicate::str::contains("\n000720 "))
+        .stdout(predicate::str::contains("\n000800 ").not());
+}
+
+#[test]
+fn battle_nmars_results() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .arg("--max-cycles")
+        .arg("2000")
+        .arg("--format")
+        .arg("nmars")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(r"^;results\nDwarf W \d L \d T \d\n\S.* W \d L \d T \d\n$")
+                .unwrap(),
+        );
+}
+
+#[test]
+fn battle_report() {
+    let report = assert_fs::NamedTempFile::new("report.html").unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .arg("--max-cycles")
+        .arg("2000")
+        .arg("--report")
+        .arg(report.path())
+        .assert()
+        .success();
+
+    report.assert(predicate::str::starts_with("<!DOCTYPE html>"));
+    report.assert(predicate::str::contains("<td>Dwarf</td>"));
+    report.assert(predicate::str::contains("<canvas id=\"core\">"));
+}
+
+#[test]
+fn corewar_io() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let warrior = temp.child("dwarf.json");
+    let replay = temp.child("battle.json");
+
+    let output = Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("dump")
+        .arg("--format")
+        .arg("corewar-io")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    warrior.write_binary(&output.stdout).unwrap();
+    warrior.assert(predicate::str::contains("\"metaData\""));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg(warrior.path())
+        .arg("battle")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .arg("--max-cycles")
+        .arg("1000")
+        .arg("--corewar-io-replay")
+        .arg(replay.path())
+        .assert()
+        .success();
+
+    replay.assert(predicate::str::contains("\"CORE_ACCESS\""));
+    replay.assert(predicate::str::contains("\"ROUND_END\""));
+}
+
+#[test]
+fn dump_linked() {
+    let main = assert_fs::NamedTempFile::new("main.red").unwrap();
+    main.write_str("IMPORT bomb\nadd #4, bomb\nmov bomb, @bomb\njmp -2\n")
+        .unwrap();
+    let bomber = assert_fs::NamedTempFile::new("bomber.red").unwrap();
+    bomber.write_str("EXPORT bomb\nbomb dat #0, #0\n").unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(main.path())
+        .arg("--link")
+        .arg(bomber.path())
+        .arg("dump")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ADD.AB  #4,     $3"));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(main.path())
+        .arg("dump")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("bomb"));
+}
+
+#[test]
+fn dump_included() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let main = dir.child("main.red");
+    main.write_str(";include \"lib/step.red\"\nadd #step, 3\nmov 2, @2\njmp -2\ndat #0, #0\n")
+        .unwrap();
+    dir.child("lib/step.red").write_str("step equ 4\n").unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(main.path())
+        .arg("dump")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ADD.AB  #4,     $3"));
+
+    // Errors point at the included file
+    dir.child("lib/step.red")
+        .write_str("step equ 4\njmp missing\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(main.path())
+        .arg("dump")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("step.red:2:"));
+}
+
+#[test]
+fn dump_env() {
+    let warrior = assert_fs::NamedTempFile::new("dwarf.red").unwrap();
+    warrior
+        .write_str("add #${STEP}, 3\nmov 2, @2\njmp -2\ndat #0, #0\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .env("STEP", "7")
+        .arg(warrior.path())
+        .arg("--env")
+        .arg("dump")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ADD.AB  #7,     $3"));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .env_remove("STEP")
+        .arg(warrior.path())
+        .arg("--env")
+        .arg("dump")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unresolved variables: STEP"));
+}
+
+#[test]
+fn dump_load_file() {
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/expected_output/simple/dwarf.redcode")
+        .arg("--load-file")
+        .arg("dump")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ADD.AB  #4,     $-1"));
+
+    // Labels aren't allowed in load files
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("--load-file")
+        .arg("dump")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn hill_serve() {
+    let submissions = assert_fs::TempDir::new().unwrap();
+    submissions
+        .child("suicide.red")
+        .write_str(";name Suicide\ndat 0, 0")
+        .unwrap();
+    submissions.child("broken.red").write_str("mov 0,").unwrap();
+
+    let serve = || {
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .args(["hill", "serve", "--once", "--size", "1", "--rounds", "2"])
+            .args(["--core-size", "800", "--max-cycles", "1000"])
+            .arg("--watch")
+            .arg(submissions.path())
+            .assert()
+            .success()
+    };
+    let count = |directory: &str| {
+        fs::read_dir(submissions.child(directory).path())
+            .unwrap()
+            .count()
+    };
+    let leader = || {
+        let scores = fs::read_to_string(submissions.child("scores.txt").path()).unwrap();
+        assert_eq!(scores.lines().count(), 2);
+        scores.lines().nth(1).unwrap().to_string()
+    };
+
+    serve()
+        .stdout(predicate::str::contains(
+            "Suicide entered the hill at rank 1\n",
+        ))
+        .stdout(predicate::str::contains("broken.red was rejected: "));
+    assert!(leader().contains(" Suicide "));
+    assert_eq!((count("hill"), count("archive")), (1, 1));
+    submissions
+        .child("crosstable.txt")
+        .assert("#  Name     1    Score\n1  Suicide  -      0.0\n");
+
+    // Restarting keeps the hill, and a new submission takes it over
+    submissions
+        .child("imp.red")
+        .write_str(";name Imp\nmov 0, 1")
+        .unwrap();
+    serve().stdout(predicate::str::similar(
+        "Imp entered the hill at rank 1\nSuicide was pushed off the hill\n",
+    ));
+    assert!(leader().contains(" Imp "));
+    assert_eq!((count("hill"), count("archive")), (1, 2));
+}
+
+#[test]
+fn warrior_package() {
+    let directory = assert_fs::TempDir::new().unwrap();
+    let root = directory.child("imp");
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .args(["package", "new"])
+        .arg(root.path())
+        .arg("--name")
+        .arg("Imp")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("created package Imp in "));
+    root.child("metadata.toml")
+        .assert(predicate::str::starts_with("name = \"Imp\"\n"));
+    root.child("metadata.toml")
+        .write_str("name = \"Imp\"\nrounds = 4\n\n[hill]\ncore_size = 800\nmax_cycles = 1000\n")
+        .unwrap();
+    root.child("benchmarks/imp.red")
+        .write_str(";name Other Imp\nmov 0, 1\n")
+        .unwrap();
+    root.child("tests/dat.red")
+        .write_str(";name Dat\ndat 0, 0\n")
+        .unwrap();
+
+    let check = |update: bool| {
+        let mut command = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        command.args(["package", "check"]).arg(root.path());
+        if update {
+            command.arg("--update-baselines");
+        }
+        command.assert()
+    };
+    check(true).success().stdout(predicate::str::similar(
+        "test dat: 4/0/0 ok\nbenchmark imp: 100.0 ± 0.0\nupdated the baselines of 1 benchmarks\n",
+    ));
+    root.child("benchmarks/baselines.toml")
+        .assert("imp = 100.0\n");
+
+    // Losing to a benchmark is a regression
+    root.child("benchmarks/baselines.toml")
+        .write_str("imp = 200.0\n")
+        .unwrap();
+    check(false)
+        .failure()
+        .stdout(predicate::str::contains(
+            "benchmark imp: 100.0 ± 0.0 REGRESSED from baseline 200.0",
+        ))
+        .stderr(predicate::str::contains("package check failed"));
+
+    // Other commands read the package's warrior
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg(root.path())
+        .arg("check")
+        .assert()
+        .success();
+}
+
+#[test]
+fn tournament_crosstable() {
+    let tournament = |format: &str| {
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .args(["tournament", "--rounds", "2", "--max-cycles", "2000"])
+            .args(["--format", format])
+            .arg("../testdata/input/simple/dwarf.redcode")
+            .arg("../testdata/input/wilkie/rave.redcode")
+            .assert()
+            .success()
+    };
+
+    tournament("text")
+        .stdout(predicate::str::starts_with("#  Name"))
+        .stdout(predicate::str::contains("  Dwarf  "))
+        .stdout(predicate::str::contains("  Rave  "));
+    tournament("json").stdout(predicate::str::contains("\"records\": ["));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["tournament", "--rounds", "2", "--max-cycles", "2000"])
+        .args(["--pairing", "single-elimination"])
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("Knockout:\n  Dwarf vs Rave: "))
+        .stdout(predicate::str::contains("\nWinner: "));
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["tournament", "--rounds", "4", "--max-cycles", "2000"])
+        .args(["--time-budget", "60"])
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\n\nEvery pair finished 4 rounds; scores with 95% margins of error:\n  ",
+        ));
+}
+
+#[test]
+fn resume_tournament() {
+    let tournament = || {
+        let mut command = Command::cargo_bin(assert_cmd::crate_name!()).unwrap();
+        command
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .args(["tournament", "--rounds", "2", "--max-cycles", "2000"])
+            .args(["--format", "json"])
+            .arg("../testdata/input/simple/dwarf.redcode")
+            .arg("../testdata/input/wilkie/rave.redcode");
+        command
+    };
+
+    let output = tournament().output().unwrap();
+    assert!(output.status.success());
+    let results = assert_fs::NamedTempFile::new("results.json").unwrap();
+    std::fs::write(results.path(), &output.stdout).unwrap();
+
+    // Nothing is left to play, so the results are the same
+    tournament()
+        .arg("--resume")
+        .arg(results.path())
+        .assert()
+        .success()
+        .stdout(String::from_utf8(output.stdout).unwrap());
+
+    tournament()
+        .args(["--pairing", "swiss", "--resume"])
+        .arg(results.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "only round robins without a time budget can be resumed",
+        ));
+}
+
+#[test]
+fn position_bias() {
+    let bias = |format: &str| {
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .args([
+                "bias",
+                "--rounds",
+                "4",
+                "--bins",
+                "2",
+                "--max-cycles",
+                "2000",
+            ])
+            .args(["--format", format])
+            .arg("../testdata/input/simple/dwarf.redcode")
+            .arg("../testdata/input/wilkie/rave.redcode")
+            .assert()
+            .success()
+    };
+
+    bias("text")
+        .stdout(predicate::str::starts_with("Separation"))
+        .stdout(predicate::str::contains("\n100-2050 "));
+    bias("csv").stdout(predicate::str::starts_with(
+        "start,end,wins,losses,ties,score,margin\n100,2050,",
+    ));
+    bias("json").stdout(predicate::str::contains("\"warrior\": \"Dwarf\""));
+}
+
+#[test]
+fn tournament_spec() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata/input");
+    dir.child("spec.toml")
+        .write_str(&format!(
+            "name = \"dwarf vs rave\"\n\
+             warriors = [{:?}, {:?}]\n\
+             rounds = 4\n\
+             seed = 3\n\n\
+             [hill]\n\
+             max_cycles = 2000\n\n\
+             [scoring]\n\
+             win = 2\n",
+            testdata.join("simple/dwarf.redcode"),
+            testdata.join("wilkie/rave.redcode"),
+        ))
+        .unwrap();
+
+    let run = |spec: &std::path::Path| {
+        Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .arg("run")
+            .arg(spec)
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("#  Name"))
+            .stdout(predicate::str::contains(
+                "\n\nStandings with 2 points per win, 1 per tie and 0 per loss:\n  ",
+            ))
+    };
+
+    run(dir.child("spec.toml").path());
+    let archive = dir.child("spec-results");
+    archive
+        .child("spec.toml")
+        .assert(predicate::str::contains("warriors = [\"warriors.red\"]\n"));
+    archive
+        .child("warriors.red")
+        .assert(predicate::str::contains(";name Dwarf"));
+    archive
+        .child("results.json")
+        .assert(predicate::path::exists());
+
+    // The archive runs again, to the same results
+    let results = fs::read_to_string(archive.child("results.txt").path()).unwrap();
+    run(archive.child("spec.toml").path());
+    assert_eq!(
+        fs::read_to_string(dir.child("spec-results/spec-results/results.txt").path()).unwrap(),
+        results
+    );
+}
+
+#[test]
+fn blind_tournament() {
+    let mapping = assert_fs::NamedTempFile::new("mapping.json").unwrap();
+
+    let output = Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["tournament", "--rounds", "2", "--max-cycles", "2000"])
+        .arg("--blind")
+        .arg(mapping.path())
+        .args(["--blind-key", "secret"])
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .arg("../testdata/input/wilkie/rave.redcode")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("  Anon-"))
+        .stdout(predicate::str::contains("Dwarf").not())
+        .get_output()
+        .stdout
+        .clone();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("reveal")
+        .arg(mapping.path())
+        .with_stdin()
+        .buffer(String::from_utf8(output).unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("  Dwarf  "))
+        .stdout(predicate::str::contains("  Rave  "))
+        .stdout(predicate::str::contains("Anon-").not());
+}
+
+#[test]
+fn message_catalog() {
+    let catalog = assert_fs::NamedTempFile::new("fr.messages").unwrap();
+    catalog
+        .write_str("severity-error = erreur\npositive-rounds = il faut au moins une manche\n")
+        .unwrap();
+
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--messages")
+        .arg(catalog.path())
+        .args(["tournament", "--rounds", "0"])
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "erreur: il faut au moins une manche",
+        ));
+
+    catalog.write_str("round-outcome = {winner}\n").unwrap();
+    Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--messages")
+        .arg(catalog.path())
+        .args(["tournament", "--rounds", "0"])
+        .arg("../testdata/input/simple/dwarf.redcode")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "line 1: message \"round-outcome\" has no argument \"winner\"",
+        ));
+}